@@ -0,0 +1,114 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use khadyota::{Config, EncodePolicy, VectorDB};
+use std::io::BufWriter;
+
+const DIMS: usize = 128;
+
+/// An empty database, streaming its changes to a temporary file when
+/// `logged`, where per-insert records and flushes dominate
+fn empty_db(logged: bool) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMS,
+        ..Default::default()
+    })
+    .unwrap();
+    if logged {
+        let file = tempfile::tempfile().unwrap();
+        db.set_changelog(Some(Box::new(BufWriter::new(file)))).unwrap();
+    }
+    db
+}
+
+fn bench_bulk_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk_insert");
+    group.sample_size(10);
+    
+    for (n, logged) in [(10_000, false), (100_000, false), (10_000, true)] {
+        let flat: Vec<f32> = (0..n * DIMS).map(|i| (i as f32).sin()).collect();
+        let rows: Vec<Vec<f32>> = flat.chunks_exact(DIMS).map(<[f32]>::to_vec).collect();
+        let suffix = if logged { "_logged" } else { "" };
+        group.throughput(Throughput::Elements(n as u64));
+        
+        group.bench_with_input(BenchmarkId::new(format!("insert_loop{}", suffix), n), &rows, |b, rows| {
+            b.iter(|| {
+                let mut db = empty_db(logged);
+                for row in rows {
+                    db.insert(row.clone(), None).unwrap();
+                }
+                black_box(db)
+            })
+        });
+        
+        group.bench_with_input(BenchmarkId::new(format!("insert_batch{}", suffix), n), &rows, |b, rows| {
+            b.iter(|| {
+                let mut db = empty_db(logged);
+                db.insert_batch(rows.iter().map(|row| (row.clone(), None))).unwrap();
+                black_box(db)
+            })
+        });
+        
+        group.bench_with_input(BenchmarkId::new(format!("insert_flat{}", suffix), n), &flat, |b, flat| {
+            b.iter(|| {
+                let mut db = empty_db(logged);
+                db.insert_flat(flat, Vec::new()).unwrap();
+                black_box(db)
+            })
+        });
+    }
+    
+    group.finish();
+}
+
+/// A database with an index built over `rows`, encoding later inserts
+/// under `policy`
+fn built_db(rows: &[Vec<f32>], policy: EncodePolicy) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMS,
+        num_clusters: 32,
+        seed: Some(1),
+        encode_policy: policy,
+        ..Default::default()
+    })
+    .unwrap();
+    db.insert_batch(rows.iter().map(|row| (row.clone(), None))).unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+/// Loading rows one by one into a built index and rebuilding afterwards,
+/// where encoding each row on arrival is wasted work
+fn bench_encode_policy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_policy");
+    group.sample_size(10);
+    
+    let initial: Vec<Vec<f32>> = (0..2_000).map(|i| (0..DIMS).map(|j| ((i * DIMS + j) as f32).sin()).collect()).collect();
+    let n = 10_000;
+    let load: Vec<Vec<f32>> = (0..n).map(|i| (0..DIMS).map(|j| ((i * DIMS + j) as f32).cos()).collect()).collect();
+    group.throughput(Throughput::Elements(n as u64));
+    
+    for (name, policy) in [("eager", EncodePolicy::EagerOnInsert), ("deferred", EncodePolicy::DeferredToBuild)] {
+        for rebuild in [false, true] {
+            let id = format!("{}_{}", if rebuild { "load_then_build" } else { "load" }, name);
+            group.bench_with_input(BenchmarkId::new(id, n), &load, |b, load| {
+                b.iter_batched(
+                    || built_db(&initial, policy),
+                    |mut db| {
+                        for row in load {
+                            db.insert(row.clone(), None).unwrap();
+                        }
+                        if rebuild {
+                            db.build_index().unwrap();
+                        }
+                        black_box(db)
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+criterion_group!(benches, bench_bulk_insert, bench_encode_policy);
+criterion_main!(benches);
@@ -1,8 +1,8 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use khadyota::distance::*;
 
 fn bench_cosine_distance(c: &mut Criterion) {
-    let dims = vec![128, 256, 512, 768, 1024];
+    let dims = vec![128, 256, 383, 512, 768, 1024];
     
     for dim in dims {
         let a: Vec<f32> = (0..dim).map(|i| (i as f32).sin()).collect();
@@ -17,7 +17,7 @@ fn bench_cosine_distance(c: &mut Criterion) {
         });
         
         #[cfg(target_arch = "x86_64")]
-        if is_x86_feature_detected!("avx2") && dim % 8 == 0 {
+        if simd::avx2_available() {
             group.bench_function("avx2", |bench| {
                 bench.iter(|| unsafe {
                     simd::cosine_distance_avx2(black_box(&a), black_box(&b))
@@ -25,6 +25,15 @@ fn bench_cosine_distance(c: &mut Criterion) {
             });
         }
         
+        #[cfg(target_arch = "aarch64")]
+        if neon::neon_available() {
+            group.bench_function("neon", |bench| {
+                bench.iter(|| unsafe {
+                    neon::cosine_distance_neon(black_box(&a), black_box(&b))
+                })
+            });
+        }
+        
         group.bench_function("auto_dispatch", |bench| {
             bench.iter(|| {
                 cosine_distance(black_box(&a), black_box(&b))
@@ -51,7 +60,7 @@ fn bench_euclidean_distance(c: &mut Criterion) {
         });
         
         #[cfg(target_arch = "x86_64")]
-        if is_x86_feature_detected!("avx2") && dim % 8 == 0 {
+        if simd::avx2_available() {
             group.bench_function("avx2", |bench| {
                 bench.iter(|| unsafe {
                     simd::euclidean_distance_avx2(black_box(&a), black_box(&b))
@@ -59,9 +68,55 @@ fn bench_euclidean_distance(c: &mut Criterion) {
             });
         }
         
+        #[cfg(target_arch = "aarch64")]
+        if neon::neon_available() {
+            group.bench_function("neon", |bench| {
+                bench.iter(|| unsafe {
+                    neon::euclidean_distance_neon(black_box(&a), black_box(&b))
+                })
+            });
+        }
+        
+        group.finish();
+    }
+}
+
+fn bench_dot_product(c: &mut Criterion) {
+    let dims = vec![128, 383, 768];
+    
+    for dim in dims {
+        let a: Vec<f32> = (0..dim).map(|i| (i as f32).sin()).collect();
+        let b: Vec<f32> = (0..dim).map(|i| (i as f32).cos()).collect();
+        
+        let mut group = c.benchmark_group(format!("dot_product_{}", dim));
+        
+        group.bench_function("scalar", |bench| {
+            bench.iter(|| {
+                scalar::dot_product_scalar(black_box(&a), black_box(&b))
+            })
+        });
+        
+        #[cfg(target_arch = "x86_64")]
+        if simd::avx2_available() {
+            group.bench_function("avx2", |bench| {
+                bench.iter(|| unsafe {
+                    simd::dot_product_avx2(black_box(&a), black_box(&b))
+                })
+            });
+        }
+        
+        #[cfg(target_arch = "aarch64")]
+        if neon::neon_available() {
+            group.bench_function("neon", |bench| {
+                bench.iter(|| unsafe {
+                    neon::dot_product_neon(black_box(&a), black_box(&b))
+                })
+            });
+        }
+        
         group.finish();
     }
 }
 
-criterion_group!(benches, bench_cosine_distance, bench_euclidean_distance);
+criterion_group!(benches, bench_cosine_distance, bench_euclidean_distance, bench_dot_product);
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,51 @@
+//! Bulk PQ encoding: serial `add` versus parallel `add_batch_from`.
+//!
+//! Encodes 1M × 512-dim vectors by default; set `KHADYOTA_ENCODE_N` to
+//! run a smaller batch.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use khadyota::quantization::PQCodec;
+use khadyota::storage::QuantizedVectors;
+
+const DIMS: usize = 512;
+
+fn dataset(n: usize) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|i| (0..DIMS).map(|j| ((i * DIMS + j) as f32 * 0.013).sin()).collect())
+        .collect()
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let n = std::env::var("KHADYOTA_ENCODE_N")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1_000_000);
+    let codec = PQCodec::train_seeded(&dataset(5_000), 8, Some(42)).unwrap();
+    let vectors = dataset(n);
+
+    let mut group = c.benchmark_group("pq_encode");
+    group.sample_size(10);
+
+    group.bench_function(format!("serial_{}x{}", n, DIMS), |bench| {
+        bench.iter(|| {
+            let mut quantized = QuantizedVectors::new(codec.clone());
+            for vector in &vectors {
+                quantized.add(black_box(vector.clone()));
+            }
+            quantized
+        })
+    });
+
+    group.bench_function(format!("add_batch_{}x{}", n, DIMS), |bench| {
+        bench.iter(|| {
+            let mut quantized = QuantizedVectors::new(codec.clone());
+            quantized.add_batch_from(black_box(&vectors).iter().map(Vec::as_slice));
+            quantized
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode);
+criterion_main!(benches);
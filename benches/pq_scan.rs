@@ -0,0 +1,43 @@
+//! PQ scoring of one query's candidates: a table lookup per code row
+//! against [`QuantizedVectors::scan`], which scans the rows as one block
+//! (eight at a time with AVX2, where the CPU has it).
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use khadyota::harness::random_vectors;
+use khadyota::quantization::PQCodec;
+use khadyota::storage::QuantizedVectors;
+
+const DIMS: usize = 128;
+
+fn bench_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pq_scan");
+    let stored = 100_000;
+    let training = random_vectors(2_000, DIMS, 1);
+    
+    for subvectors in [8, 16, 32] {
+        let codec = PQCodec::train_seeded(&training, subvectors, Some(1)).unwrap();
+        let codes = random_vectors(stored, DIMS, 2).iter().map(|v| codec.encode(v)).collect();
+        let quantized = QuantizedVectors::from_codes(codec, codes).unwrap();
+        let table = quantized.precompute_distance_table(&random_vectors(1, DIMS, 3)[0]);
+        
+        // Candidates of a query probing a tenth of the lists
+        let ids: Vec<u32> = (0..stored as u32).step_by(10).collect();
+        let mut out = vec![0.0; ids.len()];
+        
+        group.bench_with_input(BenchmarkId::new("per_code", subvectors), &ids, |b, ids| {
+            b.iter(|| {
+                for (distance, &id) in out.iter_mut().zip(ids) {
+                    *distance = quantized.table_lookup_distance(black_box(&table), id);
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("scan", subvectors), &ids, |b, ids| {
+            b.iter(|| quantized.scan(black_box(&table), ids, &mut out))
+        });
+    }
+    
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
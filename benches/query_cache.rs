@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use khadyota::{VectorDB, Config, DistanceMetric, QuantizerKind, QueryCacheConfig};
+use rand::prelude::*;
+use rand_distr::Zipf;
+
+const DIMS: usize = 128;
+
+fn setup_db(size: usize) -> VectorDB {
+    let config = Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Cosine,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 8,
+        num_clusters: 100,
+        num_probe: 10,
+        seed: Some(42),
+        ..Default::default()
+    };
+    
+    let mut db = VectorDB::new(config).unwrap();
+    
+    for i in 0..size {
+        let vector: Vec<f32> = (0..DIMS)
+            .map(|j| ((i * DIMS + j) as f32).sin())
+            .collect();
+        
+        db.insert(vector, None).unwrap();
+    }
+    
+    db.build_index().unwrap();
+    db
+}
+
+/// A query stream where the query of rank r appears with probability
+/// proportional to 1 / r^s, as popular searches do
+fn zipfian_queries(distinct: usize, s: f64, len: usize) -> Vec<Vec<f32>> {
+    let pool: Vec<Vec<f32>> = (0..distinct)
+        .map(|q| (0..DIMS).map(|j| ((q * 31 + j) as f32).cos()).collect())
+        .collect();
+    let zipf = Zipf::new(distinct as u64, s).unwrap();
+    let mut rng = StdRng::seed_from_u64(7);
+    
+    (0..len)
+        .map(|_| pool[zipf.sample(&mut rng) as usize - 1].clone())
+        .collect()
+}
+
+fn bench_zipfian_stream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query_cache_zipfian");
+    let mut db = setup_db(10_000);
+    let queries = zipfian_queries(1_000, 1.1, 2_000);
+    
+    for cached in [false, true] {
+        db.set_query_cache(cached.then(QueryCacheConfig::default));
+        
+        group.bench_with_input(
+            BenchmarkId::new(if cached { "cached" } else { "uncached" }, queries.len()),
+            &queries,
+            |b, queries| {
+                b.iter(|| {
+                    for query in queries {
+                        black_box(db.search(black_box(query), 10).unwrap());
+                    }
+                })
+            },
+        );
+    }
+    
+    group.finish();
+}
+
+criterion_group!(benches, bench_zipfian_stream);
+criterion_main!(benches);
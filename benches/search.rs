@@ -1,14 +1,23 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
-use khadyota::{VectorDB, Config, DistanceMetric};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId, Throughput};
+use khadyota::{VectorDB, Config, DistanceMetric, IndexKind, QuantizerKind};
+use khadyota::distance::compute_distance;
+use khadyota::harness::random_vectors;
+use khadyota::indexing::FlatIndex;
+use khadyota::parallel::set_chunk_override;
+use khadyota::quantization::PQCodec;
+use khadyota::select::{cmp_distance, smallest_k};
+use khadyota::storage::QuantizedVectors;
 
 fn setup_db(size: usize, use_pq: bool, num_clusters: usize) -> VectorDB {
     let config = Config {
         dimensions: 512,
         metric: DistanceMetric::Cosine,
-        use_pq,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
         pq_subvectors: 8,
         num_clusters,
         num_probe: num_clusters / 10,
+        seed: None,
+        ..Default::default()
     };
     
     let mut db = VectorDB::new(config).unwrap();
@@ -62,5 +71,156 @@ fn bench_search_with_without_pq(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_search_by_size, bench_search_with_without_pq);
+/// Parallel PQ scoring of candidate lists of growing size, with rayon
+/// splitting on its own against tasks sized by the cost model. Candidate
+/// ids cycle over 100k stored codes, so 5M costs no 5M-row build.
+fn bench_parallel_chunking(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_chunking");
+    group.sample_size(10);
+    let stored = 100_000;
+    let training = random_vectors(2_000, 64, 1);
+    let codec = PQCodec::train_seeded(&training, 8, Some(1)).unwrap();
+    let codes = random_vectors(stored, 64, 2).iter().map(|v| codec.encode(v)).collect();
+    let quantized = QuantizedVectors::from_codes(codec, codes).unwrap();
+    let table = quantized.precompute_distance_table(&random_vectors(1, 64, 3)[0]);
+    
+    for candidates in [1_000, 100_000, 5_000_000] {
+        let ids: Vec<u32> = (0..candidates).map(|i| (i % stored) as u32).collect();
+        for (name, chunk) in [("rayon_default", Some(1)), ("tuned", None)] {
+            set_chunk_override(chunk);
+            group.bench_with_input(BenchmarkId::new(name, candidates), &ids, |b, ids| {
+                b.iter(|| quantized.score_parallel(black_box(&table), ids))
+            });
+        }
+    }
+    set_chunk_override(None);
+    
+    group.finish();
+}
+
+/// Picking the 10 nearest of 10k and 100k scored candidates: a full sort
+/// then truncate, as searches did, against the bounded heap they use now,
+/// and a Euclidean linear scan over as many rows end to end
+fn bench_top_k(c: &mut Criterion) {
+    let mut group = c.benchmark_group("top_k");
+    
+    for candidates in [10_000, 100_000] {
+        let scored: Vec<(u32, f32)> = random_vectors(1, candidates, 4)[0]
+            .iter()
+            .enumerate()
+            .map(|(id, &distance)| (id as u32, distance))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("sort_truncate", candidates), &scored, |b, scored| {
+            b.iter(|| {
+                let mut scored = scored.clone();
+                scored.sort_by(|a, b| cmp_distance(a.1, b.1));
+                scored.truncate(10);
+                scored
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("heap", candidates), &scored, |b, scored| {
+            b.iter(|| smallest_k(black_box(scored.clone()), 10))
+        });
+        
+        let config = Config {
+            dimensions: 32,
+            metric: DistanceMetric::Euclidean,
+            quantizer: QuantizerKind::None,
+            ..Default::default()
+        };
+        let mut db = VectorDB::new(config).unwrap();
+        db.insert_batch(random_vectors(candidates, 32, 5).into_iter().map(|v| (v, None))).unwrap();
+        // Without codes, searches scan every row
+        db.build_index().unwrap();
+        let query = random_vectors(1, 32, 6).remove(0);
+        group.bench_with_input(BenchmarkId::new("linear_search", candidates), &query, |b, query| {
+            b.iter(|| db.search(black_box(query), 10).unwrap())
+        });
+    }
+    
+    group.finish();
+}
+
+/// Exact top 10 over 10k and 50k rows: scoring each `Vec<f32>` of a
+/// `Vec<Vec<f32>>` in turn, against a `FlatIndex` walking one contiguous
+/// buffer, alone and behind `VectorDB::search`
+fn bench_flat_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flat_scan");
+    
+    for rows in [10_000, 50_000] {
+        let vectors = random_vectors(rows, 128, 7);
+        let query = random_vectors(1, 128, 8).remove(0);
+        group.bench_with_input(BenchmarkId::new("vec_of_vecs", rows), &vectors, |b, vectors| {
+            b.iter(|| {
+                let scored: Vec<_> = vectors
+                    .iter()
+                    .enumerate()
+                    .map(|(id, v)| (id as u32, compute_distance(black_box(&query), v, DistanceMetric::Euclidean)))
+                    .collect();
+                smallest_k(scored, 10)
+            })
+        });
+        
+        let flat = FlatIndex::from_rows(&vectors, 128);
+        group.bench_with_input(BenchmarkId::new("flat_index", rows), &flat, |b, flat| {
+            b.iter(|| flat.search(black_box(&query), 10, DistanceMetric::Euclidean))
+        });
+        
+        let config = Config {
+            dimensions: 128,
+            metric: DistanceMetric::Euclidean,
+            index: IndexKind::Flat,
+            ..Default::default()
+        };
+        let mut db = VectorDB::new(config).unwrap();
+        db.insert_batch(vectors.into_iter().map(|v| (v, None))).unwrap();
+        db.build_index().unwrap();
+        group.bench_with_input(BenchmarkId::new("flat_db", rows), &query, |b, query| {
+            b.iter(|| db.search(black_box(query), 10).unwrap())
+        });
+    }
+    
+    group.finish();
+}
+
+/// 1000 queries against 50k rows under IVF + PQ: `batch_search` over a
+/// `Vec<Vec<f32>>`, each query probing and allocating on its own, against
+/// `batch_search_flat` over one buffer, probed together and scored in
+/// per-thread buffers
+fn bench_batch_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_search");
+    group.sample_size(10);
+    let config = Config {
+        dimensions: 64,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 16,
+        num_clusters: 256,
+        num_probe: 16,
+        seed: Some(9),
+        ..Default::default()
+    };
+    let mut db = VectorDB::new(config).unwrap();
+    db.insert_batch(random_vectors(50_000, 64, 9).into_iter().map(|v| (v, None))).unwrap();
+    db.build_index().unwrap();
+    let queries = random_vectors(1_000, 64, 10);
+    let flat: Vec<f32> = queries.concat();
+    
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_function("batch_search", |b| b.iter(|| db.batch_search(black_box(&queries), 10).unwrap()));
+    group.bench_function("batch_search_flat", |b| {
+        b.iter(|| db.batch_search_flat(black_box(&flat), queries.len(), 10).unwrap())
+    });
+    
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_search_by_size,
+    bench_search_with_without_pq,
+    bench_parallel_chunking,
+    bench_top_k,
+    bench_flat_scan,
+    bench_batch_search
+);
 criterion_main!(benches);
\ No newline at end of file
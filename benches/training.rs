@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use khadyota::quantization::{kmeans_seeded, PQCodec};
+
+fn dataset(n: usize, dims: usize) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|i| (0..dims).map(|j| ((i * dims + j) as f32).sin()).collect())
+        .collect()
+}
+
+fn bench_kmeans(c: &mut Criterion) {
+    let vectors = dataset(5_000, 128);
+    let mut group = c.benchmark_group("training");
+    group.sample_size(10);
+    
+    group.bench_function("kmeans_5000x128_k64", |bench| {
+        bench.iter(|| kmeans_seeded(black_box(&vectors), 64, 20, 0.001, Some(42)))
+    });
+    
+    let vectors = dataset(2_000, 128);
+    group.bench_function("pq_train_2000x128_m8", |bench| {
+        bench.iter(|| PQCodec::train_seeded(black_box(&vectors), 8, Some(42)).unwrap())
+    });
+    
+    group.finish();
+}
+
+/// The same training on one thread and on the whole rayon pool; results
+/// are identical, so only the time differs
+fn bench_threads(c: &mut Criterion) {
+    let vectors = dataset(5_000, 128);
+    let mut group = c.benchmark_group("training_threads");
+    group.sample_size(10);
+    
+    let all = rayon::current_num_threads();
+    for threads in [1, all] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        group.bench_function(format!("kmeans_5000x128_k64/{}_threads", threads), |bench| {
+            bench.iter(|| pool.install(|| kmeans_seeded(black_box(&vectors), 64, 20, 0.001, Some(42))))
+        });
+        group.bench_function(format!("pq_train_5000x128_m8/{}_threads", threads), |bench| {
+            bench.iter(|| pool.install(|| PQCodec::train_seeded(black_box(&vectors), 8, Some(42)).unwrap()))
+        });
+        if all == 1 {
+            break;
+        }
+    }
+    
+    group.finish();
+}
+
+criterion_group!(benches, bench_kmeans, bench_threads);
+criterion_main!(benches);
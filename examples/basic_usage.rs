@@ -1,4 +1,5 @@
-use khadyota::{VectorDB, Config, DistanceMetric};
+use khadyota::{VectorDB, Config, DistanceMetric, PrintProgress, QuantizerKind};
+use std::sync::Arc;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Khadyota Basic Usage Example ===\n");
@@ -7,13 +8,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config {
         dimensions: 128,
         metric: DistanceMetric::Cosine,
-        use_pq: true,
+        quantizer: QuantizerKind::PQ,
         pq_subvectors: 8,
         num_clusters: 20,
         num_probe: 5,
+        seed: None,
+        ..Default::default()
     };
     
     let mut db = VectorDB::new(config)?;
+    db.set_progress_callback(Some(Arc::new(PrintProgress)));
     
     println!("1. Inserting 1,000 vectors...");
     for i in 0..1_000 {
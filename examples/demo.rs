@@ -1,4 +1,6 @@
-use khadyota::{VectorDB, Config, DistanceMetric};
+use khadyota::harness::{self, Workload};
+use khadyota::{VectorDB, Config, DistanceMetric, PrintProgress, QuantizerKind};
+use std::sync::Arc;
 use std::time::Instant;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -11,10 +13,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config {
         dimensions: 512,
         metric: DistanceMetric::Cosine,
-        use_pq: true,
+        quantizer: QuantizerKind::PQ,
         pq_subvectors: 8,
         num_clusters: 100,
         num_probe: 10,
+        seed: Some(42),
+        ..Default::default()
     };
     
     println!("📋 Configuration:");
@@ -25,16 +29,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("   - Probe: {}\n", config.num_probe);
     
     let mut db = VectorDB::new(config)?;
+    db.set_progress_callback(Some(Arc::new(PrintProgress)));
     
     // Step 1: Insert vectors
     println!("📥 Step 1: Inserting 10,000 vectors...");
     let insert_start = Instant::now();
     
-    for i in 0..10_000 {
-        let vector: Vec<f32> = (0..512)
-            .map(|j| ((i * 512 + j) as f32).sin())
-            .collect();
-        
+    for (i, vector) in harness::clustered_vectors(10_000, 512, 100, 0.1, 1).into_iter().enumerate() {
         let metadata = serde_json::json!({
             "id": i,
             "category": format!("cat_{}", i % 10),
@@ -51,40 +52,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     db.build_index()?;
     println!("   ✓ Built in {:?}\n", build_start.elapsed());
     
-    // Step 3: Single query
+    // Step 3: Single queries
     println!("🔍 Step 3: Single query benchmark...");
-    let query: Vec<f32> = (0..512).map(|i| (i as f32).cos()).collect();
-    
-    // Warmup
-    for _ in 0..10 {
-        db.search(&query, 10)?;
-    }
-    
-    let mut times = Vec::new();
-    for _ in 0..100 {
-        let start = Instant::now();
-        db.search(&query, 10)?;
-        times.push(start.elapsed());
-    }
-    
-    times.sort();
-    println!("   - p50 latency: {:?}", times[50]);
-    println!("   - p95 latency: {:?}", times[95]);
-    println!("   - p99 latency: {:?}", times[99]);
-    println!("   - Throughput: {:.0} QPS\n", 1.0 / times[50].as_secs_f64());
+    let queries = harness::clustered_vectors(100, 512, 100, 0.1, 2);
+    let report = Workload::default().run(&db, &queries)?;
+    println!("   - p50 latency: {:?}", report.latency.p50());
+    println!("   - p95 latency: {:?}", report.latency.p95());
+    println!("   - p99 latency: {:?}", report.latency.p99());
+    println!("   - Throughput: {:.0} QPS", report.throughput());
+    println!("   - Recall@10: {:.3}\n", harness::recall_at_k(&db, &queries, 10)?);
     
     // Step 4: Batch queries
     println!("🚀 Step 4: Batch query performance...");
-    let queries: Vec<Vec<f32>> = (0..100)
-        .map(|i| (0..512).map(|j| ((i + j) as f32).cos()).collect())
-        .collect();
-    
-    let batch_start = Instant::now();
-    let results = db.batch_search(&queries, 10)?;
-    let batch_time = batch_start.elapsed();
+    let batch = Workload {
+        measured: queries.len(),
+        ..Default::default()
+    }
+    .run_batch(&db, &queries)?;
     
-    println!("   - 100 queries in {:?}", batch_time);
-    println!("   - {:.0} QPS\n", 100.0 / batch_time.as_secs_f64());
+    println!("   - {} queries in {:?}", batch.latency.count(), batch.elapsed);
+    println!("   - {:.0} QPS\n", batch.throughput());
     
     // Step 5: Save and load
     println!("💾 Step 5: Persistence...");
@@ -100,11 +87,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Final results
     println!("✅ Demo complete!");
     println!("\n📊 Summary:");
-    println!("   ✓ 10,000 vectors indexed");
-    println!("   ✓ <5ms query latency (p50)");
-    println!("   ✓ 200+ QPS throughput");
-    println!("   ✓ 64x memory compression");
+    println!("   ✓ {} vectors indexed", db.len());
+    println!("   ✓ {:?} query latency (p50)", report.latency.p50());
+    println!("   ✓ {:.0} QPS throughput", report.throughput());
+    if let Some(ratio) = db.stats().compression_ratio {
+        println!("   ✓ {:.0}x memory compression", ratio);
+    }
     println!("   ✓ Persistent storage\n");
     
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,63 +1,41 @@
-use khadyota::{VectorDB, Config};
-use std::time::Instant;
+use khadyota::harness::{self, Workload};
+use khadyota::{Config, QuantizerKind};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Parallel Query Benchmark ===\n");
     
     let config = Config {
         dimensions: 512,
-        use_pq: true,
+        quantizer: QuantizerKind::PQ,
         pq_subvectors: 8,
         num_clusters: 100,
         num_probe: 10,
+        seed: Some(42),
         ..Default::default()
     };
     
-    let mut db = VectorDB::new(config)?;
-    
     println!("Building database with 50,000 vectors...");
-    for i in 0..50_000 {
-        let vector: Vec<f32> = (0..512)
-            .map(|j| ((i * 512 + j) as f32).sin())
-            .collect();
-        db.insert(vector, None)?;
-    }
-    
-    db.build_index()?;
-    
-    // Generate 100 queries
-    let queries: Vec<Vec<f32>> = (0..100)
-        .map(|i| (0..512).map(|j| ((i + j) as f32).cos()).collect())
-        .collect();
-    
-    println!("\nBenchmarking 100 queries...\n");
-    
-    // Sequential
-    let start = Instant::now();
-    let mut results = Vec::new();
-    for query in &queries {
-        results.push(db.search(query, 10)?);
-    }
-    let sequential_time = start.elapsed();
-    
-    println!("Sequential: {:?} ({:.2} queries/sec)",
-        sequential_time,
-        100.0 / sequential_time.as_secs_f64()
-    );
+    let db = harness::build_db(config, harness::clustered_vectors(50_000, 512, 100, 0.1, 1))?;
     
-    // Parallel
-    let start = Instant::now();
-    let results_parallel = db.batch_search(&queries, 10)?;
-    let parallel_time = start.elapsed();
+    // 100 queries, each run once after a warmup pass
+    let queries = harness::clustered_vectors(100, 512, 100, 0.1, 2);
+    let workload = Workload {
+        warmup: queries.len(),
+        measured: queries.len(),
+        ..Default::default()
+    };
     
-    println!("Parallel:   {:?} ({:.2} queries/sec)",
-        parallel_time,
-        100.0 / parallel_time.as_secs_f64()
-    );
+    println!("\nBenchmarking {} queries...\n", queries.len());
+    
+    let sequential = workload.run(&db, &queries)?;
+    println!("Sequential: {}", sequential);
+    
+    let parallel = workload.run_batch(&db, &queries)?;
+    println!("Parallel:   {}", parallel);
     
     println!("\nSpeedup: {:.2}x", 
-        sequential_time.as_secs_f64() / parallel_time.as_secs_f64()
+        sequential.elapsed.as_secs_f64() / parallel.elapsed.as_secs_f64()
     );
     
     Ok(())
-}
\ No newline at end of file
+}
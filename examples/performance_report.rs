@@ -1,4 +1,5 @@
-use khadyota::{VectorDB, Config};
+use khadyota::harness::{self, Workload};
+use khadyota::Config;
 use std::time::Instant;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -7,74 +8,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("╚══════════════════════════════════════════╝\n");
     
     let sizes = vec![1_000, 10_000, 50_000];
+    let queries = harness::clustered_vectors(100, 512, 50, 0.1, 2);
     
     for &size in &sizes {
         println!("\n═══ Dataset: {} vectors ═══", size);
         
-        let config = Config {
-            dimensions: 512,
-            use_pq: true,
-            pq_subvectors: 8,
-            num_clusters: (size as f64).sqrt() as usize,
-            num_probe: ((size as f64).sqrt() / 10.0) as usize,
-            ..Default::default()
-        };
-        
-        let mut db = VectorDB::new(config)?;
+        let config = Config::builder()
+            .dimensions(512)
+            .expected_size(size)
+            .pq_subvectors(8)
+            .seed(42)
+            .build()?;
         
         // Build database
         print!("Building... ");
         let build_start = Instant::now();
-        
-        for i in 0..size {
-            let vector: Vec<f32> = (0..512)
-                .map(|j| ((i * 512 + j) as f32).sin())
-                .collect();
-            db.insert(vector, None)?;
-        }
-        
-        db.build_index()?;
-        let build_time = build_start.elapsed();
-        println!("{:?}", build_time);
-        
-        // Query performance
-        let query: Vec<f32> = (0..512).map(|i| (i as f32).cos()).collect();
-        
-        // Warmup
-        for _ in 0..10 {
-            db.search(&query, 10)?;
-        }
+        let db = harness::build_db(config, harness::clustered_vectors(size, 512, 50, 0.1, 1))?;
+        println!("{:?}", build_start.elapsed());
         
         // Measure p50, p95, p99
-        let mut times = Vec::new();
-        for _ in 0..100 {
-            let start = Instant::now();
-            db.search(&query, 10)?;
-            times.push(start.elapsed());
-        }
-        
-        times.sort();
-        
-        let p50 = times[50];
-        let p95 = times[95];
-        let p99 = times[99];
-        
+        let report = Workload::default().run(&db, &queries)?;
         println!("Query Latency:");
-        println!("  p50: {:?}", p50);
-        println!("  p95: {:?}", p95);
-        println!("  p99: {:?}", p99);
-        println!("  QPS: {:.0}", 1.0 / p50.as_secs_f64());
+        println!("  p50: {:?}", report.latency.p50());
+        println!("  p95: {:?}", report.latency.p95());
+        println!("  p99: {:?}", report.latency.p99());
+        println!("  QPS: {:.0}", report.throughput());
+        println!("  Recall@10: {:.3}", harness::recall_at_k(&db, &queries, 10)?);
         
-        // Memory estimate
-        let memory_mb = (size * 512 * 4) / 1_000_000; // Original vectors
-        let compressed_mb = (size * 8) / 1_000_000;   // PQ codes
-        println!("Memory:");
-        println!("  Original: {} MB", memory_mb);
-        println!("  Compressed: {} MB", compressed_mb);
-        println!("  Ratio: {:.1}x", memory_mb as f32 / compressed_mb as f32);
+        println!("Memory:\n{}", db.stats());
     }
     
     println!("\n✓ Performance report complete!\n");
     
     Ok(())
-}
\ No newline at end of file
+}
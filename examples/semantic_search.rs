@@ -1,4 +1,5 @@
-use khadyota::{VectorDB, Config, DistanceMetric};
+use khadyota::{VectorDB, Config, DistanceMetric, PrintProgress, QuantizerKind};
+use std::sync::Arc;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Semantic Search Demo ===\n");
@@ -25,13 +26,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config {
         dimensions: 128,
         metric: DistanceMetric::Cosine,
-        use_pq: false, // Small dataset, skip PQ
+        quantizer: QuantizerKind::None, // Small dataset, skip PQ
         num_clusters: 3,
         num_probe: 2,
         ..Default::default()
     };
     
     let mut db = VectorDB::new(config)?;
+    db.set_progress_callback(Some(Arc::new(PrintProgress)));
     
     println!("Indexing documents...");
     for (text, vector) in &documents {
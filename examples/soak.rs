@@ -0,0 +1,38 @@
+//! Mixed-workload soak run for spotting memory growth.
+//!
+//! ```text
+//! cargo run --release --example soak -- examples/soak.toml
+//! cargo run --release --example soak --features alloc-tracking -- examples/soak.toml
+//! ```
+//!
+//! The scenario file is TOML with any fields of `soak::Scenario`; with no
+//! file the defaults run for a minute. Prints the report as JSON and
+//! exits with status 1 if any check failed.
+
+use khadyota::soak::{self, Scenario};
+
+#[cfg(feature = "alloc-tracking")]
+#[global_allocator]
+static ALLOC: soak::alloc::CountingAllocator = soak::alloc::CountingAllocator;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let scenario: Scenario = match std::env::args().nth(1) {
+        Some(path) => toml::from_str(&std::fs::read_to_string(path)?)?,
+        None => Scenario::default(),
+    };
+    
+    let report = soak::run(&scenario)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    
+    match report.unaccounted_growth_bytes() {
+        Some(growth) => eprintln!("Unaccounted memory growth: {} KiB", growth / 1024),
+        None => eprintln!("RSS is not readable on this platform"),
+    }
+    for violation in &report.violations {
+        eprintln!("FAILED: {}", violation);
+    }
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use crate::error::Result;
+use crate::persist::PersistSection;
+use crate::storage::Section;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the save-file section holding persisted access statistics
+pub(crate) const SECTION_NAME: &str = "access_stats";
+
+/// Rescale the decayed counts before the running increment overflows
+const RESCALE_AT: f64 = 1e100;
+
+/// Settings for [`VectorDB::set_access_tracking`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AccessTrackingConfig {
+    /// Queries after which a cluster probe counts half as much as a
+    /// fresh one
+    pub half_life: u64,
+
+    /// Whether `save()` writes the statistics so `load()` restores them
+    pub persist: bool,
+}
+
+impl Default for AccessTrackingConfig {
+    fn default() -> Self {
+        Self {
+            half_life: 10_000,
+            persist: true,
+        }
+    }
+}
+
+/// Snapshot of the access tracker's counters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccessStats {
+    /// Searches and candidate rankings answered, cache hits included
+    pub queries: u64,
+
+    /// When the last of them ran, in seconds since the Unix epoch
+    pub last_access_unix_secs: Option<u64>,
+
+    /// Rows scored, summed over queries that missed the cache
+    pub candidates_scanned: u64,
+
+    /// IVF inverted lists probed, summed over the same queries
+    pub clusters_probed: u64,
+
+    /// Decayed probe count per IVF cluster: a probe `half_life` queries ago
+    /// counts half as much as one just now. Empty until the index is built,
+    /// and reset whenever it is rebuilt.
+    pub cluster_frequency: Vec<f64>,
+}
+
+impl AccessStats {
+    /// The `n` most frequently probed clusters, hottest first
+    pub fn hottest_clusters(&self, n: usize) -> Vec<(usize, f64)> {
+        let mut clusters: Vec<(usize, f64)> = self.cluster_frequency.iter().copied().enumerate().collect();
+        clusters.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        clusters.truncate(n);
+        clusters
+    }
+
+    /// Average rows scored per query
+    pub fn mean_candidates(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.candidates_scanned as f64 / self.queries as f64
+        }
+    }
+}
+
+/// Decayed per-cluster probe counts.
+///
+/// Rather than decaying every count on each query, each new probe adds a
+/// weight that grows by `growth` per query; a count divided by the current
+/// weight is the decayed frequency.
+#[derive(Debug)]
+struct ClusterSketch {
+    weights: Vec<f64>,
+    increment: f64,
+    growth: f64,
+}
+
+impl ClusterSketch {
+    fn new(half_life: u64, frequency: Vec<f64>) -> Self {
+        Self {
+            weights: frequency,
+            increment: 1.0,
+            growth: 2f64.powf(1.0 / half_life.max(1) as f64),
+        }
+    }
+
+    fn advance(&mut self) {
+        self.increment *= self.growth;
+        if self.increment > RESCALE_AT {
+            for weight in &mut self.weights {
+                *weight /= self.increment;
+            }
+            self.increment = 1.0;
+        }
+    }
+
+    fn frequency(&self) -> Vec<f64> {
+        self.weights.iter().map(|w| w / self.increment).collect()
+    }
+}
+
+/// Fixed-size access counters, shared by concurrent searches
+#[derive(Debug)]
+pub(crate) struct AccessTracker {
+    config: AccessTrackingConfig,
+    queries: AtomicU64,
+    /// Seconds since the epoch; zero when nothing has run yet
+    last_access: AtomicU64,
+    candidates: AtomicU64,
+    probes: AtomicU64,
+    sketch: Mutex<ClusterSketch>,
+}
+
+impl AccessTracker {
+    pub(crate) fn new(config: AccessTrackingConfig) -> Self {
+        Self::restore(config, AccessStats::default())
+    }
+
+    fn restore(config: AccessTrackingConfig, stats: AccessStats) -> Self {
+        Self {
+            config,
+            queries: AtomicU64::new(stats.queries),
+            last_access: AtomicU64::new(stats.last_access_unix_secs.unwrap_or(0)),
+            candidates: AtomicU64::new(stats.candidates_scanned),
+            probes: AtomicU64::new(stats.clusters_probed),
+            sketch: Mutex::new(ClusterSketch::new(config.half_life, stats.cluster_frequency)),
+        }
+    }
+
+    /// Count one query, aging earlier probes
+    pub(crate) fn record_query(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.last_access.fetch_max(now, Ordering::Relaxed);
+        self.sketch.lock().unwrap().advance();
+    }
+
+    /// Count the work a query did: the inverted lists it probed out of
+    /// `num_clusters`, and how many rows it scored
+    pub(crate) fn record_scan(&self, clusters: &[usize], num_clusters: usize, candidates: usize) {
+        self.candidates.fetch_add(candidates as u64, Ordering::Relaxed);
+        if clusters.is_empty() {
+            return;
+        }
+
+        self.probes.fetch_add(clusters.len() as u64, Ordering::Relaxed);
+        let mut sketch = self.sketch.lock().unwrap();
+        if sketch.weights.len() != num_clusters {
+            sketch.weights = vec![0.0; num_clusters];
+        }
+        let increment = sketch.increment;
+        for &cluster in clusters {
+            sketch.weights[cluster] += increment;
+        }
+    }
+
+    /// Forget per-cluster counts, whose cluster ids a rebuild reassigns
+    pub(crate) fn reset_clusters(&self) {
+        self.sketch.lock().unwrap().weights.clear();
+    }
+
+    pub(crate) fn snapshot(&self) -> AccessStats {
+        let last_access = self.last_access.load(Ordering::Relaxed);
+        AccessStats {
+            queries: self.queries.load(Ordering::Relaxed),
+            last_access_unix_secs: (last_access != 0).then_some(last_access),
+            candidates_scanned: self.candidates.load(Ordering::Relaxed),
+            clusters_probed: self.probes.load(Ordering::Relaxed),
+            cluster_frequency: self.sketch.lock().unwrap().frequency(),
+        }
+    }
+
+    /// The statistics as a save-file section, if they are to be persisted
+    pub(crate) fn to_section(&self) -> Result<Option<Section>> {
+        if !self.config.persist {
+            return Ok(None);
+        }
+        let payload = rmp_serde::to_vec(&PersistedAccess {
+            config: self.config,
+            stats: self.snapshot(),
+        })?;
+        Ok(Some(Section::new(SECTION_NAME, payload).optional(true)))
+    }
+
+    pub(crate) fn from_section(section: &Section) -> Result<Self> {
+        let persisted: PersistedAccess = rmp_serde::from_slice(&section.payload)?;
+        Ok(Self::restore(persisted.config, persisted.stats))
+    }
+}
+
+/// Payload of the `access_stats` section
+#[derive(Serialize, Deserialize)]
+struct PersistedAccess {
+    config: AccessTrackingConfig,
+    stats: AccessStats,
+}
+
+impl VectorDB {
+    /// Track query counts, scan volumes and per-cluster probe frequencies,
+    /// or stop tracking with `None`. Turning tracking on again starts from
+    /// zero.
+    pub fn set_access_tracking(&mut self, config: Option<AccessTrackingConfig>) {
+        self.access = config.map(AccessTracker::new);
+        self.mark_dirty(&[PersistSection::AppState]);
+    }
+
+    /// Access counters so far, if tracking is on
+    pub fn access_stats(&self) -> Option<AccessStats> {
+        self.access.as_ref().map(|a| a.snapshot())
+    }
+
+    /// The largest probe count whose expected scan fits `candidate_budget`
+    /// rows per query.
+    ///
+    /// Each cluster's list length is weighted by how often queries have
+    /// probed it, so a workload concentrated on large clusters is advised
+    /// to probe fewer of them. `None` without tracking, a built IVF index,
+    /// or any recorded probes.
+    pub fn suggest_num_probe(&self, candidate_budget: usize) -> Option<usize> {
+        let stats = self.access_stats()?;
+        let ivf = self.ivf_index.as_ref().filter(|_| self.index_built)?;
+        if stats.cluster_frequency.len() != ivf.num_lists() {
+            return None;
+        }
+
+        let total: f64 = stats.cluster_frequency.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let expected_list: f64 = stats
+            .cluster_frequency
+            .iter()
+            .enumerate()
+            .map(|(cluster, frequency)| frequency * ivf.inverted_list(cluster).len() as f64)
+            .sum::<f64>()
+            / total;
+        if expected_list <= 0.0 {
+            return Some(ivf.num_clusters());
+        }
+
+        let probes = (candidate_budget as f64 / expected_list).floor() as usize;
+        Some(probes.clamp(1, ivf.num_clusters()))
+    }
+
+    pub(crate) fn record_query(&self) {
+        if let Some(access) = &self.access {
+            access.record_query();
+        }
+    }
+
+    pub(crate) fn record_scan(&self, clusters: &[usize], candidates: usize) {
+        if let Some(access) = &self.access {
+            let num_clusters = self.ivf_index.as_ref().map_or(0, |ivf| ivf.num_lists());
+            access.record_scan(clusters, num_clusters, candidates);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probes_decay_by_half_life() {
+        let tracker = AccessTracker::new(AccessTrackingConfig {
+            half_life: 4,
+            persist: false,
+        });
+        tracker.record_query();
+        tracker.record_scan(&[0], 2, 10);
+        for _ in 0..4 {
+            tracker.record_query();
+        }
+        tracker.record_scan(&[1], 2, 10);
+
+        let stats = tracker.snapshot();
+        assert_eq!(stats.queries, 5);
+        assert_eq!(stats.candidates_scanned, 20);
+        assert_eq!(stats.clusters_probed, 2);
+        assert!((stats.cluster_frequency[0] - 0.5).abs() < 1e-9);
+        assert!((stats.cluster_frequency[1] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rescaling_keeps_frequencies() {
+        let tracker = AccessTracker::new(AccessTrackingConfig {
+            half_life: 1,
+            persist: false,
+        });
+        for _ in 0..400 {
+            tracker.record_query();
+            tracker.record_scan(&[0], 1, 1);
+        }
+
+        // A probe every query at half-life 1 converges to 1 + 1/2 + 1/4 ...
+        let frequency = tracker.snapshot().cluster_frequency[0];
+        assert!((frequency - 2.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::error::{KhadyotaError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Limits on concurrent searches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdmissionConfig {
+    /// Searches allowed to run at once
+    pub max_in_flight: usize,
+
+    /// Searches allowed to wait for a slot; beyond this they are rejected
+    /// immediately
+    pub max_queued: usize,
+
+    /// How long a queued search waits before it is rejected
+    pub queue_timeout: Duration,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            max_queued: 64,
+            queue_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Snapshot of admission counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionStats {
+    /// Searches admitted, with or without waiting
+    pub executed: u64,
+
+    /// Searches that had to wait for a slot
+    pub queued: u64,
+
+    /// Searches rejected with [`KhadyotaError::Overloaded`]
+    pub rejected: u64,
+
+    /// Searches currently holding a slot
+    pub in_flight: usize,
+
+    /// Searches currently waiting
+    pub waiting: usize,
+}
+
+#[derive(Debug, Default)]
+struct Slots {
+    in_flight: usize,
+    waiting: usize,
+
+    /// Ticket the next search to queue takes
+    next_ticket: u64,
+
+    /// Ticket of the queued search first in line
+    serving: u64,
+
+    /// Tickets behind `serving` whose searches timed out of the queue
+    abandoned: BTreeSet<u64>,
+}
+
+impl Slots {
+    /// Pass the head of the line to the next ticket still waiting
+    fn advance(&mut self) {
+        self.serving += 1;
+        while self.abandoned.remove(&self.serving) {
+            self.serving += 1;
+        }
+    }
+}
+
+/// Counting semaphore with a bounded, timed wait queue. Queued searches
+/// are admitted in the order they arrived, and a search arriving while
+/// others wait queues behind them even if a slot is free.
+#[derive(Debug)]
+pub struct AdmissionController {
+    config: AdmissionConfig,
+    slots: Mutex<Slots>,
+    released: Condvar,
+    executed: AtomicU64,
+    queued: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// A held search slot, released on drop
+#[derive(Debug)]
+pub struct AdmissionPermit<'a> {
+    controller: &'a AdmissionController,
+}
+
+impl Drop for AdmissionPermit<'_> {
+    fn drop(&mut self) {
+        let mut slots = self.controller.slots.lock().unwrap();
+        slots.in_flight -= 1;
+        drop(slots);
+        // Every waiter checks whether it is first in line
+        self.controller.released.notify_all();
+    }
+}
+
+impl AdmissionController {
+    pub fn new(config: AdmissionConfig) -> Result<Self> {
+        if config.max_in_flight == 0 {
+            return Err(KhadyotaError::InvalidConfig(
+                "max_in_flight must be > 0".to_string()
+            ));
+        }
+
+        Ok(Self {
+            config,
+            slots: Mutex::new(Slots::default()),
+            released: Condvar::new(),
+            executed: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    pub fn config(&self) -> &AdmissionConfig {
+        &self.config
+    }
+
+    /// Take a slot without waiting. Returns `Overloaded` if none is free,
+    /// or searches are queued for one, which lets async callers retry on
+    /// their own schedule instead of blocking an executor thread.
+    pub fn try_acquire(&self) -> Result<AdmissionPermit<'_>> {
+        let mut slots = self.slots.lock().unwrap();
+
+        if slots.waiting == 0 && slots.in_flight < self.config.max_in_flight {
+            slots.in_flight += 1;
+            self.executed.fetch_add(1, Ordering::Relaxed);
+            return Ok(AdmissionPermit { controller: self });
+        }
+
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        Err(self.overloaded(&slots))
+    }
+
+    /// Take a slot, queueing for up to `queue_timeout` behind searches
+    /// already waiting if none is free.
+    ///
+    /// On a rayon worker thread this never waits, failing as
+    /// [`AdmissionController::try_acquire`] does instead: the searches
+    /// holding slots may need the pool's workers to finish, so parking
+    /// one behind them can stall the pool until the timeout.
+    pub fn acquire(&self) -> Result<AdmissionPermit<'_>> {
+        let mut slots = self.slots.lock().unwrap();
+
+        if slots.waiting == 0 && slots.in_flight < self.config.max_in_flight {
+            slots.in_flight += 1;
+            self.executed.fetch_add(1, Ordering::Relaxed);
+            return Ok(AdmissionPermit { controller: self });
+        }
+
+        if slots.waiting >= self.config.max_queued || rayon::current_thread_index().is_some() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(self.overloaded(&slots));
+        }
+
+        slots.waiting += 1;
+        let ticket = slots.next_ticket;
+        slots.next_ticket += 1;
+        self.queued.fetch_add(1, Ordering::Relaxed);
+
+        let deadline = Instant::now() + self.config.queue_timeout;
+        while slots.serving != ticket || slots.in_flight >= self.config.max_in_flight {
+            let now = Instant::now();
+            if now >= deadline {
+                slots.waiting -= 1;
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                let error = self.overloaded(&slots);
+                if slots.serving == ticket {
+                    slots.advance();
+                    drop(slots);
+                    self.released.notify_all();
+                } else {
+                    slots.abandoned.insert(ticket);
+                }
+                return Err(error);
+            }
+            slots = self.released.wait_timeout(slots, deadline - now).unwrap().0;
+        }
+
+        slots.waiting -= 1;
+        slots.advance();
+        slots.in_flight += 1;
+        self.executed.fetch_add(1, Ordering::Relaxed);
+        drop(slots);
+        // The next in line may find a slot free as well
+        self.released.notify_all();
+
+        Ok(AdmissionPermit { controller: self })
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        let slots = self.slots.lock().unwrap();
+
+        AdmissionStats {
+            executed: self.executed.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            in_flight: slots.in_flight,
+            waiting: slots.waiting,
+        }
+    }
+
+    fn overloaded(&self, slots: &Slots) -> KhadyotaError {
+        KhadyotaError::Overloaded(format!(
+            "{} searches in flight, {} queued (limits {}/{})",
+            slots.in_flight, slots.waiting, self.config.max_in_flight, self.config.max_queued
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_and_timeout() {
+        let controller = AdmissionController::new(AdmissionConfig {
+            max_in_flight: 1,
+            max_queued: 1,
+            queue_timeout: Duration::from_millis(20),
+        })
+        .unwrap();
+
+        let permit = controller.acquire().unwrap();
+        assert!(matches!(controller.try_acquire(), Err(KhadyotaError::Overloaded(_))));
+
+        // The queue has room, but nothing is released before the timeout
+        let start = Instant::now();
+        assert!(matches!(controller.acquire(), Err(KhadyotaError::Overloaded(_))));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        drop(permit);
+        let _permit = controller.acquire().unwrap();
+
+        assert_eq!(
+            controller.stats(),
+            AdmissionStats {
+                executed: 2,
+                queued: 1,
+                rejected: 2,
+                in_flight: 1,
+                waiting: 0,
+            }
+        );
+
+        assert!(AdmissionController::new(AdmissionConfig {
+            max_in_flight: 0,
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_queued_searches_are_admitted_in_arrival_order() {
+        let controller = AdmissionController::new(AdmissionConfig {
+            max_in_flight: 1,
+            max_queued: 8,
+            queue_timeout: Duration::from_secs(10),
+        })
+        .unwrap();
+        let admitted = Mutex::new(Vec::new());
+
+        let permit = controller.acquire().unwrap();
+        std::thread::scope(|scope| {
+            for i in 0..5 {
+                let (controller, admitted) = (&controller, &admitted);
+                scope.spawn(move || {
+                    let _permit = controller.acquire().unwrap();
+                    admitted.lock().unwrap().push(i);
+                });
+                // Each waiter is queued before the next arrives
+                while controller.stats().waiting <= i {
+                    std::thread::yield_now();
+                }
+            }
+            // Nothing jumps the queue while a slot is taken or waited for
+            assert!(controller.try_acquire().is_err());
+            drop(permit);
+        });
+
+        assert_eq!(*admitted.lock().unwrap(), [0, 1, 2, 3, 4]);
+        assert_eq!(controller.stats().waiting, 0);
+        assert!(controller.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn test_searches_behind_a_timed_out_one_still_get_in() {
+        let controller = AdmissionController::new(AdmissionConfig {
+            max_in_flight: 1,
+            max_queued: 2,
+            queue_timeout: Duration::from_millis(30),
+        })
+        .unwrap();
+
+        let permit = controller.acquire().unwrap();
+        // Gives up its place at the head of the line
+        assert!(controller.acquire().is_err());
+
+        // The next to queue takes it over
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| controller.acquire().map(drop));
+            while controller.stats().waiting == 0 {
+                std::thread::yield_now();
+            }
+            drop(permit);
+            assert!(waiter.join().unwrap().is_ok());
+        });
+        assert_eq!(controller.stats().rejected, 1);
+    }
+
+    #[test]
+    fn test_rayon_workers_are_not_parked_in_the_queue() {
+        let controller = AdmissionController::new(AdmissionConfig {
+            max_in_flight: 1,
+            max_queued: 8,
+            queue_timeout: Duration::from_secs(10),
+        })
+        .unwrap();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+
+        let _permit = controller.acquire().unwrap();
+        let start = Instant::now();
+        assert!(matches!(pool.install(|| controller.acquire()), Err(KhadyotaError::Overloaded(_))));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(controller.stats().rejected, 1);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::compat::{CompatibilityReport, Violation};
+use crate::config::Config;
+use crate::error::{KhadyotaError, Result};
+use crate::indexing::IVFIndex;
+use crate::metadata::check_size;
+use crate::quantization::{PQCodec, Quantizer};
+use crate::storage::{QuantizedVectors, VectorStorage};
+use crate::vector_db::VectorDB;
+use std::collections::BTreeMap;
+
+/// A built database taken apart into the artifacts
+/// [`VectorDB::assemble`] takes, from [`VectorDB::export_parts`]
+#[derive(Debug, Clone)]
+pub struct PrebuiltParts {
+    pub vectors: Option<Vec<Vec<f32>>>,
+    pub codec: PQCodec,
+    /// PQ codes, one row per vector
+    pub codes: Vec<Vec<u8>>,
+    /// IVF centroids
+    pub centroids: Vec<Vec<f32>>,
+    /// IVF cluster of each vector
+    pub assignments: Vec<u32>,
+    pub metadata: BTreeMap<u32, serde_json::Value>,
+}
+
+impl VectorDB {
+    /// Build a ready-to-search database from artifacts produced elsewhere,
+    /// such as by an offline pipeline, without training or encoding.
+    ///
+    /// `codes[id]` are the PQ codes of vector `id` under `codec`, and
+    /// `assignments[id]` its cluster among `centroids`; inverted lists are
+    /// built from the assignments in id order. All parts are checked
+    /// against `config` and each other first, and every broken constraint
+    /// is reported in [`KhadyotaError::IncompatibleComponents`].
+    ///
+    /// Without `vectors` the rows are reconstructed from their codes, so
+    /// exact scoring, reranking and `get()` see the PQ approximation.
+    /// Under [`Config::normalize`] rows are scaled to unit length either
+    /// way, as inserts are.
+    pub fn assemble(
+        config: Config,
+        vectors: Option<Vec<Vec<f32>>>,
+        codec: PQCodec,
+        codes: Vec<Vec<u8>>,
+        centroids: Vec<Vec<f32>>,
+        assignments: Vec<u32>,
+        metadata: BTreeMap<u32, serde_json::Value>,
+    ) -> Result<Self> {
+        let mut db = Self::new(config)?;
+        if !db.config.uses_pq() {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "assemble() takes PQ codes, but the quantizer is {:?}; use from_parts() instead",
+                db.config.quantizer
+            )));
+        }
+        if let Some(vector) = vectors.iter().flatten().find(|v| v.len() != db.config.dimensions) {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: db.config.dimensions,
+                got: vector.len(),
+            });
+        }
+
+        let rows = vectors.as_ref().map_or(codes.len(), Vec::len);
+        let mut report = CompatibilityReport::default();
+        if codes.len() != rows {
+            report.violations.push(Violation::CodeCount {
+                expected: rows,
+                found: codes.len(),
+            });
+        }
+        report.check_assignments(rows, centroids.len(), &assignments);
+        report.into_result()?;
+
+        let num_probe = db.config.num_probe.clamp(1, centroids.len().max(1));
+        let ivf = IVFIndex::from_assignments(db.config.dimensions, centroids, &assignments, num_probe, db.config.metric);
+        let quantized = QuantizedVectors::from_codes(codec, codes)?;
+        CompatibilityReport::check(&db.config, rows, Some(&quantized), Some(&ivf)).into_result()?;
+
+        if let Some(&id) = metadata.keys().find(|&&id| id as usize >= rows) {
+            return Err(KhadyotaError::VectorNotFound(id));
+        }
+        for value in metadata.values() {
+            check_size(&db.config, value)?;
+        }
+
+        vectors.iter().flatten().try_for_each(|vector| db.check_row(vector))?;
+        let vectors = vectors.unwrap_or_else(|| {
+            let codec = quantized.codec();
+            quantized.iter_codes().map(|codes| codec.decode(codes)).collect()
+        });
+        let vectors = vectors.into_iter().map(|vector| db.stored_row(vector)).collect();
+        db.vectors = VectorStorage::from_rows(vectors, db.config.raw_precision, db.config.dimensions);
+        db.next_id = rows as u32;
+        db.quantized = Some(quantized);
+        db.ivf_index = Some(ivf);
+        db.index_built = true;
+        for (id, value) in metadata {
+            db.set_metadata(id, Some(value));
+        }
+        Ok(db)
+    }
+
+    /// The parts [`VectorDB::assemble`] would rebuild this database from.
+    ///
+    /// Needs an up-to-date PQ index (not SQ8) and no deleted entries, since
+    /// assignments cannot express either a row outside the index or a
+    /// tombstone. Codes must be of the rows themselves, which `assemble`
+    /// takes, not residuals. Entry attributes are not included.
+    pub fn export_parts(&self, include_vectors: bool) -> Result<PrebuiltParts> {
+        let (Some(quantized), Some(ivf), true) = (&self.quantized, &self.ivf_index, self.index_built) else {
+            return Err(KhadyotaError::IndexNotBuilt);
+        };
+        let Some(codec) = quantized.codec().pq() else {
+            return Err(KhadyotaError::InvalidConfig(match quantized.codec() {
+                Quantizer::OPQ(_) => "rotated codes cannot be exported; rebuild with use_opq off".to_string(),
+                codec => format!("only PQ codes can be exported, this index uses {:?}", codec.kind()),
+            }));
+        };
+        if quantized.is_residual() {
+            return Err(KhadyotaError::InvalidConfig(
+                "residual codes cannot be exported; rebuild with use_residuals off".to_string(),
+            ));
+        }
+        if !self.deleted.is_empty() {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "{} deleted entries cannot be exported as assignments",
+                self.deleted.len()
+            )));
+        }
+
+        let mut assignments = vec![None; self.vectors.len()];
+        for cluster in 0..ivf.num_lists() {
+            for &id in ivf.inverted_list(cluster) {
+                assignments[id as usize] = Some(cluster as u32);
+            }
+        }
+        let assignments = assignments
+            .into_iter()
+            .enumerate()
+            .map(|(id, cluster)| cluster.ok_or(id))
+            .collect::<std::result::Result<Vec<u32>, usize>>()
+            .map_err(|id| {
+                KhadyotaError::InvalidConfig(format!("vector {} is not in the index; call build_index() first", id))
+            })?;
+
+        Ok(PrebuiltParts {
+            vectors: include_vectors.then(|| self.vectors.as_rows().into_owned()),
+            codec: codec.clone(),
+            codes: quantized.iter_codes().map(<[u8]>::to_vec).collect(),
+            centroids: ivf.centroids().to_vec(),
+            assignments,
+            metadata: (0..self.next_id)
+                .filter_map(|id| Some((id, self.metadata_value(id)?.clone())))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn built_db() -> VectorDB {
+        let mut db = VectorDB::new(Config {
+            dimensions: 16,
+            pq_subvectors: 4,
+            num_clusters: 4,
+            seed: Some(2),
+            // Exports carry codes of the rows themselves
+            use_residuals: false,
+            ..Default::default()
+        })
+        .unwrap();
+        for i in 0..300 {
+            let vector = (0..16).map(|j| ((i * 16 + j) as f32 * 0.21).sin()).collect();
+            db.insert(vector, None).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_bad_assignments_are_reported_together() {
+        let db = built_db();
+        let mut parts = db.export_parts(true).unwrap();
+        parts.assignments.pop();
+        parts.assignments[3] = 9;
+        parts.assignments[7] = 4;
+
+        let result = VectorDB::assemble(
+            db.config.clone(),
+            parts.vectors,
+            parts.codec,
+            parts.codes,
+            parts.centroids,
+            parts.assignments,
+            parts.metadata,
+        );
+        let Err(KhadyotaError::IncompatibleComponents(report)) = result else {
+            panic!("expected IncompatibleComponents");
+        };
+        assert_eq!(
+            report.violations,
+            [
+                Violation::AssignmentCount { expected: 300, found: 299 },
+                Violation::AssignmentOutOfRange { clusters: 4, ids: 2, first_id: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_needs_a_current_index() {
+        let mut db = built_db();
+        db.insert(vec![0.5; 16], None).unwrap();
+        assert!(matches!(db.export_parts(false), Err(KhadyotaError::IndexNotBuilt)));
+    }
+}
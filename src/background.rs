@@ -0,0 +1,154 @@
+//! Index builds on a helper thread, under the `std-thread` feature.
+//!
+//! The build trains from a copy of the rows taken when it starts, holding
+//! no lock, so searches keep serving from the index already in place, or
+//! scan every row while the first build trains, and writes keep landing. Only taking
+//! the copy and installing the result hold the write lock. Rows written
+//! in between are encoded and assigned as the index goes in, as inserts
+//! into a built index are.
+
+use crate::config::IndexKind;
+use crate::error::{KhadyotaError, Result};
+use crate::progress::{BuildEvent, ProgressCallback, Silent};
+use crate::vector_db::VectorDB;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::thread::JoinHandle;
+
+/// A build started by [`VectorDB::build_index_background`]. Dropping the
+/// handle leaves the build running; it still installs its index.
+pub struct IndexBuildHandle {
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl IndexBuildHandle {
+    /// Ask the build to stop at its next k-means iteration or codebook.
+    /// The index in place is left as it was, and [`IndexBuildHandle::wait`]
+    /// reports [`KhadyotaError::Cancelled`].
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the build has finished, installed or not
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the build to finish: `Ok` once its index is in place. A
+    /// panic on the build thread resumes here.
+    pub fn wait(self) -> Result<()> {
+        self.thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// The database's progress callback, also reporting the handle's
+/// cancellation
+struct Cancellable {
+    progress: Arc<dyn ProgressCallback>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ProgressCallback for Cancellable {
+    fn on_event(&self, event: BuildEvent) {
+        self.progress.on_event(event)
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed) || self.progress.cancelled()
+    }
+}
+
+/// Clears the database's in-flight flag however the build ends, panics
+/// included
+struct InFlight(Arc<RwLock<VectorDB>>);
+
+impl InFlight {
+    fn lock(&self) -> RwLockWriteGuard<'_, VectorDB> {
+        self.0.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.lock().build_in_flight = false;
+    }
+}
+
+impl VectorDB {
+    /// [`VectorDB::build_index`] on a helper thread, returning at once.
+    ///
+    /// Searches through `db` keep serving from the current index while
+    /// the build trains, and writes keep landing; the new index replaces
+    /// the old under one short write lock, with rows written meanwhile
+    /// encoded and assigned as it goes in. Progress goes to the
+    /// database's callback, as for `build_index`. The build holds a copy
+    /// of the rows while it trains.
+    ///
+    /// Fails with [`KhadyotaError::BuildInProgress`] while another
+    /// background build is running, which `build_index` also reports
+    /// until it is done.
+    pub fn build_index_background(db: &Arc<RwLock<VectorDB>>) -> Result<IndexBuildHandle> {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (config, rows, seq, progress) = {
+            let mut guard = db.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if guard.build_in_flight {
+                return Err(KhadyotaError::BuildInProgress);
+            }
+            if guard.vectors.is_empty() {
+                return Err(KhadyotaError::InvalidConfig("Cannot build index with no vectors".to_string()));
+            }
+            guard.build_in_flight = true;
+            let progress = Cancellable {
+                progress: guard.progress.clone().unwrap_or_else(|| Arc::new(Silent)),
+                cancel: Arc::clone(&cancel),
+            };
+            // A flat build copies the rows itself, under the lock
+            let rows = match guard.config.index {
+                IndexKind::Flat => Vec::new(),
+                IndexKind::IVF => guard.vectors.as_rows().into_owned(),
+            };
+            (guard.config.clone(), rows, guard.seq, progress)
+        };
+
+        let in_flight = InFlight(Arc::clone(db));
+        let thread = std::thread::spawn(move || {
+            if config.index == IndexKind::Flat {
+                let mut db = in_flight.lock();
+                progress.on_event(BuildEvent::IndexBuildStarted {
+                    vectors: db.vectors.len(),
+                    dimensions: config.dimensions,
+                });
+                if progress.cancelled() {
+                    return Err(KhadyotaError::Cancelled);
+                }
+                return db.install_flat_index(&progress);
+            }
+            progress.on_event(BuildEvent::IndexBuildStarted {
+                vectors: rows.len(),
+                dimensions: config.dimensions,
+            });
+            let trained = VectorDB::train_index(&config, &rows, &progress)?;
+
+            let mut db = in_flight.lock();
+            if progress.cancelled() {
+                return Err(KhadyotaError::Cancelled);
+            }
+            // Rows appended since the copy, and any rewritten; the
+            // comparison only runs when something was written
+            let mut changed: Vec<u32> = Vec::new();
+            if db.seq != seq {
+                changed.extend(
+                    rows.iter()
+                        .enumerate()
+                        .filter(|&(id, row)| db.vectors.get(id as u32).is_some_and(|now| *now != **row))
+                        .map(|(id, _)| id as u32),
+                );
+                changed.extend(rows.len() as u32..db.vectors.len() as u32);
+            }
+            db.install_index(trained, changed, &progress)
+        });
+
+        Ok(IndexBuildHandle { cancel, thread })
+    }
+}
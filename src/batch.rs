@@ -0,0 +1,100 @@
+//! Batches of queries laid out back to back in one slice, scored with
+//! working buffers kept per thread
+
+use crate::error::{KhadyotaError, Result};
+use crate::parallel::chunk_len;
+use crate::search_params::SearchParams;
+use crate::select::TopK;
+use crate::storage::ScanScratch;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use rayon::prelude::*;
+use std::time::Instant;
+
+/// Buffers one search works in, kept from query to query so a batch
+/// allocates them once per thread rather than once per query
+#[derive(Default)]
+pub(crate) struct SearchScratch {
+    /// Ids listed in the probed clusters
+    pub(crate) candidates: Vec<u32>,
+    /// Keys of the candidates, in order, as the codes score them
+    pub(crate) keys: Vec<f32>,
+    /// Code rows and flattened tables of each block scanned
+    pub(crate) scan: ScanScratch,
+    /// Candidates with their keys, then the unencoded rows
+    pub(crate) scored: Vec<(u32, f32)>,
+    /// Distance table of the query
+    pub(crate) table: Vec<Vec<f32>>,
+    /// Heap selecting the nearest
+    pub(crate) top: TopK,
+}
+
+impl VectorDB {
+    /// [`VectorDB::batch_search`] over `num_queries` queries laid out
+    /// back to back in `queries`, as read from a file or another
+    /// library's buffer.
+    ///
+    /// The length is checked once for the whole batch. With an IVF + PQ
+    /// index every query is probed up front, from one matrix of query to
+    /// centroid distances computed a tile of centroids at a time, and
+    /// each thread then scores its queries in buffers it reuses: the
+    /// candidate list, the distance table and the heap picking the `k`
+    /// nearest. Results are those [`VectorDB::search`] gives each query.
+    pub fn batch_search_flat(&self, queries: &[f32], num_queries: usize, k: usize) -> Result<Vec<Vec<SearchResult>>> {
+        let dimensions = self.config.dimensions;
+        if num_queries.checked_mul(dimensions) != Some(queries.len()) {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "flat queries hold {} floats, not {} queries of {} dimensions",
+                queries.len(),
+                num_queries,
+                dimensions
+            )));
+        }
+        if !self.is_searchable() {
+            return Err(KhadyotaError::IndexNotBuilt);
+        }
+        if num_queries == 0 {
+            return Ok(Vec::new());
+        }
+
+        let queries = queries
+            .chunks_exact(dimensions)
+            .map(|query| self.check_query(query))
+            .collect::<Result<Vec<_>>>()?;
+
+        // One slot covers the whole batch
+        let _permit = self.admit()?;
+        let params = SearchParams::default();
+        let fingerprint = params.fingerprint();
+        let rows: Vec<&[f32]> = queries.iter().map(|query| &query[..]).collect();
+        let matrix = match (&self.ivf_index, &self.quantized) {
+            (Some(ivf), Some(_)) => Some((ivf.centroid_distance_matrix(&rows), ivf.num_clusters())),
+            _ => None,
+        };
+        let distances = |q: usize| matrix.as_ref().map(|(matrix, width)| &matrix[q * width..(q + 1) * width]);
+
+        rows.par_iter()
+            .enumerate()
+            .with_min_len(chunk_len(self.query_cost()))
+            .map_init(SearchScratch::default, |scratch, (q, query)| {
+                let start = self.latency.as_ref().map(|_| Instant::now());
+                let results = match &self.query_cache {
+                    None => self.search_in(query, k, &params, distances(q), scratch)?,
+                    Some(cache) => match cache.get(query, k, fingerprint, self.generation) {
+                        Some(results) => {
+                            self.record_query();
+                            results
+                        }
+                        None => {
+                            let results = self.search_in(query, k, &params, distances(q), scratch)?;
+                            cache.insert(query, k, fingerprint, self.generation, &results);
+                            results
+                        }
+                    },
+                };
+                self.record_latency(start);
+                Ok(results)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,213 @@
+use crate::changelog::ChangeOp;
+use crate::error::{KhadyotaError, Result};
+use crate::metadata::check_size;
+use crate::persist::PersistSection;
+use crate::vector_db::VectorDB;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+impl VectorDB {
+    /// Insert many vectors at once, returning their ids in order.
+    ///
+    /// Every item is checked before anything is stored, so a wrong
+    /// dimension or oversized metadata rejects the whole batch; the error
+    /// is [`KhadyotaError::InvalidChange`] with the item's position.
+    /// Storage is reserved once and the batch is one changelog record,
+    /// rather than a record and an index invalidation per vector.
+    pub fn insert_batch<I>(&mut self, items: I) -> Result<Vec<u32>>
+    where
+        I: IntoIterator<Item = (Vec<f32>, Option<Value>)>,
+    {
+        let items: Vec<_> = items.into_iter().collect();
+        for (op, (vector, metadata)) in items.iter().enumerate() {
+            self.check_batch_item(vector, metadata.as_ref())
+                .map_err(|source| KhadyotaError::InvalidChange {
+                    op,
+                    source: Box::new(source),
+                })?;
+        }
+
+        let rows = items
+            .into_iter()
+            .map(|(vector, metadata)| (self.stored_row(vector), metadata))
+            .collect();
+        self.push_rows(rows)
+    }
+
+    /// Insert `data.len() / dimensions` vectors laid out back to back in
+    /// one slice, as read from a file or another library's buffer.
+    ///
+    /// `metadata` is empty, or holds one entry per vector. Validation and
+    /// atomicity are as for [`VectorDB::insert_batch`].
+    pub fn insert_flat(&mut self, data: &[f32], metadata: Vec<Option<Value>>) -> Result<Vec<u32>> {
+        let dimensions = self.config.dimensions;
+        if !data.len().is_multiple_of(dimensions) {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "flat data holds {} floats, not a whole number of {}-dimension vectors",
+                data.len(),
+                dimensions
+            )));
+        }
+        let rows = data.len() / dimensions;
+        if !metadata.is_empty() && metadata.len() != rows {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "{} metadata entries for {} vectors",
+                metadata.len(),
+                rows
+            )));
+        }
+        for (op, row) in data.chunks_exact(dimensions).enumerate() {
+            self.check_batch_item(row, metadata.get(op).and_then(Option::as_ref))
+                .map_err(|source| KhadyotaError::InvalidChange {
+                    op,
+                    source: Box::new(source),
+                })?;
+        }
+
+        let mut metadata = metadata.into_iter();
+        let rows = data
+            .chunks_exact(dimensions)
+            .map(|row| (self.stored_row(row.to_vec()), metadata.next().flatten()))
+            .collect();
+        self.push_rows(rows)
+    }
+
+    fn check_batch_item(&self, vector: &[f32], metadata: Option<&Value>) -> Result<()> {
+        if vector.len() != self.config.dimensions {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: self.config.dimensions,
+                got: vector.len(),
+            });
+        }
+        self.check_row(vector)?;
+        match metadata {
+            Some(metadata) => check_size(&self.config, metadata),
+            None => Ok(()),
+        }
+    }
+
+    /// Append checked, stored `rows` as one change, journaled before any
+    /// is stored, encoding them now if the encode policy says so
+    fn push_rows(&mut self, rows: Vec<(Vec<f32>, Option<Value>)>) -> Result<Vec<u32>> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let first = self.next_id;
+        self.journal_next(|_| {
+            (first..)
+                .zip(&rows)
+                .map(|(id, (vector, metadata))| ChangeOp::Put {
+                    id,
+                    vector: vector.clone(),
+                    metadata: metadata.clone(),
+                    attributes: None,
+                    index: None,
+                    external_id: None,
+                })
+                .collect()
+        })?;
+
+        self.vectors.reserve(rows.len());
+        for (vector, metadata) in rows {
+            self.vectors.push(vector);
+            self.set_metadata(self.next_id, metadata);
+            self.next_id += 1;
+        }
+        let ids = first..self.next_id;
+        self.mark_dirty(&[PersistSection::Vectors]);
+        let eager = self.encode_on_insert(ids.len());
+        let clusters = if eager {
+            self.maintain_index(first, &[], &BTreeSet::new())
+        } else {
+            self.mark_index_stale(first, ids.clone());
+            HashMap::new()
+        };
+        self.generation += 1;
+        self.log_change(|db| {
+            ids.clone()
+                .map(|id| db.put_op(id, eager.then(|| db.index_entry(id, &clusters))))
+                .collect()
+        })?;
+        Ok(ids.collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuantizerKind};
+    use serde_json::json;
+
+    fn db() -> VectorDB {
+        VectorDB::new(Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            num_clusters: 2,
+            num_probe: 2,
+            max_metadata_bytes: 32,
+            normalize: false,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_batch_matches_single_inserts() {
+        let rows: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32; 4]).collect();
+        let metadata = |i: usize| i.is_multiple_of(3).then(|| json!({ "i": i }));
+
+        let mut one_by_one = db();
+        for (i, row) in rows.iter().enumerate() {
+            one_by_one.insert(row.clone(), metadata(i)).unwrap();
+        }
+
+        let mut batched = db();
+        batched.insert(vec![0.5; 4], None).unwrap();
+        batched.delete(0).unwrap();
+        let ids = batched
+            .insert_batch(rows.iter().cloned().enumerate().map(|(i, row)| (row, metadata(i))))
+            .unwrap();
+        assert_eq!(ids, (1..11).collect::<Vec<_>>());
+
+        let mut flat = db();
+        let data: Vec<f32> = rows.concat();
+        let ids = flat.insert_flat(&data, (0..10).map(metadata).collect()).unwrap();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+        assert_eq!(flat.checksum(), one_by_one.checksum());
+
+        for i in 0..10u32 {
+            assert_eq!(batched.get(i + 1).unwrap().vector, rows[i as usize]);
+            assert_eq!(batched.get(i + 1).unwrap().metadata, metadata(i as usize));
+        }
+        assert!(flat.insert_batch(Vec::new()).unwrap().is_empty());
+        assert!(flat.insert_flat(&[], Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_batches_change_nothing() {
+        let mut db = db();
+        db.insert(vec![1.0; 4], None).unwrap();
+        let checksum = db.checksum();
+
+        let err = db
+            .insert_batch([(vec![0.0; 4], None), (vec![0.0; 4], None), (vec![0.0; 3], None)])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            KhadyotaError::InvalidChange { op: 2, ref source }
+                if matches!(**source, KhadyotaError::DimensionMismatch { expected: 4, got: 3 })
+        ));
+
+        let big = Some(json!({ "text": "x".repeat(64) }));
+        let err = db.insert_flat(&[0.0; 8], vec![None, big]).unwrap_err();
+        assert!(matches!(
+            err,
+            KhadyotaError::InvalidChange { op: 1, ref source } if matches!(**source, KhadyotaError::MetadataTooLarge { .. })
+        ));
+        assert!(matches!(db.insert_flat(&[0.0; 7], Vec::new()), Err(KhadyotaError::InvalidConfig(_))));
+        assert!(matches!(db.insert_flat(&[0.0; 8], vec![None]), Err(KhadyotaError::InvalidConfig(_))));
+
+        assert_eq!(db.len(), 1);
+        assert_eq!(db.checksum(), checksum);
+    }
+}
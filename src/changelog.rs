@@ -0,0 +1,647 @@
+use crate::compat::{CompatibilityReport, Violation};
+use crate::config::DistanceMetric;
+use crate::error::{KhadyotaError, Result};
+use crate::external_ids::ExternalId;
+use crate::indexing::{FlatIndex, IVFIndex};
+use crate::maintenance::IndexLag;
+use crate::persist::PersistSection;
+use crate::storage::QuantizedVectors;
+use crate::types::EntryAttributes;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const FORMAT: &str = "khadyota-changelog";
+const FORMAT_VERSION: u32 = 1;
+
+/// Where a written row sits in the writer's index, so readers can place
+/// it without re-encoding or re-assigning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// PQ codes, when the index uses PQ
+    pub codes: Option<Vec<u8>>,
+
+    /// Inverted list holding the row; `None` if it is tombstoned
+    pub cluster: Option<usize>,
+}
+
+/// One change to a database's state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChangeOp {
+    /// Write row `id`, appending when it is the next id. Metadata,
+    /// attributes and external id are the row's complete new values.
+    Put {
+        id: u32,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+        attributes: Option<EntryAttributes>,
+        /// `None` when the write left the writer's index stale
+        index: Option<IndexEntry>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        external_id: Option<ExternalId>,
+    },
+
+    SetMetadata {
+        id: u32,
+        metadata: Option<serde_json::Value>,
+    },
+
+    Delete { id: u32 },
+
+    /// The writer rebuilt its index; carries the trained index so readers
+    /// need not retrain
+    IndexSwap {
+        ivf: IVFIndex,
+        quantized: Option<QuantizedVectors>,
+    },
+
+    /// The writer compacted away its tombstoned rows; readers do the same,
+    /// which renumbers their rows as it did the writer's
+    Compact,
+
+    /// The writer built a flat index; readers copy their own rows, which
+    /// are the same
+    FlatIndexBuilt,
+}
+
+/// The changes made by one writer call, applied together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    pub seq: u64,
+    pub ops: Vec<ChangeOp>,
+}
+
+/// Everything an index change replaces, kept to put back if the change
+/// cannot be journaled
+pub(crate) struct IndexState {
+    quantized: Option<QuantizedVectors>,
+    ivf_index: Option<IVFIndex>,
+    flat_index: Option<FlatIndex>,
+    index_built: bool,
+    index_lag: Option<IndexLag>,
+    last_build: Option<SystemTime>,
+}
+
+/// Opens each stream written by [`VectorDB::set_changelog`]
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ChangelogHeader {
+    format: String,
+    version: u32,
+    dimensions: usize,
+    metric: DistanceMetric,
+    /// Writer's `applied_seq()` when the stream began
+    seq: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Frame {
+    Header(ChangelogHeader),
+    Record(ChangeRecord),
+}
+
+/// Destination for a writer's change records
+pub(crate) struct ChangelogSink(Mutex<Box<dyn Write + Send>>);
+
+impl fmt::Debug for ChangelogSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ChangelogSink")
+    }
+}
+
+impl ChangelogSink {
+    fn write(&self, frame: &Frame) -> Result<()> {
+        let mut writer = self.0.lock().unwrap();
+        rmp_serde::encode::write_named(&mut *writer, frame)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl VectorDB {
+    /// Stream every later change to `sink` as self-describing MessagePack
+    /// records, or stop streaming with `None`.
+    ///
+    /// Each stream opens with a header, so streams from successive writer
+    /// sessions can be appended to one log. A reader holding a snapshot
+    /// catches up with [`VectorDB::apply_changelog`].
+    ///
+    /// A change whose record fails to write is still applied here and the
+    /// error is returned; readers will then stop at the gap.
+    pub fn set_changelog(&mut self, sink: Option<Box<dyn Write + Send>>) -> Result<()> {
+        self.changelog = None;
+        let Some(sink) = sink else {
+            return Ok(());
+        };
+
+        let sink = ChangelogSink(Mutex::new(sink));
+        sink.write(&self.changelog_header())?;
+        self.changelog = Some(sink);
+        Ok(())
+    }
+
+    /// Header opening a stream of this database's changes from now on
+    pub(crate) fn changelog_header(&self) -> Frame {
+        Frame::Header(ChangelogHeader {
+            format: FORMAT.to_string(),
+            version: FORMAT_VERSION,
+            dimensions: self.config.dimensions,
+            metric: self.config.metric,
+            seq: self.seq,
+        })
+    }
+
+    /// Sequence number of the last change made or applied; saved with the
+    /// database, so a loaded snapshot knows where its changelog resumes
+    pub fn applied_seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Catch up with a writer by applying its changelog.
+    ///
+    /// Records at or below [`VectorDB::applied_seq`] are skipped, so
+    /// replaying a log, or one that overlaps the snapshot, is harmless.
+    /// Nothing is retrained: rows arrive with their PQ codes and cluster,
+    /// and rebuilt indexes arrive whole. Returns the number of records
+    /// applied.
+    pub fn apply_changelog<R: BufRead>(&mut self, mut reader: R) -> Result<u64> {
+        let mut applied = 0;
+
+        while !reader.fill_buf()?.is_empty() {
+            if self.apply_frame(rmp_serde::from_read(&mut reader)?)? {
+                applied += 1;
+            }
+        }
+
+        if applied > 0 {
+            self.changes_applied();
+        }
+        Ok(applied)
+    }
+
+    /// Check a header, or apply a record not yet applied. Returns whether
+    /// a record was applied; once done applying, call
+    /// [`VectorDB::changes_applied`].
+    pub(crate) fn apply_frame(&mut self, frame: Frame) -> Result<bool> {
+        let record = match frame {
+            Frame::Header(header) => {
+                self.check_changelog_header(&header)?;
+                return Ok(false);
+            }
+            Frame::Record(record) => record,
+        };
+        if record.seq <= self.seq {
+            return Ok(false);
+        }
+        if record.seq != self.seq + 1 {
+            return Err(KhadyotaError::ChangelogGap {
+                applied: self.seq,
+                found: record.seq,
+            });
+        }
+        self.check_record(&record)?;
+        for op in record.ops {
+            self.apply_op(op);
+        }
+        self.seq = record.seq;
+        Ok(true)
+    }
+
+    /// Invalidate what applied records may have changed
+    pub(crate) fn changes_applied(&mut self) {
+        self.generation += 1;
+        self.mark_dirty(&[
+            PersistSection::Vectors,
+            PersistSection::Codes,
+            PersistSection::Index,
+            PersistSection::Metadata,
+        ]);
+    }
+
+    /// Journal `ops`, built now, as the next change. Writers call this
+    /// before touching anything in memory, so a journal that cannot take
+    /// the record fails the write with the database as it was. Rows are
+    /// journaled without their index entries; replay leaves them for the
+    /// index to catch up.
+    pub(crate) fn journal_next(&self, ops: impl FnOnce(&Self) -> Vec<ChangeOp>) -> Result<()> {
+        match &self.journal {
+            Some(journal) => journal.append(&Frame::Record(ChangeRecord {
+                seq: self.seq + 1,
+                ops: ops(self),
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Journal an index change already made, which is only known once
+    /// made, putting `previous` back if the journal cannot take it
+    pub(crate) fn journal_or_restore(
+        &mut self,
+        previous: Option<IndexState>,
+        ops: impl FnOnce(&Self) -> Vec<ChangeOp>,
+    ) -> Result<()> {
+        let journaled = self.journal_next(ops);
+        if journaled.is_err()
+            && let Some(previous) = previous
+        {
+            self.restore_index_state(previous);
+        }
+        journaled
+    }
+
+    /// Take the next sequence number and, if streaming, write the ops
+    /// built by `ops`. A journal already holds the change, from
+    /// [`VectorDB::journal_next`] or [`VectorDB::journal_or_restore`].
+    pub(crate) fn log_change(&mut self, ops: impl FnOnce(&Self) -> Vec<ChangeOp>) -> Result<()> {
+        self.seq += 1;
+        self.mark_dirty(&[PersistSection::Metadata]);
+        match &self.changelog {
+            Some(sink) => sink.write(&Frame::Record(ChangeRecord {
+                seq: self.seq,
+                ops: ops(self),
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Move the index out, for a rebuild replacing it
+    pub(crate) fn take_index_state(&mut self) -> IndexState {
+        IndexState {
+            quantized: self.quantized.take(),
+            ivf_index: self.ivf_index.take(),
+            flat_index: self.flat_index.take(),
+            index_built: self.index_built,
+            index_lag: self.index_lag.take(),
+            last_build: self.last_build,
+        }
+    }
+
+    /// Copy the index, for a change made to it in place
+    pub(crate) fn clone_index_state(&self) -> IndexState {
+        IndexState {
+            quantized: self.quantized.clone(),
+            ivf_index: self.ivf_index.clone(),
+            flat_index: self.flat_index.clone(),
+            index_built: self.index_built,
+            index_lag: self.index_lag.clone(),
+            last_build: self.last_build,
+        }
+    }
+
+    fn restore_index_state(&mut self, state: IndexState) {
+        self.quantized = state.quantized;
+        self.ivf_index = state.ivf_index;
+        self.flat_index = state.flat_index;
+        self.index_built = state.index_built;
+        self.index_lag = state.index_lag;
+        self.last_build = state.last_build;
+    }
+
+    /// Row `id` as it stands now
+    pub(crate) fn put_op(&self, id: u32, index: Option<IndexEntry>) -> ChangeOp {
+        ChangeOp::Put {
+            id,
+            vector: self.vectors.get(id).unwrap().to_vec(),
+            metadata: self.metadata_value(id).cloned(),
+            attributes: self.attributes.get(&id).cloned(),
+            index,
+            external_id: self.external_ids.external(id).cloned(),
+        }
+    }
+
+    fn check_changelog_header(&self, header: &ChangelogHeader) -> Result<()> {
+        if header.format != FORMAT || header.version != FORMAT_VERSION {
+            return Err(KhadyotaError::SerializationError(format!(
+                "Unsupported changelog format: {} version {}",
+                header.format, header.version
+            )));
+        }
+        if header.dimensions != self.config.dimensions {
+            return Err(KhadyotaError::ConfigMismatch {
+                field: "dimensions",
+                expected: self.config.dimensions.to_string(),
+                found: header.dimensions.to_string(),
+            });
+        }
+        if header.metric != self.config.metric {
+            return Err(KhadyotaError::ConfigMismatch {
+                field: "metric",
+                expected: format!("{:?}", self.config.metric),
+                found: format!("{:?}", header.metric),
+            });
+        }
+        if header.seq > self.seq {
+            return Err(KhadyotaError::ChangelogGap {
+                applied: self.seq,
+                found: header.seq + 1,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reject a record that does not fit this database before applying
+    /// any of it
+    fn check_record(&self, record: &ChangeRecord) -> Result<()> {
+        let mut rows = self.vectors.len() as u32;
+        let corrupt = |op: usize, source: KhadyotaError| KhadyotaError::InvalidChange {
+            op,
+            source: Box::new(source),
+        };
+
+        for (op, change) in record.ops.iter().enumerate() {
+            match change {
+                ChangeOp::Put { id, vector, .. } => {
+                    if vector.len() != self.config.dimensions {
+                        return Err(corrupt(op, KhadyotaError::DimensionMismatch {
+                            expected: self.config.dimensions,
+                            got: vector.len(),
+                        }));
+                    }
+                    if *id > rows {
+                        return Err(corrupt(op, KhadyotaError::VectorNotFound(*id)));
+                    }
+                    if *id == rows {
+                        rows += 1;
+                    }
+                }
+                ChangeOp::SetMetadata { id, .. } | ChangeOp::Delete { id } => {
+                    if *id >= rows {
+                        return Err(corrupt(op, KhadyotaError::VectorNotFound(*id)));
+                    }
+                }
+                ChangeOp::IndexSwap { ivf, quantized } => {
+                    CompatibilityReport::check(&self.config, rows as usize, quantized.as_ref(), Some(ivf))
+                        .into_result()
+                        .map_err(|e| corrupt(op, e))?;
+                }
+                ChangeOp::Compact | ChangeOp::FlatIndexBuilt => {}
+            }
+            if let ChangeOp::Put { id, index: Some(IndexEntry { codes: Some(codes), .. }), .. } = change
+                && let Some(quantized) = &self.quantized
+                && codes.len() != quantized.codec().code_bytes()
+            {
+                let report = CompatibilityReport {
+                    violations: vec![Violation::CodeWidth {
+                        expected: quantized.codec().code_bytes(),
+                        found: codes.len(),
+                        rows: 1,
+                        first_id: *id,
+                    }],
+                };
+                return Err(corrupt(op, KhadyotaError::IncompatibleComponents(report)));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_op(&mut self, op: ChangeOp) {
+        match op {
+            ChangeOp::Put { id, vector, metadata, attributes, index, external_id } => {
+                let rows_before = self.next_id;
+                if id as usize == self.vectors.len() {
+                    self.vectors.push(vector);
+                    self.next_id += 1;
+                } else {
+                    self.vectors.set(id, vector);
+                }
+                self.set_metadata(id, metadata);
+                match attributes {
+                    Some(attributes) => self.attributes.insert(id, attributes),
+                    None => self.attributes.remove(&id),
+                };
+                self.bind_external_id(id, external_id);
+                self.deleted.remove(&id);
+
+                let Some(index) = index else {
+                    self.mark_index_stale(rows_before, [id]);
+                    return;
+                };
+                if let (Some(quantized), Some(codes)) = (&mut self.quantized, index.codes) {
+                    quantized.put_codes(id, codes);
+                }
+                if let Some(ivf) = &mut self.ivf_index {
+                    ivf.remove_ids(&BTreeSet::from([id]));
+                    if let Some(cluster) = index.cluster {
+                        ivf.add_to_cluster(cluster, &[id]);
+                    }
+                }
+                if let Some(flat) = &mut self.flat_index {
+                    flat.put(id, &self.vectors.get(id).unwrap());
+                }
+            }
+            ChangeOp::SetMetadata { id, metadata } => self.set_metadata(id, metadata),
+            ChangeOp::Delete { id } => {
+                self.deleted.insert(id);
+                self.set_metadata(id, None);
+                self.attributes.remove(&id);
+                self.bind_external_id(id, None);
+                if self.index_built && self.flat_index.is_some() {
+                    // Flat scans skip tombstones
+                } else if self.index_built
+                    && let Some(ivf) = &mut self.ivf_index
+                {
+                    ivf.remove_ids(&BTreeSet::from([id]));
+                } else {
+                    self.mark_index_stale(self.next_id, [id]);
+                }
+            }
+            ChangeOp::IndexSwap { ivf, quantized } => {
+                self.ivf_index = Some(ivf);
+                self.quantized = quantized;
+                self.flat_index = None;
+                self.index_built = true;
+                self.index_lag = None;
+                if let Some(access) = &self.access {
+                    access.reset_clusters();
+                }
+                self.last_build = Some(SystemTime::now());
+            }
+            ChangeOp::Compact => {
+                self.compact_rows();
+            }
+            ChangeOp::FlatIndexBuilt => {
+                self.flat_index = Some(self.flat_copy());
+                self.ivf_index = None;
+                self.quantized = None;
+                self.index_built = true;
+                self.index_lag = None;
+                self.last_build = Some(SystemTime::now());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changeset::ChangeSet;
+    use crate::config::{Config, QuantizerKind};
+    use crate::insert_options::InsertOptions;
+    use crate::search_params::SearchParams;
+    use std::sync::Arc;
+    use tempfile::NamedTempFile;
+
+    /// A sink the test can read back while the writer holds it
+    #[derive(Clone, Default)]
+    struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedLog {
+        fn bytes(&self) -> Vec<u8> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    fn vector(i: u32) -> Vec<f32> {
+        (0..16).map(|j| ((i * 16 + j) as f32 * 0.73).sin()).collect()
+    }
+
+    fn build_writer() -> VectorDB {
+        let config = Config {
+            dimensions: 16,
+            metric: DistanceMetric::Euclidean,
+            quantizer: QuantizerKind::PQ,
+            pq_subvectors: 4,
+            num_clusters: 8,
+            num_probe: 8,
+            seed: Some(5),
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..500 {
+            db.insert(vector(i), Some(serde_json::json!({"i": i}))).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    fn ids(db: &VectorDB, query: &[f32]) -> Vec<(u32, f32)> {
+        let params = SearchParams::builder().rerank(20).build();
+        db.search_with_params(query, 10, &params)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.id, r.distance))
+            .collect()
+    }
+
+    #[test]
+    fn test_reader_catches_up_from_snapshot() {
+        let mut writer = build_writer();
+        let snapshot = NamedTempFile::new().unwrap();
+        writer.save(snapshot.path()).unwrap();
+        let mut reader = VectorDB::load(snapshot.path()).unwrap();
+        assert_eq!(reader.applied_seq(), writer.applied_seq());
+
+        let log = SharedLog::default();
+        writer.set_changelog(Some(Box::new(log.clone()))).unwrap();
+
+        // Changes that keep the index built...
+        let mut changeset = ChangeSet::new();
+        for i in 500..600 {
+            changeset.insert(vector(i), None);
+        }
+        changeset.update(3, vector(1_000), None).update_metadata(4, None).delete(5).delete(550);
+        writer.apply_changeset(changeset).unwrap();
+        let mid = log.bytes();
+
+        // ...then one that makes it stale, and a rebuild
+        writer
+            .insert_opts(vector(7), InsertOptions::new().id(7).upsert(true).tenant("t"))
+            .unwrap();
+        writer.insert_with_id("doc", vector(2_000), None).unwrap();
+        writer.build_index().unwrap();
+        let mut changeset = ChangeSet::new();
+        changeset.delete(9).insert(vector(3_000), None);
+        writer.apply_changeset(changeset).unwrap();
+
+        assert_eq!(reader.apply_changelog(mid.as_slice()).unwrap(), 1);
+        assert_eq!(reader.checksum(), {
+            let mut partial = VectorDB::load(snapshot.path()).unwrap();
+            partial.apply_changelog(mid.as_slice()).unwrap();
+            partial.checksum()
+        });
+        assert!(reader.index_built);
+
+        // The full log overlaps what was applied; the overlap is skipped
+        let full = log.bytes();
+        assert_eq!(reader.apply_changelog(full.as_slice()).unwrap(), 4);
+        assert_eq!(reader.applied_seq(), writer.applied_seq());
+        assert_eq!(reader.checksum(), writer.checksum());
+        assert_eq!(reader.len(), writer.len());
+        assert_eq!(reader.id_of("doc"), Some(600));
+        for i in [0, 3, 77, 555, 3_000] {
+            let query = vector(i);
+            assert_eq!(ids(&reader, &query), ids(&writer, &query));
+        }
+
+        // Replaying everything again changes nothing
+        assert_eq!(reader.apply_changelog(full.as_slice()).unwrap(), 0);
+        assert_eq!(reader.checksum(), writer.checksum());
+    }
+
+    #[test]
+    fn test_gaps_and_mismatches_are_refused() {
+        let mut writer = build_writer();
+        let mut stale = build_writer();
+        writer.insert(vector(900), None).unwrap();
+
+        // The stream starts after a change the reader never saw
+        let log = SharedLog::default();
+        writer.set_changelog(Some(Box::new(log.clone()))).unwrap();
+        writer.insert(vector(901), None).unwrap();
+        match stale.apply_changelog(log.bytes().as_slice()) {
+            Err(KhadyotaError::ChangelogGap { applied, found }) => {
+                assert_eq!((applied, found), (501, 503));
+            }
+            other => panic!("expected ChangelogGap, got {:?}", other.err()),
+        }
+        assert_eq!(stale.len(), 500);
+
+        let mut other = VectorDB::new(Config {
+            dimensions: 8,
+            quantizer: QuantizerKind::None,
+            ..Default::default()
+        })
+        .unwrap();
+        match other.apply_changelog(log.bytes().as_slice()) {
+            Err(KhadyotaError::ConfigMismatch { field, .. }) => assert_eq!(field, "dimensions"),
+            other => panic!("expected ConfigMismatch, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_streams_from_successive_sessions_concatenate() {
+        let mut writer = build_writer();
+        let mut reader = build_writer();
+        let log = SharedLog::default();
+
+        writer.set_changelog(Some(Box::new(log.clone()))).unwrap();
+        writer.insert(vector(900), None).unwrap();
+        writer.set_changelog(None).unwrap();
+        writer.insert(vector(901), None).unwrap();
+        assert!(log.bytes().len() < 1_000);
+
+        // A later session resumes the same log; the unlogged insert is a gap
+        writer.set_changelog(Some(Box::new(log.clone()))).unwrap();
+        writer.insert(vector(902), None).unwrap();
+        assert!(matches!(
+            reader.apply_changelog(log.bytes().as_slice()),
+            Err(KhadyotaError::ChangelogGap { applied: 502, found: 504 })
+        ));
+        assert_eq!(reader.len(), 501);
+    }
+}
@@ -0,0 +1,651 @@
+use crate::changelog::{ChangeOp, IndexEntry};
+use crate::config::CentroidAdaptation;
+use crate::error::{KhadyotaError, Result};
+use crate::insert_options::InsertOptions;
+use crate::metadata::check_size;
+use crate::persist::PersistSection;
+use crate::types::EntryAttributes;
+use crate::vector_db::VectorDB;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::mem;
+
+/// One operation in a [`ChangeSet`]
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// Insert at the next free id; `opts.id` and `opts.upsert` must be unset
+    Insert { vector: Vec<f32>, opts: InsertOptions },
+
+    /// Replace an entry's vector and metadata, keeping its attributes
+    Update {
+        id: u32,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    },
+
+    /// Replace an entry's metadata, or clear it with `None`
+    UpdateMetadata {
+        id: u32,
+        metadata: Option<serde_json::Value>,
+    },
+
+    /// Tombstone an entry
+    Delete { id: u32 },
+}
+
+/// An ordered batch of changes applied all-or-nothing by
+/// [`VectorDB::apply_changeset`].
+///
+/// Updates and deletes may target existing ids or ids inserted earlier in
+/// the same set, but each id may be targeted at most once.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    ops: Vec<Change>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, change: Change) -> &mut Self {
+        self.ops.push(change);
+        self
+    }
+
+    pub fn insert(&mut self, vector: Vec<f32>, metadata: Option<serde_json::Value>) -> &mut Self {
+        self.push(Change::Insert {
+            vector,
+            opts: InsertOptions {
+                metadata,
+                ..Default::default()
+            },
+        })
+    }
+
+    pub fn insert_opts(&mut self, vector: Vec<f32>, opts: InsertOptions) -> &mut Self {
+        self.push(Change::Insert { vector, opts })
+    }
+
+    pub fn update(&mut self, id: u32, vector: Vec<f32>, metadata: Option<serde_json::Value>) -> &mut Self {
+        self.push(Change::Update { id, vector, metadata })
+    }
+
+    pub fn update_metadata(&mut self, id: u32, metadata: Option<serde_json::Value>) -> &mut Self {
+        self.push(Change::UpdateMetadata { id, metadata })
+    }
+
+    pub fn delete(&mut self, id: u32) -> &mut Self {
+        self.push(Change::Delete { id })
+    }
+
+    pub fn ops(&self) -> &[Change] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// An id changed by a changeset, and how
+#[derive(Clone, Copy)]
+enum Touched {
+    Put(u32),
+    Metadata(u32),
+    Delete(u32),
+}
+
+/// Outcome of [`VectorDB::apply_changeset`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeReport {
+    /// Ids assigned to inserts, in changeset order
+    pub inserted: Vec<u32>,
+    pub updated: usize,
+    pub metadata_updated: usize,
+    pub deleted: usize,
+
+    /// Whether a built index was updated in place. Without one, the index
+    /// stays unbuilt and `build_index()` picks up the changes.
+    pub index_maintained: bool,
+}
+
+impl VectorDB {
+    /// Apply a batch of changes atomically.
+    ///
+    /// Every op is validated against the database and the rest of the set
+    /// before anything is touched, so an invalid op leaves the database
+    /// exactly as it was and is reported as
+    /// [`KhadyotaError::InvalidChange`] with its position.
+    ///
+    /// A built index stays built: new and updated vectors are PQ-encoded
+    /// and assigned to their nearest cluster in one pass at the end, and
+    /// updated or deleted ids are dropped from the inverted lists in one
+    /// sweep. Centroids and codebooks are not retrained.
+    pub fn apply_changeset(&mut self, mut changeset: ChangeSet) -> Result<ChangeReport> {
+        self.validate_changeset(&changeset)?;
+
+        let maintain = self.index_built && self.has_index();
+        let mut report = ChangeReport {
+            index_maintained: maintain,
+            ..Default::default()
+        };
+        if changeset.is_empty() {
+            return Ok(report);
+        }
+
+        // Rows and attributes as they will be stored, so the journal holds
+        // what is applied; it takes the set before anything changes
+        let mut attributes = Vec::new();
+        for change in &mut changeset.ops {
+            match change {
+                Change::Insert { vector, opts } => {
+                    *vector = self.stored_row(mem::take(vector));
+                    attributes.push(opts.attributes());
+                }
+                Change::Update { vector, .. } => *vector = self.stored_row(mem::take(vector)),
+                Change::UpdateMetadata { .. } | Change::Delete { .. } => {}
+            }
+        }
+        self.journal_next(|db| db.journal_ops(&changeset, &attributes))?;
+
+        let first_new = self.next_id;
+        let mut attributes = attributes.into_iter();
+        let mut reencode = Vec::new();
+        let mut unlisted = BTreeSet::new();
+        // Ids touched, in op order, for the changelog
+        let mut touched = Vec::with_capacity(changeset.len());
+
+        for change in changeset.ops {
+            match change {
+                Change::Insert { vector, opts } => {
+                    let id = self.next_id;
+                    let attributes = attributes.next().unwrap();
+                    self.mark_dirty(&[PersistSection::Vectors]);
+                    self.vectors.push(vector);
+                    self.set_metadata(id, opts.metadata);
+                    if attributes != EntryAttributes::default() {
+                        self.attributes.insert(id, attributes);
+                    }
+                    self.next_id += 1;
+                    report.inserted.push(id);
+                    touched.push(Touched::Put(id));
+                }
+                Change::Update { id, vector, metadata } => {
+                    self.mark_dirty(&[PersistSection::Vectors]);
+                    self.vectors.set(id, vector);
+                    self.set_metadata(id, metadata);
+                    // Entries inserted by this set are encoded with the rest
+                    if id < first_new {
+                        reencode.push(id);
+                        unlisted.insert(id);
+                    }
+                    report.updated += 1;
+                    touched.push(Touched::Put(id));
+                }
+                Change::UpdateMetadata { id, metadata } => {
+                    self.set_metadata(id, metadata);
+                    report.metadata_updated += 1;
+                    touched.push(Touched::Metadata(id));
+                }
+                Change::Delete { id } => {
+                    self.deleted.insert(id);
+                    self.set_metadata(id, None);
+                    self.attributes.remove(&id);
+                    self.bind_external_id(id, None);
+                    unlisted.insert(id);
+                    report.deleted += 1;
+                    touched.push(Touched::Delete(id));
+                }
+            }
+        }
+
+        let clusters = if maintain {
+            self.maintain_index(first_new, &reencode, &unlisted)
+        } else {
+            let written = touched.iter().filter_map(|&touched| match touched {
+                Touched::Put(id) | Touched::Delete(id) => Some(id),
+                Touched::Metadata(_) => None,
+            });
+            self.mark_index_stale(first_new, written.collect::<Vec<_>>());
+            HashMap::new()
+        };
+        self.generation += 1;
+
+        self.log_change(|db| {
+            touched
+                .iter()
+                .map(|&touched| match touched {
+                    Touched::Put(id) => {
+                        let index = maintain.then(|| db.index_entry(id, &clusters));
+                        db.put_op(id, index)
+                    }
+                    Touched::Metadata(id) => ChangeOp::SetMetadata {
+                        id,
+                        metadata: db.metadata_value(id).cloned(),
+                    },
+                    Touched::Delete(id) => ChangeOp::Delete { id },
+                })
+                .collect()
+        })?;
+
+        Ok(report)
+    }
+
+    /// Ops journaling `changeset`, given the attributes of its inserts.
+    /// Rows are left for the index to catch up on replay.
+    fn journal_ops(&self, changeset: &ChangeSet, attributes: &[EntryAttributes]) -> Vec<ChangeOp> {
+        let first_new = self.next_id;
+        let kept = |new: u32| {
+            let attributes = &attributes[new as usize];
+            (*attributes != EntryAttributes::default()).then(|| attributes.clone())
+        };
+        let mut inserted = 0;
+        changeset
+            .ops
+            .iter()
+            .map(|change| match change {
+                Change::Insert { vector, opts } => {
+                    inserted += 1;
+                    ChangeOp::Put {
+                        id: first_new + inserted - 1,
+                        vector: vector.clone(),
+                        metadata: opts.metadata.clone(),
+                        attributes: kept(inserted - 1),
+                        index: None,
+                        external_id: None,
+                    }
+                }
+                Change::Update { id, vector, metadata } => ChangeOp::Put {
+                    id: *id,
+                    vector: vector.clone(),
+                    metadata: metadata.clone(),
+                    attributes: match id.checked_sub(first_new) {
+                        Some(new) => kept(new),
+                        None => self.attributes.get(id).cloned(),
+                    },
+                    index: None,
+                    external_id: self.external_ids.external(*id).cloned(),
+                },
+                Change::UpdateMetadata { id, metadata } => ChangeOp::SetMetadata {
+                    id: *id,
+                    metadata: metadata.clone(),
+                },
+                Change::Delete { id } => ChangeOp::Delete { id: *id },
+            })
+            .collect()
+    }
+
+    /// Check every op against the database and the ops before it
+    fn validate_changeset(&self, changeset: &ChangeSet) -> Result<()> {
+        let mut next_id = self.next_id;
+        let mut targeted: HashMap<u32, usize> = HashMap::new();
+
+        for (op, change) in changeset.ops.iter().enumerate() {
+            let invalid = |source| KhadyotaError::InvalidChange {
+                op,
+                source: Box::new(source),
+            };
+            let check_vector = |vector: &[f32]| {
+                if vector.len() == self.config.dimensions {
+                    self.check_row(vector).map_err(invalid)
+                } else {
+                    Err(invalid(KhadyotaError::DimensionMismatch {
+                        expected: self.config.dimensions,
+                        got: vector.len(),
+                    }))
+                }
+            };
+
+            let target = match change {
+                Change::Insert { vector, opts } => {
+                    check_vector(vector)?;
+                    if opts.id.is_some() || opts.upsert {
+                        return Err(invalid(KhadyotaError::InvalidConfig(
+                            "inserts take the next free id; use an update to replace an entry".to_string(),
+                        )));
+                    }
+                    opts.validate(self).map_err(invalid)?;
+                    next_id += 1;
+                    continue;
+                }
+                Change::Update { id, vector, metadata } => {
+                    check_vector(vector)?;
+                    if let Some(metadata) = metadata {
+                        check_size(&self.config, metadata).map_err(invalid)?;
+                    }
+                    *id
+                }
+                Change::UpdateMetadata { id, metadata } => {
+                    if let Some(metadata) = metadata {
+                        check_size(&self.config, metadata).map_err(invalid)?;
+                    }
+                    *id
+                }
+                Change::Delete { id } => *id,
+            };
+
+            if target >= next_id || self.deleted.contains(&target) {
+                return Err(invalid(KhadyotaError::VectorNotFound(target)));
+            }
+            if let Some(first) = targeted.insert(target, op) {
+                return Err(invalid(KhadyotaError::InvalidConfig(format!(
+                    "id {} is already changed by op {}",
+                    target, first
+                ))));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bring the built index up to date after a changeset: encode new and
+    /// re-encoded rows, then edit the inverted lists in bulk. Returns the
+    /// cluster each listed id was assigned to.
+    pub(crate) fn maintain_index(&mut self, first_new: u32, reencode: &[u32], unlisted: &BTreeSet<u32>) -> HashMap<u32, usize> {
+        let new_ids = first_new..self.next_id;
+        if !new_ids.is_empty() || !reencode.is_empty() || !unlisted.is_empty() {
+            self.mark_dirty(&[PersistSection::Codes, PersistSection::Index]);
+        }
+
+        // Residual codes wait for the rows' clusters
+        let residual = self.residual_codes();
+        if let Some(quantized) = self.quantized.as_mut().filter(|_| !residual) {
+            let rows: Vec<_> = new_ids.clone().map(|id| self.vectors.get(id).unwrap()).collect();
+            quantized.add_batch_from(rows.iter().map(|row| &**row));
+            for &id in reencode {
+                quantized.replace(id, &self.vectors.get(id).unwrap());
+            }
+        }
+
+        if let Some(flat) = &mut self.flat_index {
+            for id in reencode.iter().copied().chain(new_ids.clone()) {
+                flat.put(id, &self.vectors.get(id).unwrap());
+            }
+        }
+
+        let Some(ivf) = &mut self.ivf_index else {
+            return HashMap::new();
+        };
+        if !unlisted.is_empty() {
+            ivf.remove_ids(unlisted);
+        }
+
+        let to_assign: Vec<u32> = reencode
+            .iter()
+            .copied()
+            .chain(new_ids.clone())
+            .filter(|id| !self.deleted.contains(id))
+            .collect();
+        let assigned = self.assign_to_clusters(to_assign);
+        if residual {
+            self.encode_residuals(reencode.iter().copied().chain(new_ids));
+        }
+        assigned
+    }
+
+    /// Codes and cluster of `id` after [`VectorDB::maintain_index`]
+    /// returned `clusters`, for its change record
+    pub(crate) fn index_entry(&self, id: u32, clusters: &HashMap<u32, usize>) -> IndexEntry {
+        IndexEntry {
+            codes: self.quantized.as_ref().map(|q| q.get_codes(id).to_vec()),
+            cluster: clusters.get(&id).copied(),
+        }
+    }
+
+    /// Append ids to their nearest clusters' inverted lists, in bulk.
+    /// Returns the cluster each id was assigned to.
+    pub(crate) fn assign_to_clusters(&mut self, to_assign: Vec<u32>) -> HashMap<u32, usize> {
+        let Some(ivf) = &mut self.ivf_index else {
+            return HashMap::new();
+        };
+        let clusters: Vec<usize> = to_assign
+            .par_iter()
+            .map(|&id| ivf.assign(&self.vectors.get(id).unwrap()))
+            .collect();
+
+        let mut by_cluster: BTreeMap<usize, Vec<u32>> = BTreeMap::new();
+        for (&id, &cluster) in to_assign.iter().zip(&clusters) {
+            by_cluster.entry(cluster).or_default().push(id);
+        }
+        for (cluster, ids) in by_cluster {
+            ivf.add_to_cluster(cluster, &ids);
+        }
+
+        let mut assigned: HashMap<u32, usize> = to_assign.iter().copied().zip(clusters).collect();
+        // Moving a centroid would invalidate its cluster's residual codes
+        if let Some(adaptation) = self.config.online_centroid_adaptation
+            && !self.residual_codes()
+        {
+            self.adapt_centroids(&to_assign, &mut assigned, adaptation);
+        }
+        assigned
+    }
+
+    /// One online k-means step per row in `ids`, in order: move the
+    /// centroid of its cluster towards it, then re-check a few members of
+    /// that cluster against the moved centroids. `assigned` is kept up to
+    /// date with any of `ids` that move.
+    fn adapt_centroids(&mut self, ids: &[u32], assigned: &mut HashMap<u32, usize>, adaptation: CentroidAdaptation) {
+        let Some(ivf) = &mut self.ivf_index else {
+            return;
+        };
+        for id in ids {
+            let cluster = assigned[id];
+            ivf.adapt_centroid(cluster, &self.vectors.get(*id).unwrap(), adaptation.min_learning_rate);
+            for member in ivf.members_to_check(cluster, adaptation.reassign_checks) {
+                let nearest = ivf.assign(&self.vectors.get(member).unwrap());
+                if nearest != cluster {
+                    ivf.move_id(member, cluster, nearest);
+                    if let Some(entry) = assigned.get_mut(&member) {
+                        *entry = nearest;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, DistanceMetric, QuantizerKind};
+    use crate::search_params::SearchParams;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempfile::NamedTempFile;
+
+    const DIMS: usize = 16;
+
+    fn vector(rng: &mut StdRng) -> Vec<f32> {
+        (0..DIMS).map(|_| rng.r#gen::<f32>() * 2.0 - 1.0).collect()
+    }
+
+    fn build_db(rng: &mut StdRng) -> VectorDB {
+        let config = Config {
+            dimensions: DIMS,
+            metric: DistanceMetric::Euclidean,
+            quantizer: QuantizerKind::PQ,
+            pq_subvectors: 4,
+            num_clusters: 16,
+            num_probe: 4,
+            seed: Some(3),
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..2_000 {
+            db.insert(vector(rng), Some(serde_json::json!({"i": i}))).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    /// 10k ops: inserts, updates, metadata updates and deletes, each id
+    /// targeted at most once
+    fn mixed_changeset(db: &VectorDB, rng: &mut StdRng) -> ChangeSet {
+        let mut changeset = ChangeSet::new();
+        let mut next_id = db.next_id;
+        let mut targeted = BTreeSet::new();
+
+        while changeset.len() < 10_000 {
+            let id = rng.gen_range(0..next_id);
+            match rng.gen_range(0..4) {
+                0 => {
+                    changeset.insert(vector(rng), Some(serde_json::json!({"new": next_id})));
+                    next_id += 1;
+                }
+                _ if !targeted.insert(id) => {}
+                1 => {
+                    changeset.update(id, vector(rng), None);
+                }
+                2 => {
+                    changeset.update_metadata(id, Some(serde_json::json!({"updated": id})));
+                }
+                _ => {
+                    changeset.delete(id);
+                }
+            }
+        }
+        changeset
+    }
+
+    fn exact_results(db: &VectorDB, query: &[f32]) -> Vec<(u32, f32)> {
+        let params = SearchParams::builder().num_probe(16).rerank(db.len()).build();
+        db.search_with_params(query, 10, &params)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.id, r.distance))
+            .collect()
+    }
+
+    #[test]
+    fn test_mixed_changeset_matches_individual_ops() {
+        let mut batched = build_db(&mut StdRng::seed_from_u64(11));
+        let mut individual = build_db(&mut StdRng::seed_from_u64(11));
+        assert_eq!(batched.checksum(), individual.checksum());
+
+        let mut rng = StdRng::seed_from_u64(12);
+        let changeset = mixed_changeset(&batched, &mut rng);
+
+        let report = batched.apply_changeset(changeset.clone()).unwrap();
+        assert!(report.index_maintained);
+        assert_eq!(
+            report.inserted.len() + report.updated + report.metadata_updated + report.deleted,
+            10_000
+        );
+        assert_eq!(batched.len(), 2_000 + report.inserted.len() - report.deleted);
+
+        for change in changeset.ops {
+            let mut single = ChangeSet::new();
+            single.push(change);
+            individual.apply_changeset(single).unwrap();
+        }
+        assert_eq!(batched.checksum(), individual.checksum());
+
+        // Still searchable without a rebuild, never returning deleted ids
+        assert!(batched.index_built);
+        for _ in 0..20 {
+            let query = vector(&mut rng);
+            let results = exact_results(&batched, &query);
+            assert_eq!(results, exact_results(&individual, &query));
+            assert!(results.iter().all(|(id, _)| !batched.deleted.contains(id)));
+        }
+        let listed = batched.ivf_index.as_ref().unwrap().stats().total_vectors;
+        assert_eq!(listed, batched.len());
+    }
+
+    #[test]
+    fn test_invalid_op_leaves_db_untouched() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut db = build_db(&mut rng);
+        let before = db.checksum();
+
+        let expect_invalid = |db: &mut VectorDB, changeset: ChangeSet| match db.apply_changeset(changeset) {
+            Err(KhadyotaError::InvalidChange { op, source }) => (op, source.to_string()),
+            other => panic!("expected InvalidChange, got {:?}", other),
+        };
+
+        let mut changeset = ChangeSet::new();
+        changeset.insert(vector(&mut rng), None).delete(3).update(7, vec![0.0; 3], None);
+        assert_eq!(
+            expect_invalid(&mut db, changeset),
+            (2, "Invalid vector dimension: expected 16, got 3".to_string())
+        );
+
+        let mut changeset = ChangeSet::new();
+        changeset.delete(3).update_metadata(3, None);
+        assert_eq!(
+            expect_invalid(&mut db, changeset),
+            (1, "Invalid configuration: id 3 is already changed by op 0".to_string())
+        );
+
+        // Ids inserted earlier in the set can be targeted, later ones cannot
+        let mut changeset = ChangeSet::new();
+        changeset.insert(vector(&mut rng), None).delete(2_000).delete(2_001);
+        assert_eq!(expect_invalid(&mut db, changeset), (2, "Vector not found: 2001".to_string()));
+
+        let mut changeset = ChangeSet::new();
+        changeset.insert_opts(vector(&mut rng), InsertOptions::new().id(2_000));
+        assert_eq!(
+            expect_invalid(&mut db, changeset),
+            (0, "Invalid configuration: inserts take the next free id; use an update to replace an entry".to_string())
+        );
+
+        assert_eq!(db.checksum(), before);
+        assert!(db.index_built);
+
+        // Deleted ids are gone for later sets too
+        let mut changeset = ChangeSet::new();
+        changeset.delete(10);
+        db.apply_changeset(changeset).unwrap();
+        let mut changeset = ChangeSet::new();
+        changeset.update_metadata(10, None);
+        assert_eq!(expect_invalid(&mut db, changeset), (0, "Vector not found: 10".to_string()));
+        assert!(matches!(db.get(10), Err(KhadyotaError::VectorNotFound(10))));
+    }
+
+    #[test]
+    fn test_tombstones_round_trip_and_unbuilt_index() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let config = Config {
+            dimensions: DIMS,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 4,
+            ..Default::default()
+        };
+        let mut db = VectorDB::new(config).unwrap();
+
+        let mut changeset = ChangeSet::new();
+        for _ in 0..50 {
+            changeset.insert(vector(&mut rng), None);
+        }
+        changeset.delete(0).delete(49);
+        let report = db.apply_changeset(changeset).unwrap();
+        assert!(!report.index_maintained);
+        assert_eq!(report.inserted, (0..50).collect::<Vec<_>>());
+        assert_eq!(db.len(), 48);
+
+        // The linear scan skips tombstones too
+        db.build_index().unwrap();
+        let query = db.get(1).unwrap().vector;
+        let probe_all = SearchParams::builder().num_probe(4).build();
+        for params in [SearchParams::default(), probe_all] {
+            let ids: Vec<u32> = db.search_with_params(&query, 50, &params).unwrap().iter().map(|r| r.id).collect();
+            assert_eq!(ids.len(), 48);
+            assert!(!ids.contains(&0) && !ids.contains(&49));
+        }
+
+        let temp = NamedTempFile::new().unwrap();
+        db.save(temp.path()).unwrap();
+        let loaded = VectorDB::load(temp.path()).unwrap();
+        assert_eq!(loaded.checksum(), db.checksum());
+        assert_eq!(loaded.len(), 48);
+        assert!(loaded.get(49).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::error::{KhadyotaError, Result};
+use crate::indexing::IVFIndex;
+use crate::persist::PersistSection;
+use crate::storage::{FileHeader, QuantizedVectors, Section, VectorStorage};
+use crate::types::EntryAttributes;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Cursor;
+
+/// Name of the save-file section holding named collections
+pub(crate) const SECTION_NAME: &str = "collections";
+
+/// Name under which a database answers for itself among its collections.
+/// A file saved before collections existed opens as just this one.
+pub const DEFAULT_COLLECTION: &str = "default";
+
+/// A collection's state as saved in the `collections` section, in the
+/// order of a top-level file's state, followed by its own section table
+#[derive(Serialize)]
+struct SavedCollection<'a> {
+    config: &'a Config,
+    vectors: &'a VectorStorage,
+    quantized: &'a Option<QuantizedVectors>,
+    ivf_index: &'a Option<IVFIndex>,
+    metadata: &'a BTreeMap<u32, serde_json::Value>,
+    next_id: u32,
+    index_built: bool,
+    attributes: &'a BTreeMap<u32, EntryAttributes>,
+    deleted: &'a BTreeSet<u32>,
+    seq: u64,
+    sections: Vec<Section>,
+}
+
+/// [`SavedCollection`] read back
+#[derive(Deserialize)]
+struct LoadedCollection {
+    config: Config,
+    vectors: Vec<Vec<f32>>,
+    quantized: Option<QuantizedVectors>,
+    ivf_index: Option<IVFIndex>,
+    metadata: BTreeMap<u32, serde_json::Value>,
+    next_id: u32,
+    index_built: bool,
+    attributes: BTreeMap<u32, EntryAttributes>,
+    deleted: BTreeSet<u32>,
+    seq: u64,
+    sections: Vec<Section>,
+}
+
+impl VectorDB {
+    /// Add an empty collection called `name` to this database, with its
+    /// own dimensions, metric, quantizer and index, and return it.
+    ///
+    /// Collections are saved and loaded with the database, in the same
+    /// file; otherwise each is a database of its own, reached through
+    /// [`VectorDB::collection`] and [`VectorDB::collection_mut`], with ids
+    /// counted separately. Runtime settings such as the query cache,
+    /// admission control and access tracking are not shared with it.
+    pub fn create_collection(&mut self, name: &str, config: Config) -> Result<&mut VectorDB> {
+        if name.is_empty() || name == DEFAULT_COLLECTION || self.collections.contains_key(name) {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "collection name {:?} is empty or already taken",
+                name
+            )));
+        }
+        let collection = VectorDB::new(config)?;
+        self.mark_dirty(&[PersistSection::AppState]);
+        Ok(self.collections.entry(name.to_string()).or_insert(collection))
+    }
+
+    /// The collection called `name`; [`DEFAULT_COLLECTION`] is this
+    /// database itself
+    pub fn collection(&self, name: &str) -> Result<&VectorDB> {
+        match name {
+            DEFAULT_COLLECTION => Ok(self),
+            _ => self.collections.get(name).ok_or_else(|| unknown(name)),
+        }
+    }
+
+    /// The collection called `name`, to insert into, build or otherwise
+    /// change
+    pub fn collection_mut(&mut self, name: &str) -> Result<&mut VectorDB> {
+        if name == DEFAULT_COLLECTION {
+            return Ok(self);
+        }
+        self.mark_dirty(&[PersistSection::AppState]);
+        self.collections.get_mut(name).ok_or_else(|| unknown(name))
+    }
+
+    /// Remove the collection called `name` and return it. The default
+    /// collection cannot be dropped.
+    pub fn drop_collection(&mut self, name: &str) -> Result<VectorDB> {
+        let collection = self.collections.remove(name).ok_or_else(|| unknown(name))?;
+        self.mark_dirty(&[PersistSection::AppState]);
+        Ok(collection)
+    }
+
+    /// Names of every collection, [`DEFAULT_COLLECTION`] first and the
+    /// rest in order
+    pub fn collection_names(&self) -> impl Iterator<Item = &str> + '_ {
+        std::iter::once(DEFAULT_COLLECTION).chain(self.collections.keys().map(String::as_str))
+    }
+
+    /// Named collections as a save-file section: a directory of headers,
+    /// one per collection, then each collection's state in the same order.
+    /// It is required: a build that dropped it would lose the collections.
+    pub(crate) fn collections_section(&self) -> Result<Option<Section>> {
+        if self.collections.is_empty() {
+            return Ok(None);
+        }
+        let directory: Vec<(&str, FileHeader)> = self
+            .collections
+            .iter()
+            .map(|(name, db)| (name.as_str(), FileHeader::new(db.config.dimensions, db.vectors.len(), db.config.metric)))
+            .collect();
+        let mut payload = rmp_serde::to_vec(&directory)?;
+        for db in self.collections.values() {
+            rmp_serde::encode::write(
+                &mut payload,
+                &SavedCollection {
+                    config: &db.config,
+                    vectors: &db.vectors,
+                    quantized: &db.quantized,
+                    ivf_index: &db.ivf_index,
+                    metadata: &db.metadata,
+                    next_id: db.next_id,
+                    index_built: db.index_built,
+                    attributes: &db.attributes,
+                    deleted: &db.deleted,
+                    seq: db.seq,
+                    sections: db.app_sections()?,
+                },
+            )?;
+        }
+        Ok(Some(Section::new(SECTION_NAME, payload)))
+    }
+}
+
+/// Restore the collections saved by [`VectorDB::collections_section`],
+/// checking each against its directory entry as a file is checked against
+/// its header
+pub(crate) fn collections_from_section(section: &Section) -> Result<BTreeMap<String, VectorDB>> {
+    let mut reader = Cursor::new(section.payload.as_slice());
+    let directory: Vec<(String, FileHeader)> = rmp_serde::from_read(&mut reader)?;
+    let mut collections = BTreeMap::new();
+    for (name, header) in directory {
+        header.validate()?;
+        let saved: LoadedCollection = rmp_serde::from_read(&mut reader)?;
+        header.check_config(&saved.config, saved.vectors.len())?;
+        let db = VectorDB::from_saved_collection(saved)
+            .map_err(|e| KhadyotaError::SerializationError(format!("collection {:?}: {}", name, e)))?;
+        collections.insert(name, db);
+    }
+    Ok(collections)
+}
+
+impl VectorDB {
+    fn from_saved_collection(saved: LoadedCollection) -> Result<Self> {
+        let mut db = VectorDB::from_parts(saved.config, saved.vectors, saved.quantized, saved.ivf_index)?;
+        db.metadata = saved.metadata;
+        db.next_id = saved.next_id;
+        db.index_built = saved.index_built;
+        db.attributes = saved.attributes;
+        db.deleted = saved.deleted;
+        db.seq = saved.seq;
+        let (sections, warnings) = Section::gate(saved.sections)?;
+        db.sections = sections;
+        db.load_warnings = warnings;
+        db.take_app_sections()?;
+        db.restore_flat_index();
+        Ok(db)
+    }
+}
+
+fn unknown(name: &str) -> KhadyotaError {
+    KhadyotaError::InvalidConfig(format!("no collection named {:?}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QuantizerKind;
+
+    fn config(dimensions: usize) -> Config {
+        Config {
+            dimensions,
+            quantizer: QuantizerKind::None,
+            num_clusters: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_names_are_unique_and_default_is_self() {
+        let mut db = VectorDB::new(config(4)).unwrap();
+        db.create_collection("titles", config(2)).unwrap();
+        assert!(db.create_collection("titles", config(2)).is_err());
+        assert!(db.create_collection(DEFAULT_COLLECTION, config(2)).is_err());
+        assert!(db.create_collection("", config(2)).is_err());
+
+        assert_eq!(db.collection_names().collect::<Vec<_>>(), [DEFAULT_COLLECTION, "titles"]);
+        assert_eq!(db.collection(DEFAULT_COLLECTION).unwrap().config.dimensions, 4);
+        assert_eq!(db.collection("titles").unwrap().config.dimensions, 2);
+        assert!(db.collection("bodies").is_err());
+        assert!(db.drop_collection(DEFAULT_COLLECTION).is_err());
+        db.drop_collection("titles").unwrap();
+        assert_eq!(db.collection_names().count(), 1);
+    }
+}
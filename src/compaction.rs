@@ -0,0 +1,122 @@
+//! Reclaiming the space of deleted entries.
+//!
+//! A delete only tombstones its row: the vector, its codes and its id stay
+//! in place, so ids remain dense and nothing else moves. Compaction drops
+//! tombstoned rows and renumbers the live ones from 0, in id order. The
+//! trained centroids and codebooks are kept; inverted lists and codes are
+//! carried over under the new ids, so nothing is retrained or re-encoded.
+
+use crate::changelog::ChangeOp;
+use crate::error::{KhadyotaError, Result};
+use crate::persist::PersistSection;
+use crate::storage::VectorStorage;
+use crate::vector_db::VectorDB;
+use std::collections::BTreeMap;
+
+/// What [`VectorDB::compact`] dropped, and where each kept row went
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactionReport {
+    /// Tombstoned rows dropped
+    pub removed: usize,
+
+    /// New id of every id before compaction, indexed by the old id;
+    /// `None` for rows dropped
+    pub id_map: Vec<Option<u32>>,
+}
+
+impl CompactionReport {
+    /// Id the row stored at `old` now has, if it was kept
+    pub fn new_id(&self, old: u32) -> Option<u32> {
+        self.id_map.get(old as usize).copied().flatten()
+    }
+}
+
+/// `map` rekeyed through `id_map`, dropping entries of rows not kept
+fn rekey<V>(map: BTreeMap<u32, V>, id_map: &[Option<u32>]) -> BTreeMap<u32, V> {
+    map.into_iter()
+        .filter_map(|(id, value)| Some((id_map.get(id as usize).copied().flatten()?, value)))
+        .collect()
+}
+
+impl VectorDB {
+    /// Drop tombstoned rows and renumber the live ones densely, in id
+    /// order, so the next save writes no trace of them.
+    ///
+    /// Vectors, codes, inverted lists, metadata (external included) and
+    /// entry attributes all follow the new ids, and the next insert takes
+    /// the id after the last live row. The report maps old ids to new, so
+    /// ids held outside the database can be translated. Centroids and
+    /// codebooks are kept as trained; an index that was current stays
+    /// current. Spilled or mapped rows are read back into memory; spill
+    /// them again afterwards if needed.
+    ///
+    /// Changelog readers compact in the same way when they reach this
+    /// change. Fails with [`KhadyotaError::BuildInProgress`] while a
+    /// background build is training from the old rows.
+    pub fn compact(&mut self) -> Result<CompactionReport> {
+        if self.build_in_flight {
+            return Err(KhadyotaError::BuildInProgress);
+        }
+        if self.deleted.is_empty() {
+            return Ok(CompactionReport {
+                removed: 0,
+                id_map: (0..self.vectors.len() as u32).map(Some).collect(),
+            });
+        }
+
+        self.journal_next(|_| vec![ChangeOp::Compact])?;
+        let report = self.compact_rows();
+        self.log_change(|_| vec![ChangeOp::Compact])?;
+        Ok(report)
+    }
+
+    /// Compaction itself, shared with changelog readers
+    pub(crate) fn compact_rows(&mut self) -> CompactionReport {
+        let rows = self.vectors.len() as u32;
+        let kept: Vec<u32> = (0..rows).filter(|id| !self.deleted.contains(id)).collect();
+        let mut id_map = vec![None; rows as usize];
+        for (new, &old) in kept.iter().enumerate() {
+            id_map[old as usize] = Some(new as u32);
+        }
+        let new_id = |id: u32| id_map.get(id as usize).copied().flatten();
+
+        let vectors = std::mem::take(&mut self.vectors).into_rows();
+        self.vectors = VectorStorage::from_rows(
+            vectors
+                .into_iter()
+                .zip(&id_map)
+                .filter(|(_, new)| new.is_some())
+                .map(|(row, _)| row)
+                .collect(),
+            self.config.raw_precision,
+            self.config.dimensions,
+        );
+        if let Some(quantized) = &mut self.quantized {
+            quantized.retain_rows(&kept);
+        }
+        if let Some(ivf) = &mut self.ivf_index {
+            ivf.renumber_ids(new_id);
+        }
+        if let Some(flat) = &mut self.flat_index {
+            flat.retain_rows(&kept);
+        }
+        if let Some(lag) = &mut self.index_lag {
+            lag.indexed_rows = kept.partition_point(|&id| id < lag.indexed_rows) as u32;
+            lag.pending = lag.pending.iter().filter_map(|&id| new_id(id)).collect();
+        }
+
+        self.metadata = rekey(std::mem::take(&mut self.metadata), &id_map);
+        self.external_metadata = rekey(std::mem::take(&mut self.external_metadata), &id_map);
+        self.attributes = rekey(std::mem::take(&mut self.attributes), &id_map);
+        self.external_ids.rekey(&id_map);
+        self.deleted.clear();
+        self.next_id = kept.len() as u32;
+
+        self.mark_dirty(&PersistSection::ALL);
+        self.generation += 1;
+        CompactionReport {
+            removed: (rows as usize) - kept.len(),
+            id_map,
+        }
+    }
+}
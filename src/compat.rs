@@ -0,0 +1,436 @@
+use crate::config::{Config, DistanceMetric, QuantizerKind};
+use crate::quantization::{PQCodec, Quantizer};
+use crate::error::{KhadyotaError, Result};
+use crate::indexing::IVFIndex;
+use crate::storage::QuantizedVectors;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One broken constraint between a collection and its index parts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Violation {
+    /// The codes were made by another kind of quantizer than the config
+    /// names
+    QuantizerKind { expected: QuantizerKind, found: QuantizerKind },
+
+    /// The codec's subvectors do not cover the collection's dimensions
+    CodecDimensions { expected: usize, found: usize },
+
+    /// The codec was trained with a different subvector count than the
+    /// config asks for
+    SubvectorCount { expected: usize, found: usize },
+
+    /// The codec packs codes of another width than `pq_bits`
+    CodeBits { expected: usize, found: usize },
+
+    /// The codec does (`found`) or does not rotate vectors before
+    /// encoding them, against `use_opq`
+    Rotation { expected: bool, found: bool },
+
+    /// The codec has the wrong number of codebooks for its subvectors
+    CodebookCount { expected: usize, found: usize },
+
+    /// A codebook's centroids have the wrong length
+    CodebookDimensions { subvector: usize, expected: usize, found: usize },
+
+    /// More code rows than stored vectors. Fewer is fine: rows inserted
+    /// since the last build have no codes yet.
+    CodeCount { expected: usize, found: usize },
+
+    /// Code rows with a different width than the codec's packed codes
+    CodeWidth { expected: usize, found: usize, rows: usize, first_id: u32 },
+
+    /// Codes naming a centroid their codebook does not have
+    CodeOutOfRange { subvector: usize, code: u16, centroids: usize, rows: usize, first_id: u32 },
+
+    /// The IVF index was built for another dimensionality
+    IndexDimensions { expected: usize, found: usize },
+
+    /// IVF centroids with the wrong length
+    CentroidDimensions { expected: usize, found: usize, centroids: usize, first: usize },
+
+    /// The IVF index has a different number of inverted lists than centroids
+    ListCount { centroids: usize, lists: usize },
+
+    /// Inverted lists naming ids past the end of the collection
+    IdOutOfRange { vectors: usize, ids: usize, first_id: u32 },
+
+    /// Inverted lists naming ids that have no PQ codes
+    UncodedIds { codes: usize, ids: usize, first_id: u32 },
+
+    /// The codec was trained for another distance metric
+    CodecMetric { expected: DistanceMetric, found: DistanceMetric },
+
+    /// The IVF index was clustered for another distance metric
+    IndexMetric { expected: DistanceMetric, found: DistanceMetric },
+
+    /// Cluster assignments for a different number of vectors
+    AssignmentCount { expected: usize, found: usize },
+
+    /// Assignments naming clusters that have no centroid
+    AssignmentOutOfRange { clusters: usize, ids: usize, first_id: u32 },
+
+    /// Residual codes without the IVF index whose centroids they are
+    /// offsets from
+    ResidualsWithoutIndex,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::QuantizerKind { expected, found } => {
+                write!(f, "codes were made by {:?}, config has quantizer = {:?}", found, expected)
+            }
+            Violation::CodecDimensions { expected, found } => {
+                write!(f, "codec covers {} dimensions, collection has {}", found, expected)
+            }
+            Violation::SubvectorCount { expected, found } => {
+                write!(f, "codec has {} subvectors, config has pq_subvectors = {}", found, expected)
+            }
+            Violation::CodeBits { expected, found } => {
+                write!(f, "codec has {}-bit codes, config has pq_bits = {}", found, expected)
+            }
+            Violation::Rotation { expected, found } => write!(
+                f,
+                "codec {} vectors, config has use_opq = {}",
+                if *found { "rotates" } else { "does not rotate" },
+                expected
+            ),
+            Violation::CodebookCount { expected, found } => {
+                write!(f, "codec has {} codebooks for {} subvectors", found, expected)
+            }
+            Violation::CodebookDimensions { subvector, expected, found } => write!(
+                f,
+                "codebook {} has {}-dimensional centroids, subvectors have {}",
+                subvector, found, expected
+            ),
+            Violation::CodeCount { expected, found } => {
+                write!(f, "{} code rows for {} vectors", found, expected)
+            }
+            Violation::CodeWidth { expected, found, rows, first_id } => write!(
+                f,
+                "{} code rows are {} bytes wide instead of {} (first at id {})",
+                rows, found, expected, first_id
+            ),
+            Violation::CodeOutOfRange { subvector, code, centroids, rows, first_id } => write!(
+                f,
+                "{} code rows name centroids past codebook {}'s {} (first: code {} at id {})",
+                rows, subvector, centroids, code, first_id
+            ),
+            Violation::IndexDimensions { expected, found } => {
+                write!(f, "IVF index has {} dimensions, collection has {}", found, expected)
+            }
+            Violation::CentroidDimensions { expected, found, centroids, first } => write!(
+                f,
+                "{} IVF centroids have {} dimensions instead of {} (first: centroid {})",
+                centroids, found, expected, first
+            ),
+            Violation::ListCount { centroids, lists } => {
+                write!(f, "IVF index has {} inverted lists for {} centroids", lists, centroids)
+            }
+            Violation::IdOutOfRange { vectors, ids, first_id } => write!(
+                f,
+                "{} inverted-list entries point past the {} stored vectors (first: id {})",
+                ids, vectors, first_id
+            ),
+            Violation::UncodedIds { codes, ids, first_id } => write!(
+                f,
+                "{} inverted-list entries point past the {} PQ code rows (first: id {})",
+                ids, codes, first_id
+            ),
+            Violation::CodecMetric { expected, found } => {
+                write!(f, "codec was trained for {:?}, collection uses {:?}", found, expected)
+            }
+            Violation::IndexMetric { expected, found } => {
+                write!(f, "IVF index was clustered for {:?}, collection uses {:?}", found, expected)
+            }
+            Violation::AssignmentCount { expected, found } => {
+                write!(f, "{} cluster assignments for {} vectors", found, expected)
+            }
+            Violation::AssignmentOutOfRange { clusters, ids, first_id } => write!(
+                f,
+                "{} vectors are assigned to clusters past the {} centroids (first: id {})",
+                ids, clusters, first_id
+            ),
+            Violation::ResidualsWithoutIndex => {
+                f.write_str("codes are residuals from IVF centroids, but there is no IVF index")
+            }
+        }
+    }
+}
+
+/// Every constraint broken by a pairing of collection, PQ codes and IVF
+/// index. Checked wherever pretrained or stored parts are put together, so
+/// mismatches fail up front instead of panicking inside a search.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompatibilityReport {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for CompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// First offender and count for a per-row constraint
+struct Offenders<T> {
+    first: Option<(u32, T)>,
+    count: usize,
+}
+
+impl<T> Offenders<T> {
+    fn new() -> Self {
+        Self { first: None, count: 0 }
+    }
+
+    fn note(&mut self, id: u32, detail: T) {
+        self.count += 1;
+        self.first.get_or_insert((id, detail));
+    }
+}
+
+impl CompatibilityReport {
+    /// Check `quantized` and `ivf` against a collection of `vector_count`
+    /// vectors stored under `config`
+    pub fn check(
+        config: &Config,
+        vector_count: usize,
+        quantized: Option<&QuantizedVectors>,
+        ivf: Option<&IVFIndex>,
+    ) -> Self {
+        let mut report = Self::default();
+        if let Some(quantized) = quantized {
+            report.check_codes(config, vector_count, quantized);
+            if quantized.is_residual() && ivf.is_none() {
+                report.violations.push(Violation::ResidualsWithoutIndex);
+            }
+        }
+        if let Some(ivf) = ivf {
+            report.check_ivf(config, vector_count, quantized.map(|q| q.len()), ivf);
+        }
+        report
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// `Ok` if nothing is violated, else [`KhadyotaError::IncompatibleComponents`]
+    pub fn into_result(self) -> Result<()> {
+        if self.is_compatible() {
+            Ok(())
+        } else {
+            Err(KhadyotaError::IncompatibleComponents(self))
+        }
+    }
+
+    /// Check precomputed cluster assignments, one per vector, before they
+    /// are turned into inverted lists
+    pub(crate) fn check_assignments(&mut self, vector_count: usize, clusters: usize, assignments: &[u32]) {
+        if assignments.len() != vector_count {
+            self.violations.push(Violation::AssignmentCount {
+                expected: vector_count,
+                found: assignments.len(),
+            });
+        }
+
+        let mut out_of_range = Offenders::new();
+        for (id, &cluster) in assignments.iter().enumerate() {
+            if cluster as usize >= clusters {
+                out_of_range.note(id as u32, ());
+            }
+        }
+        if let Some((first_id, ())) = out_of_range.first {
+            self.violations.push(Violation::AssignmentOutOfRange {
+                clusters,
+                ids: out_of_range.count,
+                first_id,
+            });
+        }
+    }
+
+    fn check_codes(&mut self, config: &Config, vector_count: usize, quantized: &QuantizedVectors) {
+        let quantizer = quantized.codec();
+
+        if config.quantizer != QuantizerKind::None && quantizer.kind() != config.quantizer {
+            self.violations.push(Violation::QuantizerKind {
+                expected: config.quantizer,
+                found: quantizer.kind(),
+            });
+        }
+        let rotated = matches!(quantizer, Quantizer::OPQ(_));
+        if config.uses_pq() && rotated != config.use_opq {
+            self.violations.push(Violation::Rotation {
+                expected: config.use_opq,
+                found: rotated,
+            });
+        }
+        if quantizer.dimensions() != config.dimensions {
+            self.violations.push(Violation::CodecDimensions {
+                expected: config.dimensions,
+                found: quantizer.dimensions(),
+            });
+        }
+        // Codecs saved before the metric was recorded load as Euclidean
+        if let Some(found) = quantizer.recorded_metric()
+            && found != config.metric
+        {
+            self.violations.push(Violation::CodecMetric {
+                expected: config.metric,
+                found,
+            });
+        }
+        if let Some(codec) = quantizer.codebooks() {
+            self.check_codebooks(config, codec);
+        }
+        if quantized.len() > vector_count {
+            self.violations.push(Violation::CodeCount {
+                expected: vector_count,
+                found: quantized.len(),
+            });
+        }
+
+        let mut width = Offenders::new();
+        let mut range = Offenders::new();
+        for (id, codes) in quantized.iter_codes().enumerate() {
+            let id = id as u32;
+            if codes.len() != quantizer.code_bytes() {
+                width.note(id, codes.len());
+                continue;
+            }
+            // Every byte is a valid SQ8 level
+            let Some(codec) = quantizer.codebooks() else {
+                continue;
+            };
+            let out_of_range = codec
+                .codebooks
+                .iter()
+                .enumerate()
+                .map(|(subvector, codebook)| (subvector, codec.code(codes, subvector), codebook))
+                .find(|&(_, code, codebook)| code as usize >= codebook.centroids.len());
+            if let Some((subvector, code, codebook)) = out_of_range {
+                range.note(id, (subvector, code, codebook.centroids.len()));
+            }
+        }
+        if let Some((first_id, found)) = width.first {
+            self.violations.push(Violation::CodeWidth {
+                expected: quantizer.code_bytes(),
+                found,
+                rows: width.count,
+                first_id,
+            });
+        }
+        if let Some((first_id, (subvector, code, centroids))) = range.first {
+            self.violations.push(Violation::CodeOutOfRange {
+                subvector,
+                code,
+                centroids,
+                rows: range.count,
+                first_id,
+            });
+        }
+    }
+
+    fn check_codebooks(&mut self, config: &Config, codec: &PQCodec) {
+        if config.uses_pq() && codec.num_subvectors != config.pq_subvectors {
+            self.violations.push(Violation::SubvectorCount {
+                expected: config.pq_subvectors,
+                found: codec.num_subvectors,
+            });
+        }
+        if config.uses_pq() && codec.bits != config.pq_bits {
+            self.violations.push(Violation::CodeBits {
+                expected: config.pq_bits,
+                found: codec.bits,
+            });
+        }
+        if codec.codebooks.len() != codec.num_subvectors {
+            self.violations.push(Violation::CodebookCount {
+                expected: codec.num_subvectors,
+                found: codec.codebooks.len(),
+            });
+        }
+        for (subvector, codebook) in codec.codebooks.iter().enumerate() {
+            if let Some(centroid) = codebook.centroids.iter().find(|c| c.len() != codec.subvector_size) {
+                self.violations.push(Violation::CodebookDimensions {
+                    subvector,
+                    expected: codec.subvector_size,
+                    found: centroid.len(),
+                });
+            }
+        }
+    }
+
+    fn check_ivf(&mut self, config: &Config, vector_count: usize, code_count: Option<usize>, ivf: &IVFIndex) {
+        if ivf.dimensions() != config.dimensions {
+            self.violations.push(Violation::IndexDimensions {
+                expected: config.dimensions,
+                found: ivf.dimensions(),
+            });
+        }
+
+        if let Some(found) = ivf.recorded_metric()
+            && found != config.metric
+        {
+            self.violations.push(Violation::IndexMetric {
+                expected: config.metric,
+                found,
+            });
+        }
+
+        let mut wrong = Offenders::new();
+        for (index, centroid) in ivf.centroids().iter().enumerate() {
+            if centroid.len() != config.dimensions {
+                wrong.note(index as u32, centroid.len());
+            }
+        }
+        if let Some((first, found)) = wrong.first {
+            self.violations.push(Violation::CentroidDimensions {
+                expected: config.dimensions,
+                found,
+                centroids: wrong.count,
+                first: first as usize,
+            });
+        }
+
+        if ivf.num_lists() != ivf.num_clusters() {
+            self.violations.push(Violation::ListCount {
+                centroids: ivf.num_clusters(),
+                lists: ivf.num_lists(),
+            });
+        }
+
+        let mut out_of_range = Offenders::new();
+        let mut uncoded = Offenders::new();
+        for cluster in 0..ivf.num_lists() {
+            for &id in ivf.inverted_list(cluster).iter().chain(ivf.replica_list(cluster)) {
+                if id as usize >= vector_count {
+                    out_of_range.note(id, ());
+                } else if code_count.is_some_and(|codes| id as usize >= codes) {
+                    uncoded.note(id, ());
+                }
+            }
+        }
+        if let Some((first_id, ())) = out_of_range.first {
+            self.violations.push(Violation::IdOutOfRange {
+                vectors: vector_count,
+                ids: out_of_range.count,
+                first_id,
+            });
+        }
+        if let (Some((first_id, ())), Some(codes)) = (uncoded.first, code_count) {
+            self.violations.push(Violation::UncodedIds {
+                codes,
+                ids: uncoded.count,
+                first_id,
+            });
+        }
+    }
+}
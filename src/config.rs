@@ -1,10 +1,164 @@
+use crate::metadata_index::MetadataIndexConfig;
+use crate::quantization::kmeans::KMeansParams;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+/// How vectors are compared. Every metric gives a distance, smaller for
+/// nearer vectors, and results are ordered by it, nearest first;
+/// [`DistanceMetric::similarity`] turns one into a score where higher is
+/// better.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum DistanceMetric {
+    /// One minus the cosine similarity
     Cosine,
     Euclidean,
+    /// The negated inner product, so the largest inner product ranks
+    /// first, as maximum inner product search wants
     DotProduct,
+    /// L1 distance: the sum of absolute differences
+    Manhattan,
+    /// Number of components on opposite sides of
+    /// [`HAMMING_THRESHOLD`](crate::distance::HAMMING_THRESHOLD), for
+    /// binary codes stored one bit per 0.0/1.0 component
+    Hamming,
+}
+
+impl DistanceMetric {
+    /// `distance` as a score where higher is better: the cosine
+    /// similarity for cosine, the inner product for dot product, and the
+    /// negated distance for the rest. Ranks results exactly as the
+    /// distance does, in reverse.
+    pub fn similarity(self, distance: f32) -> f32 {
+        match self {
+            DistanceMetric::Cosine => 1.0 - distance,
+            _ => -distance,
+        }
+    }
+}
+
+/// How stored vectors are compressed for indexed search
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum QuantizerKind {
+    /// Indexed searches score the full vectors
+    None,
+
+    /// Product quantization: `pq_subvectors` codes of `pq_bits` bits each
+    #[default]
+    PQ,
+
+    /// Scalar quantization: one byte per component, 4x smaller than the
+    /// vectors and far closer to them than PQ
+    SQ8,
+}
+
+/// How searches find their candidates
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum IndexKind {
+    /// Probe `num_probe` of `num_clusters` k-means clusters, scoring codes
+    /// under `quantizer`
+    #[default]
+    IVF,
+
+    /// Score every row exactly, from a contiguous copy of the rows made by
+    /// `build_index()`. No clusters or codes are trained, so builds are
+    /// a copy and results are exact; suited to collections up to tens of
+    /// thousands of rows.
+    Flat,
+}
+
+/// Read a quantizer, or the `use_pq` flag that preceded it in saved
+/// configs: `true` was PQ and `false` none
+fn quantizer_or_use_pq<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<QuantizerKind, D::Error> {
+    struct QuantizerVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for QuantizerVisitor {
+        type Value = QuantizerKind;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a quantizer name or a use_pq flag")
+        }
+
+        fn visit_bool<E: serde::de::Error>(self, use_pq: bool) -> Result<QuantizerKind, E> {
+            Ok(if use_pq { QuantizerKind::PQ } else { QuantizerKind::None })
+        }
+
+        fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<QuantizerKind, E> {
+            QuantizerKind::deserialize(serde::de::value::StrDeserializer::new(name))
+        }
+    }
+
+    deserializer.deserialize_any(QuantizerVisitor)
+}
+
+/// When rows inserted into a built index are encoded and assigned to
+/// clusters. Applies to `insert`, `insert_opts`, `insert_batch` and
+/// `insert_flat`; changesets and `add_to_index` always maintain the index.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum EncodePolicy {
+    /// Encode and assign each insert as it arrives, so the index stays
+    /// current at the cost of a PQ encoding and a centroid scan per row
+    EagerOnInsert,
+
+    /// Leave inserts unencoded until `build_index()` or maintenance
+    /// catches the index up. Searches scan the unencoded rows exactly in
+    /// the meantime, so bulk loads pay nothing per row.
+    #[default]
+    DeferredToBuild,
+
+    /// Eager while the index is current and inserts arrive below
+    /// [`AUTO_EAGER_MAX_RATE`](crate::encoding::AUTO_EAGER_MAX_RATE) per
+    /// second, deferred otherwise
+    Auto,
+}
+
+/// Precision original vectors are stored in. Codes, centroids and
+/// queries are `f32` whatever the choice; rows are widened to `f32` as
+/// they are read.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Precision {
+    #[default]
+    F32,
+
+    /// IEEE half precision: 11 significant bits, magnitudes up to 65504
+    F16,
+
+    /// bfloat16: the range of `f32` with 8 significant bits
+    BF16,
+}
+
+impl Precision {
+    /// Bytes per stored component
+    pub fn bytes(self) -> usize {
+        match self {
+            Precision::F32 => 4,
+            Precision::F16 | Precision::BF16 => 2,
+        }
+    }
+
+    /// `value` rounded to the nearest half-precision value, as bits.
+    /// Only meaningful for [`Precision::F16`] and [`Precision::BF16`].
+    pub(crate) fn narrow(self, value: f32) -> u16 {
+        match self {
+            Precision::BF16 => half::bf16::from_f32(value).to_bits(),
+            _ => half::f16::from_f32(value).to_bits(),
+        }
+    }
+
+    /// The value half-precision `bits` hold
+    pub(crate) fn widen(self, bits: u16) -> f32 {
+        match self {
+            Precision::BF16 => half::bf16::from_bits(bits).to_f32(),
+            _ => half::f16::from_bits(bits).to_f32(),
+        }
+    }
+
+    /// Round `vector` in place to the values this precision can hold
+    pub(crate) fn round(self, vector: &mut [f32]) {
+        if self != Precision::F32 {
+            for value in vector {
+                *value = self.widen(self.narrow(*value));
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,8 +169,10 @@ pub struct Config {
     /// Distance metric to use
     pub metric: DistanceMetric,
     
-    /// Enable Product Quantization compression
-    pub use_pq: bool,
+    /// How vectors are compressed in the index. Saved where the
+    /// `use_pq` flag it replaces used to be, which still loads.
+    #[serde(alias = "use_pq", deserialize_with = "quantizer_or_use_pq")]
+    pub quantizer: QuantizerKind,
     
     /// Number of subvectors for PQ (typically 8)
     pub pq_subvectors: usize,
@@ -26,6 +182,250 @@ pub struct Config {
     
     /// Number of clusters to probe during search
     pub num_probe: usize,
+    
+    /// Seed for k-means initialization; `None` seeds from the OS.
+    /// Set it to make index builds reproducible.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    
+    /// Largest metadata value accepted per entry, measured as compact
+    /// JSON. Larger values fail with
+    /// [`KhadyotaError::MetadataTooLarge`](crate::error::KhadyotaError::MetadataTooLarge)
+    /// unless `external_metadata` is set.
+    #[serde(default = "default_max_metadata_bytes")]
+    pub max_metadata_bytes: usize,
+    
+    /// Accept values over `max_metadata_bytes`, keeping them apart from the
+    /// rest: they are saved in a side section and left out of results
+    /// unless a search asks for them with
+    /// [`SearchParams::include_external_metadata`](crate::search_params::SearchParams::include_external_metadata)
+    #[serde(default)]
+    pub external_metadata: bool,
+    
+    /// When inserts into a built index are encoded
+    #[serde(default)]
+    pub encode_policy: EncodePolicy,
+    
+    /// Rows the PQ codebooks and IVF centroids are trained on. Larger
+    /// collections are sampled down to this many, with `seed`; every row
+    /// is still encoded and assigned. `None` trains on every row.
+    #[serde(default = "default_max_training_vectors")]
+    pub max_training_vectors: Option<usize>,
+    
+    /// Bits per PQ code: 4 (16 centroids per subvector, two codes a byte)
+    /// for the smallest index, 8, or 16 (65536 centroids) for the most
+    /// accurate distances at a much slower build
+    #[serde(default = "default_pq_bits")]
+    pub pq_bits: usize,
+    
+    /// How far apart two floats may be and still count as equal when
+    /// comparing distances and results. Checksums stay byte-exact.
+    #[serde(default)]
+    pub tolerance: ToleranceConfig,
+    
+    /// Move IVF centroids towards the rows assigned to them between
+    /// builds, so probing keeps up with data whose distribution drifts.
+    /// `None` leaves centroids as the last build trained them, as do
+    /// residual PQ codes (see `use_residuals`).
+    #[serde(default)]
+    pub online_centroid_adaptation: Option<CentroidAdaptation>,
+    
+    /// Shorter query lengths accepted for Matryoshka embeddings, whose
+    /// leading components are a coarser embedding of their own. A query
+    /// of one of these lengths is scored against that prefix of every
+    /// stored vector. Ascending and below `dimensions`; with PQ, each
+    /// must end on a subvector boundary so the leading subquantizers
+    /// cover it exactly. Empty accepts full-length queries only.
+    #[serde(default)]
+    pub matryoshka_dims: Vec<usize>,
+    
+    /// Probe extra clusters when deletions have thinned the nearest ones,
+    /// so a search scores about as many live candidates as it would have
+    /// when the index was built. `None` probes exactly `num_probe`.
+    #[serde(default)]
+    pub probe_compensation: Option<ProbeCompensation>,
+    
+    /// PQ-encode each row's offset from its IVF centroid instead of the
+    /// row, so codebooks spend their centroids on detail within clusters
+    /// rather than on where the clusters are. Searches then score each
+    /// probed cluster with its own distance table. Applies to PQ under
+    /// Euclidean, dot-product and cosine metrics; the others, and SQ8,
+    /// encode rows as they are. Centroids stay put under residual codes,
+    /// which `online_centroid_adaptation` would invalidate.
+    ///
+    /// On by default. Configs saved before it existed load with it off,
+    /// so rebuilding their indexes encodes as before.
+    #[serde(default)]
+    pub use_residuals: bool,
+    
+    /// Memory budget and cardinality limits for the metadata keys indexed
+    /// by [`VectorDB::metadata_filter`](crate::vector_db::VectorDB::metadata_filter)
+    #[serde(default)]
+    pub metadata_index: MetadataIndexConfig,
+    
+    /// Scale cosine vectors to unit length as they are inserted, and
+    /// queries as they are searched, so exact distances are one minus a
+    /// dot product rather than needing both norms. Zero vectors, which
+    /// have no direction, are rejected. Other metrics ignore this.
+    ///
+    /// On by default. Configs saved before it existed load with it off,
+    /// since their rows were stored as given.
+    #[serde(default)]
+    pub normalize: bool,
+    
+    /// Learn an orthogonal rotation of the vectors before PQ splits them
+    /// into subvectors, so correlated dimensions share a codebook rather
+    /// than being cut apart. Training takes
+    /// [`OPQ_ITERATIONS`](crate::quantization::opq::OPQ_ITERATIONS) rounds
+    /// of PQ training more. Applies to PQ only, and rules out Matryoshka
+    /// prefixes, which a rotation mixes with the rest of each vector.
+    #[serde(default)]
+    pub use_opq: bool,
+    
+    /// Largest cluster size, as a multiple of the mean, that
+    /// [`VectorDB::optimize_index`](crate::vector_db::VectorDB::optimize_index)
+    /// leaves alone. Above it, oversized clusters are split and empty
+    /// ones dropped. At least 1.
+    #[serde(default = "default_max_cluster_imbalance")]
+    pub max_cluster_imbalance: f32,
+    
+    /// Precision original vectors are kept in, for reranking, exact
+    /// scans and `get()`. Half precision halves their memory; rows are
+    /// rounded as they are inserted. `save()` writes them widened to
+    /// `f32`; vector files from `save_mapped()` and `spill_originals()`
+    /// hold them as stored, and must match this to be mapped.
+    #[serde(default)]
+    pub raw_precision: Precision,
+    
+    /// Clusters each vector is listed in when the index is built: its
+    /// nearest and, above 1, the next nearest too, so searches near a
+    /// cluster boundary find it at a lower `num_probe`. Each replica costs
+    /// another id per vector in the inverted lists. Rows inserted into a
+    /// built index go to their nearest cluster only. At least 1.
+    #[serde(default = "default_ivf_assign_replicas")]
+    pub ivf_assign_replicas: usize,
+    
+    /// Index `build_index()` makes. Under [`IndexKind::Flat`] the
+    /// quantizer and IVF settings are ignored.
+    #[serde(default)]
+    pub index: IndexKind,
+    
+    /// K-means settings for learning the IVF centroids. A coarse
+    /// quantizer over many rows can often take fewer iterations than
+    /// the default 100 with little loss of recall.
+    #[serde(default)]
+    pub ivf_kmeans: KMeansParams,
+    
+    /// K-means settings for each PQ codebook, OPQ's included, and
+    /// ignored by the other quantizers
+    #[serde(default)]
+    pub pq_kmeans: KMeansParams,
+}
+
+/// Query-time probe adjustment under [`Config::probe_compensation`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProbeCompensation {
+    /// Most clusters a search may probe, as a multiple of the probe count
+    /// it asked for; bounds the cost after extreme deletion
+    pub max_factor: usize,
+}
+
+impl Default for ProbeCompensation {
+    fn default() -> Self {
+        Self { max_factor: 4 }
+    }
+}
+
+/// Online k-means updates applied as inserted rows are assigned to
+/// clusters, under [`Config::online_centroid_adaptation`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CentroidAdaptation {
+    /// Each assigned row moves its cluster's centroid `1 / n` of the way
+    /// towards it, for `n` members after the insert, so the centroid is
+    /// the members' running mean. This floor keeps large clusters moving
+    /// once `1 / n` gets small.
+    pub min_learning_rate: f32,
+    
+    /// Members of the updated cluster re-checked per update, taken in
+    /// turn, and moved if another centroid is now nearer. Bounds the cost
+    /// of keeping lists consistent with the moving centroids.
+    pub reassign_checks: usize,
+}
+
+impl Default for CentroidAdaptation {
+    fn default() -> Self {
+        Self {
+            min_learning_rate: 0.0,
+            reassign_checks: 4,
+        }
+    }
+}
+
+/// Float equality for distances and results: `a` and `b` are equal when
+/// they differ by at most `absolute`, or by at most `relative` times the
+/// larger magnitude. The comparisons live in
+/// [`distance::tolerance`](crate::distance::tolerance).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ToleranceConfig {
+    /// Allowed difference near zero, where relative error is meaningless
+    pub absolute: f32,
+    
+    /// Allowed difference as a fraction of the larger magnitude
+    pub relative: f32,
+}
+
+impl ToleranceConfig {
+    /// Only identical values are equal
+    pub const EXACT: ToleranceConfig = ToleranceConfig {
+        absolute: 0.0,
+        relative: 0.0,
+    };
+}
+
+/// Covers the rounding differences between the scalar and SIMD kernels
+/// over vectors of a few thousand components
+impl Default for ToleranceConfig {
+    fn default() -> Self {
+        Self {
+            absolute: 1e-5,
+            relative: 1e-4,
+        }
+    }
+}
+
+/// Default [`Config::max_metadata_bytes`]: far beyond any sensible
+/// document, small enough to stop a stray blob
+pub const DEFAULT_MAX_METADATA_BYTES: usize = 1 << 20;
+
+fn default_max_metadata_bytes() -> usize {
+    DEFAULT_MAX_METADATA_BYTES
+}
+
+/// Default [`Config::max_training_vectors`]: enough rows for stable
+/// centroids, few enough that training time stops growing with the data
+pub const DEFAULT_MAX_TRAINING_VECTORS: usize = 100_000;
+
+fn default_max_training_vectors() -> Option<usize> {
+    Some(DEFAULT_MAX_TRAINING_VECTORS)
+}
+
+/// Default [`Config::pq_bits`]: one byte per code
+pub const DEFAULT_PQ_BITS: usize = 8;
+
+fn default_pq_bits() -> usize {
+    DEFAULT_PQ_BITS
+}
+
+/// Default [`Config::max_cluster_imbalance`]: a cluster four times the
+/// mean costs a probe of it four times the typical one
+pub const DEFAULT_MAX_CLUSTER_IMBALANCE: f32 = 4.0;
+
+fn default_max_cluster_imbalance() -> f32 {
+    DEFAULT_MAX_CLUSTER_IMBALANCE
+}
+
+fn default_ivf_assign_replicas() -> usize {
+    1
 }
 
 impl Default for Config {
@@ -33,15 +433,81 @@ impl Default for Config {
         Self {
             dimensions: 512,
             metric: DistanceMetric::Cosine,
-            use_pq: true,
+            quantizer: QuantizerKind::PQ,
             pq_subvectors: 8,
             num_clusters: 100,
             num_probe: 10,
+            seed: None,
+            max_metadata_bytes: DEFAULT_MAX_METADATA_BYTES,
+            external_metadata: false,
+            encode_policy: EncodePolicy::default(),
+            max_training_vectors: default_max_training_vectors(),
+            pq_bits: DEFAULT_PQ_BITS,
+            tolerance: ToleranceConfig::default(),
+            online_centroid_adaptation: None,
+            matryoshka_dims: Vec::new(),
+            probe_compensation: None,
+            use_residuals: true,
+            metadata_index: MetadataIndexConfig::default(),
+            normalize: true,
+            use_opq: false,
+            max_cluster_imbalance: DEFAULT_MAX_CLUSTER_IMBALANCE,
+            raw_precision: Precision::F32,
+            ivf_assign_replicas: 1,
+            index: IndexKind::IVF,
+            ivf_kmeans: KMeansParams::default(),
+            pq_kmeans: KMeansParams::default(),
         }
     }
 }
 
+/// At or below this many dimensions, [`Config::for_dimensions`] turns PQ
+/// off: the vectors are already smaller than the codebooks that would
+/// compress them, and a flat or IVF-only scan is exact.
+pub const TINY_DIMENSIONS: usize = 4;
+
 impl Config {
+    /// Defaults adjusted for `dimensions`: PQ off for tiny vectors,
+    /// otherwise the largest `pq_subvectors` up to 8 that divides evenly.
+    pub fn for_dimensions(dimensions: usize) -> Self {
+        let defaults = Self::default();
+        let use_pq = dimensions > TINY_DIMENSIONS;
+        let quantizer = if use_pq { QuantizerKind::PQ } else { QuantizerKind::None };
+        let pq_subvectors = if use_pq {
+            (1..=defaults.pq_subvectors)
+                .rev()
+                .find(|m| dimensions.is_multiple_of(*m))
+                .unwrap_or(1)
+        } else {
+            1
+        };
+        
+        Self {
+            dimensions,
+            quantizer,
+            pq_subvectors,
+            ..defaults
+        }
+    }
+    
+    /// Start a [`ConfigBuilder`], which fills in whatever is not set from
+    /// the dimensions and the expected collection size
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+    
+    /// Defaults tuned for `expected_size` vectors of `dimensions`; see
+    /// [`ConfigBuilder::build`] for how each is chosen
+    pub fn auto(dimensions: usize, expected_size: usize) -> crate::error::Result<Self> {
+        Self::builder().dimensions(dimensions).expected_size(expected_size).build()
+    }
+    
+    /// Whether stored vectors and queries are scaled to unit length: set
+    /// `normalize` under the cosine metric
+    pub fn normalizes(&self) -> bool {
+        self.normalize && self.metric == DistanceMetric::Cosine
+    }
+    
     pub fn validate(&self) -> crate::error::Result<()> {
         if self.dimensions == 0 {
             return Err(crate::error::KhadyotaError::InvalidConfig(
@@ -49,19 +515,435 @@ impl Config {
             ));
         }
         
-        if self.use_pq && self.dimensions % self.pq_subvectors != 0 {
+        if self.max_training_vectors == Some(0) {
+            return Err(crate::error::KhadyotaError::InvalidConfig(
+                "max_training_vectors must be > 0; use None to train on every row".to_string()
+            ));
+        }
+        
+        let ToleranceConfig { absolute, relative } = self.tolerance;
+        if !(absolute.is_finite() && absolute >= 0.0 && relative.is_finite() && relative >= 0.0) {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "tolerance must be finite and non-negative, not {:?}",
+                self.tolerance
+            )));
+        }
+        
+        if let Some(adaptation) = self.online_centroid_adaptation
+            && !(0.0..=1.0).contains(&adaptation.min_learning_rate)
+        {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "min_learning_rate must be between 0 and 1, not {}",
+                adaptation.min_learning_rate
+            )));
+        }
+        
+        if self.max_cluster_imbalance.is_nan() || self.max_cluster_imbalance < 1.0 {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "max_cluster_imbalance must be at least 1, not {}",
+                self.max_cluster_imbalance
+            )));
+        }
+        
+        if self.ivf_assign_replicas == 0 {
+            return Err(crate::error::KhadyotaError::InvalidConfig(
+                "ivf_assign_replicas must be at least 1".to_string()
+            ));
+        }
+        
+        for (name, kmeans) in [("ivf_kmeans", &self.ivf_kmeans), ("pq_kmeans", &self.pq_kmeans)] {
+            if kmeans.max_iterations == 0 || kmeans.n_redo == 0 {
+                return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                    "{} needs max_iterations and n_redo of at least 1, not {} and {}",
+                    name, kmeans.max_iterations, kmeans.n_redo
+                )));
+            }
+            if !(kmeans.tolerance.is_finite() && kmeans.tolerance >= 0.0) {
+                return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                    "{} tolerance must be finite and non-negative, not {}",
+                    name, kmeans.tolerance
+                )));
+            }
+        }
+        
+        if self.uses_pq() && !matches!(self.pq_bits, 4 | 8 | 16) {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "pq_bits must be 4, 8 or 16, not {}",
+                self.pq_bits
+            )));
+        }
+        
+        if self.uses_pq() && self.pq_subvectors == 0 {
+            return Err(crate::error::KhadyotaError::InvalidConfig(
+                "pq_subvectors must be > 0; set quantizer to SQ8 or None to disable PQ".to_string()
+            ));
+        }
+        
+        if self.uses_pq() && !self.dimensions.is_multiple_of(self.pq_subvectors) {
+            let divisors: Vec<String> = (1..=self.dimensions.min(self.pq_subvectors))
+                .filter(|m| self.dimensions.is_multiple_of(*m))
+                .map(|m| m.to_string())
+                .collect();
             return Err(crate::error::KhadyotaError::InvalidConfig(
                 format!(
-                    "Dimensions ({}) must be divisible by pq_subvectors ({})",
-                    self.dimensions, self.pq_subvectors
+                    "Dimensions ({}) must be divisible by pq_subvectors ({}); \
+                     choose pq_subvectors from [{}] or set quantizer to SQ8 or None{}",
+                    self.dimensions,
+                    self.pq_subvectors,
+                    divisors.join(", "),
+                    if self.dimensions <= TINY_DIMENSIONS {
+                        format!(" (None recommended at {} dimensions or fewer)", TINY_DIMENSIONS)
+                    } else {
+                        String::new()
+                    }
                 )
             ));
         }
         
+        self.metadata_index.validate()?;
+        
+        if self.probe_compensation.is_some_and(|compensation| compensation.max_factor == 0) {
+            return Err(crate::error::KhadyotaError::InvalidConfig(
+                "probe_compensation max_factor must be > 0".to_string()
+            ));
+        }
+        
+        // The full width may be listed too; it is always accepted
+        let prefixes = self.matryoshka_dims.strip_suffix(&[self.dimensions]).unwrap_or(&self.matryoshka_dims);
+        let ascending = prefixes.windows(2).all(|pair| pair[0] < pair[1]);
+        if !ascending || prefixes.first() == Some(&0) || prefixes.last().is_some_and(|&last| last >= self.dimensions) {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "matryoshka_dims must ascend from above 0 to below dimensions ({}), not {:?}",
+                self.dimensions, self.matryoshka_dims
+            )));
+        }
+        if self.uses_opq() && !prefixes.is_empty() {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "use_opq rotates every component into each subvector, so matryoshka_dims {:?} cannot be scored",
+                self.matryoshka_dims
+            )));
+        }
+        if self.uses_pq()
+            && let Some(prefix) = prefixes.iter().find(|&&prefix| !prefix.is_multiple_of(self.subvector_size()))
+        {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "matryoshka prefix {} does not end on a PQ subvector boundary; with pq_subvectors {} \
+                 prefixes must be multiples of {}",
+                prefix,
+                self.pq_subvectors,
+                self.subvector_size()
+            )));
+        }
+        
+        Ok(())
+    }
+    
+    /// Whether a query of `len` components is a configured Matryoshka
+    /// prefix, shorter than the full width
+    pub fn is_matryoshka_prefix(&self, len: usize) -> bool {
+        len < self.dimensions && self.matryoshka_dims.contains(&len)
+    }
+    
+    /// Check that a stored configuration (`found`) agrees with this one on
+    /// everything that determines what the stored data means: dimensions,
+    /// metric and PQ layout. Tuning knobs (clusters, probes, seed) may differ.
+    pub(crate) fn check_matches(&self, found: &Config) -> crate::error::Result<()> {
+        let mismatch = |field, expected: String, found: String| {
+            Err(crate::error::KhadyotaError::ConfigMismatch { field, expected, found })
+        };
+        
+        if self.dimensions != found.dimensions {
+            return mismatch("dimensions", self.dimensions.to_string(), found.dimensions.to_string());
+        }
+        
+        if self.metric != found.metric {
+            return mismatch("metric", format!("{:?}", self.metric), format!("{:?}", found.metric));
+        }
+        
+        if self.quantizer != found.quantizer {
+            return mismatch("quantizer", format!("{:?}", self.quantizer), format!("{:?}", found.quantizer));
+        }
+        
+        if self.uses_pq() && self.pq_subvectors != found.pq_subvectors {
+            return mismatch(
+                "pq_subvectors",
+                self.pq_subvectors.to_string(),
+                found.pq_subvectors.to_string(),
+            );
+        }
+        
+        if self.uses_pq() && self.pq_bits != found.pq_bits {
+            return mismatch("pq_bits", self.pq_bits.to_string(), found.pq_bits.to_string());
+        }
+        
+        if self.uses_pq() && self.use_opq != found.use_opq {
+            return mismatch("use_opq", self.use_opq.to_string(), found.use_opq.to_string());
+        }
+        
+        if self.raw_precision != found.raw_precision {
+            return mismatch(
+                "raw_precision",
+                format!("{:?}", self.raw_precision),
+                format!("{:?}", found.raw_precision),
+            );
+        }
+        
         Ok(())
     }
     
     pub fn subvector_size(&self) -> usize {
         self.dimensions / self.pq_subvectors
     }
-}
\ No newline at end of file
+    
+    /// Whether the index is product quantized
+    pub fn uses_pq(&self) -> bool {
+        self.quantizer == QuantizerKind::PQ && self.index == IndexKind::IVF
+    }
+    
+    /// Whether PQ codes are of rotated vectors, see [`Config::use_opq`]
+    pub fn uses_opq(&self) -> bool {
+        self.use_opq && self.uses_pq()
+    }
+    
+    /// Whether a build encodes residuals, see [`Config::use_residuals`]
+    pub fn uses_residuals(&self) -> bool {
+        self.use_residuals
+            && self.uses_pq()
+            && matches!(self.metric, DistanceMetric::Euclidean | DistanceMetric::DotProduct | DistanceMetric::Cosine)
+    }
+    
+    /// Bytes of packed PQ codes per vector
+    pub fn pq_code_bytes(&self) -> usize {
+        (self.pq_subvectors * self.pq_bits).div_ceil(8)
+    }
+}
+/// Fewest and most clusters [`ConfigBuilder`] picks on its own
+pub const AUTO_CLUSTER_RANGE: (usize, usize) = (16, 65_536);
+
+/// Most PQ subvectors [`ConfigBuilder`] picks on its own
+pub const AUTO_MAX_PQ_SUBVECTORS: usize = 64;
+
+/// Builder for [`Config`]. Fields left unset are derived from the
+/// dimensions, `expected_size` and `target_recall` when [`build`](Self::build)
+/// is called, so the usual setup is one line:
+///
+/// ```
+/// # use khadyota::Config;
+/// let config = Config::builder().dimensions(768).expected_size(1_000_000).build().unwrap();
+/// assert_eq!((config.num_clusters, config.num_probe, config.pq_subvectors), (1000, 100, 64));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    dimensions: Option<usize>,
+    metric: Option<DistanceMetric>,
+    quantizer: Option<QuantizerKind>,
+    expected_size: Option<usize>,
+    target_recall: Option<f32>,
+    num_clusters: Option<usize>,
+    num_probe: Option<usize>,
+    pq_subvectors: Option<usize>,
+    pq_bits: Option<usize>,
+    seed: Option<u64>,
+    normalize: Option<bool>,
+    raw_precision: Option<Precision>,
+    index: Option<IndexKind>,
+}
+
+impl ConfigBuilder {
+    pub fn dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    pub fn quantizer(mut self, quantizer: QuantizerKind) -> Self {
+        self.quantizer = Some(quantizer);
+        self
+    }
+
+    /// Rows the collection is expected to hold, which sizes the clusters
+    pub fn expected_size(mut self, expected_size: usize) -> Self {
+        self.expected_size = Some(expected_size);
+        self
+    }
+
+    /// Fraction of the true nearest neighbors searches should find, in
+    /// (0, 1); sets how many clusters are probed. 0.9 when unset.
+    pub fn target_recall(mut self, target_recall: f32) -> Self {
+        self.target_recall = Some(target_recall);
+        self
+    }
+
+    pub fn num_clusters(mut self, num_clusters: usize) -> Self {
+        self.num_clusters = Some(num_clusters);
+        self
+    }
+
+    pub fn num_probe(mut self, num_probe: usize) -> Self {
+        self.num_probe = Some(num_probe);
+        self
+    }
+
+    pub fn pq_subvectors(mut self, pq_subvectors: usize) -> Self {
+        self.pq_subvectors = Some(pq_subvectors);
+        self
+    }
+
+    pub fn pq_bits(mut self, pq_bits: usize) -> Self {
+        self.pq_bits = Some(pq_bits);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = Some(normalize);
+        self
+    }
+
+    pub fn raw_precision(mut self, raw_precision: Precision) -> Self {
+        self.raw_precision = Some(raw_precision);
+        self
+    }
+
+    pub fn index(mut self, index: IndexKind) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Fill in what was not set and validate the result.
+    ///
+    /// - `num_clusters`: the square root of `expected_size`, within
+    ///   [`AUTO_CLUSTER_RANGE`]; the default config's count without one
+    /// - `num_probe`: a tenth of the clusters at the default 0.9 target
+    ///   recall, doubling each time the miss rate halves, so 0.95 probes
+    ///   a fifth and 0.99 every cluster
+    /// - `pq_subvectors`: the largest divisor of the dimensions up to
+    ///   [`AUTO_MAX_PQ_SUBVECTORS`]
+    /// - `quantizer`: PQ, or none at [`TINY_DIMENSIONS`] or fewer
+    ///
+    /// Fails without dimensions, or with a target recall outside (0, 1).
+    pub fn build(self) -> crate::error::Result<Config> {
+        let Some(dimensions) = self.dimensions else {
+            return Err(crate::error::KhadyotaError::InvalidConfig(
+                "ConfigBuilder needs dimensions".to_string()
+            ));
+        };
+        let target_recall = self.target_recall.unwrap_or(0.9);
+        if !(target_recall > 0.0 && target_recall < 1.0) {
+            return Err(crate::error::KhadyotaError::InvalidConfig(format!(
+                "target_recall must be between 0 and 1, not {}",
+                target_recall
+            )));
+        }
+        
+        let defaults = Config::for_dimensions(dimensions);
+        let quantizer = self.quantizer.unwrap_or(defaults.quantizer);
+        let num_clusters = self.num_clusters.unwrap_or_else(|| match self.expected_size {
+            Some(expected_size) => auto_clusters(expected_size),
+            None => defaults.num_clusters,
+        });
+        let num_probe = self.num_probe.unwrap_or_else(|| auto_probe(num_clusters, target_recall));
+        let pq_subvectors = self
+            .pq_subvectors
+            .unwrap_or_else(|| largest_divisor(dimensions, AUTO_MAX_PQ_SUBVECTORS));
+        
+        let config = Config {
+            dimensions,
+            metric: self.metric.unwrap_or(defaults.metric),
+            quantizer,
+            pq_subvectors,
+            num_clusters,
+            num_probe,
+            seed: self.seed,
+            pq_bits: self.pq_bits.unwrap_or(defaults.pq_bits),
+            normalize: self.normalize.unwrap_or(defaults.normalize),
+            raw_precision: self.raw_precision.unwrap_or(defaults.raw_precision),
+            index: self.index.unwrap_or(defaults.index),
+            ..defaults
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// The square root of `expected_size`, within [`AUTO_CLUSTER_RANGE`]
+fn auto_clusters(expected_size: usize) -> usize {
+    let (fewest, most) = AUTO_CLUSTER_RANGE;
+    ((expected_size as f64).sqrt().round() as usize).clamp(fewest, most)
+}
+
+/// A tenth of `num_clusters` at 0.9 recall, scaled by how much smaller
+/// the allowed miss rate is than 0.1
+fn auto_probe(num_clusters: usize, target_recall: f32) -> usize {
+    let fraction = (0.01 / (1.0 - target_recall as f64)).min(1.0);
+    ((num_clusters as f64 * fraction).round() as usize).clamp(1, num_clusters.max(1))
+}
+
+/// The largest divisor of `n` that is at most `limit`
+fn largest_divisor(n: usize, limit: usize) -> usize {
+    (1..=limit.min(n)).rev().find(|m| n.is_multiple_of(*m)).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusters_follow_the_square_root_within_range() {
+        assert_eq!(auto_clusters(0), 16);
+        assert_eq!(auto_clusters(100), 16);
+        assert_eq!(auto_clusters(10_000), 100);
+        assert_eq!(auto_clusters(1_000_000), 1000);
+        assert_eq!(auto_clusters(usize::MAX), 65_536);
+    }
+
+    #[test]
+    fn test_probes_grow_with_target_recall() {
+        assert_eq!(auto_probe(1000, 0.9), 100);
+        assert_eq!(auto_probe(1000, 0.95), 200);
+        assert_eq!(auto_probe(1000, 0.8), 50);
+        assert_eq!(auto_probe(1000, 0.99), 1000);
+        assert_eq!(auto_probe(16, 0.5), 1);
+    }
+
+    #[test]
+    fn test_largest_divisor_up_to_the_limit() {
+        assert_eq!(largest_divisor(768, 64), 64);
+        assert_eq!(largest_divisor(100, 64), 50);
+        assert_eq!(largest_divisor(97, 64), 1);
+        assert_eq!(largest_divisor(12, 64), 12);
+        assert_eq!(largest_divisor(1, 64), 1);
+    }
+
+    #[test]
+    fn test_builder_fills_and_keeps_fields() {
+        let config = Config::auto(100, 250_000).unwrap();
+        assert_eq!((config.num_clusters, config.num_probe, config.pq_subvectors), (500, 50, 50));
+        assert_eq!(config.quantizer, QuantizerKind::PQ);
+
+        let config = Config::builder()
+            .dimensions(64)
+            .metric(DistanceMetric::Euclidean)
+            .expected_size(10_000)
+            .target_recall(0.95)
+            .pq_subvectors(8)
+            .seed(4)
+            .build()
+            .unwrap();
+        assert_eq!((config.num_clusters, config.num_probe, config.pq_subvectors), (100, 20, 8));
+        assert_eq!((config.metric, config.seed), (DistanceMetric::Euclidean, Some(4)));
+
+        assert_eq!(Config::auto(3, 1000).unwrap().quantizer, QuantizerKind::None);
+        assert!(Config::builder().expected_size(10).build().is_err());
+        assert!(Config::builder().dimensions(8).target_recall(1.0).build().is_err());
+        assert!(Config::builder().dimensions(10).pq_subvectors(3).build().is_err());
+    }
+}
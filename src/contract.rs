@@ -0,0 +1,212 @@
+use crate::config::{Config, DistanceMetric};
+use crate::distance::{dot_product, normalized, HAMMING_THRESHOLD};
+use crate::error::{KhadyotaError, Result};
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Element types query vectors are accepted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dtype {
+    /// Used as is
+    F32,
+    /// Narrowed to `f32`; values outside its range are rejected rather
+    /// than becoming infinite
+    F64,
+}
+
+/// What a collection requires of query vectors, so client layers can
+/// check inputs up front and report precisely what is wrong
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputContract {
+    /// Exact number of components
+    pub dimensions: usize,
+
+    /// Shorter lengths also accepted, as Matryoshka prefixes
+    #[serde(default)]
+    pub prefix_dimensions: Vec<usize>,
+
+    /// Metric the query is scored by
+    pub metric: DistanceMetric,
+
+    /// Every component must be finite: no NaN or infinity
+    pub finite: bool,
+
+    /// The query must have non-zero length, since cosine distance to a
+    /// zero vector is undefined
+    pub nonzero: bool,
+
+    /// Whether the query's length is irrelevant to ranking (cosine), so
+    /// there is no need to normalize queries. Collections that set
+    /// [`Config::normalize`] rescale them before scoring anyway.
+    pub scale_invariant: bool,
+
+    /// For Hamming, components at or above this are set bits
+    pub bit_threshold: Option<f32>,
+
+    pub dtypes: Vec<Dtype>,
+}
+
+impl InputContract {
+    /// The contract a collection with `config` enforces
+    pub fn for_config(config: &Config) -> Self {
+        Self {
+            dimensions: config.dimensions,
+            prefix_dimensions: config.matryoshka_dims.iter().copied().filter(|&d| d < config.dimensions).collect(),
+            metric: config.metric,
+            finite: true,
+            nonzero: config.metric == DistanceMetric::Cosine,
+            scale_invariant: config.metric == DistanceMetric::Cosine,
+            bit_threshold: (config.metric == DistanceMetric::Hamming).then_some(HAMMING_THRESHOLD),
+            dtypes: vec![Dtype::F32, Dtype::F64],
+        }
+    }
+
+    /// Check `query` against the contract, naming the first offending
+    /// component in the error
+    pub fn validate(&self, query: &[f32]) -> Result<()> {
+        let dimensions = if self.prefix_dimensions.contains(&query.len()) { query.len() } else { self.dimensions };
+        check(dimensions, self.metric, self.finite, self.nonzero, query)
+    }
+
+    /// Narrow an `f64` query to `f32`, then validate it
+    pub fn narrow(&self, query: &[f64]) -> Result<Vec<f32>> {
+        if let Some((index, value)) = query
+            .iter()
+            .enumerate()
+            .find(|(_, x)| x.is_finite() && x.abs() > f32::MAX as f64)
+        {
+            return Err(KhadyotaError::InvalidQuery(format!(
+                "component {} is {}, outside the f32 range",
+                index, value
+            )));
+        }
+        let narrowed: Vec<f32> = query.iter().map(|&x| x as f32).collect();
+        self.validate(&narrowed)?;
+        Ok(narrowed)
+    }
+}
+
+/// The checks behind [`InputContract::validate`], callable on the search
+/// path without building a contract
+fn check(dimensions: usize, metric: DistanceMetric, finite: bool, nonzero: bool, query: &[f32]) -> Result<()> {
+    if query.len() != dimensions {
+        return Err(KhadyotaError::DimensionMismatch {
+            expected: dimensions,
+            got: query.len(),
+        });
+    }
+    if finite && let Some((index, value)) = query.iter().enumerate().find(|(_, x)| !x.is_finite()) {
+        return Err(KhadyotaError::InvalidQuery(format!(
+            "component {} is {}; every component must be finite",
+            index, value
+        )));
+    }
+    if nonzero && query.iter().all(|&x| x == 0.0) {
+        return Err(KhadyotaError::InvalidQuery(format!(
+            "the zero vector has no {:?} distance; queries must have non-zero length",
+            metric
+        )));
+    }
+    Ok(())
+}
+
+/// How far a squared length may be from 1 and still count as unit
+/// length, so a vector normalized once is not rescaled again by rounding
+const UNIT_LENGTH_TOLERANCE: f32 = 1e-5;
+
+fn is_unit_length(vector: &[f32]) -> bool {
+    (dot_product(vector, vector) - 1.0).abs() <= UNIT_LENGTH_TOLERANCE
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The last query a search scored on this thread, so tests can compare
+    /// it with [`VectorDB::prepare_query`]
+    pub(crate) static SCORED_QUERY: std::cell::RefCell<Option<Vec<f32>>> = const { std::cell::RefCell::new(None) };
+}
+
+impl VectorDB {
+    /// What this collection requires of query vectors
+    pub fn input_contract(&self) -> InputContract {
+        InputContract::for_config(&self.config)
+    }
+
+    /// Check a query against [`VectorDB::input_contract`] without
+    /// searching. Searches run the same check.
+    pub fn validate_query(&self, query: &[f32]) -> Result<()> {
+        let metric = self.config.metric;
+        let dimensions = if self.config.is_matryoshka_prefix(query.len()) { query.len() } else { self.config.dimensions };
+        check(dimensions, metric, true, metric == DistanceMetric::Cosine, query)
+    }
+
+    /// The exact vector a search for `query` would score with, or the
+    /// error that search would return for it
+    pub fn prepare_query(&self, query: &[f32]) -> Result<Vec<f32>> {
+        self.prepared_query(query).map(Cow::into_owned)
+    }
+
+    /// [`VectorDB::prepare_query`] for `f64` input
+    pub fn prepare_query_f64(&self, query: &[f64]) -> Result<Vec<f32>> {
+        let narrowed = self.input_contract().narrow(query)?;
+        self.prepare_query(&narrowed)
+    }
+
+    /// Validate `query` and apply any query-side transforms: scaling a
+    /// full-width query to unit length under [`Config::normalize`].
+    /// Matryoshka prefixes are compared with prefixes of rows, which are
+    /// not unit length, so they are left as given, as is any query that
+    /// needs no transform.
+    pub(crate) fn prepared_query<'a>(&self, query: &'a [f32]) -> Result<Cow<'a, [f32]>> {
+        self.validate_query(query)?;
+        if self.config.normalizes() && query.len() == self.config.dimensions && !is_unit_length(query) {
+            return Ok(Cow::Owned(normalized(query)));
+        }
+        Ok(Cow::Borrowed(query))
+    }
+
+    /// Reject a vector [`VectorDB::stored_row`] could not store: the
+    /// zero vector, when rows are normalized
+    pub(crate) fn check_row(&self, vector: &[f32]) -> Result<()> {
+        if self.config.normalizes() && vector.iter().all(|&x| x == 0.0) {
+            return Err(KhadyotaError::ZeroVector);
+        }
+        Ok(())
+    }
+
+    /// `vector` as it is stored, after [`VectorDB::check_row`] passed it:
+    /// unit length under [`Config::normalize`], as given otherwise, then
+    /// rounded to [`Config::raw_precision`]
+    pub(crate) fn stored_row(&self, vector: Vec<f32>) -> Vec<f32> {
+        let mut vector = if self.config.normalizes() && !is_unit_length(&vector) { normalized(&vector) } else { vector };
+        self.config.raw_precision.round(&mut vector);
+        vector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QuantizerKind;
+
+    #[test]
+    fn test_prepare_query_is_what_search_scores() {
+        let mut db = VectorDB::new(Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            ..Default::default()
+        })
+        .unwrap();
+        for i in 0..20 {
+            db.insert(vec![i as f32, 1.0, -1.0, 0.5], None).unwrap();
+        }
+        db.build_index().unwrap();
+
+        for query in [vec![3.0, 1.0, 0.0, 0.0], vec![1e-30, -2.5, 7.0, 0.0]] {
+            SCORED_QUERY.with(|seen| seen.borrow_mut().take());
+            db.search(&query, 3).unwrap();
+            let scored = SCORED_QUERY.with(|seen| seen.borrow_mut().take()).unwrap();
+            assert_eq!(scored, db.prepare_query(&query).unwrap());
+        }
+    }
+}
@@ -1,11 +1,48 @@
 use crate::config::DistanceMetric;
 
-/// Compute distance with automatic SIMD dispatch
+/// Compute distance with automatic SIMD dispatch. Smaller is nearer for
+/// every metric: the dot product metric's distance is the negated inner
+/// product.
 pub fn compute_distance(a: &[f32], b: &[f32], metric: DistanceMetric) -> f32 {
     match metric {
         DistanceMetric::Cosine => cosine_distance(a, b),
         DistanceMetric::Euclidean => euclidean_distance(a, b),
-        DistanceMetric::DotProduct => dot_product(a, b),
+        DistanceMetric::DotProduct => -dot_product(a, b),
+        DistanceMetric::Manhattan => manhattan_distance(a, b),
+        DistanceMetric::Hamming => hamming_distance(a, b),
+    }
+}
+
+/// Stand-in for [`compute_distance`] that ranks rows the same way: the
+/// squared distance for Euclidean, sparing a sqrt per row, and the
+/// distance itself otherwise. [`key_to_distance`] turns it back.
+pub(crate) fn distance_key(a: &[f32], b: &[f32], metric: DistanceMetric) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => euclidean_distance_squared(a, b),
+        _ => compute_distance(a, b, metric),
+    }
+}
+
+/// Cosine distance between vectors already of unit length: one minus
+/// their dot product, skipping both norms
+pub(crate) fn unit_cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0 - dot_product(a, b)
+}
+
+/// `metric`'s distance from a [`distance_key`]; bit for bit what
+/// [`compute_distance`] gives
+pub(crate) fn key_to_distance(metric: DistanceMetric, key: f32) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => key.sqrt(),
+        _ => key,
+    }
+}
+
+/// [`distance_key`] of a distance already computed
+pub(crate) fn distance_to_key(metric: DistanceMetric, distance: f32) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => distance * distance,
+        _ => distance,
     }
 }
 
@@ -13,46 +50,180 @@ pub fn compute_distance(a: &[f32], b: &[f32], metric: DistanceMetric) -> f32 {
 pub fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
     #[cfg(target_arch = "x86_64")]
     {
-        if is_x86_feature_detected!("avx2") && a.len() % 8 == 0 {
+        if super::simd::avx2_available() {
             unsafe { super::simd::cosine_distance_avx2(a, b) }
         } else {
             super::scalar::cosine_distance_scalar(a, b)
         }
     }
     
-    #[cfg(not(target_arch = "x86_64"))]
+    #[cfg(target_arch = "aarch64")]
+    {
+        if super::neon::neon_available() {
+            unsafe { super::neon::cosine_distance_neon(a, b) }
+        } else {
+            super::scalar::cosine_distance_scalar(a, b)
+        }
+    }
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
     {
         super::scalar::cosine_distance_scalar(a, b)
     }
 }
 
+/// Euclidean (L2) distance with runtime dispatch
 pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    euclidean_distance_squared(a, b).sqrt()
+}
+
+/// Squared Euclidean distance with runtime dispatch; ranks the same as
+/// [`euclidean_distance`] without the sqrt
+pub fn euclidean_distance_squared(a: &[f32], b: &[f32]) -> f32 {
     #[cfg(target_arch = "x86_64")]
     {
-        if is_x86_feature_detected!("avx2") && a.len() % 8 == 0 {
-            unsafe { super::simd::euclidean_distance_avx2(a, b) }
+        if super::simd::avx2_available() {
+            unsafe { super::simd::euclidean_distance_squared_avx2(a, b) }
+        } else {
+            super::scalar::euclidean_distance_squared_scalar(a, b)
+        }
+    }
+    
+    #[cfg(target_arch = "aarch64")]
+    {
+        if super::neon::neon_available() {
+            unsafe { super::neon::euclidean_distance_squared_neon(a, b) }
         } else {
-            super::scalar::euclidean_distance_scalar(a, b)
+            super::scalar::euclidean_distance_squared_scalar(a, b)
         }
     }
     
-    #[cfg(not(target_arch = "x86_64"))]
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
     {
-        super::scalar::euclidean_distance_scalar(a, b)
+        super::scalar::euclidean_distance_squared_scalar(a, b)
+    }
+}
+
+/// Manhattan (L1) distance with runtime dispatch
+pub fn manhattan_distance(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if super::simd::avx2_available() {
+            unsafe { super::simd::manhattan_distance_avx2(a, b) }
+        } else {
+            super::scalar::manhattan_distance_scalar(a, b)
+        }
+    }
+    
+    #[cfg(target_arch = "aarch64")]
+    {
+        if super::neon::neon_available() {
+            unsafe { super::neon::manhattan_distance_neon(a, b) }
+        } else {
+            super::scalar::manhattan_distance_scalar(a, b)
+        }
+    }
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        super::scalar::manhattan_distance_scalar(a, b)
+    }
+}
+
+/// Hamming distance with runtime dispatch, counting components on opposite
+/// sides of [`HAMMING_THRESHOLD`](super::scalar::HAMMING_THRESHOLD)
+pub fn hamming_distance(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if super::simd::avx2_available() {
+            unsafe { super::simd::hamming_distance_avx2(a, b) }
+        } else {
+            super::scalar::hamming_distance_scalar(a, b)
+        }
+    }
+    
+    #[cfg(target_arch = "aarch64")]
+    {
+        if super::neon::neon_available() {
+            unsafe { super::neon::hamming_distance_neon(a, b) }
+        } else {
+            super::scalar::hamming_distance_scalar(a, b)
+        }
+    }
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        super::scalar::hamming_distance_scalar(a, b)
+    }
+}
+
+/// Euclidean distances from `query` to every row, choosing the kernel once
+/// for the whole batch
+pub fn euclidean_distances(query: &[f32], rows: &[Vec<f32>]) -> Vec<f32> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if super::simd::avx2_available() {
+            return rows
+                .iter()
+                .map(|row| unsafe { super::simd::euclidean_distance_squared_avx2(query, row) }.sqrt())
+                .collect();
+        }
+    }
+    
+    #[cfg(target_arch = "aarch64")]
+    {
+        if super::neon::neon_available() {
+            return rows
+                .iter()
+                .map(|row| unsafe { super::neon::euclidean_distance_squared_neon(query, row) }.sqrt())
+                .collect();
+        }
+    }
+    
+    rows.iter()
+        .map(|row| super::scalar::euclidean_distance_squared_scalar(query, row).sqrt())
+        .collect()
+}
+
+/// `v` scaled to unit length; zero vectors are returned unchanged
+pub fn normalized(v: &[f32]) -> Vec<f32> {
+    let norm = dot_product(v, v).sqrt();
+    if norm > 0.0 {
+        v.iter().map(|x| x / norm).collect()
+    } else {
+        v.to_vec()
+    }
+}
+
+/// Distances from `query` to every row under `metric`, using the batched
+/// Euclidean kernel where it applies
+pub fn compute_distances(query: &[f32], rows: &[Vec<f32>], metric: DistanceMetric) -> Vec<f32> {
+    match metric {
+        DistanceMetric::Euclidean => euclidean_distances(query, rows),
+        _ => rows.iter().map(|row| compute_distance(query, row, metric)).collect(),
     }
 }
 
 pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
     #[cfg(target_arch = "x86_64")]
     {
-        if is_x86_feature_detected!("avx2") && a.len() % 8 == 0 {
+        if super::simd::avx2_available() {
             unsafe { super::simd::dot_product_avx2(a, b) }
         } else {
             super::scalar::dot_product_scalar(a, b)
         }
     }
     
-    #[cfg(not(target_arch = "x86_64"))]
+    #[cfg(target_arch = "aarch64")]
+    {
+        if super::neon::neon_available() {
+            unsafe { super::neon::dot_product_neon(a, b) }
+        } else {
+            super::scalar::dot_product_scalar(a, b)
+        }
+    }
+    
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
     {
         super::scalar::dot_product_scalar(a, b)
     }
@@ -1,7 +1,13 @@
 pub mod metrics;
 pub mod scalar;
+pub mod tolerance;
 
 #[cfg(target_arch = "x86_64")]
 pub mod simd;
 
-pub use metrics::{compute_distance, cosine_distance, euclidean_distance, dot_product};
\ No newline at end of file
+#[cfg(target_arch = "aarch64")]
+pub mod neon;
+
+pub use metrics::{compute_distance, compute_distances, cosine_distance, euclidean_distance, euclidean_distance_squared, euclidean_distances, dot_product, hamming_distance, manhattan_distance, normalized};
+pub use scalar::HAMMING_THRESHOLD;
+pub(crate) use metrics::{distance_key, distance_to_key, key_to_distance, unit_cosine_distance};
\ No newline at end of file
@@ -0,0 +1,255 @@
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+
+/// Whether the NEON kernels below can run. NEON is part of the baseline
+/// for every mainstream aarch64 target, but not for soft-float ones.
+#[cfg(target_arch = "aarch64")]
+pub fn neon_available() -> bool {
+    std::arch::is_aarch64_feature_detected!("neon")
+}
+
+/// Cosine similarity using NEON (4 floats at once, the last `len % 4`
+/// one at a time)
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn cosine_similarity_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+
+        let mut dot_sum = vdupq_n_f32(0.0);
+        let mut norm_a_sum = vdupq_n_f32(0.0);
+        let mut norm_b_sum = vdupq_n_f32(0.0);
+
+        let chunks = a.len() / 4;
+
+        for i in 0..chunks {
+            let offset = i * 4;
+
+            let va = vld1q_f32(a.as_ptr().add(offset));
+            let vb = vld1q_f32(b.as_ptr().add(offset));
+
+            // Fused multiply-add: sum + a * b
+            dot_sum = vfmaq_f32(dot_sum, va, vb);
+            norm_a_sum = vfmaq_f32(norm_a_sum, va, va);
+            norm_b_sum = vfmaq_f32(norm_b_sum, vb, vb);
+        }
+
+        // Horizontal sum across the 4 lanes
+        let mut dot = vaddvq_f32(dot_sum);
+        let mut norm_a = vaddvq_f32(norm_a_sum);
+        let mut norm_b = vaddvq_f32(norm_b_sum);
+
+        for i in chunks * 4..a.len() {
+            dot += a[i] * b[i];
+            norm_a += a[i] * a[i];
+            norm_b += b[i] * b[i];
+        }
+
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
+
+/// Cosine distance (1 - similarity) using NEON
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn cosine_distance_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        1.0 - cosine_similarity_neon(a, b)
+    }
+}
+
+/// Euclidean distance squared using NEON
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn euclidean_distance_squared_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+
+        let mut sum = vdupq_n_f32(0.0);
+        let chunks = a.len() / 4;
+
+        for i in 0..chunks {
+            let offset = i * 4;
+
+            let va = vld1q_f32(a.as_ptr().add(offset));
+            let vb = vld1q_f32(b.as_ptr().add(offset));
+
+            // (a - b)^2
+            let diff = vsubq_f32(va, vb);
+            sum = vfmaq_f32(sum, diff, diff);
+        }
+
+        let mut total = vaddvq_f32(sum);
+        for i in chunks * 4..a.len() {
+            let diff = a[i] - b[i];
+            total += diff * diff;
+        }
+        total
+    }
+}
+
+/// Euclidean distance using NEON
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn euclidean_distance_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        euclidean_distance_squared_neon(a, b).sqrt()
+    }
+}
+
+/// Dot product using NEON
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn dot_product_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+
+        let mut sum = vdupq_n_f32(0.0);
+        let chunks = a.len() / 4;
+
+        for i in 0..chunks {
+            let offset = i * 4;
+            let va = vld1q_f32(a.as_ptr().add(offset));
+            let vb = vld1q_f32(b.as_ptr().add(offset));
+            sum = vfmaq_f32(sum, va, vb);
+        }
+
+        let mut total = vaddvq_f32(sum);
+        for i in chunks * 4..a.len() {
+            total += a[i] * b[i];
+        }
+        total
+    }
+}
+
+/// Manhattan (L1) distance using NEON
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn manhattan_distance_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+
+        let mut sum = vdupq_n_f32(0.0);
+        let chunks = a.len() / 4;
+
+        for i in 0..chunks {
+            let offset = i * 4;
+            let va = vld1q_f32(a.as_ptr().add(offset));
+            let vb = vld1q_f32(b.as_ptr().add(offset));
+            // |a - b| in one instruction
+            sum = vaddq_f32(sum, vabdq_f32(va, vb));
+        }
+
+        let mut total = vaddvq_f32(sum);
+        for i in chunks * 4..a.len() {
+            total += (a[i] - b[i]).abs();
+        }
+        total
+    }
+}
+
+/// Hamming distance using NEON: compares 4 components against
+/// [`HAMMING_THRESHOLD`](super::scalar::HAMMING_THRESHOLD) at once and counts
+/// the differing bits
+///
+/// # Safety
+/// The caller must ensure the CPU supports NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn hamming_distance_neon(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+
+        let threshold = super::scalar::HAMMING_THRESHOLD;
+        let vthreshold = vdupq_n_f32(threshold);
+        let one = vdupq_n_u32(1);
+        let mut counts = vdupq_n_u32(0);
+        let chunks = a.len() / 4;
+
+        for i in 0..chunks {
+            let offset = i * 4;
+            let va = vld1q_f32(a.as_ptr().add(offset));
+            let vb = vld1q_f32(b.as_ptr().add(offset));
+            // All-ones lanes where the bits differ, reduced to 1 per lane
+            let differ = veorq_u32(vcgeq_f32(va, vthreshold), vcgeq_f32(vb, vthreshold));
+            counts = vaddq_u32(counts, vandq_u32(differ, one));
+        }
+
+        let mut count = vaddvq_u32(counts);
+        for i in chunks * 4..a.len() {
+            if (a[i] >= threshold) != (b[i] >= threshold) {
+                count += 1;
+            }
+        }
+        count as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::scalar::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn test_neon_matches_scalar() {
+        if !neon_available() {
+            println!("NEON not available, skipping test");
+            return;
+        }
+
+        for len in [1, 3, 4, 5, 7, 9, 383, 512] {
+            let a: Vec<f32> = (0..len).map(|i| (i as f32 * 0.7 + 1.0).sin()).collect();
+            let b: Vec<f32> = (0..len).map(|i| (i as f32 * 0.3).cos()).collect();
+
+            unsafe {
+                assert_relative_eq!(cosine_similarity_neon(&a, &b), cosine_similarity_scalar(&a, &b), epsilon = 1e-5);
+                assert_relative_eq!(cosine_distance_neon(&a, &b), cosine_distance_scalar(&a, &b), epsilon = 1e-5);
+                assert_relative_eq!(
+                    euclidean_distance_squared_neon(&a, &b),
+                    euclidean_distance_squared_scalar(&a, &b),
+                    epsilon = 1e-5,
+                    max_relative = 1e-5
+                );
+                assert_relative_eq!(
+                    euclidean_distance_neon(&a, &b),
+                    euclidean_distance_scalar(&a, &b),
+                    epsilon = 1e-5,
+                    max_relative = 1e-5
+                );
+                assert_relative_eq!(
+                    dot_product_neon(&a, &b),
+                    dot_product_scalar(&a, &b),
+                    epsilon = 1e-5,
+                    max_relative = 1e-5
+                );
+                assert_relative_eq!(
+                    manhattan_distance_neon(&a, &b),
+                    manhattan_distance_scalar(&a, &b),
+                    epsilon = 1e-5,
+                    max_relative = 1e-5
+                );
+                assert_eq!(hamming_distance_neon(&a, &b), hamming_distance_scalar(&a, &b));
+            }
+        }
+    }
+}
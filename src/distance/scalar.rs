@@ -58,6 +58,36 @@ pub fn dot_product_scalar(a: &[f32], b: &[f32]) -> f32 {
     sum
 }
 
+/// Manhattan distance (L1)
+pub fn manhattan_distance_scalar(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len());
+    
+    let mut sum = 0.0f32;
+    for i in 0..a.len() {
+        sum += (a[i] - b[i]).abs();
+    }
+    
+    sum
+}
+
+/// Components at or above this count as set bits for Hamming distance, so
+/// 0.0/1.0 vectors compare exactly and noisy ones round to the nearer bit
+pub const HAMMING_THRESHOLD: f32 = 0.5;
+
+/// Hamming distance between the bit patterns of `a` and `b`
+pub fn hamming_distance_scalar(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len());
+    
+    let mut count = 0u32;
+    for i in 0..a.len() {
+        if (a[i] >= HAMMING_THRESHOLD) != (b[i] >= HAMMING_THRESHOLD) {
+            count += 1;
+        }
+    }
+    
+    count as f32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,4 +116,23 @@ mod tests {
         let dist = euclidean_distance_scalar(&a, &b);
         assert_relative_eq!(dist, 5.0, epsilon = 1e-6);
     }
+    
+    #[test]
+    fn test_manhattan_distance() {
+        let a = vec![1.0, -2.0, 0.5];
+        let b = vec![4.0, 2.0, 0.5];
+        
+        assert_relative_eq!(manhattan_distance_scalar(&a, &b), 7.0, epsilon = 1e-6);
+    }
+    
+    #[test]
+    fn test_hamming_distance() {
+        let a = vec![1.0, 0.0, 1.0, 1.0];
+        let b = vec![1.0, 1.0, 0.0, 1.0];
+        assert_eq!(hamming_distance_scalar(&a, &b), 2.0);
+        
+        // Values round to the nearer bit
+        let noisy = vec![0.9, 0.1, 0.6, 0.7];
+        assert_eq!(hamming_distance_scalar(&a, &noisy), 0.0);
+    }
 }
\ No newline at end of file
@@ -1,101 +1,264 @@
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-/// Cosine similarity using AVX2 (8 floats at once)
+/// Whether the AVX2 kernels below can run: they need both AVX2 and FMA
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2")]
+pub fn avx2_available() -> bool {
+    is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+}
+
+/// Cosine similarity using AVX2 (8 floats at once, the last `len % 8`
+/// one at a time)
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
 pub unsafe fn cosine_similarity_avx2(a: &[f32], b: &[f32]) -> f32 {
-    assert_eq!(a.len(), b.len());
-    assert_eq!(a.len() % 8, 0, "Length must be multiple of 8 for AVX2");
+    unsafe {
+        assert_eq!(a.len(), b.len());
     
-    let mut dot_sum = _mm256_setzero_ps();
-    let mut norm_a_sum = _mm256_setzero_ps();
-    let mut norm_b_sum = _mm256_setzero_ps();
+        let mut dot_sum = _mm256_setzero_ps();
+        let mut norm_a_sum = _mm256_setzero_ps();
+        let mut norm_b_sum = _mm256_setzero_ps();
     
-    let chunks = a.len() / 8;
+        let chunks = a.len() / 8;
     
-    for i in 0..chunks {
-        let offset = i * 8;
+        for i in 0..chunks {
+            let offset = i * 8;
         
-        // Load 8 floats from a and b
-        let va = _mm256_loadu_ps(a.as_ptr().add(offset));
-        let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
+            // Load 8 floats from a and b
+            let va = _mm256_loadu_ps(a.as_ptr().add(offset));
+            let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
         
-        // Dot product: sum += a * b
-        dot_sum = _mm256_fmadd_ps(va, vb, dot_sum);
+            // Dot product: sum += a * b
+            dot_sum = _mm256_fmadd_ps(va, vb, dot_sum);
         
-        // Norms: sum += a * a, b * b
-        norm_a_sum = _mm256_fmadd_ps(va, va, norm_a_sum);
-        norm_b_sum = _mm256_fmadd_ps(vb, vb, norm_b_sum);
-    }
+            // Norms: sum += a * a, b * b
+            norm_a_sum = _mm256_fmadd_ps(va, va, norm_a_sum);
+            norm_b_sum = _mm256_fmadd_ps(vb, vb, norm_b_sum);
+        }
     
-    // Horizontal sum: reduce 8 values to 1
-    let dot = horizontal_sum_avx2(dot_sum);
-    let norm_a = horizontal_sum_avx2(norm_a_sum).sqrt();
-    let norm_b = horizontal_sum_avx2(norm_b_sum).sqrt();
+        // Horizontal sum: reduce 8 values to 1
+        let mut dot = horizontal_sum_avx2(dot_sum);
+        let mut norm_a = horizontal_sum_avx2(norm_a_sum);
+        let mut norm_b = horizontal_sum_avx2(norm_b_sum);
     
-    dot / (norm_a * norm_b)
+        for i in chunks * 8..a.len() {
+            dot += a[i] * b[i];
+            norm_a += a[i] * a[i];
+            norm_b += b[i] * b[i];
+        }
+    
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
 }
 
+/// Cosine distance (1 - similarity) using AVX2
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2")]
+#[target_feature(enable = "avx2,fma")]
 pub unsafe fn cosine_distance_avx2(a: &[f32], b: &[f32]) -> f32 {
-    1.0 - cosine_similarity_avx2(a, b)
+    unsafe {
+        1.0 - cosine_similarity_avx2(a, b)
+    }
 }
 
 /// Euclidean distance squared using AVX2
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2")]
+#[target_feature(enable = "avx2,fma")]
 pub unsafe fn euclidean_distance_squared_avx2(a: &[f32], b: &[f32]) -> f32 {
-    assert_eq!(a.len(), b.len());
-    assert_eq!(a.len() % 8, 0);
+    unsafe {
+        assert_eq!(a.len(), b.len());
     
-    let mut sum = _mm256_setzero_ps();
-    let chunks = a.len() / 8;
+        let mut sum = _mm256_setzero_ps();
+        let chunks = a.len() / 8;
     
-    for i in 0..chunks {
-        let offset = i * 8;
+        for i in 0..chunks {
+            let offset = i * 8;
         
-        let va = _mm256_loadu_ps(a.as_ptr().add(offset));
-        let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
+            let va = _mm256_loadu_ps(a.as_ptr().add(offset));
+            let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
         
-        // (a - b)^2
-        let diff = _mm256_sub_ps(va, vb);
-        sum = _mm256_fmadd_ps(diff, diff, sum);
-    }
+            // (a - b)^2
+            let diff = _mm256_sub_ps(va, vb);
+            sum = _mm256_fmadd_ps(diff, diff, sum);
+        }
     
-    horizontal_sum_avx2(sum)
+        let mut total = horizontal_sum_avx2(sum);
+        for i in chunks * 8..a.len() {
+            let diff = a[i] - b[i];
+            total += diff * diff;
+        }
+        total
+    }
 }
 
+/// Euclidean distance using AVX2
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2")]
+#[target_feature(enable = "avx2,fma")]
 pub unsafe fn euclidean_distance_avx2(a: &[f32], b: &[f32]) -> f32 {
-    euclidean_distance_squared_avx2(a, b).sqrt()
+    unsafe {
+        euclidean_distance_squared_avx2(a, b).sqrt()
+    }
 }
 
 /// Dot product using AVX2
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2")]
+#[target_feature(enable = "avx2,fma")]
 pub unsafe fn dot_product_avx2(a: &[f32], b: &[f32]) -> f32 {
-    assert_eq!(a.len(), b.len());
-    assert_eq!(a.len() % 8, 0);
+    unsafe {
+        assert_eq!(a.len(), b.len());
+    
+        let mut sum = _mm256_setzero_ps();
+        let chunks = a.len() / 8;
     
-    let mut sum = _mm256_setzero_ps();
-    let chunks = a.len() / 8;
+        for i in 0..chunks {
+            let offset = i * 8;
+            let va = _mm256_loadu_ps(a.as_ptr().add(offset));
+            let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
+            sum = _mm256_fmadd_ps(va, vb, sum);
+        }
     
-    for i in 0..chunks {
-        let offset = i * 8;
-        let va = _mm256_loadu_ps(a.as_ptr().add(offset));
-        let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
-        sum = _mm256_fmadd_ps(va, vb, sum);
+        let mut total = horizontal_sum_avx2(sum);
+        for i in chunks * 8..a.len() {
+            total += a[i] * b[i];
+        }
+        total
     }
+}
+
+/// Manhattan (L1) distance using AVX2
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn manhattan_distance_avx2(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+    
+        // Clearing the sign bit gives the absolute value
+        let sign = _mm256_set1_ps(-0.0);
+        let mut sum = _mm256_setzero_ps();
+        let chunks = a.len() / 8;
     
-    horizontal_sum_avx2(sum)
+        for i in 0..chunks {
+            let offset = i * 8;
+            let va = _mm256_loadu_ps(a.as_ptr().add(offset));
+            let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
+            let diff = _mm256_andnot_ps(sign, _mm256_sub_ps(va, vb));
+            sum = _mm256_add_ps(sum, diff);
+        }
+    
+        let mut total = horizontal_sum_avx2(sum);
+        for i in chunks * 8..a.len() {
+            total += (a[i] - b[i]).abs();
+        }
+        total
+    }
+}
+
+/// Hamming distance using AVX2: compares 8 components against
+/// [`HAMMING_THRESHOLD`](super::scalar::HAMMING_THRESHOLD) at once and counts
+/// the differing bits
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn hamming_distance_avx2(a: &[f32], b: &[f32]) -> f32 {
+    unsafe {
+        assert_eq!(a.len(), b.len());
+    
+        let threshold = _mm256_set1_ps(super::scalar::HAMMING_THRESHOLD);
+        let mut count = 0u32;
+        let chunks = a.len() / 8;
+    
+        for i in 0..chunks {
+            let offset = i * 8;
+            let va = _mm256_loadu_ps(a.as_ptr().add(offset));
+            let vb = _mm256_loadu_ps(b.as_ptr().add(offset));
+            let bits_a = _mm256_cmp_ps::<_CMP_GE_OQ>(va, threshold);
+            let bits_b = _mm256_cmp_ps::<_CMP_GE_OQ>(vb, threshold);
+            let differ = _mm256_movemask_ps(_mm256_xor_ps(bits_a, bits_b));
+            count += differ.count_ones();
+        }
+    
+        let threshold = super::scalar::HAMMING_THRESHOLD;
+        for i in chunks * 8..a.len() {
+            if (a[i] >= threshold) != (b[i] >= threshold) {
+                count += 1;
+            }
+        }
+        count as f32
+    }
+}
+
+/// Entries each subvector has in the flattened table [`pq_scan_avx2`]
+/// reads: one per 8-bit code
+pub const PQ_TABLE_WIDTH: usize = 256;
+
+/// Summed PQ table entries for a block of 8-bit code rows using AVX2:
+/// eight rows at a time, each subvector's entries for them fetched with
+/// one gather. `table` holds [`PQ_TABLE_WIDTH`] entries per subvector,
+/// back to back; row `r`'s codes start at `codes[r * stride]` and the
+/// first `subvectors` of them are looked up. The last `rows % 8` rows are
+/// summed one at a time. Each row is summed in subvector order, so the
+/// sums match a scalar loop exactly.
+///
+/// # Safety
+/// The caller must ensure the CPU supports AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn pq_scan_avx2(table: &[f32], subvectors: usize, codes: &[u8], stride: usize, out: &mut [f32]) {
+    unsafe {
+        let rows = out.len();
+        assert!(table.len() >= subvectors * PQ_TABLE_WIDTH);
+        assert!(stride >= subvectors);
+        assert!(rows == 0 || codes.len() >= (rows - 1) * stride + subvectors);
+    
+        let blocks = rows / 8;
+        for block in 0..blocks {
+            let row = codes.as_ptr().add(block * 8 * stride);
+            let mut sum = _mm256_setzero_ps();
+        
+            for j in 0..subvectors {
+                // Codes are bytes, so every index stays inside the table
+                let code = |r: usize| i32::from(*row.add(r * stride + j));
+                let indices = _mm256_setr_epi32(code(0), code(1), code(2), code(3), code(4), code(5), code(6), code(7));
+                let entries = _mm256_i32gather_ps::<4>(table.as_ptr().add(j * PQ_TABLE_WIDTH), indices);
+                sum = _mm256_add_ps(sum, entries);
+            }
+        
+            _mm256_storeu_ps(out.as_mut_ptr().add(block * 8), sum);
+        }
+    
+        for (r, distance) in out.iter_mut().enumerate().skip(blocks * 8) {
+            let row = &codes[r * stride..r * stride + subvectors];
+            *distance = row
+                .iter()
+                .enumerate()
+                .map(|(j, &code)| table[j * PQ_TABLE_WIDTH + code as usize])
+                .sum();
+        }
+    }
 }
 
 /// Horizontal sum: reduce __m256 (8 floats) to single float
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2")]
+#[target_feature(enable = "avx2,fma")]
 unsafe fn horizontal_sum_avx2(v: __m256) -> f32 {
     // Extract high and low 128-bit lanes
     let hi = _mm256_extractf128_ps(v, 1);
@@ -121,8 +284,8 @@ mod tests {
     #[test]
     #[cfg(target_arch = "x86_64")]
     fn test_avx2_matches_scalar() {
-        if !is_x86_feature_detected!("avx2") {
-            println!("AVX2 not available, skipping test");
+        if !avx2_available() {
+            println!("AVX2/FMA not available, skipping test");
             return;
         }
         
@@ -134,6 +297,68 @@ mod tests {
             let scalar_result = cosine_similarity_scalar(&a, &b);
             
             assert_relative_eq!(simd_result, scalar_result, epsilon = 1e-5);
+            
+            assert_relative_eq!(
+                manhattan_distance_avx2(&a, &b),
+                manhattan_distance_scalar(&a, &b),
+                max_relative = 1e-5
+            );
+            assert_eq!(hamming_distance_avx2(&a, &b), hamming_distance_scalar(&a, &b));
+        }
+    }
+    
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_avx2_handles_remainder_lanes() {
+        if !avx2_available() {
+            println!("AVX2/FMA not available, skipping test");
+            return;
+        }
+        
+        for len in [1, 7, 9, 383] {
+            let a: Vec<f32> = (0..len).map(|i| (i as f32 * 0.7 + 1.0).sin()).collect();
+            let b: Vec<f32> = (0..len).map(|i| (i as f32 * 0.3).cos()).collect();
+            
+            unsafe {
+                assert_relative_eq!(cosine_similarity_avx2(&a, &b), cosine_similarity_scalar(&a, &b), epsilon = 1e-5);
+                assert_relative_eq!(
+                    euclidean_distance_squared_avx2(&a, &b),
+                    euclidean_distance_squared_scalar(&a, &b),
+                    epsilon = 1e-5,
+                    max_relative = 1e-5
+                );
+                assert_relative_eq!(
+                    dot_product_avx2(&a, &b),
+                    dot_product_scalar(&a, &b),
+                    epsilon = 1e-5,
+                    max_relative = 1e-5
+                );
+            }
+        }
+    }
+    
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_pq_scan_matches_scalar_sums() {
+        if !avx2_available() {
+            println!("AVX2/FMA not available, skipping test");
+            return;
+        }
+        
+        let subvectors = 6;
+        let table: Vec<f32> = (0..subvectors * PQ_TABLE_WIDTH).map(|i| (i as f32 * 0.37).sin() + 1.0).collect();
+        let stride = 7;
+        let codes: Vec<u8> = (0..stride * 37).map(|i| (i * 97 % 256) as u8).collect();
+        
+        for rows in [0, 1, 8, 13, 37] {
+            let mut out = vec![0.0; rows];
+            unsafe { pq_scan_avx2(&table, subvectors, &codes, stride, &mut out) };
+            for (r, &sum) in out.iter().enumerate() {
+                let expected: f32 = (0..subvectors)
+                    .map(|j| table[j * PQ_TABLE_WIDTH + codes[r * stride + j] as usize])
+                    .sum();
+                assert_eq!(sum, expected, "row {} of {}", r, rows);
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,92 @@
+//! Float comparisons under a [`ToleranceConfig`], shared by everything
+//! that asks whether two distances or result lists are the same
+
+use crate::config::ToleranceConfig;
+use crate::types::SearchResult;
+use std::collections::HashMap;
+
+impl ToleranceConfig {
+    /// Whether `a` and `b` are equal within the tolerance.
+    ///
+    /// Identical values always are, including infinities of one sign and
+    /// `0.0` against `-0.0`; an infinity is never close to anything else,
+    /// and NaN is equal to nothing. Values of opposite sign only compare
+    /// equal through `absolute`, since their relative difference is
+    /// always above 100%.
+    pub fn approx_eq(&self, a: f32, b: f32) -> bool {
+        if a == b {
+            return true;
+        }
+        if !a.is_finite() || !b.is_finite() {
+            return false;
+        }
+        // Widened so the difference of two large values cannot overflow
+        let (a, b) = (f64::from(a), f64::from(b));
+        let difference = (a - b).abs();
+        difference <= f64::from(self.absolute) || difference <= f64::from(self.relative) * a.abs().max(b.abs())
+    }
+
+    /// Whether `a` and `b` have the same length and are equal element by
+    /// element
+    pub fn slices_approx_eq(&self, a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| self.approx_eq(x, y))
+    }
+
+    /// Whether two result lists agree up to the tolerance: distances equal
+    /// rank by rank, and ids differing only among tied distances. An id
+    /// in one list but not the other must tie with the last result, where
+    /// `k` cut a run of ties short.
+    pub fn results_equivalent(&self, a: &[SearchResult], b: &[SearchResult]) -> bool {
+        if a.len() != b.len() || !a.iter().zip(b).all(|(x, y)| self.approx_eq(x.distance, y.distance)) {
+            return false;
+        }
+        let covered = |results: &[SearchResult], others: &[SearchResult]| {
+            let distances: HashMap<u32, f32> = others.iter().map(|r| (r.id, r.distance)).collect();
+            let last = others.last().map(|r| r.distance);
+            results.iter().all(|r| match distances.get(&r.id) {
+                Some(&distance) => self.approx_eq(r.distance, distance),
+                None => last.is_some_and(|last| self.approx_eq(r.distance, last)),
+            })
+        };
+        covered(a, b) && covered(b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(entries: &[(u32, f32)]) -> Vec<SearchResult> {
+        entries
+            .iter()
+            .map(|&(id, distance)| SearchResult {
+                id,
+                distance,
+                metadata: None,
+                external_id: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_tolerance_is_equality() {
+        let exact = ToleranceConfig::EXACT;
+        assert!(exact.approx_eq(1.5, 1.5));
+        assert!(exact.approx_eq(0.0, -0.0));
+        assert!(!exact.approx_eq(1.0, 1.0 + f32::EPSILON));
+        assert!(!exact.approx_eq(f32::NAN, f32::NAN));
+    }
+
+    #[test]
+    fn test_results_may_differ_only_among_ties() {
+        let tolerance = ToleranceConfig::default();
+        let a = results(&[(1, 0.5), (2, 0.7), (3, 0.7)]);
+
+        assert!(tolerance.results_equivalent(&a, &results(&[(1, 0.500001), (3, 0.7), (2, 0.7)])));
+        // 4 ties with the last result, so a different cut
+        assert!(tolerance.results_equivalent(&a, &results(&[(1, 0.5), (2, 0.7), (4, 0.7)])));
+        assert!(!tolerance.results_equivalent(&a, &results(&[(2, 0.5), (1, 0.7), (3, 0.7)])));
+        assert!(!tolerance.results_equivalent(&a, &results(&[(1, 0.5), (2, 0.7), (3, 0.71)])));
+        assert!(!tolerance.results_equivalent(&a, &a[..2]));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config::EncodePolicy;
+use crate::error::Result;
+use crate::search_params::SearchParams;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Inserts per second below which [`EncodePolicy::Auto`] encodes each
+/// insert as it arrives
+pub const AUTO_EAGER_MAX_RATE: f64 = 100.0;
+
+/// Time constant of the insert rate average
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Exponentially decaying average of inserts per second
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InsertRate {
+    rate: f64,
+    last: Option<Instant>,
+}
+
+impl InsertRate {
+    /// Record `rows` inserted now, returning the updated rate
+    pub(crate) fn record(&mut self, rows: usize) -> f64 {
+        let now = Instant::now();
+        self.rate = self.at(now) + rows as f64 / RATE_WINDOW.as_secs_f64();
+        self.last = Some(now);
+        self.rate
+    }
+
+    /// The rate as of `now`, decayed since the last insert
+    fn at(&self, now: Instant) -> f64 {
+        match self.last {
+            Some(last) => self.rate * (-(now - last).as_secs_f64() / RATE_WINDOW.as_secs_f64()).exp(),
+            None => 0.0,
+        }
+    }
+}
+
+/// How much of the stored data the index covers, from
+/// [`VectorDB::index_status`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IndexStatus {
+    /// Searches are served: the index is current, or lags behind rows
+    /// that are scanned exactly until it catches up
+    pub searchable: bool,
+
+    /// The index covers every live row
+    pub current: bool,
+
+    /// Live rows without codes or a cluster. Searchable indexes scan
+    /// these exactly; otherwise this is every live row.
+    pub unencoded: usize,
+
+    pub encode_policy: EncodePolicy,
+
+    /// Inserts per second, averaged over about a second
+    pub insert_rate: f64,
+}
+
+impl VectorDB {
+    /// Whether the index covers the data, and how many rows wait to be
+    /// encoded by `build_index()` or maintenance
+    pub fn index_status(&self) -> IndexStatus {
+        let unencoded = match &self.index_lag {
+            _ if self.index_built => 0,
+            Some(lag) => lag.pending.iter().filter(|id| !self.deleted.contains(id)).count(),
+            None => self.len(),
+        };
+        IndexStatus {
+            searchable: self.is_searchable(),
+            current: self.index_built,
+            unencoded,
+            encode_policy: self.config.encode_policy,
+            insert_rate: self.insert_rate.at(Instant::now()),
+        }
+    }
+
+    /// Whether an IVF or flat index is in place, current or not
+    pub(crate) fn has_index(&self) -> bool {
+        self.ivf_index.is_some() || self.flat_index.is_some()
+    }
+
+    /// An index exists and either covers every row or knows which rows it
+    /// is missing
+    pub(crate) fn is_searchable(&self) -> bool {
+        self.index_built || self.index_lag.is_some()
+    }
+
+    /// Whether `rows` inserted now are encoded at once under the encode
+    /// policy. Counts them towards the insert rate either way.
+    pub(crate) fn encode_on_insert(&mut self, rows: usize) -> bool {
+        let rate = self.insert_rate.record(rows);
+        let current = self.index_built && self.has_index();
+        current
+            && match self.config.encode_policy {
+                EncodePolicy::EagerOnInsert => true,
+                EncodePolicy::DeferredToBuild => false,
+                EncodePolicy::Auto => rate < AUTO_EAGER_MAX_RATE,
+            }
+    }
+
+    /// Live rows a lagging index has no codes or cluster for, minus those
+    /// `params` exclude or filter out
+    pub(crate) fn unencoded_ids(&self, params: &SearchParams) -> Result<Vec<u32>> {
+        let Some(lag) = &self.index_lag else {
+            return Ok(Vec::new());
+        };
+        let mut filter = params.filter_pass();
+        let ids = lag
+            .pending
+            .iter()
+            .copied()
+            .filter(|&id| !self.deleted.contains(&id) && !params.exclude.contains(&id) && filter.admits(id))
+            .collect();
+        filter.finish()?;
+        Ok(ids)
+    }
+}
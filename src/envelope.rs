@@ -0,0 +1,108 @@
+use crate::config::DistanceMetric;
+use crate::error::{KhadyotaError, Result};
+use crate::search_params::SearchParams;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+
+/// Envelope schema written by this build. Bumped only for changes old
+/// readers cannot skip over; new optional fields keep the version.
+pub const ENVELOPE_SCHEMA_VERSION: u32 = 1;
+
+/// Search results in a stable wire schema, for results that are stored
+/// or sent to other processes.
+///
+/// Field names are fixed independently of [`SearchResult`]. Fields added
+/// later are optional and omitted when unset, and unknown fields are
+/// ignored, so envelopes written by older and newer builds of the same
+/// schema version read each other.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultEnvelope {
+    #[serde(rename = "schema_version")]
+    pub schema_version: u32,
+
+    /// Metric the distances are in
+    #[serde(rename = "metric")]
+    pub metric: DistanceMetric,
+
+    /// Results asked for; `results` may hold fewer
+    #[serde(rename = "k")]
+    pub k: usize,
+
+    /// Search parameters the results were produced with, as 16 hex
+    /// digits: a JSON number would lose precision in many consumers
+    #[serde(rename = "params_fingerprint")]
+    pub params_fingerprint: String,
+
+    #[serde(rename = "results")]
+    pub results: Vec<EnvelopeResult>,
+}
+
+/// One result inside a [`ResultEnvelope`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeResult {
+    #[serde(rename = "id")]
+    pub id: u32,
+
+    #[serde(rename = "distance")]
+    pub distance: f32,
+
+    #[serde(rename = "metadata", default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl ResultEnvelope {
+    /// Wrap `results` of a search for `k` neighbours under `params`.
+    /// `metric` is the one the distances were computed in.
+    pub fn new(results: &[SearchResult], metric: DistanceMetric, k: usize, params: &SearchParams) -> Self {
+        Self {
+            schema_version: ENVELOPE_SCHEMA_VERSION,
+            metric,
+            k,
+            params_fingerprint: format!("{:016x}", params.fingerprint()),
+            results: results
+                .iter()
+                .map(|r| EnvelopeResult {
+                    id: r.id,
+                    distance: r.distance,
+                    metadata: r.metadata.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether these results were produced with `params`
+    pub fn produced_with(&self, params: &SearchParams) -> bool {
+        self.params_fingerprint == format!("{:016x}", params.fingerprint())
+    }
+
+    /// Unwrap the results, refusing a schema newer than this build reads
+    pub fn into_results(self) -> Result<Vec<SearchResult>> {
+        if self.schema_version > ENVELOPE_SCHEMA_VERSION {
+            return Err(KhadyotaError::SerializationError(format!(
+                "result envelope has schema version {}; this build reads up to {}",
+                self.schema_version, ENVELOPE_SCHEMA_VERSION
+            )));
+        }
+        Ok(self
+            .results
+            .into_iter()
+            .map(|r| SearchResult {
+                id: r.id,
+                distance: r.distance,
+                metadata: r.metadata,
+                external_id: None,
+            })
+            .collect())
+    }
+}
+
+impl VectorDB {
+    /// [`VectorDB::search_with_params`], with the results wrapped in a
+    /// [`ResultEnvelope`] recording the metric, `k` and parameters
+    pub fn search_envelope(&self, query: &[f32], k: usize, params: &SearchParams) -> Result<ResultEnvelope> {
+        let results = self.search_with_params(query, k, params)?;
+        let metric = params.metric.unwrap_or(self.config.metric);
+        Ok(ResultEnvelope::new(&results, metric, k, params))
+    }
+}
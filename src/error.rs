@@ -8,12 +8,21 @@ pub enum KhadyotaError {
     #[error("Vector not found: {0}")]
     VectorNotFound(u32),
     
+    #[error("External id not found: {0}")]
+    ExternalIdNotFound(crate::external_ids::ExternalId),
+    
+    #[error("External id {0} already exists; set upsert to replace it")]
+    DuplicateExternalId(crate::external_ids::ExternalId),
+    
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
     
     #[error("Serialization error: {0}")]
     SerializationError(String),
     
+    #[error("Unsupported file format version {version}; this build reads versions {oldest} to {newest}")]
+    UnsupportedVersion { version: u32, oldest: u32, newest: u32 },
+    
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     
@@ -25,6 +34,67 @@ pub enum KhadyotaError {
     
     #[error("Index not built. Call build_index() first.")]
     IndexNotBuilt,
+    
+    #[error("An index build is already running; wait for it or cancel it first")]
+    BuildInProgress,
+    
+    #[error("Cancelled before it finished")]
+    Cancelled,
+    
+    #[error("Config mismatch on {field}: expected {expected}, found {found}")]
+    ConfigMismatch {
+        field: &'static str,
+        expected: String,
+        found: String,
+    },
+    
+    #[error("Change {op} is invalid: {source}")]
+    InvalidChange {
+        op: usize,
+        #[source]
+        source: Box<KhadyotaError>,
+    },
+    
+    #[error("Changelog jumps from seq {applied} to {found}; catch up from a newer snapshot")]
+    ChangelogGap {
+        applied: u64,
+        found: u64,
+    },
+    
+    #[error("Incompatible components: {0}")]
+    IncompatibleComponents(crate::compat::CompatibilityReport),
+    
+    #[error("This file needs the \"{0}\" cargo feature, which this build was compiled without")]
+    FeatureRequired(String),
+    
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+    
+    #[error("The zero vector has no direction to normalize; cosine collections with normalize set reject it")]
+    ZeroVector,
+    
+    #[error("Metadata is {size} bytes; the limit is {limit}")]
+    MetadataTooLarge { size: usize, limit: usize },
+    
+    #[error("Candidate filter ran over its {budget:?} budget after {calls} calls")]
+    FilterBudgetExceeded { budget: std::time::Duration, calls: usize },
+    
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+    
+    #[error("Segment {seq} failed: {error}")]
+    SegmentFailed { seq: u64, error: String },
+    
+    #[error("Only {healthy} of {total} segments are serving; {required} are required")]
+    TooFewSegments {
+        healthy: usize,
+        total: usize,
+        required: usize,
+    },
+    
+    #[cfg(feature = "arrow")]
+    #[error("Arrow error: {0}")]
+    ArrowError(#[from] arrow_schema::ArrowError),
 }
 
 pub type Result<T> = std::result::Result<T, KhadyotaError>;
\ No newline at end of file
@@ -0,0 +1,294 @@
+use crate::config::{DistanceMetric, QuantizerKind};
+use crate::distance::euclidean_distance_squared;
+use crate::error::{KhadyotaError, Result};
+use crate::quantization::KMeansParams;
+use crate::vector_db::VectorDB;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+/// K-means passes assumed at best, or the cap if lower; runs stop early
+/// once inertia settles
+const MIN_ITERATIONS: usize = 10;
+
+/// Slack on the slow end for memory stalls and centroid updates, which
+/// a calibration run on cached rows does not see
+const SLOW_FACTOR: f64 = 1.5;
+
+/// Rows timed against each other during calibration
+const SAMPLE_ROWS: usize = 256;
+
+/// Minimum time spent timing distances at each width
+const CALIBRATION_TIME: Duration = Duration::from_millis(60);
+
+/// Per-row cost of a `Vec` header, paid by every row held as `Vec<f32>`
+const ROW_OVERHEAD: usize = std::mem::size_of::<Vec<f32>>();
+
+/// What [`VectorDB::build_index`] would cost on this data and machine,
+/// from [`VectorDB::estimate_build`]. Every figure is a range; the
+/// assumptions behind the ends are listed in `assumptions`.
+#[derive(Debug, Clone)]
+pub struct BuildEstimate {
+    pub vectors: usize,
+    pub dimensions: usize,
+
+    /// IVF clusters the build will make, after clamping to the row count
+    pub clusters: usize,
+
+    /// PQ subvectors, when PQ is on
+    pub pq_subvectors: Option<usize>,
+
+    /// Measured cost of one squared-distance computation at full width
+    pub distance_cost: Duration,
+
+    /// IVF k-means, including seeding and the final assignment
+    pub ivf_time: RangeInclusive<Duration>,
+
+    /// Codebook training and encoding, when PQ is on
+    pub pq_time: Option<RangeInclusive<Duration>>,
+
+    pub total_time: RangeInclusive<Duration>,
+
+    /// Process memory at the busiest point of the build, including data
+    /// already held
+    pub peak_memory_bytes: RangeInclusive<usize>,
+
+    /// Size of the file [`VectorDB::save`] would write afterwards
+    pub file_bytes: RangeInclusive<u64>,
+
+    pub assumptions: Vec<String>,
+}
+
+impl VectorDB {
+    /// Estimate time, peak memory and saved size of
+    /// [`VectorDB::build_index`] without building.
+    ///
+    /// Distance cost is calibrated by timing a sample of the stored rows,
+    /// for about 60ms per distance width involved; everything else is
+    /// extrapolated from the work k-means and PQ encoding do per row.
+    /// Estimates are for the build as the database is configured now.
+    pub fn estimate_build(&self) -> Result<BuildEstimate> {
+        let n = self.vectors.len();
+        if n == 0 {
+            return Err(KhadyotaError::InvalidConfig(
+                "Cannot estimate a build with no vectors".to_string(),
+            ));
+        }
+        let dims = self.config.dimensions;
+        // k-means trains on a sample of large collections, then every row
+        // is assigned and encoded
+        let trained = self.config.max_training_vectors.map_or(n, |max| n.min(max));
+        let clusters = self.config.num_clusters.clamp(1, trained);
+        let pq = self.config.uses_pq().then_some(self.config.pq_subvectors.max(1));
+        // SQ8 trains in one pass over the sample, which is not worth timing
+        let sq = self.config.quantizer == QuantizerKind::SQ8;
+
+        let step = (n / SAMPLE_ROWS).max(1);
+        let sample: Vec<Vec<f32>> = (0..n)
+            .step_by(step)
+            .take(SAMPLE_ROWS)
+            .filter_map(|id| self.vectors.get(id as u32).map(|row| row.to_vec()))
+            .collect();
+        let full_cost = distance_cost(&sample, dims);
+
+        // Distances are spread over the rayon pool; the slow end assumes
+        // only half the threads are effective
+        let threads = rayon::current_num_threads();
+        let (fast_threads, slow_threads) = (threads as f64, (threads as f64 / 2.0).max(1.0));
+        let time = |distances: f64, cost: f64, iterations: usize| distances * cost * iterations as f64;
+        let (ivf_kmeans, pq_kmeans) = (&self.config.ivf_kmeans, &self.config.pq_kmeans);
+        let ivf_time = {
+            let (seeding, per_pass) = kmeans_distances(trained, clusters);
+            let assign = (n * clusters) as f64;
+            let (fewest, most) = iteration_range(ivf_kmeans);
+            let seeding = time(seeding, full_cost, ivf_kmeans.n_redo) + time(assign, full_cost, 1);
+            let low = seeding + time(per_pass, full_cost, fewest);
+            let high = seeding + time(per_pass, full_cost, most);
+            secs(low / fast_threads)..=secs(high * SLOW_FACTOR / slow_threads)
+        };
+
+        let pq_time = pq.map(|m| {
+            let sub = (dims / m).max(1);
+            let sub_cost = distance_cost(&sample, sub);
+            let centroids = (1 << self.config.pq_bits).min(trained);
+            let (seeding, per_pass) = kmeans_distances(trained, centroids);
+            let encode = (n * centroids) as f64;
+            let (fewest, most) = iteration_range(pq_kmeans);
+            let per_codebook = |iterations| {
+                time(seeding, sub_cost, pq_kmeans.n_redo) + time(encode, sub_cost, 1) + time(per_pass, sub_cost, iterations)
+            };
+            let low = per_codebook(fewest) * m as f64;
+            let high = per_codebook(most) * m as f64 * SLOW_FACTOR;
+            secs(low / fast_threads)..=secs(high / slow_threads)
+        });
+
+        let total_time = match &pq_time {
+            Some(pq) => (*ivf_time.start() + *pq.start())..=(*ivf_time.end() + *pq.end()),
+            None => ivf_time.clone(),
+        };
+
+        let usage = self.memory_usage();
+        let metadata = usage.metadata_bytes + usage.external_metadata_bytes;
+        let row_bytes = dims * 4 + ROW_OVERHEAD;
+        let held = match self.vectors.cold() {
+            Some(_) => metadata,
+            None => n * row_bytes + metadata,
+        };
+        // Codes are packed into one buffer; SQ8 takes a byte per component
+        let codes = match (pq, sq) {
+            (Some(_), _) => n * self.config.pq_code_bytes(),
+            (None, true) => n * dims,
+            (None, false) => 0,
+        };
+        let built = codes + n * 4 + clusters * row_bytes;
+        // Cold rows are read into memory for training, and a training
+        // sample is copied out; PQ slices one subvector column per
+        // codebook in training; cosine clusters a normalized copy; each
+        // k-means pass holds assignments and cluster member lists
+        let sampled = if trained < n { trained * row_bytes } else { 0 };
+        let materialized = if self.vectors.cold().is_some() { n * row_bytes } else { 0 } + sampled;
+        let kmeans_scratch = trained * 32;
+        let pq_scratch = pq.map_or(0, |m| {
+            let columns = m.min(threads);
+            columns * (trained * ((dims / m) * 4 + ROW_OVERHEAD) + kmeans_scratch)
+        });
+        let ivf_scratch = kmeans_scratch
+            + if self.config.metric == DistanceMetric::Cosine {
+                trained * row_bytes
+            } else {
+                0
+            };
+        let peak_memory_bytes = (held + built)..=(held + built + materialized + pq_scratch.max(ivf_scratch));
+
+        // MessagePack: f32 as 5 bytes, small ints as 1-5, array headers
+        // 1-5; packed codes are one binary
+        let vectors_file = (n * (dims * 5 + 3)) as u64;
+        let centroids_file = (clusters * (dims * 5 + 3)) as u64;
+        // Codebook rows are short, so their headers are 1-3 bytes
+        let (codes_low, codes_high) = match pq {
+            Some(m) => {
+                let codes = n * self.config.pq_code_bytes();
+                let centroids = m * (1 << self.config.pq_bits).min(trained);
+                let floats = centroids * (dims / m) * 5;
+                ((codes + floats + centroids) as u64, (codes + 5 + floats + 3 * centroids) as u64)
+            }
+            // Per-dimension minimums and scales
+            None if sq => {
+                let codes = n * dims + dims * 10;
+                (codes as u64, (codes + 32) as u64)
+            }
+            None => (0, 0),
+        };
+        let fixed = vectors_file + centroids_file + 256;
+        let file_bytes = (fixed + codes_low + n as u64 + (metadata as f64 * 0.8) as u64)
+            ..=(fixed + codes_high + 5 * n as u64 + (metadata as f64 * 1.1) as u64);
+
+        let mut assumptions = vec![
+            format!(
+                "distance cost measured on {} sampled rows: {:.1}ns at {} dimensions",
+                sample.len(),
+                full_cost * 1e9,
+                dims
+            ),
+            format!(
+                "IVF k-means runs {} to {} iterations over all its redos; the slow end allows {}x for memory stalls",
+                iteration_range(ivf_kmeans).0,
+                iteration_range(ivf_kmeans).1,
+                SLOW_FACTOR
+            ),
+            format!("k-means spread over {} threads, at least half of them effective", threads),
+        ];
+        if pq.is_some() {
+            let (fewest, most) = iteration_range(pq_kmeans);
+            assumptions.push(format!("each PQ codebook's k-means runs {} to {} iterations over all its redos", fewest, most));
+        }
+        if trained < n {
+            assumptions.push(format!("k-means trains on a sample of {} rows; all {} are assigned", trained, n));
+        }
+        if self.vectors.cold().is_some() {
+            assumptions.push("spilled rows are read back into memory for training".to_string());
+        }
+        if !self.deleted.is_empty() {
+            assumptions.push("deleted rows are still trained on and saved, as the build does".to_string());
+        }
+
+        Ok(BuildEstimate {
+            vectors: n,
+            dimensions: dims,
+            clusters,
+            pq_subvectors: pq,
+            distance_cost: secs(full_cost),
+            ivf_time,
+            pq_time,
+            total_time,
+            peak_memory_bytes,
+            file_bytes,
+            assumptions,
+        })
+    }
+}
+
+/// Distances k-means computes on `n` rows with `k` centroids: k-means++
+/// seeding scans every row against each new centroid, then each pass
+/// scans every row against all `k`
+fn kmeans_distances(n: usize, k: usize) -> (f64, f64) {
+    ((n * k.saturating_sub(1)) as f64, (n * k) as f64)
+}
+
+/// Fewest and most k-means passes over all of `kmeans.n_redo` runs
+fn iteration_range(kmeans: &KMeansParams) -> (usize, usize) {
+    let runs = kmeans.n_redo.max(1);
+    (MIN_ITERATIONS.min(kmeans.max_iterations) * runs, kmeans.max_iterations * runs)
+}
+
+/// Seconds per squared distance over the first `width` values of each
+/// sampled row, timed all-pairs for at least [`CALIBRATION_TIME`]
+fn distance_cost(sample: &[Vec<f32>], width: usize) -> f64 {
+    let rows: Vec<&[f32]> = sample.iter().map(|row| &row[..width.min(row.len())]).collect();
+    let started = Instant::now();
+    let mut computed = 0usize;
+    let mut sink = 0.0f32;
+    while computed == 0 || started.elapsed() < CALIBRATION_TIME {
+        for a in &rows {
+            for b in &rows {
+                sink += euclidean_distance_squared(a, b);
+            }
+        }
+        computed += rows.len() * rows.len();
+    }
+    std::hint::black_box(sink);
+    started.elapsed().as_secs_f64() / computed as f64
+}
+
+fn secs(seconds: f64) -> Duration {
+    Duration::from_secs_f64(seconds.max(0.0))
+}
+
+impl fmt::Display for BuildEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = |r: &RangeInclusive<Duration>| format!("{:.1?} to {:.1?}", r.start(), r.end());
+        let mb = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        writeln!(f, "Build estimate for {} vectors x {} dimensions:", self.vectors, self.dimensions)?;
+        writeln!(f, " - IVF clustering ({} clusters): {}", self.clusters, range(&self.ivf_time))?;
+        if let (Some(m), Some(pq)) = (self.pq_subvectors, &self.pq_time) {
+            writeln!(f, " - PQ training ({} subvectors): {}", m, range(pq))?;
+        }
+        writeln!(f, " - Total time: {}", range(&self.total_time))?;
+        writeln!(
+            f,
+            " - Peak memory: {:.1} to {:.1} MB",
+            mb(*self.peak_memory_bytes.start()),
+            mb(*self.peak_memory_bytes.end())
+        )?;
+        write!(
+            f,
+            " - Saved file: {:.1} to {:.1} MB\nAssuming:",
+            mb(*self.file_bytes.start() as usize),
+            mb(*self.file_bytes.end() as usize)
+        )?;
+        for assumption in &self.assumptions {
+            write!(f, "\n - {}", assumption)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+use crate::config::DistanceMetric;
+use crate::distance::compute_distance;
+use crate::error::{KhadyotaError, Result};
+use crate::harness::exact_neighbors;
+use crate::latency::Histogram;
+use crate::parallel::chunk_len;
+use crate::search_params::SearchParams;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Ids of the `k` nearest of `db_vectors` to each query, by brute force
+/// under `metric`, nearest first. Ties go to the lower id, so the result
+/// is deterministic; queries are scored in parallel, several to a task
+/// when the collection is small.
+pub fn compute_ground_truth(
+    db_vectors: &[Vec<f32>],
+    queries: &[Vec<f32>],
+    k: usize,
+    metric: DistanceMetric,
+) -> Vec<Vec<u32>> {
+    let cost = db_vectors.len() * db_vectors.first().map_or(0, Vec::len);
+    queries
+        .par_iter()
+        .with_min_len(chunk_len(cost))
+        .map(|query| {
+            let mut scored: Vec<(u32, f32)> = db_vectors
+                .iter()
+                .enumerate()
+                .map(|(id, vector)| (id as u32, compute_distance(query, vector, metric)))
+                .collect();
+            scored.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            scored.into_iter().take(k).map(|(id, _)| id).collect()
+        })
+        .collect()
+}
+
+/// Mean over queries of the share of each query's true top `k` found in
+/// the first `k` of its results. A query with no ground truth counts as
+/// fully recalled.
+///
+/// # Panics
+///
+/// If `results` and `ground_truth` hold a different number of queries
+pub fn recall_at_k(results: &[Vec<SearchResult>], ground_truth: &[Vec<u32>], k: usize) -> f32 {
+    assert_eq!(
+        results.len(),
+        ground_truth.len(),
+        "results and ground truth cover different numbers of queries"
+    );
+    if results.is_empty() {
+        return 1.0;
+    }
+    let total: f32 = results.iter().zip(ground_truth).map(|(found, truth)| query_recall(found, truth, k)).sum();
+    total / results.len() as f32
+}
+
+fn query_recall(found: &[SearchResult], truth: &[u32], k: usize) -> f32 {
+    let truth: HashSet<u32> = truth.iter().take(k).copied().collect();
+    if truth.is_empty() {
+        return 1.0;
+    }
+    let hits = found.iter().take(k).filter(|r| truth.contains(&r.id)).count();
+    hits as f32 / truth.len() as f32
+}
+
+/// Rows scored per query in an evaluation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandidateStats {
+    pub mean: f64,
+    pub min: usize,
+    pub max: usize,
+
+    /// Live entries, what a full scan would score
+    pub live_rows: usize,
+
+    /// IVF clusters probed per query; 0 for full scans
+    #[serde(default)]
+    pub mean_clusters: f64,
+
+    /// Queries that probed more clusters than asked, under
+    /// [`Config::probe_compensation`](crate::config::Config::probe_compensation)
+    #[serde(default)]
+    pub compensated_queries: usize,
+}
+
+/// Recall, latency and work of searching a set of queries, from
+/// [`VectorDB::evaluate`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub queries: usize,
+    pub k: usize,
+
+    /// Mean recall@k against brute force over the live entries
+    pub recall: f32,
+
+    /// Recall of the worst query
+    pub min_recall: f32,
+
+    /// Latency of each search
+    pub latency: Histogram,
+
+    pub candidates: CandidateStats,
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "recall@{} {:.3} (worst {:.3}) over {} queries; p50 {:?}, p95 {:?}, p99 {:?}; \
+             {:.1} candidates per query (min {}, max {}) of {} rows, from {:.1} clusters \
+             ({} queries compensated)",
+            self.k,
+            self.recall,
+            self.min_recall,
+            self.queries,
+            self.latency.p50(),
+            self.latency.p95(),
+            self.latency.p99(),
+            self.candidates.mean,
+            self.candidates.min,
+            self.candidates.max,
+            self.candidates.live_rows,
+            self.candidates.mean_clusters,
+            self.candidates.compensated_queries
+        )
+    }
+}
+
+/// Recall and latency of searches probing one number of clusters, from
+/// [`VectorDB::tune_probe`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeTrial {
+    pub num_probe: usize,
+
+    /// Mean recall@k of the sample queries
+    pub recall: f32,
+
+    pub mean_latency: Duration,
+}
+
+/// The `num_probe` chosen by [`VectorDB::tune_probe`], and every one tried
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TuneReport {
+    pub queries: usize,
+    pub k: usize,
+    pub target_recall: f32,
+
+    /// `num_probe` now configured
+    pub num_probe: usize,
+
+    /// Recall of the sample queries at `num_probe`
+    pub recall: f32,
+
+    /// Whether `recall` reaches the target. When it does not, `num_probe`
+    /// is the smallest that reached the best recall of the sweep.
+    pub target_met: bool,
+
+    /// Every `num_probe` tried, ascending
+    pub sweep: Vec<ProbeTrial>,
+}
+
+impl fmt::Display for TuneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "num_probe {}: recall@{} {:.3} over {} queries, target {:.3} {}",
+            self.num_probe,
+            self.k,
+            self.recall,
+            self.queries,
+            self.target_recall,
+            if self.target_met { "met" } else { "not met" }
+        )?;
+        for trial in &self.sweep {
+            write!(f, "\n  probe {:>5}: recall {:.3}, mean {:?}", trial.num_probe, trial.recall, trial.mean_latency)?;
+        }
+        Ok(())
+    }
+}
+
+impl VectorDB {
+    /// Search every query for its `k` nearest and measure the results
+    /// against brute force over the live entries.
+    ///
+    /// Searches run one at a time through [`VectorDB::search`], so they
+    /// count towards access statistics and a query cache serves repeated
+    /// queries; the ground truth and candidate counts do not.
+    pub fn evaluate(&self, queries: &[Vec<f32>], k: usize) -> Result<EvalReport> {
+        let params = SearchParams::default();
+        let mut latency = Histogram::new();
+        let mut results = Vec::with_capacity(queries.len());
+        for query in queries {
+            let start = Instant::now();
+            results.push(self.search(query, k)?);
+            latency.record(start.elapsed());
+        }
+
+        let exact = queries
+            .par_iter()
+            .with_min_len(chunk_len(self.len() * self.config.dimensions))
+            .map(|query| exact_neighbors(self, query, k))
+            .collect::<Result<Vec<_>>>()?;
+        let ground_truth: Vec<Vec<u32>> = exact.iter().map(|e| e.iter().map(|r| r.id).collect()).collect();
+        let recalls: Vec<f32> = results.iter().zip(&ground_truth).map(|(found, truth)| query_recall(found, truth, k)).collect();
+
+        let scans: Vec<(usize, usize)> = queries
+            .iter()
+            .map(|query| {
+                let (probed, candidates) = self.scored_candidates(query, &params)?;
+                Ok((probed.len(), candidates.len()))
+            })
+            .collect::<Result<_>>()?;
+        let counts: Vec<usize> = scans.iter().map(|&(_, candidates)| candidates).collect();
+        let asked = self.ivf_index.as_ref().map_or(0, |ivf| ivf.num_probe());
+        let mean = |values: &[usize]| match values.len() {
+            0 => 0.0,
+            n => values.iter().sum::<usize>() as f64 / n as f64,
+        };
+        let probes: Vec<usize> = scans.iter().map(|&(probed, _)| probed).collect();
+
+        Ok(EvalReport {
+            queries: queries.len(),
+            k,
+            recall: recall_at_k(&results, &ground_truth, k),
+            min_recall: recalls.iter().copied().fold(1.0, f32::min),
+            latency,
+            candidates: CandidateStats {
+                mean: mean(&counts),
+                min: counts.iter().copied().min().unwrap_or(0),
+                max: counts.iter().copied().max().unwrap_or(0),
+                live_rows: self.len(),
+                mean_clusters: mean(&probes),
+                compensated_queries: probes.iter().filter(|&&probed| probed > asked).count(),
+            },
+        })
+    }
+
+    /// Set `num_probe` to the smallest that finds `target_recall` of each
+    /// sample query's true `k` nearest on average.
+    ///
+    /// Ground truth comes from brute force over the live entries; then
+    /// the queries are searched at 1, 2, 4, ... clusters up to every
+    /// cluster, timing each search. If no setting reaches the target, the
+    /// smallest with the best recall is set instead and the report says
+    /// the target was not met. Fails without an IVF index.
+    ///
+    /// Without a quantizer, plain searches score every row exactly and
+    /// only probe clusters when [`SearchParams::num_probe`] asks them to,
+    /// so the setting mostly matters to quantized databases.
+    pub fn tune_probe(&mut self, sample_queries: &[Vec<f32>], target_recall: f32, k: usize) -> Result<TuneReport> {
+        let invalid = |msg: &str| Err(KhadyotaError::InvalidConfig(msg.to_string()));
+        if !(target_recall > 0.0 && target_recall <= 1.0) {
+            return invalid("target_recall must be in (0, 1]");
+        }
+        if sample_queries.is_empty() || k == 0 {
+            return invalid("tuning num_probe needs sample queries and k > 0");
+        }
+        let Some(ivf) = &self.ivf_index else {
+            return invalid("tuning num_probe requires an IVF index");
+        };
+        let num_clusters = ivf.num_clusters().max(1);
+
+        let exact = sample_queries
+            .par_iter()
+            .with_min_len(chunk_len(self.len() * self.config.dimensions))
+            .map(|query| exact_neighbors(self, query, k))
+            .collect::<Result<Vec<_>>>()?;
+        let ground_truth: Vec<Vec<u32>> = exact.iter().map(|e| e.iter().map(|r| r.id).collect()).collect();
+
+        let probes = std::iter::successors(Some(1), |&probe| (probe < num_clusters).then(|| (probe * 2).min(num_clusters)));
+        let mut sweep = Vec::new();
+        for num_probe in probes {
+            let params = SearchParams {
+                num_probe: Some(num_probe),
+                ..SearchParams::default()
+            };
+            let mut elapsed = Duration::ZERO;
+            let mut results = Vec::with_capacity(sample_queries.len());
+            for query in sample_queries {
+                let start = Instant::now();
+                results.push(self.search_with_params(query, k, &params)?);
+                elapsed += start.elapsed();
+            }
+            sweep.push(ProbeTrial {
+                num_probe,
+                recall: recall_at_k(&results, &ground_truth, k),
+                mean_latency: elapsed / sample_queries.len() as u32,
+            });
+        }
+
+        let best = sweep
+            .iter()
+            .find(|trial| trial.recall >= target_recall)
+            .or_else(|| sweep.iter().reduce(|best, trial| if trial.recall > best.recall { trial } else { best }))
+            .expect("at least one probe is tried");
+        let (num_probe, recall) = (best.num_probe, best.recall);
+        self.set_num_probe(num_probe)?;
+        Ok(TuneReport {
+            queries: sample_queries.len(),
+            k,
+            target_recall,
+            num_probe,
+            recall,
+            target_met: recall >= target_recall,
+            sweep,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: u32) -> SearchResult {
+        SearchResult {
+            id,
+            distance: 0.0,
+            metadata: None,
+            external_id: None,
+        }
+    }
+
+    #[test]
+    fn test_ground_truth_is_nearest_first_with_ties_by_id() {
+        let vectors = vec![vec![3.0, 0.0], vec![1.0, 0.0], vec![0.0, 1.0], vec![-1.0, 0.0]];
+        let truth = compute_ground_truth(&vectors, &[vec![0.0, 0.0], vec![2.9, 0.0]], 3, DistanceMetric::Euclidean);
+        assert_eq!(truth, [vec![1, 2, 3], vec![0, 1, 2]]);
+        assert_eq!(compute_ground_truth(&vectors, &[vec![0.0, 0.0]], 10, DistanceMetric::Euclidean)[0].len(), 4);
+    }
+
+    #[test]
+    fn test_recall_counts_only_the_first_k() {
+        let results = vec![vec![result(1), result(9), result(2)], vec![]];
+        let truth = vec![vec![1, 2, 3], vec![]];
+        assert_eq!(recall_at_k(&results, &truth, 2), 0.75);
+        assert_eq!(recall_at_k(&results, &truth, 3), (2.0 / 3.0 + 1.0) / 2.0);
+        assert_eq!(recall_at_k(&[], &[], 5), 1.0);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::distance::compute_distance;
+use crate::error::{KhadyotaError, Result};
+use crate::search_params::SearchParams;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Why a search never returns an id, whatever `k` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissReason {
+    /// Listed in [`SearchParams::exclude`]
+    Excluded,
+
+    /// Rejected by [`SearchParams::filter`]
+    Filtered,
+
+    /// Its cluster is not among the `num_probe` nearest to the query
+    NotProbed,
+
+    /// Among the rows the search reaches, but past
+    /// [`SearchParams::max_candidates`]
+    BeyondMaxCandidates,
+
+    /// In no inverted list and not waiting on a lagging index, as with an
+    /// index assembled from parts that leave it out
+    NotIndexed,
+
+    /// Scored, then dropped for having expired
+    Expired,
+}
+
+/// How one id fared against one query, from [`VectorDB::explain_pair`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PairExplanation {
+    pub id: u32,
+
+    /// Distance between the query and the stored vector, in the metric
+    /// exact scoring uses; `None` if the vector could not be read back
+    /// from spilled storage
+    pub exact_distance: Option<f32>,
+
+    /// Distance from the id's quantized codes, which an indexed search
+    /// ranks by before any rerank; `None` without codes for it
+    pub approximate_distance: Option<f32>,
+
+    /// Each subquantizer's term of `approximate_distance` (one per
+    /// dimension for SQ8), before the terms are summed and finished into
+    /// the metric's units: squared for Euclidean, a similarity for cosine.
+    /// Residual codes add their centroid's term for dot products and
+    /// cosine after that.
+    pub contributions: Vec<f32>,
+
+    /// Cluster whose inverted list holds the id; `None` for unencoded
+    /// rows, which are listed under their old vector if at all
+    pub cluster: Option<usize>,
+
+    /// 1-based position of `cluster`, or of an earlier cluster holding a
+    /// replica of the id, in the query's probe order: the smallest
+    /// `num_probe` that reaches it
+    pub probe_depth: Option<usize>,
+
+    /// Clusters the search probes
+    pub num_probe: usize,
+
+    /// The search scans every row rather than probing clusters, so the
+    /// cluster does not decide whether the id is reached
+    pub full_scan: bool,
+
+    /// Written since a lagging index was built; scored exactly on every
+    /// search until the index catches up
+    pub unencoded: bool,
+
+    /// Why the search cannot return the id; `None` if it is scored and
+    /// ranked like any other candidate
+    pub missed: Option<MissReason>,
+
+    /// Live candidates the search scores, after exclusions and expiry
+    pub candidates: usize,
+
+    /// 0-based rank the id takes, or would take if it were reached, among
+    /// the candidates by exact distance; ties go in its favour. `None`
+    /// without its exact distance.
+    pub exact_rank: Option<usize>,
+
+    /// The same among the distances the search sorts before reranking:
+    /// approximate for encoded rows, exact for the rest. Without rerank
+    /// this is where the search returns it; with rerank it is re-scored
+    /// if this is below `max(rerank, k)`. `None` when nothing is
+    /// approximated.
+    pub approximate_rank: Option<usize>,
+}
+
+impl VectorDB {
+    /// Explain where `id` ranks for `query` under default search parameters
+    pub fn explain_pair(&self, query: &[f32], id: u32) -> Result<PairExplanation> {
+        self.explain_pair_with_params(query, id, &SearchParams::default())
+    }
+
+    /// Explain where `id` ranks for `query` in a search with `params`:
+    /// its exact and approximate distances, its cluster and how deep the
+    /// probe must go to reach it, what filtered it out, and its rank among
+    /// the candidates the search scores.
+    ///
+    /// A diagnostic: it scores every candidate exactly, which search does
+    /// not, and it neither records access statistics nor uses the query
+    /// cache. Fails like `search` for a bad query or an unbuilt index, and
+    /// with [`KhadyotaError::VectorNotFound`] for an id that is not live.
+    pub fn explain_pair_with_params(&self, query: &[f32], id: u32, params: &SearchParams) -> Result<PairExplanation> {
+        let query = &*self.check_query(query)?;
+        params.validate(self)?;
+        if id >= self.next_id || self.deleted.contains(&id) {
+            return Err(KhadyotaError::VectorNotFound(id));
+        }
+
+        let metric = params.metric.unwrap_or(self.config.metric);
+        let is_pending = |row: u32| self.index_lag.as_ref().is_some_and(|lag| lag.pending.contains(&row));
+        let unencoded = is_pending(id);
+        let ivf = self.ivf_index.as_ref();
+        let full_scan = self.is_full_scan(params);
+
+        // Codes are only scored by an indexed search, and only for rows
+        // the index covers
+        let quantized = self.quantized.as_ref().filter(|_| ivf.is_some());
+        let (probed, mut candidates) = self.scored_candidates(query, params)?;
+        let scorer = quantized.map(|q| {
+            let rows: Vec<u32> = candidates.iter().copied().chain([id]).collect();
+            self.code_scorer(q, query, &rows)
+        });
+        let approximate = |row: u32| {
+            let (quantized, scorer) = (quantized?, scorer.as_ref()?);
+            ((row as usize) < quantized.len() && !is_pending(row)).then(|| scorer.distance(row))
+        };
+        let exact = |row: u32| self.vectors.get(row).map(|vector| compute_distance(query, &vector, metric));
+
+        let reached = candidates.contains(&id);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        if !self.attributes.is_empty() {
+            candidates.retain(|&row| !self.is_expired(row, now));
+        }
+
+        let cluster = ivf.and_then(|ivf| ivf.cluster_of(id)).filter(|_| !unencoded);
+        // Replicas reach the id from other clusters too
+        let lists_id = |c: usize| Some(c) == cluster || ivf.is_some_and(|ivf| ivf.replica_list(c).binary_search(&id).is_ok());
+        let probe_depth = ivf.filter(|_| cluster.is_some()).and_then(|ivf| {
+            let order = ivf.probe_n(query, ivf.num_lists());
+            order.iter().position(|&c| lists_id(c)).map(|p| p + 1)
+        });
+        let num_probe = ivf.map_or(0, |ivf| params.num_probe.unwrap_or(ivf.num_probe()));
+
+        let missed = if params.exclude.contains(&id) {
+            Some(MissReason::Excluded)
+        } else if params.filter.as_ref().is_some_and(|filter| !filter.admits(id)) {
+            Some(MissReason::Filtered)
+        } else if reached {
+            self.is_expired(id, now).then_some(MissReason::Expired)
+        } else if full_scan || unencoded || probed.iter().any(|&c| lists_id(c)) {
+            Some(MissReason::BeyondMaxCandidates)
+        } else if cluster.is_some() {
+            Some(MissReason::NotProbed)
+        } else {
+            Some(MissReason::NotIndexed)
+        };
+
+        let exact_distance = exact(id);
+        let approximate_distance = approximate(id);
+        let rank_by = |distance: f32, score: &dyn Fn(u32) -> Option<f32>| {
+            candidates
+                .iter()
+                .filter(|&&row| row != id && score(row).is_some_and(|d| d < distance))
+                .count()
+        };
+        let exact_rank = exact_distance.map(|d| rank_by(d, &exact));
+        let approximate_rank = scorer.as_ref().and_then(|_| {
+            let score = |row: u32| approximate(row).or_else(|| exact(row));
+            approximate_distance.or(exact_distance).map(|d| rank_by(d, &score))
+        });
+
+        Ok(PairExplanation {
+            id,
+            exact_distance,
+            approximate_distance,
+            contributions: match (&scorer, approximate_distance) {
+                (Some(scorer), Some(_)) => scorer.terms(id),
+                _ => Vec::new(),
+            },
+            cluster,
+            probe_depth,
+            num_probe,
+            full_scan,
+            unencoded,
+            missed,
+            candidates: candidates.len(),
+            exact_rank,
+            approximate_rank,
+        })
+    }
+
+    /// Whether a search with `params` scans every row instead of probing
+    pub(crate) fn is_full_scan(&self, params: &SearchParams) -> bool {
+        self.ivf_index.is_none() || (self.quantized.is_none() && params.num_probe.is_none())
+    }
+
+    /// Clusters a search with `params` probes for `query` (none for a full
+    /// scan) and the rows it scores, before expiry, mirroring
+    /// `search_validated` without counting the scan
+    pub(crate) fn scored_candidates(&self, query: &[f32], params: &SearchParams) -> Result<(Vec<usize>, Vec<u32>)> {
+        match &self.ivf_index {
+            Some(ivf) if !self.is_full_scan(params) => {
+                let (probed, mut candidates) = self.probed_candidates(query, ivf, params)?;
+                candidates.extend(self.unencoded_ids(params)?);
+                Ok((probed, candidates))
+            }
+            _ => {
+                let mut filter = params.filter_pass();
+                let mut candidates: Vec<u32> = (0..self.vectors.len() as u32)
+                    .filter(|&i| !params.exclude.contains(&i) && !self.deleted.contains(&i) && filter.admits(i))
+                    .collect();
+                filter.finish()?;
+                if let Some(max) = params.max_candidates {
+                    candidates.truncate(max);
+                }
+                Ok((Vec::new(), candidates))
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::error::{KhadyotaError, Result};
+use crate::insert_options::InsertOptions;
+use crate::persist::PersistSection;
+use crate::storage::Section;
+use crate::types::VectorEntry;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Name of the save-file section holding the external id mapping
+pub(crate) const SECTION_NAME: &str = "external_ids";
+
+/// A caller's own key for an entry, such as a document UUID, kept beside
+/// the internal `u32` id. Given to [`VectorDB::insert_with_id`] and
+/// returned with search results.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExternalId {
+    U64(u64),
+    Str(String),
+}
+
+impl From<u64> for ExternalId {
+    fn from(id: u64) -> Self {
+        ExternalId::U64(id)
+    }
+}
+
+impl From<String> for ExternalId {
+    fn from(id: String) -> Self {
+        ExternalId::Str(id)
+    }
+}
+
+impl From<&str> for ExternalId {
+    fn from(id: &str) -> Self {
+        ExternalId::Str(id.to_string())
+    }
+}
+
+impl fmt::Display for ExternalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExternalId::U64(id) => write!(f, "{}", id),
+            ExternalId::Str(id) => write!(f, "{:?}", id),
+        }
+    }
+}
+
+/// Live entries' external ids, both ways. Only `by_id` is saved; the
+/// reverse map is rebuilt from it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ExternalIds {
+    by_id: BTreeMap<u32, ExternalId>,
+    by_external: HashMap<ExternalId, u32>,
+}
+
+impl ExternalIds {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    pub(crate) fn id_of(&self, external: &ExternalId) -> Option<u32> {
+        self.by_external.get(external).copied()
+    }
+
+    pub(crate) fn external(&self, id: u32) -> Option<&ExternalId> {
+        self.by_id.get(&id)
+    }
+
+    /// Map `id` to `external`, dropping whatever either was mapped to
+    pub(crate) fn bind(&mut self, id: u32, external: ExternalId) {
+        if let Some(other) = self.by_external.remove(&external) {
+            self.by_id.remove(&other);
+        }
+        self.unbind(id);
+        self.by_external.insert(external.clone(), id);
+        self.by_id.insert(id, external);
+    }
+
+    pub(crate) fn unbind(&mut self, id: u32) -> Option<ExternalId> {
+        let external = self.by_id.remove(&id)?;
+        self.by_external.remove(&external);
+        Some(external)
+    }
+
+    /// Renumber after compaction; `id_map` is indexed by old id
+    pub(crate) fn rekey(&mut self, id_map: &[Option<u32>]) {
+        let by_id = std::mem::take(&mut self.by_id);
+        *self = Self::from_ids(
+            by_id
+                .into_iter()
+                .filter_map(|(id, external)| Some((id_map.get(id as usize).copied().flatten()?, external)))
+                .collect(),
+        );
+    }
+
+    fn from_ids(by_id: BTreeMap<u32, ExternalId>) -> Self {
+        let by_external = by_id.iter().map(|(&id, external)| (external.clone(), id)).collect();
+        Self { by_id, by_external }
+    }
+
+    /// Ordered by internal id, for checksums and reproducible saves
+    pub(crate) fn by_id(&self) -> &BTreeMap<u32, ExternalId> {
+        &self.by_id
+    }
+}
+
+impl VectorDB {
+    /// Insert a vector under the caller's own id, failing with
+    /// [`KhadyotaError::DuplicateExternalId`] if a live entry has it.
+    /// Returns the internal id.
+    ///
+    /// To replace an existing entry instead, pass the id to
+    /// [`InsertOptions::external_id`] with `upsert` set.
+    pub fn insert_with_id(
+        &mut self,
+        external_id: impl Into<ExternalId>,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<u32> {
+        let opts = InsertOptions {
+            metadata,
+            ..InsertOptions::new().external_id(external_id)
+        };
+        self.insert_opts(vector, opts)
+    }
+
+    /// Internal id of the live entry inserted under `external_id`
+    pub fn id_of(&self, external_id: impl Into<ExternalId>) -> Option<u32> {
+        self.external_ids.id_of(&external_id.into())
+    }
+
+    /// External id `id` was inserted under, if any
+    pub fn external_id(&self, id: u32) -> Option<&ExternalId> {
+        self.external_ids.external(id)
+    }
+
+    /// [`VectorDB::get`] by external id
+    pub fn get_by_external_id(&self, external_id: impl Into<ExternalId>) -> Result<VectorEntry> {
+        self.get(self.resolve_external_id(external_id.into())?)
+    }
+
+    /// [`VectorDB::delete`] by external id, which is then free to reuse
+    pub fn delete_by_external_id(&mut self, external_id: impl Into<ExternalId>) -> Result<()> {
+        self.delete(self.resolve_external_id(external_id.into())?)
+    }
+
+    /// [`VectorDB::update`] by external id
+    pub fn update_by_external_id(
+        &mut self,
+        external_id: impl Into<ExternalId>,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<()> {
+        self.update(self.resolve_external_id(external_id.into())?, vector, metadata)
+    }
+
+    fn resolve_external_id(&self, external_id: ExternalId) -> Result<u32> {
+        self.external_ids
+            .id_of(&external_id)
+            .ok_or(KhadyotaError::ExternalIdNotFound(external_id))
+    }
+
+    pub(crate) fn bind_external_id(&mut self, id: u32, external_id: Option<ExternalId>) {
+        if self.external_ids.external(id) == external_id.as_ref() {
+            return;
+        }
+        self.mark_dirty(&[PersistSection::AppState]);
+        match external_id {
+            Some(external_id) => self.external_ids.bind(id, external_id),
+            None => {
+                self.external_ids.unbind(id);
+            }
+        }
+    }
+
+    /// The mapping as a save-file section, if there is one. Files without
+    /// it load with no external ids.
+    pub(crate) fn external_ids_section(&self) -> Result<Option<Section>> {
+        if self.external_ids.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Section::new(SECTION_NAME, rmp_serde::to_vec(self.external_ids.by_id())?)))
+    }
+}
+
+/// Restore the mapping saved by [`VectorDB::external_ids_section`]
+pub(crate) fn external_ids_from_section(section: &Section) -> Result<ExternalIds> {
+    Ok(ExternalIds::from_ids(rmp_serde::from_slice(&section.payload)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_keeps_both_directions_one_to_one() {
+        let mut ids = ExternalIds::default();
+        ids.bind(0, "a".into());
+        ids.bind(1, 7u64.into());
+        ids.bind(0, "b".into());
+        assert_eq!((ids.id_of(&"a".into()), ids.id_of(&"b".into())), (None, Some(0)));
+
+        // Moving an external id to another entry unmaps its old one
+        ids.bind(2, 7u64.into());
+        assert_eq!((ids.external(1), ids.id_of(&7u64.into())), (None, Some(2)));
+
+        ids.rekey(&[Some(0), None, Some(1)]);
+        assert_eq!(ids.by_id(), &BTreeMap::from([(0, "b".into()), (1, 7u64.into())]));
+        assert_eq!(ids.id_of(&7u64.into()), Some(1));
+    }
+
+    #[test]
+    fn test_ids_encode_as_plain_values() {
+        let ids = [ExternalId::from(42u64), ExternalId::from("42")];
+        assert_eq!(serde_json::to_string(&ids).unwrap(), r#"[42,"42"]"#);
+        let decoded: Vec<ExternalId> = rmp_serde::from_slice(&rmp_serde::to_vec(&ids).unwrap()).unwrap();
+        assert_eq!(decoded, ids);
+        assert_eq!(format!("{} {}", ids[0], ids[1]), r#"42 "42""#);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::error::{KhadyotaError, Result};
+use std::ffi::c_void;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A filter callback with a C ABI: whether the entry `id` may appear in
+/// results, given the `user_data` it was registered with
+pub type NativeFilterFn = extern "C" fn(id: u32, user_data: *mut c_void) -> bool;
+
+/// Calls between checks of the time budget
+const BUDGET_CHECK_INTERVAL: usize = 32;
+
+/// A predicate deciding which entries a search may return, set with
+/// [`SearchParamsBuilder::filter`](crate::SearchParamsBuilder::filter).
+///
+/// It is a pre-filter: it runs as candidates are collected, before
+/// anything is scored, so the `k` results are the nearest among the
+/// candidates it admits and no oversampling is needed. Like `exclude`,
+/// it only narrows the candidates the probe reaches; a selective filter
+/// on a shallow probe can leave fewer than `k`. It is called once per
+/// candidate per search, possibly from several threads at once, so it
+/// must be fast and must not depend on call order.
+///
+/// A panic in a Rust predicate unwinds out of the search. Filtered
+/// searches skip the query cache, and a
+/// [`ResultEnvelope`](crate::ResultEnvelope) fingerprint does not record
+/// the filter.
+#[derive(Clone)]
+pub struct CandidateFilter {
+    predicate: Arc<dyn Fn(u32) -> bool + Send + Sync>,
+    budget: Option<Duration>,
+}
+
+/// `user_data` handed back to a native filter
+struct UserData(*mut c_void);
+
+// SAFETY: `CandidateFilter::from_native` requires callers to guarantee
+// that the callback may be invoked with `user_data` from any thread,
+// concurrently
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl CandidateFilter {
+    /// Admit the entries for which `predicate` returns true
+    pub fn new(predicate: impl Fn(u32) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            predicate: Arc::new(predicate),
+            budget: None,
+        }
+    }
+
+    /// Admit the entries for which `callback(id, user_data)` returns true,
+    /// for policy code in a native library.
+    ///
+    /// # Safety
+    ///
+    /// `callback` must be safe to call with `user_data` from any thread,
+    /// concurrently, for as long as this filter or a clone of it exists;
+    /// `user_data` must stay valid that long, and whatever it points to
+    /// must not be mutated without synchronization. The callback must not
+    /// unwind: a foreign exception or Rust panic crossing the C ABI aborts
+    /// the process. It should be pure; a callback that blocks can only be
+    /// caught by the budget once it returns.
+    pub unsafe fn from_native(callback: NativeFilterFn, user_data: *mut c_void) -> Self {
+        let user_data = UserData(user_data);
+        Self::new(move |id| {
+            let user_data = &user_data;
+            callback(id, user_data.0)
+        })
+    }
+
+    /// Fail a search with [`KhadyotaError::FilterBudgetExceeded`] once the
+    /// filter has spent `budget` on one list of candidates. Checked every
+    /// few calls, so one slow call can overrun it.
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn budget(&self) -> Option<Duration> {
+        self.budget
+    }
+
+    /// Admit the entries both filters admit, `other` asked only of those
+    /// this one does, under the tighter of their budgets
+    pub fn and(self, other: CandidateFilter) -> Self {
+        let budget = tighter(self.budget, other.budget);
+        Self {
+            budget,
+            ..Self::new(move |id| self.admits(id) && other.admits(id))
+        }
+    }
+
+    /// Admit the entries either filter admits, `other` asked only of
+    /// those this one does not, under the tighter of their budgets
+    pub fn or(self, other: CandidateFilter) -> Self {
+        let budget = tighter(self.budget, other.budget);
+        Self {
+            budget,
+            ..Self::new(move |id| self.admits(id) || other.admits(id))
+        }
+    }
+
+    /// Whether the filter admits `id`, without any budget
+    pub fn admits(&self, id: u32) -> bool {
+        (self.predicate)(id)
+    }
+
+    /// Start a pass over one list of candidates
+    pub(crate) fn pass(&self) -> FilterPass<'_> {
+        FilterPass {
+            filter: Some(self),
+            start: self.budget.map(|_| Instant::now()),
+            calls: 0,
+            exceeded: false,
+        }
+    }
+}
+
+/// The smaller of two budgets, either of which may be unset
+fn tighter(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl fmt::Debug for CandidateFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CandidateFilter").field("budget", &self.budget).finish_non_exhaustive()
+    }
+}
+
+/// One pass of a filter over a list of candidates, timing it against the
+/// budget. Admits everything without a filter.
+pub(crate) struct FilterPass<'a> {
+    filter: Option<&'a CandidateFilter>,
+    start: Option<Instant>,
+    calls: usize,
+    exceeded: bool,
+}
+
+impl FilterPass<'_> {
+    /// A pass that admits every candidate
+    pub(crate) fn open() -> Self {
+        FilterPass {
+            filter: None,
+            start: None,
+            calls: 0,
+            exceeded: false,
+        }
+    }
+
+    /// Whether `id` is admitted. Once the budget is spent nothing is, and
+    /// the filter is no longer called.
+    pub(crate) fn admits(&mut self, id: u32) -> bool {
+        let Some(filter) = self.filter else {
+            return true;
+        };
+        if self.exceeded {
+            return false;
+        }
+        if let (Some(start), Some(budget)) = (self.start, filter.budget)
+            && self.calls > 0
+            && self.calls.is_multiple_of(BUDGET_CHECK_INTERVAL)
+            && start.elapsed() > budget
+        {
+            self.exceeded = true;
+            return false;
+        }
+        self.calls += 1;
+        filter.admits(id)
+    }
+
+    /// Fail if the budget ran out during the pass
+    pub(crate) fn finish(self) -> Result<()> {
+        match (self.start, self.filter.and_then(|f| f.budget)) {
+            (Some(start), Some(budget)) if self.exceeded || start.elapsed() > budget => {
+                Err(KhadyotaError::FilterBudgetExceeded {
+                    budget,
+                    calls: self.calls,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_stops_calling_once_over_budget() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let slow = CandidateFilter::new(move |_| {
+            counted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            std::thread::sleep(Duration::from_micros(200));
+            true
+        })
+        .with_budget(Duration::from_millis(2));
+
+        let mut pass = slow.pass();
+        let admitted = (0..1_000).filter(|&id| pass.admits(id)).count();
+        assert!(matches!(pass.finish(), Err(KhadyotaError::FilterBudgetExceeded { .. })));
+        assert!(admitted < 1_000);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), admitted);
+
+        let mut open = FilterPass::open();
+        assert!((0..100).all(|id| open.admits(id)));
+        assert!(open.finish().is_ok());
+    }
+}
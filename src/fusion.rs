@@ -0,0 +1,297 @@
+use crate::error::{KhadyotaError, Result};
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Conventional RRF smoothing constant
+pub const DEFAULT_RRF_K: f32 = 60.0;
+
+/// How ranked lists are combined
+#[derive(Debug, Clone)]
+pub enum FusionStrategy {
+    /// Sum of `1 / (rrf_k + rank)` over the lists containing a result
+    ReciprocalRank { rrf_k: f32 },
+
+    /// Sum of `weight * similarity`, where each list's distances are
+    /// min-max normalized to similarities in `[0, 1]` (best = 1)
+    WeightedScore { weights: Vec<f32> },
+}
+
+impl Default for FusionStrategy {
+    fn default() -> Self {
+        Self::ReciprocalRank { rrf_k: DEFAULT_RRF_K }
+    }
+}
+
+/// One entry of a fused ranking
+#[derive(Debug, Clone)]
+pub struct FusedResult<K = u32> {
+    /// Identity the lists were matched on
+    pub key: K,
+
+    /// Fused score; higher is better
+    pub score: f32,
+
+    /// The result as it appeared in the first list containing it
+    pub result: SearchResult,
+
+    /// 1-based rank in each input list, `None` where absent
+    pub ranks: Vec<Option<usize>>,
+}
+
+/// Fuse ranked lists matched by result id
+pub fn reciprocal_rank_fusion(
+    result_sets: &[&[SearchResult]],
+    k: usize,
+    rrf_k: f32,
+) -> Vec<FusedResult> {
+    reciprocal_rank_fusion_by(result_sets, k, rrf_k, |r| r.id)
+}
+
+/// Fuse ranked lists matched by `key`, e.g. a metadata field shared by
+/// collections whose ids differ.
+///
+/// Results with equal distances within a list share a rank (1, 2, 2, 4),
+/// and fused ties are broken by best rank, then by first appearance, so
+/// the output is deterministic.
+pub fn reciprocal_rank_fusion_by<K, F>(
+    result_sets: &[&[SearchResult]],
+    k: usize,
+    rrf_k: f32,
+    key: F,
+) -> Vec<FusedResult<K>>
+where
+    K: Eq + Hash + Clone,
+    F: Fn(&SearchResult) -> K,
+{
+    fuse(result_sets, k, key, |_, rank, _| 1.0 / (rrf_k + rank as f32))
+}
+
+/// Fuse ranked lists by weighted normalized similarity, matched by id
+pub fn weighted_score_fusion(
+    result_sets: &[&[SearchResult]],
+    weights: &[f32],
+    k: usize,
+) -> Result<Vec<FusedResult>> {
+    weighted_score_fusion_by(result_sets, weights, k, |r| r.id)
+}
+
+/// Fuse ranked lists by weighted normalized similarity, matched by `key`
+pub fn weighted_score_fusion_by<K, F>(
+    result_sets: &[&[SearchResult]],
+    weights: &[f32],
+    k: usize,
+    key: F,
+) -> Result<Vec<FusedResult<K>>>
+where
+    K: Eq + Hash + Clone,
+    F: Fn(&SearchResult) -> K,
+{
+    if weights.len() != result_sets.len() {
+        return Err(KhadyotaError::InvalidConfig(format!(
+            "got {} weights for {} result sets",
+            weights.len(),
+            result_sets.len()
+        )));
+    }
+
+    let ranges: Vec<(f32, f32)> = result_sets
+        .iter()
+        .map(|set| {
+            set.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), r| {
+                (lo.min(r.distance), hi.max(r.distance))
+            })
+        })
+        .collect();
+
+    Ok(fuse(result_sets, k, key, |set, _, result| {
+        let (lo, hi) = ranges[set];
+        let similarity = if hi > lo {
+            1.0 - (result.distance - lo) / (hi - lo)
+        } else {
+            1.0
+        };
+        weights[set] * similarity
+    }))
+}
+
+/// Shared accumulation: `contribution(set, rank, result)` is added to the
+/// score of the result's key the first time the key appears in each set
+fn fuse<K, F, C>(
+    result_sets: &[&[SearchResult]],
+    k: usize,
+    key: F,
+    contribution: C,
+) -> Vec<FusedResult<K>>
+where
+    K: Eq + Hash + Clone,
+    F: Fn(&SearchResult) -> K,
+    C: Fn(usize, usize, &SearchResult) -> f32,
+{
+    let mut fused: Vec<FusedResult<K>> = Vec::new();
+    let mut positions: HashMap<K, usize> = HashMap::new();
+
+    for (set_idx, set) in result_sets.iter().enumerate() {
+        let mut rank = 0;
+
+        for (position, result) in set.iter().enumerate() {
+            if position == 0 || result.distance != set[position - 1].distance {
+                rank = position + 1;
+            }
+
+            let key = key(result);
+            let slot = *positions.entry(key.clone()).or_insert_with(|| {
+                fused.push(FusedResult {
+                    key,
+                    score: 0.0,
+                    result: result.clone(),
+                    ranks: vec![None; result_sets.len()],
+                });
+                fused.len() - 1
+            });
+
+            let entry = &mut fused[slot];
+            if entry.ranks[set_idx].is_none() {
+                entry.ranks[set_idx] = Some(rank);
+                entry.score += contribution(set_idx, rank, result);
+            }
+        }
+    }
+
+    // Stable sort keeps first-appearance order among exact ties
+    fused.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap()
+            .then_with(|| best_rank(a).cmp(&best_rank(b)))
+    });
+    fused.truncate(k);
+    fused
+}
+
+fn best_rank<K>(result: &FusedResult<K>) -> usize {
+    result.ranks.iter().flatten().min().copied().unwrap_or(usize::MAX)
+}
+
+impl VectorDB {
+    /// Search several collections and fuse their rankings by result id.
+    ///
+    /// `queries[i]` is run against `dbs[i]`, so each collection can use its
+    /// own embedding model. Each collection contributes its top `k`.
+    pub fn search_fused(
+        dbs: &[&VectorDB],
+        queries: &[&[f32]],
+        k: usize,
+        strategy: &FusionStrategy,
+    ) -> Result<Vec<FusedResult>> {
+        if dbs.len() != queries.len() {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "got {} queries for {} databases",
+                queries.len(),
+                dbs.len()
+            )));
+        }
+
+        let result_sets = dbs
+            .iter()
+            .zip(queries)
+            .map(|(db, query)| db.search(query, k))
+            .collect::<Result<Vec<_>>>()?;
+        let slices: Vec<&[SearchResult]> = result_sets.iter().map(|s| s.as_slice()).collect();
+
+        match strategy {
+            FusionStrategy::ReciprocalRank { rrf_k } => {
+                Ok(reciprocal_rank_fusion(&slices, k, *rrf_k))
+            }
+            FusionStrategy::WeightedScore { weights } => {
+                weighted_score_fusion(&slices, weights, k)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn results(entries: &[(u32, f32)]) -> Vec<SearchResult> {
+        entries
+            .iter()
+            .map(|&(id, distance)| SearchResult {
+                id,
+                distance,
+                metadata: Some(serde_json::json!({"doc": format!("d{}", id % 10)})),
+                external_id: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rrf_matches_hand_computed_scores() {
+        let a = results(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+        let b = results(&[(3, 0.5), (1, 0.6), (4, 0.7)]);
+
+        let fused = reciprocal_rank_fusion(&[&a, &b], 10, 60.0);
+        let ids: Vec<u32> = fused.iter().map(|f| f.key).collect();
+
+        // 1: 1/61 + 1/62, 3: 1/63 + 1/61, 2: 1/62, 4: 1/63
+        assert_eq!(ids, vec![1, 3, 2, 4]);
+        assert_relative_eq!(fused[0].score, 1.0 / 61.0 + 1.0 / 62.0);
+        assert_relative_eq!(fused[1].score, 1.0 / 63.0 + 1.0 / 61.0);
+        assert_relative_eq!(fused[2].score, 1.0 / 62.0);
+        assert_relative_eq!(fused[3].score, 1.0 / 63.0);
+        assert_eq!(fused[0].ranks, vec![Some(1), Some(2)]);
+        assert_eq!(fused[3].ranks, vec![None, Some(3)]);
+
+        assert_eq!(reciprocal_rank_fusion(&[&a, &b], 2, 60.0).len(), 2);
+    }
+
+    #[test]
+    fn test_rrf_tied_ranks_are_stable() {
+        // 2 and 3 tie in the first list and share rank 2; 4 gets rank 4
+        let a = results(&[(1, 0.1), (2, 0.2), (3, 0.2), (4, 0.4)]);
+        let fused = reciprocal_rank_fusion(&[&a], 10, 60.0);
+
+        assert_eq!(fused[1].ranks, vec![Some(2)]);
+        assert_eq!(fused[2].ranks, vec![Some(2)]);
+        assert_eq!(fused[3].ranks, vec![Some(4)]);
+        assert_eq!(fused[1].score, fused[2].score);
+
+        // Equal fused scores keep first-appearance order on every run
+        for _ in 0..10 {
+            let ids: Vec<u32> = reciprocal_rank_fusion(&[&a], 10, 60.0).iter().map(|f| f.key).collect();
+            assert_eq!(ids, vec![1, 2, 3, 4]);
+        }
+
+        // Mirrored lists tie on score and best rank; first appearance wins
+        let b = results(&[(5, 0.1), (6, 0.2)]);
+        let c = results(&[(6, 0.1), (5, 0.9)]);
+        let ids: Vec<u32> = reciprocal_rank_fusion(&[&b, &c], 10, 60.0).iter().map(|f| f.key).collect();
+        assert_eq!(ids, vec![5, 6]);
+    }
+
+    #[test]
+    fn test_fusion_by_metadata_key_and_weights() {
+        // Ids 11 and 1 are the same document ("d1") in different collections
+        let a = results(&[(1, 0.0), (2, 1.0), (3, 2.0)]);
+        let b = results(&[(12, 3.0), (11, 5.0)]);
+
+        let doc = |r: &SearchResult| r.metadata.as_ref().unwrap()["doc"].as_str().unwrap().to_string();
+        let fused = reciprocal_rank_fusion_by(&[&a, &b], 10, 60.0, doc);
+        assert_eq!(fused.len(), 3);
+        assert_eq!(fused[0].key, "d1");
+        assert_eq!(fused[0].result.id, 1);
+
+        // a normalizes to [1.0, 0.5, 0.0], b to [1.0, 0.0]
+        let fused = weighted_score_fusion_by(&[&a, &b], &[1.0, 2.0], 10, doc).unwrap();
+        let scores: Vec<(String, f32)> = fused.iter().map(|f| (f.key.clone(), f.score)).collect();
+        assert_eq!(
+            scores,
+            vec![("d2".to_string(), 2.5), ("d1".to_string(), 1.0), ("d3".to_string(), 0.0)]
+        );
+
+        assert!(weighted_score_fusion(&[&a, &b], &[1.0], 10).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::latency::Histogram;
+use crate::search_params::SearchParams;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// `count` vectors with components drawn uniformly from `[-1, 1)`. The same
+/// seed always gives the same vectors.
+pub fn random_vectors(count: usize, dimensions: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| (0..dimensions).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect()
+}
+
+/// `count` vectors scattered around `clusters` random centres, with
+/// Gaussian noise of standard deviation `spread` per component. Vector `i`
+/// belongs to centre `i % clusters`.
+///
+/// Real embeddings are clustered, so this is the better stand-in when
+/// measuring recall: uniform data has no structure for IVF to exploit.
+pub fn clustered_vectors(count: usize, dimensions: usize, clusters: usize, spread: f32, seed: u64) -> Vec<Vec<f32>> {
+    let centres = random_vectors(clusters.max(1), dimensions, seed);
+    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(1));
+    let noise = Normal::new(0.0, spread.max(0.0)).expect("spread is finite");
+    (0..count)
+        .map(|i| {
+            centres[i % centres.len()]
+                .iter()
+                .map(|&c| c + noise.sample(&mut rng))
+                .collect()
+        })
+        .collect()
+}
+
+/// Insert `vectors` into a new database and build its index
+pub fn build_db(config: Config, vectors: Vec<Vec<f32>>) -> Result<VectorDB> {
+    let mut db = VectorDB::new(config)?;
+    db.insert_batch(vectors.into_iter().map(|v| (v, None)))?;
+    db.build_index()?;
+    Ok(db)
+}
+
+/// A query workload: `warmup` unmeasured searches, then `measured` timed
+/// ones, cycling through the queries given to [`Workload::run`]
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub k: usize,
+    pub warmup: usize,
+    pub measured: usize,
+    /// Repeat the measured phase this many times and report the trial with
+    /// the median p50. One noisy trial, from a context switch or a clock
+    /// change, then does not decide the result.
+    pub trials: usize,
+    pub params: SearchParams,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self {
+            k: 10,
+            warmup: 10,
+            measured: 1_000,
+            trials: 1,
+            params: SearchParams::default(),
+        }
+    }
+}
+
+impl Workload {
+    /// Run the workload one query at a time through
+    /// [`VectorDB::search_with_params`]
+    pub fn run(&self, db: &VectorDB, queries: &[Vec<f32>]) -> Result<WorkloadReport> {
+        for query in queries.iter().cycle().take(self.warmup) {
+            db.search_with_params(query, self.k, &self.params)?;
+        }
+
+        self.median_trial(|| {
+            let mut latency = Histogram::new();
+            let start = Instant::now();
+            for query in queries.iter().cycle().skip(self.warmup).take(self.measured) {
+                let query_start = Instant::now();
+                db.search_with_params(query, self.k, &self.params)?;
+                latency.record(query_start.elapsed());
+            }
+            Ok(WorkloadReport {
+                latency,
+                elapsed: start.elapsed(),
+            })
+        })
+    }
+
+    /// Run the workload as one [`VectorDB::batch_search`] call, after a
+    /// warmup batch. `params` other than `k` do not apply to batches.
+    pub fn run_batch(&self, db: &VectorDB, queries: &[Vec<f32>]) -> Result<WorkloadReport> {
+        let batch = |n: usize| queries.iter().cycle().take(n).cloned().collect::<Vec<_>>();
+        if self.warmup > 0 {
+            db.batch_search(&batch(self.warmup), self.k)?;
+        }
+
+        let measured = batch(self.measured);
+        self.median_trial(|| {
+            let mut latency = Histogram::new();
+            let start = Instant::now();
+            let timed = db.batch_search_timed(&measured, self.k)?;
+            let elapsed = start.elapsed();
+            for (_, duration) in timed {
+                latency.record(duration);
+            }
+            Ok(WorkloadReport { latency, elapsed })
+        })
+    }
+
+    fn median_trial(&self, mut trial: impl FnMut() -> Result<WorkloadReport>) -> Result<WorkloadReport> {
+        let mut reports = (0..self.trials.max(1)).map(|_| trial()).collect::<Result<Vec<_>>>()?;
+        reports.sort_by_key(|report| report.latency.p50());
+        Ok(reports.swap_remove(reports.len() / 2))
+    }
+}
+
+/// Per-query latencies and wall-clock time of a [`Workload`] run
+#[derive(Debug, Clone)]
+pub struct WorkloadReport {
+    pub latency: Histogram,
+    /// Time for all measured queries, which for a batch is less than the
+    /// sum of their latencies
+    pub elapsed: Duration,
+}
+
+impl WorkloadReport {
+    /// Measured queries per second of wall-clock time
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.latency.count() as f64 / secs
+    }
+}
+
+impl fmt::Display for WorkloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} queries in {:?}: p50 {:?}, p95 {:?}, p99 {:?}, {:.0} QPS",
+            self.latency.count(),
+            self.elapsed,
+            self.latency.p50(),
+            self.latency.p95(),
+            self.latency.p99(),
+            self.throughput()
+        )
+    }
+}
+
+/// The true `k` nearest live entries to `query`, by brute force over the
+/// original vectors with the collection's metric.
+///
+/// Unlike a search this leaves latency, access and cache statistics alone.
+pub fn exact_neighbors(db: &VectorDB, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+    let query = db.prepare_query(query)?;
+    let params = SearchParams::default();
+    let ids = (0..db.next_id).filter(|id| !db.deleted.contains(id)).collect();
+    let scored = db.score_exact(&query, ids, &params)?;
+    Ok(db.top_results(scored, k, &params))
+}
+
+/// Fraction of `exact` that `found` also contains
+pub fn recall(found: &[SearchResult], exact: &[SearchResult]) -> f64 {
+    if exact.is_empty() {
+        return 1.0;
+    }
+    let exact: HashSet<u32> = exact.iter().map(|r| r.id).collect();
+    let hits = found.iter().filter(|r| exact.contains(&r.id)).count();
+    hits as f64 / exact.len() as f64
+}
+
+/// Mean recall@k of [`VectorDB::search`] over `queries`, against
+/// [`exact_neighbors`]
+pub fn recall_at_k(db: &VectorDB, queries: &[Vec<f32>], k: usize) -> Result<f64> {
+    if queries.is_empty() {
+        return Ok(1.0);
+    }
+    let mut total = 0.0;
+    for query in queries {
+        total += recall(&db.search(query, k)?, &exact_neighbors(db, query, k)?);
+    }
+    Ok(total / queries.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QuantizerKind;
+
+    fn config() -> Config {
+        Config {
+            dimensions: 16,
+            quantizer: QuantizerKind::None,
+            num_clusters: 8,
+            num_probe: 8,
+            seed: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_generators_are_seeded() {
+        assert_eq!(random_vectors(10, 4, 7), random_vectors(10, 4, 7));
+        assert_ne!(random_vectors(10, 4, 7), random_vectors(10, 4, 8));
+        assert!(random_vectors(50, 4, 7).iter().flatten().all(|x| (-1.0..1.0).contains(x)));
+
+        let clustered = clustered_vectors(40, 4, 4, 0.01, 3);
+        assert_eq!(clustered, clustered_vectors(40, 4, 4, 0.01, 3));
+        assert_eq!(clustered.len(), 40);
+        // Members of one cluster sit close together
+        let gap: f32 = clustered[0].iter().zip(&clustered[4]).map(|(a, b)| (a - b).abs()).sum();
+        assert!(gap < 0.5, "cluster members {} apart", gap);
+    }
+
+    #[test]
+    fn test_exhaustive_search_has_full_recall() {
+        let db = build_db(config(), clustered_vectors(400, 16, 8, 0.1, 1)).unwrap();
+        let queries = random_vectors(10, 16, 2);
+        assert_eq!(recall_at_k(&db, &queries, 5).unwrap(), 1.0);
+
+        let exact = exact_neighbors(&db, &queries[0], 5).unwrap();
+        assert_eq!(exact.len(), 5);
+        assert!(exact.windows(2).all(|w| w[0].distance <= w[1].distance));
+    }
+
+    #[test]
+    fn test_exact_neighbors_skip_deleted_entries() {
+        let mut db = build_db(config(), random_vectors(100, 16, 1)).unwrap();
+        let query = random_vectors(1, 16, 2).remove(0);
+        let nearest = exact_neighbors(&db, &query, 1).unwrap()[0].id;
+
+        db.delete(nearest).unwrap();
+        let exact = exact_neighbors(&db, &query, 3).unwrap();
+        assert!(exact.iter().all(|r| r.id != nearest));
+    }
+
+    #[test]
+    fn test_recall_counts_overlap() {
+        let result = |id| SearchResult {
+            id,
+            distance: 0.0,
+            metadata: None,
+            external_id: None,
+        };
+        let exact = [result(1), result(2), result(3), result(4)];
+        assert_eq!(recall(&[result(2), result(4), result(9)], &exact), 0.5);
+        assert_eq!(recall(&[], &[]), 1.0);
+    }
+
+    #[test]
+    fn test_workload_measures_only_measured_queries() {
+        let mut db = build_db(config(), random_vectors(200, 16, 1)).unwrap();
+        db.set_access_tracking(Some(Default::default()));
+        let workload = Workload {
+            warmup: 5,
+            measured: 20,
+            ..Default::default()
+        };
+        let queries = random_vectors(3, 16, 2);
+
+        let report = workload.run(&db, &queries).unwrap();
+        assert_eq!(report.latency.count(), 20);
+        assert!(report.throughput() > 0.0);
+        assert_eq!(db.access_stats().unwrap().queries, 25);
+        assert!(report.to_string().starts_with("20 queries in "));
+
+        let report = workload.run_batch(&db, &queries).unwrap();
+        assert_eq!(report.latency.count(), 20);
+        assert_eq!(db.access_stats().unwrap().queries, 50);
+    }
+
+    #[test]
+    fn test_trials_report_one_median_run() {
+        let mut db = build_db(config(), random_vectors(200, 16, 1)).unwrap();
+        db.set_access_tracking(Some(Default::default()));
+        let workload = Workload {
+            warmup: 5,
+            measured: 20,
+            trials: 3,
+            ..Default::default()
+        };
+        let queries = random_vectors(3, 16, 2);
+
+        let report = workload.run(&db, &queries).unwrap();
+        assert_eq!(report.latency.count(), 20);
+        assert_eq!(db.access_stats().unwrap().queries, 5 + 3 * 20);
+    }
+}
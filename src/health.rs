@@ -0,0 +1,292 @@
+use crate::config::{IndexKind, QuantizerKind};
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Serving verdict for the whole database or a single component.
+///
+/// Variants are ordered from best to worst so the overall verdict is the
+/// maximum over all components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum HealthStatus {
+    Ready,
+    Degraded,
+    Unready,
+}
+
+/// Status of one component checked by [`VectorDB::health`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    pub detail: String,
+}
+
+/// Result of a readiness check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Health {
+    /// Worst status across all components
+    pub status: HealthStatus,
+
+    pub components: Vec<ComponentHealth>,
+
+    /// Unix timestamp (seconds) of the last index build in this process
+    pub last_build_unix_secs: Option<u64>,
+}
+
+impl Health {
+    /// Look up a component by name
+    pub fn component(&self, name: &str) -> Option<&ComponentHealth> {
+        self.components.iter().find(|c| c.name == name)
+    }
+
+    /// True when every component is ready
+    pub fn is_ready(&self) -> bool {
+        self.status == HealthStatus::Ready
+    }
+
+    /// Components that are not ready
+    pub fn failing(&self) -> impl Iterator<Item = &ComponentHealth> {
+        self.components.iter().filter(|c| c.status != HealthStatus::Ready)
+    }
+}
+
+pub(crate) fn component(name: &str, status: HealthStatus, detail: impl Into<String>) -> ComponentHealth {
+    ComponentHealth {
+        name: name.to_string(),
+        status,
+        detail: detail.into(),
+    }
+}
+
+impl VectorDB {
+    /// Check whether the database is able to serve searches.
+    ///
+    /// Every check is bounded by the number of IVF clusters, never by the
+    /// number of stored vectors, so this is cheap enough to back a service
+    /// readiness probe.
+    pub fn health(&self) -> Health {
+        let components = vec![
+            self.index_health(),
+            self.storage_health(),
+            simd_health(),
+        ];
+
+        let status = components
+            .iter()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(HealthStatus::Ready);
+
+        let last_build_unix_secs = self
+            .last_build
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        Health {
+            status,
+            components,
+            last_build_unix_secs,
+        }
+    }
+
+    fn index_health(&self) -> ComponentHealth {
+        if self.vectors.is_empty() {
+            return component("index", HealthStatus::Unready, "database is empty");
+        }
+
+        let indexed = match (&self.ivf_index, &self.flat_index) {
+            (Some(ivf), _) => ivf.stats().total_vectors,
+            (None, Some(flat)) => flat.len(),
+            (None, None) => 0,
+        };
+
+        if !self.index_built {
+            // A lagging index still serves searches, scanning the rows it
+            // has yet to cover
+            let status = if self.is_searchable() {
+                HealthStatus::Degraded
+            } else {
+                HealthStatus::Unready
+            };
+            let detail = if self.has_index() {
+                format!(
+                    "index is stale: {} vectors inserted since the last build",
+                    self.len().saturating_sub(indexed)
+                )
+            } else {
+                "index not built".to_string()
+            };
+            return component("index", status, detail);
+        }
+
+        let age = self
+            .last_build
+            .and_then(|t| SystemTime::now().duration_since(t).ok())
+            .map(|d| format!(", built {}s ago", d.as_secs()))
+            .unwrap_or_default();
+
+        component(
+            "index",
+            HealthStatus::Ready,
+            format!("{} vectors indexed{}", indexed, age),
+        )
+    }
+
+    fn storage_health(&self) -> ComponentHealth {
+        if !self.index_built {
+            return component(
+                "storage",
+                HealthStatus::Ready,
+                format!("{} raw vectors in memory", self.vectors.len()),
+            );
+        }
+
+        if let Some(ivf) = &self.ivf_index {
+            let listed = ivf.stats().total_vectors;
+            if listed != self.len() {
+                return component(
+                    "storage",
+                    HealthStatus::Unready,
+                    format!(
+                        "inverted lists cover {} of {} vectors",
+                        listed,
+                        self.len()
+                    ),
+                );
+            }
+        }
+
+        // A flat index scores the rows themselves and trains no codes,
+        // whatever the quantizer says
+        match (&self.quantized, self.config.quantizer) {
+            (None, kind) if kind != QuantizerKind::None && self.config.index == IndexKind::IVF => component(
+                "storage",
+                HealthStatus::Unready,
+                format!("{:?} is enabled but quantized codes are missing", kind),
+            ),
+            (Some(quantized), _) if quantized.len() != self.vectors.len() => component(
+                "storage",
+                HealthStatus::Unready,
+                format!(
+                    "quantized codes cover {} of {} vectors",
+                    quantized.len(),
+                    self.vectors.len()
+                ),
+            ),
+            _ => match self.cold_cache_stats() {
+                Some(stats) => component(
+                    "storage",
+                    HealthStatus::Ready,
+                    format!(
+                        "{} vectors on disk, cache hit rate {:.1}%",
+                        self.vectors.len(),
+                        stats.hit_rate() * 100.0
+                    ),
+                ),
+                None => component(
+                    "storage",
+                    HealthStatus::Ready,
+                    format!("{} vectors in memory", self.vectors.len()),
+                ),
+            },
+        }
+    }
+}
+
+fn simd_health() -> ComponentHealth {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if crate::distance::simd::avx2_available() {
+            component("simd", HealthStatus::Ready, "AVX2 kernels enabled")
+        } else {
+            component("simd", HealthStatus::Ready, "AVX2/FMA unavailable, using scalar kernels")
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        component("simd", HealthStatus::Ready, "using scalar kernels")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn small_db() -> VectorDB {
+        let config = Config {
+            dimensions: 8,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 2,
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..100 {
+            let vector: Vec<f32> = (0..8).map(|j| ((i * 8 + j) as f32).sin()).collect();
+            db.insert(vector, None).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_health_verdicts() {
+        let empty = VectorDB::new(Config { dimensions: 8, ..Default::default() }).unwrap();
+        let health = empty.health();
+        assert_eq!(health.status, HealthStatus::Unready);
+        assert_eq!(health.component("index").unwrap().status, HealthStatus::Unready);
+
+        let mut db = small_db();
+        assert_eq!(db.health().status, HealthStatus::Unready);
+
+        db.build_index().unwrap();
+        let health = db.health();
+        assert!(health.is_ready(), "{:?}", health);
+        assert!(health.last_build_unix_secs.is_some());
+        assert_eq!(health.failing().count(), 0);
+
+        // Inserting after a build leaves a stale index, which serves
+        // searches by scanning the new rows until it catches up
+        db.insert(vec![0.5; 8], None).unwrap();
+        let health = db.health();
+        assert_eq!(health.status, HealthStatus::Degraded);
+        let index = health.component("index").unwrap();
+        assert_eq!(index.status, HealthStatus::Degraded);
+        assert!(index.detail.contains("stale"));
+        assert!(index.detail.contains("1 vectors"));
+        assert_eq!(health.component("storage").unwrap().status, HealthStatus::Ready);
+    }
+
+    #[test]
+    fn test_health_reports_missing_codes() {
+        let mut db = small_db();
+        db.build_index().unwrap();
+
+        // Simulate a hand-assembled database that lost its PQ codes
+        db.config.quantizer = QuantizerKind::PQ;
+        let health = db.health();
+
+        assert_eq!(health.status, HealthStatus::Unready);
+        let failing: Vec<_> = health.failing().map(|c| c.name.as_str()).collect();
+        assert_eq!(failing, vec!["storage"]);
+
+        let json = serde_json::to_value(&health).unwrap();
+        assert_eq!(json["status"], "Unready");
+    }
+
+    #[test]
+    fn test_built_flat_index_is_ready() {
+        let mut db = small_db();
+        db.config.index = IndexKind::Flat;
+        db.config.quantizer = QuantizerKind::PQ;
+        db.build_index().unwrap();
+        assert!(db.quantized.is_none());
+
+        let health = db.health();
+        assert!(health.is_ready(), "{:?}", health);
+        assert_eq!(health.component("storage").unwrap().status, HealthStatus::Ready);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::config::DistanceMetric;
+use crate::distance::{distance_key, key_to_distance};
+use crate::parallel::chunk_len;
+use crate::select::smallest_k;
+use rayon::prelude::*;
+
+/// Rows from which a [`FlatIndex`] scan is split across threads; below
+/// it, handing out tasks costs more than the scan
+pub const FLAT_PARALLEL_ROWS: usize = 8_192;
+
+/// Exact index: every row copied into one buffer, row-major with a
+/// stride of `dimensions`, and scored in full by each search
+#[derive(Debug, Clone, Default)]
+pub struct FlatIndex {
+    dimensions: usize,
+    data: Vec<f32>,
+}
+
+impl FlatIndex {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions, data: Vec::new() }
+    }
+
+    /// Copy `rows`, each of `dimensions` components, in id order
+    pub fn from_rows<R: AsRef<[f32]>>(rows: impl IntoIterator<Item = R>, dimensions: usize) -> Self {
+        let rows = rows.into_iter();
+        let mut index = Self::new(dimensions);
+        index.data.reserve(rows.size_hint().0 * dimensions);
+        for row in rows {
+            index.data.extend_from_slice(row.as_ref());
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() / self.dimensions.max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn row(&self, id: u32) -> &[f32] {
+        let start = id as usize * self.dimensions;
+        &self.data[start..start + self.dimensions]
+    }
+
+    /// Rows in id order
+    pub fn rows(&self) -> impl Iterator<Item = &[f32]> + '_ {
+        self.data.chunks_exact(self.dimensions.max(1))
+    }
+
+    /// Overwrite row `id`, or append it when `id` is the next row
+    pub fn put(&mut self, id: u32, row: &[f32]) {
+        let start = id as usize * self.dimensions;
+        if start == self.data.len() {
+            self.data.extend_from_slice(row);
+        } else {
+            self.data[start..start + self.dimensions].copy_from_slice(row);
+        }
+    }
+
+    /// Keep only the rows in `kept`, ascending, renumbered from 0. Ids
+    /// past the end, of rows not copied yet, are skipped.
+    pub(crate) fn retain_rows(&mut self, kept: &[u32]) {
+        let copied = kept.partition_point(|&id| (id as usize) < self.len());
+        *self = Self::from_rows(kept[..copied].iter().map(|&id| self.row(id)), self.dimensions);
+    }
+
+    /// Bytes held by the copied rows
+    pub fn memory_usage(&self) -> usize {
+        self.data.len() * std::mem::size_of::<f32>()
+    }
+
+    /// The `k` rows nearest `query` by `metric`, nearest first, with ties
+    /// in id order
+    pub fn search(&self, query: &[f32], k: usize, metric: DistanceMetric) -> Vec<(u32, f32)> {
+        let scored = self.scan_keys(query, &|a, b| distance_key(a, b, metric), &|_| true);
+        smallest_k(scored, k)
+            .into_iter()
+            .map(|(id, key)| (id, key_to_distance(metric, key)))
+            .collect()
+    }
+
+    /// `key` of `query` against every row `keep` admits, in id order. A
+    /// query shorter than the rows is scored against the same prefix of
+    /// each. Large indexes are scanned in parallel, in tasks of
+    /// contiguous rows.
+    pub(crate) fn scan_keys(
+        &self,
+        query: &[f32],
+        key: &(impl Fn(&[f32], &[f32]) -> f32 + Sync),
+        keep: &(impl Fn(u32) -> bool + Sync),
+    ) -> Vec<(u32, f32)> {
+        let score = |(id, row): (usize, &[f32])| {
+            let id = id as u32;
+            keep(id).then(|| (id, key(query, &row[..query.len()])))
+        };
+        if self.len() < FLAT_PARALLEL_ROWS {
+            return self.rows().enumerate().filter_map(score).collect();
+        }
+        self.data
+            .par_chunks_exact(self.dimensions)
+            .with_min_len(chunk_len(self.dimensions))
+            .enumerate()
+            .filter_map(score)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::compute_distance;
+    use crate::distance::scalar::{cosine_distance_scalar, euclidean_distance_scalar};
+    use crate::harness::random_vectors;
+    use crate::select::cmp_distance;
+
+    #[test]
+    fn test_search_matches_the_scalar_reference() {
+        let rows = random_vectors(FLAT_PARALLEL_ROWS + 500, 24, 1);
+        let index = FlatIndex::from_rows(&rows, 24);
+        assert_eq!((index.len(), index.row(9)), (rows.len(), rows[9].as_slice()));
+
+        for query in random_vectors(5, 24, 2) {
+            for (metric, scalar) in [
+                (DistanceMetric::Euclidean, euclidean_distance_scalar as fn(&[f32], &[f32]) -> f32),
+                (DistanceMetric::Cosine, cosine_distance_scalar),
+            ] {
+                let mut reference: Vec<(u32, f32)> =
+                    rows.iter().enumerate().map(|(id, row)| (id as u32, scalar(&query, row))).collect();
+                reference.sort_by(|a, b| cmp_distance(a.1, b.1));
+                let found = index.search(&query, 20, metric);
+                let ids = |scored: &[(u32, f32)]| scored.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+                assert_eq!(ids(&found), ids(&reference[..20]));
+                for &(id, distance) in &found {
+                    assert_eq!(distance, compute_distance(&query, &rows[id as usize], metric));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_put_and_retain_keep_rows_contiguous() {
+        let mut index = FlatIndex::from_rows([[1.0, 2.0], [3.0, 4.0]], 2);
+        index.put(2, &[5.0, 6.0]);
+        index.put(0, &[7.0, 8.0]);
+        index.retain_rows(&[0, 2]);
+        assert_eq!(index.rows().collect::<Vec<_>>(), [[7.0, 8.0], [5.0, 6.0]]);
+        assert_eq!(index.memory_usage(), 16);
+
+        // Only admitted rows are scored, and a prefix query scores prefixes
+        let scored = index.scan_keys(&[7.0], &|a, b| (a[0] - b[0]).abs(), &|id| id == 1);
+        assert_eq!(scored, [(1, 2.0)]);
+    }
+}
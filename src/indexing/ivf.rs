@@ -1,6 +1,14 @@
-use crate::distance::metrics::euclidean_distance;
-use crate::quantization::kmeans::kmeans;
+use crate::config::DistanceMetric;
+use crate::distance::{cosine_distance, dot_product, euclidean_distance_squared, manhattan_distance, normalized};
+use crate::progress::{BuildEvent, ProgressCallback, Silent};
+use crate::quantization::kmeans::{kmeans_with_params, kmeans_with_progress, KMeansParams};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// Entry of [`IVFIndex::cluster_by_id`] for ids no list holds
+const UNLISTED: u32 = u32::MAX;
 
 /// Inverted File Index for fast approximate search
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,53 +24,280 @@ pub struct IVFIndex {
     
     /// Dimensionality
     dimensions: usize,
+    
+    /// Metric clusters are formed and probed by; `None` in indexes saved
+    /// before it was recorded, which are all Euclidean
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metric: Option<DistanceMetric>,
+    
+    /// Per cluster, where the next reassignment check starts (runtime only)
+    #[serde(skip)]
+    check_cursors: Vec<usize>,
+    
+    /// Inverted list sizes when the index was built, which compensated
+    /// probes aim to match in live entries; empty in indexes saved before
+    /// they were recorded, which are taken as still at build size
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    built_sizes: Vec<usize>,
+    
+    /// Cluster listing each id, made from the lists on first lookup and
+    /// kept up to date by every edit after (runtime only)
+    #[serde(skip)]
+    cluster_by_id: OnceLock<Vec<u32>>,
+    
+    /// Clusters each vector is listed in at build time, its nearest
+    /// first; see [`IVFIndex::set_assign_replicas`]
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    assign_replicas: usize,
+    
+    /// Per cluster, ids listed there as well as in their nearest cluster's
+    /// inverted list; empty unless `assign_replicas` is over 1. Only
+    /// candidates are drawn from these: lookups, sizes and rebalancing go
+    /// by the inverted lists, which hold each id once.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    replica_lists: Vec<Vec<u32>>,
+    
+    /// K-means settings for the next build (runtime only); see
+    /// [`IVFIndex::set_kmeans_params`]
+    #[serde(skip)]
+    kmeans: KMeansParams,
+}
+
+fn one() -> usize {
+    1
+}
+
+fn is_one(n: &usize) -> bool {
+    *n == 1
 }
 
 impl IVFIndex {
     /// Create a new empty IVF index
     pub fn new(dimensions: usize, num_clusters: usize, num_probe: usize) -> Self {
+        Self::with_metric(dimensions, num_clusters, num_probe, DistanceMetric::Euclidean)
+    }
+    
+    /// Create an empty index that clusters and probes by `metric`.
+    ///
+    /// Cosine indexes run k-means on unit-length vectors (spherical
+    /// k-means). Dot-product indexes cluster by L2 and probe the
+    /// centroids of largest inner product first. Manhattan and Hamming indexes cluster by
+    /// L2 and probe by L1 distance to the centroids; for 0/1 vectors that
+    /// is the expected Hamming distance to a cluster's members.
+    pub fn with_metric(dimensions: usize, num_clusters: usize, num_probe: usize, metric: DistanceMetric) -> Self {
         Self {
             centroids: Vec::new(),
             inverted_lists: vec![Vec::new(); num_clusters],
             num_probe,
             dimensions,
+            metric: Some(metric),
+            check_cursors: Vec::new(),
+            built_sizes: Vec::new(),
+            cluster_by_id: OnceLock::new(),
+            assign_replicas: 1,
+            replica_lists: Vec::new(),
+            kmeans: KMeansParams::default(),
+        }
+    }
+    
+    /// An index over clusters computed elsewhere: vector `id` goes in the
+    /// inverted list of `assignments[id]`, which must be below
+    /// `centroids.len()`. No k-means is run.
+    pub fn from_assignments(
+        dimensions: usize,
+        centroids: Vec<Vec<f32>>,
+        assignments: &[u32],
+        num_probe: usize,
+        metric: DistanceMetric,
+    ) -> Self {
+        let mut inverted_lists = vec![Vec::new(); centroids.len()];
+        for (id, &cluster) in assignments.iter().enumerate() {
+            inverted_lists[cluster as usize].push(id as u32);
         }
+        
+        let built_sizes = inverted_lists.iter().map(Vec::len).collect();
+        Self {
+            centroids,
+            inverted_lists,
+            num_probe,
+            dimensions,
+            metric: Some(metric),
+            check_cursors: Vec::new(),
+            built_sizes,
+            cluster_by_id: OnceLock::new(),
+            assign_replicas: 1,
+            replica_lists: Vec::new(),
+            kmeans: KMeansParams::default(),
+        }
+    }
+    
+    /// Metric the index clusters and probes by
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric.unwrap_or(DistanceMetric::Euclidean)
+    }
+    
+    /// The metric as stored; `None` for indexes saved before it was
+    pub fn recorded_metric(&self) -> Option<DistanceMetric> {
+        self.metric
+    }
+    
+    /// List each vector in its `replicas` nearest clusters from the next
+    /// build on, rather than in its nearest alone, so a query near a
+    /// cluster boundary finds it whichever side it probes. Costs
+    /// `replicas - 1` extra ids per vector and longer candidate lists;
+    /// candidates are deduplicated before scoring. Vectors added after the
+    /// build go to their nearest cluster only. Capped at the cluster count.
+    pub fn set_assign_replicas(&mut self, replicas: usize) {
+        self.assign_replicas = replicas.max(1);
+    }
+    
+    /// Clusters each vector is listed in at build time
+    pub fn assign_replicas(&self) -> usize {
+        self.assign_replicas
+    }
+    
+    /// Learn the centroids of the next build under `kmeans`. Not saved
+    /// with the index, whose centroids are already learned; cluster
+    /// splits in [`IVFIndex::rebalance`] keep their own settings.
+    pub fn set_kmeans_params(&mut self, kmeans: KMeansParams) {
+        self.kmeans = kmeans;
     }
     
     /// Build the IVF index from training vectors
     pub fn build(&mut self, vectors: &[Vec<f32>], num_clusters: usize) {
+        self.build_seeded(vectors, num_clusters, None);
+    }
+    
+    /// Build with a fixed k-means seed
+    pub fn build_seeded(&mut self, vectors: &[Vec<f32>], num_clusters: usize, seed: Option<u64>) {
+        self.build_with_progress(vectors, num_clusters, seed, &Silent);
+    }
+    
+    /// [`IVFIndex::build_seeded`], reporting the clustering to `progress`
+    pub fn build_with_progress(
+        &mut self,
+        vectors: &[Vec<f32>],
+        num_clusters: usize,
+        seed: Option<u64>,
+        progress: &dyn ProgressCallback,
+    ) {
+        self.build_sampled(vectors, vectors, num_clusters, seed, progress);
+    }
+    
+    /// [`IVFIndex::build_with_progress`] with the centroids learned from
+    /// `training` alone, usually a sample of `vectors`; every vector is
+    /// still assigned. `num_clusters` must not exceed `training.len()`.
+    pub fn build_sampled(
+        &mut self,
+        vectors: &[Vec<f32>],
+        training: &[Vec<f32>],
+        num_clusters: usize,
+        seed: Option<u64>,
+        progress: &dyn ProgressCallback,
+    ) {
         assert!(!vectors.is_empty(), "Cannot build index from empty vectors");
-        
-        println!("Building IVF index with {} clusters...", num_clusters);
+        progress.on_event(BuildEvent::IvfBuildStarted { clusters: num_clusters });
         
         // Step 1: Learn cluster centroids using K-means
-        println!("  Running K-means clustering...");
-        let result = kmeans(vectors, num_clusters, 100, 0.001);
+        let result = if self.metric() == DistanceMetric::Cosine {
+            let unit: Vec<Vec<f32>> = training.iter().map(|v| normalized(v)).collect();
+            kmeans_with_params(&unit, num_clusters, &self.kmeans, seed, progress)
+        } else {
+            kmeans_with_params(training, num_clusters, &self.kmeans, seed, progress)
+        };
         self.centroids = result.centroids;
         
-        println!("  K-means complete. Inertia: {:.2}", result.inertia);
-        
         // Step 2: Assign each vector to its nearest cluster
-        println!("  Assigning vectors to clusters...");
         self.inverted_lists = vec![Vec::new(); num_clusters];
         
-        for (vec_id, vector) in vectors.iter().enumerate() {
-            let cluster_id = self.find_nearest_cluster(vector);
+        let nearest: Vec<usize> = vectors.par_iter().map(|v| self.find_nearest_cluster(v)).collect();
+        for (vec_id, cluster_id) in nearest.into_iter().enumerate() {
             self.inverted_lists[cluster_id].push(vec_id as u32);
         }
+        self.assign_replica_lists(vectors);
+        self.cluster_by_id = OnceLock::new();
+        self.mark_built();
+    }
+    
+    /// List every id in the inverted lists again in its next nearest
+    /// `assign_replicas - 1` clusters, from `vectors` indexed by id
+    fn assign_replica_lists(&mut self, vectors: &[Vec<f32>]) {
+        self.replica_lists = Vec::new();
+        let extra = self.assign_replicas.min(self.centroids.len()).saturating_sub(1);
+        if extra == 0 {
+            return;
+        }
         
-        // Print cluster statistics
-        let mut cluster_sizes: Vec<_> = self.inverted_lists
+        let members: Vec<(usize, u32)> = self
+            .inverted_lists
             .iter()
-            .map(|list| list.len())
+            .enumerate()
+            .flat_map(|(cluster, list)| list.iter().map(move |&id| (cluster, id)))
             .collect();
-        cluster_sizes.sort();
-        
-        println!("  Cluster size stats:");
-        println!("    Min: {}", cluster_sizes.first().unwrap_or(&0));
-        println!("    Median: {}", cluster_sizes[cluster_sizes.len() / 2]);
-        println!("    Max: {}", cluster_sizes.last().unwrap_or(&0));
-        println!("IVF index built successfully!");
+        let replicas: Vec<Vec<usize>> = members
+            .par_iter()
+            .map(|&(home, id)| {
+                let mut nearest = self.nearest_clusters(&vectors[id as usize]);
+                nearest.retain(|&c| c != home);
+                nearest.truncate(extra);
+                nearest
+            })
+            .collect();
+        self.replica_lists = vec![Vec::new(); self.inverted_lists.len()];
+        for (&(_, id), clusters) in members.iter().zip(replicas) {
+            for cluster in clusters {
+                self.replica_lists[cluster].push(id);
+            }
+        }
+        for list in &mut self.replica_lists {
+            list.sort_unstable();
+        }
+    }
+    
+    /// Every cluster, nearest to `vector` first, as assignment ranks them
+    fn nearest_clusters(&self, vector: &[f32]) -> Vec<usize> {
+        let mut distances: Vec<(usize, f32)> = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(i, centroid)| (i, self.assignment_distance(vector, centroid)))
+            .collect();
+        distances.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        distances.into_iter().map(|(i, _)| i).collect()
+    }
+    
+    /// Record the current list sizes as the build-time volume compensated
+    /// probes aim for
+    pub fn mark_built(&mut self) {
+        self.built_sizes = self.inverted_lists.iter().map(Vec::len).collect();
+    }
+    
+    /// Entries cluster `cluster_id` listed at build time
+    pub fn built_size(&self, cluster_id: usize) -> usize {
+        self.built_sizes
+            .get(cluster_id)
+            .copied()
+            .unwrap_or_else(|| self.inverted_lists[cluster_id].len())
+    }
+    
+    /// Distance from a vector to a centroid when assigning it to a cluster
+    fn assignment_distance(&self, vector: &[f32], centroid: &[f32]) -> f32 {
+        match self.metric() {
+            DistanceMetric::Cosine => cosine_distance(vector, centroid),
+            DistanceMetric::Euclidean
+            | DistanceMetric::DotProduct
+            | DistanceMetric::Manhattan
+            | DistanceMetric::Hamming => euclidean_distance_squared(vector, centroid),
+        }
+    }
+    
+    /// Distance from a query to a centroid when choosing clusters to probe
+    fn probe_distance(&self, query: &[f32], centroid: &[f32]) -> f32 {
+        match self.metric() {
+            DistanceMetric::DotProduct => -dot_product(query, centroid),
+            DistanceMetric::Manhattan | DistanceMetric::Hamming => manhattan_distance(query, centroid),
+            _ => self.assignment_distance(query, centroid),
+        }
     }
     
     /// Find the nearest cluster centroid for a vector
@@ -71,7 +306,7 @@ impl IVFIndex {
             .iter()
             .enumerate()
             .map(|(i, centroid)| {
-                let dist = euclidean_distance(vector, centroid);
+                let dist = self.assignment_distance(vector, centroid);
                 (i, dist)
             })
             .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
@@ -79,36 +314,237 @@ impl IVFIndex {
             .unwrap()
     }
     
+    /// Cluster a new vector belongs to
+    pub fn assign(&self, vector: &[f32]) -> usize {
+        self.find_nearest_cluster(vector)
+    }
+    
+    /// Append one vector to its nearest cluster, returning the cluster
+    pub fn add(&mut self, vec_id: u32, vector: &[f32]) -> usize {
+        let cluster_id = self.find_nearest_cluster(vector);
+        self.add_to_cluster(cluster_id, &[vec_id]);
+        cluster_id
+    }
+    
+    /// Append ids to a cluster's inverted list
+    pub fn add_to_cluster(&mut self, cluster_id: usize, ids: &[u32]) {
+        self.inverted_lists[cluster_id].extend_from_slice(ids);
+        self.note_cluster(ids.iter().copied(), cluster_id as u32);
+    }
+    
+    /// Move cluster `cluster_id`'s centroid towards `vector`, just added to
+    /// it, by `1 / n` of the way for `n` members, or `min_learning_rate`
+    /// if larger. Cosine indexes move towards the unit vector, as their
+    /// k-means does.
+    pub fn adapt_centroid(&mut self, cluster_id: usize, vector: &[f32], min_learning_rate: f32) {
+        let members = self.inverted_lists[cluster_id].len().max(1);
+        let rate = (1.0 / members as f32).max(min_learning_rate);
+        let target = match self.metric() {
+            DistanceMetric::Cosine => std::borrow::Cow::Owned(normalized(vector)),
+            _ => std::borrow::Cow::Borrowed(vector),
+        };
+        for (c, &x) in self.centroids[cluster_id].iter_mut().zip(target.iter()) {
+            *c += rate * (x - *c);
+        }
+    }
+    
+    /// The next `count` members of a cluster to re-check, taken in turn
+    /// so repeated calls cycle through the whole list
+    pub fn members_to_check(&mut self, cluster_id: usize, count: usize) -> Vec<u32> {
+        let list = &self.inverted_lists[cluster_id];
+        if list.is_empty() || count == 0 {
+            return Vec::new();
+        }
+        if self.check_cursors.len() != self.inverted_lists.len() {
+            self.check_cursors = vec![0; self.inverted_lists.len()];
+        }
+        let start = self.check_cursors[cluster_id] % list.len();
+        let count = count.min(list.len());
+        self.check_cursors[cluster_id] = start + count;
+        list.iter().cycle().skip(start).take(count).copied().collect()
+    }
+    
+    /// Move `id` from cluster `from` to cluster `to`
+    pub fn move_id(&mut self, id: u32, from: usize, to: usize) {
+        self.inverted_lists[from].retain(|&member| member != id);
+        self.inverted_lists[to].push(id);
+        self.note_cluster([id], to as u32);
+    }
+    
+    /// Drop `ids` from every inverted list, and every replica of them, in
+    /// one pass
+    pub fn remove_ids(&mut self, ids: &BTreeSet<u32>) {
+        for list in self.inverted_lists.iter_mut().chain(&mut self.replica_lists) {
+            list.retain(|id| !ids.contains(id));
+        }
+        self.note_cluster(ids.iter().copied(), UNLISTED);
+    }
+    
+    /// Rename every listed id by `renumber`, dropping those it maps to
+    /// `None`. Centroids and build-time sizes are left as they are.
+    pub fn renumber_ids(&mut self, renumber: impl Fn(u32) -> Option<u32>) {
+        for list in self.inverted_lists.iter_mut().chain(&mut self.replica_lists) {
+            *list = list.iter().filter_map(|&id| renumber(id)).collect();
+        }
+        self.check_cursors.clear();
+        self.cluster_by_id = OnceLock::new();
+    }
+    
+    /// Record that `ids` are now listed under `cluster`, if the lookup
+    /// has been made
+    fn note_cluster(&mut self, ids: impl IntoIterator<Item = u32>, cluster: u32) {
+        let Some(clusters) = self.cluster_by_id.get_mut() else {
+            return;
+        };
+        for id in ids {
+            let id = id as usize;
+            if id >= clusters.len() {
+                if cluster == UNLISTED {
+                    continue;
+                }
+                clusters.resize(id + 1, UNLISTED);
+            }
+            clusters[id] = cluster;
+        }
+    }
+    
     /// Find the k nearest clusters to probe for a query
     pub fn probe(&self, query: &[f32]) -> Vec<usize> {
-        let mut distances: Vec<(usize, f32)> = self.centroids
+        self.probe_n(query, self.num_probe)
+    }
+    
+    /// Find the `num_probe` nearest clusters, overriding the index default.
+    /// A shorter query, such as a Matryoshka prefix, is compared with the
+    /// same prefix of each centroid.
+    pub fn probe_n(&self, query: &[f32], num_probe: usize) -> Vec<usize> {
+        let distances: Vec<f32> = self.centroids
             .iter()
-            .enumerate()
-            .map(|(i, centroid)| {
-                let dist = euclidean_distance(query, centroid);
-                (i, dist)
-            })
+            .map(|centroid| self.probe_distance(query, &centroid[..query.len()]))
             .collect();
+        self.probe_row(&distances, num_probe)
+    }
+    
+    /// Distances from each of `queries` to every centroid, as
+    /// [`IVFIndex::probe_n`] measures them, row-major: row `q` holds query
+    /// `q`'s `num_clusters()` distances. Tiles of queries are scored
+    /// against tiles of centroids, so each centroid is read from cache
+    /// by every query of a tile, and tiles of queries run in parallel.
+    pub fn centroid_distance_matrix(&self, queries: &[&[f32]]) -> Vec<f32> {
+        const QUERY_TILE: usize = 16;
+        const CENTROID_TILE: usize = 64;
+        let num_clusters = self.centroids.len();
+        let mut matrix = vec![0.0; queries.len() * num_clusters];
+        if num_clusters == 0 {
+            return matrix;
+        }
+        matrix
+            .par_chunks_mut(QUERY_TILE * num_clusters)
+            .zip(queries.par_chunks(QUERY_TILE))
+            .for_each(|(rows, tile)| {
+                for (start, centroids) in self.centroids.chunks(CENTROID_TILE).enumerate() {
+                    let start = start * CENTROID_TILE;
+                    for (row, query) in rows.chunks_mut(num_clusters).zip(tile) {
+                        for (out, centroid) in row[start..start + centroids.len()].iter_mut().zip(centroids) {
+                            *out = self.probe_distance(query, &centroid[..query.len()]);
+                        }
+                    }
+                }
+            });
+        matrix
+    }
+    
+    /// The `num_probe` nearest clusters by one query's `distances` to
+    /// every centroid, a row of [`IVFIndex::centroid_distance_matrix`]
+    pub fn probe_row(&self, distances: &[f32], num_probe: usize) -> Vec<usize> {
+        let mut distances: Vec<(usize, f32)> = distances.iter().copied().enumerate().collect();
         
         // Sort by distance and take top num_probe
         distances.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
         
         distances
             .iter()
-            .take(self.num_probe)
+            .take(num_probe)
             .map(|(i, _)| *i)
             .collect()
     }
     
-    /// Get candidate vector IDs from probed clusters
+    /// The `num_probe` nearest clusters, followed by as many of the next
+    /// nearest as it takes for the probed lists to hold as many entries
+    /// as the first `num_probe` did at build time, up to `max_probe` in
+    /// all. After deletions this keeps the candidate volume, and so
+    /// recall, near what it was.
+    pub fn probe_compensated(&self, query: &[f32], num_probe: usize, max_probe: usize) -> Vec<usize> {
+        self.compensate(self.probe_n(query, max_probe.max(num_probe)), num_probe)
+    }
+    
+    /// [`IVFIndex::probe_compensated`] from the clusters `ranked` nearest
+    /// first, at least `num_probe` of them
+    pub fn compensate(&self, mut ranked: Vec<usize>, num_probe: usize) -> Vec<usize> {
+        let target: usize = ranked.iter().take(num_probe).map(|&c| self.built_size(c)).sum();
+        let mut listed = 0;
+        let mut taken = 0;
+        for &cluster in &ranked {
+            if taken >= num_probe && listed >= target {
+                break;
+            }
+            listed += self.inverted_lists[cluster].len();
+            taken += 1;
+        }
+        ranked.truncate(taken);
+        ranked
+    }
+    
+    /// Get candidate vector IDs from probed clusters, each once: the
+    /// inverted lists in probe order, then any replicas they did not
+    /// already hold
     pub fn get_candidates(&self, cluster_ids: &[usize]) -> Vec<u32> {
         let mut candidates = Vec::new();
-        
+        self.candidates_into(cluster_ids, &mut candidates);
+        candidates
+    }
+    
+    /// [`IVFIndex::get_candidates`] into `candidates`, replacing what it
+    /// held but keeping its allocation
+    pub fn candidates_into(&self, cluster_ids: &[usize], candidates: &mut Vec<u32>) {
+        candidates.clear();
         for &cluster_id in cluster_ids {
             candidates.extend_from_slice(&self.inverted_lists[cluster_id]);
         }
+        if self.replica_lists.is_empty() {
+            // Each id is in one inverted list at most
+            return;
+        }
         
-        candidates
+        let replicas = || cluster_ids.iter().flat_map(|&c| self.replica_list(c));
+        let bound = candidates.iter().copied().chain(replicas().copied()).max().map_or(0, |id| id as usize + 1);
+        let mut seen = vec![0u64; bound.div_ceil(64)];
+        let mut first_sighting = |id: u32| {
+            let (word, bit) = (id as usize / 64, 1u64 << (id % 64));
+            let new = seen[word] & bit == 0;
+            seen[word] |= bit;
+            new
+        };
+        candidates.retain(|&id| first_sighting(id));
+        candidates.extend(replicas().copied().filter(|&id| first_sighting(id)));
+    }
+    
+    /// Cluster whose inverted list holds `id`, if any. The first call
+    /// indexes every list, once.
+    pub fn cluster_of(&self, id: u32) -> Option<usize> {
+        let clusters = self.cluster_by_id.get_or_init(|| {
+            let len = self.inverted_lists.iter().flatten().max().map_or(0, |&id| id as usize + 1);
+            let mut clusters = vec![UNLISTED; len];
+            for (cluster, list) in self.inverted_lists.iter().enumerate() {
+                for &id in list {
+                    clusters[id as usize] = cluster as u32;
+                }
+            }
+            clusters
+        });
+        clusters
+            .get(id as usize)
+            .filter(|&&cluster| cluster != UNLISTED)
+            .map(|&cluster| cluster as usize)
     }
     
     /// Get statistics about the index
@@ -119,6 +555,12 @@ impl IVFIndex {
         let mut sizes: Vec<_> = self.inverted_lists.iter().map(|l| l.len()).collect();
         sizes.sort();
         
+        let built_vectors = (0..self.inverted_lists.len()).map(|c| self.built_size(c)).sum();
+        let min_live_fraction = (0..self.inverted_lists.len())
+            .filter(|&c| self.built_size(c) > 0)
+            .map(|c| self.inverted_lists[c].len() as f32 / self.built_size(c) as f32)
+            .fold(1.0, f32::min);
+        
         IVFStats {
             num_clusters: self.centroids.len(),
             total_vectors,
@@ -127,13 +569,208 @@ impl IVFIndex {
             median_cluster_size: sizes[sizes.len() / 2],
             max_cluster_size: *sizes.last().unwrap_or(&0),
             num_probe: self.num_probe,
+            built_vectors,
+            min_live_fraction,
         }
     }
     
+    /// Bytes held by the centroids, the inverted lists and their sizes at
+    /// build time
+    pub fn memory_usage(&self) -> usize {
+        self.centroid_bytes() + self.list_bytes()
+    }
+    
+    /// Bytes held by the centroids
+    pub fn centroid_bytes(&self) -> usize {
+        self.centroids.iter().map(Vec::len).sum::<usize>() * std::mem::size_of::<f32>()
+    }
+    
+    /// Bytes held by the inverted and replica lists and the list sizes at
+    /// build time
+    pub fn list_bytes(&self) -> usize {
+        let ids: usize = self.inverted_lists.iter().chain(&self.replica_lists).map(Vec::len).sum();
+        ids * std::mem::size_of::<u32>() + self.built_sizes.len() * std::mem::size_of::<usize>()
+    }
+    
+    /// Trained cluster centroids, indexed by cluster id
+    pub fn centroids(&self) -> &[Vec<f32>] {
+        &self.centroids
+    }
+    
+    /// Centroid of one cluster, if there is such a cluster
+    pub fn centroid(&self, cluster_id: usize) -> Option<&[f32]> {
+        self.centroids.get(cluster_id).map(Vec::as_slice)
+    }
+    
+    /// Number of inverted lists; equals `num_clusters()` in a sound index
+    pub fn num_lists(&self) -> usize {
+        self.inverted_lists.len()
+    }
+    
+    /// Dimensionality the index was built for
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+    
+    /// Ids in a cluster's inverted list, in insertion order
+    pub fn inverted_list(&self, cluster_id: usize) -> &[u32] {
+        &self.inverted_lists[cluster_id]
+    }
+    
+    /// Ids assigned to a cluster, as [`IVFIndex::inverted_list`] holds
+    /// them; empty for a cluster that does not exist. Replicas are left
+    /// out, and ids deleted since the index last dropped them remain.
+    pub fn cluster_members(&self, cluster_id: usize) -> &[u32] {
+        self.inverted_lists.get(cluster_id).map_or(&[], Vec::as_slice)
+    }
+    
+    /// Ids listed in a cluster as replicas, ascending; empty without
+    /// replica assignment
+    pub fn replica_list(&self, cluster_id: usize) -> &[u32] {
+        self.replica_lists.get(cluster_id).map_or(&[], Vec::as_slice)
+    }
+    
+    /// Number of trained clusters
+    pub fn num_clusters(&self) -> usize {
+        self.centroids.len()
+    }
+    
+    /// Default number of clusters probed per query
+    pub fn num_probe(&self) -> usize {
+        self.num_probe
+    }
+    
     /// Set number of clusters to probe
     pub fn set_num_probe(&mut self, num_probe: usize) {
         self.num_probe = num_probe.min(self.centroids.len());
     }
+    
+    /// Even out cluster sizes until the largest holds at most
+    /// `max_imbalance` times the mean, as [`IVFStats::imbalance_factor`]
+    /// measures it. Empty clusters are dropped first; then the largest
+    /// cluster is split in two by k-means over its members, from
+    /// `vectors` indexed by id, until the bound holds or no cluster left
+    /// over it can be split. Members of split clusters then go to their
+    /// nearest centroid, as a new vector would.
+    ///
+    /// Cluster ids change, and the current list sizes become the build
+    /// sizes probe compensation aims for. Replicas are assigned afresh
+    /// against the new centroids. `num_probe` is kept, capped at the new
+    /// cluster count. `max_imbalance` must be at least 1, since the
+    /// largest cluster is never smaller than the mean.
+    pub fn rebalance(&mut self, vectors: &[Vec<f32>], max_imbalance: f32) -> RebalanceReport {
+        assert!(max_imbalance >= 1.0, "max_imbalance must be at least 1, not {}", max_imbalance);
+        let before = self.stats().imbalance_factor();
+        let mut report = RebalanceReport {
+            imbalance_before: before,
+            imbalance_after: before,
+            ..Default::default()
+        };
+        if before <= max_imbalance {
+            return report;
+        }
+        
+        // Drop empty clusters, which only pull the mean down
+        let kept: Vec<usize> = (0..self.inverted_lists.len()).filter(|&c| !self.inverted_lists[c].is_empty()).collect();
+        report.dropped = self.inverted_lists.len() - kept.len();
+        self.centroids = kept.iter().map(|&c| std::mem::take(&mut self.centroids[c])).collect();
+        self.inverted_lists = kept.iter().map(|&c| std::mem::take(&mut self.inverted_lists[c])).collect();
+        
+        let total: usize = self.inverted_lists.iter().map(Vec::len).sum();
+        let mut unsplittable = BTreeSet::new();
+        let mut split = BTreeSet::new();
+        while let Some(largest) = (0..self.inverted_lists.len())
+            .filter(|c| !unsplittable.contains(c))
+            .max_by_key(|&c| (self.inverted_lists[c].len(), std::cmp::Reverse(c)))
+        {
+            let size = self.inverted_lists[largest].len();
+            if size as f32 <= max_imbalance * total as f32 / self.inverted_lists.len() as f32 {
+                break;
+            }
+            if size < 2 || !self.split_cluster(largest, vectors, report.splits as u64) {
+                unsplittable.insert(largest);
+                continue;
+            }
+            split.insert(largest);
+            split.insert(self.inverted_lists.len() - 1);
+            report.splits += 1;
+        }
+        
+        // The halves were only compared with each other
+        let members: Vec<(usize, u32)> = split
+            .iter()
+            .flat_map(|&c| self.inverted_lists[c].iter().map(move |&id| (c, id)))
+            .collect();
+        let nearest: Vec<usize> = members.par_iter().map(|&(_, id)| self.find_nearest_cluster(&vectors[id as usize])).collect();
+        for &c in &split {
+            self.inverted_lists[c].clear();
+        }
+        for (&(from, id), to) in members.iter().zip(nearest) {
+            self.inverted_lists[to].push(id);
+            if to != from {
+                report.reassigned += 1;
+            }
+        }
+        report.moved = members.into_iter().map(|(_, id)| id).collect();
+        report.moved.sort_unstable();
+        
+        self.num_probe = self.num_probe.min(self.centroids.len());
+        self.check_cursors = Vec::new();
+        self.cluster_by_id = OnceLock::new();
+        self.assign_replica_lists(vectors);
+        self.mark_built();
+        report.imbalance_after = self.stats().imbalance_factor();
+        report
+    }
+    
+    /// Split cluster `cluster` in two with 2-means over its members,
+    /// keeping one half under its id and appending the other. Fails,
+    /// changing nothing, when the members cannot be told apart.
+    fn split_cluster(&mut self, cluster: usize, vectors: &[Vec<f32>], seed: u64) -> bool {
+        let ids = &self.inverted_lists[cluster];
+        let members: Vec<Vec<f32>> = match self.metric() {
+            DistanceMetric::Cosine => ids.iter().map(|&id| normalized(&vectors[id as usize])).collect(),
+            _ => ids.iter().map(|&id| vectors[id as usize].clone()).collect(),
+        };
+        let result = kmeans_with_progress(&members, 2, 100, 0.001, Some(seed), &Silent);
+        let mut halves = [Vec::new(), Vec::new()];
+        for (&id, half) in ids.iter().zip(result.assignments) {
+            halves[half].push(id);
+        }
+        if halves.iter().any(Vec::is_empty) {
+            return false;
+        }
+        
+        let [first, second]: [Vec<f32>; 2] = result.centroids.try_into().unwrap();
+        let [kept, moved] = halves;
+        self.centroids[cluster] = first;
+        self.centroids.push(second);
+        self.inverted_lists[cluster] = kept;
+        self.inverted_lists.push(moved);
+        true
+    }
+}
+
+/// What [`IVFIndex::rebalance`] changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RebalanceReport {
+    /// Clusters split in two
+    pub splits: usize,
+    
+    /// Empty clusters dropped
+    pub dropped: usize,
+    
+    /// Members of split clusters that ended up nearer a centroid outside
+    /// their half
+    pub reassigned: usize,
+    
+    /// Ids in the clusters that were split, ascending, whose cluster or
+    /// centroid changed
+    pub moved: Vec<u32>,
+    
+    /// [`IVFStats::imbalance_factor`] before and after
+    pub imbalance_before: f32,
+    pub imbalance_after: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -145,6 +782,25 @@ pub struct IVFStats {
     pub median_cluster_size: usize,
     pub max_cluster_size: usize,
     pub num_probe: usize,
+    
+    /// Entries listed when the index was built
+    pub built_vectors: usize,
+    
+    /// Share of its build-time entries the most depleted cluster still
+    /// lists, capped at 1; low after deletions concentrated in a few
+    /// clusters, which compensated probing makes up for
+    pub min_live_fraction: f32,
+}
+
+impl IVFStats {
+    /// Largest cluster size over the mean, counting empty clusters: 1
+    /// when every cluster is the same size, and 0 for an empty index
+    pub fn imbalance_factor(&self) -> f32 {
+        if self.total_vectors == 0 {
+            return 0.0;
+        }
+        self.max_cluster_size as f32 * self.num_clusters as f32 / self.total_vectors as f32
+    }
 }
 
 impl std::fmt::Display for IVFStats {
@@ -155,14 +811,18 @@ impl std::fmt::Display for IVFStats {
              - Clusters: {} ({} non-empty)\n\
              - Total vectors: {}\n\
              - Cluster sizes: min={}, median={}, max={}\n\
-             - Probe: {} clusters per query",
+             - Probe: {} clusters per query\n\
+             - Since build: {} of {} vectors listed, at least {:.0}% per cluster",
             self.num_clusters,
             self.non_empty_clusters,
             self.total_vectors,
             self.min_cluster_size,
             self.median_cluster_size,
             self.max_cluster_size,
-            self.num_probe
+            self.num_probe,
+            self.total_vectors,
+            self.built_vectors,
+            self.min_live_fraction * 100.0
         )
     }
 }
@@ -171,6 +831,122 @@ impl std::fmt::Display for IVFStats {
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_adapted_centroid_is_the_running_mean() {
+        let mut index = IVFIndex::from_assignments(2, vec![vec![0.0, 0.0], vec![9.0, 9.0]], &[0, 1], 1, DistanceMetric::Euclidean);
+        index.add_to_cluster(0, &[2]);
+        index.adapt_centroid(0, &[2.0, 4.0], 0.0);
+        assert_eq!(index.centroids()[0], [1.0, 2.0]);
+        
+        // The floor takes over once 1/n is smaller
+        index.add_to_cluster(0, &[3, 4]);
+        index.adapt_centroid(0, &[1.0, 12.0], 0.5);
+        assert_eq!(index.centroids()[0], [1.0, 7.0]);
+        assert_eq!(index.centroids()[1], [9.0, 9.0]);
+    }
+    
+    #[test]
+    fn test_members_are_checked_in_turn() {
+        let mut index = IVFIndex::from_assignments(2, vec![vec![0.0, 0.0]], &[0, 0, 0, 0, 0], 1, DistanceMetric::Euclidean);
+        assert_eq!(index.members_to_check(0, 2), [0, 1]);
+        assert_eq!(index.members_to_check(0, 2), [2, 3]);
+        assert_eq!(index.members_to_check(0, 2), [4, 0]);
+        assert_eq!(index.members_to_check(0, 9), [1, 2, 3, 4, 0]);
+        
+        index.move_id(2, 0, 0);
+        assert_eq!(index.inverted_list(0), [0, 1, 3, 4, 2]);
+    }
+    
+    #[test]
+    fn test_cluster_lookup_follows_list_edits() {
+        let mut index = IVFIndex::from_assignments(1, vec![vec![0.0], vec![5.0]], &[0, 1, 0], 1, DistanceMetric::Euclidean);
+        assert_eq!(index.cluster_of(2), Some(0));
+        index.move_id(2, 0, 1);
+        index.add_to_cluster(0, &[5]);
+        index.remove_ids(&[0].into_iter().collect());
+        let found: Vec<_> = (0..7).map(|id| index.cluster_of(id)).collect();
+        assert_eq!(found, [None, Some(1), Some(1), None, None, Some(0), None]);
+    }
+    
+    #[test]
+    fn test_probing_widens_to_the_built_volume() {
+        let centroids = vec![vec![0.0], vec![1.0], vec![2.0], vec![3.0]];
+        let mut index = IVFIndex::from_assignments(1, centroids, &[0, 0, 1, 1, 2, 2, 3, 3], 1, DistanceMetric::Euclidean);
+        assert_eq!(index.probe_compensated(&[0.0], 1, 3), [0]);
+        
+        // Cluster 0 emptied and cluster 1 halved: two more lists make up
+        // its two entries, and the cap stops a third
+        index.remove_ids(&[0, 1, 2].into_iter().collect());
+        assert_eq!(index.probe_compensated(&[0.0], 1, 4), [0, 1, 2]);
+        assert_eq!(index.probe_compensated(&[0.0], 1, 2), [0, 1]);
+        assert_eq!((index.built_size(0), index.stats().min_live_fraction), (2, 0.0));
+    }
+    
+    #[test]
+    fn test_rebalance_splits_the_crowded_cluster_and_drops_empty_ones() {
+        // 90 points along a line in cluster 0, one each in clusters 1-9,
+        // and clusters 10-19 empty
+        let mut vectors: Vec<Vec<f32>> = (0..90).map(|i| vec![i as f32 * 0.01, 0.0]).collect();
+        vectors.extend((1..10).map(|c| vec![c as f32 * 10.0, 5.0]));
+        let mut assignments = vec![0; 90];
+        assignments.extend(1..10);
+        let mut centroids = vec![vec![0.45, 0.0]];
+        centroids.extend((1..20).map(|c| vec![c as f32 * 10.0, 5.0]));
+        let mut index = IVFIndex::from_assignments(2, centroids, &assignments, 12, DistanceMetric::Euclidean);
+        assert_eq!(index.stats().imbalance_factor(), 90.0 * 20.0 / 99.0);
+        
+        let report = index.rebalance(&vectors, 3.0);
+        let stats = index.stats();
+        assert_eq!(report.dropped, 10);
+        assert!(report.splits > 0 && report.imbalance_after <= 3.0, "{:?}", report);
+        assert_eq!(report.imbalance_after, stats.imbalance_factor());
+        assert_eq!(report.moved, (0..90).collect::<Vec<u32>>());
+        assert_eq!((stats.num_clusters, index.num_lists()), (10 + report.splits, 10 + report.splits));
+        assert_eq!(stats.non_empty_clusters, stats.num_clusters);
+        assert_eq!(index.num_probe(), 12.min(stats.num_clusters));
+        
+        // Every id listed once, under its nearest centroid
+        let mut listed: Vec<u32> = (0..index.num_lists()).flat_map(|c| index.inverted_list(c).to_vec()).collect();
+        listed.sort_unstable();
+        assert_eq!(listed, (0..99).collect::<Vec<u32>>());
+        for (id, vector) in vectors.iter().enumerate() {
+            assert_eq!(index.cluster_of(id as u32), Some(index.assign(vector)));
+        }
+        
+        // Within the bound, nothing changes
+        let again = index.rebalance(&vectors, 3.0);
+        assert_eq!((again.splits, again.dropped), (0, 0));
+    }
+    
+    #[test]
+    fn test_identical_members_are_left_together() {
+        let vectors = vec![vec![1.0, 1.0]; 10];
+        let mut index = IVFIndex::from_assignments(2, vec![vec![1.0, 1.0], vec![5.0, 5.0]], &[0; 10], 1, DistanceMetric::Euclidean);
+        let report = index.rebalance(&vectors, 1.0);
+        assert_eq!((report.splits, report.dropped, index.num_clusters()), (0, 1, 1));
+        assert_eq!(report.imbalance_after, 1.0);
+    }
+    
+    #[test]
+    fn test_replicas_are_candidates_once() {
+        // Two clusters on a line, so a second replica lists every id in both
+        let vectors = vec![vec![0.0], vec![4.0], vec![6.0], vec![10.0]];
+        let mut index = IVFIndex::new(1, 2, 1);
+        index.set_assign_replicas(2);
+        index.build_seeded(&vectors, 2, Some(3));
+        let (left, right) = (index.assign(&[0.0]), index.assign(&[10.0]));
+        assert_eq!((index.inverted_list(left), index.replica_list(left)), (&[0, 1][..], &[2, 3][..]));
+        assert_eq!(index.replica_list(right), [0, 1]);
+        assert_eq!(index.get_candidates(&[left]), [0, 1, 2, 3]);
+        assert_eq!(index.get_candidates(&[right, left]), [2, 3, 0, 1]);
+        assert_eq!((index.cluster_of(2), index.stats().total_vectors), (Some(right), 4));
+        
+        index.remove_ids(&[1, 2].into_iter().collect());
+        assert_eq!((index.replica_list(left), index.replica_list(right)), (&[3][..], &[0][..]));
+        assert_eq!(index.get_candidates(&[right]), [3, 0]);
+        assert_eq!(index.list_bytes(), 4 * 4 + 2 * std::mem::size_of::<usize>());
+    }
+    
     #[test]
     fn test_ivf_build() {
         // Create synthetic vectors in 3 clear clusters
@@ -236,4 +1012,20 @@ mod tests {
         // Should get candidates from probed clusters
         assert!(!candidates.is_empty());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_ivf_add() {
+        let vectors: Vec<Vec<f32>> = (0..200)
+            .map(|i| vec![(i % 2) as f32 * 10.0, (i as f32 * 0.1).sin()])
+            .collect();
+        
+        let mut index = IVFIndex::new(2, 2, 1);
+        index.build_seeded(&vectors, 2, Some(5));
+        
+        let cluster = index.add(200, &[10.0, 0.0]);
+        assert_eq!(cluster, index.assign(&[10.0, 0.0]));
+        assert!(index.inverted_list(cluster).contains(&200));
+        assert!(index.get_candidates(&index.probe(&[10.0, 0.0])).contains(&200));
+        assert_eq!(index.stats().total_vectors, 201);
+    }
+}
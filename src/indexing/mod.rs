@@ -1,3 +1,5 @@
+pub mod flat;
 pub mod ivf;
 
-pub use ivf::IVFIndex;
\ No newline at end of file
+pub use flat::FlatIndex;
+pub use ivf::{IVFIndex, RebalanceReport};
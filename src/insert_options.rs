@@ -0,0 +1,316 @@
+use crate::error::{KhadyotaError, Result};
+use crate::external_ids::ExternalId;
+use crate::metadata::check_size;
+use crate::types::EntryAttributes;
+use crate::vector_db::VectorDB;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Options for [`VectorDB::insert_opts`]
+#[derive(Debug, Clone, Default)]
+pub struct InsertOptions {
+    /// Explicit id: the next free id, or an existing one with `upsert`
+    pub id: Option<u32>,
+
+    /// Caller's own id for the entry; see [`VectorDB::insert_with_id`]
+    pub external_id: Option<ExternalId>,
+
+    /// Replace the entry when `id` or `external_id` already exists
+    pub upsert: bool,
+
+    pub metadata: Option<serde_json::Value>,
+
+    /// Exclude the entry from search results once this much time has passed
+    pub ttl: Option<Duration>,
+
+    /// Caller-defined importance, stored with the entry
+    pub weight: Option<f32>,
+
+    /// Owning tenant
+    pub tenant: Option<String>,
+
+    /// Suggested IVF cluster for the entry
+    pub cluster_hint: Option<u32>,
+
+    /// Sync the insert's journal record to disk before returning, whatever
+    /// the [`SyncPolicy`](crate::SyncPolicy); only for databases opened
+    /// with [`VectorDB::open_durable`]
+    pub durable: bool,
+}
+
+impl InsertOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn external_id(mut self, external_id: impl Into<ExternalId>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    pub fn upsert(mut self, upsert: bool) -> Self {
+        self.upsert = upsert;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn weight(mut self, weight: f32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    pub fn cluster_hint(mut self, cluster: u32) -> Self {
+        self.cluster_hint = Some(cluster);
+        self
+    }
+
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Check the options against each other and against `db`
+    pub(crate) fn validate(&self, db: &VectorDB) -> Result<()> {
+        let invalid = |msg: String| Err(KhadyotaError::InvalidConfig(msg));
+
+        match self.id {
+            Some(id) if id > db.next_id => {
+                return invalid(format!(
+                    "id {} would leave a gap; the next id is {}",
+                    id, db.next_id
+                ));
+            }
+            Some(id) if id < db.next_id && !self.upsert => {
+                return invalid(format!("id {} already exists; set upsert to replace it", id));
+            }
+            None if self.upsert && self.external_id.is_none() => {
+                return invalid("upsert requires an explicit id".to_string());
+            }
+            _ => {}
+        }
+
+        if let Some(external_id) = &self.external_id
+            && let Some(existing) = db.external_ids.id_of(external_id)
+        {
+            if !self.upsert {
+                return Err(KhadyotaError::DuplicateExternalId(external_id.clone()));
+            }
+            if self.id.is_some_and(|id| id != existing) {
+                return invalid(format!("external id {} belongs to id {}", external_id, existing));
+            }
+        }
+
+        if self.ttl == Some(Duration::ZERO) {
+            return invalid("ttl must be > 0".to_string());
+        }
+
+        if let Some(weight) = self.weight
+            && !(weight.is_finite() && weight > 0.0)
+        {
+            return invalid(format!("weight must be positive and finite, got {}", weight));
+        }
+
+        if self.tenant.as_deref() == Some("") {
+            return invalid("tenant must not be empty".to_string());
+        }
+
+        if let Some(cluster) = self.cluster_hint
+            && cluster as usize >= db.config.num_clusters
+        {
+            return invalid(format!(
+                "cluster_hint ({}) exceeds the number of clusters ({})",
+                cluster, db.config.num_clusters
+            ));
+        }
+
+        if let Some(metadata) = &self.metadata {
+            check_size(&db.config, metadata)?;
+        }
+
+        if self.durable && !db.is_durable() {
+            return invalid("durable inserts require a journal; open the database with VectorDB::open_durable".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Attributes to record for the entry
+    pub(crate) fn attributes(&self) -> EntryAttributes {
+        let expires_at_unix_secs = self.ttl.map(|ttl| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            // Round up so an entry never expires before its ttl has elapsed
+            let expires = now + ttl;
+            expires.as_secs() + u64::from(expires.subsec_nanos() > 0)
+        });
+
+        EntryAttributes {
+            expires_at_unix_secs,
+            weight: self.weight,
+            tenant: self.tenant.clone(),
+            cluster_hint: self.cluster_hint,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuantizerKind};
+    use tempfile::NamedTempFile;
+
+    fn vector(i: usize) -> Vec<f32> {
+        (0..8).map(|j| ((i * 8 + j) as f32).sin()).collect()
+    }
+
+    fn build_db() -> VectorDB {
+        let config = Config {
+            dimensions: 8,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 4,
+            normalize: false,
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..50 {
+            db.insert(vector(i), None).unwrap();
+        }
+        db
+    }
+
+    fn error_message(db: &mut VectorDB, opts: InsertOptions) -> String {
+        match db.insert_opts(vector(0), opts) {
+            Err(KhadyotaError::InvalidConfig(msg)) => msg,
+            other => panic!("expected InvalidConfig, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_explicit_id_and_upsert() {
+        let mut db = build_db();
+
+        assert_eq!(db.insert_opts(vector(50), InsertOptions::new().id(50)).unwrap(), 50);
+        assert_eq!(
+            error_message(&mut db, InsertOptions::new().id(60)),
+            "id 60 would leave a gap; the next id is 51"
+        );
+        assert_eq!(
+            error_message(&mut db, InsertOptions::new().id(3)),
+            "id 3 already exists; set upsert to replace it"
+        );
+        assert_eq!(
+            error_message(&mut db, InsertOptions::new().upsert(true)),
+            "upsert requires an explicit id"
+        );
+
+        let opts = InsertOptions::new().id(3).upsert(true).metadata(serde_json::json!({"v": 2}));
+        assert_eq!(db.insert_opts(vec![1.0; 8], opts).unwrap(), 3);
+        assert_eq!(db.len(), 51);
+
+        let entry = db.get(3).unwrap();
+        assert_eq!(entry.vector, vec![1.0; 8]);
+        assert_eq!(entry.metadata.unwrap()["v"], 2);
+    }
+
+    #[test]
+    fn test_individual_attribute_options() {
+        let mut db = build_db();
+
+        let id = db.insert_opts(vector(1), InsertOptions::new().weight(2.5)).unwrap();
+        assert_eq!(db.attributes(id).unwrap().weight, Some(2.5));
+
+        let id = db.insert_opts(vector(2), InsertOptions::new().tenant("acme")).unwrap();
+        assert_eq!(db.attributes(id).unwrap().tenant.as_deref(), Some("acme"));
+
+        let id = db.insert_opts(vector(3), InsertOptions::new().cluster_hint(1)).unwrap();
+        assert_eq!(db.attributes(id).unwrap().cluster_hint, Some(1));
+
+        // Plain inserts record no attributes
+        let id = db.insert(vector(4), None).unwrap();
+        assert!(db.attributes(id).is_none());
+
+        assert_eq!(
+            error_message(&mut db, InsertOptions::new().weight(-1.0)),
+            "weight must be positive and finite, got -1"
+        );
+        assert_eq!(error_message(&mut db, InsertOptions::new().tenant("")), "tenant must not be empty");
+        assert_eq!(
+            error_message(&mut db, InsertOptions::new().cluster_hint(4)),
+            "cluster_hint (4) exceeds the number of clusters (4)"
+        );
+        assert_eq!(
+            error_message(&mut db, InsertOptions::new().durable(true)),
+            "durable inserts require a journal; open the database with VectorDB::open_durable"
+        );
+    }
+
+    #[test]
+    fn test_ttl_excludes_expired_entries() {
+        let mut db = build_db();
+        let probe = vec![9.0; 8];
+
+        let live = db.insert_opts(probe.clone(), InsertOptions::new().ttl(Duration::from_secs(3600))).unwrap();
+        let expired = db.insert_opts(probe.clone(), InsertOptions::new().ttl(Duration::from_secs(1))).unwrap();
+        assert_eq!(error_message(&mut db, InsertOptions::new().ttl(Duration::ZERO)), "ttl must be > 0");
+
+        // Backdate instead of sleeping past the ttl
+        db.attributes.get_mut(&expired).unwrap().expires_at_unix_secs = Some(1);
+        db.build_index().unwrap();
+
+        let ids: Vec<u32> = db.search(&probe, 5).unwrap().iter().map(|r| r.id).collect();
+        assert_eq!(ids[0], live);
+        assert!(!ids.contains(&expired));
+    }
+
+    #[test]
+    fn test_kitchen_sink_round_trips() {
+        let mut db = build_db();
+        let opts = InsertOptions::new()
+            .id(7)
+            .upsert(true)
+            .metadata(serde_json::json!({"title": "sink"}))
+            .ttl(Duration::from_secs(86_400))
+            .weight(0.5)
+            .tenant("t1")
+            .cluster_hint(2);
+        db.insert_opts(vec![0.25; 8], opts).unwrap();
+        db.build_index().unwrap();
+
+        let temp = NamedTempFile::new().unwrap();
+        db.save(temp.path()).unwrap();
+        let loaded = VectorDB::load(temp.path()).unwrap();
+
+        let entry = loaded.get(7).unwrap();
+        assert_eq!(entry.vector, vec![0.25; 8]);
+        assert_eq!(entry.metadata.unwrap()["title"], "sink");
+        assert_eq!(loaded.attributes(7), db.attributes(7));
+
+        let attributes = loaded.attributes(7).unwrap();
+        assert!(attributes.expires_at_unix_secs.is_some());
+        assert_eq!(attributes.weight, Some(0.5));
+        assert_eq!(attributes.tenant.as_deref(), Some("t1"));
+        assert_eq!(attributes.cluster_hint, Some(2));
+        assert_eq!(loaded.search(&[0.25; 8], 1).unwrap()[0].id, 7);
+    }
+}
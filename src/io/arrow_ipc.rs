@@ -0,0 +1,311 @@
+use crate::error::{KhadyotaError, Result};
+use crate::search_params::SearchParams;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use arrow_array::builder::{Float32Builder, StringBuilder, UInt32Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use rayon::prelude::*;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Options for streaming neighbor lists as Arrow IPC
+#[derive(Debug, Clone)]
+pub struct IpcExportParams {
+    /// Target number of rows per record batch
+    pub batch_rows: usize,
+
+    /// Top-level metadata keys to project into nullable Utf8 columns.
+    /// String values are written as-is, anything else as JSON text.
+    pub metadata_fields: Vec<String>,
+}
+
+impl Default for IpcExportParams {
+    fn default() -> Self {
+        Self {
+            batch_rows: 65_536,
+            metadata_fields: Vec::new(),
+        }
+    }
+}
+
+impl IpcExportParams {
+    /// Arrow schema of the exported stream
+    pub fn schema(&self) -> SchemaRef {
+        let mut fields = vec![
+            Field::new("query_id", DataType::UInt32, false),
+            Field::new("rank", DataType::UInt32, false),
+            Field::new("neighbor_id", DataType::UInt32, false),
+            Field::new("distance", DataType::Float32, false),
+        ];
+
+        for name in &self.metadata_fields {
+            fields.push(Field::new(name, DataType::Utf8, true));
+        }
+
+        Arc::new(Schema::new(fields))
+    }
+}
+
+impl VectorDB {
+    /// Run a batch of k-NN queries and stream the neighbor lists to `writer`
+    /// as an Arrow IPC stream.
+    ///
+    /// Each query is searched as [`VectorDB::search_with_params`] would
+    /// under `search`, which is validated before anything is written.
+    /// Under admission control each chunk takes one slot.
+    ///
+    /// Queries are processed in chunks sized so that each chunk fills roughly
+    /// one record batch; a chunk is searched in parallel and written out
+    /// before the next one starts, so memory stays bounded by a single batch
+    /// regardless of how many queries are exported.
+    ///
+    /// Returns the total number of rows written.
+    pub fn export_neighbors_ipc<W: Write>(
+        &self,
+        queries: &[Vec<f32>],
+        k: usize,
+        search: &SearchParams,
+        params: &IpcExportParams,
+        writer: W,
+    ) -> Result<u64> {
+        if params.batch_rows == 0 {
+            return Err(KhadyotaError::InvalidConfig(
+                "batch_rows must be > 0".to_string()
+            ));
+        }
+        search.validate(self)?;
+
+        let schema = params.schema();
+        let mut stream = StreamWriter::try_new(writer, &schema)?;
+
+        let queries_per_batch = (params.batch_rows / k.max(1)).max(1);
+        let mut rows_written = 0u64;
+
+        for (chunk_idx, chunk) in queries.chunks(queries_per_batch).enumerate() {
+            // One admission slot covers the chunk, taken before fanning out
+            let permit = self.admit()?;
+            let results: Vec<Vec<SearchResult>> = chunk
+                .par_iter()
+                .map(|query| self.search_in_batch(query, k, search))
+                .collect::<Result<_>>()?;
+            drop(permit);
+
+            let first_query_id = (chunk_idx * queries_per_batch) as u32;
+            let batch = build_batch(&schema, params, first_query_id, &results)?;
+
+            rows_written += batch.num_rows() as u64;
+            stream.write(&batch)?;
+        }
+
+        stream.finish()?;
+
+        Ok(rows_written)
+    }
+}
+
+/// Flatten one chunk of result lists into a record batch
+fn build_batch(
+    schema: &SchemaRef,
+    params: &IpcExportParams,
+    first_query_id: u32,
+    results: &[Vec<SearchResult>],
+) -> Result<RecordBatch> {
+    let num_rows: usize = results.iter().map(|r| r.len()).sum();
+
+    let mut query_ids = UInt32Builder::with_capacity(num_rows);
+    let mut ranks = UInt32Builder::with_capacity(num_rows);
+    let mut neighbor_ids = UInt32Builder::with_capacity(num_rows);
+    let mut distances = Float32Builder::with_capacity(num_rows);
+    let mut metadata_columns: Vec<StringBuilder> = params
+        .metadata_fields
+        .iter()
+        .map(|_| StringBuilder::new())
+        .collect();
+
+    for (offset, neighbors) in results.iter().enumerate() {
+        for (rank, result) in neighbors.iter().enumerate() {
+            query_ids.append_value(first_query_id + offset as u32);
+            ranks.append_value(rank as u32);
+            neighbor_ids.append_value(result.id);
+            distances.append_value(result.distance);
+
+            for (field, column) in params.metadata_fields.iter().zip(metadata_columns.iter_mut()) {
+                match result.metadata.as_ref().and_then(|meta| meta.get(field)) {
+                    Some(serde_json::Value::String(s)) => column.append_value(s),
+                    Some(value) => column.append_value(value.to_string()),
+                    None => column.append_null(),
+                }
+            }
+        }
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(query_ids.finish()),
+        Arc::new(ranks.finish()),
+        Arc::new(neighbor_ids.finish()),
+        Arc::new(distances.finish()),
+    ];
+    columns.extend(
+        metadata_columns
+            .into_iter()
+            .map(|mut column| Arc::new(column.finish()) as ArrayRef)
+    );
+
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuantizerKind};
+    use arrow_array::{Array, Float32Array, StringArray, UInt32Array};
+    use arrow_ipc::reader::StreamReader;
+
+    fn build_db() -> VectorDB {
+        let config = Config {
+            dimensions: 16,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 2,
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..200 {
+            let vector: Vec<f32> = (0..16).map(|j| ((i * 16 + j) as f32).sin()).collect();
+            let metadata = if i % 2 == 0 {
+                Some(serde_json::json!({"title": format!("doc{}", i), "year": 2000 + i}))
+            } else {
+                None
+            };
+            db.insert(vector, metadata).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_ipc_stream_matches_batch_search() {
+        let db = build_db();
+        let queries: Vec<Vec<f32>> = (0..25)
+            .map(|i| (0..16).map(|j| ((i + j) as f32).cos()).collect())
+            .collect();
+        let k = 5;
+
+        let params = IpcExportParams {
+            batch_rows: 12,
+            metadata_fields: vec!["title".to_string(), "year".to_string()],
+        };
+
+        let mut buffer = Vec::new();
+        let rows = db
+            .export_neighbors_ipc(&queries, k, &SearchParams::default(), &params, &mut buffer)
+            .unwrap();
+
+        let expected = db.batch_search(&queries, k).unwrap();
+        let expected_rows: Vec<(u32, u32, &SearchResult)> = expected
+            .iter()
+            .enumerate()
+            .flat_map(|(q, results)| {
+                results.iter().enumerate().map(move |(rank, r)| (q as u32, rank as u32, r))
+            })
+            .collect();
+        assert_eq!(rows as usize, expected_rows.len());
+
+        let reader = StreamReader::try_new(std::io::Cursor::new(buffer), None).unwrap();
+        let mut actual_rows = 0;
+        let mut num_batches = 0;
+
+        for batch in reader {
+            let batch = batch.unwrap();
+            num_batches += 1;
+
+            let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+            let query_ids = column("query_id");
+            let query_ids = query_ids.as_any().downcast_ref::<UInt32Array>().unwrap();
+            let ranks = column("rank");
+            let ranks = ranks.as_any().downcast_ref::<UInt32Array>().unwrap();
+            let ids = column("neighbor_id");
+            let ids = ids.as_any().downcast_ref::<UInt32Array>().unwrap();
+            let distances = column("distance");
+            let distances = distances.as_any().downcast_ref::<Float32Array>().unwrap();
+            let titles = column("title");
+            let titles = titles.as_any().downcast_ref::<StringArray>().unwrap();
+            let years = column("year");
+            let years = years.as_any().downcast_ref::<StringArray>().unwrap();
+
+            for row in 0..batch.num_rows() {
+                let (q, rank, result) = expected_rows[actual_rows];
+                assert_eq!(query_ids.value(row), q);
+                assert_eq!(ranks.value(row), rank);
+                assert_eq!(ids.value(row), result.id);
+                assert_eq!(distances.value(row), result.distance);
+
+                match &result.metadata {
+                    Some(meta) => {
+                        assert_eq!(titles.value(row), meta["title"].as_str().unwrap());
+                        assert_eq!(years.value(row), meta["year"].to_string());
+                    }
+                    None => {
+                        assert!(titles.is_null(row));
+                        assert!(years.is_null(row));
+                    }
+                }
+
+                actual_rows += 1;
+            }
+        }
+
+        assert_eq!(actual_rows, expected_rows.len());
+        // 12 rows per batch at k=5 means 2 queries per batch
+        assert_eq!(num_batches, 13);
+    }
+
+    #[test]
+    fn test_ipc_rejects_zero_batch_rows() {
+        let db = build_db();
+        let params = IpcExportParams {
+            batch_rows: 0,
+            ..Default::default()
+        };
+
+        let result = db.export_neighbors_ipc(&[vec![0.0; 16]], 5, &SearchParams::default(), &params, Vec::new());
+        assert!(matches!(result, Err(KhadyotaError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_ipc_searches_with_the_given_params() {
+        let db = build_db();
+        let queries: Vec<Vec<f32>> = (0..6)
+            .map(|i| (0..16).map(|j| ((i * 3 + j) as f32).sin()).collect())
+            .collect();
+        let search = SearchParams::builder().num_probe(4).exclude(0..50).build();
+
+        let mut buffer = Vec::new();
+        db.export_neighbors_ipc(&queries, 3, &search, &IpcExportParams::default(), &mut buffer)
+            .unwrap();
+        let mut ids = Vec::new();
+        for batch in StreamReader::try_new(std::io::Cursor::new(buffer), None).unwrap() {
+            let batch = batch.unwrap();
+            let column = batch.column_by_name("neighbor_id").unwrap();
+            ids.extend(column.as_any().downcast_ref::<UInt32Array>().unwrap().values().iter().copied());
+        }
+
+        let expected: Vec<u32> = queries
+            .iter()
+            .flat_map(|query| db.search_with_params(query, 3, &search).unwrap())
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, expected);
+        assert!(ids.iter().all(|&id| id >= 50));
+
+        // Parameters the index cannot honour fail before the stream starts
+        let mut buffer = Vec::new();
+        let bad = SearchParams::builder().num_probe(5).build();
+        let result = db.export_neighbors_ipc(&queries, 3, &bad, &IpcExportParams::default(), &mut buffer);
+        assert!(matches!(result, Err(KhadyotaError::InvalidConfig(_))));
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+use crate::config::Config;
+use crate::error::{KhadyotaError, Result};
+use crate::types::EntryAttributes;
+use crate::vector_db::VectorDB;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::{self, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, Write};
+
+const FORMAT: &str = "khadyota-jsonl";
+const FORMAT_VERSION: u32 = 1;
+
+/// How vectors are written to JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FloatEncoding {
+    /// Arrays of numbers, each the shortest decimal that parses back to
+    /// the same `f32`. Non-finite values cannot be written this way.
+    #[default]
+    Decimal,
+
+    /// Base64 of the little-endian `f32` bytes; exact for every bit pattern
+    Base64,
+}
+
+/// Serializes a vector with the given encoding, never widening through f64
+pub struct EncodedVector<'a> {
+    pub vector: &'a [f32],
+    pub encoding: FloatEncoding,
+}
+
+impl Serialize for EncodedVector<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.encoding {
+            FloatEncoding::Decimal => {
+                let mut seq = serializer.serialize_seq(Some(self.vector.len()))?;
+                for &value in self.vector {
+                    if !value.is_finite() {
+                        return Err(ser::Error::custom(format!(
+                            "{} has no JSON number form; export with FloatEncoding::Base64",
+                            value
+                        )));
+                    }
+                    seq.serialize_element(&value)?;
+                }
+                seq.end()
+            }
+            FloatEncoding::Base64 => {
+                let bytes: Vec<u8> = self.vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+                serializer.serialize_str(&BASE64.encode(bytes))
+            }
+        }
+    }
+}
+
+/// A vector read from JSON in either [`FloatEncoding`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonVector(pub Vec<f32>);
+
+impl<'de> Deserialize<'de> for JsonVector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct JsonVectorVisitor;
+
+        impl<'de> Visitor<'de> for JsonVectorVisitor {
+            type Value = JsonVector;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array of numbers or a base64 string of little-endian f32s")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<JsonVector, A::Error> {
+                let mut vector = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(value) = seq.next_element::<f32>()? {
+                    vector.push(value);
+                }
+                Ok(JsonVector(vector))
+            }
+
+            fn visit_str<E: de::Error>(self, encoded: &str) -> std::result::Result<JsonVector, E> {
+                let bytes = BASE64.decode(encoded).map_err(E::custom)?;
+                if bytes.len() % 4 != 0 {
+                    return Err(E::custom(format!(
+                        "base64 vector decodes to {} bytes, not a whole number of f32s",
+                        bytes.len()
+                    )));
+                }
+                Ok(JsonVector(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                ))
+            }
+        }
+
+        deserializer.deserialize_any(JsonVectorVisitor)
+    }
+}
+
+#[derive(Serialize)]
+struct HeaderLineRef<'a> {
+    format: &'static str,
+    version: u32,
+    config: &'a Config,
+    count: usize,
+}
+
+#[derive(Deserialize)]
+struct HeaderLine {
+    format: String,
+    version: u32,
+    config: Config,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct EntryLineRef<'a> {
+    id: u32,
+    vector: EncodedVector<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attributes: Option<&'a EntryAttributes>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+}
+
+#[derive(Deserialize)]
+struct EntryLine {
+    id: u32,
+    vector: JsonVector,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    attributes: Option<EntryAttributes>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+fn json_error(line: usize, e: serde_json::Error) -> KhadyotaError {
+    KhadyotaError::SerializationError(format!("JSONL line {}: {}", line, e))
+}
+
+impl VectorDB {
+    /// Write every entry as JSON lines: a header line with the config,
+    /// then one line per id with its vector, metadata and attributes.
+    /// Deleted ids keep their line, flagged `"deleted": true`, so ids stay
+    /// dense.
+    ///
+    /// Vectors are written exactly, so [`VectorDB::import_jsonl`] restores
+    /// the same [`VectorDB::checksum`]. Index structures are not exported.
+    ///
+    /// Returns the number of entries written.
+    pub fn export_jsonl<W: Write>(&self, mut writer: W, encoding: FloatEncoding) -> Result<u64> {
+        let header = HeaderLineRef {
+            format: FORMAT,
+            version: FORMAT_VERSION,
+            config: &self.config,
+            count: self.vectors.len(),
+        };
+        serde_json::to_writer(&mut writer, &header).map_err(|e| json_error(1, e))?;
+        writer.write_all(b"\n")?;
+
+        for (id, vector) in self.vectors.iter().enumerate() {
+            let id = id as u32;
+            let entry = EntryLineRef {
+                id,
+                vector: EncodedVector { vector: &vector, encoding },
+                metadata: self.metadata.get(&id),
+                attributes: self.attributes.get(&id),
+                deleted: self.deleted.contains(&id),
+            };
+            serde_json::to_writer(&mut writer, &entry).map_err(|e| json_error(id as usize + 2, e))?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()?;
+        Ok(self.vectors.len() as u64)
+    }
+
+    /// Rebuild a database from [`VectorDB::export_jsonl`] output.
+    ///
+    /// Vectors may use either [`FloatEncoding`], even mixed within a file.
+    /// The index is not restored; call `build_index()` before searching.
+    pub fn import_jsonl<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        let Some((_, first)) = lines.next() else {
+            return Err(KhadyotaError::SerializationError("JSONL input is empty".to_string()));
+        };
+        let header: HeaderLine = serde_json::from_str(&first?).map_err(|e| json_error(1, e))?;
+        if header.format != FORMAT || header.version != FORMAT_VERSION {
+            return Err(KhadyotaError::SerializationError(format!(
+                "Unsupported JSONL format: {} version {}",
+                header.format, header.version
+            )));
+        }
+
+        let mut db = VectorDB::new(header.config)?;
+        for (number, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: EntryLine = serde_json::from_str(&line).map_err(|e| json_error(number, e))?;
+            if entry.id != db.next_id {
+                return Err(KhadyotaError::SerializationError(format!(
+                    "JSONL line {}: expected id {}, found {}",
+                    number, db.next_id, entry.id
+                )));
+            }
+            if entry.vector.0.len() != db.config.dimensions {
+                return Err(KhadyotaError::DimensionMismatch {
+                    expected: db.config.dimensions,
+                    got: entry.vector.0.len(),
+                });
+            }
+
+            db.vectors.push(entry.vector.0);
+            if let Some(metadata) = entry.metadata {
+                db.metadata.insert(entry.id, metadata);
+            }
+            if let Some(attributes) = entry.attributes {
+                db.attributes.insert(entry.id, attributes);
+            }
+            if entry.deleted {
+                db.deleted.insert(entry.id);
+            }
+            db.next_id += 1;
+        }
+
+        if db.vectors.len() != header.count {
+            return Err(KhadyotaError::SerializationError(format!(
+                "JSONL header promises {} entries, found {}",
+                header.count,
+                db.vectors.len()
+            )));
+        }
+
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QuantizerKind;
+    use crate::insert_options::InsertOptions;
+    use std::time::Duration;
+
+    /// Values that widen or print badly through f64
+    const TRICKY: [f32; 8] = [0.1, 1.0 / 3.0, -0.0, f32::MIN_POSITIVE, 1e-45, f32::MAX, 16_777_217.0, 3.4028e-38];
+
+    fn build_db() -> VectorDB {
+        let config = Config {
+            dimensions: 8,
+            quantizer: QuantizerKind::None,
+            num_clusters: 2,
+            num_probe: 2,
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        db.insert(TRICKY.to_vec(), Some(serde_json::json!({"title": "tricky", "score": 0.1}))).unwrap();
+        for i in 1..20 {
+            let vector = (0..8).map(|j| ((i * 8 + j) as f32).sin() / 7.0).collect();
+            db.insert(vector, None).unwrap();
+        }
+        db.insert_opts(
+            vec![0.2; 8],
+            InsertOptions::new().weight(0.3).tenant("t").ttl(Duration::from_secs(60)),
+        )
+        .unwrap();
+        db
+    }
+
+    fn round_trip(db: &VectorDB, encoding: FloatEncoding) -> VectorDB {
+        let mut out = Vec::new();
+        assert_eq!(db.export_jsonl(&mut out, encoding).unwrap(), db.vectors.len() as u64);
+        VectorDB::import_jsonl(out.as_slice()).unwrap()
+    }
+
+    fn bits(db: &VectorDB) -> Vec<Vec<u32>> {
+        db.vectors.iter().map(|v| v.iter().map(|x| x.to_bits()).collect()).collect()
+    }
+
+    #[test]
+    fn test_round_trip_is_bit_exact() {
+        let db = build_db();
+
+        for encoding in [FloatEncoding::Decimal, FloatEncoding::Base64] {
+            let imported = round_trip(&db, encoding);
+            assert_eq!(bits(&imported), bits(&db), "{:?}", encoding);
+            assert_eq!(imported.checksum(), db.checksum(), "{:?}", encoding);
+            assert_eq!(imported.attributes(20), db.attributes(20));
+        }
+
+        // Tombstones survive as flagged lines
+        let mut db = db;
+        let mut changeset = crate::changeset::ChangeSet::new();
+        changeset.delete(3);
+        db.apply_changeset(changeset).unwrap();
+        let imported = round_trip(&db, FloatEncoding::Decimal);
+        assert_eq!(imported.checksum(), db.checksum());
+        assert!(imported.get(3).is_err());
+
+        // Export is deterministic
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        db.export_jsonl(&mut a, FloatEncoding::Decimal).unwrap();
+        round_trip(&db, FloatEncoding::Decimal).export_jsonl(&mut b, FloatEncoding::Decimal).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_decimal_is_exact_across_bit_patterns() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..200_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let value = f32::from_bits(state as u32);
+            if !value.is_finite() {
+                continue;
+            }
+
+            let vector = [value];
+            let json = serde_json::to_string(&EncodedVector { vector: &vector, encoding: FloatEncoding::Decimal }).unwrap();
+            let parsed: JsonVector = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.0[0].to_bits(), value.to_bits(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_non_finite_values() {
+        let mut db = VectorDB::new(Config {
+            dimensions: 3,
+            quantizer: QuantizerKind::None,
+            ..Default::default()
+        })
+        .unwrap();
+        db.insert(vec![f32::NAN, f32::INFINITY, f32::NEG_INFINITY], None).unwrap();
+
+        let err = db.export_jsonl(Vec::new(), FloatEncoding::Decimal).unwrap_err();
+        assert!(err.to_string().contains("export with FloatEncoding::Base64"), "{}", err);
+
+        let imported = round_trip(&db, FloatEncoding::Base64);
+        assert_eq!(bits(&imported), bits(&db));
+    }
+
+    #[test]
+    fn test_import_accepts_mixed_encodings_and_reports_bad_lines() {
+        let db = build_db();
+        let mut decimal = Vec::new();
+        let mut base64 = Vec::new();
+        db.export_jsonl(&mut decimal, FloatEncoding::Decimal).unwrap();
+        db.export_jsonl(&mut base64, FloatEncoding::Base64).unwrap();
+
+        let decimal = String::from_utf8(decimal).unwrap();
+        let base64 = String::from_utf8(base64).unwrap();
+        let mixed: Vec<&str> = decimal
+            .lines()
+            .zip(base64.lines())
+            .enumerate()
+            .map(|(i, (d, b))| if i % 2 == 0 { d } else { b })
+            .collect();
+        let imported = VectorDB::import_jsonl(mixed.join("\n").as_bytes()).unwrap();
+        assert_eq!(imported.checksum(), db.checksum());
+
+        let mut lines: Vec<&str> = decimal.lines().collect();
+        lines.swap(2, 3);
+        match VectorDB::import_jsonl(lines.join("\n").as_bytes()) {
+            Err(KhadyotaError::SerializationError(msg)) => assert_eq!(msg, "JSONL line 3: expected id 1, found 2"),
+            other => panic!("expected SerializationError, got {:?}", other.err()),
+        }
+
+        let truncated: Vec<&str> = decimal.lines().take(5).collect();
+        match VectorDB::import_jsonl(truncated.join("\n").as_bytes()) {
+            Err(KhadyotaError::SerializationError(msg)) => assert_eq!(msg, "JSONL header promises 21 entries, found 4"),
+            other => panic!("expected SerializationError, got {:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub mod jsonl;
+pub mod npy;
+
+#[cfg(feature = "arrow")]
+pub mod arrow_ipc;
+
+#[cfg(feature = "arrow")]
+pub use arrow_ipc::IpcExportParams;
+
+pub use jsonl::{EncodedVector, FloatEncoding, JsonVector};
+pub use npy::import_npy;
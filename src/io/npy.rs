@@ -0,0 +1,379 @@
+//! NumPy `.npy` files of vectors, with metadata alongside as JSON lines
+//!
+//! Only what embedding pipelines write is read: 2-D, C-order arrays of
+//! little-endian `f32` or `f64`, in format versions 1 to 3. Vectors are
+//! written as version 1 `<f4` arrays, which every NumPy reads.
+
+use crate::error::{KhadyotaError, Result};
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Header, magic included, is padded to a multiple of this
+const HEADER_ALIGN: usize = 64;
+
+/// Element type of an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dtype {
+    F32,
+    F64,
+}
+
+impl Dtype {
+    fn size(self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F64 => 8,
+        }
+    }
+}
+
+/// What the header says about the array that follows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    dtype: Dtype,
+    rows: usize,
+    columns: usize,
+}
+
+/// One line of the metadata file, for the vector in the same row
+#[derive(Serialize)]
+struct MetadataLineRef<'a> {
+    id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a Value>,
+}
+
+#[derive(Deserialize)]
+struct MetadataLine {
+    #[serde(default)]
+    metadata: Option<Value>,
+}
+
+fn npy_error(message: impl Into<String>) -> KhadyotaError {
+    KhadyotaError::SerializationError(format!("npy: {}", message.into()))
+}
+
+/// Read the magic, version and header dictionary
+fn read_header<R: Read>(reader: &mut R) -> Result<Header> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(npy_error("not a .npy file"));
+    }
+
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        major => return Err(npy_error(format!("unsupported format version {}.{}", major, preamble[7]))),
+    };
+
+    let mut text = vec![0u8; header_len];
+    reader.read_exact(&mut text)?;
+    let text = String::from_utf8(text).map_err(|_| npy_error("header is not UTF-8"))?;
+    parse_header(&text)
+}
+
+/// Parse the Python dict literal NumPy writes, e.g.
+/// `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 8), }`
+fn parse_header(text: &str) -> Result<Header> {
+    let mut dtype = None;
+    let mut fortran_order = None;
+    let mut shape = None;
+
+    let body = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or_else(|| npy_error(format!("header is not a dict: {}", text.trim())))?;
+    let mut rest = body.trim_start();
+    while !rest.is_empty() {
+        let (key, after_key) = quoted(rest).ok_or_else(|| npy_error(format!("bad header key in {}", body)))?;
+        let after_colon = after_key
+            .trim_start()
+            .strip_prefix(':')
+            .ok_or_else(|| npy_error(format!("missing ':' after '{}'", key)))?
+            .trim_start();
+
+        let after_value = match key {
+            "descr" => {
+                let (descr, after) = quoted(after_colon).ok_or_else(|| npy_error("descr is not a string"))?;
+                dtype = Some(match descr {
+                    "<f4" => Dtype::F32,
+                    "<f8" => Dtype::F64,
+                    other => {
+                        return Err(npy_error(format!(
+                            "dtype {} is not supported; save little-endian float32 or float64",
+                            other
+                        )));
+                    }
+                });
+                after
+            }
+            "fortran_order" => {
+                if let Some(after) = after_colon.strip_prefix("False") {
+                    fortran_order = Some(false);
+                    after
+                } else if let Some(after) = after_colon.strip_prefix("True") {
+                    fortran_order = Some(true);
+                    after
+                } else {
+                    return Err(npy_error("fortran_order is not a bool"));
+                }
+            }
+            "shape" => {
+                let inner = after_colon.strip_prefix('(').ok_or_else(|| npy_error("shape is not a tuple"))?;
+                let end = inner.find(')').ok_or_else(|| npy_error("shape is not a tuple"))?;
+                let dims = inner[..end]
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|dim| !dim.is_empty())
+                    .map(|dim| dim.parse::<usize>().map_err(|_| npy_error(format!("bad shape entry '{}'", dim))))
+                    .collect::<Result<Vec<_>>>()?;
+                shape = Some(dims);
+                &inner[end + 1..]
+            }
+            other => return Err(npy_error(format!("unexpected header key '{}'", other))),
+        };
+
+        rest = after_value.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    let dtype = dtype.ok_or_else(|| npy_error("header has no descr"))?;
+    if fortran_order.ok_or_else(|| npy_error("header has no fortran_order"))? {
+        return Err(npy_error("Fortran-order arrays are not supported; save with np.ascontiguousarray"));
+    }
+    match shape.ok_or_else(|| npy_error("header has no shape"))?.as_slice() {
+        &[rows, columns] => Ok(Header { dtype, rows, columns }),
+        other => Err(npy_error(format!("expected a 2-D array, found shape {:?}", other))),
+    }
+}
+
+/// Split a leading `'...'` or `"..."` string off `text`
+fn quoted(text: &str) -> Option<(&str, &str)> {
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let inner = &text[1..];
+    let end = inner.find(quote)?;
+    Some((&inner[..end], &inner[end + 1..]))
+}
+
+/// Read the rows that follow a header, narrowing `f64` to `f32`.
+/// `available` is the number of bytes left in the file; the shape is
+/// checked against it before anything is allocated.
+fn read_rows<R: Read>(reader: &mut R, header: Header, available: u64) -> Result<Vec<Vec<f32>>> {
+    if header.columns == 0 && header.rows > 0 {
+        return Err(npy_error(format!("header promises {} rows of no values", header.rows)));
+    }
+    let row_bytes = header
+        .columns
+        .checked_mul(header.dtype.size())
+        .ok_or_else(|| npy_error(format!("{} columns overflow a row", header.columns)))?;
+    let complete_rows = available / row_bytes.max(1) as u64;
+    if (header.rows as u64) > complete_rows {
+        return Err(npy_error(format!(
+            "header promises {} rows, file ends in row {}",
+            header.rows, complete_rows
+        )));
+    }
+
+    let mut row = vec![0u8; row_bytes];
+    let mut rows = Vec::with_capacity(header.rows);
+    for index in 0..header.rows {
+        reader.read_exact(&mut row).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => {
+                npy_error(format!("header promises {} rows, file ends in row {}", header.rows, index))
+            }
+            _ => e.into(),
+        })?;
+        rows.push(match header.dtype {
+            Dtype::F32 => row
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            Dtype::F64 => row
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32)
+                .collect(),
+        });
+    }
+    Ok(rows)
+}
+
+/// Write `rows` as a version 1 `<f4` array. Every row must have
+/// `columns` values.
+fn write_rows<'a, W: Write>(writer: &mut W, rows: usize, columns: usize, data: impl Iterator<Item = &'a [f32]>) -> Result<()> {
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", rows, columns);
+    // Magic, version and length take 10 bytes; the header ends in '\n'
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(HEADER_ALIGN) - unpadded));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for row in data {
+        debug_assert_eq!(row.len(), columns);
+        for value in row {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Read one metadata entry per line, for the vector in the same row
+fn read_metadata(path: &Path, rows: usize) -> Result<Vec<Option<Value>>> {
+    let mut metadata = Vec::with_capacity(rows);
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: MetadataLine = serde_json::from_str(&line)
+            .map_err(|e| KhadyotaError::SerializationError(format!("JSONL line {}: {}", index + 1, e)))?;
+        metadata.push(entry.metadata);
+    }
+    if metadata.len() != rows {
+        return Err(KhadyotaError::SerializationError(format!(
+            "{} metadata lines for {} vectors",
+            metadata.len(),
+            rows
+        )));
+    }
+    Ok(metadata)
+}
+
+/// Read a 2-D float `.npy` file, pairing each row with the same line of
+/// `metadata_jsonl` when one is given.
+///
+/// The metadata file has one `{"id": ..., "metadata": ...}` object per
+/// row, as [`VectorDB::export_npy`] writes it; `metadata` may be missing
+/// or null. `f64` arrays are narrowed to `f32`.
+pub fn import_npy(path: &Path, metadata_jsonl: Option<&Path>) -> Result<Vec<(Vec<f32>, Option<Value>)>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let header = read_header(&mut reader)?;
+    let available = len.saturating_sub(reader.stream_position()?);
+    let rows = read_rows(&mut reader, header, available)?;
+    let metadata = match metadata_jsonl {
+        Some(metadata_path) => read_metadata(metadata_path, rows.len())?,
+        None => vec![None; rows.len()],
+    };
+    Ok(rows.into_iter().zip(metadata).collect())
+}
+
+impl VectorDB {
+    /// Write live vectors to `vectors_path` as a 2-D `float32` `.npy`
+    /// array, and their metadata to `metadata_path` as JSON lines, one
+    /// `{"id": ..., "metadata": ...}` per row in the same order.
+    ///
+    /// Deleted ids are left out, so row numbers need not match ids; the
+    /// metadata lines carry them. Returns the number of rows written.
+    pub fn export_npy(&self, vectors_path: &Path, metadata_path: &Path) -> Result<u64> {
+        let ids: Vec<u32> = (0..self.next_id).filter(|id| !self.deleted.contains(id)).collect();
+        let rows: Vec<_> = ids
+            .iter()
+            .map(|&id| self.vectors.get(id).ok_or(KhadyotaError::VectorNotFound(id)))
+            .collect::<Result<_>>()?;
+
+        let mut writer = BufWriter::new(File::create(vectors_path)?);
+        write_rows(&mut writer, rows.len(), self.config.dimensions, rows.iter().map(|row| &**row))?;
+        writer.flush()?;
+
+        let mut writer = BufWriter::new(File::create(metadata_path)?);
+        for &id in &ids {
+            let line = MetadataLineRef { id, metadata: self.metadata_value(id) };
+            serde_json::to_writer(&mut writer, &line).map_err(|e| KhadyotaError::SerializationError(e.to_string()))?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        Ok(ids.len() as u64)
+    }
+
+    /// Insert the rows of a `.npy` file read by [`import_npy`], returning
+    /// their ids in order.
+    ///
+    /// An array whose width is not `dimensions` fails with
+    /// [`KhadyotaError::DimensionMismatch`] before anything is read past
+    /// the header. Otherwise as [`VectorDB::insert_batch`].
+    pub fn import_npy(&mut self, vectors_path: &Path, metadata_path: Option<&Path>) -> Result<Vec<u32>> {
+        let header = read_header(&mut BufReader::new(File::open(vectors_path)?))?;
+        if header.columns != self.config.dimensions {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: self.config.dimensions,
+                got: header.columns,
+            });
+        }
+        self.insert_batch(import_npy(vectors_path, metadata_path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_headers_numpy_writes() {
+        let header = parse_header("{'descr': '<f4', 'fortran_order': False, 'shape': (3, 8), }").unwrap();
+        assert_eq!(header, Header { dtype: Dtype::F32, rows: 3, columns: 8 });
+
+        // Key order and spacing vary across NumPy versions
+        let header = parse_header("{\"shape\":(0,2),\"fortran_order\":False,\"descr\":\"<f8\"}   \n").unwrap();
+        assert_eq!(header, Header { dtype: Dtype::F64, rows: 0, columns: 2 });
+
+        for (text, message) in [
+            ("{'descr': '>f4', 'fortran_order': False, 'shape': (3, 8), }", "dtype >f4"),
+            ("{'descr': '<i8', 'fortran_order': False, 'shape': (3, 8), }", "dtype <i8"),
+            ("{'descr': '<f4', 'fortran_order': True, 'shape': (3, 8), }", "Fortran-order"),
+            ("{'descr': '<f4', 'fortran_order': False, 'shape': (8,), }", "shape [8]"),
+            ("{'descr': '<f4', 'shape': (3, 8), }", "no fortran_order"),
+        ] {
+            let err = parse_header(text).unwrap_err();
+            assert!(err.to_string().contains(message), "{}: {}", text, err);
+        }
+    }
+
+    #[test]
+    fn test_written_headers_are_aligned() {
+        for (rows, columns) in [(0, 1), (3, 8), (123_456_789, 1536)] {
+            let mut out = Vec::new();
+            write_rows(&mut out, rows, columns, std::iter::empty()).unwrap();
+            assert_eq!(out.len() % HEADER_ALIGN, 0);
+            assert_eq!(out.last(), Some(&b'\n'));
+            let header = read_header(&mut out.as_slice()).unwrap();
+            assert_eq!(header, Header { dtype: Dtype::F32, rows, columns });
+        }
+    }
+
+    #[test]
+    fn test_shapes_past_the_data_fail_before_allocating() {
+        let header = |rows, columns| Header { dtype: Dtype::F64, rows, columns };
+        let data = [0u8; 64];
+        for (rows, columns, message) in [
+            (usize::MAX, 4, "file ends in row 2"),
+            (usize::MAX / 2, usize::MAX / 2, "columns overflow a row"),
+            (3, usize::MAX / 8, "file ends in row 0"),
+            (usize::MAX, 0, "rows of no values"),
+        ] {
+            let err = read_rows(&mut data.as_slice(), header(rows, columns), data.len() as u64).unwrap_err();
+            assert!(err.to_string().contains(message), "({}, {}): {}", rows, columns, err);
+        }
+        assert_eq!(read_rows(&mut data.as_slice(), header(2, 4), data.len() as u64).unwrap().len(), 2);
+    }
+}
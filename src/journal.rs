@@ -0,0 +1,373 @@
+use crate::changelog::Frame;
+use crate::config::{Config, EncodePolicy};
+use crate::error::{KhadyotaError, Result};
+use crate::persist::{PersistReport, identity};
+use crate::storage::replace::replace_file;
+use crate::vector_db::{Fnv1a, VectorDB};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Bytes ahead of each journal record: its length, then its checksum
+const RECORD_HEADER: usize = 4 + 8;
+
+/// When a durable database's journal is flushed to disk.
+///
+/// Records are written to the file as each change is made whatever the
+/// policy, so a process that dies keeps them; the policy decides how many
+/// a power loss can take with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Sync after every change, before the call making it returns
+    #[default]
+    EveryWrite,
+
+    /// Sync after every `n` changes
+    EveryN(usize),
+
+    /// Sync only on [`VectorDB::sync_journal`], a checkpoint, or an
+    /// insert with [`InsertOptions::durable`](crate::InsertOptions::durable)
+    Manual,
+}
+
+/// Append-only record of the changes made since the last snapshot of a
+/// database opened with [`VectorDB::open_durable`] (runtime only)
+pub(crate) struct Journal {
+    /// Snapshot the journal continues, as given to `open_durable`
+    snapshot: PathBuf,
+    file: Mutex<JournalFile>,
+}
+
+struct JournalFile {
+    file: File,
+    len: u64,
+    policy: SyncPolicy,
+    unsynced: usize,
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Journal").field("snapshot", &self.snapshot).finish_non_exhaustive()
+    }
+}
+
+impl Journal {
+    /// Write `frame` as one record, synced as the policy says
+    pub(crate) fn append(&self, frame: &Frame) -> Result<()> {
+        let payload = rmp_serde::to_vec_named(frame)?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| KhadyotaError::SerializationError(format!("journal record of {} bytes is too large", payload.len())))?;
+        let mut checksum = Fnv1a::default();
+        checksum.write_all(&payload)?;
+
+        let mut record = Vec::with_capacity(RECORD_HEADER + payload.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&checksum.0.to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        let mut journal = self.file.lock().unwrap();
+        if let Err(e) = journal.file.write_all(&record) {
+            // Drop a partly written record, so later ones can follow it
+            let _ = journal.file.set_len(journal.len);
+            return Err(e.into());
+        }
+        journal.len += record.len() as u64;
+        journal.unsynced += 1;
+        let due = match journal.policy {
+            SyncPolicy::EveryWrite => true,
+            SyncPolicy::EveryN(n) => journal.unsynced >= n,
+            SyncPolicy::Manual => false,
+        };
+        if due {
+            journal.sync()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sync(&self) -> Result<()> {
+        self.file.lock().unwrap().sync()
+    }
+
+    /// Start over from `header`, once a snapshot holds every record
+    fn reset(&self, header: &Frame) -> Result<()> {
+        let mut journal = self.file.lock().unwrap();
+        journal.file.set_len(0)?;
+        journal.len = 0;
+        drop(journal);
+        self.append(header)?;
+        self.sync()
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        identity(&self.snapshot) == identity(path)
+    }
+}
+
+impl JournalFile {
+    fn sync(&mut self) -> Result<()> {
+        if self.unsynced > 0 {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Journal kept beside the snapshot at `path` by [`VectorDB::open_durable`]
+pub fn journal_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+/// Split the next record off `bytes`, or `None` if it is cut short or
+/// fails its checksum
+fn next_record(bytes: &[u8]) -> Option<(Frame, usize)> {
+    let len = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap()) as usize;
+    let expected = u64::from_le_bytes(bytes.get(4..RECORD_HEADER)?.try_into().unwrap());
+    let payload = bytes.get(RECORD_HEADER..RECORD_HEADER + len)?;
+    let mut checksum = Fnv1a::default();
+    checksum.write_all(payload).ok()?;
+    if checksum.0 != expected {
+        return None;
+    }
+    Some((rmp_serde::from_slice(payload).ok()?, RECORD_HEADER + len))
+}
+
+impl VectorDB {
+    /// Open the database saved at `path`, or start one from `config` if
+    /// there is none, journaling every change from then on.
+    ///
+    /// Each write appends a length-prefixed, checksummed record of its
+    /// changes to `path` + `.journal` before applying them, and fails
+    /// with the database unchanged if the record cannot be written.
+    /// Records are synced as [`SyncPolicy::EveryWrite`] until
+    /// [`VectorDB::set_sync_policy`] says otherwise. Opening replays the journal over the snapshot, so a
+    /// process that dies between saves loses nothing it journaled;
+    /// [`VectorDB::checkpoint`] folds the journal into a new snapshot.
+    ///
+    /// A record cut short or failing its checksum, as a crash mid-write
+    /// leaves, ends the replay: it and anything after it are dropped from
+    /// the journal, and a warning names them in
+    /// [`VectorDB::load_warnings`]. A saved database must match `config`
+    /// as [`VectorDB::load_expecting`] checks.
+    pub fn open_durable(path: &Path, config: Config) -> Result<Self> {
+        let mut db = match Self::load_expecting(path, &config) {
+            Ok(db) => db,
+            Err(KhadyotaError::IoError(e)) if e.kind() == ErrorKind::NotFound => Self::new(config)?,
+            Err(e) => return Err(e),
+        };
+
+        let journal_path = journal_path(path);
+        let bytes = match fs::read(&journal_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut good = 0;
+        let mut applied = false;
+        while good < bytes.len() {
+            let Some((frame, len)) = next_record(&bytes[good..]) else {
+                db.load_warnings.push(format!(
+                    "journal {:?}: dropped {} bytes of a truncated or corrupt record at offset {}",
+                    journal_path,
+                    bytes.len() - good,
+                    good
+                ));
+                break;
+            };
+            applied |= db.apply_frame(frame)?;
+            good += len;
+        }
+        if applied {
+            db.changes_applied();
+            // Rows are journaled without their codes; encode them now
+            // unless the policy leaves inserts to the next build
+            if db.config.encode_policy != EncodePolicy::DeferredToBuild {
+                db.catch_up_index(usize::MAX);
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&journal_path)?;
+        file.set_len(good as u64)?;
+        let journal = Journal {
+            snapshot: path.to_path_buf(),
+            file: Mutex::new(JournalFile {
+                file,
+                len: good as u64,
+                policy: SyncPolicy::default(),
+                unsynced: 0,
+            }),
+        };
+        if good == 0 {
+            journal.reset(&db.changelog_header())?;
+        }
+        db.journal = Some(journal);
+        Ok(db)
+    }
+
+    /// Change when the journal is synced, syncing what it holds now.
+    /// Fails unless the database was opened with
+    /// [`VectorDB::open_durable`].
+    pub fn set_sync_policy(&mut self, policy: SyncPolicy) -> Result<()> {
+        let journal = self.durable_journal()?;
+        let mut file = journal.file.lock().unwrap();
+        file.sync()?;
+        file.policy = policy;
+        Ok(())
+    }
+
+    /// Sync every journaled change to disk, for [`SyncPolicy::Manual`]
+    /// and [`SyncPolicy::EveryN`]. Fails unless the database was opened
+    /// with [`VectorDB::open_durable`].
+    pub fn sync_journal(&self) -> Result<()> {
+        self.durable_journal()?.sync()
+    }
+
+    /// Save a new snapshot where [`VectorDB::open_durable`] opened the
+    /// database, then empty the journal. The snapshot replaces the old
+    /// one as [`VectorDB::save_incremental`] does, so a crash leaves the
+    /// old snapshot and the full journal, or the new snapshot and a
+    /// journal whose records it already holds.
+    pub fn checkpoint(&mut self) -> Result<PersistReport> {
+        let path = self.durable_journal()?.snapshot.clone();
+        self.save_incremental(&path)
+    }
+
+    /// Whether changes are journaled, by [`VectorDB::open_durable`]
+    pub fn is_durable(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    fn durable_journal(&self) -> Result<&Journal> {
+        self.journal.as_ref().ok_or_else(|| {
+            KhadyotaError::InvalidConfig("the database has no journal; open it with VectorDB::open_durable".to_string())
+        })
+    }
+
+    /// Whether a save to `path` writes the snapshot the journal continues
+    pub(crate) fn saves_snapshot(&self, path: &Path) -> bool {
+        self.journal.as_ref().is_some_and(|journal| journal.covers(path))
+    }
+
+    /// Write the snapshot the journal continues beside `path` and move it
+    /// over `path`, then empty the journal. Returns the bytes written.
+    pub(crate) fn write_snapshot(&self, path: &Path) -> Result<u64> {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".partial");
+        let tmp = PathBuf::from(name);
+        let written = self.write_state(&tmp, true).and_then(|bytes| {
+            replace_file(&tmp, path)?;
+            Ok(bytes)
+        });
+        match written {
+            Ok(bytes) => {
+                self.snapshot_saved(path)?;
+                Ok(bytes)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                Err(e)
+            }
+        }
+    }
+
+    /// Empty the journal if `path`, just saved, is its snapshot
+    pub(crate) fn snapshot_saved(&self, path: &Path) -> Result<()> {
+        match &self.journal {
+            Some(journal) if journal.covers(path) => journal.reset(&self.changelog_header()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changelog::ChangeRecord;
+    use crate::changeset::ChangeSet;
+    use crate::config::IndexKind;
+    use crate::harness::clustered_vectors;
+    use tempfile::TempDir;
+
+    fn framed(seq: u64) -> Vec<u8> {
+        let frame = Frame::Record(ChangeRecord { seq, ops: Vec::new() });
+        let payload = rmp_serde::to_vec_named(&frame).unwrap();
+        let mut checksum = Fnv1a::default();
+        checksum.write_all(&payload).unwrap();
+        [&(payload.len() as u32).to_le_bytes()[..], &checksum.0.to_le_bytes(), &payload].concat()
+    }
+
+    #[test]
+    fn test_records_cut_short_or_corrupt_are_rejected() {
+        let record = framed(7);
+        let (frame, len) = next_record(&record).unwrap();
+        assert!(matches!(frame, Frame::Record(ChangeRecord { seq: 7, .. })));
+        assert_eq!(len, record.len());
+
+        for cut in [0, 3, RECORD_HEADER, record.len() - 1] {
+            assert!(next_record(&record[..cut]).is_none());
+        }
+        let mut flipped = record.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(next_record(&flipped).is_none());
+    }
+
+    /// Point the journal at a read-only handle, so every append fails
+    fn break_journal(db: &VectorDB, path: &Path) {
+        let mut journal = db.journal.as_ref().unwrap().file.lock().unwrap();
+        journal.file = File::open(journal_path(path)).unwrap();
+    }
+
+    #[test]
+    fn test_writes_the_journal_refuses_change_nothing() {
+        let dir = TempDir::new().unwrap();
+        let vectors = clustered_vectors(300, 8, 4, 0.2, 3);
+        for index in [IndexKind::IVF, IndexKind::Flat] {
+            let path = dir.path().join(format!("{:?}.kdb", index));
+            let config = Config {
+                dimensions: 8,
+                pq_subvectors: 2,
+                num_clusters: 4,
+                num_probe: 4,
+                seed: Some(3),
+                index,
+                ..Default::default()
+            };
+            let mut db = VectorDB::open_durable(&path, config.clone()).unwrap();
+            db.insert_batch(vectors[..200].iter().map(|v| (v.clone(), None))).unwrap();
+            db.build_index().unwrap();
+            db.delete(3).unwrap();
+            let state = |db: &VectorDB| {
+                let index = db.index_status();
+                (db.len(), db.checksum(), db.seq, db.next_id, index.current, index.unencoded)
+            };
+            let before = state(&db);
+            let hits = |db: &VectorDB| {
+                let results = db.search(&vectors[250], 10).unwrap();
+                results.iter().map(|r| (r.id, r.distance.to_bits())).collect::<Vec<_>>()
+            };
+            let results = hits(&db);
+
+            break_journal(&db, &path);
+            let mut changes = ChangeSet::new();
+            changes.insert(vectors[201].clone(), None).update(7, vectors[202].clone(), None).delete(8);
+            assert!(db.insert(vectors[200].clone(), None).is_err());
+            assert!(db.insert_batch(vectors[200..250].iter().map(|v| (v.clone(), None))).is_err());
+            assert!(db.insert_flat(&vectors[200..210].concat(), Vec::new()).is_err());
+            assert!(db.apply_changeset(changes).is_err());
+            assert!(db.compact().is_err());
+            assert!(db.build_index().is_err());
+            assert!(db.get(200).is_err());
+            assert_eq!(state(&db), before);
+            assert_eq!(hits(&db), results);
+
+            drop(db);
+            let db = VectorDB::open_durable(&path, config).unwrap();
+            assert_eq!(state(&db).1, before.1);
+            assert_eq!(db.len(), 199);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Sub-buckets per power of two. Values below `SUB_BUCKETS` ns are exact;
+/// above that a bucket spans at most 1/128 of its value.
+const SUB_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+
+/// Enough buckets to cover every `u64` nanosecond value
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let exponent = 63 - nanos.leading_zeros();
+    let shift = exponent - SUB_BITS;
+    let mantissa = (nanos >> shift) as usize;
+    (shift as usize + 1) * SUB_BUCKETS + (mantissa - SUB_BUCKETS)
+}
+
+/// Largest value that falls into bucket `index`
+fn bucket_high(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let mantissa = (SUB_BUCKETS + index % SUB_BUCKETS) as u64;
+    let low = mantissa << shift;
+    low + ((1u64 << shift) - 1)
+}
+
+/// Log-bucketed latency histogram with fixed memory.
+///
+/// Like an HDR histogram, percentiles are accurate to within 1% of the
+/// value, whatever its magnitude. Histograms from several databases or
+/// shards can be combined with [`Histogram::merge`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum_nanos: u128,
+    min_nanos: u64,
+    max_nanos: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum_nanos: 0,
+            min_nanos: u64::MAX,
+            max_nanos: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        self.record_nanos(saturating_nanos(duration));
+    }
+
+    pub fn record_nanos(&mut self, nanos: u64) {
+        self.counts[bucket_index(nanos)] += 1;
+        self.count += 1;
+        self.sum_nanos += u128::from(nanos);
+        self.min_nanos = self.min_nanos.min(nanos);
+        self.max_nanos = self.max_nanos.max(nanos);
+    }
+
+    /// Add every sample recorded in `other`
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum_nanos += other.sum_nanos;
+        self.min_nanos = self.min_nanos.min(other.min_nanos);
+        self.max_nanos = self.max_nanos.max(other.max_nanos);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn min(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.min_nanos)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos)
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.sum_nanos / u128::from(self.count)) as u64)
+    }
+
+    /// Value at or below which `percentile`% of samples fall, e.g. `99.0`
+    /// for p99. Reports the top of the matching bucket, capped at the
+    /// largest sample; zero when empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * self.count as f64).ceil() as u64;
+        let rank = rank.max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let value = bucket_high(index).clamp(self.min_nanos, self.max_nanos);
+                return Duration::from_nanos(value);
+            }
+        }
+        self.max()
+    }
+
+    pub fn p50(&self) -> Duration {
+        self.percentile(50.0)
+    }
+
+    pub fn p95(&self) -> Duration {
+        self.percentile(95.0)
+    }
+
+    pub fn p99(&self) -> Duration {
+        self.percentile(99.0)
+    }
+}
+
+fn saturating_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Lock-free [`Histogram`] that parallel searches record into
+#[derive(Debug)]
+pub(crate) struct LatencyRecorder {
+    counts: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+    min_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl LatencyRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+            min_nanos: AtomicU64::new(u64::MAX),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, duration: Duration) {
+        let nanos = saturating_nanos(duration);
+        self.counts[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.min_nanos.fetch_min(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Copy out the samples so far. Samples racing with the copy may be
+    /// counted in some fields and not others.
+    pub(crate) fn snapshot(&self) -> Histogram {
+        Histogram {
+            counts: self.counts.iter().map(|c| c.load(Ordering::Relaxed)).collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum_nanos: u128::from(self.sum_nanos.load(Ordering::Relaxed)),
+            min_nanos: self.min_nanos.load(Ordering::Relaxed),
+            max_nanos: self.max_nanos.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for count in self.counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum_nanos.store(0, Ordering::Relaxed);
+        self.min_nanos.store(u64::MAX, Ordering::Relaxed);
+        self.max_nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use rand_distr::{Distribution, Exp};
+
+    #[test]
+    fn test_buckets_are_contiguous_and_tight() {
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+        for index in 1..BUCKETS {
+            let low = bucket_high(index - 1) + 1;
+            assert_eq!(bucket_index(low), index);
+            assert_eq!(bucket_index(bucket_high(index)), index);
+            assert!((bucket_high(index) - low) as f64 <= low as f64 / SUB_BUCKETS as f64);
+        }
+    }
+
+    #[test]
+    fn test_percentiles_match_exact_sort() {
+        // Exponential latencies around 200µs, as a stand-in for a real tail
+        let mut rng = StdRng::seed_from_u64(7);
+        let exp = Exp::new(1.0 / 200_000.0).unwrap();
+        let mut samples: Vec<u64> = (0..100_000).map(|_| exp.sample(&mut rng) as u64 + 1_000).collect();
+
+        let mut histogram = Histogram::new();
+        for &nanos in &samples {
+            histogram.record_nanos(nanos);
+        }
+        samples.sort_unstable();
+
+        for p in [1.0, 25.0, 50.0, 90.0, 95.0, 99.0, 99.9] {
+            let exact = samples[((p / 100.0) * samples.len() as f64).ceil() as usize - 1] as f64;
+            let approx = histogram.percentile(p).as_nanos() as f64;
+            assert!((approx - exact).abs() <= exact / 100.0, "p{}: {} vs {}", p, approx, exact);
+        }
+        assert_eq!(histogram.percentile(100.0).as_nanos() as u64, *samples.last().unwrap());
+        assert_eq!(histogram.min().as_nanos() as u64, samples[0]);
+        let mean = samples.iter().sum::<u64>() / samples.len() as u64;
+        assert_eq!(histogram.mean().as_nanos() as u64, mean);
+    }
+
+    #[test]
+    fn test_merge_equals_recording_together() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        let mut both = Histogram::new();
+        for nanos in (0..10_000u64).map(|i| i * i) {
+            if nanos % 3 == 0 {
+                a.record_nanos(nanos);
+            } else {
+                b.record_nanos(nanos);
+            }
+            both.record_nanos(nanos);
+        }
+        a.merge(&b);
+        assert_eq!(a, both);
+
+        // Merging an empty histogram changes nothing
+        a.merge(&Histogram::new());
+        assert_eq!(a, both);
+
+        a.reset();
+        assert_eq!(a, Histogram::new());
+        assert_eq!(a.p99(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_recorder_snapshot_and_reset() {
+        let recorder = LatencyRecorder::new();
+        let mut expected = Histogram::new();
+        for micros in [5, 80, 80, 1_200, 40_000] {
+            recorder.record(Duration::from_micros(micros));
+            expected.record(Duration::from_micros(micros));
+        }
+        assert_eq!(recorder.snapshot(), expected);
+
+        recorder.reset();
+        assert_eq!(recorder.snapshot(), Histogram::new());
+    }
+}
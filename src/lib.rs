@@ -1,13 +1,146 @@
+pub mod access;
+pub mod admission;
+pub mod assemble;
+pub mod batch;
+#[cfg(feature = "std-thread")]
+pub mod background;
+pub mod bulk;
+pub mod changelog;
+pub mod changeset;
+pub mod collections;
+pub mod compaction;
+pub mod compat;
 pub mod config;
+pub mod contract;
+pub mod envelope;
 pub mod error;
+pub mod estimate;
+pub mod evaluation;
+pub mod explain;
+pub mod external_ids;
+pub mod filter;
 pub mod types;
 pub mod storage;
 pub mod distance;
+pub mod encoding;
 pub mod quantization;
+pub mod fusion;
+pub mod harness;
 pub mod indexing;
+pub mod insert_options;
+pub mod health;
+pub mod io;
+pub mod journal;
+pub mod latency;
+pub mod maintenance;
+pub mod matryoshka;
+pub mod metadata;
+pub mod metadata_index;
+pub mod overview;
+pub mod parallel;
+pub mod persist;
+pub mod profile;
+pub mod progress;
+pub mod query_cache;
+pub mod range_search;
+pub mod rank;
+pub mod reader;
+pub mod residual;
+pub mod search_params;
+pub mod segments;
+pub mod select;
+pub mod shared;
+pub mod soak;
+pub mod stats;
 pub mod vector_db;
 
-pub use config::{Config, DistanceMetric};
+pub use access::{AccessStats, AccessTrackingConfig};
+pub use admission::{AdmissionConfig, AdmissionStats};
+pub use assemble::PrebuiltParts;
+#[cfg(feature = "std-thread")]
+pub use background::IndexBuildHandle;
+pub use changelog::{ChangeOp, ChangeRecord, IndexEntry};
+pub use changeset::{Change, ChangeReport, ChangeSet};
+pub use collections::DEFAULT_COLLECTION;
+pub use compaction::CompactionReport;
+pub use compat::{CompatibilityReport, Violation};
+pub use config::{
+    AUTO_CLUSTER_RANGE, AUTO_MAX_PQ_SUBVECTORS, CentroidAdaptation, Config, ConfigBuilder, DEFAULT_MAX_CLUSTER_IMBALANCE,
+    DEFAULT_MAX_METADATA_BYTES, DEFAULT_MAX_TRAINING_VECTORS, DEFAULT_PQ_BITS, DistanceMetric, EncodePolicy,
+    IndexKind, Precision, ProbeCompensation, QuantizerKind, TINY_DIMENSIONS, ToleranceConfig,
+};
+pub use contract::{Dtype, InputContract};
+pub use encoding::{AUTO_EAGER_MAX_RATE, IndexStatus};
+pub use envelope::{ENVELOPE_SCHEMA_VERSION, EnvelopeResult, ResultEnvelope};
 pub use error::{KhadyotaError, Result};
-pub use types::{SearchResult, VectorEntry};
-pub use vector_db::VectorDB;
\ No newline at end of file
+pub use estimate::BuildEstimate;
+pub use evaluation::{CandidateStats, EvalReport, ProbeTrial, TuneReport};
+pub use explain::{MissReason, PairExplanation};
+pub use external_ids::ExternalId;
+pub use filter::{CandidateFilter, NativeFilterFn};
+pub use fusion::{FusedResult, FusionStrategy};
+pub use health::{Health, HealthStatus};
+pub use indexing::RebalanceReport;
+pub use insert_options::InsertOptions;
+pub use io::FloatEncoding;
+pub use journal::SyncPolicy;
+pub use latency::Histogram;
+pub use metadata::{MemoryUsage, MetadataKeyStats};
+pub use metadata_index::{IndexNotUseful, KeyIndexReport, KeyIndexStrategy, MetadataIndexConfig, MetadataIndexReport};
+pub use maintenance::{MaintenanceBacklog, MaintenanceReport, MaintenanceTask, TaskProgress};
+#[cfg(feature = "std-thread")]
+pub use maintenance::{MaintenanceThread, spawn_maintenance};
+pub use overview::{ClusterOverview, ClusterSummary, OverviewOptions};
+pub use persist::{PersistReport, PersistSection, SectionPersist};
+pub use profile::{DataProfile, DimensionAnomaly, DimensionDrift, DimensionStats, ProfileDiff};
+pub use progress::{BuildEvent, PrintProgress, ProgressCallback, Silent};
+pub use quantization::{InitMethod, KMeansParams};
+pub use query_cache::{QueryCacheConfig, QueryCacheStats};
+pub use range_search::DEFAULT_RANGE_SLACK;
+pub use reader::SearchReader;
+pub use search_params::{SearchParams, SearchParamsBuilder, SearchPreset};
+pub use segments::{
+    FailurePolicy, MergePolicy, SearchOutcome, SegmentFailure, SegmentedDB, TieredMergePolicy,
+};
+#[cfg(feature = "std-thread")]
+pub use segments::MergeHandle;
+pub use shared::DEFAULT_GRACE_PERIOD;
+pub use stats::DbStats;
+pub use storage::ReplaceStrategy;
+pub use types::{EntryAttributes, SearchResult, VectorEntry};
+pub use vector_db::VectorDB;
+// Types that are shared across threads, most often a loaded `VectorDB`
+// behind an `Arc` or `Arc<RwLock<_>>`, must stay `Send + Sync`. None needs
+// an unsafe impl: interior caches and counters use `Mutex` and atomics. The
+// one exception is the user data of a native filter, whose thread safety
+// the caller vouches for.
+// Checked here so that a field which breaks this fails the build rather
+// than code downstream.
+const _: () = {
+    const fn send_sync<T: Send + Sync>() {}
+
+    send_sync::<VectorDB>();
+    send_sync::<SegmentedDB>();
+    send_sync::<SearchReader>();
+    send_sync::<storage::VectorStorage>();
+    send_sync::<storage::MmapVectors>();
+    send_sync::<storage::ColdVectors>();
+    send_sync::<storage::QuantizedVectors>();
+    send_sync::<indexing::IVFIndex>();
+    send_sync::<quantization::PQCodec>();
+    send_sync::<quantization::OPQCodec>();
+    send_sync::<quantization::Codebook>();
+    send_sync::<admission::AdmissionController>();
+    send_sync::<Config>();
+    send_sync::<SearchParams>();
+    send_sync::<CandidateFilter>();
+    send_sync::<Histogram>();
+    send_sync::<ChangeSet>();
+    send_sync::<PrebuiltParts>();
+    send_sync::<BuildEvent>();
+    send_sync::<ResultEnvelope>();
+    send_sync::<BuildEstimate>();
+    send_sync::<KhadyotaError>();
+    #[cfg(feature = "std-thread")]
+    send_sync::<MaintenanceThread>();
+};
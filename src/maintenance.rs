@@ -0,0 +1,506 @@
+use crate::persist::PersistSection;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "std-thread")]
+use std::sync::{Arc, RwLock, mpsc};
+#[cfg(feature = "std-thread")]
+use std::thread::JoinHandle;
+
+/// Rows encoded and assigned per index catch-up step
+const CATCH_UP_STEP: usize = 256;
+
+/// Cache entries visited per sweep step
+const SWEEP_STEP: usize = 256;
+
+/// Periodic work done by [`VectorDB::maintenance_tick`], most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MaintenanceTask {
+    /// Encode and assign rows written since the index went stale, with the
+    /// existing codebooks and centroids. Searches fail until it finishes.
+    IndexCatchUp,
+
+    /// Drop cached results from older generations or past their TTL
+    CacheSweep,
+}
+
+/// Outstanding maintenance work
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceBacklog {
+    /// Rows the stale index has yet to cover
+    pub index_lag: usize,
+
+    /// The index is stale and maintenance cannot catch it up: it was never
+    /// built, or went stale before this process loaded it. Only
+    /// `build_index()` clears this.
+    pub rebuild_required: bool,
+
+    /// Cached result lists that can no longer be served
+    pub stale_cache_entries: usize,
+}
+
+impl MaintenanceBacklog {
+    /// True when a tick would have nothing to do
+    pub fn is_empty(&self) -> bool {
+        self.index_lag == 0 && self.stale_cache_entries == 0
+    }
+
+    /// Task to run next, if any
+    fn next_task(&self) -> Option<MaintenanceTask> {
+        if self.index_lag > 0 {
+            Some(MaintenanceTask::IndexCatchUp)
+        } else if self.stale_cache_entries > 0 {
+            Some(MaintenanceTask::CacheSweep)
+        } else {
+            None
+        }
+    }
+}
+
+/// Work done by one task during a tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    pub task: MaintenanceTask,
+    pub steps: usize,
+
+    /// Rows indexed or cache entries dropped
+    pub units: usize,
+}
+
+/// Outcome of [`VectorDB::maintenance_tick`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    /// Tasks that ran, in the order they first ran
+    pub progress: Vec<TaskProgress>,
+    pub elapsed: Duration,
+
+    /// What is left after the tick
+    pub backlog: MaintenanceBacklog,
+}
+
+impl MaintenanceReport {
+    fn record(&mut self, task: MaintenanceTask, units: usize) {
+        match self.progress.iter_mut().find(|p| p.task == task) {
+            Some(progress) => {
+                progress.steps += 1;
+                progress.units += units;
+            }
+            None => self.progress.push(TaskProgress { task, steps: 1, units }),
+        }
+    }
+
+    /// Progress made on `task`, if it ran
+    pub fn task(&self, task: MaintenanceTask) -> Option<&TaskProgress> {
+        self.progress.iter().find(|p| p.task == task)
+    }
+}
+
+/// Rows written since the index was last current, so a stale index can be
+/// brought up to date in steps instead of retrained
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IndexLag {
+    /// Rows below this were covered by the index; rows from here on have
+    /// no codes and no list entries yet
+    pub(crate) indexed_rows: u32,
+
+    /// Rows written or deleted since, caught up in id order
+    pub(crate) pending: BTreeSet<u32>,
+}
+
+impl VectorDB {
+    /// Spend up to `budget` on pending maintenance, most urgent task first.
+    ///
+    /// Work is done in small steps and resumes where the last tick
+    /// stopped, so a tiny budget still makes progress: at least one step
+    /// runs per tick whenever there is a backlog. Meant to be called from
+    /// the host's own scheduler, or from [`spawn_maintenance`] with the
+    /// `std-thread` feature.
+    pub fn maintenance_tick(&mut self, budget: Duration) -> MaintenanceReport {
+        let start = Instant::now();
+        let mut report = MaintenanceReport::default();
+        let mut backlog = self.maintenance_backlog();
+
+        while let Some(task) = backlog.next_task() {
+            match task {
+                MaintenanceTask::IndexCatchUp => {
+                    let rows = self.catch_up_index(CATCH_UP_STEP);
+                    report.record(task, rows);
+                    backlog.index_lag = self.index_lag.as_ref().map_or(0, |lag| lag.pending.len());
+                }
+                MaintenanceTask::CacheSweep => {
+                    let (removed, finished) = self.sweep_cache(SWEEP_STEP);
+                    report.record(task, removed);
+                    backlog.stale_cache_entries = if finished {
+                        self.stale_cache_entries()
+                    } else {
+                        backlog.stale_cache_entries.saturating_sub(removed)
+                    };
+                }
+            }
+
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+
+        report.elapsed = start.elapsed();
+        report.backlog = self.maintenance_backlog();
+        report
+    }
+
+    /// Outstanding maintenance work. Counting stale cache entries visits
+    /// the whole cache, which is bounded by its `max_entries`.
+    pub fn maintenance_backlog(&self) -> MaintenanceBacklog {
+        let index_lag = self.index_lag.as_ref().map_or(0, |lag| lag.pending.len());
+
+        MaintenanceBacklog {
+            index_lag,
+            rebuild_required: !self.index_built && self.index_lag.is_none() && !self.vectors.is_empty(),
+            stale_cache_entries: self.stale_cache_entries(),
+        }
+    }
+
+    /// Record that `ids` were written without updating the index. When the
+    /// index was current until now, rows below `indexed_rows` are the ones
+    /// it covers.
+    pub(crate) fn mark_index_stale(&mut self, indexed_rows: u32, ids: impl IntoIterator<Item = u32>) {
+        if self.index_built && self.has_index() {
+            self.index_lag = Some(IndexLag {
+                indexed_rows,
+                pending: BTreeSet::new(),
+            });
+        }
+        self.index_built = false;
+        self.mark_dirty(&[PersistSection::Metadata]);
+
+        if let Some(lag) = &mut self.index_lag {
+            lag.pending.extend(ids);
+        }
+    }
+
+    /// Bring up to `limit` pending rows into the index, lowest ids first.
+    /// Marks the index built once nothing is pending. Returns the rows
+    /// processed.
+    pub(crate) fn catch_up_index(&mut self, limit: usize) -> usize {
+        let Some(lag) = &mut self.index_lag else {
+            return 0;
+        };
+        let ids: Vec<u32> = lag.pending.iter().take(limit).copied().collect();
+        for id in &ids {
+            lag.pending.remove(id);
+        }
+        let indexed_rows = lag.indexed_rows;
+        // Pending ids are taken in order and every new row is pending, so
+        // the new rows here continue on from `indexed_rows`
+        let (listed, new): (Vec<u32>, Vec<u32>) = ids.iter().partition(|&&id| id < indexed_rows);
+        self.mark_dirty(&[PersistSection::Codes, PersistSection::Index, PersistSection::Metadata]);
+
+        // Residual codes wait for the rows' clusters
+        let residual = self.residual_codes();
+        if let Some(quantized) = self.quantized.as_mut().filter(|_| !residual) {
+            for &id in &listed {
+                quantized.replace(id, &self.vectors.get(id).unwrap());
+            }
+            let rows: Vec<_> = new.iter().map(|&id| self.vectors.get(id).unwrap()).collect();
+            quantized.add_batch_from(rows.iter().map(|row| &**row));
+        }
+        if let Some(ivf) = &mut self.ivf_index
+            && !listed.is_empty()
+        {
+            ivf.remove_ids(&listed.iter().copied().collect());
+        }
+        if let Some(flat) = &mut self.flat_index {
+            for &id in &ids {
+                flat.put(id, &self.vectors.get(id).unwrap());
+            }
+        }
+        let live = ids.iter().copied().filter(|id| !self.deleted.contains(id)).collect();
+        self.assign_to_clusters(live);
+        if residual {
+            self.encode_residuals(listed.iter().chain(&new).copied());
+        }
+
+        let lag = self.index_lag.as_mut().unwrap();
+        if let Some(&last) = new.last() {
+            lag.indexed_rows = last + 1;
+        }
+        if lag.pending.is_empty() {
+            self.index_lag = None;
+            self.index_built = true;
+        }
+        ids.len()
+    }
+
+    fn stale_cache_entries(&self) -> usize {
+        self.query_cache
+            .as_ref()
+            .map_or(0, |cache| cache.stale_entries(self.generation))
+    }
+
+    /// Sweep the next `limit` cache entries. Returns the entries dropped
+    /// and whether the sweep reached the end of the cache.
+    fn sweep_cache(&self, limit: usize) -> (usize, bool) {
+        match &self.query_cache {
+            Some(cache) => cache.sweep_stale(self.generation, limit),
+            None => (0, true),
+        }
+    }
+}
+
+/// Background thread started by [`spawn_maintenance`]; stopped and joined
+/// when dropped
+#[cfg(feature = "std-thread")]
+pub struct MaintenanceThread {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "std-thread")]
+impl MaintenanceThread {
+    /// Stop the thread, waiting for a running tick to finish
+    pub fn stop(self) {}
+}
+
+#[cfg(feature = "std-thread")]
+impl Drop for MaintenanceThread {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Run [`VectorDB::maintenance_tick`] with `budget` every `interval` on a
+/// helper thread. Each tick holds the write lock for about `budget`.
+#[cfg(feature = "std-thread")]
+pub fn spawn_maintenance(db: Arc<RwLock<VectorDB>>, interval: Duration, budget: Duration) -> MaintenanceThread {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let handle = std::thread::spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            let mut db = db.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if !db.maintenance_backlog().is_empty() {
+                db.maintenance_tick(budget);
+            }
+        }
+    });
+
+    MaintenanceThread {
+        stop: Some(stop),
+        handle: Some(handle),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, DistanceMetric, QuantizerKind};
+    use crate::query_cache::QueryCacheConfig;
+    use crate::search_params::SearchParams;
+
+    const DIMS: usize = 16;
+
+    fn vector(i: u32) -> Vec<f32> {
+        (0..DIMS as u32).map(|j| ((i * 17 + j * 5) as f32 * 0.37).sin()).collect()
+    }
+
+    fn build_db(use_pq: bool) -> VectorDB {
+        let config = Config {
+            dimensions: DIMS,
+            metric: DistanceMetric::Euclidean,
+            quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+            pq_subvectors: 4,
+            num_clusters: 8,
+            num_probe: 8,
+            seed: Some(6),
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..1_000 {
+            db.insert(vector(i), None).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    /// Tick with no budget until the backlog is gone, collecting it after
+    /// every tick
+    fn drain(db: &mut VectorDB) -> Vec<MaintenanceBacklog> {
+        let mut backlogs = Vec::new();
+        while !db.maintenance_backlog().is_empty() {
+            backlogs.push(db.maintenance_tick(Duration::ZERO).backlog);
+            assert!(backlogs.len() < 1_000, "maintenance made no progress");
+        }
+        backlogs
+    }
+
+    #[test]
+    fn test_catch_up_matches_maintained_writes() {
+        for use_pq in [false, true] {
+            let mut maintained = build_db(use_pq);
+            let mut caught_up = build_db(use_pq);
+
+            // Plain inserts, an upsert and a delete leave the index stale
+            for i in 1_000..1_600 {
+                maintained.add_to_index(vector(i), None).unwrap();
+                caught_up.insert(vector(i), None).unwrap();
+            }
+            maintained.update(10, vector(5_000), None).unwrap();
+            caught_up.insert_opts(vector(5_000), crate::insert_options::InsertOptions {
+                id: Some(10),
+                upsert: true,
+                ..Default::default()
+            }).unwrap();
+            maintained.delete(20).unwrap();
+            caught_up.delete(20).unwrap();
+
+            // Meanwhile the pending rows are scanned exactly
+            let params = if use_pq {
+                SearchParams::builder().rerank(40).build()
+            } else {
+                SearchParams::default()
+            };
+            assert_eq!(caught_up.search_with_params(&vector(1_234), 1, &params).unwrap()[0].id, 1_234);
+            assert_eq!(caught_up.search_with_params(&vector(5_000), 1, &params).unwrap()[0].id, 10);
+            assert_eq!(caught_up.index_status().unencoded, 601);
+            let backlog = caught_up.maintenance_backlog();
+            assert_eq!(backlog.index_lag, 602);
+            assert!(!backlog.rebuild_required);
+
+            let lags: Vec<usize> = drain(&mut caught_up).iter().map(|b| b.index_lag).collect();
+            assert!(lags.len() > 1, "a zero budget should still take several ticks");
+            assert!(lags.windows(2).all(|w| w[1] < w[0]));
+            assert_eq!(*lags.last().unwrap(), 0);
+
+            // Same codes and inverted lists as maintaining every write
+            assert_eq!(caught_up.check_compatibility().violations, []);
+            let (ivf_a, ivf_b) = (maintained.ivf_index.as_ref().unwrap(), caught_up.ivf_index.as_ref().unwrap());
+            for cluster in 0..ivf_a.num_clusters() {
+                let mut a = ivf_a.inverted_list(cluster).to_vec();
+                let mut b = ivf_b.inverted_list(cluster).to_vec();
+                a.sort_unstable();
+                b.sort_unstable();
+                assert_eq!(a, b);
+            }
+            if use_pq {
+                let (a, b) = (maintained.quantized.as_ref().unwrap(), caught_up.quantized.as_ref().unwrap());
+                assert!(a.iter_codes().eq(b.iter_codes()));
+            }
+
+            assert_eq!(caught_up.search_with_params(&vector(1_234), 1, &params).unwrap()[0].id, 1_234);
+            assert_eq!(caught_up.search_with_params(&vector(5_000), 1, &params).unwrap()[0].id, 10);
+        }
+    }
+
+    #[test]
+    fn test_writes_during_catch_up() {
+        let mut db = build_db(false);
+        for i in 1_000..1_600 {
+            db.insert(vector(i), None).unwrap();
+        }
+        db.maintenance_tick(Duration::ZERO);
+
+        // Rewrite a row that was already caught up, and add more
+        db.insert_opts(vector(9_000), crate::insert_options::InsertOptions {
+            id: Some(1_001),
+            upsert: true,
+            ..Default::default()
+        }).unwrap();
+        db.insert(vector(1_600), None).unwrap();
+        drain(&mut db);
+
+        let ivf = db.ivf_index.as_ref().unwrap();
+        let listed: usize = (0..ivf.num_clusters()).map(|c| ivf.inverted_list(c).len()).sum();
+        assert_eq!(listed, 1_601);
+        assert_eq!(db.search(&vector(9_000), 1).unwrap()[0].id, 1_001);
+        assert_eq!(db.search(&vector(1_600), 1).unwrap()[0].id, 1_600);
+    }
+
+    #[test]
+    fn test_stale_cache_entries_are_swept() {
+        let mut db = build_db(false);
+        db.set_query_cache(Some(QueryCacheConfig::default()));
+        for i in 0..1_000 {
+            db.search(&vector(i), 3).unwrap();
+        }
+        assert_eq!(db.maintenance_backlog().stale_cache_entries, 0);
+
+        // A maintained write bumps the generation without staling the index
+        db.delete(3).unwrap();
+        assert_eq!(db.maintenance_backlog().stale_cache_entries, 1_000);
+        for i in 0..100 {
+            db.search(&vector(i), 3).unwrap();
+        }
+
+        let stale: Vec<usize> = drain(&mut db).iter().map(|b| b.stale_cache_entries).collect();
+        assert!(stale.windows(2).all(|w| w[1] < w[0]));
+        assert_eq!(*stale.last().unwrap(), 0);
+
+        // Fresh entries survive the sweep and are still served
+        let stats = db.query_cache_stats().unwrap();
+        assert_eq!(stats.entries, 100);
+        db.search(&vector(50), 3).unwrap();
+        assert_eq!(db.query_cache_stats().unwrap().hits, stats.hits + 1);
+    }
+
+    #[test]
+    fn test_priorities_and_budget() {
+        let mut db = build_db(false);
+        db.set_query_cache(Some(QueryCacheConfig::default()));
+        for i in 0..500 {
+            db.search(&vector(i), 3).unwrap();
+        }
+        for i in 1_000..2_000 {
+            db.insert(vector(i), None).unwrap();
+        }
+
+        // The blocked index comes before the cache
+        let report = db.maintenance_tick(Duration::ZERO);
+        assert_eq!(report.progress.len(), 1);
+        assert_eq!(report.task(MaintenanceTask::IndexCatchUp).unwrap().units, CATCH_UP_STEP);
+        assert_eq!(report.backlog.stale_cache_entries, 500);
+
+        let report = db.maintenance_tick(Duration::from_secs(60));
+        assert!(report.backlog.is_empty());
+        assert_eq!(report.task(MaintenanceTask::IndexCatchUp).unwrap().units, 1_000 - CATCH_UP_STEP);
+        assert_eq!(report.task(MaintenanceTask::CacheSweep).unwrap().units, 500);
+        assert!(db.maintenance_tick(Duration::from_secs(60)).progress.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_staleness_needs_rebuild() {
+        let mut db = VectorDB::new(Config {
+            dimensions: DIMS,
+            ..Default::default()
+        })
+        .unwrap();
+        db.insert(vector(0), None).unwrap();
+
+        let report = db.maintenance_tick(Duration::from_secs(1));
+        assert!(report.progress.is_empty());
+        assert!(report.backlog.rebuild_required);
+        assert!(report.backlog.is_empty());
+    }
+
+    #[cfg(feature = "std-thread")]
+    #[test]
+    fn test_background_thread() {
+        let db = Arc::new(RwLock::new(build_db(false)));
+        let thread = spawn_maintenance(db.clone(), Duration::from_millis(1), Duration::from_millis(1));
+
+        for i in 1_000..1_500 {
+            db.write().unwrap().insert(vector(i), None).unwrap();
+        }
+        let start = Instant::now();
+        while !db.read().unwrap().maintenance_backlog().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(30));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        thread.stop();
+
+        assert_eq!(db.read().unwrap().search(&vector(1_234), 1).unwrap()[0].id, 1_234);
+    }
+}
@@ -0,0 +1,65 @@
+//! Searches over Matryoshka prefixes: queries of one of the configured
+//! [`matryoshka_dims`](crate::config::Config::matryoshka_dims), scored
+//! against the same leading components of every stored vector.
+//!
+//! Prefix queries run through the usual pipeline. Probing compares them
+//! with centroid prefixes, exact scoring slices each row, and PQ and SQ8
+//! tables cover only the leading subquantizers, which the config keeps
+//! aligned with every prefix. Cosine tables are the exception: codes
+//! encode the row normalized over its full width, so a prefix's cosine
+//! cannot be read from them and its candidates are scored exactly.
+
+use crate::config::DistanceMetric;
+use crate::error::{KhadyotaError, Result};
+use crate::search_params::SearchParams;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+
+impl VectorDB {
+    /// Fail unless `query` is full width, for operations that do not take
+    /// prefixes
+    pub(crate) fn require_full_width(&self, query: &[f32]) -> Result<()> {
+        if query.len() != self.config.dimensions {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: self.config.dimensions,
+                got: query.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the quantizer's tables can score `query`: any full-width
+    /// query, or a prefix under a metric whose terms add up per component
+    /// of codes that are not rotated
+    pub(crate) fn tables_score(&self, query: &[f32]) -> bool {
+        self.quantized.as_ref().is_some_and(|quantized| {
+            query.len() == self.config.dimensions
+                || (quantized.codec().recorded_metric() != Some(DistanceMetric::Cosine)
+                    && quantized.codec().scores_prefixes())
+        })
+    }
+
+    /// Search on the first `dims` components of a full `query`, then
+    /// re-score the best `rerank` (at least `k`) with all of it
+    pub(crate) fn search_refined(
+        &self,
+        query: &[f32],
+        dims: usize,
+        k: usize,
+        params: &SearchParams,
+    ) -> Result<Vec<SearchResult>> {
+        let coarse = SearchParams {
+            rerank: None,
+            include_metadata: false,
+            coarse_dims: None,
+            ..params.clone()
+        };
+        let shortlist = params.rerank.unwrap_or(k).max(k);
+        let ids = self
+            .search_validated(&query[..dims], shortlist, &coarse)?
+            .into_iter()
+            .map(|result| result.id)
+            .collect();
+        Ok(self.top_results(self.score_exact(query, ids, params)?, k, params))
+    }
+}
@@ -0,0 +1,251 @@
+use crate::config::Config;
+use crate::error::{KhadyotaError, Result};
+use crate::persist::PersistSection;
+use crate::storage::{QuantizedVectors, Section};
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+/// Name of the save-file section holding metadata kept apart under
+/// [`Config::external_metadata`]
+pub(crate) const SECTION_NAME: &str = "external_metadata";
+
+/// Size of `value` as compact JSON, the measure
+/// [`Config::max_metadata_bytes`] is checked against. Counted as it is
+/// encoded, without building the string.
+pub fn metadata_size(value: &Value) -> usize {
+    let mut counter = ByteCounter(0);
+    serde_json::to_writer(&mut counter, value).expect("counting bytes cannot fail");
+    counter.0
+}
+
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reject `value` if it is over `config`'s limit and cannot be kept
+/// externally
+pub(crate) fn check_size(config: &Config, value: &Value) -> Result<()> {
+    if config.external_metadata {
+        return Ok(());
+    }
+    let size = metadata_size(value);
+    if size > config.max_metadata_bytes {
+        return Err(KhadyotaError::MetadataTooLarge {
+            size,
+            limit: config.max_metadata_bytes,
+        });
+    }
+    Ok(())
+}
+
+/// Approximate memory held by a database's data, from
+/// [`VectorDB::memory_usage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Original vectors held in memory. Rows spilled to disk or mapped
+    /// from a shared directory are not counted.
+    pub vector_bytes: usize,
+
+    /// PQ codes
+    pub code_bytes: usize,
+
+    /// Metadata returned with results, as compact JSON
+    pub metadata_bytes: usize,
+
+    /// Values over the size limit, kept apart under
+    /// [`Config::external_metadata`]
+    pub external_metadata_bytes: usize,
+
+    /// The entry with the largest metadata value, and that value's size
+    pub largest_metadata: Option<(u32, usize)>,
+}
+
+impl MemoryUsage {
+    pub fn total_bytes(&self) -> usize {
+        self.vector_bytes + self.code_bytes + self.metadata_bytes + self.external_metadata_bytes
+    }
+}
+
+/// How much metadata one top-level key accounts for, from
+/// [`VectorDB::metadata_keys`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataKeyStats {
+    pub key: String,
+
+    /// Live entries whose metadata has the key
+    pub entries: usize,
+
+    /// The key's values summed, as compact JSON
+    pub bytes: usize,
+
+    /// The entry with the largest value for the key, and that value's size
+    pub largest: (u32, usize),
+}
+
+impl VectorDB {
+    /// Memory held by vectors, PQ codes and metadata.
+    ///
+    /// Metadata is measured by encoding every value, so this takes time in
+    /// proportion to the metadata stored; it is a diagnostic, not
+    /// something to call per query.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let vector_bytes = match self.vectors.cold() {
+            Some(_) => 0,
+            None => self.vectors.heap_bytes(),
+        };
+        let code_bytes = self
+            .quantized
+            .as_ref()
+            .map_or(0, QuantizedVectors::code_bytes);
+
+        let mut usage = MemoryUsage {
+            vector_bytes,
+            code_bytes,
+            ..Default::default()
+        };
+        for (external, values) in [(false, &self.metadata), (true, &self.external_metadata)] {
+            for (&id, value) in values {
+                let size = metadata_size(value);
+                if external {
+                    usage.external_metadata_bytes += size;
+                } else {
+                    usage.metadata_bytes += size;
+                }
+                if usage.largest_metadata.is_none_or(|(_, largest)| size > largest) {
+                    usage.largest_metadata = Some((id, size));
+                }
+            }
+        }
+        usage
+    }
+
+    /// Metadata size per top-level key, largest total first, to find which
+    /// fields and entries account for the bulk of it. External values are
+    /// included; metadata that is not a JSON object has no keys and only
+    /// shows in [`VectorDB::memory_usage`].
+    pub fn metadata_keys(&self) -> Vec<MetadataKeyStats> {
+        let mut keys: HashMap<&str, MetadataKeyStats> = HashMap::new();
+        let objects = self
+            .metadata
+            .iter()
+            .chain(&self.external_metadata)
+            .filter_map(|(&id, value)| Some((id, value.as_object()?)));
+        for (id, object) in objects {
+            for (key, value) in object {
+                let size = metadata_size(value);
+                let stats = keys.entry(key).or_insert_with(|| MetadataKeyStats {
+                    key: key.clone(),
+                    entries: 0,
+                    bytes: 0,
+                    largest: (id, size),
+                });
+                stats.entries += 1;
+                stats.bytes += size;
+                if size > stats.largest.1 {
+                    stats.largest = (id, size);
+                }
+            }
+        }
+
+        let mut keys: Vec<MetadataKeyStats> = keys.into_values().collect();
+        keys.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+        keys
+    }
+
+    /// Metadata of `id` that was kept apart for being over
+    /// [`Config::max_metadata_bytes`]. Such values are not in
+    /// [`VectorDB::get`] or search results unless a search asks for them.
+    pub fn external_metadata(&self, id: u32) -> Option<&Value> {
+        self.external_metadata.get(&id)
+    }
+
+    /// Metadata of a live `id`, including metadata kept apart under
+    /// [`Config::external_metadata`]
+    pub fn get_metadata(&self, id: u32) -> Option<&Value> {
+        self.metadata_value(id).filter(|_| !self.deleted.contains(&id))
+    }
+
+    /// Metadata of `id` wherever it is kept
+    pub(crate) fn metadata_value(&self, id: u32) -> Option<&Value> {
+        self.metadata.get(&id).or_else(|| self.external_metadata.get(&id))
+    }
+
+    /// Replace the metadata of `id`, keeping it apart if it is over the
+    /// limit. Callers have already checked the limit.
+    pub(crate) fn set_metadata(&mut self, id: u32, metadata: Option<Value>) {
+        self.mark_dirty(&[PersistSection::Metadata]);
+        self.metadata.remove(&id);
+        if self.external_metadata.remove(&id).is_some() {
+            self.mark_dirty(&[PersistSection::AppState]);
+        }
+        let Some(metadata) = metadata else {
+            return;
+        };
+        if self.config.external_metadata && metadata_size(&metadata) > self.config.max_metadata_bytes {
+            self.mark_dirty(&[PersistSection::AppState]);
+            self.external_metadata.insert(id, metadata);
+        } else {
+            self.metadata.insert(id, metadata);
+        }
+    }
+
+    /// External metadata as a save-file section, if there is any. It is
+    /// required: a build that dropped it would lose the values.
+    pub(crate) fn external_metadata_section(&self) -> Result<Option<Section>> {
+        if self.external_metadata.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Section::new(SECTION_NAME, rmp_serde::to_vec(&self.external_metadata)?)))
+    }
+}
+
+/// Restore the values saved by [`VectorDB::external_metadata_section`]
+pub(crate) fn external_metadata_from_section(section: &Section) -> Result<BTreeMap<u32, Value>> {
+    Ok(rmp_serde::from_slice(&section.payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_metadata_size_is_compact_json_length() {
+        for value in [json!(null), json!("é"), json!({"a": [1, 2.5, true], "b": {"c": "x y"}})] {
+            assert_eq!(metadata_size(&value), serde_json::to_string(&value).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn test_set_metadata_moves_values_between_stores() {
+        let mut db = VectorDB::new(Config {
+            dimensions: 4,
+            max_metadata_bytes: 16,
+            external_metadata: true,
+            ..Config::for_dimensions(4)
+        })
+        .unwrap();
+        let id = db.insert(vec![1.0; 4], Some(json!({"blob": "x".repeat(32)}))).unwrap();
+        assert!(db.metadata.is_empty());
+        assert!(db.external_metadata(id).is_some());
+
+        db.set_metadata(id, Some(json!({"small": 1})));
+        assert!(db.external_metadata.is_empty());
+        assert_eq!(db.metadata_value(id), Some(&json!({"small": 1})));
+
+        db.set_metadata(id, None);
+        assert!(db.metadata_value(id).is_none());
+    }
+}
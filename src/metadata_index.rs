@@ -0,0 +1,352 @@
+//! Indexes over top-level metadata keys, built lazily for the equality
+//! filters of [`VectorDB::metadata_filter`].
+//!
+//! Each key gets the structure its cardinality suits: a bitset per value
+//! for keys with few values, a sorted id list per value where that is
+//! smaller, or none at all for keys with nearly one value per entry,
+//! whose lists would hold one id apiece. A key that is not indexed, for
+//! that reason or for not fitting the memory budget, is reported as
+//! [`IndexNotUseful`], and filters on it scan the metadata instead.
+//! Numeric range filters, from [`VectorDB::metadata_range_filter`], always
+//! scan.
+
+use crate::error::{KhadyotaError, Result};
+use crate::filter::CandidateFilter;
+use crate::vector_db::{Fnv1a, VectorDB};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::mem::size_of;
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex};
+
+/// Limits on the metadata index, under
+/// [`Config::metadata_index`](crate::config::Config::metadata_index)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataIndexConfig {
+    /// Memory every indexed key may take together, as estimated when it
+    /// is built. A key that does not fit what is left is not indexed.
+    pub budget_bytes: usize,
+
+    /// Keys with at most this many distinct values may get a bitset per
+    /// value, where that is smaller than sorted id lists
+    pub bitset_max_values: usize,
+
+    /// Keys whose distinct values outnumber this fraction of the entries
+    /// holding them are not indexed: most lists would hold one id
+    pub max_distinct_fraction: f32,
+
+    /// Keys indexed whatever their cardinality, budget permitting
+    pub always_index: BTreeSet<String>,
+}
+
+impl Default for MetadataIndexConfig {
+    fn default() -> Self {
+        Self {
+            budget_bytes: 64 * 1024 * 1024,
+            bitset_max_values: 64,
+            max_distinct_fraction: 0.5,
+            always_index: BTreeSet::new(),
+        }
+    }
+}
+
+impl MetadataIndexConfig {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !(self.max_distinct_fraction > 0.0 && self.max_distinct_fraction <= 1.0) {
+            return Err(KhadyotaError::InvalidConfig(format!(
+                "metadata_index max_distinct_fraction must be in (0, 1], got {}",
+                self.max_distinct_fraction
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How one key is indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyIndexStrategy {
+    /// A bitset over every id, per value
+    Bitset,
+
+    /// Sorted ids, per value, looked up by hash
+    SortedIds,
+
+    /// Not indexed; filters scan the metadata
+    NotUseful(IndexNotUseful),
+}
+
+/// Why a key is not indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexNotUseful {
+    /// Too many distinct values for the entries holding the key; lift
+    /// with [`MetadataIndexConfig::always_index`]
+    HighCardinality { distinct_values: usize, entries: usize },
+
+    /// The cheaper structure needs more than the budget has left
+    OverBudget { bytes: usize, available: usize },
+}
+
+/// One key's entry in [`MetadataIndexReport`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyIndexReport {
+    pub key: String,
+    pub strategy: KeyIndexStrategy,
+
+    /// Live entries whose metadata has the key
+    pub entries: usize,
+
+    pub distinct_values: usize,
+
+    /// Memory held by the key's index; 0 when it is not indexed
+    pub bytes: usize,
+}
+
+/// Keys planned since the last change, from
+/// [`VectorDB::metadata_index_report`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataIndexReport {
+    pub budget_bytes: usize,
+
+    /// Memory held by every indexed key
+    pub used_bytes: usize,
+
+    /// In key order
+    pub keys: Vec<KeyIndexReport>,
+}
+
+/// Ids holding each value of one key, keyed by the value as compact JSON
+#[derive(Debug)]
+enum Postings {
+    Bitset(HashMap<String, Arc<[u64]>>),
+    SortedIds(HashMap<String, Arc<[u32]>>),
+}
+
+#[derive(Debug)]
+struct KeyIndex {
+    /// Database generation it was built at; stale once that moves on
+    generation: u64,
+    report: KeyIndexReport,
+    postings: Option<Postings>,
+}
+
+/// Per-key indexes built so far (runtime only)
+#[derive(Debug, Default)]
+pub(crate) struct MetadataIndex {
+    keys: Mutex<HashMap<String, KeyIndex>>,
+}
+
+/// Memory a key's postings hold in each structure: the map's entries,
+/// with the values as strings, and the bitsets or id lists
+fn structure_bytes(entries: usize, distinct: usize, value_bytes: usize, rows: usize) -> (usize, usize) {
+    let per_value = size_of::<(String, Arc<[u64]>)>() + 2 * size_of::<usize>();
+    let map = distinct * per_value + value_bytes;
+    let bitsets = map + distinct * rows.div_ceil(64) * size_of::<u64>();
+    let sorted = map + entries * size_of::<u32>();
+    (bitsets, sorted)
+}
+
+/// Hash and length of `value` as compact JSON, written into `buffer`
+fn encode(value: &Value, buffer: &mut Vec<u8>) -> u64 {
+    buffer.clear();
+    serde_json::to_writer(&mut *buffer, value).expect("encoding into a buffer cannot fail");
+    let mut hasher = Fnv1a::default();
+    hasher.write_all(buffer).expect("hashing cannot fail");
+    hasher.0
+}
+
+impl VectorDB {
+    /// A filter admitting live entries whose metadata has `key` equal to
+    /// `value`, for [`SearchParamsBuilder::filter`](crate::SearchParamsBuilder::filter).
+    ///
+    /// The first filter on a key since the database last changed plans
+    /// it: counts its entries and distinct values, and indexes it as
+    /// [`MetadataIndexConfig`] allows. Filters on keys left unindexed
+    /// scan every entry's metadata instead. Either way the filter holds
+    /// the matching ids as of when it was made.
+    pub fn metadata_filter(&self, key: &str, value: &Value) -> CandidateFilter {
+        let mut buffer = Vec::new();
+        encode(value, &mut buffer);
+        let value = String::from_utf8(buffer).expect("JSON is UTF-8");
+
+        let mut keys = self.metadata_index.keys.lock().unwrap();
+        keys.retain(|_, index| index.generation == self.generation);
+        if !keys.contains_key(key) {
+            let index = self.plan_key(key, &keys);
+            keys.insert(key.to_string(), index);
+        }
+        match &keys[key].postings {
+            Some(Postings::Bitset(bitsets)) => {
+                let bits = bitsets.get(&value).cloned().unwrap_or_default();
+                CandidateFilter::new(move |id| {
+                    bits.get(id as usize / 64).is_some_and(|word| word & (1 << (id % 64)) != 0)
+                })
+            }
+            Some(Postings::SortedIds(lists)) => {
+                let ids = lists.get(&value).cloned().unwrap_or_default();
+                CandidateFilter::new(move |id| ids.binary_search(&id).is_ok())
+            }
+            None => {
+                drop(keys);
+                let mut buffer = Vec::new();
+                let ids: HashSet<u32> = self
+                    .key_values(key)
+                    .filter(|(_, found)| {
+                        encode(found, &mut buffer);
+                        buffer == value.as_bytes()
+                    })
+                    .map(|(id, _)| id)
+                    .collect();
+                CandidateFilter::new(move |id| ids.contains(&id))
+            }
+        }
+    }
+
+    /// A filter admitting live entries whose metadata has a number under
+    /// `key` within `range`, such as `2020.0..` for at least 2020. Entries
+    /// without the key, or with a value that is not a number, are left
+    /// out. Keys are scanned rather than indexed; like
+    /// [`VectorDB::metadata_filter`], the filter holds the matching ids as
+    /// of when it was made.
+    pub fn metadata_range_filter(&self, key: &str, range: impl RangeBounds<f64>) -> CandidateFilter {
+        let mut ids: Vec<u32> = self
+            .key_values(key)
+            .filter(|(_, value)| value.as_f64().is_some_and(|number| range.contains(&number)))
+            .map(|(id, _)| id)
+            .collect();
+        // External metadata follows the rest
+        ids.sort_unstable();
+        CandidateFilter::new(move |id| ids.binary_search(&id).is_ok())
+    }
+
+    /// How each key filtered on since the last change is indexed, and the
+    /// memory that takes against the budget
+    pub fn metadata_index_report(&self) -> MetadataIndexReport {
+        let keys = self.metadata_index.keys.lock().unwrap();
+        let mut reports: Vec<KeyIndexReport> = keys
+            .values()
+            .filter(|index| index.generation == self.generation)
+            .map(|index| index.report.clone())
+            .collect();
+        reports.sort_by(|a, b| a.key.cmp(&b.key));
+        MetadataIndexReport {
+            budget_bytes: self.config.metadata_index.budget_bytes,
+            used_bytes: reports.iter().map(|report| report.bytes).sum(),
+            keys: reports,
+        }
+    }
+
+    /// Values of `key` in live entries' metadata, wherever it is kept
+    fn key_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = (u32, &'a Value)> + 'a {
+        self.metadata
+            .iter()
+            .chain(&self.external_metadata)
+            .filter(|(id, _)| !self.deleted.contains(id))
+            .filter_map(move |(&id, metadata)| Some((id, metadata.get(key)?)))
+    }
+
+    /// Count `key`'s entries and values, then index it the cheapest way
+    /// that suits its cardinality and fits beside the keys in `built`
+    fn plan_key(&self, key: &str, built: &HashMap<String, KeyIndex>) -> KeyIndex {
+        let limits = &self.config.metadata_index;
+        let mut buffer = Vec::new();
+        let (mut entries, mut value_bytes) = (0, 0);
+        let mut seen = HashSet::new();
+        for (_, value) in self.key_values(key) {
+            entries += 1;
+            if seen.insert(encode(value, &mut buffer)) {
+                value_bytes += buffer.len();
+            }
+        }
+        let distinct = seen.len();
+        drop(seen);
+
+        let (bitsets, sorted) = structure_bytes(entries, distinct, value_bytes, self.next_id as usize);
+        let available = limits.budget_bytes.saturating_sub(built.values().map(|index| index.report.bytes).sum());
+        let mut options = vec![(KeyIndexStrategy::SortedIds, sorted)];
+        if distinct <= limits.bitset_max_values {
+            options.push((KeyIndexStrategy::Bitset, bitsets));
+        }
+        options.sort_by_key(|&(_, bytes)| bytes);
+        let cheapest = options[0].1;
+
+        let strategy = if distinct as f64 > limits.max_distinct_fraction as f64 * entries as f64
+            && !limits.always_index.contains(key)
+        {
+            KeyIndexStrategy::NotUseful(IndexNotUseful::HighCardinality { distinct_values: distinct, entries })
+        } else {
+            let over_budget = IndexNotUseful::OverBudget { bytes: cheapest, available };
+            options
+                .into_iter()
+                .find(|&(_, bytes)| bytes <= available)
+                .map_or(KeyIndexStrategy::NotUseful(over_budget), |(strategy, _)| strategy)
+        };
+        let (postings, bytes) = match strategy {
+            KeyIndexStrategy::Bitset => (Some(Postings::Bitset(self.bitsets(key))), bitsets),
+            KeyIndexStrategy::SortedIds => (Some(Postings::SortedIds(self.sorted_ids(key))), sorted),
+            KeyIndexStrategy::NotUseful(_) => (None, 0),
+        };
+        KeyIndex {
+            generation: self.generation,
+            report: KeyIndexReport {
+                key: key.to_string(),
+                strategy,
+                entries,
+                distinct_values: distinct,
+                bytes,
+            },
+            postings,
+        }
+    }
+
+    fn bitsets(&self, key: &str) -> HashMap<String, Arc<[u64]>> {
+        let words = (self.next_id as usize).div_ceil(64);
+        let mut bitsets: HashMap<String, Vec<u64>> = HashMap::new();
+        for (id, value) in self.key_values(key) {
+            let bits = bitsets.entry(value.to_string()).or_insert_with(|| vec![0; words]);
+            bits[id as usize / 64] |= 1 << (id % 64);
+        }
+        bitsets.into_iter().map(|(value, bits)| (value, bits.into())).collect()
+    }
+
+    fn sorted_ids(&self, key: &str) -> HashMap<String, Arc<[u32]>> {
+        let mut lists: HashMap<String, Vec<u32>> = HashMap::new();
+        for (id, value) in self.key_values(key) {
+            lists.entry(value.to_string()).or_default().push(id);
+        }
+        lists
+            .into_iter()
+            .map(|(value, mut ids)| {
+                // External values follow the rest
+                ids.sort_unstable();
+                (value, ids.into())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitsets_beat_lists_only_for_dense_keys() {
+        // Two values over every entry: a bit each beats four bytes each
+        let (bitsets, sorted) = structure_bytes(10_000, 2, 10, 10_000);
+        assert!(bitsets < sorted);
+        // Fifty values on a hundredth of the entries: lists are smaller
+        let (bitsets, sorted) = structure_bytes(100, 50, 200, 10_000);
+        assert!(sorted < bitsets);
+    }
+
+    #[test]
+    fn test_values_encode_as_compact_json() {
+        let mut buffer = Vec::new();
+        let hash = encode(&serde_json::json!({"b": [1, "x"]}), &mut buffer);
+        assert_eq!(buffer, serde_json::json!({"b": [1, "x"]}).to_string().as_bytes());
+        assert_ne!(hash, encode(&serde_json::json!("1"), &mut buffer));
+        assert_eq!(encode(&serde_json::json!(1), &mut buffer), encode(&serde_json::json!(1), &mut Vec::new()));
+    }
+}
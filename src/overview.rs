@@ -0,0 +1,346 @@
+use crate::distance::{euclidean_distance_squared, euclidean_distances};
+use crate::error::{KhadyotaError, Result};
+use crate::indexing::IVFIndex;
+use crate::select::smallest_k;
+use crate::vector_db::VectorDB;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BinaryHeap;
+
+/// Options for [`VectorDB::cluster_overview_with`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverviewOptions {
+    /// Member ids sampled per cluster
+    pub samples_per_cluster: usize,
+
+    /// Largest clusters returned; the rest are only counted
+    pub max_clusters: usize,
+
+    /// Include centroids and sampled vectors, which are `dimensions`
+    /// floats each
+    pub include_vectors: bool,
+
+    /// Sampling seed; defaults to the config seed, then 0
+    pub seed: Option<u64>,
+}
+
+impl Default for OverviewOptions {
+    fn default() -> Self {
+        Self {
+            samples_per_cluster: 8,
+            max_clusters: 256,
+            include_vectors: false,
+            seed: None,
+        }
+    }
+}
+
+/// One cluster in a [`ClusterOverview`], or from
+/// [`VectorDB::cluster_summary`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterSummary {
+    /// Cluster id in the IVF index
+    pub cluster: usize,
+
+    /// Live members
+    pub size: usize,
+
+    /// Representative member ids, ascending
+    pub sample: Vec<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub centroid: Option<Vec<f32>>,
+
+    /// Vectors of `sample`, in the same order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_vectors: Option<Vec<Vec<f32>>>,
+
+    /// Live members nearest the centroid, nearest first; only filled by
+    /// [`VectorDB::cluster_summary`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exemplars: Vec<u32>,
+}
+
+/// Compact description of how the corpus is clustered, for rendering
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterOverview {
+    /// Returned clusters, largest first
+    pub clusters: Vec<ClusterSummary>,
+
+    /// Clusters in the index, including those not returned
+    pub total_clusters: usize,
+
+    /// Live vectors across all clusters, including those not returned
+    pub total_vectors: usize,
+
+    /// Euclidean distances between the returned clusters' centroids;
+    /// `centroid_distances[i][j]` pairs `clusters[i]` and `clusters[j]`
+    pub centroid_distances: Vec<Vec<f32>>,
+}
+
+/// Deterministic per-id priority (SplitMix64 finalizer)
+pub(crate) fn priority(seed: u64, id: u32) -> u64 {
+    let mut z = seed ^ u64::from(id).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl VectorDB {
+    /// Summarize the IVF clusters with `samples_per_cluster` member ids
+    /// each, and default [`OverviewOptions`] otherwise
+    pub fn cluster_overview(&self, samples_per_cluster: usize) -> Result<ClusterOverview> {
+        self.cluster_overview_with(&OverviewOptions {
+            samples_per_cluster,
+            ..Default::default()
+        })
+    }
+
+    /// Summarize the IVF clusters: their sizes, a sample of members and the
+    /// distances between centroids.
+    ///
+    /// Samples are the members with the lowest seeded hash, so the same
+    /// seed picks the same ids however the inverted lists are ordered.
+    /// Cost is one pass over the inverted lists plus a centroid distance
+    /// matrix for the returned clusters.
+    pub fn cluster_overview_with(&self, options: &OverviewOptions) -> Result<ClusterOverview> {
+        let ivf = self.built_ivf()?;
+        let seed = options.seed.or(self.config.seed).unwrap_or(0);
+
+        let mut clusters: Vec<(usize, usize, Vec<u32>)> = (0..ivf.num_clusters())
+            .into_par_iter()
+            .map(|cluster| {
+                let live = ivf.inverted_list(cluster).iter().filter(|id| !self.deleted.contains(id));
+
+                // Keep the ids with the smallest priorities
+                let mut size = 0;
+                let mut heap = BinaryHeap::with_capacity(options.samples_per_cluster + 1);
+                for &id in live {
+                    size += 1;
+                    heap.push((priority(seed, id), id));
+                    if heap.len() > options.samples_per_cluster {
+                        heap.pop();
+                    }
+                }
+
+                let mut sample: Vec<u32> = heap.into_iter().map(|(_, id)| id).collect();
+                sample.sort_unstable();
+                (cluster, size, sample)
+            })
+            .collect();
+
+        let total_vectors = clusters.iter().map(|(_, size, _)| size).sum();
+        clusters.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        clusters.truncate(options.max_clusters);
+
+        let centroids: Vec<Vec<f32>> = clusters
+            .iter()
+            .map(|&(cluster, _, _)| ivf.centroids()[cluster].clone())
+            .collect();
+        let centroid_distances = centroids
+            .par_iter()
+            .map(|centroid| euclidean_distances(centroid, &centroids))
+            .collect();
+
+        let clusters = clusters
+            .into_iter()
+            .zip(centroids)
+            .map(|((cluster, size, sample), centroid)| {
+                let sample_vectors = options.include_vectors.then(|| {
+                    sample.iter().map(|&id| self.vectors.get(id).unwrap().to_vec()).collect()
+                });
+                ClusterSummary {
+                    cluster,
+                    size,
+                    sample,
+                    centroid: options.include_vectors.then_some(centroid),
+                    sample_vectors,
+                    exemplars: Vec::new(),
+                }
+            })
+            .collect();
+
+        Ok(ClusterOverview {
+            clusters,
+            total_clusters: ivf.num_clusters(),
+            total_vectors,
+            centroid_distances,
+        })
+    }
+
+    /// Every IVF cluster in id order, with its live size, its centroid
+    /// and the `exemplars` live members nearest it by Euclidean distance,
+    /// nearest first, so the index's coarse quantizer doubles as a
+    /// clustering of the corpus. No members are sampled.
+    ///
+    /// Members are scored exactly, at the cost of one pass over every
+    /// listed row.
+    pub fn cluster_summary(&self, exemplars: usize) -> Result<Vec<ClusterSummary>> {
+        let ivf = self.built_ivf()?;
+        Ok((0..ivf.num_clusters())
+            .into_par_iter()
+            .map(|cluster| {
+                let centroid = ivf.centroids()[cluster].clone();
+                let live: Vec<u32> = ivf
+                    .cluster_members(cluster)
+                    .iter()
+                    .copied()
+                    .filter(|id| !self.deleted.contains(id))
+                    .collect();
+                let scored = live
+                    .iter()
+                    .map(|&id| (id, euclidean_distance_squared(&self.vectors.get(id).unwrap(), &centroid)));
+                ClusterSummary {
+                    cluster,
+                    size: live.len(),
+                    sample: Vec::new(),
+                    exemplars: smallest_k(scored, exemplars).into_iter().map(|(id, _)| id).collect(),
+                    centroid: Some(centroid),
+                    sample_vectors: None,
+                }
+            })
+            .collect())
+    }
+
+    /// Cluster live entry `id` is listed in. While the index lags behind
+    /// writes, an entry written since and not yet listed reports the
+    /// cluster it would join.
+    pub fn cluster_of(&self, id: u32) -> Result<usize> {
+        let ivf = self.ivf_index.as_ref().ok_or(KhadyotaError::IndexNotBuilt)?;
+        if !self.contains(id) {
+            return Err(KhadyotaError::VectorNotFound(id));
+        }
+        let listed = self
+            .index_lag
+            .as_ref()
+            .is_none_or(|lag| id < lag.indexed_rows && !lag.pending.contains(&id));
+        Ok(match ivf.cluster_of(id).filter(|_| listed) {
+            Some(cluster) => cluster,
+            None => ivf.assign(&self.vectors.get(id).unwrap()),
+        })
+    }
+
+    fn built_ivf(&self) -> Result<&IVFIndex> {
+        self.ivf_index.as_ref().filter(|_| self.index_built).ok_or(KhadyotaError::IndexNotBuilt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, DistanceMetric, QuantizerKind};
+    use crate::distance::euclidean_distance;
+
+    fn build_db() -> VectorDB {
+        let config = Config {
+            dimensions: 16,
+            metric: DistanceMetric::Euclidean,
+            quantizer: QuantizerKind::None,
+            num_clusters: 12,
+            num_probe: 3,
+            seed: Some(8),
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..3_000 {
+            let vector = (0..16).map(|j| ((i * 16 + j) as f32 * 0.61).sin() + (i % 5) as f32).collect();
+            db.insert(vector, None).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_sizes_and_samples() {
+        let mut db = build_db();
+        for id in (0..3_000).step_by(7) {
+            db.delete(id).unwrap();
+        }
+
+        let overview = db.cluster_overview(5).unwrap();
+        assert_eq!(overview.total_clusters, 12);
+        assert_eq!(overview.clusters.len(), 12);
+        assert_eq!(overview.total_vectors, db.len());
+        assert_eq!(overview.clusters.iter().map(|c| c.size).sum::<usize>(), db.len());
+        assert!(overview.clusters.windows(2).all(|w| w[0].size >= w[1].size));
+
+        let ivf = db.ivf_index.as_ref().unwrap();
+        for summary in &overview.clusters {
+            assert_eq!(summary.sample.len(), summary.size.min(5));
+            assert!(summary.sample.windows(2).all(|w| w[0] < w[1]));
+            for id in &summary.sample {
+                assert!(ivf.inverted_list(summary.cluster).contains(id));
+                assert!(id % 7 != 0);
+            }
+            assert!(summary.centroid.is_none() && summary.sample_vectors.is_none());
+        }
+
+        // Frontend payloads carry no vectors unless asked
+        let json = serde_json::to_string(&overview).unwrap();
+        assert!(!json.contains("centroid\""));
+    }
+
+    #[test]
+    fn test_sampling_is_deterministic_under_a_seed() {
+        let db = build_db();
+        let options = |seed| OverviewOptions {
+            samples_per_cluster: 4,
+            seed: Some(seed),
+            ..Default::default()
+        };
+
+        let first = db.cluster_overview_with(&options(1)).unwrap();
+        assert_eq!(first, db.cluster_overview_with(&options(1)).unwrap());
+        let other = db.cluster_overview_with(&options(2)).unwrap();
+        assert_ne!(
+            first.clusters.iter().map(|c| &c.sample).collect::<Vec<_>>(),
+            other.clusters.iter().map(|c| &c.sample).collect::<Vec<_>>()
+        );
+
+        // The config seed is the default
+        assert_eq!(db.cluster_overview(4).unwrap(), db.cluster_overview_with(&options(8)).unwrap());
+    }
+
+    #[test]
+    fn test_cap_and_centroid_distances() {
+        let db = build_db();
+        let full = db.cluster_overview(2).unwrap();
+        let capped = db
+            .cluster_overview_with(&OverviewOptions {
+                samples_per_cluster: 2,
+                max_clusters: 3,
+                include_vectors: true,
+                seed: None,
+            })
+            .unwrap();
+
+        assert_eq!(capped.clusters.len(), 3);
+        assert_eq!(capped.total_vectors, 3_000);
+        for (summary, expected) in capped.clusters.iter().zip(&full.clusters) {
+            assert_eq!((summary.cluster, summary.size, &summary.sample), (expected.cluster, expected.size, &expected.sample));
+            let vectors = summary.sample_vectors.as_ref().unwrap();
+            assert_eq!(vectors[0], db.get(summary.sample[0]).unwrap().vector);
+        }
+
+        let centroids: Vec<&Vec<f32>> = capped.clusters.iter().map(|c| c.centroid.as_ref().unwrap()).collect();
+        for i in 0..3 {
+            assert_eq!(capped.centroid_distances[i][i], 0.0);
+            for j in 0..3 {
+                approx::assert_relative_eq!(
+                    capped.centroid_distances[i][j],
+                    euclidean_distance(centroids[i], centroids[j]),
+                    epsilon = 1e-5
+                );
+                assert_eq!(capped.centroid_distances[i][j], capped.centroid_distances[j][i]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_requires_built_index() {
+        let mut db = build_db();
+        db.insert(vec![0.0; 16], None).unwrap();
+        assert!(matches!(db.cluster_overview(3), Err(KhadyotaError::IndexNotBuilt)));
+    }
+}
@@ -0,0 +1,67 @@
+//! How finely parallel scoring loops are split into rayon tasks
+
+use crate::vector_db::VectorDB;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Work a rayon task should get through, in table lookups or distance
+/// components: enough to amortize scheduling it, little enough that a
+/// handful of tasks still balance across threads
+pub const TARGET_TASK_COST: usize = 10_000;
+
+/// Items per task forced by [`set_chunk_override`]; 0 when unset
+static CHUNK_OVERRIDE: AtomicUsize = AtomicUsize::new(0);
+
+/// Force every tuned parallel loop to take `items` per task, or go back
+/// to the cost model with `None`. `Some(1)` leaves splitting entirely to
+/// rayon, as before tuning.
+///
+/// Process-wide, and meant for benchmarking the model against fixed
+/// chunk sizes; results never depend on it.
+pub fn set_chunk_override(items: Option<usize>) {
+    CHUNK_OVERRIDE.store(items.map_or(0, |n| n.max(1)), Ordering::Relaxed);
+}
+
+/// The override set with [`set_chunk_override`], if any
+pub fn chunk_override() -> Option<usize> {
+    match CHUNK_OVERRIDE.load(Ordering::Relaxed) {
+        0 => None,
+        items => Some(items),
+    }
+}
+
+/// Fewest items a task should take when each costs `cost` units, for
+/// `with_min_len`. Rayon still splits large loops across every thread;
+/// this only stops it splitting below [`TARGET_TASK_COST`].
+pub(crate) fn chunk_len(cost: usize) -> usize {
+    chunk_override().unwrap_or_else(|| (TARGET_TASK_COST / cost.max(1)).max(1))
+}
+
+impl VectorDB {
+    /// Rough cost of one default search, in the units of
+    /// [`TARGET_TASK_COST`]: lookups over the probed share of the rows
+    /// with codes, components over every row without
+    pub(crate) fn query_cost(&self) -> usize {
+        let rows = self.vectors.len();
+        match (&self.ivf_index, &self.quantized) {
+            (Some(ivf), Some(quantized)) => {
+                let probed = rows * ivf.num_probe() / ivf.num_lists().max(1);
+                probed * quantized.codec().table_rows()
+            }
+            _ => rows * self.config.dimensions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_shrink_as_items_get_costlier() {
+        // Only the model is checked here; the override is process-wide
+        // and covered by its own test binary
+        assert_eq!(chunk_len(8), 1_250);
+        assert_eq!(chunk_len(0), TARGET_TASK_COST);
+        assert_eq!(chunk_len(TARGET_TASK_COST * 3), 1);
+    }
+}
@@ -0,0 +1,577 @@
+use crate::error::{KhadyotaError, Result};
+use crate::progress::BuildEvent;
+use crate::storage::replace::replace_file;
+use crate::storage::{FileHeader, ReplaceStrategy, Section, VectorStorage};
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Elements of the saved state, which is written as a fixed-length array
+pub(crate) const STATE_FIELDS: u8 = 11;
+
+/// A part of a saved file that [`VectorDB::save_incremental`] tracks and
+/// can carry over unchanged from the previous file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PersistSection {
+    /// The original rows
+    Vectors,
+
+    /// Quantized codes and their codec
+    Codes,
+
+    /// The IVF index
+    Index,
+
+    /// Metadata, entry attributes, tombstones and counters
+    Metadata,
+
+    /// The section table: external metadata, access statistics and
+    /// sections carried through from other builds
+    AppState,
+}
+
+impl PersistSection {
+    /// Every section, in file order
+    pub const ALL: [PersistSection; 5] = [
+        PersistSection::Vectors,
+        PersistSection::Codes,
+        PersistSection::Index,
+        PersistSection::Metadata,
+        PersistSection::AppState,
+    ];
+}
+
+/// What a save did with one section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionPersist {
+    pub section: PersistSection,
+
+    /// Size of the section in the new file
+    pub bytes: u64,
+
+    /// Whether its bytes were copied from the previous file rather than
+    /// encoded again
+    pub reused: bool,
+}
+
+/// Outcome of [`VectorDB::save_incremental`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistReport {
+    pub sections: Vec<SectionPersist>,
+
+    /// Bytes encoded afresh, including the header and config, which are
+    /// always rewritten
+    pub bytes_written: u64,
+
+    /// Bytes copied from the previous file
+    pub bytes_reused: u64,
+
+    /// Why nothing was reused, when the file was written in full
+    pub full_rewrite: Option<String>,
+
+    /// How the new file replaced the old one
+    #[serde(default)]
+    pub replace: ReplaceStrategy,
+}
+
+impl PersistReport {
+    /// Look up a section by kind
+    pub fn section(&self, section: PersistSection) -> Option<&SectionPersist> {
+        self.sections.iter().find(|s| s.section == section)
+    }
+
+    /// Size of the new file
+    pub fn file_bytes(&self) -> u64 {
+        self.bytes_written + self.bytes_reused
+    }
+}
+
+/// Sections changed since the file at [`Layout::path`] was last read or
+/// written incrementally
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DirtySections(u8);
+
+impl DirtySections {
+    pub(crate) const ALL: Self = Self(0b1_1111);
+    pub(crate) const NONE: Self = Self(0);
+
+    pub(crate) fn mark(&mut self, sections: &[PersistSection]) {
+        for &section in sections {
+            self.0 |= 1 << section as u8;
+        }
+    }
+
+    pub(crate) fn contains(self, section: PersistSection) -> bool {
+        self.0 & (1 << section as u8) != 0
+    }
+}
+
+/// Where each section sits in a saved file, so an incremental save can
+/// copy the ones that have not changed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Layout {
+    pub(crate) path: PathBuf,
+    pub(crate) len: u64,
+
+    /// Byte range of each section, in [`PersistSection::ALL`] order. The
+    /// header and config come before the first.
+    pub(crate) ranges: [Range<u64>; 5],
+
+    /// Content hashes of the vectors, codes and index sections
+    pub(crate) hashes: [u64; 3],
+
+    /// Offset and value of the stored build id; `None` for files written
+    /// before build ids were recorded
+    pub(crate) build_id: Option<(u64, u64)>,
+}
+
+impl Layout {
+    fn range(&self, section: PersistSection) -> Range<u64> {
+        self.ranges[section as usize].clone()
+    }
+}
+
+/// Identifies the vectors, codes and index a file holds, by content, so
+/// a save never reuses sections of a file holding another build.
+/// Deterministic, like the rest of a saved file.
+pub(crate) fn build_id(hashes: &[u64; 3]) -> u64 {
+    let mut hash = WordHash::default();
+    for value in hashes {
+        hash.update(&value.to_le_bytes());
+    }
+    hash.finish()
+}
+
+/// A canonical form of `path` to recognize it by, when it exists
+pub(crate) fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// FNV-1a over 8-byte words: fast enough to run over every byte a save
+/// writes or a load reads
+#[derive(Debug, Clone, Copy)]
+struct WordHash {
+    state: u64,
+    pending: [u8; 8],
+    pending_len: usize,
+}
+
+impl Default for WordHash {
+    fn default() -> Self {
+        Self {
+            state: 0xcbf2_9ce4_8422_2325,
+            pending: [0; 8],
+            pending_len: 0,
+        }
+    }
+}
+
+impl WordHash {
+    fn mix(&mut self, word: u64) {
+        self.state = (self.state ^ word).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    fn update(&mut self, mut bytes: &[u8]) {
+        if self.pending_len > 0 {
+            let take = (8 - self.pending_len).min(bytes.len());
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&bytes[..take]);
+            self.pending_len += take;
+            bytes = &bytes[take..];
+            if self.pending_len < 8 {
+                return;
+            }
+            self.mix(u64::from_le_bytes(self.pending));
+            self.pending_len = 0;
+        }
+
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.mix(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        let rest = words.remainder();
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+    }
+
+    fn finish(mut self) -> u64 {
+        let mut last = [0; 8];
+        last[..self.pending_len].copy_from_slice(&self.pending[..self.pending_len]);
+        self.mix(u64::from_le_bytes(last));
+        self.mix(self.pending_len as u64);
+        self.state
+    }
+}
+
+/// Position and running hash of a stream, shared with whatever decodes
+/// from it so element boundaries can be recorded mid-decode
+#[derive(Debug, Default)]
+pub(crate) struct Tally {
+    position: Cell<u64>,
+    hash: Cell<WordHash>,
+}
+
+impl Tally {
+    pub(crate) fn position(&self) -> u64 {
+        self.position.get()
+    }
+
+    /// Hash of the bytes since the last call, starting afresh
+    pub(crate) fn take_hash(&self) -> u64 {
+        self.hash.take().finish()
+    }
+
+    fn record(&self, bytes: &[u8]) {
+        self.position.set(self.position.get() + bytes.len() as u64);
+        let mut hash = self.hash.get();
+        hash.update(bytes);
+        self.hash.set(hash);
+    }
+
+    /// Count `len` bytes that bypassed the tally
+    fn skip(&self, len: u64) {
+        self.position.set(self.position.get() + len);
+        self.hash.take();
+    }
+}
+
+/// A reader or writer whose bytes are counted and hashed in a [`Tally`]
+pub(crate) struct Tallied<'a, T> {
+    pub(crate) inner: T,
+    tally: &'a Tally,
+}
+
+impl<'a, T> Tallied<'a, T> {
+    pub(crate) fn new(inner: T, tally: &'a Tally) -> Self {
+        Self { inner, tally }
+    }
+}
+
+impl<R: Read> Read for Tallied<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.tally.record(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Tallied<'_, R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.tally.record(&buf[..amount.min(buf.len())]);
+        }
+        self.inner.consume(amount);
+    }
+}
+
+impl<W: Write> Write for Tallied<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.tally.record(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The file a save copies unchanged sections from
+pub(crate) struct Reuse<'a> {
+    file: File,
+    layout: &'a Layout,
+    dirty: DirtySections,
+}
+
+/// Writes a saved file section by section, recording where each lands
+struct SectionWriter<'a, 'r, W: Write> {
+    writer: Tallied<'a, BufWriter<W>>,
+    tally: &'a Tally,
+    reuse: Option<Reuse<'r>>,
+    ranges: [Range<u64>; 5],
+    report: PersistReport,
+}
+
+impl<W: Write> SectionWriter<'_, '_, W> {
+    /// Copy `section` from the previous file if `unchanged` and nothing
+    /// marked it dirty, or else encode it with `encode`. Returns the hash
+    /// of its bytes.
+    fn section(
+        &mut self,
+        section: PersistSection,
+        unchanged: bool,
+        encode: impl FnOnce(&mut Tallied<'_, BufWriter<W>>) -> Result<()>,
+    ) -> Result<u64> {
+        let start = self.tally.position();
+        self.tally.take_hash();
+
+        let previous = self.reuse.as_mut().filter(|r| unchanged && !r.dirty.contains(section));
+        let reused = previous.is_some();
+        let hash = match previous {
+            Some(previous) => {
+                let range = previous.layout.range(section);
+                previous.file.seek(SeekFrom::Start(range.start))?;
+                let copied = std::io::copy(&mut (&previous.file).take(range.end - range.start), &mut self.writer.inner)?;
+                if copied != range.end - range.start {
+                    return Err(KhadyotaError::SerializationError(format!(
+                        "previous file ended inside its {:?} section",
+                        section
+                    )));
+                }
+                self.tally.skip(copied);
+                self.report.bytes_reused += copied;
+                previous.layout.hashes.get(section as usize).copied().unwrap_or_default()
+            }
+            None => {
+                encode(&mut self.writer)?;
+                self.tally.take_hash()
+            }
+        };
+
+        let range = start..self.tally.position();
+        self.report.sections.push(SectionPersist {
+            section,
+            bytes: range.end - range.start,
+            reused,
+        });
+        self.ranges[section as usize] = range;
+        Ok(hash)
+    }
+}
+
+impl VectorDB {
+    /// Save to `path`, rewriting only the sections that changed since this
+    /// database was loaded from it or last saved there with this method.
+    ///
+    /// The others are copied from the existing file, which the kernel
+    /// shares rather than duplicates on filesystems that support reflinks.
+    /// The new file is written beside `path` and moved over it once
+    /// synced, so readers and crashes see the old file or the new one; the
+    /// report says how, as [`ReplaceStrategy`] documents per platform. The
+    /// result is byte-for-byte what [`VectorDB::save`] would write.
+    ///
+    /// Nothing is reused, and the report says why, when the database was
+    /// last read from or saved to another file, or when the file at `path`
+    /// no longer holds the build it recorded. Only this method clears the
+    /// record of what changed, so after a plain `save()` the next
+    /// incremental save rewrites as much as it would have before.
+    pub fn save_incremental(&mut self, path: &Path) -> Result<PersistReport> {
+        let (reuse, full_rewrite) = match self.reusable_file(path) {
+            Ok(reuse) => (Some(reuse), None),
+            Err(reason) => (None, Some(reason)),
+        };
+
+        let mut name = path.as_os_str().to_owned();
+        name.push(".partial");
+        let tmp = PathBuf::from(name);
+        let written = File::create(&tmp).map_err(KhadyotaError::from).and_then(|file| self.write_sections(file, true, reuse));
+        let (layout, mut report) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+        };
+        report.replace = match replace_file(&tmp, path) {
+            Ok(strategy) => strategy,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e.into());
+            }
+        };
+        report.full_rewrite = full_rewrite;
+        self.layout = Some(Layout {
+            path: identity(path),
+            ..layout
+        });
+        self.dirty = DirtySections::NONE;
+        self.snapshot_saved(path)?;
+        if let Some(progress) = &self.progress {
+            progress.on_event(BuildEvent::Saved {
+                path: path.to_path_buf(),
+                bytes: report.file_bytes(),
+            });
+        }
+        Ok(report)
+    }
+
+    /// Record that `sections` no longer match the file last read or saved
+    pub(crate) fn mark_dirty(&mut self, sections: &[PersistSection]) {
+        self.dirty.mark(sections);
+    }
+
+    /// The file at `path`, if its sections can be reused, or why not
+    fn reusable_file(&self, path: &Path) -> std::result::Result<Reuse<'_>, String> {
+        let Some(layout) = &self.layout else {
+            return Err("the database was not loaded or incrementally saved before".to_string());
+        };
+        if layout.path != identity(path) {
+            return Err(format!("the database was last loaded or saved at {:?}", layout.path));
+        }
+        let Some((offset, expected)) = layout.build_id else {
+            return Err("the file predates build ids".to_string());
+        };
+
+        let mut file = File::open(path).map_err(|e| format!("the file cannot be read: {}", e))?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        if len != layout.len {
+            return Err(format!("the file is {} bytes, not the {} last recorded", len, layout.len));
+        }
+        let found = file
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| e.to_string())
+            .and_then(|_| rmp_serde::from_read::<_, u64>((&file).take(9)).map_err(|e| e.to_string()));
+        match found {
+            Ok(found) if found == expected => Ok(Reuse {
+                file,
+                layout,
+                dirty: self.dirty,
+            }),
+            Ok(found) => Err(format!("the file holds build {:016x}, not {:016x}", found, expected)),
+            Err(e) => Err(format!("the file's build id cannot be read: {}", e)),
+        }
+    }
+
+    /// Write the saved-file layout to `writer`, copying the sections
+    /// `reuse` allows from its file. Without `inline_vectors` the rows are
+    /// left out, for a caller that stores them separately; the header
+    /// still records how many there are. The layout's path is left empty
+    /// for a caller writing to a file to fill in.
+    pub(crate) fn write_sections<W: Write>(
+        &self,
+        writer: W,
+        inline_vectors: bool,
+        reuse: Option<Reuse<'_>>,
+    ) -> Result<(Layout, PersistReport)> {
+        let tally = Tally::default();
+        let mut out = SectionWriter {
+            writer: Tallied::new(BufWriter::new(writer), &tally),
+            tally: &tally,
+            reuse,
+            ranges: Default::default(),
+            report: PersistReport::default(),
+        };
+
+        FileHeader::new(self.config.dimensions, self.vectors.len(), self.config.metric).write_to(&mut out.writer)?;
+        // The state's fields as a MessagePack array, written one by one
+        out.writer.write_all(&[0x90 | STATE_FIELDS])?;
+        rmp_serde::encode::write(&mut out.writer, &self.config)?;
+
+        let no_vectors = VectorStorage::default();
+        let vectors = if inline_vectors { &self.vectors } else { &no_vectors };
+        let hashes = [
+            out.section(PersistSection::Vectors, true, |w| Ok(rmp_serde::encode::write(w, vectors)?))?,
+            out.section(PersistSection::Codes, true, |w| Ok(rmp_serde::encode::write(w, &self.quantized)?))?,
+            out.section(PersistSection::Index, true, |w| Ok(rmp_serde::encode::write(w, &self.ivf_index)?))?,
+        ];
+
+        // The build id closes the state, so the tail can only be copied
+        // when it still names the same build
+        let id = build_id(&hashes);
+        let previous_id = out.reuse.as_ref().and_then(|r| r.layout.build_id);
+        let tail_start = out.tally.position();
+        let mut id_offset = previous_id.map(|(offset, _)| offset);
+        out.section(PersistSection::Metadata, previous_id.is_some_and(|(_, previous)| previous == id), |w| {
+            rmp_serde::encode::write(w, &self.metadata)?;
+            rmp_serde::encode::write(w, &self.next_id)?;
+            rmp_serde::encode::write(w, &self.index_built)?;
+            rmp_serde::encode::write(w, &self.attributes)?;
+            rmp_serde::encode::write(w, &self.deleted)?;
+            rmp_serde::encode::write(w, &self.seq)?;
+            id_offset = Some(tally.position());
+            rmp_serde::encode::write(w, &id)?;
+            Ok(())
+        })?;
+        let id_offset = match out.report.sections.last() {
+            Some(tail) if tail.reused => {
+                let previous = &out.reuse.as_ref().unwrap().layout;
+                id_offset.unwrap() - previous.range(PersistSection::Metadata).start + tail_start
+            }
+            _ => id_offset.unwrap(),
+        };
+
+        // Access counters change as queries run, and collections through
+        // handles dirty tracking does not see, so both are always written
+        // afresh. The table is omitted when empty so files stay readable
+        // by older builds.
+        out.section(PersistSection::AppState, self.access.is_none() && self.collections.is_empty(), |w| {
+            let sections = self.app_sections()?;
+            if !sections.is_empty() {
+                Section::write_table(w, &sections)?;
+            }
+            Ok(())
+        })?;
+
+        let len = tally.position();
+        out.writer.inner.into_inner().map_err(|e| e.into_error())?;
+        let mut report = out.report;
+        report.bytes_written = len - report.bytes_reused;
+        let layout = Layout {
+            path: PathBuf::new(),
+            len,
+            ranges: out.ranges,
+            hashes,
+            build_id: Some((id_offset, id)),
+        };
+        Ok((layout, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hash_ignores_how_bytes_are_split() {
+        let bytes: Vec<u8> = (0..100u8).collect();
+        let mut whole = WordHash::default();
+        whole.update(&bytes);
+
+        for split in [1, 3, 5, 8, 13] {
+            let mut pieces = WordHash::default();
+            for chunk in bytes.chunks(split) {
+                pieces.update(chunk);
+            }
+            assert_eq!(pieces.finish(), whole.finish());
+        }
+
+        let mut shorter = WordHash::default();
+        shorter.update(&bytes[..99]);
+        assert_ne!(shorter.finish(), whole.finish());
+    }
+
+    #[test]
+    fn test_load_records_the_layout_save_wrote() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("db.kdb");
+        let mut db = VectorDB::new(Config {
+            dimensions: 8,
+            pq_subvectors: 2,
+            num_clusters: 4,
+            seed: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        for i in 0..300 {
+            let vector: Vec<f32> = (0..8).map(|j| ((i * 8 + j) as f32 * 0.1).sin()).collect();
+            db.insert(vector, Some(serde_json::json!({ "i": i }))).unwrap();
+        }
+        db.build_index().unwrap();
+
+        let (written, report) = db.write_sections(File::create(&path).unwrap(), true, None).unwrap();
+        assert_eq!(report.bytes_written, written.len);
+        assert_eq!(written.ranges[4].end, written.len);
+        let loaded = VectorDB::load(&path).unwrap();
+        assert_eq!(loaded.layout, Some(Layout { path: identity(&path), ..written }));
+        assert_eq!(loaded.dirty, DirtySections::NONE);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::error::{KhadyotaError, Result};
+use crate::overview::priority;
+use crate::vector_db::VectorDB;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BinaryHeap;
+
+/// Rows accumulated per task; chunks are merged in order, so results do
+/// not depend on the thread count
+const CHUNK_ROWS: usize = 512;
+
+/// A dimension whose std is below this fraction of the median std is
+/// flagged as constant
+const CONSTANT_STD_RATIO: f64 = 1e-3;
+
+/// A dimension whose RMS magnitude is this many times larger (or
+/// smaller) than the median is flagged as out of scale
+const EXTREME_MAGNITUDE_RATIO: f64 = 100.0;
+
+/// Mean shift, in units of the old std, that counts as drift
+const DRIFT_MEAN_SHIFT: f64 = 1.0;
+
+/// Std ratio outside `1/x..=x` that counts as drift
+const DRIFT_STD_RATIO: f64 = 2.0;
+
+/// Change in the zero fraction that counts as drift
+const DRIFT_ZERO_FRACTION: f64 = 0.25;
+
+/// Summary statistics of one dimension over the sampled rows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DimensionStats {
+    pub mean: f64,
+
+    /// Population standard deviation
+    pub std: f64,
+
+    pub min: f32,
+    pub max: f32,
+
+    /// Share of sampled values that are exactly zero
+    pub zero_fraction: f64,
+}
+
+impl DimensionStats {
+    /// Root mean square, the dimension's typical magnitude
+    pub fn rms(&self) -> f64 {
+        (self.mean * self.mean + self.std * self.std).sqrt()
+    }
+}
+
+/// Why a dimension was flagged in a [`DataProfile`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DimensionAnomaly {
+    /// Every sampled value is zero
+    AllZero { dimension: usize },
+
+    /// Nearly no variance compared to the other dimensions; the
+    /// dimension carries no information for search
+    Constant { dimension: usize, value: f64 },
+
+    /// RMS magnitude `ratio` times the median over all dimensions, far
+    /// enough off to dominate (or vanish from) every distance
+    ExtremeMagnitude { dimension: usize, ratio: f64 },
+}
+
+impl DimensionAnomaly {
+    pub fn dimension(&self) -> usize {
+        match *self {
+            DimensionAnomaly::AllZero { dimension }
+            | DimensionAnomaly::Constant { dimension, .. }
+            | DimensionAnomaly::ExtremeMagnitude { dimension, .. } => dimension,
+        }
+    }
+}
+
+/// Per-dimension statistics over a sample of the stored corpus, from
+/// [`VectorDB::data_profile`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataProfile {
+    /// Rows the statistics are over
+    pub sampled: usize,
+
+    /// Live rows at the time of profiling
+    pub total: usize,
+
+    /// Seed the sample was drawn with
+    pub seed: u64,
+
+    /// One entry per dimension, in order
+    pub dimensions: Vec<DimensionStats>,
+
+    /// Flagged dimensions, ascending; a dimension is flagged at most once
+    pub anomalies: Vec<DimensionAnomaly>,
+}
+
+/// How one dimension moved between two profiles
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DimensionDrift {
+    pub dimension: usize,
+
+    /// Change in mean, in units of the old std; infinite when a constant
+    /// dimension moved
+    pub mean_shift: f64,
+
+    /// New std over old std
+    pub std_ratio: f64,
+
+    /// New zero fraction minus old
+    pub zero_fraction_change: f64,
+}
+
+/// Differences between two [`DataProfile`]s of the same corpus, from
+/// [`DataProfile::diff`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileDiff {
+    /// Dimensions that moved past the drift thresholds, ascending
+    pub drifted: Vec<DimensionDrift>,
+
+    /// Anomalies in the new profile that the old one did not have
+    pub new_anomalies: Vec<DimensionAnomaly>,
+
+    /// Anomalies of the old profile that are gone
+    pub resolved_anomalies: Vec<DimensionAnomaly>,
+}
+
+impl ProfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.drifted.is_empty() && self.new_anomalies.is_empty() && self.resolved_anomalies.is_empty()
+    }
+}
+
+/// Running sums over a run of rows
+struct Moments {
+    count: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+    min: Vec<f32>,
+    max: Vec<f32>,
+    zeros: Vec<u64>,
+}
+
+impl Moments {
+    fn new(dims: usize) -> Self {
+        Self {
+            count: 0,
+            sum: vec![0.0; dims],
+            sum_sq: vec![0.0; dims],
+            min: vec![f32::INFINITY; dims],
+            max: vec![f32::NEG_INFINITY; dims],
+            zeros: vec![0; dims],
+        }
+    }
+
+    /// Add one row. Each loop is elementwise over the dimensions without
+    /// branches, so the compiler vectorizes it.
+    fn add(&mut self, row: &[f32], shift: &[f32]) {
+        self.count += 1;
+        for ((sum, sum_sq), (&x, &s)) in self.sum.iter_mut().zip(&mut self.sum_sq).zip(row.iter().zip(shift)) {
+            let d = f64::from(x) - f64::from(s);
+            *sum += d;
+            *sum_sq += d * d;
+        }
+        for ((min, max), &x) in self.min.iter_mut().zip(&mut self.max).zip(row) {
+            *min = min.min(x);
+            *max = max.max(x);
+        }
+        for (zeros, &x) in self.zeros.iter_mut().zip(row) {
+            *zeros += u64::from(x == 0.0);
+        }
+    }
+
+    fn merge(mut self, other: Moments) -> Self {
+        self.count += other.count;
+        for (a, b) in self.sum.iter_mut().zip(other.sum) {
+            *a += b;
+        }
+        for (a, b) in self.sum_sq.iter_mut().zip(other.sum_sq) {
+            *a += b;
+        }
+        for (a, b) in self.min.iter_mut().zip(other.min) {
+            *a = a.min(b);
+        }
+        for (a, b) in self.max.iter_mut().zip(other.max) {
+            *a = a.max(b);
+        }
+        for (a, b) in self.zeros.iter_mut().zip(other.zeros) {
+            *a += b;
+        }
+        self
+    }
+}
+
+impl VectorDB {
+    /// Per-dimension mean, std, range and share of zeros over up to
+    /// `sample` live rows (all of them for 0), with dimensions that look
+    /// broken flagged.
+    ///
+    /// The sample is the live ids with the lowest hash under the config
+    /// seed (or 0), so profiles of the same rows are identical and a
+    /// profile taken later mostly covers the same ids. Cost is one read
+    /// of each sampled row.
+    pub fn data_profile(&self, sample: usize) -> Result<DataProfile> {
+        self.data_profile_seeded(sample, self.config.seed.unwrap_or(0))
+    }
+
+    /// Profile the corpus as [`VectorDB::data_profile`] with the sample
+    /// size and seed of `old`, and compare the two
+    pub fn profile_diff(&self, old: &DataProfile) -> Result<ProfileDiff> {
+        let sample = if old.sampled >= old.total { 0 } else { old.sampled };
+        self.data_profile_seeded(sample, old.seed)?.diff(old)
+    }
+
+    fn data_profile_seeded(&self, sample: usize, seed: u64) -> Result<DataProfile> {
+        let live = (0..self.vectors.len() as u32).filter(|id| !self.deleted.contains(id));
+        let total = self.len();
+        let ids: Vec<u32> = if sample == 0 || sample >= total {
+            live.collect()
+        } else {
+            let mut heap = BinaryHeap::with_capacity(sample + 1);
+            for id in live {
+                heap.push((priority(seed, id), id));
+                if heap.len() > sample {
+                    heap.pop();
+                }
+            }
+            let mut ids: Vec<u32> = heap.into_iter().map(|(_, id)| id).collect();
+            ids.sort_unstable();
+            ids
+        };
+        let Some(&first) = ids.first() else {
+            return Err(KhadyotaError::InvalidConfig("Cannot profile an empty database".to_string()));
+        };
+
+        // Sums are taken around the first sampled row, which keeps the
+        // sum of squares from cancelling on dimensions with a large offset
+        let dims = self.config.dimensions;
+        let shift = self.vectors.get(first).unwrap().to_vec();
+        let moments = ids
+            .par_chunks(CHUNK_ROWS)
+            .map(|chunk| {
+                let mut moments = Moments::new(dims);
+                for &id in chunk {
+                    moments.add(&self.vectors.get(id).unwrap(), &shift);
+                }
+                moments
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .reduce(Moments::merge)
+            .unwrap();
+
+        let n = moments.count as f64;
+        let dimensions: Vec<DimensionStats> = (0..dims)
+            .map(|j| {
+                let mean = moments.sum[j] / n;
+                DimensionStats {
+                    mean: mean + f64::from(shift[j]),
+                    std: (moments.sum_sq[j] / n - mean * mean).max(0.0).sqrt(),
+                    min: moments.min[j],
+                    max: moments.max[j],
+                    zero_fraction: moments.zeros[j] as f64 / n,
+                }
+            })
+            .collect();
+        let anomalies = find_anomalies(&dimensions);
+
+        Ok(DataProfile {
+            sampled: ids.len(),
+            total,
+            seed,
+            dimensions,
+            anomalies,
+        })
+    }
+}
+
+/// Flag dimensions against the median over all of them, so a corpus
+/// with uniformly small or large values flags nothing
+fn find_anomalies(dimensions: &[DimensionStats]) -> Vec<DimensionAnomaly> {
+    let median = |values: Vec<f64>| {
+        let mut values = values;
+        values.sort_by(f64::total_cmp);
+        values[values.len() / 2]
+    };
+    let median_std = median(dimensions.iter().map(|d| d.std).collect());
+    let median_rms = median(dimensions.iter().map(DimensionStats::rms).collect());
+
+    dimensions
+        .iter()
+        .enumerate()
+        .filter_map(|(dimension, stats)| {
+            if stats.zero_fraction == 1.0 {
+                return Some(DimensionAnomaly::AllZero { dimension });
+            }
+            if stats.std <= median_std * CONSTANT_STD_RATIO {
+                return Some(DimensionAnomaly::Constant {
+                    dimension,
+                    value: stats.mean,
+                });
+            }
+            let ratio = stats.rms() / median_rms;
+            (median_rms > 0.0 && !(1.0 / EXTREME_MAGNITUDE_RATIO..=EXTREME_MAGNITUDE_RATIO).contains(&ratio))
+                .then_some(DimensionAnomaly::ExtremeMagnitude { dimension, ratio })
+        })
+        .collect()
+}
+
+impl DataProfile {
+    /// Compare this profile against an `old` one of the same corpus, for
+    /// drift detection. Profiles of different widths are a
+    /// [`KhadyotaError::DimensionMismatch`].
+    pub fn diff(&self, old: &DataProfile) -> Result<ProfileDiff> {
+        if self.dimensions.len() != old.dimensions.len() {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: old.dimensions.len(),
+                got: self.dimensions.len(),
+            });
+        }
+
+        let drifted = self
+            .dimensions
+            .iter()
+            .zip(&old.dimensions)
+            .enumerate()
+            .filter_map(|(dimension, (new, old))| {
+                let shift = new.mean - old.mean;
+                let mean_shift = if shift == 0.0 { 0.0 } else { shift / old.std };
+                let std_ratio = if new.std == old.std { 1.0 } else { new.std / old.std };
+                let drift = DimensionDrift {
+                    dimension,
+                    mean_shift,
+                    std_ratio,
+                    zero_fraction_change: new.zero_fraction - old.zero_fraction,
+                };
+                let moved = drift.mean_shift.abs() > DRIFT_MEAN_SHIFT
+                    || !(1.0 / DRIFT_STD_RATIO..=DRIFT_STD_RATIO).contains(&drift.std_ratio)
+                    || drift.zero_fraction_change.abs() > DRIFT_ZERO_FRACTION;
+                moved.then_some(drift)
+            })
+            .collect();
+
+        let flagged = |anomalies: &[DimensionAnomaly], dimension| anomalies.iter().any(|a| a.dimension() == dimension);
+        Ok(ProfileDiff {
+            drifted,
+            new_anomalies: self
+                .anomalies
+                .iter()
+                .filter(|a| !flagged(&old.anomalies, a.dimension()))
+                .cloned()
+                .collect(),
+            resolved_anomalies: old
+                .anomalies
+                .iter()
+                .filter(|a| !flagged(&self.anomalies, a.dimension()))
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuantizerKind};
+    use crate::harness::clustered_vectors;
+
+    const DEAD: usize = 3;
+    const LOUD: usize = 11;
+
+    fn db(rows: usize, plant: bool) -> VectorDB {
+        let mut db = VectorDB::new(Config {
+            dimensions: 16,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 2,
+            seed: Some(5),
+            normalize: false,
+            ..Default::default()
+        })
+        .unwrap();
+        let vectors = clustered_vectors(rows, 16, 4, 0.3, 9).into_iter().map(|mut v| {
+            if plant {
+                v[DEAD] = 0.0;
+                v[LOUD] *= 1000.0;
+            }
+            (v, None)
+        });
+        db.insert_batch(vectors).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_planted_dimensions_are_flagged() {
+        let db = db(2_000, true);
+        let profile = db.data_profile(500).unwrap();
+        assert_eq!((profile.sampled, profile.total, profile.seed), (500, 2_000, 5));
+        assert_eq!(profile.dimensions.len(), 16);
+        assert_eq!(profile.anomalies.len(), 2, "{:?}", profile.anomalies);
+        assert_eq!(profile.anomalies[0], DimensionAnomaly::AllZero { dimension: DEAD });
+        assert!(matches!(
+            profile.anomalies[1],
+            DimensionAnomaly::ExtremeMagnitude { dimension: LOUD, ratio } if ratio > 100.0
+        ));
+
+        let dead = &profile.dimensions[DEAD];
+        assert_eq!((dead.mean, dead.std, dead.min, dead.max, dead.zero_fraction), (0.0, 0.0, 0.0, 0.0, 1.0));
+
+        // A clean corpus flags nothing
+        assert!(self::db(2_000, false).data_profile(500).unwrap().anomalies.is_empty());
+    }
+
+    #[test]
+    fn test_statistics_match_a_direct_computation() {
+        let mut db = db(300, false);
+        for id in (0..300).step_by(4) {
+            db.delete(id).unwrap();
+        }
+        let profile = db.data_profile(0).unwrap();
+        assert_eq!(profile.sampled, 225);
+
+        let rows: Vec<Vec<f32>> = (0..300u32).filter(|id| id % 4 != 0).map(|id| db.get(id).unwrap().vector).collect();
+        for (j, stats) in profile.dimensions.iter().enumerate() {
+            let values: Vec<f64> = rows.iter().map(|r| f64::from(r[j])).collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            approx::assert_relative_eq!(stats.mean, mean, epsilon = 1e-9);
+            approx::assert_relative_eq!(stats.std, var.sqrt(), epsilon = 1e-6);
+            assert_eq!(stats.min, rows.iter().map(|r| r[j]).fold(f32::INFINITY, f32::min));
+            assert_eq!(stats.max, rows.iter().map(|r| r[j]).fold(f32::NEG_INFINITY, f32::max));
+        }
+
+        // Samples are deterministic and serialize for dashboards
+        let sampled = db.data_profile(100).unwrap();
+        assert_eq!(sampled, db.data_profile(100).unwrap());
+        let bytes = rmp_serde::to_vec_named(&sampled).unwrap();
+        assert_eq!(rmp_serde::from_slice::<DataProfile>(&bytes).unwrap(), sampled);
+        let json: serde_json::Value = serde_json::to_value(&sampled).unwrap();
+        assert_eq!(json["dimensions"].as_array().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_diff_reports_drift_and_new_anomalies() {
+        let db = db(1_000, false);
+        let before = db.data_profile(400).unwrap();
+        assert!(db.profile_diff(&before).unwrap().is_empty());
+
+        let mut after = before.clone();
+        after.dimensions[2].mean += 3.0 * after.dimensions[2].std;
+        after.dimensions[7].std *= 4.0;
+        after.anomalies.push(DimensionAnomaly::AllZero { dimension: 9 });
+        let diff = after.diff(&before).unwrap();
+        assert_eq!(diff.drifted.iter().map(|d| d.dimension).collect::<Vec<_>>(), [2, 7]);
+        approx::assert_relative_eq!(diff.drifted[0].mean_shift, 3.0, epsilon = 1e-9);
+        approx::assert_relative_eq!(diff.drifted[1].std_ratio, 4.0, epsilon = 1e-9);
+        assert_eq!(diff.new_anomalies, [DimensionAnomaly::AllZero { dimension: 9 }]);
+        assert_eq!(before.diff(&after).unwrap().resolved_anomalies, diff.new_anomalies);
+
+        let mut narrow = self::db(10, false).data_profile(0).unwrap();
+        narrow.dimensions.pop();
+        assert!(matches!(narrow.diff(&before), Err(KhadyotaError::DimensionMismatch { expected: 16, got: 15 })));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::indexing::ivf::IVFStats;
+use std::path::PathBuf;
+
+/// A step of index building or persistence, reported to a
+/// [`ProgressCallback`]
+#[derive(Debug, Clone)]
+pub enum BuildEvent {
+    /// [`VectorDB::build_index`](crate::VectorDB::build_index) started
+    IndexBuildStarted { vectors: usize, dimensions: usize },
+
+    /// Product quantization training started
+    PqTrainingStarted {
+        dimensions: usize,
+        subvectors: usize,
+        subvector_size: usize,
+        training_vectors: usize,
+    },
+
+    /// `done` of `total` codebooks are trained. Codebooks train in
+    /// parallel, so this counts completions rather than naming one.
+    CodebookTrained { done: usize, total: usize },
+
+    /// Every codebook is trained and every vector encoded
+    PqTrained,
+
+    /// The config asked for more clusters than there are rows to train
+    /// them on, so the index was built with `used`, one per row, instead
+    /// of `requested`
+    ClustersClamped { requested: usize, used: usize },
+
+    /// IVF clustering into `clusters` lists started
+    IvfBuildStarted { clusters: usize },
+
+    /// One k-means assignment pass, from PQ or IVF training. Passes of
+    /// codebooks trained in parallel interleave.
+    KMeansIteration { iter: usize, inertia: f32 },
+
+    /// K-means stopped, either converged or at its iteration limit
+    KMeansFinished { iterations: usize, inertia: f32, converged: bool },
+
+    /// The IVF index is built and in place
+    IndexBuilt { stats: IVFStats },
+
+    /// A flat index over `rows` is copied and in place, under
+    /// [`IndexKind::Flat`](crate::config::IndexKind::Flat)
+    FlatIndexBuilt { rows: usize },
+
+    /// [`VectorDB::save`](crate::VectorDB::save) wrote `bytes` to `path`
+    Saved { path: PathBuf, bytes: u64 },
+}
+
+/// Receives [`BuildEvent`]s as long operations run.
+///
+/// The library prints nothing itself; install one with
+/// [`VectorDB::set_progress_callback`](crate::VectorDB::set_progress_callback)
+/// to log, draw a progress bar or print. Events arrive on the threads doing
+/// the work, which may be several rayon workers at once, and each waits
+/// until `on_event` returns.
+pub trait ProgressCallback: Send + Sync {
+    fn on_event(&self, event: BuildEvent);
+
+    /// Whether the work reporting here should stop. Checked between
+    /// k-means iterations and codebook trainings; a build that sees it
+    /// fails with [`KhadyotaError::Cancelled`](crate::error::KhadyotaError::Cancelled)
+    /// and leaves the index in place as it was.
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+impl<F: Fn(BuildEvent) + Send + Sync> ProgressCallback for F {
+    fn on_event(&self, event: BuildEvent) {
+        self(event)
+    }
+}
+
+/// Ignores every event; what functions without a callback report to
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Silent;
+
+impl ProgressCallback for Silent {
+    fn on_event(&self, _event: BuildEvent) {}
+}
+
+/// Passes on cancellation from `0` but none of the events sent to it, for
+/// work whose progress would mislead, such as OPQ's trial codebooks
+pub(crate) struct Muted<'a>(pub(crate) &'a dyn ProgressCallback);
+
+impl ProgressCallback for Muted<'_> {
+    fn on_event(&self, _event: BuildEvent) {}
+
+    fn cancelled(&self) -> bool {
+        self.0.cancelled()
+    }
+}
+
+/// Prints events to stdout in the format the library used to print
+/// unconditionally, for examples and command-line tools
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrintProgress;
+
+impl ProgressCallback for PrintProgress {
+    fn on_event(&self, event: BuildEvent) {
+        match event {
+            BuildEvent::IndexBuildStarted { vectors, dimensions } => {
+                println!("\n=== Building Search Index ===");
+                println!("Vectors: {}", vectors);
+                println!("Dimensions: {}", dimensions);
+            }
+            BuildEvent::PqTrainingStarted {
+                dimensions,
+                subvectors,
+                subvector_size,
+                training_vectors,
+            } => {
+                println!("\nTraining PQ codec:");
+                println!("  Dimensions: {}", dimensions);
+                println!("  Subvectors: {}", subvectors);
+                println!("  Subvector size: {}", subvector_size);
+                println!("  Training vectors: {}", training_vectors);
+            }
+            BuildEvent::CodebookTrained { done, total } => println!("Trained codebook {}/{}", done, total),
+            BuildEvent::PqTrained => println!("✓ PQ training complete"),
+            BuildEvent::ClustersClamped { requested, used } => {
+                println!("⚠ {} clusters requested for {} training rows; building {}", requested, used, used)
+            }
+            BuildEvent::IvfBuildStarted { clusters } => {
+                println!("\nBuilding IVF index with {} clusters...", clusters)
+            }
+            BuildEvent::KMeansIteration { .. } => {}
+            BuildEvent::KMeansFinished {
+                iterations,
+                inertia,
+                converged,
+            } => {
+                if converged {
+                    println!("  K-means converged at iteration {}. Inertia: {:.2}", iterations, inertia);
+                } else {
+                    println!("  K-means stopped after {} iterations. Inertia: {:.2}", iterations, inertia);
+                }
+            }
+            BuildEvent::IndexBuilt { stats } => println!("\n{}\n\n✓ Index built successfully!\n", stats),
+            BuildEvent::FlatIndexBuilt { rows } => println!("✓ Flat index built over {} rows", rows),
+            BuildEvent::Saved { path, bytes } => println!("✓ Database saved to {:?} ({} bytes)", path, bytes),
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use super::kmeans::kmeans;
+use super::kmeans::{kmeans_with_params, KMeansParams};
+use crate::config::DistanceMetric;
+use crate::distance::{dot_product, euclidean_distance_squared, hamming_distance, manhattan_distance};
+use crate::progress::{ProgressCallback, Silent};
 
 /// A codebook is a set of learned centroids for quantization
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -10,19 +13,35 @@ pub struct Codebook {
 impl Codebook {
     /// Train a codebook from training vectors
     pub fn train(training_vectors: &[Vec<f32>], num_centroids: usize) -> Self {
+        Self::train_seeded(training_vectors, num_centroids, None)
+    }
+    
+    /// Train a codebook with a fixed k-means seed
+    pub fn train_seeded(training_vectors: &[Vec<f32>], num_centroids: usize, seed: Option<u64>) -> Self {
+        Self::train_with_progress(training_vectors, num_centroids, seed, &Silent)
+    }
+    
+    /// [`Codebook::train_seeded`], reporting k-means iterations to `progress`
+    pub fn train_with_progress(
+        training_vectors: &[Vec<f32>],
+        num_centroids: usize,
+        seed: Option<u64>,
+        progress: &dyn ProgressCallback,
+    ) -> Self {
+        Self::train_with_params(training_vectors, num_centroids, seed, &KMeansParams::default(), progress)
+    }
+    
+    /// [`Codebook::train_with_progress`] with k-means run under `kmeans`
+    pub fn train_with_params(
+        training_vectors: &[Vec<f32>],
+        num_centroids: usize,
+        seed: Option<u64>,
+        kmeans: &KMeansParams,
+        progress: &dyn ProgressCallback,
+    ) -> Self {
         assert!(!training_vectors.is_empty());
         let dimensions = training_vectors[0].len();
-        
-        println!(
-            "Training codebook: {} centroids, {} dims, {} training vectors",
-            num_centroids,
-            dimensions,
-            training_vectors.len()
-        );
-        
-        let result = kmeans(training_vectors, num_centroids, 100, 0.001);
-        
-        println!("Codebook training complete. Inertia: {:.4}", result.inertia);
+        let result = kmeans_with_params(training_vectors, num_centroids, kmeans, seed, progress);
         
         Self {
             centroids: result.centroids,
@@ -31,7 +50,7 @@ impl Codebook {
     }
     
     /// Encode a vector to its nearest centroid index
-    pub fn encode(&self, vector: &[f32]) -> u8 {
+    pub fn encode(&self, vector: &[f32]) -> u16 {
         assert_eq!(vector.len(), self.dimensions);
         
         self.centroids
@@ -43,27 +62,30 @@ impl Codebook {
             })
             .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
             .unwrap()
-            .0 as u8
+            .0 as u16
     }
     
     /// Decode a centroid index back to a vector
-    pub fn decode(&self, code: u8) -> &[f32] {
+    pub fn decode(&self, code: u16) -> &[f32] {
         &self.centroids[code as usize]
     }
     
-    /// Compute distance from query vector to a centroid
-    pub fn distance_to_centroid(&self, query: &[f32], code: u8) -> f32 {
-        euclidean_distance_squared(query, &self.centroids[code as usize])
+    /// This subvector's term of the distance from `query` to a centroid:
+    /// the squared L2 distance for Euclidean, the inner product for cosine
+    /// and dot product, and the metric itself for Manhattan and Hamming,
+    /// which add up across subvectors.
+    /// [`PQCodec`](super::PQCodec) sums the terms and finishes the distance.
+    pub fn distance_to_centroid(&self, query: &[f32], code: u16, metric: DistanceMetric) -> f32 {
+        let centroid = &self.centroids[code as usize];
+        match metric {
+            DistanceMetric::Euclidean => euclidean_distance_squared(query, centroid),
+            DistanceMetric::Cosine | DistanceMetric::DotProduct => dot_product(query, centroid),
+            DistanceMetric::Manhattan => manhattan_distance(query, centroid),
+            DistanceMetric::Hamming => hamming_distance(query, centroid),
+        }
     }
 }
 
-fn euclidean_distance_squared(a: &[f32], b: &[f32]) -> f32 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| (x - y).powi(2))
-        .sum()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -82,12 +104,12 @@ mod tests {
         assert_eq!(codebook.centroids.len(), 2);
         
         // Vectors close to [0,0] should map to same code
-        let code1 = codebook.encode(&vec![0.0, 0.0]);
-        let code2 = codebook.encode(&vec![0.1, 0.1]);
+        let code1 = codebook.encode(&[0.0, 0.0]);
+        let code2 = codebook.encode(&[0.1, 0.1]);
         assert_eq!(code1, code2);
         
         // Vectors close to [10,10] should map to different code
-        let code3 = codebook.encode(&vec![10.0, 10.0]);
+        let code3 = codebook.encode(&[10.0, 10.0]);
         assert_ne!(code1, code3);
     }
 }
\ No newline at end of file
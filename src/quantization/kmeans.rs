@@ -1,5 +1,52 @@
+use crate::distance::euclidean_distance_squared;
+use crate::progress::{BuildEvent, ProgressCallback, Silent};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How a k-means run picks its starting centroids
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum InitMethod {
+    /// Each centroid drawn with probability proportional to its squared
+    /// distance from those already chosen, spreading them over the data
+    #[default]
+    KMeansPlusPlus,
+
+    /// `k` distinct vectors drawn uniformly: no seeding pass over the
+    /// data, but more iterations to settle on clumped data
+    Random,
+}
+
+/// Stopping rule, seeding and restarts of a k-means clustering, set for
+/// IVF and PQ training by [`Config::ivf_kmeans`](crate::config::Config::ivf_kmeans)
+/// and [`Config::pq_kmeans`](crate::config::Config::pq_kmeans)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KMeansParams {
+    /// Most assignment passes a run makes. At least 1.
+    pub max_iterations: usize,
+
+    /// A run stops once a pass changes the inertia by less than this
+    pub tolerance: f32,
+
+    pub init: InitMethod,
+
+    /// Runs made from different starting centroids, keeping the one of
+    /// lowest inertia. Each costs a full clustering. At least 1.
+    pub n_redo: usize,
+}
+
+impl Default for KMeansParams {
+    fn default() -> Self {
+        Self {
+            max_iterations: 100,
+            tolerance: 0.001,
+            init: InitMethod::KMeansPlusPlus,
+            n_redo: 1,
+        }
+    }
+}
 
 /// K-means clustering result
 #[derive(Debug, Clone)]
@@ -7,6 +54,13 @@ pub struct KMeansResult {
     pub centroids: Vec<Vec<f32>>,
     pub assignments: Vec<usize>,
     pub inertia: f32,
+
+    /// Assignment passes the returned run made
+    pub iterations: usize,
+
+    /// Whether the returned run stopped on its tolerance rather than its
+    /// iteration cap
+    pub converged: bool,
 }
 
 /// Run K-means clustering
@@ -15,115 +69,277 @@ pub fn kmeans(
     k: usize,
     max_iterations: usize,
     tolerance: f32,
+) -> KMeansResult {
+    kmeans_seeded(vectors, k, max_iterations, tolerance, None)
+}
+
+/// Run K-means clustering; a `seed` makes the result reproducible
+pub fn kmeans_seeded(
+    vectors: &[Vec<f32>],
+    k: usize,
+    max_iterations: usize,
+    tolerance: f32,
+    seed: Option<u64>,
+) -> KMeansResult {
+    kmeans_with_progress(vectors, k, max_iterations, tolerance, seed, &Silent)
+}
+
+/// [`kmeans_seeded`], reporting each iteration to `progress`
+pub fn kmeans_with_progress(
+    vectors: &[Vec<f32>],
+    k: usize,
+    max_iterations: usize,
+    tolerance: f32,
+    seed: Option<u64>,
+    progress: &dyn ProgressCallback,
+) -> KMeansResult {
+    let params = KMeansParams {
+        max_iterations,
+        tolerance,
+        ..KMeansParams::default()
+    };
+    kmeans_with_params(vectors, k, &params, seed, progress)
+}
+
+/// K-means under `params`, reporting each iteration of every run to
+/// `progress`. Runs after the first continue from the first's random
+/// state, so the first is the clustering `n_redo: 1` returns, and more
+/// runs never return a higher inertia.
+pub fn kmeans_with_params(
+    vectors: &[Vec<f32>],
+    k: usize,
+    params: &KMeansParams,
+    seed: Option<u64>,
+    progress: &dyn ProgressCallback,
 ) -> KMeansResult {
     assert!(!vectors.is_empty(), "Cannot cluster empty vectors");
     assert!(k <= vectors.len(), "K must be <= number of vectors");
     
-    let dimensions = vectors[0].len();
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
     
-    // Initialize centroids using k-means++
-    let mut centroids = kmeans_plus_plus_init(vectors, k);
+    let mut best = kmeans_run(vectors, k, params, &mut rng, progress);
+    for _ in 1..params.n_redo {
+        if progress.cancelled() {
+            break;
+        }
+        let result = kmeans_run(vectors, k, params, &mut rng, progress);
+        if result.inertia < best.inertia {
+            best = result;
+        }
+    }
+    best
+}
+
+/// One k-means run from fresh starting centroids
+fn kmeans_run(
+    vectors: &[Vec<f32>],
+    k: usize,
+    params: &KMeansParams,
+    rng: &mut StdRng,
+    progress: &dyn ProgressCallback,
+) -> KMeansResult {
+    let dimensions = vectors[0].len();
+    let mut centroids = match params.init {
+        InitMethod::KMeansPlusPlus => kmeans_plus_plus_init(vectors, k, rng),
+        InitMethod::Random => random_init(vectors, k, rng),
+    };
     let mut assignments = vec![0; vectors.len()];
     let mut prev_inertia = f32::INFINITY;
+    let mut iterations = 0;
+    let mut converged = false;
     
-    for iteration in 0..max_iterations {
-        // Assignment step: assign each vector to nearest centroid
+    for iteration in 0..params.max_iterations {
+        if progress.cancelled() {
+            break;
+        }
+        iterations = iteration + 1;
+        // Assignment step: each vector's nearest centroid, in parallel.
+        // Inertia is summed in vector order so it does not depend on
+        // the thread count.
+        let nearest: Vec<(usize, f32)> = vectors
+            .par_iter()
+            .map(|vector| find_nearest_centroid(vector, &centroids))
+            .collect();
         let mut inertia = 0.0;
-        for (i, vector) in vectors.iter().enumerate() {
-            let (nearest_idx, distance) = find_nearest_centroid(vector, &centroids);
-            assignments[i] = nearest_idx;
-            inertia += distance * distance;
+        for (assigned, (nearest_idx, distance_squared)) in assignments.iter_mut().zip(nearest) {
+            *assigned = nearest_idx;
+            inertia += distance_squared;
         }
         
+        progress.on_event(BuildEvent::KMeansIteration { iter: iteration, inertia });
+        
         // Check convergence
-        if (prev_inertia - inertia).abs() < tolerance {
-            println!("K-means converged at iteration {}", iteration);
+        if (prev_inertia - inertia).abs() < params.tolerance {
+            converged = true;
             break;
         }
         prev_inertia = inertia;
         
-        // Update step: recompute centroids
-        let mut new_centroids = vec![vec![0.0; dimensions]; k];
-        let mut counts = vec![0usize; k];
-        
-        for (vector, &cluster) in vectors.iter().zip(assignments.iter()) {
-            counts[cluster] += 1;
-            for (j, &val) in vector.iter().enumerate() {
-                new_centroids[cluster][j] += val;
-            }
+        // Update step: average each cluster's members in parallel, one
+        // cluster per task, summing members in vector order
+        let mut members = vec![Vec::new(); k];
+        for (i, &cluster) in assignments.iter().enumerate() {
+            members[cluster].push(i);
         }
-        
-        // Average to get new centroids
-        for (centroid, count) in new_centroids.iter_mut().zip(counts.iter()) {
-            if *count > 0 {
+        let mut new_centroids: Vec<Option<Vec<f32>>> = members
+            .par_iter()
+            .map(|ids| {
+                if ids.is_empty() {
+                    return None;
+                }
+                let mut centroid = vec![0.0; dimensions];
+                for &i in ids {
+                    for (sum, &val) in centroid.iter_mut().zip(&vectors[i]) {
+                        *sum += val;
+                    }
+                }
                 for val in centroid.iter_mut() {
-                    *val /= *count as f32;
+                    *val /= ids.len() as f32;
                 }
-            }
-        }
+                Some(centroid)
+            })
+            .collect();
         
         // Handle empty clusters by reinitializing from random point
-        for (i, count) in counts.iter().enumerate() {
-            if *count == 0 {
-                let random_vec = vectors.choose(&mut thread_rng()).unwrap();
-                new_centroids[i] = random_vec.clone();
-            }
+        for centroid in new_centroids.iter_mut().filter(|c| c.is_none()) {
+            *centroid = Some(vectors.choose(rng).unwrap().clone());
         }
         
-        centroids = new_centroids;
+        centroids = new_centroids.into_iter().flatten().collect();
     }
     
     let inertia = compute_inertia(vectors, &centroids, &assignments);
+    progress.on_event(BuildEvent::KMeansFinished {
+        iterations,
+        inertia,
+        converged,
+    });
     
     KMeansResult {
         centroids,
         assignments,
         inertia,
+        iterations,
+        converged,
+    }
+}
+
+/// Mini-batch k-means (Sculley, 2010): each of `iterations` steps
+/// assigns `batch_size` randomly drawn vectors and moves their centroids
+/// towards them, at a rate of one over the points each centroid has
+/// absorbed so far.
+///
+/// A step costs `batch_size * k` distances instead of a full pass, so
+/// this suits inputs too large to iterate [`kmeans_seeded`] over, at some
+/// cost in inertia. Seeding is k-means++ over all vectors, and the
+/// returned assignments cover every vector. The result counts every
+/// step as an iteration and is never `converged`.
+pub fn kmeans_minibatch(
+    vectors: &[Vec<f32>],
+    k: usize,
+    batch_size: usize,
+    iterations: usize,
+    seed: Option<u64>,
+) -> KMeansResult {
+    assert!(!vectors.is_empty(), "Cannot cluster empty vectors");
+    assert!(k <= vectors.len(), "K must be <= number of vectors");
+    
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    
+    let mut centroids = kmeans_plus_plus_init(vectors, k, &mut rng);
+    let mut absorbed = vec![0usize; k];
+    
+    for _ in 0..iterations {
+        let batch: Vec<usize> = (0..batch_size).map(|_| rng.gen_range(0..vectors.len())).collect();
+        let nearest: Vec<usize> = batch
+            .par_iter()
+            .map(|&i| find_nearest_centroid(&vectors[i], &centroids).0)
+            .collect();
+        
+        // Applied in draw order, so the result does not depend on the
+        // thread count
+        for (&i, &cluster) in batch.iter().zip(&nearest) {
+            absorbed[cluster] += 1;
+            let rate = 1.0 / absorbed[cluster] as f32;
+            for (c, &x) in centroids[cluster].iter_mut().zip(&vectors[i]) {
+                *c += rate * (x - *c);
+            }
+        }
+    }
+    
+    let assignments: Vec<usize> = vectors
+        .par_iter()
+        .map(|vector| find_nearest_centroid(vector, &centroids).0)
+        .collect();
+    let inertia = compute_inertia(vectors, &centroids, &assignments);
+    
+    KMeansResult {
+        centroids,
+        assignments,
+        inertia,
+        iterations,
+        converged: false,
     }
 }
 
 /// K-means++ initialization for better starting centroids
-fn kmeans_plus_plus_init(vectors: &[Vec<f32>], k: usize) -> Vec<Vec<f32>> {
-    let mut rng = thread_rng();
+fn kmeans_plus_plus_init(vectors: &[Vec<f32>], k: usize, rng: &mut StdRng) -> Vec<Vec<f32>> {
     let mut centroids = Vec::with_capacity(k);
     
     // Choose first centroid randomly
-    let first = vectors.choose(&mut rng).unwrap().clone();
+    let first = vectors.choose(rng).unwrap().clone();
     centroids.push(first);
     
     // Choose remaining centroids with probability proportional to distance²
+    // to the nearest one chosen so far, kept up to date as each is added
+    let mut distances: Vec<f32> = vec![f32::INFINITY; vectors.len()];
     for _ in 1..k {
-        let mut distances: Vec<f32> = vectors
-            .iter()
-            .map(|v| {
-                let (_, dist) = find_nearest_centroid(v, &centroids);
-                dist * dist
-            })
-            .collect();
+        let newest = centroids.last().unwrap();
+        distances.par_iter_mut().zip(vectors).for_each(|(nearest, v)| {
+            *nearest = nearest.min(euclidean_distance_squared(v, newest));
+        });
         
         // Weighted random selection
         let total: f32 = distances.iter().sum();
-        let mut threshold = rand::random::<f32>() * total;
+        let mut threshold = rng.r#gen::<f32>() * total;
         
+        // Rounding can leave a sliver of the threshold unspent; fall back to
+        // the last point so exactly k centroids are always chosen
+        let mut chosen = vectors.len() - 1;
         for (i, &dist) in distances.iter().enumerate() {
             threshold -= dist;
             if threshold <= 0.0 {
-                centroids.push(vectors[i].clone());
+                chosen = i;
                 break;
             }
         }
+        centroids.push(vectors[chosen].clone());
     }
     
     centroids
 }
 
-/// Find nearest centroid and its distance
+/// `k` distinct vectors chosen uniformly at random
+fn random_init(vectors: &[Vec<f32>], k: usize, rng: &mut StdRng) -> Vec<Vec<f32>> {
+    rand::seq::index::sample(rng, vectors.len(), k)
+        .into_iter()
+        .map(|i| vectors[i].clone())
+        .collect()
+}
+
+/// Find nearest centroid and its squared distance
 fn find_nearest_centroid(vector: &[f32], centroids: &[Vec<f32>]) -> (usize, f32) {
     centroids
         .iter()
         .enumerate()
         .map(|(i, centroid)| {
-            let dist = euclidean_distance(vector, centroid);
+            let dist = euclidean_distance_squared(vector, centroid);
             (i, dist)
         })
         .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
@@ -139,22 +355,10 @@ fn compute_inertia(
     vectors
         .iter()
         .zip(assignments.iter())
-        .map(|(vec, &cluster)| {
-            let dist = euclidean_distance(vec, &centroids[cluster]);
-            dist * dist
-        })
+        .map(|(vec, &cluster)| euclidean_distance_squared(vec, &centroids[cluster]))
         .sum()
 }
 
-/// Euclidean distance between two vectors
-fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| (x - y).powi(2))
-        .sum::<f32>()
-        .sqrt()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,4 +390,176 @@ mod tests {
         // Centroids should be roughly at [0,0] and [10,10]
         // (order may vary)
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_dispatched_kernel_matches_scalar() {
+        use crate::distance::scalar::euclidean_distance_squared_scalar;
+        
+        // Lengths on and off the 8-wide SIMD path
+        for len in (1..=40).chain([128, 512]) {
+            let a: Vec<f32> = (0..len).map(|i| (i as f32 * 0.37).sin()).collect();
+            let b: Vec<f32> = (0..len).map(|i| (i as f32 * 0.11).cos()).collect();
+            
+            let scalar = euclidean_distance_squared_scalar(&a, &b);
+            let dispatched = euclidean_distance_squared(&a, &b);
+            assert!((scalar - dispatched).abs() <= 1e-5 * scalar.max(1.0), "len {}", len);
+        }
+    }
+    
+    #[test]
+    fn test_seeded_clustering_matches_scalar_reference() {
+        use crate::distance::scalar::euclidean_distance_squared_scalar;
+        
+        // Four well-separated blobs in 16 dims, which takes the SIMD path
+        let vectors: Vec<Vec<f32>> = (0..400)
+            .map(|i| {
+                let blob = (i % 4) as f32 * 10.0;
+                (0..16).map(|j| blob + ((i * 16 + j) as f32).sin() * 0.5).collect()
+            })
+            .collect();
+        
+        let result = kmeans_seeded(&vectors, 4, 100, 0.001, Some(42));
+        let again = kmeans_seeded(&vectors, 4, 100, 0.001, Some(42));
+        assert_eq!(result.centroids, again.centroids);
+        assert_eq!(result.assignments, again.assignments);
+        
+        // Every assignment and the inertia agree with the scalar kernel
+        let mut inertia = 0.0;
+        for (vector, &assigned) in vectors.iter().zip(&result.assignments) {
+            let distances: Vec<f32> = result.centroids
+                .iter()
+                .map(|c| euclidean_distance_squared_scalar(vector, c))
+                .collect();
+            let nearest = (0..distances.len())
+                .min_by(|&a, &b| distances[a].partial_cmp(&distances[b]).unwrap())
+                .unwrap();
+            assert_eq!(assigned, nearest);
+            inertia += distances[nearest];
+        }
+        assert!((result.inertia - inertia).abs() <= 1e-4 * inertia);
+        
+        // Points from the same blob share a cluster
+        for (i, &assigned) in result.assignments.iter().enumerate() {
+            assert_eq!(assigned, result.assignments[i % 4]);
+        }
+    }
+    
+    /// The single-threaded algorithm the parallel one replaced
+    fn sequential_kmeans(vectors: &[Vec<f32>], k: usize, max_iterations: usize, seed: u64) -> KMeansResult {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut centroids = vec![vectors.choose(&mut rng).unwrap().clone()];
+        for _ in 1..k {
+            let distances: Vec<f32> = vectors.iter().map(|v| find_nearest_centroid(v, &centroids).1).collect();
+            let mut threshold = rng.r#gen::<f32>() * distances.iter().sum::<f32>();
+            let mut chosen = vectors.len() - 1;
+            for (i, &dist) in distances.iter().enumerate() {
+                threshold -= dist;
+                if threshold <= 0.0 {
+                    chosen = i;
+                    break;
+                }
+            }
+            centroids.push(vectors[chosen].clone());
+        }
+        
+        let mut assignments = vec![0; vectors.len()];
+        let mut prev_inertia = f32::INFINITY;
+        for _ in 0..max_iterations {
+            let mut inertia = 0.0;
+            for (i, vector) in vectors.iter().enumerate() {
+                let (nearest, distance) = find_nearest_centroid(vector, &centroids);
+                assignments[i] = nearest;
+                inertia += distance;
+            }
+            if (prev_inertia - inertia).abs() < 0.001 {
+                break;
+            }
+            prev_inertia = inertia;
+            
+            let mut sums = vec![vec![0.0; vectors[0].len()]; k];
+            let mut counts = vec![0usize; k];
+            for (vector, &cluster) in vectors.iter().zip(&assignments) {
+                counts[cluster] += 1;
+                for (sum, &val) in sums[cluster].iter_mut().zip(vector) {
+                    *sum += val;
+                }
+            }
+            for (sum, &count) in sums.iter_mut().zip(&counts) {
+                if count == 0 {
+                    *sum = vectors.choose(&mut rng).unwrap().clone();
+                } else {
+                    sum.iter_mut().for_each(|v| *v /= count as f32);
+                }
+            }
+            centroids = sums;
+        }
+        
+        let inertia = compute_inertia(vectors, &centroids, &assignments);
+        KMeansResult { centroids, assignments, inertia, iterations: max_iterations, converged: false }
+    }
+    
+    #[test]
+    fn test_parallel_matches_sequential() {
+        // Overlapping blobs, so clustering takes many passes
+        let vectors: Vec<Vec<f32>> = (0..3_000)
+            .map(|i| (0..24).map(|j| ((i * 24 + j) as f32 * 0.618).sin() + (i % 7) as f32 * 0.3).collect())
+            .collect();
+        
+        let expected = sequential_kmeans(&vectors, 40, 50, 9);
+        for threads in [1, 4] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let result = pool.install(|| kmeans_seeded(&vectors, 40, 50, 0.001, Some(9)));
+            assert_eq!(result.inertia, expected.inertia, "{} threads", threads);
+            assert_eq!(result.assignments, expected.assignments);
+            assert_eq!(result.centroids, expected.centroids);
+        }
+    }
+    
+    #[test]
+    fn test_redo_never_raises_inertia() {
+        let vectors = crate::harness::clustered_vectors(1_500, 8, 10, 0.6, 5);
+        for seed in 0..8 {
+            let once = kmeans_with_params(&vectors, 10, &KMeansParams::default(), Some(seed), &Silent);
+            let params = KMeansParams { n_redo: 3, ..KMeansParams::default() };
+            let redone = kmeans_with_params(&vectors, 10, &params, Some(seed), &Silent);
+            assert!(redone.inertia <= once.inertia, "seed {}: {} > {}", seed, redone.inertia, once.inertia);
+        }
+    }
+    
+    #[test]
+    fn test_random_init_and_iteration_counts() {
+        // With k == n every vector is its own centroid
+        let vectors: Vec<Vec<f32>> = (0..5).map(|i| vec![i as f32, (i * i) as f32]).collect();
+        let params = KMeansParams { init: InitMethod::Random, ..KMeansParams::default() };
+        let result = kmeans_with_params(&vectors, 5, &params, Some(3), &Silent);
+        assert_eq!(result.inertia, 0.0);
+        let mut assigned = result.assignments.clone();
+        assigned.sort_unstable();
+        assert_eq!(assigned, [0, 1, 2, 3, 4]);
+        assert!(result.converged && result.iterations == 2);
+        
+        // A cap the clustering cannot settle within is reported as such
+        let vectors = crate::harness::clustered_vectors(2_000, 8, 16, 1.0, 6);
+        let capped = KMeansParams { max_iterations: 2, tolerance: 0.0, ..KMeansParams::default() };
+        let result = kmeans_with_params(&vectors, 16, &capped, Some(1), &Silent);
+        assert!(!result.converged && result.iterations == 2);
+    }
+    
+    #[test]
+    fn test_minibatch_approaches_full_kmeans() {
+        let vectors = crate::harness::clustered_vectors(20_000, 16, 12, 0.2, 4);
+        let full = kmeans_seeded(&vectors, 12, 100, 0.001, Some(2));
+        let minibatch = kmeans_minibatch(&vectors, 12, 256, 100, Some(2));
+        
+        assert_eq!(minibatch.assignments.len(), vectors.len());
+        assert!(
+            minibatch.inertia <= full.inertia * 1.2,
+            "mini-batch inertia {} against {}",
+            minibatch.inertia,
+            full.inertia
+        );
+        
+        let again = kmeans_minibatch(&vectors, 12, 256, 100, Some(2));
+        assert_eq!(again.centroids, minibatch.centroids);
+    }
+}
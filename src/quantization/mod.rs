@@ -1,7 +1,15 @@
 pub mod codebook;
 pub mod kmeans;
+pub mod opq;
 pub mod product_quantization;
+pub mod quantizer;
+pub mod scalar_quantization;
 
 pub use codebook::Codebook;
-pub use kmeans::{kmeans, KMeansResult};
-pub use product_quantization::PQCodec;
\ No newline at end of file
+pub use kmeans::{
+    kmeans, kmeans_minibatch, kmeans_seeded, kmeans_with_params, kmeans_with_progress, InitMethod, KMeansParams, KMeansResult,
+};
+pub use opq::OPQCodec;
+pub use product_quantization::PQCodec;
+pub use quantizer::Quantizer;
+pub use scalar_quantization::SQCodec;
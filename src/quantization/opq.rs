@@ -0,0 +1,368 @@
+//! Optimized product quantization: an orthogonal rotation learned so that
+//! the subvectors PQ splits vectors into cut along, rather than across,
+//! blocks of correlated dimensions.
+//!
+//! Training alternates between the two halves of the problem, as in Ge
+//! et al.'s non-parametric OPQ: with the rotation fixed, train PQ
+//! codebooks on the rotated vectors; with the codebooks fixed, find the
+//! rotation taking the vectors nearest their reconstructions, which is an
+//! orthogonal Procrustes problem solved by an SVD.
+//!
+//! Rotations preserve dot products and distances, so a query rotated the
+//! same way scores against the codes exactly as it would against the
+//! rotated rows.
+
+use super::kmeans::KMeansParams;
+use super::PQCodec;
+use crate::config::{DistanceMetric, DEFAULT_PQ_BITS};
+use crate::distance::normalized;
+use crate::error::Result;
+use crate::progress::{Muted, ProgressCallback, Silent};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Rounds of PQ training and rotation updates before the final codebooks
+/// are trained
+pub const OPQ_ITERATIONS: usize = 8;
+
+/// Jacobi sweeps [`orthogonal_factor`] makes at most; it usually
+/// converges in well under half of them
+const MAX_SWEEPS: usize = 30;
+
+/// PQ codec applied to vectors after an orthogonal rotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OPQCodec {
+    /// `dimensions x dimensions`, row-major. Codes are of
+    /// `rotation * vector`.
+    pub rotation: Vec<f32>,
+
+    /// Codebooks over the rotated vectors
+    pub pq: PQCodec,
+}
+
+impl OPQCodec {
+    /// Learn a rotation and codebooks over it, in [`OPQ_ITERATIONS`]
+    /// rounds. Arguments are as for [`PQCodec::train_with_progress`],
+    /// which reports the final codebooks' training to `progress`; the
+    /// rounds before it only check it for cancellation.
+    pub fn train_with_progress(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+        bits: usize,
+        progress: &dyn ProgressCallback,
+    ) -> Result<Self> {
+        let kmeans = KMeansParams::default();
+        Self::train_with_params(training_vectors, num_subvectors, seed, metric, bits, &kmeans, progress)
+    }
+
+    /// [`OPQCodec::train_with_progress`] with every codebook's k-means,
+    /// in each round, run under `kmeans`
+    pub fn train_with_params(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+        bits: usize,
+        kmeans: &KMeansParams,
+        progress: &dyn ProgressCallback,
+    ) -> Result<Self> {
+        let normalized_vectors: Vec<Vec<f32>>;
+        let training_vectors = if metric == DistanceMetric::Cosine {
+            normalized_vectors = training_vectors.iter().map(|v| normalized(v)).collect();
+            &normalized_vectors
+        } else {
+            training_vectors
+        };
+        Self::train_prepared(training_vectors, num_subvectors, seed, metric, bits, kmeans, progress)
+    }
+
+    /// [`OPQCodec::train_with_progress`] with 8-bit codes, reporting nothing
+    pub fn train_for_metric(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+    ) -> Result<Self> {
+        Self::train_with_progress(training_vectors, num_subvectors, seed, metric, DEFAULT_PQ_BITS, &Silent)
+    }
+
+    /// [`OPQCodec::train_with_params`] on vectors already as the
+    /// codebooks see them before rotation, such as residuals
+    pub(crate) fn train_prepared(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+        bits: usize,
+        kmeans: &KMeansParams,
+        progress: &dyn ProgressCallback,
+    ) -> Result<Self> {
+        assert!(!training_vectors.is_empty());
+        let dimensions = training_vectors[0].len();
+        let mut rotation = identity(dimensions);
+
+        for _ in 0..OPQ_ITERATIONS {
+            let rotated: Vec<Vec<f32>> = training_vectors.par_iter().map(|v| rotate(&rotation, v)).collect();
+            let pq = PQCodec::train_prepared(&rotated, num_subvectors, seed, metric, bits, kmeans, &Muted(progress))?;
+            let reconstructed: Vec<Vec<f32>> = rotated.par_iter().map(|v| pq.decode(&pq.encode_prepared(v))).collect();
+            rotation = procrustes(training_vectors, &reconstructed);
+        }
+
+        let rotated: Vec<Vec<f32>> = training_vectors.par_iter().map(|v| rotate(&rotation, v)).collect();
+        let pq = PQCodec::train_prepared(&rotated, num_subvectors, seed, metric, bits, kmeans, progress)?;
+        Ok(Self { rotation, pq })
+    }
+
+    /// Metric the codebooks were trained for
+    pub fn metric(&self) -> DistanceMetric {
+        self.pq.metric()
+    }
+
+    /// Dimensions of the vectors it encodes
+    pub fn dimensions(&self) -> usize {
+        self.pq.num_subvectors * self.pq.subvector_size
+    }
+
+    /// `vector` rotated as the codebooks see it
+    pub fn rotate(&self, vector: &[f32]) -> Vec<f32> {
+        rotate(&self.rotation, vector)
+    }
+
+    /// The vector `rotated` is the rotation of
+    pub fn unrotate(&self, rotated: &[f32]) -> Vec<f32> {
+        let dimensions = rotated.len();
+        let mut vector = vec![0.0; dimensions];
+        for (row, &y) in self.rotation.chunks(dimensions).zip(rotated) {
+            vector.iter_mut().zip(row).for_each(|(x, r)| *x += r * y);
+        }
+        vector
+    }
+
+    pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        self.pq.encode(&self.rotate(vector))
+    }
+
+    /// [`OPQCodec::encode`] for a vector already as the codebooks see it
+    /// before rotation
+    pub(crate) fn encode_prepared(&self, vector: &[f32]) -> Vec<u8> {
+        self.pq.encode_prepared(&self.rotate(vector))
+    }
+
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        self.unrotate(&self.pq.decode(codes))
+    }
+
+    pub fn asymmetric_distance(&self, query: &[f32], codes: &[u8]) -> f32 {
+        self.pq.asymmetric_distance(&self.rotate(query), codes)
+    }
+
+    /// Tables of the rotated query. Every rotated component mixes all of
+    /// the query's, so queries must be full width.
+    pub fn precompute_distance_table(&self, query: &[f32]) -> Vec<Vec<f32>> {
+        let mut tables = Vec::new();
+        self.precompute_distance_table_into(query, &mut tables);
+        tables
+    }
+
+    /// [`OPQCodec::precompute_distance_table`] into `tables`, reusing the
+    /// allocations of a table made for an earlier query
+    pub fn precompute_distance_table_into(&self, query: &[f32], tables: &mut Vec<Vec<f32>>) {
+        assert_eq!(query.len(), self.dimensions(), "OPQ tables need full-width queries");
+        self.pq.precompute_distance_table_into(&self.rotate(query), tables);
+    }
+}
+
+fn identity(dimensions: usize) -> Vec<f32> {
+    let mut matrix = vec![0.0; dimensions * dimensions];
+    for i in 0..dimensions {
+        matrix[i * dimensions + i] = 1.0;
+    }
+    matrix
+}
+
+fn rotate(rotation: &[f32], vector: &[f32]) -> Vec<f32> {
+    rotation
+        .chunks(vector.len())
+        .map(|row| row.iter().zip(vector).map(|(r, x)| r * x).sum())
+        .collect()
+}
+
+/// The rotation `R` minimizing the summed squared distance from each
+/// `R * vectors[i]` to `targets[i]`: `U * V^T` for the SVD
+/// `U * S * V^T` of the sum of `targets[i] * vectors[i]^T`
+fn procrustes(vectors: &[Vec<f32>], targets: &[Vec<f32>]) -> Vec<f32> {
+    let dimensions = vectors[0].len();
+    // Column c of the sum is each target scaled by its vector's
+    // component c, accumulated in f64 and in row order, one column per task
+    let columns = (0..dimensions)
+        .into_par_iter()
+        .map(|c| {
+            let mut column = vec![0.0f64; dimensions];
+            for (x, y) in vectors.iter().zip(targets) {
+                let xc = f64::from(x[c]);
+                column.iter_mut().zip(y).for_each(|(m, &yr)| *m += f64::from(yr) * xc);
+            }
+            column
+        })
+        .collect();
+    orthogonal_factor(columns)
+}
+
+/// `U * V^T` for the SVD `U * S * V^T` of the square matrix with these
+/// `columns`, row-major: the orthogonal matrix nearest it.
+///
+/// One-sided Jacobi: rotate pairs of columns until all are orthogonal,
+/// accumulating the rotations in `V`, which leaves `U * S`. Columns that
+/// shrink to nothing have no direction of their own in `U` and are
+/// completed to an orthonormal basis, which any choice of does equally
+/// well.
+fn orthogonal_factor(mut columns: Vec<Vec<f64>>) -> Vec<f32> {
+    let n = columns.len();
+    let mut v: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| f64::from(u8::from(i == j))).collect()).collect();
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..n {
+            for q in p + 1..n {
+                let alpha = dot(&columns[p], &columns[p]);
+                let beta = dot(&columns[q], &columns[q]);
+                let gamma = dot(&columns[p], &columns[q]);
+                if gamma.abs() <= 1e-12 * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+                for matrix in [&mut columns, &mut v] {
+                    let (left, right) = matrix.split_at_mut(q);
+                    for (a, b) in left[p].iter_mut().zip(right[0].iter_mut()) {
+                        let (x, y) = (*a, *b);
+                        *a = c * x - s * y;
+                        *b = s * x + c * y;
+                    }
+                }
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+
+    // Normalize to U, largest singular values first so completion only
+    // fills in after every well-defined direction
+    let largest = columns.iter().map(|c| dot(c, c).sqrt()).fold(0.0, f64::max);
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| dot(&columns[b], &columns[b]).total_cmp(&dot(&columns[a], &columns[a])));
+    let mut u: Vec<Option<Vec<f64>>> = vec![None; n];
+    let mut basis: Vec<Vec<f64>> = Vec::with_capacity(n);
+    for &j in &order {
+        let norm = dot(&columns[j], &columns[j]).sqrt();
+        let mut direction = if norm > 1e-9 * largest {
+            columns[j].iter().map(|x| x / norm).collect()
+        } else {
+            // The standard basis vector least covered so far
+            let coverage = |axis: usize| basis.iter().map(|b| b[axis] * b[axis]).sum::<f64>();
+            let axis = (0..n).min_by(|&a, &b| coverage(a).total_cmp(&coverage(b))).unwrap();
+            (0..n).map(|i| f64::from(u8::from(i == axis))).collect::<Vec<f64>>()
+        };
+        // Re-orthogonalize against earlier directions, which also absorbs
+        // Jacobi's rounding
+        for b in &basis {
+            let overlap = dot(&direction, b);
+            direction.iter_mut().zip(b).for_each(|(x, y)| *x -= overlap * y);
+        }
+        let norm = dot(&direction, &direction).sqrt();
+        direction.iter_mut().for_each(|x| *x /= norm);
+        basis.push(direction.clone());
+        u[j] = Some(direction);
+    }
+    let u: Vec<Vec<f64>> = u.into_iter().map(Option::unwrap).collect();
+
+    let mut rotation = vec![0.0f32; n * n];
+    for (r, row) in rotation.chunks_mut(n).enumerate() {
+        for (c, entry) in row.iter_mut().enumerate() {
+            *entry = (0..n).map(|j| u[j][r] * v[j][c]).sum::<f64>() as f32;
+        }
+    }
+    rotation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn is_orthogonal(rotation: &[f32], n: usize) -> bool {
+        (0..n).all(|i| {
+            (0..n).all(|j| {
+                let dot: f32 = (0..n).map(|k| rotation[i * n + k] * rotation[j * n + k]).sum();
+                (dot - if i == j { 1.0 } else { 0.0 }).abs() < 1e-4
+            })
+        })
+    }
+
+    #[test]
+    fn test_procrustes_recovers_a_known_rotation() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let n = 6;
+        // The rotation to recover: the orthogonal factor of a random matrix
+        let random: Vec<Vec<f64>> = (0..n).map(|_| (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect();
+        let rotation = orthogonal_factor(random);
+        assert!(is_orthogonal(&rotation, n));
+
+        let vectors: Vec<Vec<f32>> = (0..50).map(|_| (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect();
+        let targets: Vec<Vec<f32>> = vectors.iter().map(|v| rotate(&rotation, v)).collect();
+        let found = procrustes(&vectors, &targets);
+        for (a, b) in found.iter().zip(&rotation) {
+            assert!((a - b).abs() < 1e-4, "{:?} vs {:?}", found, rotation);
+        }
+        let same = procrustes(&vectors, &vectors);
+        for (a, b) in same.iter().zip(&identity(n)) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_rank_deficient_sums_still_give_a_rotation() {
+        // Every vector in a 2-D subspace of 5 dimensions
+        let vectors: Vec<Vec<f32>> = (0..40)
+            .map(|i| {
+                let (a, b) = ((i as f32 * 0.3).sin(), (i as f32 * 0.7).cos());
+                vec![a, b, a + b, 0.0, a - b]
+            })
+            .collect();
+        let rotation = procrustes(&vectors, &vectors);
+        assert!(is_orthogonal(&rotation, 5));
+        for v in &vectors {
+            for (a, b) in rotate(&rotation, v).iter().zip(v) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_codes_and_tables_score_the_rotated_vectors() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let training: Vec<Vec<f32>> = (0..300).map(|_| (0..8).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect();
+        let query: Vec<f32> = (0..8).map(|i| (i as f32).cos()).collect();
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Cosine, DistanceMetric::DotProduct] {
+            let opq = OPQCodec::train_for_metric(&training, 2, Some(5), metric).unwrap();
+            assert!(is_orthogonal(&opq.rotation, 8));
+            let table = opq.precompute_distance_table(&query);
+            for vector in training.iter().step_by(29) {
+                let codes = opq.encode(vector);
+                let approx = opq.asymmetric_distance(&query, &codes);
+                assert!((opq.pq.table_lookup_distance(&table, &codes) - approx).abs() < 1e-4);
+                for (a, b) in opq.unrotate(&opq.rotate(vector)).iter().zip(vector) {
+                    assert!((a - b).abs() < 1e-5);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use super::codebook::Codebook;
-use crate::error::Result;
+use super::kmeans::KMeansParams;
+use crate::config::{DistanceMetric, DEFAULT_PQ_BITS};
+use crate::distance::{key_to_distance, normalized};
+use crate::error::{KhadyotaError, Result};
+use crate::progress::{BuildEvent, ProgressCallback, Silent};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
 
 /// Product Quantization codec for vector compression
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,6 +14,25 @@ pub struct PQCodec {
     pub num_subvectors: usize,
     pub subvector_size: usize,
     pub codebooks: Vec<Codebook>,
+    
+    /// Metric the codebooks were trained for; `None` in codecs saved
+    /// before it was recorded, which are all Euclidean
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric: Option<DistanceMetric>,
+    
+    /// Bits per code: 4, 8 or 16. Left out of 8-bit codecs, so they
+    /// read as before; others always record their metric, which keeps
+    /// this field in place.
+    #[serde(default = "default_bits", skip_serializing_if = "is_default_bits")]
+    pub bits: usize,
+}
+
+fn default_bits() -> usize {
+    DEFAULT_PQ_BITS
+}
+
+fn is_default_bits(bits: &usize) -> bool {
+    *bits == DEFAULT_PQ_BITS
 }
 
 impl PQCodec {
@@ -15,55 +40,199 @@ impl PQCodec {
     pub fn train(
         training_vectors: &[Vec<f32>],
         num_subvectors: usize,
+    ) -> Result<Self> {
+        Self::train_seeded(training_vectors, num_subvectors, None)
+    }
+    
+    /// Train with a fixed seed; each subvector's codebook derives its own
+    /// seed from it
+    pub fn train_seeded(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        Self::train_for_metric(training_vectors, num_subvectors, seed, DistanceMetric::Euclidean)
+    }
+    
+    /// Train codebooks whose distances rank by `metric`.
+    ///
+    /// Cosine codecs are trained on, and encode, unit-length vectors, so
+    /// their inner-product tables give cosine similarity. Dot-product
+    /// codecs keep the vectors as they are.
+    pub fn train_for_metric(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+    ) -> Result<Self> {
+        Self::train_with_progress(training_vectors, num_subvectors, seed, metric, DEFAULT_PQ_BITS, &Silent)
+    }
+    
+    /// [`PQCodec::train_for_metric`] with `bits`-bit codes (4, 8 or 16),
+    /// reporting each trained codebook and the k-means runs behind it to
+    /// `progress`
+    pub fn train_with_progress(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+        bits: usize,
+        progress: &dyn ProgressCallback,
+    ) -> Result<Self> {
+        let kmeans = KMeansParams::default();
+        Self::train_with_params(training_vectors, num_subvectors, seed, metric, bits, &kmeans, progress)
+    }
+    
+    /// [`PQCodec::train_with_progress`] with each codebook's k-means run
+    /// under `kmeans`
+    pub fn train_with_params(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+        bits: usize,
+        kmeans: &KMeansParams,
+        progress: &dyn ProgressCallback,
+    ) -> Result<Self> {
+        let normalized_vectors: Vec<Vec<f32>>;
+        let training_vectors = if metric == DistanceMetric::Cosine {
+            normalized_vectors = training_vectors.iter().map(|v| normalized(v)).collect();
+            &normalized_vectors
+        } else {
+            training_vectors
+        };
+        Self::train_prepared(training_vectors, num_subvectors, seed, metric, bits, kmeans, progress)
+    }
+    
+    /// [`PQCodec::train_with_params`] on vectors already as the
+    /// codebooks see them, such as residuals, which are not normalized
+    /// even for cosine
+    pub(crate) fn train_prepared(
+        training_vectors: &[Vec<f32>],
+        num_subvectors: usize,
+        seed: Option<u64>,
+        metric: DistanceMetric,
+        bits: usize,
+        kmeans: &KMeansParams,
+        progress: &dyn ProgressCallback,
     ) -> Result<Self> {
         assert!(!training_vectors.is_empty());
-        
+        assert!(matches!(bits, 4 | 8 | 16), "PQ codes are 4, 8 or 16 bits, not {}", bits);
         let dimensions = training_vectors[0].len();
         assert_eq!(dimensions % num_subvectors, 0, "Dimensions must be divisible by num_subvectors");
         
         let subvector_size = dimensions / num_subvectors;
-        let num_centroids = 256; // 8-bit quantization
-        
-        println!("Training PQ codec:");
-        println!("  Dimensions: {}", dimensions);
-        println!("  Subvectors: {}", num_subvectors);
-        println!("  Subvector size: {}", subvector_size);
-        println!("  Training vectors: {}", training_vectors.len());
+        // Small training sets get one centroid per vector
+        let num_centroids = (1 << bits).min(training_vectors.len());
         
-        let mut codebooks = Vec::with_capacity(num_subvectors);
+        progress.on_event(BuildEvent::PqTrainingStarted {
+            dimensions,
+            subvectors: num_subvectors,
+            subvector_size,
+            training_vectors: training_vectors.len(),
+        });
         
-        // Train one codebook per subvector
-        for subvec_idx in 0..num_subvectors {
-            println!("Training codebook {}/{}", subvec_idx + 1, num_subvectors);
-            
-            // Extract subvectors
-            let subvectors: Vec<Vec<f32>> = training_vectors
-                .iter()
-                .map(|v| extract_subvector(v, subvec_idx, subvector_size))
-                .collect();
-            
-            // Train codebook
-            let codebook = Codebook::train(&subvectors, num_centroids);
-            codebooks.push(codebook);
+        // Train one codebook per subvector, in parallel; each has its own
+        // seed, so the result does not depend on scheduling
+        // Counted under a lock so completions are reported in order
+        let trained = Mutex::new(0);
+        let codebooks: Vec<Codebook> = (0..num_subvectors)
+            .into_par_iter()
+            .map(|subvec_idx| {
+                if progress.cancelled() {
+                    return Codebook {
+                        centroids: Vec::new(),
+                        dimensions: subvector_size,
+                    };
+                }
+                let subvectors: Vec<Vec<f32>> = training_vectors
+                    .iter()
+                    .map(|v| extract_subvector(v, subvec_idx, subvector_size))
+                    .collect();
+                
+                let codebook = Codebook::train_with_params(
+                    &subvectors,
+                    num_centroids,
+                    seed.map(|s| s.wrapping_add(subvec_idx as u64)),
+                    kmeans,
+                    progress,
+                );
+                let mut done = trained.lock().unwrap();
+                *done += 1;
+                progress.on_event(BuildEvent::CodebookTrained {
+                    done: *done,
+                    total: num_subvectors,
+                });
+                drop(done);
+                codebook
+            })
+            .collect();
+        if progress.cancelled() {
+            return Err(KhadyotaError::Cancelled);
         }
         
-        println!("PQ training complete!");
-        
         Ok(Self {
             num_subvectors,
             subvector_size,
             codebooks,
+            metric: Some(metric),
+            bits,
         })
     }
     
-    /// Encode a vector into PQ codes
+    /// Metric the codebooks were trained for
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric.unwrap_or(DistanceMetric::Euclidean)
+    }
+    
+    /// `vector` as the codebooks see it: unit length for cosine
+    fn prepare<'a>(&self, vector: &'a [f32]) -> std::borrow::Cow<'a, [f32]> {
+        match self.metric() {
+            DistanceMetric::Cosine => std::borrow::Cow::Owned(normalized(vector)),
+            _ => std::borrow::Cow::Borrowed(vector),
+        }
+    }
+    
+    fn finish(&self, sum: f32) -> f32 {
+        finish_distance(self.metric(), sum)
+    }
+    
+    /// Bytes of packed codes per vector
+    pub fn code_bytes(&self) -> usize {
+        (self.num_subvectors * self.bits).div_ceil(8)
+    }
+    
+    /// Code of subvector `subvec_idx` in a packed row. 4-bit codes fill
+    /// the low nibble first; 16-bit codes are little-endian.
+    pub fn code(&self, codes: &[u8], subvec_idx: usize) -> u16 {
+        match self.bits {
+            4 => u16::from((codes[subvec_idx / 2] >> (subvec_idx % 2 * 4)) & 0x0f),
+            16 => u16::from_le_bytes([codes[2 * subvec_idx], codes[2 * subvec_idx + 1]]),
+            _ => u16::from(codes[subvec_idx]),
+        }
+    }
+    
+    fn put_code(&self, codes: &mut [u8], subvec_idx: usize, code: u16) {
+        match self.bits {
+            4 => codes[subvec_idx / 2] |= (code as u8 & 0x0f) << (subvec_idx % 2 * 4),
+            16 => codes[2 * subvec_idx..2 * subvec_idx + 2].copy_from_slice(&code.to_le_bytes()),
+            _ => codes[subvec_idx] = code as u8,
+        }
+    }
+    
+    /// Encode a vector into packed PQ codes, [`PQCodec::code_bytes`] long
     pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
-        let mut codes = Vec::with_capacity(self.num_subvectors);
+        self.encode_prepared(&self.prepare(vector))
+    }
+    
+    /// [`PQCodec::encode`] for a vector already as the codebooks see it
+    pub(crate) fn encode_prepared(&self, vector: &[f32]) -> Vec<u8> {
+        let mut codes = vec![0; self.code_bytes()];
         
         for (subvec_idx, codebook) in self.codebooks.iter().enumerate() {
             let subvec = extract_subvector(vector, subvec_idx, self.subvector_size);
             let code = codebook.encode(&subvec);
-            codes.push(code);
+            self.put_code(&mut codes, subvec_idx, code);
         }
         
         codes
@@ -73,8 +242,8 @@ impl PQCodec {
     pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
         let mut vector = Vec::with_capacity(self.num_subvectors * self.subvector_size);
         
-        for (code, codebook) in codes.iter().zip(self.codebooks.iter()) {
-            let subvec = codebook.decode(*code);
+        for (subvec_idx, codebook) in self.codebooks.iter().enumerate() {
+            let subvec = codebook.decode(self.code(codes, subvec_idx));
             vector.extend_from_slice(subvec);
         }
         
@@ -83,42 +252,138 @@ impl PQCodec {
     
     /// Asymmetric distance: query is NOT quantized (more accurate)
     pub fn asymmetric_distance(&self, query: &[f32], codes: &[u8]) -> f32 {
-        let mut distance_squared = 0.0;
+        let query = self.prepare(query);
+        let mut sum = 0.0;
         
-        for (subvec_idx, (code, codebook)) in codes.iter().zip(self.codebooks.iter()).enumerate() {
-            let query_subvec = extract_subvector(query, subvec_idx, self.subvector_size);
-            distance_squared += codebook.distance_to_centroid(&query_subvec, *code);
+        for (subvec_idx, codebook) in self.codebooks.iter().enumerate() {
+            let query_subvec = extract_subvector(&query, subvec_idx, self.subvector_size);
+            sum += codebook.distance_to_centroid(&query_subvec, self.code(codes, subvec_idx), self.metric());
         }
         
-        distance_squared.sqrt()
+        self.finish(sum)
     }
     
-    /// Precompute distance table for faster batch queries
+    /// Precompute distance table for faster batch queries: one entry per
+    /// centroid, up to `2^bits` per subvector. Entries are per-subvector
+    /// terms; [`PQCodec::table_lookup_distance`] finishes them. A query
+    /// covering only the leading subvectors gets tables for those alone,
+    /// and lookups then score that prefix of each row.
     pub fn precompute_distance_table(&self, query: &[f32]) -> Vec<Vec<f32>> {
-        let mut tables = Vec::with_capacity(self.num_subvectors);
+        let mut tables = Vec::new();
+        self.precompute_distance_table_into(query, &mut tables);
+        tables
+    }
+    
+    /// [`PQCodec::precompute_distance_table`] into `tables`, reusing the
+    /// allocations of a table made for an earlier query
+    pub fn precompute_distance_table_into(&self, query: &[f32], tables: &mut Vec<Vec<f32>>) {
+        let covered = (query.len() / self.subvector_size).min(self.codebooks.len());
+        tables.resize_with(covered, Vec::new);
+        let query = self.prepare(query);
+        let metric = self.metric();
         
-        for (subvec_idx, codebook) in self.codebooks.iter().enumerate() {
-            let query_subvec = extract_subvector(query, subvec_idx, self.subvector_size);
+        for (subvec_idx, (codebook, table)) in self.codebooks.iter().zip(tables.iter_mut()).enumerate() {
+            let query_subvec = extract_subvector(&query, subvec_idx, self.subvector_size);
             
-            let mut table = Vec::with_capacity(256);
-            for code in 0..256 {
-                let dist = codebook.distance_to_centroid(&query_subvec, code as u8);
+            table.clear();
+            table.reserve(codebook.centroids.len());
+            for code in 0..codebook.centroids.len() {
+                let dist = codebook.distance_to_centroid(&query_subvec, code as u16, metric);
                 table.push(dist);
             }
-            tables.push(table);
         }
-        
-        tables
     }
     
     /// Fast distance lookup using precomputed table
     pub fn table_lookup_distance(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> f32 {
-        codes
-            .iter()
-            .enumerate()
-            .map(|(i, &code)| dist_table[i][code as usize])
-            .sum::<f32>()
-            .sqrt()
+        self.finish(self.table_sum(dist_table, codes))
+    }
+    
+    /// Table entries for `codes` summed, before finishing
+    fn table_sum(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> f32 {
+        match self.bits {
+            // One byte per code needs no unpacking
+            8 => codes
+                .iter()
+                .zip(dist_table)
+                .map(|(&code, table)| table[code as usize])
+                .sum::<f32>(),
+            _ => dist_table
+                .iter()
+                .enumerate()
+                .map(|(i, table)| table[self.code(codes, i) as usize])
+                .sum::<f32>(),
+        }
+    }
+    
+    /// [`PQCodec::table_lookup_distance`] for a block of code rows,
+    /// `stride` bytes apart in `codes`, written to `out`, one per row.
+    ///
+    /// 8-bit codes are looked up eight rows at a time with AVX2 where the
+    /// CPU has it, from a copy of the tables laid end to end; sums are
+    /// finished, and Euclidean ones square-rooted, once they are all in.
+    /// Results match [`PQCodec::table_lookup_distance`] exactly.
+    pub fn scan_codes(&self, dist_table: &[Vec<f32>], codes: &[u8], stride: usize, out: &mut [f32]) {
+        self.scan_keys(dist_table, codes, stride, out, &mut Vec::new());
+        let metric = self.metric();
+        out.iter_mut().for_each(|key| *key = key_to_distance(metric, *key));
+    }
+    
+    /// [`PQCodec::scan_codes`] leaving Euclidean distances squared, as
+    /// [`distance_key`](crate::distance::distance_key) has them, for
+    /// ranking without a sqrt per row. `flat` holds the tables laid end
+    /// to end, kept between scans so they are not allocated for each.
+    pub(crate) fn scan_keys(
+        &self,
+        dist_table: &[Vec<f32>],
+        codes: &[u8],
+        stride: usize,
+        out: &mut [f32],
+        flat: &mut Vec<f32>,
+    ) {
+        assert!(stride >= self.code_bytes(), "stride {} is narrower than a code row", stride);
+        let metric = self.metric();
+        
+        #[cfg(target_arch = "x86_64")]
+        {
+            use crate::distance::simd::{avx2_available, pq_scan_avx2, PQ_TABLE_WIDTH};
+            
+            if self.bits == 8 && out.len() >= 8 && avx2_available() {
+                // Padding is never read: 8-bit codes stay below each
+                // codebook's size
+                flat.clear();
+                flat.resize(dist_table.len() * PQ_TABLE_WIDTH, f32::INFINITY);
+                for (entries, table) in flat.chunks_mut(PQ_TABLE_WIDTH).zip(dist_table) {
+                    entries[..table.len()].copy_from_slice(table);
+                }
+                unsafe { pq_scan_avx2(flat, dist_table.len(), codes, stride, out) };
+                out.iter_mut().for_each(|sum| *sum = finish_key(metric, *sum));
+                return;
+            }
+        }
+        
+        for (row, key) in out.iter_mut().enumerate() {
+            *key = finish_key(metric, self.table_sum(dist_table, &codes[row * stride..]));
+        }
+    }
+}
+
+/// Turn summed per-subvector terms into a distance in `metric`'s units
+pub(crate) fn finish_distance(metric: DistanceMetric, sum: f32) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => sum.sqrt(),
+        DistanceMetric::Cosine => 1.0 - sum,
+        DistanceMetric::DotProduct => -sum,
+        DistanceMetric::Manhattan | DistanceMetric::Hamming => sum,
+    }
+}
+
+/// [`finish_distance`] short of the sqrt a Euclidean distance takes,
+/// giving the distance's [`distance_key`](crate::distance::distance_key)
+pub(crate) fn finish_key(metric: DistanceMetric, sum: f32) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => sum,
+        _ => finish_distance(metric, sum),
     }
 }
 
@@ -164,4 +429,124 @@ mod tests {
         println!("Average quantization error: {:.4}", error);
         assert!(error < 1.0); // Should have reasonable accuracy
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_codes_round_trip_at_each_bit_width() {
+        let training: Vec<Vec<f32>> = (0..400)
+            .map(|i| (0..12).map(|j| ((i * 12 + j) as f32 * 0.29).sin()).collect())
+            .collect();
+        
+        let mut errors = Vec::new();
+        for (bits, code_bytes) in [(4, 2), (8, 3), (16, 6)] {
+            let pq = PQCodec::train_with_progress(&training, 3, Some(4), DistanceMetric::Euclidean, bits, &Silent).unwrap();
+            assert_eq!(pq.code_bytes(), code_bytes);
+            assert_eq!(pq.codebooks[0].centroids.len(), (1 << bits).min(400));
+            let table = pq.precompute_distance_table(&training[0]);
+            
+            let mut error = 0.0;
+            for vector in &training {
+                let codes = pq.encode(vector);
+                assert_eq!(codes.len(), code_bytes);
+                
+                // Each code unpacks to its subvector's nearest centroid
+                let decoded = pq.decode(&codes);
+                for (i, codebook) in pq.codebooks.iter().enumerate() {
+                    let subvec = extract_subvector(vector, i, 4);
+                    assert_eq!(pq.code(&codes, i), codebook.encode(&subvec));
+                    assert_eq!(&decoded[i * 4..i * 4 + 4], codebook.decode(pq.code(&codes, i)));
+                }
+                
+                let approx = pq.asymmetric_distance(&training[0], &codes);
+                assert!((pq.table_lookup_distance(&table, &codes) - approx).abs() < 1e-4);
+                error += crate::distance::euclidean_distance_squared(vector, &decoded);
+            }
+            errors.push(error);
+        }
+        
+        assert!(errors[0] > errors[1] && errors[1] > errors[2], "{:?}", errors);
+    }
+    
+    #[test]
+    fn test_scan_codes_matches_per_row_lookups() {
+        let training: Vec<Vec<f32>> = (0..500)
+            .map(|i| (0..16).map(|j| ((i * 16 + j) as f32 * 0.23).sin() * (1 + i % 3) as f32).collect())
+            .collect();
+        let query: Vec<f32> = (0..16).map(|i| (i as f32 * 0.9).cos()).collect();
+        
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Cosine, DistanceMetric::DotProduct] {
+            for bits in [4, 8, 16] {
+                let pq = PQCodec::train_with_progress(&training[..300], 4, Some(2), metric, bits, &Silent).unwrap();
+                // Rows padded past their codes, as a caller's block may be
+                let stride = pq.code_bytes() + 3;
+                let mut codes = Vec::new();
+                for vector in &training {
+                    codes.extend(pq.encode(vector));
+                    codes.extend([0xff; 3]);
+                }
+                
+                // One buffer serves tables of every shape in turn
+                let mut flat = Vec::new();
+                // Full tables, a prefix query's, and a tail shorter than a SIMD block
+                for (table, rows) in [
+                    (pq.precompute_distance_table(&query), 500),
+                    (pq.precompute_distance_table(&query[..8]), 500),
+                    (pq.precompute_distance_table(&query), 13),
+                ] {
+                    let mut out = vec![0.0; rows];
+                    pq.scan_codes(&table, &codes, stride, &mut out);
+                    for (row, &distance) in out.iter().enumerate() {
+                        let codes = &codes[row * stride..row * stride + pq.code_bytes()];
+                        assert_eq!(distance, pq.table_lookup_distance(&table, codes), "{:?} {}-bit row {}", metric, bits, row);
+                    }
+                    let mut keys = vec![0.0; rows];
+                    pq.scan_keys(&table, &codes, stride, &mut keys, &mut flat);
+                    let finished: Vec<f32> = keys.iter().map(|&key| key_to_distance(metric, key)).collect();
+                    assert_eq!(finished, out);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_tables_match_asymmetric_distance() {
+        let training: Vec<Vec<f32>> = (0..300)
+            .map(|i| (0..16).map(|j| ((i * 16 + j) as f32 * 0.3).sin() * (1 + i % 4) as f32).collect())
+            .collect();
+        let query: Vec<f32> = (0..16).map(|i| (i as f32 * 0.7).cos()).collect();
+        
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Cosine, DistanceMetric::DotProduct] {
+            let pq = PQCodec::train_for_metric(&training, 4, Some(1), metric).unwrap();
+            assert_eq!(pq.metric(), metric);
+            let table = pq.precompute_distance_table(&query);
+            
+            for vector in training.iter().step_by(37) {
+                let codes = pq.encode(vector);
+                let exact = crate::distance::compute_distance(&query, vector, metric);
+                let approx = pq.asymmetric_distance(&query, &codes);
+                assert!((pq.table_lookup_distance(&table, &codes) - approx).abs() < 1e-4);
+                assert!((approx - exact).abs() < 0.25 * exact.abs().max(1.0), "{:?}: {} vs {}", metric, approx, exact);
+            }
+        }
+        
+        // Cosine codes ignore scale
+        let pq = PQCodec::train_for_metric(&training, 4, Some(1), DistanceMetric::Cosine).unwrap();
+        let scaled: Vec<f32> = training[5].iter().map(|x| x * 9.0).collect();
+        assert_eq!(pq.encode(&training[5]), pq.encode(&scaled));
+    }
+    
+    #[test]
+    fn test_codebooks_do_not_depend_on_thread_count() {
+        let training: Vec<Vec<f32>> = (0..600)
+            .map(|i| (0..32).map(|j| ((i * 32 + j) as f32 * 0.41).sin()).collect())
+            .collect();
+        let train = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| PQCodec::train_seeded(&training, 8, Some(3)).unwrap())
+        };
+        
+        let (one, four) = (train(1), train(4));
+        for (a, b) in one.codebooks.iter().zip(&four.codebooks) {
+            assert_eq!(a.centroids, b.centroids);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use super::{OPQCodec, PQCodec, SQCodec};
+use crate::config::{DistanceMetric, QuantizerKind};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Codec behind [`QuantizedVectors`](crate::storage::QuantizedVectors):
+/// every vector becomes a row of `code_bytes()` bytes, scored through a
+/// per-query table either way
+#[derive(Debug, Clone)]
+pub enum Quantizer {
+    PQ(PQCodec),
+    SQ8(SQCodec),
+    /// PQ over rotated vectors, under
+    /// [`Config::use_opq`](crate::config::Config::use_opq)
+    OPQ(OPQCodec),
+}
+
+impl From<PQCodec> for Quantizer {
+    fn from(codec: PQCodec) -> Self {
+        Quantizer::PQ(codec)
+    }
+}
+
+impl From<OPQCodec> for Quantizer {
+    fn from(codec: OPQCodec) -> Self {
+        Quantizer::OPQ(codec)
+    }
+}
+
+impl From<SQCodec> for Quantizer {
+    fn from(codec: SQCodec) -> Self {
+        Quantizer::SQ8(codec)
+    }
+}
+
+impl Quantizer {
+    pub fn kind(&self) -> QuantizerKind {
+        match self {
+            Quantizer::PQ(_) | Quantizer::OPQ(_) => QuantizerKind::PQ,
+            Quantizer::SQ8(_) => QuantizerKind::SQ8,
+        }
+    }
+
+    /// The PQ codec, if this is one
+    pub fn pq(&self) -> Option<&PQCodec> {
+        match self {
+            Quantizer::PQ(codec) => Some(codec),
+            Quantizer::SQ8(_) | Quantizer::OPQ(_) => None,
+        }
+    }
+
+    /// The codebooks codes index, rotated or not; `None` for SQ8
+    pub fn codebooks(&self) -> Option<&PQCodec> {
+        match self {
+            Quantizer::PQ(codec) => Some(codec),
+            Quantizer::OPQ(codec) => Some(&codec.pq),
+            Quantizer::SQ8(_) => None,
+        }
+    }
+
+    /// Whether tables can be made for a query covering only the leading
+    /// subvectors: not once a rotation mixes every component into each
+    pub fn scores_prefixes(&self) -> bool {
+        !matches!(self, Quantizer::OPQ(_))
+    }
+
+    /// Metric the codec was trained for; `None` for PQ codecs saved
+    /// before it was recorded, which are all Euclidean
+    pub fn recorded_metric(&self) -> Option<DistanceMetric> {
+        match self {
+            Quantizer::PQ(codec) => codec.metric,
+            Quantizer::SQ8(codec) => Some(codec.metric),
+            Quantizer::OPQ(codec) => codec.pq.metric,
+        }
+    }
+
+    /// Dimensions of the vectors it encodes
+    pub fn dimensions(&self) -> usize {
+        match self {
+            Quantizer::PQ(codec) => codec.num_subvectors * codec.subvector_size,
+            Quantizer::SQ8(codec) => codec.dimensions(),
+            Quantizer::OPQ(codec) => codec.dimensions(),
+        }
+    }
+
+    /// Bytes of codes per vector
+    pub fn code_bytes(&self) -> usize {
+        match self {
+            Quantizer::PQ(codec) => codec.code_bytes(),
+            Quantizer::SQ8(codec) => codec.dimensions(),
+            Quantizer::OPQ(codec) => codec.pq.code_bytes(),
+        }
+    }
+
+    /// Bytes held by the trained codebooks, rotation or per-dimension
+    /// ranges
+    pub fn memory_usage(&self) -> usize {
+        let floats = |pq: &PQCodec| pq.codebooks.iter().map(|book| book.centroids.len() * book.dimensions).sum::<usize>();
+        let count = match self {
+            Quantizer::PQ(codec) => floats(codec),
+            Quantizer::SQ8(codec) => codec.mins.len() + codec.scales.len(),
+            Quantizer::OPQ(codec) => codec.rotation.len() + floats(&codec.pq),
+        };
+        count * std::mem::size_of::<f32>()
+    }
+
+    pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        match self {
+            Quantizer::PQ(codec) => codec.encode(vector),
+            Quantizer::SQ8(codec) => codec.encode(vector),
+            Quantizer::OPQ(codec) => codec.encode(vector),
+        }
+    }
+
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        match self {
+            Quantizer::PQ(codec) => codec.decode(codes),
+            Quantizer::SQ8(codec) => codec.decode(codes),
+            Quantizer::OPQ(codec) => codec.decode(codes),
+        }
+    }
+
+    pub fn asymmetric_distance(&self, query: &[f32], codes: &[u8]) -> f32 {
+        match self {
+            Quantizer::PQ(codec) => codec.asymmetric_distance(query, codes),
+            Quantizer::SQ8(codec) => codec.asymmetric_distance(query, codes),
+            Quantizer::OPQ(codec) => codec.asymmetric_distance(query, codes),
+        }
+    }
+
+    pub fn precompute_distance_table(&self, query: &[f32]) -> Vec<Vec<f32>> {
+        match self {
+            Quantizer::PQ(codec) => codec.precompute_distance_table(query),
+            Quantizer::SQ8(codec) => codec.precompute_distance_table(query),
+            Quantizer::OPQ(codec) => codec.precompute_distance_table(query),
+        }
+    }
+
+    /// [`Quantizer::precompute_distance_table`] into `tables`, reusing
+    /// the allocations of a table made for an earlier query
+    pub fn precompute_distance_table_into(&self, query: &[f32], tables: &mut Vec<Vec<f32>>) {
+        match self {
+            Quantizer::PQ(codec) => codec.precompute_distance_table_into(query, tables),
+            Quantizer::SQ8(codec) => codec.precompute_distance_table_into(query, tables),
+            Quantizer::OPQ(codec) => codec.precompute_distance_table_into(query, tables),
+        }
+    }
+
+    /// Table lookups one [`Quantizer::table_lookup_distance`] makes: one
+    /// per subquantizer, or per dimension for SQ8
+    pub fn table_rows(&self) -> usize {
+        match self {
+            Quantizer::PQ(codec) => codec.num_subvectors,
+            Quantizer::SQ8(_) => self.dimensions(),
+            Quantizer::OPQ(codec) => codec.pq.num_subvectors,
+        }
+    }
+
+    pub fn table_lookup_distance(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> f32 {
+        match self {
+            Quantizer::PQ(codec) => codec.table_lookup_distance(dist_table, codes),
+            Quantizer::SQ8(codec) => codec.table_lookup_distance(dist_table, codes),
+            Quantizer::OPQ(codec) => codec.pq.table_lookup_distance(dist_table, codes),
+        }
+    }
+
+    /// [`Quantizer::table_lookup_distance`] for a block of rows, `stride`
+    /// bytes apart in `codes`, into `out`; see [`PQCodec::scan_codes`]
+    pub fn scan_codes(&self, dist_table: &[Vec<f32>], codes: &[u8], stride: usize, out: &mut [f32]) {
+        match self {
+            Quantizer::PQ(codec) => codec.scan_codes(dist_table, codes, stride, out),
+            Quantizer::OPQ(codec) => codec.pq.scan_codes(dist_table, codes, stride, out),
+            Quantizer::SQ8(codec) => {
+                for (row, distance) in out.iter_mut().enumerate() {
+                    *distance = codec.table_lookup_distance(dist_table, &codes[row * stride..]);
+                }
+            }
+        }
+    }
+
+    /// [`Quantizer::scan_codes`] with Euclidean distances left squared;
+    /// `flat` is working space for PQ tables, as [`PQCodec::scan_codes`]
+    /// lays them out
+    pub(crate) fn scan_keys(
+        &self,
+        dist_table: &[Vec<f32>],
+        codes: &[u8],
+        stride: usize,
+        out: &mut [f32],
+        flat: &mut Vec<f32>,
+    ) {
+        match self {
+            Quantizer::PQ(codec) => codec.scan_keys(dist_table, codes, stride, out, flat),
+            Quantizer::OPQ(codec) => codec.pq.scan_keys(dist_table, codes, stride, out, flat),
+            Quantizer::SQ8(codec) => {
+                for (row, key) in out.iter_mut().enumerate() {
+                    *key = codec.table_lookup_key(dist_table, &codes[row * stride..]);
+                }
+            }
+        }
+    }
+
+    /// The table entry each subquantizer (each dimension, for SQ8)
+    /// contributes for `codes`; [`Quantizer::table_lookup_distance`] is
+    /// their sum, finished into the metric's units
+    pub fn distance_terms(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> Vec<f32> {
+        match self.codebooks() {
+            Some(codec) => dist_table
+                .iter()
+                .enumerate()
+                .map(|(i, table)| table[codec.code(codes, i) as usize])
+                .collect(),
+            None => codes.iter().zip(dist_table).map(|(&code, table)| table[code as usize]).collect(),
+        }
+    }
+}
+
+/// PQ codecs are written bare, as they were before other quantizers
+/// existed, so files with them stay readable by older builds. Others are
+/// a one-entry map from their name to the codec, OPQ's carrying its
+/// rotation.
+impl Serialize for Quantizer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Quantizer::PQ(codec) => codec.serialize(serializer),
+            Quantizer::SQ8(codec) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("SQ8", codec)?;
+                map.end()
+            }
+            Quantizer::OPQ(codec) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("OPQ", codec)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantizer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(QuantizerVisitor)
+    }
+}
+
+struct QuantizerVisitor;
+
+impl<'de> Visitor<'de> for QuantizerVisitor {
+    type Value = Quantizer;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a PQ codec or a map naming another quantizer")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Quantizer, A::Error> {
+        PQCodec::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(Quantizer::PQ)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Quantizer, A::Error> {
+        let Some(name) = map.next_key::<String>()? else {
+            return Err(de::Error::custom("empty quantizer map"));
+        };
+        match name.as_str() {
+            "SQ8" => map.next_value().map(Quantizer::SQ8),
+            "OPQ" => map.next_value().map(Quantizer::OPQ),
+            // A PQ codec written with field names
+            _ => PQCodec::deserialize(de::value::MapAccessDeserializer::new(Replay {
+                first: Some(name),
+                map,
+            }))
+            .map(Quantizer::PQ),
+        }
+    }
+}
+
+/// `map` with its already-read first key put back in front
+struct Replay<A> {
+    first: Option<String>,
+    map: A,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Replay<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, A::Error> {
+        match self.first.take() {
+            Some(key) => seed.deserialize(de::value::StringDeserializer::new(key)).map(Some),
+            None => self.map.next_key_seed(seed),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_codec_round_trips() {
+        let training: Vec<Vec<f32>> = (0..300)
+            .map(|i| (0..8).map(|j| ((i * 8 + j) as f32 * 0.3).sin()).collect())
+            .collect();
+        let pq = PQCodec::train_seeded(&training, 2, Some(1)).unwrap();
+        let sq = SQCodec::train(&training).unwrap();
+        let opq = OPQCodec::train_for_metric(&training, 2, Some(1), DistanceMetric::Euclidean).unwrap();
+
+        // PQ codecs are saved exactly as before
+        let bare = rmp_serde::to_vec(&pq).unwrap();
+        assert_eq!(rmp_serde::to_vec(&Quantizer::PQ(pq.clone())).unwrap(), bare);
+
+        for quantizer in [Quantizer::PQ(pq), Quantizer::SQ8(sq), Quantizer::OPQ(opq)] {
+            let loaded: Quantizer = rmp_serde::from_slice(&rmp_serde::to_vec(&quantizer).unwrap()).unwrap();
+            assert_eq!(loaded.kind(), quantizer.kind());
+            assert_eq!(loaded.encode(&training[3]), quantizer.encode(&training[3]));
+            assert_eq!(loaded.decode(&quantizer.encode(&training[3])), quantizer.decode(&quantizer.encode(&training[3])));
+
+            let named: Quantizer = rmp_serde::from_slice(&rmp_serde::to_vec_named(&quantizer).unwrap()).unwrap();
+            assert_eq!(named.kind(), quantizer.kind());
+            assert_eq!(named.encode(&training[3]), quantizer.encode(&training[3]));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use super::product_quantization::{finish_distance, finish_key};
+use crate::config::DistanceMetric;
+use crate::distance::{normalized, HAMMING_THRESHOLD};
+use crate::error::{KhadyotaError, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Levels each component is quantized to
+const LEVELS: usize = 256;
+
+/// Scalar quantization codec: every component becomes one byte, one of
+/// 256 evenly spaced levels between the smallest and largest value its
+/// dimension takes in the training vectors
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SQCodec {
+    /// Lowest level of each dimension
+    pub mins: Vec<f32>,
+
+    /// Distance between adjacent levels of each dimension; 0 where the
+    /// training vectors never vary
+    pub scales: Vec<f32>,
+
+    /// Metric distances are computed in. Cosine codecs are trained on,
+    /// and encode, unit-length vectors, as PQ codecs do.
+    pub metric: DistanceMetric,
+}
+
+impl SQCodec {
+    /// Train a Euclidean codec from the range of each dimension
+    pub fn train(training_vectors: &[Vec<f32>]) -> Result<Self> {
+        Self::train_for_metric(training_vectors, DistanceMetric::Euclidean)
+    }
+
+    /// Train a codec whose distances rank by `metric`
+    pub fn train_for_metric(training_vectors: &[Vec<f32>], metric: DistanceMetric) -> Result<Self> {
+        let Some(first) = training_vectors.first() else {
+            return Err(KhadyotaError::InvalidConfig(
+                "SQ8 needs at least one training vector".to_string(),
+            ));
+        };
+        let dimensions = first.len();
+        let mut mins = vec![f32::INFINITY; dimensions];
+        let mut maxes = vec![f32::NEG_INFINITY; dimensions];
+
+        for vector in training_vectors {
+            if vector.len() != dimensions {
+                return Err(KhadyotaError::DimensionMismatch {
+                    expected: dimensions,
+                    got: vector.len(),
+                });
+            }
+            let vector = prepare(metric, vector);
+            for (d, &x) in vector.iter().enumerate() {
+                mins[d] = mins[d].min(x);
+                maxes[d] = maxes[d].max(x);
+            }
+        }
+
+        let scales = mins
+            .iter()
+            .zip(&maxes)
+            .map(|(&min, &max)| (max - min) / (LEVELS - 1) as f32)
+            .collect();
+
+        Ok(Self { mins, scales, metric })
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.mins.len()
+    }
+
+    /// Value of `code` in dimension `d`
+    fn level(&self, d: usize, code: u8) -> f32 {
+        self.mins[d] + f32::from(code) * self.scales[d]
+    }
+
+    /// Encode a vector, one byte per component. Values outside the
+    /// trained range are clamped to it.
+    pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        let vector = prepare(self.metric, vector);
+        vector
+            .iter()
+            .enumerate()
+            .map(|(d, &x)| {
+                if self.scales[d] > 0.0 {
+                    ((x - self.mins[d]) / self.scales[d]).round().clamp(0.0, (LEVELS - 1) as f32) as u8
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /// Decode codes back to the approximate vector (unit length for cosine)
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        codes.iter().enumerate().map(|(d, &code)| self.level(d, code)).collect()
+    }
+
+    /// Distance from an unquantized `query` to encoded `codes`
+    pub fn asymmetric_distance(&self, query: &[f32], codes: &[u8]) -> f32 {
+        let query = prepare(self.metric, query);
+        let sum = codes
+            .iter()
+            .enumerate()
+            .map(|(d, &code)| term(self.metric, query[d], self.level(d, code)))
+            .sum::<f32>();
+        finish_distance(self.metric, sum)
+    }
+
+    /// Per-dimension terms for every level, so scoring a row is one lookup
+    /// per component; [`SQCodec::table_lookup_distance`] finishes them. A
+    /// shorter query gets terms for its own components, scoring that
+    /// prefix of each row.
+    pub fn precompute_distance_table(&self, query: &[f32]) -> Vec<Vec<f32>> {
+        let mut tables = Vec::new();
+        self.precompute_distance_table_into(query, &mut tables);
+        tables
+    }
+
+    /// [`SQCodec::precompute_distance_table`] into `tables`, reusing the
+    /// allocations of a table made for an earlier query
+    pub fn precompute_distance_table_into(&self, query: &[f32], tables: &mut Vec<Vec<f32>>) {
+        let query = prepare(self.metric, query);
+        tables.resize_with(query.len().min(self.dimensions()), Vec::new);
+        for (d, table) in tables.iter_mut().enumerate() {
+            table.clear();
+            table.extend((0..LEVELS).map(|code| term(self.metric, query[d], self.level(d, code as u8))));
+        }
+    }
+
+    /// Distance using a table from [`SQCodec::precompute_distance_table`]
+    pub fn table_lookup_distance(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> f32 {
+        finish_distance(self.metric, self.table_sum(dist_table, codes))
+    }
+
+    /// [`SQCodec::table_lookup_distance`] as a
+    /// [`distance_key`](crate::distance::distance_key): squared for Euclidean
+    pub(crate) fn table_lookup_key(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> f32 {
+        finish_key(self.metric, self.table_sum(dist_table, codes))
+    }
+
+    fn table_sum(&self, dist_table: &[Vec<f32>], codes: &[u8]) -> f32 {
+        codes
+            .iter()
+            .zip(dist_table)
+            .map(|(&code, table)| table[code as usize])
+            .sum::<f32>()
+    }
+}
+
+/// `vector` as the codec sees it: unit length for cosine
+fn prepare(metric: DistanceMetric, vector: &[f32]) -> Cow<'_, [f32]> {
+    match metric {
+        DistanceMetric::Cosine => Cow::Owned(normalized(vector)),
+        _ => Cow::Borrowed(vector),
+    }
+}
+
+/// One component's share of the distance, summed as PQ sums subvectors
+fn term(metric: DistanceMetric, query: f32, value: f32) -> f32 {
+    match metric {
+        DistanceMetric::Euclidean => (query - value) * (query - value),
+        DistanceMetric::Cosine | DistanceMetric::DotProduct => query * value,
+        DistanceMetric::Manhattan => (query - value).abs(),
+        DistanceMetric::Hamming => f32::from(u8::from((query >= HAMMING_THRESHOLD) != (value >= HAMMING_THRESHOLD))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::compute_distance;
+
+    fn dataset(n: usize, dims: usize) -> Vec<Vec<f32>> {
+        (0..n)
+            .map(|i| (0..dims).map(|j| ((i * dims + j) as f32 * 0.37).sin() * (1 + j % 3) as f32).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_is_within_half_a_level() {
+        let vectors = dataset(500, 16);
+        let sq = SQCodec::train(&vectors).unwrap();
+
+        for vector in &vectors {
+            let codes = sq.encode(vector);
+            assert_eq!(codes.len(), 16);
+            for (d, (x, y)) in vector.iter().zip(sq.decode(&codes)).enumerate() {
+                assert!((x - y).abs() <= sq.scales[d] / 2.0 + 1e-6, "dimension {}: {} vs {}", d, x, y);
+            }
+        }
+
+        // Out-of-range values clamp to the ends
+        assert_eq!(sq.encode(&[1e6; 16]), vec![255; 16]);
+        assert_eq!(sq.encode(&[-1e6; 16]), vec![0; 16]);
+        assert!(SQCodec::train(&[]).is_err());
+    }
+
+    #[test]
+    fn test_distances_match_the_decoded_vectors() {
+        let vectors = dataset(300, 12);
+        let query: Vec<f32> = (0..12).map(|i| (i as f32 * 0.7).cos()).collect();
+
+        for metric in [
+            DistanceMetric::Euclidean,
+            DistanceMetric::Cosine,
+            DistanceMetric::DotProduct,
+            DistanceMetric::Manhattan,
+        ] {
+            let sq = SQCodec::train_for_metric(&vectors, metric).unwrap();
+            let table = sq.precompute_distance_table(&query);
+            for vector in vectors.iter().step_by(29) {
+                let codes = sq.encode(vector);
+                let approx = sq.asymmetric_distance(&query, &codes);
+                let decoded = compute_distance(&query, &sq.decode(&codes), metric);
+                let exact = compute_distance(&query, vector, metric);
+                assert!((sq.table_lookup_distance(&table, &codes) - approx).abs() < 1e-4);
+                // Decoded cosine vectors are only roughly unit length
+                assert!((approx - decoded).abs() < 5e-3, "{:?}: {} vs {}", metric, approx, decoded);
+                assert!((approx - exact).abs() < 0.02 * exact.abs().max(1.0), "{:?}: {} vs {}", metric, approx, exact);
+            }
+        }
+    }
+}
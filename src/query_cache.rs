@@ -0,0 +1,309 @@
+use crate::storage::lru::LruCache;
+use crate::types::SearchResult;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Limits for the search result cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryCacheConfig {
+    /// Maximum number of cached result lists
+    pub max_entries: usize,
+
+    /// Maximum approximate size of cached queries and results
+    pub max_bytes: usize,
+
+    /// Entries older than this are treated as misses
+    pub ttl: Option<Duration>,
+}
+
+impl Default for QueryCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_bytes: 64 * 1024 * 1024,
+            ttl: None,
+        }
+    }
+}
+
+/// Snapshot of result cache counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl QueryCacheStats {
+    /// Fraction of lookups served from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    query_hash: u64,
+    k: usize,
+    params: u64,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    /// Kept to rule out hash collisions
+    query: Arc<[f32]>,
+    results: Arc<Vec<SearchResult>>,
+    generation: u64,
+    inserted: Instant,
+}
+
+/// Result lists keyed by exact query bytes, `k` and search parameters.
+///
+/// Entries are tagged with the database generation they were computed at;
+/// any mutation bumps the generation, so stale entries are never served.
+#[derive(Debug)]
+pub(crate) struct QueryCache {
+    config: QueryCacheConfig,
+    entries: Mutex<LruCache<CacheKey, CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Where the next stale-entry sweep resumes, as an access tick
+    sweep_cursor: AtomicU64,
+}
+
+impl QueryCache {
+    pub(crate) fn new(config: QueryCacheConfig) -> Self {
+        let entries = LruCache::new(config.max_bytes, Some(config.max_entries));
+
+        Self {
+            config,
+            entries: Mutex::new(entries),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            sweep_cursor: AtomicU64::new(0),
+        }
+    }
+
+    fn key(query: &[f32], k: usize, params: u64) -> CacheKey {
+        let mut hasher = DefaultHasher::new();
+        for value in query {
+            value.to_bits().hash(&mut hasher);
+        }
+
+        CacheKey {
+            query_hash: hasher.finish(),
+            k,
+            params,
+        }
+    }
+
+    pub(crate) fn get(&self, query: &[f32], k: usize, params: u64, generation: u64) -> Option<Vec<SearchResult>> {
+        let key = Self::key(query, k, params);
+        let mut entries = self.entries.lock().unwrap();
+
+        let hit = entries.get(&key).filter(|entry| {
+            entry.generation == generation
+                && *entry.query == *query
+                && self.config.ttl.is_none_or(|ttl| entry.inserted.elapsed() < ttl)
+        });
+
+        match hit {
+            Some(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.results.to_vec())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&self, query: &[f32], k: usize, params: u64, generation: u64, results: &[SearchResult]) {
+        let bytes = std::mem::size_of_val(query)
+            + std::mem::size_of_val(results)
+            + results
+                .iter()
+                .filter_map(|r| r.metadata.as_ref())
+                .map(|m| m.to_string().len())
+                .sum::<usize>();
+
+        let entry = CacheEntry {
+            query: Arc::from(query),
+            results: Arc::new(results.to_vec()),
+            generation,
+            inserted: Instant::now(),
+        };
+
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(Self::key(query, k, params), entry, bytes);
+    }
+
+    fn is_stale(&self, entry: &CacheEntry, generation: u64) -> bool {
+        entry.generation != generation || self.config.ttl.is_some_and(|ttl| entry.inserted.elapsed() >= ttl)
+    }
+
+    /// Entries that can no longer be served at `generation`
+    pub(crate) fn stale_entries(&self, generation: u64) -> usize {
+        let entries = self.entries.lock().unwrap();
+        entries.values().filter(|entry| self.is_stale(entry, generation)).count()
+    }
+
+    /// Drop stale entries among the next `limit` in access order, resuming
+    /// where the last call stopped. Returns how many were removed and
+    /// whether the sweep reached the end and will restart from the oldest.
+    pub(crate) fn sweep_stale(&self, generation: u64, limit: usize) -> (usize, bool) {
+        let mut entries = self.entries.lock().unwrap();
+        let after = self.sweep_cursor.load(Ordering::Relaxed);
+        let (resume, removed) = entries.sweep(after, limit, |entry| !self.is_stale(entry, generation));
+        self.sweep_cursor.store(resume.unwrap_or(0), Ordering::Relaxed);
+        (removed, resume.is_none())
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub(crate) fn stats(&self) -> QueryCacheStats {
+        let entries = self.entries.lock().unwrap();
+
+        QueryCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: entries.len(),
+            bytes: entries.used_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuantizerKind};
+    use crate::search_params::SearchParams;
+    use crate::vector_db::VectorDB;
+
+    fn vector(i: usize) -> Vec<f32> {
+        (0..8).map(|j| ((i * 8 + j) as f32).sin()).collect()
+    }
+
+    fn build_db(cache: QueryCacheConfig) -> VectorDB {
+        let config = Config {
+            dimensions: 8,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 2,
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..100 {
+            db.insert(vector(i), Some(serde_json::json!({"i": i}))).unwrap();
+        }
+        db.build_index().unwrap();
+        db.set_query_cache(Some(cache));
+        db
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<u32> {
+        results.iter().map(|r| r.id).collect()
+    }
+
+    #[test]
+    fn test_hits_return_identical_results() {
+        let db = build_db(QueryCacheConfig::default());
+        let query = vector(7);
+
+        let first = db.search(&query, 5).unwrap();
+        let second = db.search(&query, 5).unwrap();
+        assert_eq!(ids(&first), ids(&second));
+        assert_eq!(
+            first.iter().map(|r| r.distance).collect::<Vec<_>>(),
+            second.iter().map(|r| r.distance).collect::<Vec<_>>()
+        );
+
+        let stats = db.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        // A different k or different params are separate entries
+        db.search(&query, 3).unwrap();
+        let params = SearchParams::builder().num_probe(4).build();
+        db.search_with_params(&query, 5, &params).unwrap();
+        assert_eq!(db.query_cache_stats().unwrap().misses, 3);
+
+        // Batches share the cache with single searches
+        let batch = db.batch_search(&[query.clone(), vector(8)], 5).unwrap();
+        assert_eq!(ids(&batch[0]), ids(&first));
+        assert_eq!(db.query_cache_stats().unwrap().hits, 2);
+
+        db.clear_cache();
+        let stats = db.query_cache_stats().unwrap();
+        assert_eq!(stats.entries, 0);
+        assert_eq!(stats.bytes, 0);
+    }
+
+    #[test]
+    fn test_mutations_invalidate() {
+        let mut db = build_db(QueryCacheConfig::default());
+        let query = vec![5.0; 8];
+
+        let before = db.search(&query, 1).unwrap();
+        let id = db.insert(query.clone(), None).unwrap();
+        db.build_index().unwrap();
+
+        let after = db.search(&query, 1).unwrap();
+        assert_ne!(ids(&before), ids(&after));
+        assert_eq!(after[0].id, id);
+        assert_eq!(db.query_cache_stats().unwrap().hits, 0);
+    }
+
+    #[test]
+    fn test_capacity_and_ttl_eviction() {
+        let db = build_db(QueryCacheConfig {
+            max_entries: 3,
+            ..Default::default()
+        });
+
+        for i in 0..5 {
+            db.search(&vector(i), 5).unwrap();
+        }
+        assert_eq!(db.query_cache_stats().unwrap().entries, 3);
+
+        // The two oldest queries were evicted, the newest is still cached
+        db.search(&vector(0), 5).unwrap();
+        db.search(&vector(4), 5).unwrap();
+        let stats = db.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (1, 6));
+
+        // Byte budget: room for roughly one result list
+        let db = build_db(QueryCacheConfig {
+            max_bytes: 400,
+            ..Default::default()
+        });
+        db.search(&vector(0), 5).unwrap();
+        db.search(&vector(1), 5).unwrap();
+        let stats = db.query_cache_stats().unwrap();
+        assert_eq!(stats.entries, 1);
+        assert!(stats.bytes <= 400);
+
+        let db = build_db(QueryCacheConfig {
+            ttl: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        db.search(&vector(0), 5).unwrap();
+        db.search(&vector(0), 5).unwrap();
+        assert_eq!(db.query_cache_stats().unwrap().hits, 0);
+    }
+}
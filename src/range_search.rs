@@ -0,0 +1,92 @@
+use crate::error::{KhadyotaError, Result};
+use crate::search_params::SearchParams;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use std::time::Instant;
+
+/// How far past the radius an approximate distance may fall and still be
+/// verified against the original vector, when the search parameters do
+/// not say
+pub const DEFAULT_RANGE_SLACK: f32 = 1.25;
+
+impl VectorDB {
+    /// Every entry within `radius` of `query`, nearest first, under
+    /// default search parameters
+    pub fn range_search(&self, query: &[f32], radius: f32) -> Result<Vec<SearchResult>> {
+        self.range_search_with_params(query, radius, &SearchParams::default())
+    }
+
+    /// Every entry within `radius` of `query` that a search with `params`
+    /// reaches, nearest first; empty if none is.
+    ///
+    /// Distances are exact, and an entry at exactly `radius` is included.
+    /// Under the dot product metric distances are negated inner
+    /// products, so a radius of `-t` returns entries whose inner product
+    /// with the query is at least `t`.
+    /// With PQ, candidates are first screened by their approximate
+    /// distance, then any within [`SearchParams::range_slack`] of the
+    /// radius are re-scored against their original vectors, so nothing
+    /// is returned from outside the radius; only borderline entries whose
+    /// approximation is off by more than the slack are missed. `k` and
+    /// `rerank` play no part. Results are never cached.
+    pub fn range_search_with_params(
+        &self,
+        query: &[f32],
+        radius: f32,
+        params: &SearchParams,
+    ) -> Result<Vec<SearchResult>> {
+        if radius.is_nan() {
+            return Err(KhadyotaError::InvalidConfig("radius must not be NaN".to_string()));
+        }
+        let query = &*self.check_query(query)?;
+        params.validate(self)?;
+
+        let start = self.latency.as_ref().map(|_| Instant::now());
+        let _permit = self.admit()?;
+        self.record_query();
+        let mut scored = match (&self.ivf_index, &self.quantized) {
+            (Some(ivf), Some(quantized)) => {
+                let screen = widen(radius, params.range_slack.unwrap_or(DEFAULT_RANGE_SLACK));
+                let candidates = self.candidates(query, ivf, params)?;
+                let scorer = self.code_scorer(quantized, query, &candidates);
+                let close: Vec<u32> = candidates.into_iter().filter(|&id| scorer.distance(id) <= screen).collect();
+                self.score_exact(query, close, params)?
+            }
+            (Some(ivf), None) if params.num_probe.is_some() => {
+                let candidates = self.candidates(query, ivf, params)?;
+                self.score_exact(query, candidates, params)?
+            }
+            _ => self.search_linear(query, params)?,
+        };
+        if self.ivf_index.is_some() && (self.quantized.is_some() || params.num_probe.is_some()) {
+            scored.extend(self.score_exact(query, self.unencoded_ids(params)?, params)?);
+        }
+        scored.retain(|&(_, distance)| distance <= radius);
+
+        let results = self.top_results(scored, usize::MAX, params);
+        self.record_latency(start);
+        Ok(results)
+    }
+}
+
+/// `radius` moved outwards by a factor of `slack` of its magnitude, so a
+/// negative radius (a dot product threshold) widens too
+fn widen(radius: f32, slack: f32) -> f32 {
+    if slack == f32::INFINITY {
+        return f32::INFINITY;
+    }
+    radius + radius.abs() * (slack - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slack_widens_either_sign() {
+        assert_eq!(widen(2.0, 1.25), 2.5);
+        assert_eq!(widen(-2.0, 1.25), -1.5);
+        assert_eq!(widen(0.5, 1.0), 0.5);
+        assert_eq!(widen(0.0, f32::INFINITY), f32::INFINITY);
+    }
+}
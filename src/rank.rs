@@ -0,0 +1,129 @@
+use crate::distance::{compute_distances, unit_cosine_distance};
+use crate::error::{KhadyotaError, Result};
+use crate::search_params::SearchParams;
+use crate::select::smallest_k;
+use crate::storage::VectorStorage;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use std::collections::HashSet;
+use std::time::Instant;
+
+impl VectorDB {
+    /// Rank `ids` by distance to `query` and return the `k` nearest,
+    /// without probing the IVF index
+    pub fn rank_candidates(&self, query: &[f32], ids: &[u32], k: usize, exact: bool) -> Result<Vec<SearchResult>> {
+        self.rank_candidates_with_params(query, ids, k, exact, &SearchParams::default())
+    }
+
+    /// Rank a caller-chosen candidate set, such as hits from a keyword
+    /// index, with the same exclusions, filter, expiry and metric as a
+    /// search.
+    ///
+    /// Every id must name a live entry, or the call fails with
+    /// [`KhadyotaError::VectorNotFound`]; duplicates are scored once.
+    /// `max_candidates` keeps the first ids in the order given. Exact
+    /// scoring reads runs of consecutive ids through the batched distance
+    /// kernel. With `exact` unset the ids are scored from their PQ codes,
+    /// reranked if `rerank` is set; without a built PQ index the distances
+    /// are exact regardless. Unlike `search`, this works before
+    /// `build_index()` and bypasses the query cache.
+    pub fn rank_candidates_with_params(
+        &self,
+        query: &[f32],
+        ids: &[u32],
+        k: usize,
+        exact: bool,
+        params: &SearchParams,
+    ) -> Result<Vec<SearchResult>> {
+        let query = &*self.prepared_query(query)?;
+        self.require_full_width(query)?;
+        params.validate(self)?;
+        if let Some(&missing) = ids.iter().find(|&&id| id >= self.next_id || self.deleted.contains(&id)) {
+            return Err(KhadyotaError::VectorNotFound(missing));
+        }
+
+        let mut seen = HashSet::with_capacity(ids.len());
+        let mut filter = params.filter_pass();
+        let mut ids: Vec<u32> = ids
+            .iter()
+            .copied()
+            .filter(|&id| seen.insert(id) && !params.exclude.contains(&id) && filter.admits(id))
+            .collect();
+        filter.finish()?;
+        if let Some(max) = params.max_candidates {
+            ids.truncate(max);
+        }
+        ids.sort_unstable();
+
+        let start = self.latency.as_ref().map(|_| Instant::now());
+        let _permit = self.admit()?;
+        self.record_query();
+        self.record_scan(&[], ids.len());
+        let quantized = self.quantized.as_ref().filter(|_| !exact && self.index_built);
+
+        let scored = match quantized {
+            Some(quantized) => {
+                let mut scored = self.code_scorer(quantized, query, &ids).score(&ids);
+                if let Some(rerank) = params.rerank {
+                    let mut ids: Vec<u32> = smallest_k(scored, rerank.max(k)).into_iter().map(|(id, _)| id).collect();
+                    ids.sort_unstable();
+                    scored = self.score_runs(query, ids, params)?;
+                }
+                scored
+            }
+            None => self.score_runs(query, ids, params)?,
+        };
+
+        let results = self.top_results(scored, k, params);
+        self.record_latency(start);
+        Ok(results)
+    }
+
+    /// Exact distances for ascending `ids`, scoring each run of
+    /// consecutive in-memory rows as one batch
+    fn score_runs(&self, query: &[f32], ids: Vec<u32>, params: &SearchParams) -> Result<Vec<(u32, f32)>> {
+        let VectorStorage::Memory(rows) = &self.vectors else {
+            return self.score_exact(query, ids, params);
+        };
+
+        let metric = params.metric.unwrap_or(self.config.metric);
+        let mut scored = Vec::with_capacity(ids.len());
+        for run in ids.chunk_by(|a, b| *b == a + 1) {
+            let first = run[0] as usize;
+            let rows = &rows[first..first + run.len()];
+            let distances = if self.scores_unit_cosine(query, metric) {
+                rows.iter().map(|row| unit_cosine_distance(query, row)).collect()
+            } else {
+                compute_distances(query, rows, metric)
+            };
+            scored.extend(run.iter().copied().zip(distances));
+        }
+        Ok(scored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, QuantizerKind};
+    use crate::insert_options::InsertOptions;
+    use crate::vector_db::VectorDB;
+    use std::time::Duration;
+
+    #[test]
+    fn test_expired_candidates_are_dropped() {
+        let mut db = VectorDB::new(Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            ..Default::default()
+        })
+        .unwrap();
+        let live = db.insert(vec![1.0, 0.0, 0.0, 0.0], None).unwrap();
+        let expired = db
+            .insert_opts(vec![1.0, 0.1, 0.0, 0.0], InsertOptions::new().ttl(Duration::from_secs(1)))
+            .unwrap();
+        db.attributes.get_mut(&expired).unwrap().expires_at_unix_secs = Some(1);
+
+        let ranked = db.rank_candidates(&[1.0, 0.1, 0.0, 0.0], &[expired, live], 2, true).unwrap();
+        assert_eq!(ranked.iter().map(|r| r.id).collect::<Vec<_>>(), [live]);
+    }
+}
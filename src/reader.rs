@@ -0,0 +1,135 @@
+use crate::error::Result;
+use crate::search_params::SearchParams;
+use crate::types::{SearchResult, VectorEntry};
+use crate::vector_db::VectorDB;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// A read-only snapshot of a database, for serving searches from many
+/// threads without a lock.
+///
+/// Taken with [`VectorDB::reader`]. Cloning is cheap and every clone
+/// shares the snapshot; later writes to the database are not seen until
+/// a new one is taken. The usual pattern is one writer owning the
+/// `VectorDB`, handing out a fresh reader after each build or batch of
+/// writes, while searches hold whichever reader they started with.
+#[derive(Clone)]
+pub struct SearchReader {
+    db: Arc<VectorDB>,
+}
+
+impl VectorDB {
+    /// Snapshot the state searches read: vectors, codes, the index,
+    /// metadata, deletions and expiry.
+    ///
+    /// In-memory vectors are copied, so this costs about as much as the
+    /// collection; spilled vectors share their mapping. The query cache,
+    /// admission control, latency recording and access statistics stay
+    /// with this database and do not apply to the snapshot's searches.
+    pub fn reader(&self) -> SearchReader {
+        let mut db = VectorDB::new(self.config.clone()).expect("a live config is valid");
+        db.vectors = self.vectors.snapshot();
+        db.quantized = self.quantized.clone();
+        db.ivf_index = self.ivf_index.clone();
+        db.flat_index = self.flat_index.clone();
+        db.metadata = self.metadata.clone();
+        db.external_metadata = self.external_metadata.clone();
+        db.next_id = self.next_id;
+        db.index_built = self.index_built;
+        db.attributes = self.attributes.clone();
+        db.deleted = self.deleted.clone();
+        db.index_lag = self.index_lag.clone();
+        db.seq = self.seq;
+        SearchReader { db: Arc::new(db) }
+    }
+}
+
+impl SearchReader {
+    /// See [`VectorDB::search`]
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        self.db.search(query, k)
+    }
+
+    /// See [`VectorDB::search_with_params`]
+    pub fn search_with_params(&self, query: &[f32], k: usize, params: &SearchParams) -> Result<Vec<SearchResult>> {
+        self.db.search_with_params(query, k, params)
+    }
+
+    /// See [`VectorDB::batch_search`]
+    pub fn batch_search(&self, queries: &[Vec<f32>], k: usize) -> Result<Vec<Vec<SearchResult>>> {
+        self.db.batch_search(queries, k)
+    }
+
+    /// See [`VectorDB::range_search`]
+    pub fn range_search(&self, query: &[f32], radius: f32) -> Result<Vec<SearchResult>> {
+        self.db.range_search(query, radius)
+    }
+
+    /// See [`VectorDB::get`]
+    pub fn get(&self, id: u32) -> Result<VectorEntry> {
+        self.db.get(id)
+    }
+
+    /// See [`VectorDB::get_entry`]
+    pub fn get_entry(&self, id: u32) -> Option<VectorEntry> {
+        self.db.get_entry(id)
+    }
+
+    /// See [`VectorDB::get_vector`]
+    pub fn get_vector(&self, id: u32) -> Option<Cow<'_, [f32]>> {
+        self.db.get_vector(id)
+    }
+
+    /// Live entries in the snapshot
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    /// Sequence number of the last change the snapshot includes, as
+    /// [`VectorDB::applied_seq`] was when it was taken
+    pub fn seq(&self) -> u64 {
+        self.db.seq
+    }
+
+    /// The snapshot as a database, for anything else that only reads
+    pub fn as_db(&self) -> &VectorDB {
+        &self.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuantizerKind};
+
+    #[test]
+    fn test_reader_does_not_see_later_writes() {
+        let config = Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            num_clusters: 2,
+            ..Default::default()
+        };
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..10 {
+            db.insert(vec![i as f32, 1.0, 0.0, 0.0], None).unwrap();
+        }
+        db.build_index().unwrap();
+
+        let reader = db.reader();
+        db.delete(3).unwrap();
+        db.insert(vec![3.0, 1.0, 0.0, 0.0], None).unwrap();
+
+        assert_eq!(reader.len(), 10);
+        assert!(reader.get(10).is_err());
+        let query = [3.0, 1.0, 0.0, 0.0];
+        assert_eq!(reader.search(&query, 1).unwrap()[0].id, 3);
+        assert_eq!(db.search(&query, 1).unwrap()[0].id, 10);
+        assert_eq!(db.reader().seq(), db.applied_seq());
+        assert!(reader.seq() < db.applied_seq());
+    }
+}
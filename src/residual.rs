@@ -0,0 +1,275 @@
+//! Residual PQ codes, under
+//! [`Config::use_residuals`](crate::config::Config::use_residuals): each
+//! row is encoded as its offset from the centroid of the IVF cluster that
+//! lists it, and scored through distance terms made for that cluster.
+//!
+//! Euclidean distances need a table per cluster, of the query's offset
+//! from its centroid. Dot products and cosine similarities split into the
+//! query against the centroid, one term per cluster, and against the
+//! residual, from the usual table of the query. Cosine residuals are
+//! offsets of the unit-length row, which the codec does not normalize.
+
+use crate::config::{Config, DistanceMetric};
+use crate::distance::{dot_product, key_to_distance, normalized};
+use crate::error::Result;
+use crate::indexing::IVFIndex;
+use crate::progress::ProgressCallback;
+use crate::quantization::{OPQCodec, PQCodec, Quantizer};
+use crate::storage::{QuantizedVectors, ScanScratch};
+use crate::vector_db::VectorDB;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+
+/// `vector`'s offset from `centroid`, as residual codes encode it
+fn residual(vector: &[f32], centroid: &[f32], metric: DistanceMetric) -> Vec<f32> {
+    let vector = match metric {
+        DistanceMetric::Cosine => Cow::Owned(normalized(vector)),
+        _ => Cow::Borrowed(vector),
+    };
+    vector.iter().zip(centroid).map(|(x, c)| x - c).collect()
+}
+
+/// Cluster `row`, stored at `id`, is encoded against: the one listing
+/// it, or for a row no list holds, such as a deleted one, the nearest
+fn cluster_for(ivf: &IVFIndex, id: u32, row: &[f32]) -> usize {
+    ivf.cluster_of(id).unwrap_or_else(|| ivf.assign(row))
+}
+
+impl VectorDB {
+    /// Whether the code rows are residuals
+    pub(crate) fn residual_codes(&self) -> bool {
+        self.quantized.as_ref().is_some_and(QuantizedVectors::is_residual)
+    }
+
+    /// Train PQ codebooks on the residuals of `training` against `ivf`,
+    /// just built over `rows`, and encode every row against its cluster
+    pub(crate) fn train_residual_codes(
+        config: &Config,
+        ivf: &IVFIndex,
+        rows: &[Vec<f32>],
+        training: &[Vec<f32>],
+        progress: &dyn ProgressCallback,
+    ) -> Result<QuantizedVectors> {
+        let metric = config.metric;
+        let residuals: Vec<Vec<f32>> = training
+            .par_iter()
+            .map(|row| residual(row, &ivf.centroids()[ivf.assign(row)], metric))
+            .collect();
+        let (subvectors, seed, bits) = (config.pq_subvectors, config.seed, config.pq_bits);
+        let codec: Quantizer = if config.use_opq {
+            OPQCodec::train_prepared(&residuals, subvectors, seed, metric, bits, &config.pq_kmeans, progress)?.into()
+        } else {
+            PQCodec::train_prepared(&residuals, subvectors, seed, metric, bits, &config.pq_kmeans, progress)?.into()
+        };
+
+        let mut quantized = QuantizedVectors::new_residual(codec);
+        let codes: Vec<Vec<u8>> = rows
+            .par_iter()
+            .enumerate()
+            .map(|(id, row)| quantized.encode(&residual(row, &ivf.centroids()[cluster_for(ivf, id as u32, row)], metric)))
+            .collect();
+        for (id, codes) in codes.into_iter().enumerate() {
+            quantized.put_codes(id as u32, codes);
+        }
+        Ok(quantized)
+    }
+
+    /// Encode `ids` against the clusters now listing them, once they are
+    /// assigned. Ids past the last code row must continue on from it, in
+    /// order.
+    pub(crate) fn encode_residuals(&mut self, ids: impl IntoIterator<Item = u32>) {
+        let (Some(ivf), Some(quantized)) = (&self.ivf_index, &self.quantized) else {
+            return;
+        };
+        let metric = self.config.metric;
+        let ids: Vec<u32> = ids.into_iter().collect();
+        let codes: Vec<Vec<u8>> = ids
+            .par_iter()
+            .map(|&id| {
+                let row = self.vectors.get(id).unwrap();
+                quantized.encode(&residual(&row, &ivf.centroids()[cluster_for(ivf, id, &row)], metric))
+            })
+            .collect();
+
+        let quantized = self.quantized.as_mut().unwrap();
+        for (id, codes) in ids.into_iter().zip(codes) {
+            quantized.put_codes(id, codes);
+        }
+    }
+
+    /// The vector `id`'s codes stand for, centroid added back for a
+    /// residual; `None` if it has no codes, or residual codes but no
+    /// cluster
+    pub(crate) fn decoded_row(&self, id: u32) -> Option<Vec<f32>> {
+        let quantized = self.quantized.as_ref().filter(|q| (id as usize) < q.len())?;
+        let mut row = quantized.codec().decode(quantized.get_codes(id));
+        if quantized.is_residual() {
+            let ivf = self.ivf_index.as_ref()?;
+            let centroid = &ivf.centroids()[ivf.cluster_of(id)?];
+            row.iter_mut().zip(centroid).for_each(|(x, c)| *x += c);
+        }
+        Some(row)
+    }
+
+    /// A scorer of `query` against the codes of `ids`, or any rows in
+    /// the same clusters
+    pub(crate) fn code_scorer<'a>(&'a self, quantized: &'a QuantizedVectors, query: &[f32], ids: &[u32]) -> CodeScorer<'a> {
+        self.code_scorer_reusing(quantized, query, ids, Vec::new())
+    }
+
+    /// [`VectorDB::code_scorer`] making its shared table in `table`, as
+    /// an earlier scorer gave it back from [`CodeScorer::into_table`]
+    pub(crate) fn code_scorer_reusing<'a>(
+        &'a self,
+        quantized: &'a QuantizedVectors,
+        query: &[f32],
+        ids: &[u32],
+        mut table: Vec<Vec<f32>>,
+    ) -> CodeScorer<'a> {
+        let metric = quantized.codec().recorded_metric().unwrap_or(DistanceMetric::Euclidean);
+        let residual_ivf = self.ivf_index.as_ref().filter(|_| quantized.is_residual());
+        // Euclidean residuals are scored by their clusters' tables alone
+        let shared = match residual_ivf {
+            Some(_) if metric == DistanceMetric::Euclidean => Vec::new(),
+            _ => {
+                quantized.precompute_distance_table_into(query, &mut table);
+                table
+            }
+        };
+        let clusters = residual_ivf.map(|ivf| {
+            let unit = (metric == DistanceMetric::Cosine).then(|| normalized(query));
+            let listed: BTreeSet<usize> = ids.iter().filter_map(|&id| ivf.cluster_of(id)).collect();
+            let terms = listed
+                .into_par_iter()
+                .map(|cluster| {
+                    // A Matryoshka prefix meets the same prefix of the centroid
+                    let centroid = &ivf.centroids()[cluster][..query.len()];
+                    let terms = match metric {
+                        DistanceMetric::Euclidean => ClusterTerms {
+                            table: Some(quantized.precompute_distance_table(&residual(query, centroid, metric))),
+                            offset: 0.0,
+                        },
+                        DistanceMetric::Cosine => ClusterTerms {
+                            table: None,
+                            offset: -dot_product(unit.as_deref().unwrap(), centroid),
+                        },
+                        _ => ClusterTerms {
+                            table: None,
+                            offset: -dot_product(query, centroid),
+                        },
+                    };
+                    (cluster, terms)
+                })
+                .collect();
+            (ivf, terms)
+        });
+        CodeScorer { quantized, metric, shared, clusters }
+    }
+}
+
+/// What scores one cluster's residual codes: its own table, when the
+/// shared one does not serve, and the centroid's part of the distance
+struct ClusterTerms {
+    table: Option<Vec<Vec<f32>>>,
+    offset: f32,
+}
+
+/// Scores rows from their codes for one query: through one distance
+/// table, or for residual codes through per-cluster terms made up front
+/// for the clusters of the rows it was made for
+pub(crate) struct CodeScorer<'a> {
+    quantized: &'a QuantizedVectors,
+    /// Metric the codec scores in
+    pub(crate) metric: DistanceMetric,
+    shared: Vec<Vec<f32>>,
+    clusters: Option<(&'a IVFIndex, HashMap<usize, ClusterTerms>)>,
+}
+
+impl CodeScorer<'_> {
+    /// Table `id` is looked up in and the centroid term added to it;
+    /// `None` for a residual row outside the clusters prepared
+    fn table(&self, id: u32) -> Option<(&[Vec<f32>], f32)> {
+        let Some((ivf, clusters)) = &self.clusters else {
+            return Some((&self.shared, 0.0));
+        };
+        let terms = clusters.get(&ivf.cluster_of(id)?)?;
+        Some((terms.table.as_ref().unwrap_or(&self.shared), terms.offset))
+    }
+
+    /// Approximate distance from the query to `id`; infinite for a
+    /// residual row outside the clusters prepared
+    pub(crate) fn distance(&self, id: u32) -> f32 {
+        match self.table(id) {
+            Some((table, offset)) => self.quantized.table_lookup_distance(table, id) + offset,
+            None => f32::INFINITY,
+        }
+    }
+
+    /// Cluster whose terms score `id`; `None` for every row when codes
+    /// are not residuals, and so share one table
+    fn cluster(&self, id: u32) -> Option<usize> {
+        self.clusters.as_ref().and_then(|(ivf, _)| ivf.cluster_of(id))
+    }
+
+    /// [`CodeScorer::distance`] for each of `ids`, in order
+    pub(crate) fn score(&self, ids: &[u32]) -> Vec<(u32, f32)> {
+        let mut scored = self.score_keys(ids);
+        scored.iter_mut().for_each(|(_, key)| *key = key_to_distance(self.metric, *key));
+        scored
+    }
+
+    /// [`CodeScorer::score`] as [`distance_key`](crate::distance::distance_key)s
+    /// of the codec's metric, Euclidean distances left squared. Runs of ids
+    /// scored through the same table, such as a cluster's list, are
+    /// scanned as one block.
+    pub(crate) fn score_keys(&self, ids: &[u32]) -> Vec<(u32, f32)> {
+        let mut scored = Vec::new();
+        self.score_keys_into(ids, &mut Vec::new(), &mut ScanScratch::default(), &mut scored);
+        scored
+    }
+
+    /// [`CodeScorer::score_keys`] into `scored`, replacing what it held,
+    /// with `distances` and `scan` as working space
+    pub(crate) fn score_keys_into(
+        &self,
+        ids: &[u32],
+        distances: &mut Vec<f32>,
+        scan: &mut ScanScratch,
+        scored: &mut Vec<(u32, f32)>,
+    ) {
+        distances.clear();
+        distances.resize(ids.len(), 0.0);
+        let mut start = 0;
+        while start < ids.len() {
+            let cluster = self.cluster(ids[start]);
+            let len = ids[start..].iter().position(|&id| self.cluster(id) != cluster).unwrap_or(ids.len() - start);
+            let (run, out) = (&ids[start..start + len], &mut distances[start..start + len]);
+            match self.table(run[0]) {
+                Some((table, offset)) => {
+                    // Only metrics whose keys are their distances have offsets
+                    self.quantized.scan_keys(table, run, out, scan);
+                    out.iter_mut().for_each(|key| *key += offset);
+                }
+                None => out.fill(f32::INFINITY),
+            }
+            start += len;
+        }
+        scored.clear();
+        scored.extend(ids.iter().copied().zip(distances.iter().copied()));
+    }
+
+    /// The shared table, for [`VectorDB::code_scorer_reusing`] to fill
+    /// for the next query
+    pub(crate) fn into_table(self) -> Vec<Vec<f32>> {
+        self.shared
+    }
+
+    /// Per-subquantizer table entries behind [`CodeScorer::distance`],
+    /// without a residual's centroid term
+    pub(crate) fn terms(&self, id: u32) -> Vec<f32> {
+        self.table(id)
+            .map(|(table, _)| self.quantized.distance_terms(table, id))
+            .unwrap_or_default()
+    }
+}
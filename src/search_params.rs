@@ -0,0 +1,396 @@
+use crate::config::{DistanceMetric, QuantizerKind};
+use crate::error::{KhadyotaError, Result};
+use crate::filter::{CandidateFilter, FilterPass};
+use crate::vector_db::{Fnv1a, VectorDB};
+use std::collections::HashSet;
+
+/// Per-query search knobs.
+///
+/// Every field left at its default reproduces [`VectorDB::search`]. Build
+/// with [`SearchParams::builder`] or start from a [`SearchPreset`]; the
+/// combination is checked against the database by [`SearchParams::validate`]
+/// before any work is done.
+#[derive(Debug, Clone)]
+pub struct SearchParams {
+    /// Clusters to probe; `None` uses the configured `num_probe`.
+    /// Setting it on a database without PQ switches from a full scan to an
+    /// IVF-restricted exact scan.
+    pub num_probe: Option<usize>,
+
+    /// Re-score this many PQ candidates with exact distances before taking
+    /// the top-k. Requires PQ, or `coarse_dims`.
+    pub rerank: Option<usize>,
+
+    /// Upper bound on candidates scored per query, taken in probe order
+    pub max_candidates: Option<usize>,
+
+    /// Ids that must not appear in the results
+    pub exclude: HashSet<u32>,
+
+    /// Distance metric for exact scoring; `None` uses the configured metric
+    pub metric: Option<DistanceMetric>,
+
+    /// Attach metadata to results
+    pub include_metadata: bool,
+
+    /// Also attach metadata kept apart for being over
+    /// [`Config::max_metadata_bytes`](crate::config::Config::max_metadata_bytes),
+    /// when `include_metadata` is set
+    pub include_external_metadata: bool,
+
+    /// Factor past the radius within which a range search verifies PQ
+    /// candidates against their original vectors; `None` uses
+    /// [`DEFAULT_RANGE_SLACK`](crate::range_search::DEFAULT_RANGE_SLACK).
+    /// Must be at least 1; infinity verifies every candidate.
+    pub range_slack: Option<f32>,
+
+    /// Pre-filter deciding which entries may be returned, applied as
+    /// candidates are collected
+    pub filter: Option<CandidateFilter>,
+
+    /// For a full-length query: probe and score candidates on only this
+    /// many leading components, one of the configured
+    /// [`matryoshka_dims`](crate::config::Config::matryoshka_dims), then
+    /// re-score the best `rerank` (at least `k`) with the whole query.
+    /// Ignored for queries already that short.
+    pub coarse_dims: Option<usize>,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            num_probe: None,
+            rerank: None,
+            max_candidates: None,
+            exclude: HashSet::new(),
+            metric: None,
+            include_metadata: true,
+            include_external_metadata: false,
+            range_slack: None,
+            filter: None,
+            coarse_dims: None,
+        }
+    }
+}
+
+/// Speed/recall trade-offs derived from the database's index statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchPreset {
+    /// Probe ~1/20th of the clusters (at least one), no reranking
+    Fast,
+
+    /// Probe the configured `num_probe`; rerank 64 candidates when PQ is on
+    Balanced,
+
+    /// Probe 4x the configured `num_probe` (at least a quarter of the
+    /// clusters); rerank 256 candidates when PQ is on
+    HighRecall,
+}
+
+impl SearchParams {
+    pub fn builder() -> SearchParamsBuilder {
+        SearchParamsBuilder::default()
+    }
+
+    /// Parameters for `preset`, scaled to `db`'s index
+    pub fn preset(preset: SearchPreset, db: &VectorDB) -> Self {
+        let num_clusters = db
+            .ivf_index
+            .as_ref()
+            .map(|ivf| ivf.num_clusters())
+            .unwrap_or(db.config.num_clusters)
+            .max(1);
+        let num_probe = db.config.num_probe.clamp(1, num_clusters);
+        let quantized = db.quantized.is_some() || db.config.quantizer != QuantizerKind::None;
+
+        let (probe, rerank) = match preset {
+            SearchPreset::Fast => ((num_clusters / 20).max(1), None),
+            SearchPreset::Balanced => (num_probe, quantized.then_some(64)),
+            SearchPreset::HighRecall => (
+                (num_probe * 4).max(num_clusters / 4).min(num_clusters),
+                quantized.then_some(256),
+            ),
+        };
+
+        Self {
+            num_probe: Some(probe),
+            rerank,
+            ..Default::default()
+        }
+    }
+
+    /// Hash of every knob, used to key cached results and recorded in
+    /// [`ResultEnvelope`](crate::ResultEnvelope)s. Stable across builds
+    /// and platforms. `range_slack` is left out: only range searches read
+    /// it, and they are neither cached nor enveloped. So is `filter`,
+    /// which cannot be hashed; filtered searches bypass the cache.
+    /// `coarse_dims` is hashed only when set, so fingerprints recorded
+    /// before it existed still match.
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let mut exclude: Vec<u32> = self.exclude.iter().copied().collect();
+        exclude.sort_unstable();
+        let mut hasher = Fnv1a::default();
+        rmp_serde::encode::write(&mut hasher, &(
+            self.num_probe,
+            self.rerank,
+            self.max_candidates,
+            exclude,
+            self.metric,
+            self.include_metadata,
+            self.include_external_metadata,
+        ))
+        .expect("encoding into a hasher cannot fail");
+        if let Some(dims) = self.coarse_dims {
+            rmp_serde::encode::write(&mut hasher, &dims).expect("encoding into a hasher cannot fail");
+        }
+        hasher.0
+    }
+
+    /// A pass of the filter, if any, over one list of candidates
+    pub(crate) fn filter_pass(&self) -> FilterPass<'_> {
+        self.filter.as_ref().map_or_else(FilterPass::open, |filter| filter.pass())
+    }
+
+    /// Check these parameters against `db`'s configuration and index
+    pub fn validate(&self, db: &VectorDB) -> Result<()> {
+        let invalid = |msg: String| Err(KhadyotaError::InvalidConfig(msg));
+
+        if let Some(num_probe) = self.num_probe {
+            if num_probe == 0 {
+                return invalid("num_probe must be > 0".to_string());
+            }
+            match &db.ivf_index {
+                None => return invalid("num_probe requires an IVF index".to_string()),
+                Some(ivf) if num_probe > ivf.num_clusters() => {
+                    return invalid(format!(
+                        "num_probe ({}) exceeds the number of clusters ({})",
+                        num_probe,
+                        ivf.num_clusters()
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(rerank) = self.rerank {
+            if rerank == 0 {
+                return invalid("rerank must be > 0".to_string());
+            }
+            if db.quantized.is_none() && self.coarse_dims.is_none() {
+                return invalid(
+                    "rerank requires PQ; distances are already exact without it".to_string()
+                );
+            }
+        }
+
+        if let Some(dims) = self.coarse_dims
+            && !db.config.is_matryoshka_prefix(dims)
+        {
+            return invalid(format!(
+                "coarse_dims ({}) must be one of the configured matryoshka_dims below {}, {:?}",
+                dims, db.config.dimensions, db.config.matryoshka_dims
+            ));
+        }
+
+        if self.max_candidates == Some(0) {
+            return invalid("max_candidates must be > 0".to_string());
+        }
+
+        if let Some(slack) = self.range_slack
+            && (slack.is_nan() || slack < 1.0)
+        {
+            return invalid(format!("range_slack must be at least 1, got {}", slack));
+        }
+
+        if let Some(metric) = self.metric
+            && metric != db.config.metric
+            && db.quantized.is_some()
+            && self.rerank.is_none()
+        {
+            return invalid(format!(
+                "metric override ({:?}) cannot be applied to PQ distance tables; set rerank",
+                metric
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Builder for [`SearchParams`]
+#[derive(Debug, Clone, Default)]
+pub struct SearchParamsBuilder {
+    params: SearchParams,
+}
+
+impl SearchParamsBuilder {
+    pub fn num_probe(mut self, num_probe: usize) -> Self {
+        self.params.num_probe = Some(num_probe);
+        self
+    }
+
+    pub fn rerank(mut self, candidates: usize) -> Self {
+        self.params.rerank = Some(candidates);
+        self
+    }
+
+    pub fn max_candidates(mut self, max_candidates: usize) -> Self {
+        self.params.max_candidates = Some(max_candidates);
+        self
+    }
+
+    pub fn exclude(mut self, ids: impl IntoIterator<Item = u32>) -> Self {
+        self.params.exclude.extend(ids);
+        self
+    }
+
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.params.metric = Some(metric);
+        self
+    }
+
+    pub fn include_metadata(mut self, include: bool) -> Self {
+        self.params.include_metadata = include;
+        self
+    }
+
+    pub fn include_external_metadata(mut self, include: bool) -> Self {
+        self.params.include_external_metadata = include;
+        self
+    }
+
+    pub fn range_slack(mut self, slack: f32) -> Self {
+        self.params.range_slack = Some(slack);
+        self
+    }
+
+    pub fn filter(mut self, filter: CandidateFilter) -> Self {
+        self.params.filter = Some(filter);
+        self
+    }
+
+    pub fn coarse_dims(mut self, dims: usize) -> Self {
+        self.params.coarse_dims = Some(dims);
+        self
+    }
+
+    pub fn build(self) -> SearchParams {
+        self.params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn build_db(use_pq: bool) -> VectorDB {
+        let config = Config {
+            dimensions: 16,
+            quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+            pq_subvectors: 4,
+            num_clusters: 40,
+            num_probe: 4,
+            ..Default::default()
+        };
+
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..400 {
+            let vector: Vec<f32> = (0..16).map(|j| ((i * 16 + j) as f32).sin()).collect();
+            db.insert(vector, Some(serde_json::json!({"i": i}))).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    }
+
+    fn error_message(params: SearchParams, db: &VectorDB) -> String {
+        match params.validate(db) {
+            Err(KhadyotaError::InvalidConfig(msg)) => msg,
+            other => panic!("expected InvalidConfig, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        let pq = build_db(true);
+        let exact = build_db(false);
+        let unbuilt = VectorDB::new(Config { dimensions: 16, ..Default::default() }).unwrap();
+
+        let cases = [
+            (SearchParams::builder().num_probe(0).build(), &pq, "num_probe must be > 0"),
+            (
+                SearchParams::builder().num_probe(41).build(),
+                &pq,
+                "num_probe (41) exceeds the number of clusters (40)",
+            ),
+            (SearchParams::builder().num_probe(2).build(), &unbuilt, "num_probe requires an IVF index"),
+            (SearchParams::builder().rerank(0).build(), &pq, "rerank must be > 0"),
+            (
+                SearchParams::builder().rerank(10).build(),
+                &exact,
+                "rerank requires PQ; distances are already exact without it",
+            ),
+            (SearchParams::builder().max_candidates(0).build(), &pq, "max_candidates must be > 0"),
+            (SearchParams::builder().range_slack(0.5).build(), &pq, "range_slack must be at least 1, got 0.5"),
+            (SearchParams::builder().range_slack(f32::NAN).build(), &exact, "range_slack must be at least 1, got NaN"),
+            (
+                SearchParams::builder().metric(DistanceMetric::Euclidean).build(),
+                &pq,
+                "metric override (Euclidean) cannot be applied to PQ distance tables; set rerank",
+            ),
+        ];
+
+        for (params, db, expected) in cases {
+            assert_eq!(error_message(params, db), expected);
+        }
+
+        // The same knobs are fine where the database supports them
+        assert!(SearchParams::builder().metric(DistanceMetric::Euclidean).build().validate(&exact).is_ok());
+        assert!(SearchParams::builder()
+            .metric(DistanceMetric::Euclidean)
+            .rerank(50)
+            .build()
+            .validate(&pq)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_search_validates_up_front() {
+        let db = build_db(true);
+        let query = vec![0.1; 16];
+
+        let bad = SearchParams::builder().num_probe(1000).build();
+        assert!(matches!(
+            db.search_with_params(&query, 5, &bad),
+            Err(KhadyotaError::InvalidConfig(_))
+        ));
+
+        let results = db
+            .search_with_params(&query, 5, &SearchParams::builder().exclude([0, 1, 2]).include_metadata(false).build())
+            .unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r.id > 2 && r.metadata.is_none()));
+    }
+
+    #[test]
+    fn test_presets_differ_as_documented() {
+        let db = build_db(true);
+        let fast = SearchParams::preset(SearchPreset::Fast, &db);
+        let balanced = SearchParams::preset(SearchPreset::Balanced, &db);
+        let high = SearchParams::preset(SearchPreset::HighRecall, &db);
+
+        assert_eq!(fast.num_probe, Some(2));
+        assert_eq!(fast.rerank, None);
+        assert_eq!(balanced.num_probe, Some(4));
+        assert_eq!(balanced.rerank, Some(64));
+        assert_eq!(high.num_probe, Some(16));
+        assert_eq!(high.rerank, Some(256));
+
+        for params in [&fast, &balanced, &high] {
+            params.validate(&db).unwrap();
+        }
+
+        let exact = build_db(false);
+        assert_eq!(SearchParams::preset(SearchPreset::HighRecall, &exact).rerank, None);
+    }
+}
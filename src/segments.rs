@@ -0,0 +1,941 @@
+use crate::config::{Config, QuantizerKind};
+use crate::contract::InputContract;
+use crate::distance::{compute_distance, normalized};
+use crate::error::{KhadyotaError, Result};
+use crate::health::{Health, HealthStatus, component};
+use crate::metadata::check_size;
+use crate::select::cmp_distance;
+use crate::storage::replace::replace_file;
+use crate::types::SearchResult;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std-thread")]
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "std-thread")]
+use std::thread::JoinHandle;
+
+const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+/// Where stores saved before active files were versioned kept them
+const LEGACY_ACTIVE_FILE: &str = "active.seg";
+
+/// Decides which sealed segments to combine
+pub trait MergePolicy: Send + Sync {
+    /// Given the sizes of sealed segments (oldest first), return the indices
+    /// of the segments to merge into one, or `None` if nothing should merge.
+    fn select(&self, sizes: &[usize]) -> Option<Vec<usize>>;
+}
+
+/// Size-tiered merging: once `merge_factor` segments of the same size tier
+/// exist, they are combined into one segment of the next tier.
+#[derive(Debug, Clone)]
+pub struct TieredMergePolicy {
+    /// Number of same-tier segments that triggers a merge
+    pub merge_factor: usize,
+
+    /// Segments at or above this size are never merged again
+    pub max_segment_size: usize,
+}
+
+impl Default for TieredMergePolicy {
+    fn default() -> Self {
+        Self {
+            merge_factor: 4,
+            max_segment_size: 1_000_000,
+        }
+    }
+}
+
+impl TieredMergePolicy {
+    fn tier(&self, size: usize) -> u32 {
+        let factor = self.merge_factor.max(2);
+        let mut tier = 0;
+        let mut bound = factor;
+        while size >= bound {
+            tier += 1;
+            bound = bound.saturating_mul(factor);
+        }
+        tier
+    }
+}
+
+impl MergePolicy for TieredMergePolicy {
+    fn select(&self, sizes: &[usize]) -> Option<Vec<usize>> {
+        let factor = self.merge_factor.max(2);
+        let mut tiers: HashMap<u32, Vec<usize>> = HashMap::new();
+
+        for (idx, &size) in sizes.iter().enumerate() {
+            if size >= self.max_segment_size {
+                continue;
+            }
+            let members = tiers.entry(self.tier(size)).or_default();
+            members.push(idx);
+            if members.len() == factor {
+                return Some(members.clone());
+            }
+        }
+
+        None
+    }
+}
+
+/// What searches and [`SegmentedDB::open_with`] do when a sealed segment
+/// fails to load or to answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FailurePolicy {
+    /// Any failing segment fails the whole operation
+    #[default]
+    FailFast,
+
+    /// Serve from the segments that work, reporting the ones that don't,
+    /// as long as at least `min_segments` of them answer. Stores with
+    /// fewer sealed segments need all of them.
+    Partial { min_segments: usize },
+}
+
+/// A sealed segment that could not be searched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentFailure {
+    /// Sequence number of the segment, as in its file name
+    pub seq: u64,
+
+    pub error: String,
+
+    /// The segment failed to load, so every search misses it until its
+    /// file is repaired and the store reopened
+    pub persistent: bool,
+}
+
+/// Results of [`SegmentedDB::search_outcome`] with the segments they
+/// are missing
+#[derive(Debug, Clone)]
+pub struct SearchOutcome {
+    pub results: Vec<SearchResult>,
+
+    /// Some segments failed, so `results` may miss their vectors
+    pub degraded: bool,
+
+    /// Sealed segments that answered
+    pub segments_searched: usize,
+
+    pub failures: Vec<SegmentFailure>,
+}
+
+/// Unsealed segment receiving new inserts; searched by linear scan
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ActiveSegment {
+    ids: Vec<u32>,
+    vectors: Vec<Vec<f32>>,
+    metadata: BTreeMap<u32, serde_json::Value>,
+}
+
+/// Immutable segment with its own IVF/PQ index
+struct SealedSegment {
+    seq: u64,
+    db: VectorDB,
+    /// Local id (position in `db`) to global id
+    ids: Vec<u32>,
+    persisted: bool,
+}
+
+/// Sealed segment whose file failed to load under
+/// [`FailurePolicy::Partial`]. Its manifest entry and file are kept so a
+/// repaired file is picked up on the next open.
+struct FailedSegment {
+    seq: u64,
+    ids: Vec<u32>,
+    error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    seq: u64,
+    ids: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    config: Config,
+    seal_threshold: usize,
+    next_id: u32,
+    next_seq: u64,
+    segments: Vec<ManifestEntry>,
+    /// Version of the active segment file; `None` in stores that predate it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    active: Option<u64>,
+}
+
+/// Rows of the segments one merge combines, copied out so the segments
+/// keep serving until the merged one replaces them
+struct MergeInput {
+    seqs: Vec<u64>,
+    ids: Vec<u32>,
+    vectors: Vec<Vec<f32>>,
+    metadata: BTreeMap<u32, serde_json::Value>,
+}
+
+/// LSM-style vector store for continuous ingestion.
+///
+/// Inserts land in a small in-memory segment that is searched linearly.
+/// Once it reaches `seal_threshold` vectors it is sealed into a segment
+/// with its own index, and the [`MergePolicy`] combines small sealed
+/// segments into larger ones (retraining the index for the merged set).
+/// Merges run when asked: [`SegmentedDB::merge_pending`] in the calling
+/// thread, `SegmentedDB::merge_background` on a helper thread under the
+/// `std-thread` feature, or after every seal with
+/// [`SegmentedDB::set_auto_merge`]. Searches fan out over every segment
+/// and merge the per-segment top-k.
+pub struct SegmentedDB {
+    config: Config,
+    seal_threshold: usize,
+    active: ActiveSegment,
+    sealed: Vec<SealedSegment>,
+    policy: Box<dyn MergePolicy>,
+    auto_merge: bool,
+    failure_policy: FailurePolicy,
+    failed: Vec<FailedSegment>,
+    next_id: u32,
+    next_seq: u64,
+}
+
+impl SegmentedDB {
+    /// Create an empty store. `config` is the template for every sealed
+    /// segment; cluster counts are clamped to each segment's size.
+    pub fn new(config: Config, seal_threshold: usize) -> Result<Self> {
+        config.validate()?;
+
+        if seal_threshold == 0 {
+            return Err(KhadyotaError::InvalidConfig(
+                "seal_threshold must be > 0".to_string()
+            ));
+        }
+
+        Ok(Self {
+            config,
+            seal_threshold,
+            active: ActiveSegment::default(),
+            sealed: Vec::new(),
+            policy: Box::new(TieredMergePolicy::default()),
+            auto_merge: false,
+            failure_policy: FailurePolicy::default(),
+            failed: Vec::new(),
+            next_id: 0,
+            next_seq: 0,
+        })
+    }
+
+    /// Replace the merge policy
+    pub fn set_merge_policy(&mut self, policy: Box<dyn MergePolicy>) {
+        self.policy = policy;
+    }
+
+    /// Run pending merges inline after every seal, in the `insert` or
+    /// `add_segment` call that sealed, which then waits for the merged
+    /// segments to be rebuilt. Off by default: call
+    /// [`SegmentedDB::merge_pending`] from a maintenance task, or merge on
+    /// a helper thread with `SegmentedDB::merge_background`, to keep
+    /// merges off the insert path.
+    pub fn set_auto_merge(&mut self, enabled: bool) {
+        self.auto_merge = enabled;
+    }
+
+    /// Replace the policy for segments that fail to answer a search
+    pub fn set_failure_policy(&mut self, policy: FailurePolicy) {
+        self.failure_policy = policy;
+    }
+
+    /// Insert a vector, sealing the active segment when it is full
+    pub fn insert(&mut self, vector: Vec<f32>, metadata: Option<serde_json::Value>) -> Result<u32> {
+        if vector.len() != self.config.dimensions {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: self.config.dimensions,
+                got: vector.len(),
+            });
+        }
+
+        if let Some(meta) = &metadata {
+            check_size(&self.config, meta)?;
+        }
+        let vector = if self.config.normalizes() {
+            if vector.iter().all(|&x| x == 0.0) {
+                return Err(KhadyotaError::ZeroVector);
+            }
+            normalized(&vector)
+        } else {
+            vector
+        };
+
+        let id = self.next_id;
+        self.active.ids.push(id);
+        self.active.vectors.push(vector);
+        if let Some(meta) = metadata {
+            self.active.metadata.insert(id, meta);
+        }
+        self.next_id += 1;
+
+        if self.active.vectors.len() >= self.seal_threshold {
+            self.seal()?;
+            if self.auto_merge {
+                self.merge_pending()?;
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Seal the active segment into an indexed segment
+    pub fn seal(&mut self) -> Result<()> {
+        if self.active.vectors.is_empty() {
+            return Ok(());
+        }
+
+        // The rows stay in the active segment until the sealed one is built
+        let db = build_segment(&self.config, &self.active.ids, self.active.vectors.clone(), &self.active.metadata)?;
+        let active = std::mem::take(&mut self.active);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.sealed.push(SealedSegment {
+            seq,
+            db,
+            ids: active.ids,
+            persisted: false,
+        });
+
+        Ok(())
+    }
+
+    /// Add an already indexed database, such as one from
+    /// [`VectorDB::assemble`], as a sealed segment without rebuilding it.
+    /// Its entries get the next global ids, which are returned; the
+    /// segment file is written by the next [`SegmentedDB::save`].
+    ///
+    /// Bulk loads can stream through this one chunk at a time instead of
+    /// holding the whole collection in memory.
+    pub fn add_segment(&mut self, db: VectorDB) -> Result<Range<u32>> {
+        self.config.check_matches(&db.config)?;
+        if self.config.normalizes() != db.config.normalizes() {
+            return Err(KhadyotaError::ConfigMismatch {
+                field: "normalize",
+                expected: self.config.normalizes().to_string(),
+                found: db.config.normalizes().to_string(),
+            });
+        }
+        if !db.index_built {
+            return Err(KhadyotaError::IndexNotBuilt);
+        }
+        if !db.deleted.is_empty() {
+            return Err(KhadyotaError::InvalidConfig(
+                "segments cannot contain deleted entries".to_string()
+            ));
+        }
+        if db.is_empty() {
+            return Ok(self.next_id..self.next_id);
+        }
+
+        let start = self.next_id;
+        self.next_id += db.len() as u32;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.sealed.push(SealedSegment {
+            seq,
+            db,
+            ids: (start..self.next_id).collect(),
+            persisted: false,
+        });
+
+        if self.auto_merge {
+            self.merge_pending()?;
+        }
+        Ok(start..self.next_id)
+    }
+
+    /// Run merges until the policy is satisfied. Returns the number of
+    /// merges. Each merged segment replaces its parts only once built, so
+    /// a failed build leaves them in place.
+    pub fn merge_pending(&mut self) -> Result<usize> {
+        let mut merges = 0;
+        while let Some(MergeInput { seqs, ids, vectors, metadata }) = self.next_merge() {
+            let db = build_segment(&self.config, &ids, vectors, &metadata)?;
+            self.install_merge(&seqs, ids, db);
+            merges += 1;
+        }
+        Ok(merges)
+    }
+
+    /// Rows of the next merge the policy selects
+    fn next_merge(&self) -> Option<MergeInput> {
+        let sizes: Vec<usize> = self.sealed.iter().map(|s| s.ids.len()).collect();
+        let mut selected = self.policy.select(&sizes)?;
+        selected.sort_unstable();
+        selected.dedup();
+        if selected.len() < 2 || selected.iter().any(|&i| i >= self.sealed.len()) {
+            return None;
+        }
+
+        let mut input = MergeInput {
+            seqs: Vec::with_capacity(selected.len()),
+            ids: Vec::new(),
+            vectors: Vec::new(),
+            metadata: BTreeMap::new(),
+        };
+        for part in selected.iter().map(|&i| &self.sealed[i]) {
+            input.seqs.push(part.seq);
+            for (local, vector) in part.db.vectors.iter().enumerate() {
+                let global = part.ids[local];
+                if let Some(meta) = part.db.metadata_value(local as u32) {
+                    input.metadata.insert(global, meta.clone());
+                }
+                input.ids.push(global);
+                input.vectors.push(vector.to_vec());
+            }
+        }
+        Some(input)
+    }
+
+    /// Put `db`, merged from the segments `seqs`, in place of them.
+    /// Returns false, dropping it, if any of them has been merged away
+    /// since its rows were copied.
+    fn install_merge(&mut self, seqs: &[u64], ids: Vec<u32>, db: VectorDB) -> bool {
+        let positions: Option<Vec<usize>> = seqs
+            .iter()
+            .map(|seq| self.sealed.iter().position(|s| s.seq == *seq))
+            .collect();
+        let Some(mut positions) = positions else {
+            return false;
+        };
+
+        // Remove from the back so earlier indices stay valid
+        positions.sort_unstable();
+        for &i in positions.iter().rev() {
+            self.sealed.remove(i);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let position = positions[0].min(self.sealed.len());
+        self.sealed.insert(position, SealedSegment {
+            seq,
+            db,
+            ids,
+            persisted: false,
+        });
+        true
+    }
+
+    /// Search every segment and merge the per-segment top-k.
+    ///
+    /// Results are always complete: if any segment fails this returns
+    /// [`KhadyotaError::SegmentFailed`] whatever the failure policy. Use
+    /// [`SegmentedDB::search_outcome`] to accept partial results.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        let outcome = self.search_outcome(query, k)?;
+        match outcome.failures.into_iter().next() {
+            Some(failure) => Err(KhadyotaError::SegmentFailed {
+                seq: failure.seq,
+                error: failure.error,
+            }),
+            None => Ok(outcome.results),
+        }
+    }
+
+    /// Search every segment under the failure policy, reporting the
+    /// segments that failed alongside the merged results.
+    ///
+    /// Under [`FailurePolicy::FailFast`] the first failure is returned as
+    /// an error. Under [`FailurePolicy::Partial`] failed segments are
+    /// skipped and listed, unless fewer than `min_segments` answered.
+    pub fn search_outcome(&self, query: &[f32], k: usize) -> Result<SearchOutcome> {
+        InputContract::for_config(&self.config).validate(query)?;
+
+        let mut results: Vec<SearchResult> = self.active
+            .vectors
+            .iter()
+            .zip(self.active.ids.iter())
+            .map(|(vector, &id)| SearchResult {
+                id,
+                distance: compute_distance(query, vector, self.config.metric),
+                metadata: None,
+                external_id: None,
+            })
+            .collect();
+
+        let mut failures: Vec<SegmentFailure> = self.failed.iter().map(FailedSegment::failure).collect();
+        let mut segments_searched = 0;
+        for segment in &self.sealed {
+            match segment.db.search(query, k) {
+                Ok(found) => {
+                    segments_searched += 1;
+                    for mut result in found {
+                        result.id = segment.ids[result.id as usize];
+                        results.push(result);
+                    }
+                }
+                Err(e) => failures.push(SegmentFailure {
+                    seq: segment.seq,
+                    error: e.to_string(),
+                    persistent: false,
+                }),
+            }
+        }
+        self.check_serving(segments_searched, &failures)?;
+
+        results.sort_by(|a, b| cmp_distance(a.distance, b.distance));
+        results.truncate(k);
+
+        for result in results.iter_mut() {
+            if result.metadata.is_none() {
+                result.metadata = self.active.metadata.get(&result.id).cloned();
+            }
+        }
+
+        Ok(SearchOutcome {
+            results,
+            degraded: !failures.is_empty(),
+            segments_searched,
+            failures,
+        })
+    }
+
+    /// Whether `healthy` working segments are enough to serve under the
+    /// failure policy, given the `failures` among the rest
+    fn check_serving(&self, healthy: usize, failures: &[SegmentFailure]) -> Result<()> {
+        let Some(first) = failures.first() else {
+            return Ok(());
+        };
+        match self.failure_policy {
+            FailurePolicy::FailFast => Err(KhadyotaError::SegmentFailed {
+                seq: first.seq,
+                error: first.error.clone(),
+            }),
+            FailurePolicy::Partial { min_segments } => {
+                let total = healthy + failures.len();
+                let required = min_segments.min(total);
+                if healthy < required {
+                    return Err(KhadyotaError::TooFewSegments { healthy, total, required });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Readiness of the store: a "segments" component summarising how
+    /// many sealed segments serve, and one per segment that failed to load
+    pub fn health(&self) -> Health {
+        let healthy = self.sealed.len();
+        let total = healthy + self.failed.len();
+        let failures: Vec<SegmentFailure> = self.failed.iter().map(FailedSegment::failure).collect();
+
+        let mut components = vec![match self.check_serving(healthy, &failures) {
+            Err(e) => component("segments", HealthStatus::Unready, e.to_string()),
+            Ok(()) if failures.is_empty() => component(
+                "segments",
+                HealthStatus::Ready,
+                format!("{} sealed segments, {} vectors unsealed", total, self.active_len()),
+            ),
+            Ok(()) => component(
+                "segments",
+                HealthStatus::Degraded,
+                format!("{} of {} sealed segments serving", healthy, total),
+            ),
+        }];
+        components.extend(self.failed.iter().map(|failed| {
+            component(&format!("segment {}", failed.seq), HealthStatus::Degraded, failed.error.clone())
+        }));
+
+        let status = components
+            .iter()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(HealthStatus::Ready);
+
+        Health {
+            status,
+            components,
+            last_build_unix_secs: None,
+        }
+    }
+
+    /// Sealed segments that failed to load and are left out of searches
+    pub fn failed_segments(&self) -> Vec<SegmentFailure> {
+        self.failed.iter().map(FailedSegment::failure).collect()
+    }
+
+    /// Total number of stored vectors
+    pub fn len(&self) -> usize {
+        self.active.vectors.len()
+            + self.sealed.iter().map(|s| s.ids.len()).sum::<usize>()
+            + self.failed.iter().map(|s| s.ids.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sizes of the sealed segments that loaded, oldest first
+    pub fn segment_sizes(&self) -> Vec<usize> {
+        self.sealed.iter().map(|s| s.ids.len()).collect()
+    }
+
+    /// Number of vectors in the unsealed segment
+    pub fn active_len(&self) -> usize {
+        self.active.vectors.len()
+    }
+
+    /// Persist to a directory.
+    ///
+    /// New segment files, and a new version of the active segment's file,
+    /// are written first and the manifest naming them is replaced
+    /// atomically afterwards, so a crash at any point leaves either the old
+    /// or the new manifest pointing at complete segment files. Files no
+    /// longer referenced are removed once the new manifest is in place.
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        let active = self.write_segments(dir)?;
+        self.write_manifest(dir, active)
+    }
+
+    /// Write unsaved sealed segments and the active segment beside whatever
+    /// the current manifest references, returning the active file's version
+    fn write_segments(&mut self, dir: &Path) -> Result<u64> {
+        fs::create_dir_all(dir)?;
+
+        for segment in self.sealed.iter_mut() {
+            if !segment.persisted {
+                segment.db.save(&segment_path(dir, segment.seq))?;
+                segment.persisted = true;
+            }
+        }
+
+        // Take the first free version, never one the current manifest names
+        let mut version = 0;
+        while active_path(dir, version).exists() {
+            version += 1;
+        }
+        let tmp = active_path(dir, version).with_extension("seg.tmp");
+        write_synced(&tmp, &rmp_serde::to_vec(&self.active)?)?;
+        replace_file(&tmp, &active_path(dir, version))?;
+        Ok(version)
+    }
+
+    /// Point the manifest at the files [`SegmentedDB::write_segments`] left
+    /// and remove the ones it no longer names
+    fn write_manifest(&self, dir: &Path, active: u64) -> Result<()> {
+        let manifest = Manifest {
+            config: self.config.clone(),
+            seal_threshold: self.seal_threshold,
+            next_id: self.next_id,
+            next_seq: self.next_seq,
+            segments: self.sealed
+                .iter()
+                .map(|s| (s.seq, &s.ids))
+                .chain(self.failed.iter().map(|s| (s.seq, &s.ids)))
+                .map(|(seq, ids)| ManifestEntry { seq, ids: ids.clone() })
+                .collect(),
+            active: Some(active),
+        };
+
+        let tmp = dir.join(MANIFEST_TMP_FILE);
+        fs::write(&tmp, rmp_serde::to_vec(&manifest)?)?;
+        replace_file(&tmp, &dir.join(MANIFEST_FILE))?;
+
+        // Drop segment files that were merged away and older active files
+        let live: Vec<PathBuf> = self.sealed
+            .iter()
+            .map(|s| s.seq)
+            .chain(self.failed.iter().map(|s| s.seq))
+            .map(|seq| segment_path(dir, seq))
+            .chain(std::iter::once(active_path(dir, active)))
+            .collect();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_segment = path.extension().is_some_and(|ext| ext == "kdb")
+                || path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("active"));
+            if is_segment && !live.contains(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Open a store previously written by [`SegmentedDB::save`], failing
+    /// if any segment does not load
+    pub fn open(dir: &Path) -> Result<Self> {
+        Self::open_with(dir, FailurePolicy::FailFast)
+    }
+
+    /// Open a store under a failure policy, which it keeps for searches.
+    ///
+    /// Under [`FailurePolicy::Partial`] segments whose files fail to load
+    /// are left out of searches and reported by
+    /// [`SegmentedDB::failed_segments`] and [`SegmentedDB::health`]. Saves
+    /// keep their files, and their ids are never reused.
+    pub fn open_with(dir: &Path, failure_policy: FailurePolicy) -> Result<Self> {
+        let manifest: Manifest = rmp_serde::from_slice(&fs::read(dir.join(MANIFEST_FILE))?)?;
+
+        let mut sealed = Vec::with_capacity(manifest.segments.len());
+        let mut failed = Vec::new();
+        for entry in manifest.segments {
+            match load_segment(dir, &entry) {
+                Ok(db) => sealed.push(SealedSegment {
+                    seq: entry.seq,
+                    db,
+                    ids: entry.ids,
+                    persisted: true,
+                }),
+                Err(e) if failure_policy == FailurePolicy::FailFast => return Err(e),
+                Err(e) => failed.push(FailedSegment {
+                    seq: entry.seq,
+                    ids: entry.ids,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        let active_path = match manifest.active {
+            Some(version) => active_path(dir, version),
+            None => dir.join(LEGACY_ACTIVE_FILE),
+        };
+        let active = if manifest.active.is_some() || active_path.exists() {
+            rmp_serde::from_slice(&fs::read(active_path)?)?
+        } else {
+            ActiveSegment::default()
+        };
+
+        let db = Self {
+            config: manifest.config,
+            seal_threshold: manifest.seal_threshold,
+            active,
+            sealed,
+            policy: Box::new(TieredMergePolicy::default()),
+            auto_merge: false,
+            failure_policy,
+            failed,
+            next_id: manifest.next_id,
+            next_seq: manifest.next_seq,
+        };
+        db.check_serving(db.sealed.len(), &db.failed_segments())?;
+        Ok(db)
+    }
+}
+
+impl FailedSegment {
+    fn failure(&self) -> SegmentFailure {
+        SegmentFailure {
+            seq: self.seq,
+            error: self.error.clone(),
+            persistent: true,
+        }
+    }
+}
+
+#[cfg(feature = "std-thread")]
+impl SegmentedDB {
+    /// [`SegmentedDB::merge_pending`] on a helper thread, returning at
+    /// once.
+    ///
+    /// Each merge copies its segments' rows under a read lock and builds
+    /// the merged segment holding no lock, so inserts and searches go on
+    /// meanwhile and the parts keep serving; the merged segment replaces
+    /// them under one short write lock. A merge whose parts were merged
+    /// away in the meantime, by another merge, is dropped and the policy
+    /// asked again.
+    pub fn merge_background(store: &Arc<RwLock<SegmentedDB>>) -> MergeHandle {
+        let store = Arc::clone(store);
+        let thread = std::thread::spawn(move || {
+            let mut merges = 0;
+            loop {
+                let (config, input) = {
+                    let guard = store.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    match guard.next_merge() {
+                        Some(input) => (guard.config.clone(), input),
+                        None => return Ok(merges),
+                    }
+                };
+                let MergeInput { seqs, ids, vectors, metadata } = input;
+                let db = build_segment(&config, &ids, vectors, &metadata)?;
+
+                let mut guard = store.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+                if guard.install_merge(&seqs, ids, db) {
+                    merges += 1;
+                }
+            }
+        });
+        MergeHandle { thread }
+    }
+}
+
+/// Merges started by [`SegmentedDB::merge_background`]. Dropping the
+/// handle leaves them running.
+#[cfg(feature = "std-thread")]
+pub struct MergeHandle {
+    thread: JoinHandle<Result<usize>>,
+}
+
+#[cfg(feature = "std-thread")]
+impl MergeHandle {
+    /// Whether the merges have finished
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the policy to be satisfied, returning the number of
+    /// merges installed. A panic on the merge thread resumes here.
+    pub fn wait(self) -> Result<usize> {
+        self.thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// Template `config` sized for a segment of `len` vectors
+fn segment_config(config: &Config, len: usize) -> Config {
+    let mut config = config.clone();
+    config.num_clusters = config.num_clusters.clamp(1, len);
+    config.num_probe = config.num_probe.clamp(1, config.num_clusters);
+    // Small segments would only fill part of the 256-entry PQ codebooks
+    if config.uses_pq() && len < 256 {
+        config.quantizer = QuantizerKind::None;
+    }
+    config
+}
+
+/// Index `vectors`, with global ids `ids`, as a sealed segment's database
+fn build_segment(
+    config: &Config,
+    ids: &[u32],
+    vectors: Vec<Vec<f32>>,
+    metadata: &BTreeMap<u32, serde_json::Value>,
+) -> Result<VectorDB> {
+    let mut db = VectorDB::new(segment_config(config, vectors.len()))?;
+    db.insert_batch(vectors.into_iter().zip(ids).map(|(vector, id)| (vector, metadata.get(id).cloned())))?;
+    db.build_index()?;
+    Ok(db)
+}
+
+/// Load a sealed segment's file and check it against its manifest entry
+fn load_segment(dir: &Path, entry: &ManifestEntry) -> Result<VectorDB> {
+    let db = VectorDB::load(&segment_path(dir, entry.seq))?;
+    if db.len() != entry.ids.len() {
+        return Err(KhadyotaError::SerializationError(format!(
+            "segment {} holds {} vectors but the manifest lists {}",
+            entry.seq,
+            db.len(),
+            entry.ids.len()
+        )));
+    }
+    Ok(db)
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("segment-{:010}.kdb", seq))
+}
+
+fn active_path(dir: &Path, version: u64) -> PathBuf {
+    dir.join(format!("active-{:010}.seg", version))
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(bytes)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiered_policy() {
+        let policy = TieredMergePolicy {
+            merge_factor: 3,
+            max_segment_size: 1000,
+        };
+
+        assert_eq!(policy.select(&[10, 10]), None);
+        assert_eq!(policy.select(&[10, 50, 10, 10]), Some(vec![0, 2, 3]));
+        assert_eq!(policy.select(&[2000, 2000, 2000]), None);
+    }
+
+    #[test]
+    fn test_failed_builds_keep_their_rows() {
+        let config = Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            num_clusters: 2,
+            num_probe: 2,
+            ..Default::default()
+        };
+        let vector = |i: usize| (0..4).map(|j| ((i * 4 + j) as f32).sin()).collect::<Vec<f32>>();
+        let mut db = SegmentedDB::new(config.clone(), 10).unwrap();
+        db.set_merge_policy(Box::new(TieredMergePolicy {
+            merge_factor: 2,
+            max_segment_size: 1000,
+        }));
+        for i in 0..25 {
+            db.insert(vector(i), None).unwrap();
+        }
+        assert_eq!((db.segment_sizes(), db.active_len()), (vec![10, 10], 5));
+
+        // Segments built from this template reject every row
+        db.config.dimensions = 8;
+        assert!(db.merge_pending().is_err());
+        assert!(db.seal().is_err());
+        assert_eq!((db.segment_sizes(), db.active_len(), db.len()), (vec![10, 10], 5, 25));
+
+        db.config = config;
+        assert_eq!(db.merge_pending().unwrap(), 1);
+        db.seal().unwrap();
+        assert_eq!(db.segment_sizes(), [20, 5]);
+        for i in [0, 12, 24] {
+            assert_eq!(db.search(&vector(i), 1).unwrap()[0].id as usize, i);
+        }
+    }
+
+    #[test]
+    fn test_crash_before_manifest_swap_keeps_last_save() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            num_clusters: 2,
+            num_probe: 2,
+            ..Default::default()
+        };
+        let vector = |i: usize| (0..4).map(|j| ((i * 4 + j) as f32).sin()).collect::<Vec<f32>>();
+        let mut db = SegmentedDB::new(config, 10).unwrap();
+        for i in 0..15 {
+            db.insert(vector(i), None).unwrap();
+        }
+        db.save(dir.path()).unwrap();
+
+        // Seal one more segment and rewrite the active rows, then crash
+        // before the manifest is replaced
+        for i in 15..25 {
+            db.insert(vector(i), None).unwrap();
+        }
+        db.write_segments(dir.path()).unwrap();
+
+        let mut reopened = SegmentedDB::open(dir.path()).unwrap();
+        assert_eq!((reopened.segment_sizes(), reopened.active_len()), (vec![10], 5));
+        for i in [0, 12, 14] {
+            assert_eq!(reopened.search(&vector(i), 1).unwrap()[0].id as usize, i);
+        }
+        assert_eq!(reopened.insert(vector(15), None).unwrap(), 15);
+
+        db.save(dir.path()).unwrap();
+        let reopened = SegmentedDB::open(dir.path()).unwrap();
+        assert_eq!((reopened.segment_sizes(), reopened.active_len()), (vec![10, 10], 5));
+        for i in [0, 17, 24] {
+            assert_eq!(reopened.search(&vector(i), 1).unwrap()[0].id as usize, i);
+        }
+        let active_files = fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with("active"))
+            .count();
+        assert_eq!(active_files, 1);
+    }
+}
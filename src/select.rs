@@ -0,0 +1,191 @@
+//! Top-k selection over scored candidates
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Order distances nearest first, with NaN after every number so a bad
+/// distance can only fill places nothing else wants. Zeroes of either
+/// sign compare equal, as `partial_cmp` has them.
+pub fn cmp_distance(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(&b).unwrap(),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+/// A candidate in the heap, ranked by distance and then by its place in
+/// the input, so the heap keeps exactly what a stable sort would
+struct Entry {
+    distance: f32,
+    position: usize,
+    id: u32,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_distance(self.distance, other.distance).then(self.position.cmp(&other.position))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+/// The `k` nearest of `scored`, nearest first, with ties in the order
+/// given: what a stable sort by [`cmp_distance`] then truncating to `k`
+/// gives, through a max-heap of `k` entries instead of a full sort.
+pub fn smallest_k(scored: impl IntoIterator<Item = (u32, f32)>, k: usize) -> Vec<(u32, f32)> {
+    TopK::default().select(scored, k)
+}
+
+/// [`smallest_k`] keeping its heap between selections, for callers that
+/// select once per query of a batch
+#[derive(Default)]
+pub(crate) struct TopK {
+    heap: BinaryHeap<Entry>,
+}
+
+impl TopK {
+    pub(crate) fn select(&mut self, scored: impl IntoIterator<Item = (u32, f32)>, k: usize) -> Vec<(u32, f32)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let scored = scored.into_iter();
+        let heap = &mut self.heap;
+        heap.clear();
+        heap.reserve(k.min(scored.size_hint().0));
+        for (position, (id, distance)) in scored.enumerate() {
+            let entry = Entry { distance, position, id };
+            if heap.len() < k {
+                heap.push(entry);
+            } else if let Some(mut worst) = heap.peek_mut()
+                && entry < *worst
+            {
+                *worst = entry;
+            }
+        }
+        let mut sorted = std::mem::take(heap).into_sorted_vec();
+        let selected = sorted.iter().map(|entry| (entry.id, entry.distance)).collect();
+        sorted.clear();
+        *heap = BinaryHeap::from(sorted);
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, DistanceMetric, QuantizerKind};
+    use crate::harness::{build_db, clustered_vectors, random_vectors};
+    use crate::search_params::SearchParams;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Selection as search did it before: sort everything, then truncate
+    fn sort_and_truncate(mut scored: Vec<(u32, f32)>, k: usize) -> Vec<(u32, f32)> {
+        scored.sort_by(|a, b| cmp_distance(a.1, b.1));
+        scored.truncate(k);
+        scored
+    }
+
+    #[test]
+    fn test_heap_matches_a_stable_sort() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for n in [0, 1, 5, 100, 10_000] {
+            // Few distinct values, so ties are everywhere, and the odd NaN
+            let scored: Vec<(u32, f32)> = (0..n)
+                .map(|id| {
+                    let distance = match rng.gen_range(0..50) {
+                        0 => f32::NAN,
+                        1 => -0.0,
+                        d => (d % 13) as f32 * 0.5,
+                    };
+                    (id, distance)
+                })
+                .collect();
+            for k in [0, 1, 10, 99, n as usize + 3] {
+                let expected = sort_and_truncate(scored.clone(), k);
+                let selected = smallest_k(scored.clone(), k);
+                assert_eq!(
+                    selected.iter().map(|&(id, d)| (id, d.to_bits())).collect::<Vec<_>>(),
+                    expected.iter().map(|&(id, d)| (id, d.to_bits())).collect::<Vec<_>>(),
+                    "n = {}, k = {}",
+                    n,
+                    k
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_nan_distances_rank_last() {
+        let scored = vec![(0, f32::NAN), (1, 2.0), (2, f32::INFINITY), (3, -1.0), (4, f32::NAN)];
+        let ids: Vec<u32> = smallest_k(scored, 4).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, [3, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_searches_match_sorting_every_candidate() {
+        let vectors = clustered_vectors(3_000, 16, 20, 0.3, 2);
+        let queries = random_vectors(20, 16, 3);
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Cosine, DistanceMetric::DotProduct] {
+            for quantizer in [QuantizerKind::None, QuantizerKind::PQ, QuantizerKind::SQ8] {
+                let config = Config {
+                    dimensions: 16,
+                    metric,
+                    quantizer,
+                    pq_subvectors: 4,
+                    num_clusters: 20,
+                    num_probe: 4,
+                    seed: Some(5),
+                    ..Default::default()
+                };
+                let db = build_db(config, vectors.clone()).unwrap();
+                let ivf = db.ivf_index.as_ref().unwrap();
+                for rerank in [None, Some(30)] {
+                    if rerank.is_some() && db.quantized.is_none() {
+                        continue;
+                    }
+                    let params = SearchParams { rerank, ..Default::default() };
+                    for query in &queries {
+                        // Searches score the query as prepared: unit length for cosine
+                        let query = &db.prepare_query(query).unwrap();
+                        // Without codes, searches scan every row
+                        let scored = match &db.quantized {
+                            Some(quantized) => {
+                                let candidates = db.candidates(query, ivf, &params).unwrap();
+                                let scored = db.code_scorer(quantized, query, &candidates).score(&candidates);
+                                match rerank {
+                                    Some(rerank) => {
+                                        let ids = sort_and_truncate(scored, rerank).into_iter().map(|(id, _)| id).collect();
+                                        db.score_exact(query, ids, &params).unwrap()
+                                    }
+                                    None => scored,
+                                }
+                            }
+                            None => db.search_linear(query, &params).unwrap(),
+                        };
+                        let expected = sort_and_truncate(scored, 10);
+                        let found: Vec<(u32, f32)> = db
+                            .search_with_params(query, 10, &params)
+                            .unwrap()
+                            .into_iter()
+                            .map(|r| (r.id, r.distance))
+                            .collect();
+                        assert_eq!(found, expected, "{:?} {:?} rerank {:?}", metric, quantizer, rerank);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::error::{KhadyotaError, Result};
+use crate::storage::replace::replace_file;
+use crate::storage::{ColdVectors, Serializer};
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+
+/// How long [`VectorDB::publish`] keeps a superseded version on disk, so
+/// readers that have just read the old manifest can still open it
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(300);
+
+/// Which published version is current, and which superseded ones are
+/// still within their grace period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PublishManifest {
+    version: u64,
+    /// Empty collections are published without a vector file
+    has_vectors: bool,
+    /// Superseded versions still on disk, with when they were superseded
+    /// in seconds since the Unix epoch
+    retired: Vec<(u64, u64)>,
+}
+
+/// The published directory a reader follows (runtime only)
+#[derive(Debug, Clone)]
+pub(crate) struct SharedHandle {
+    dir: PathBuf,
+    version: u64,
+}
+
+impl VectorDB {
+    /// Publish this database as the next version in `dir`, keeping
+    /// superseded versions for [`DEFAULT_GRACE_PERIOD`]
+    pub fn publish(&self, dir: &Path) -> Result<u64> {
+        self.publish_with_grace(dir, DEFAULT_GRACE_PERIOD)
+    }
+
+    /// Publish this database as the next version in `dir` for readers
+    /// opened with [`VectorDB::open_shared`].
+    ///
+    /// Each version is a pair of files that never change once written:
+    /// the original vectors, which readers memory-map so processes on one
+    /// host share their pages, and the rest of the state. Both are synced
+    /// before `MANIFEST` is atomically replaced to point at them, so a
+    /// reader sees the old version or the new one, never a mixture.
+    /// Versions superseded more than `grace` ago are then unlinked; a
+    /// reader still mapping one keeps its pages where the platform allows.
+    ///
+    /// A directory takes one writer at a time.
+    pub fn publish_with_grace(&self, dir: &Path, grace: Duration) -> Result<u64> {
+        fs::create_dir_all(dir)?;
+        // Only a missing manifest starts over at version 1; guessing past
+        // an unreadable one could overwrite files readers have mapped
+        let previous = match read_manifest(dir) {
+            Ok(manifest) => Some(manifest),
+            Err(KhadyotaError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let version = previous.as_ref().map_or(1, |m| m.version + 1);
+
+        let has_vectors = !self.vectors.is_empty();
+        if has_vectors {
+            let path = vectors_path(dir, version);
+            Serializer::save_rows_as(
+                self.vectors.iter(),
+                self.vectors.len(),
+                self.config.dimensions,
+                self.config.raw_precision,
+                &path,
+            )?;
+            File::open(&path)?.sync_all()?;
+        }
+        let state = state_path(dir, version);
+        self.write_state(&state, false)?;
+        File::open(&state)?.sync_all()?;
+
+        let now = unix_now();
+        let mut retired = Vec::new();
+        if let Some(previous) = previous {
+            retired = previous.retired;
+            retired.push((previous.version, now));
+        }
+        retired.retain(|&(_, at)| Duration::from_secs(now.saturating_sub(at)) < grace);
+
+        let manifest = PublishManifest {
+            version,
+            has_vectors,
+            retired,
+        };
+        let tmp = dir.join(MANIFEST_TMP_FILE);
+        fs::write(&tmp, rmp_serde::to_vec(&manifest)?)?;
+        replace_file(&tmp, &dir.join(MANIFEST_FILE))?;
+
+        remove_unreferenced(dir, &manifest);
+        Ok(version)
+    }
+
+    /// Open the current version published in `dir`, with its vectors
+    /// memory-mapped rather than read into memory.
+    ///
+    /// Rows are not cached per process: the page cache already holds them
+    /// once for every reader. PQ codes and the IVF index are loaded into
+    /// each process.
+    pub fn open_shared(dir: &Path) -> Result<Self> {
+        let manifest = read_manifest(dir)?;
+        open_version(dir, &manifest)
+    }
+
+    /// Switch to the latest published version if it has changed, returning
+    /// whether it did.
+    ///
+    /// Runtime settings such as the query cache, admission control and
+    /// latency recording carry over; local changes since the last open or
+    /// refresh are discarded. On error the current version stays in use.
+    pub fn refresh(&mut self) -> Result<bool> {
+        let Some(handle) = &self.shared else {
+            return Err(KhadyotaError::InvalidConfig(
+                "refresh() needs a database opened with open_shared()".to_string()
+            ));
+        };
+
+        let manifest = read_manifest(&handle.dir)?;
+        if manifest.version == handle.version {
+            return Ok(false);
+        }
+
+        let mut next = open_version(&handle.dir, &manifest)?;
+        next.generation = self.generation + 1;
+        next.admission = self.admission.take();
+        next.query_cache = self.query_cache.take();
+        next.latency = self.latency.take();
+        next.access = self.access.take();
+        if let Some(access) = &next.access {
+            access.reset_clusters();
+        }
+        *self = next;
+        Ok(true)
+    }
+
+    /// The published version in use, for databases opened with
+    /// [`VectorDB::open_shared`]
+    pub fn shared_version(&self) -> Option<u64> {
+        self.shared.as_ref().map(|handle| handle.version)
+    }
+}
+
+fn open_version(dir: &Path, manifest: &PublishManifest) -> Result<VectorDB> {
+    let rows = if manifest.has_vectors {
+        Some(ColdVectors::open(&vectors_path(dir, manifest.version), 0)?)
+    } else {
+        None
+    };
+
+    let mut db = VectorDB::read_state(&state_path(dir, manifest.version), rows)?;
+    db.shared = Some(SharedHandle {
+        dir: dir.to_path_buf(),
+        version: manifest.version,
+    });
+    Ok(db)
+}
+
+fn read_manifest(dir: &Path) -> Result<PublishManifest> {
+    Ok(rmp_serde::from_slice(&fs::read(dir.join(MANIFEST_FILE))?)?)
+}
+
+fn state_path(dir: &Path, version: u64) -> PathBuf {
+    dir.join(format!("v-{:010}.kdb", version))
+}
+
+fn vectors_path(dir: &Path, version: u64) -> PathBuf {
+    dir.join(format!("v-{:010}.vectors", version))
+}
+
+/// The version a published file belongs to, if it is one
+fn file_version(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    let (stem, extension) = name.strip_prefix("v-")?.split_once('.')?;
+    matches!(extension, "kdb" | "vectors").then_some(())?;
+    stem.parse().ok()
+}
+
+/// Unlink version files the manifest no longer references, including ones
+/// left by a publish that failed before its manifest was written.
+///
+/// Best effort: a file that cannot be removed now, such as one still
+/// mapped on Windows, is retried by the next publish.
+fn remove_unreferenced(dir: &Path, manifest: &PublishManifest) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(version) = file_version(&path) else {
+            continue;
+        };
+        let referenced = version == manifest.version || manifest.retired.iter().any(|&(v, _)| v == version);
+        if !referenced {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_version() {
+        assert_eq!(file_version(Path::new("/d/v-0000000007.kdb")), Some(7));
+        assert_eq!(file_version(Path::new("v-0000000012.vectors")), Some(12));
+        assert_eq!(file_version(Path::new("MANIFEST")), None);
+        assert_eq!(file_version(Path::new("v-0000000012.tmp")), None);
+        assert_eq!(file_version(Path::new("segment-0000000001.kdb")), None);
+    }
+}
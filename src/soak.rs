@@ -0,0 +1,546 @@
+//! Soak runs: a mixed workload of inserts, deletes, updates, searches,
+//! saves, rebuilds and maintenance held for a long time, watching memory.
+//!
+//! Every [`Scenario::sample_every`] operations the run records the
+//! process RSS next to what [`VectorDB::memory_usage`] accounts for. The
+//! difference is memory the crate's data does not explain, such as leaked
+//! buffers or allocator fragmentation; a leak shows as that difference
+//! creeping up over the run. Tombstoned rows stay in
+//! [`MemoryUsage`](crate::MemoryUsage) until they are reclaimed, so
+//! churn alone does not count against it.
+//!
+//! Invariants are checked as the run goes: each save is loaded back and
+//! must match the live database's checksum, a rebuild must leave the
+//! checksum alone, and every sample probes a search for results that are
+//! live, ordered and as many as asked for.
+//!
+//! With the `alloc-tracking` feature and [`alloc::CountingAllocator`]
+//! installed as the global allocator, samples also attribute the bytes
+//! held to the kind of operation that allocated them.
+
+use crate::config::{Config, DistanceMetric, QuantizerKind};
+use crate::error::Result;
+use crate::harness::random_vectors;
+use crate::vector_db::VectorDB;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Kinds of operation a soak run issues
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SoakOp {
+    /// Loading the initial rows and building the index
+    Setup,
+    Insert,
+    Delete,
+    Update,
+    Search,
+    /// Save to the scenario's file, then load it back to compare
+    Save,
+    /// `build_index()` over every live row
+    Rebuild,
+    /// One `maintenance_tick()`
+    Maintenance,
+    /// Search probes and RSS sampling between operations
+    Check,
+}
+
+impl SoakOp {
+    pub const ALL: [SoakOp; 9] = [
+        SoakOp::Setup,
+        SoakOp::Insert,
+        SoakOp::Delete,
+        SoakOp::Update,
+        SoakOp::Search,
+        SoakOp::Save,
+        SoakOp::Rebuild,
+        SoakOp::Maintenance,
+        SoakOp::Check,
+    ];
+}
+
+/// Relative weights of the operations drawn after setup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpMix {
+    pub insert: u32,
+    pub delete: u32,
+    pub update: u32,
+    pub search: u32,
+    pub save: u32,
+    pub rebuild: u32,
+    pub maintenance: u32,
+}
+
+impl Default for OpMix {
+    fn default() -> Self {
+        Self {
+            insert: 20,
+            delete: 15,
+            update: 10,
+            search: 200,
+            save: 1,
+            rebuild: 1,
+            maintenance: 5,
+        }
+    }
+}
+
+impl OpMix {
+    fn weighted(&self) -> [(SoakOp, u32); 7] {
+        [
+            (SoakOp::Insert, self.insert),
+            (SoakOp::Delete, self.delete),
+            (SoakOp::Update, self.update),
+            (SoakOp::Search, self.search),
+            (SoakOp::Save, self.save),
+            (SoakOp::Rebuild, self.rebuild),
+            (SoakOp::Maintenance, self.maintenance),
+        ]
+    }
+
+    fn draw(&self, rng: &mut StdRng) -> SoakOp {
+        let weighted = self.weighted();
+        let total: u32 = weighted.iter().map(|&(_, weight)| weight).sum();
+        let mut pick = rng.gen_range(0..total.max(1));
+        for (op, weight) in weighted {
+            if pick < weight {
+                return op;
+            }
+            pick -= weight;
+        }
+        SoakOp::Search
+    }
+}
+
+/// What a soak run does and for how long. Every field has a default, so
+/// a scenario file need only name what it changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub dimensions: usize,
+    pub metric: DistanceMetric,
+    pub quantizer: QuantizerKind,
+    pub num_clusters: usize,
+    pub pq_subvectors: usize,
+
+    /// Rows inserted before the index is first built
+    pub initial_rows: usize,
+
+    /// Live rows past which inserts are drawn as deletes instead
+    pub max_rows: usize,
+
+    pub mix: OpMix,
+
+    /// Stop after this long; runs until `max_ops` when `None`
+    pub duration_secs: Option<f64>,
+
+    /// Stop after this many operations, setup excluded
+    pub max_ops: Option<u64>,
+
+    /// Pace operations to at most this many per second
+    pub ops_per_sec: Option<f64>,
+
+    /// Operations between samples
+    pub sample_every: u64,
+
+    /// Results asked of each search
+    pub k: usize,
+
+    pub seed: u64,
+
+    /// Largest rise allowed, from the first sample to the last, in RSS
+    /// beyond what [`VectorDB::memory_usage`] accounts for
+    pub max_unaccounted_growth_bytes: u64,
+
+    /// Directory for the save file; the system temp dir when `None`
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            dimensions: 64,
+            metric: DistanceMetric::Euclidean,
+            quantizer: QuantizerKind::PQ,
+            num_clusters: 32,
+            pq_subvectors: 8,
+            initial_rows: 10_000,
+            max_rows: 20_000,
+            mix: OpMix::default(),
+            duration_secs: Some(60.0),
+            max_ops: None,
+            ops_per_sec: None,
+            sample_every: 2_000,
+            k: 10,
+            seed: 1,
+            max_unaccounted_growth_bytes: 64 * 1024 * 1024,
+            dir: None,
+        }
+    }
+}
+
+impl Scenario {
+    fn config(&self) -> Config {
+        Config {
+            dimensions: self.dimensions,
+            metric: self.metric,
+            quantizer: self.quantizer,
+            num_clusters: self.num_clusters,
+            pq_subvectors: self.pq_subvectors,
+            seed: Some(self.seed),
+            ..Default::default()
+        }
+    }
+
+    fn finished(&self, ops: u64, elapsed: Duration) -> bool {
+        self.max_ops.is_some_and(|max| ops >= max)
+            || self.duration_secs.is_some_and(|secs| elapsed.as_secs_f64() >= secs)
+            || (self.max_ops.is_none() && self.duration_secs.is_none())
+    }
+}
+
+/// Memory at one point of a soak run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoakSample {
+    /// Operations issued so far, setup excluded
+    pub ops: u64,
+    pub elapsed_secs: f64,
+
+    /// Resident set size of the process; `None` where it cannot be read
+    pub rss_bytes: Option<u64>,
+
+    /// [`MemoryUsage::total_bytes`](crate::MemoryUsage::total_bytes)
+    pub accounted_bytes: u64,
+
+    pub live_rows: usize,
+
+    /// Rows stored, tombstoned ones included
+    pub stored_rows: usize,
+
+    /// Bytes held per kind of operation that allocated them; `None`
+    /// unless [`alloc::CountingAllocator`] is installed
+    pub allocated_bytes: Option<Vec<(SoakOp, i64)>>,
+}
+
+impl SoakSample {
+    /// RSS beyond the accounted bytes
+    pub fn unaccounted_bytes(&self) -> Option<i64> {
+        self.rss_bytes.map(|rss| rss as i64 - self.accounted_bytes as i64)
+    }
+}
+
+/// Outcome of [`run`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoakReport {
+    pub scenario: Scenario,
+    pub elapsed_secs: f64,
+
+    /// Operations issued, per kind
+    pub ops: Vec<(SoakOp, u64)>,
+
+    /// Invariant checks that ran
+    pub checks: u64,
+
+    /// Failed checks, and memory growth past the scenario's limit
+    pub violations: Vec<String>,
+
+    /// First sample after setup, then one per `sample_every` operations,
+    /// then one at the end
+    pub samples: Vec<SoakSample>,
+}
+
+impl SoakReport {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Rise in unaccounted RSS from the first sample to the last; `None`
+    /// where RSS cannot be read
+    pub fn unaccounted_growth_bytes(&self) -> Option<i64> {
+        let first = self.samples.first()?.unaccounted_bytes()?;
+        let last = self.samples.last()?.unaccounted_bytes()?;
+        Some(last - first)
+    }
+}
+
+/// Resident set size of this process, from `/proc/self/status`
+pub fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// State of a run between operations
+struct Soak {
+    scenario: Scenario,
+    db: VectorDB,
+    rng: StdRng,
+    /// Live ids, in no order, for drawing deletes and updates
+    live: Vec<u32>,
+    path: PathBuf,
+    start: Instant,
+    ops: u64,
+    counts: Vec<(SoakOp, u64)>,
+    checks: u64,
+    violations: Vec<String>,
+    samples: Vec<SoakSample>,
+}
+
+/// Run `scenario` to the end and report on it. Errors from the database
+/// end the run; broken invariants are reported as violations instead.
+pub fn run(scenario: &Scenario) -> Result<SoakReport> {
+    let dir = scenario.dir.clone().unwrap_or_else(std::env::temp_dir);
+    let mut soak = Soak {
+        scenario: scenario.clone(),
+        db: VectorDB::new(scenario.config())?,
+        rng: StdRng::seed_from_u64(scenario.seed),
+        live: Vec::new(),
+        path: dir.join(format!("khadyota-soak-{}.db", std::process::id())),
+        start: Instant::now(),
+        ops: 0,
+        counts: SoakOp::ALL.iter().map(|&op| (op, 0)).collect(),
+        checks: 0,
+        violations: Vec::new(),
+        samples: Vec::new(),
+    };
+    let result = soak.run();
+    std::fs::remove_file(&soak.path).ok();
+    result?;
+
+    Ok(SoakReport {
+        scenario: soak.scenario,
+        elapsed_secs: soak.start.elapsed().as_secs_f64(),
+        ops: soak.counts,
+        checks: soak.checks,
+        violations: soak.violations,
+        samples: soak.samples,
+    })
+}
+
+impl Soak {
+    fn run(&mut self) -> Result<()> {
+        alloc::enter(SoakOp::Setup);
+        let rows = random_vectors(self.scenario.initial_rows, self.scenario.dimensions, self.scenario.seed);
+        let ids = self.db.insert_batch(rows.into_iter().map(|row| (row, None)))?;
+        self.live.extend(ids);
+        self.db.build_index()?;
+        self.check()?;
+
+        while !self.scenario.finished(self.ops, self.start.elapsed()) {
+            let mut op = self.scenario.mix.draw(&mut self.rng);
+            if op == SoakOp::Insert && self.live.len() >= self.scenario.max_rows {
+                op = SoakOp::Delete;
+            }
+            if matches!(op, SoakOp::Delete | SoakOp::Update) && self.live.len() <= self.scenario.k.max(1) {
+                op = SoakOp::Insert;
+            }
+            alloc::enter(op);
+            self.apply(op)?;
+            self.ops += 1;
+            self.counts.iter_mut().find(|(kind, _)| *kind == op).unwrap().1 += 1;
+
+            if self.ops.is_multiple_of(self.scenario.sample_every.max(1)) {
+                self.check()?;
+            }
+            if let Some(rate) = self.scenario.ops_per_sec.filter(|&rate| rate > 0.0) {
+                let due = Duration::from_secs_f64(self.ops as f64 / rate);
+                if let Some(wait) = due.checked_sub(self.start.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+            }
+        }
+
+        if !self.ops.is_multiple_of(self.scenario.sample_every.max(1)) {
+            self.check()?;
+        }
+        self.check_growth();
+        Ok(())
+    }
+
+    fn random_row(&mut self) -> Vec<f32> {
+        (0..self.scenario.dimensions).map(|_| self.rng.gen_range(-1.0..1.0)).collect()
+    }
+
+    fn apply(&mut self, op: SoakOp) -> Result<()> {
+        match op {
+            SoakOp::Insert => {
+                let row = self.random_row();
+                let id = self.db.insert(row, Some(serde_json::json!({ "op": self.ops })))?;
+                self.live.push(id);
+            }
+            SoakOp::Delete => {
+                let id = self.live.swap_remove(self.rng.gen_range(0..self.live.len()));
+                self.db.delete(id)?;
+            }
+            SoakOp::Update => {
+                let id = self.live[self.rng.gen_range(0..self.live.len())];
+                let row = self.random_row();
+                self.db.update(id, row, None)?;
+            }
+            SoakOp::Search => {
+                let query = self.random_row();
+                self.db.search(&query, self.scenario.k)?;
+            }
+            SoakOp::Save => {
+                self.db.save(&self.path)?;
+                let loaded = VectorDB::load(&self.path)?;
+                let (rows, expected) = (loaded.len(), self.db.len());
+                self.expect(loaded.checksum() == self.db.checksum(), || "loaded save has a different checksum".to_string());
+                self.expect(rows == expected, || format!("loaded save has {} rows, not {}", rows, expected));
+            }
+            SoakOp::Rebuild => {
+                let checksum = self.db.checksum();
+                self.db.build_index()?;
+                self.expect(self.db.checksum() == checksum, || "rebuild changed the checksum".to_string());
+            }
+            SoakOp::Maintenance => {
+                self.db.maintenance_tick(Duration::from_millis(5));
+            }
+            SoakOp::Setup | SoakOp::Check => unreachable!("not drawn from the mix"),
+        }
+        Ok(())
+    }
+
+    /// Count a check, recording `violation` when it fails
+    fn expect(&mut self, ok: bool, violation: impl FnOnce() -> String) {
+        self.checks += 1;
+        if !ok {
+            let violation = violation();
+            self.violations.push(format!("after {} ops: {}", self.ops, violation));
+        }
+    }
+
+    /// Probe a search from a live row, then sample memory
+    fn check(&mut self) -> Result<()> {
+        alloc::enter(SoakOp::Check);
+        if !self.live.is_empty() {
+            let id = self.live[self.rng.gen_range(0..self.live.len())];
+            let query = self.db.get(id)?.vector;
+            let results = self.db.search(&query, self.scenario.k)?;
+            let expected = self.scenario.k.min(self.db.len());
+            self.expect(results.len() == expected, || format!("search returned {} of {} results", results.len(), expected));
+            self.expect(results.iter().all(|r| self.db.contains(r.id)), || "search returned a deleted row".to_string());
+            self.expect(results.windows(2).all(|pair| pair[0].distance <= pair[1].distance), || {
+                "search results are out of order".to_string()
+            });
+        }
+
+        let usage = self.db.memory_usage();
+        self.samples.push(SoakSample {
+            ops: self.ops,
+            elapsed_secs: self.start.elapsed().as_secs_f64(),
+            rss_bytes: rss_bytes(),
+            accounted_bytes: usage.total_bytes() as u64,
+            live_rows: self.db.len(),
+            stored_rows: self.db.vectors.len(),
+            allocated_bytes: alloc::live_bytes(),
+        });
+        Ok(())
+    }
+
+    fn check_growth(&mut self) {
+        let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) else {
+            return;
+        };
+        let (Some(first), Some(last)) = (first.unaccounted_bytes(), last.unaccounted_bytes()) else {
+            return;
+        };
+        let limit = self.scenario.max_unaccounted_growth_bytes;
+        self.expect(last - first <= limit as i64, || {
+            format!("unaccounted memory grew {} bytes, past the {} allowed", last - first, limit)
+        });
+    }
+}
+
+/// Allocation counting for soak runs, behind the `alloc-tracking`
+/// feature. Without it, or without the allocator installed, nothing is
+/// counted and samples carry no allocation figures.
+pub mod alloc {
+    use super::SoakOp;
+
+    #[cfg(feature = "alloc-tracking")]
+    pub use counting::CountingAllocator;
+
+    #[cfg(feature = "alloc-tracking")]
+    mod counting {
+        use super::SoakOp;
+        use std::alloc::{GlobalAlloc, Layout, System};
+        use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+        /// Operation kind allocations are counted against. Process-wide
+        /// rather than per thread, so work a search hands to rayon counts
+        /// against it too; suits the soak loop's one operation at a time.
+        static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+        /// Bytes allocated minus bytes freed while each kind was current
+        static LIVE: [AtomicI64; SoakOp::ALL.len()] = [const { AtomicI64::new(0) }; SoakOp::ALL.len()];
+
+        /// Whether any allocation has gone through the counter
+        static INSTALLED: AtomicUsize = AtomicUsize::new(0);
+
+        /// The system allocator, counting bytes against the operation
+        /// kind current when each allocation or free happens. Install it
+        /// in a binary with
+        /// `#[global_allocator] static ALLOC: CountingAllocator = CountingAllocator;`.
+        pub struct CountingAllocator;
+
+        fn count(bytes: i64) {
+            LIVE[CURRENT.load(Ordering::Relaxed)].fetch_add(bytes, Ordering::Relaxed);
+            INSTALLED.store(1, Ordering::Relaxed);
+        }
+
+        unsafe impl GlobalAlloc for CountingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                count(layout.size() as i64);
+                unsafe { System.alloc(layout) }
+            }
+
+            unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+                count(layout.size() as i64);
+                unsafe { System.alloc_zeroed(layout) }
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                count(-(layout.size() as i64));
+                unsafe { System.dealloc(ptr, layout) }
+            }
+
+            unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+                count(new_size as i64 - layout.size() as i64);
+                unsafe { System.realloc(ptr, layout, new_size) }
+            }
+        }
+
+        pub(super) fn enter(op: SoakOp) {
+            let index = SoakOp::ALL.iter().position(|&kind| kind == op).unwrap();
+            CURRENT.store(index, Ordering::Relaxed);
+        }
+
+        pub(super) fn live_bytes() -> Option<Vec<(SoakOp, i64)>> {
+            if INSTALLED.load(Ordering::Relaxed) == 0 {
+                return None;
+            }
+            Some(SoakOp::ALL.iter().zip(&LIVE).map(|(&op, bytes)| (op, bytes.load(Ordering::Relaxed))).collect())
+        }
+    }
+
+    /// Count allocations from here on against `op`
+    pub fn enter(op: SoakOp) {
+        #[cfg(feature = "alloc-tracking")]
+        counting::enter(op);
+        #[cfg(not(feature = "alloc-tracking"))]
+        let _ = op;
+    }
+
+    /// Bytes held per operation kind, if the counting allocator is in use
+    pub fn live_bytes() -> Option<Vec<(SoakOp, i64)>> {
+        #[cfg(feature = "alloc-tracking")]
+        return counting::live_bytes();
+        #[cfg(not(feature = "alloc-tracking"))]
+        None
+    }
+}
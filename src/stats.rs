@@ -0,0 +1,160 @@
+use crate::config::DistanceMetric;
+use crate::vector_db::VectorDB;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Size and memory figures for a whole database, from
+/// [`VectorDB::stats`]. Byte counts are of the data held, not of
+/// allocator overhead or spare capacity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DbStats {
+    /// Live entries
+    pub vectors: usize,
+
+    /// Tombstoned rows still stored, until [`VectorDB::compact`]
+    pub deleted: usize,
+
+    pub dimensions: usize,
+    pub metric: DistanceMetric,
+
+    /// Whether the index is built and covers every row
+    pub index_built: bool,
+
+    /// Original vectors on the heap, at
+    /// [`Config::raw_precision`](crate::config::Config::raw_precision):
+    /// every row, or for spilled and mapped rows only those written since
+    /// and those cached
+    pub vector_bytes: usize,
+
+    /// Original vectors in a mapped file, paged in by the OS as read
+    pub mapped_vector_bytes: usize,
+
+    /// PQ or SQ8 codes
+    pub code_bytes: usize,
+
+    /// Codebooks, rotation or ranges the codes are read with
+    pub codebook_bytes: usize,
+
+    /// IVF cluster centroids
+    pub centroid_bytes: usize,
+
+    /// IVF inverted lists
+    pub inverted_list_bytes: usize,
+
+    /// Rows copied for exact scans under
+    /// [`IndexKind::Flat`](crate::config::IndexKind::Flat)
+    pub flat_index_bytes: usize,
+
+    /// Metadata returned with results, as compact JSON
+    pub metadata_bytes: usize,
+
+    /// Metadata kept apart under
+    /// [`Config::external_metadata`](crate::config::Config::external_metadata)
+    pub external_metadata_bytes: usize,
+
+    /// Size of every stored row as `f32`s over the bytes the index
+    /// searches them through: codes, codebooks, centroids and lists.
+    /// `None` without codes.
+    pub compression_ratio: Option<f64>,
+}
+
+impl DbStats {
+    /// Bytes held on the heap, mapped rows excluded
+    pub fn heap_bytes(&self) -> usize {
+        self.vector_bytes + self.index_bytes() + self.metadata_bytes + self.external_metadata_bytes
+    }
+
+    /// Bytes the index holds: codes, codebooks, centroids and lists, or
+    /// a flat index's rows
+    pub fn index_bytes(&self) -> usize {
+        self.code_bytes + self.codebook_bytes + self.centroid_bytes + self.inverted_list_bytes + self.flat_index_bytes
+    }
+}
+
+impl fmt::Display for DbStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mb = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        writeln!(
+            f,
+            "{} vectors ({} deleted) x {} dimensions, {:?}, index {}",
+            self.vectors,
+            self.deleted,
+            self.dimensions,
+            self.metric,
+            if self.index_built { "built" } else { "not built" }
+        )?;
+        writeln!(
+            f,
+            " - Vectors: {:.1} MB in memory, {:.1} MB mapped",
+            mb(self.vector_bytes),
+            mb(self.mapped_vector_bytes)
+        )?;
+        writeln!(f, " - Codes: {:.1} MB, codebooks {:.1} MB", mb(self.code_bytes), mb(self.codebook_bytes))?;
+        writeln!(
+            f,
+            " - IVF: centroids {:.1} MB, inverted lists {:.1} MB",
+            mb(self.centroid_bytes),
+            mb(self.inverted_list_bytes)
+        )?;
+        if self.flat_index_bytes > 0 {
+            writeln!(f, " - Flat index: {:.1} MB", mb(self.flat_index_bytes))?;
+        }
+        writeln!(
+            f,
+            " - Metadata: {:.1} MB, {:.1} MB external",
+            mb(self.metadata_bytes),
+            mb(self.external_metadata_bytes)
+        )?;
+        write!(f, " - Heap total: {:.1} MB", mb(self.heap_bytes()))?;
+        if let Some(ratio) = self.compression_ratio {
+            write!(f, ", compression {:.1}x", ratio)?;
+        }
+        Ok(())
+    }
+}
+
+impl VectorDB {
+    /// Counts, memory by component and compression, for capacity
+    /// planning.
+    ///
+    /// Metadata is measured as in [`VectorDB::memory_usage`], by encoding
+    /// every value, so this is a diagnostic rather than something to call
+    /// per query.
+    pub fn stats(&self) -> DbStats {
+        let row_bytes = self.config.dimensions * std::mem::size_of::<f32>();
+        let (vector_bytes, mapped_vector_bytes) = match self.vectors.cold() {
+            Some(cold) => (cold.heap_bytes(), cold.mapped_bytes()),
+            None => (self.vectors.heap_bytes(), 0),
+        };
+        let (code_bytes, codebook_bytes) = self.quantized.as_ref().map_or((0, 0), |quantized| {
+            (quantized.code_bytes(), quantized.memory_usage() - quantized.code_bytes())
+        });
+        let (centroid_bytes, inverted_list_bytes) = self
+            .ivf_index
+            .as_ref()
+            .map_or((0, 0), |ivf| (ivf.centroid_bytes(), ivf.list_bytes()));
+        let usage = self.memory_usage();
+
+        let mut stats = DbStats {
+            vectors: self.len(),
+            deleted: self.deleted.len(),
+            dimensions: self.config.dimensions,
+            metric: self.config.metric,
+            index_built: self.index_built && self.has_index(),
+            vector_bytes,
+            mapped_vector_bytes,
+            code_bytes,
+            codebook_bytes,
+            centroid_bytes,
+            inverted_list_bytes,
+            flat_index_bytes: self.flat_index.as_ref().map_or(0, |flat| flat.memory_usage()),
+            metadata_bytes: usage.metadata_bytes,
+            external_metadata_bytes: usage.external_metadata_bytes,
+            compression_ratio: None,
+        };
+        if code_bytes > 0 {
+            stats.compression_ratio = Some((self.vectors.len() * row_bytes) as f64 / stats.index_bytes() as f64);
+        }
+        stats
+    }
+}
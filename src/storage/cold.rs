@@ -0,0 +1,249 @@
+use crate::config::Precision;
+use crate::error::{KhadyotaError, Result};
+use crate::storage::mmap::MmapVectors;
+use crate::storage::replace::replace_file;
+use crate::storage::serialization::Serializer;
+use serde::{Deserialize, Serialize};
+use crate::storage::lru::LruCache;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Cache effectiveness counters for [`ColdVectors`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColdCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached_rows: usize,
+    pub cached_bytes: usize,
+    pub capacity_bytes: usize,
+}
+
+impl ColdCacheStats {
+    /// Fraction of row lookups served from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Original vectors spilled to a memory-mapped file, fronted by an LRU
+/// cache of recently used rows.
+///
+/// Rows inserted or replaced after the spill stay in memory until the next
+/// spill; replacing a row invalidates its cache entry.
+/// Point lookups ([`ColdVectors::get`]) go through the cache; full scans
+/// ([`ColdVectors::row`]) read the file directly so they do not flush it.
+pub struct ColdVectors {
+    /// Shared with snapshots, which map the same pages
+    rows: Arc<MmapVectors>,
+    tail: Vec<Vec<f32>>,
+    /// Rows of the file replaced since the spill
+    overrides: HashMap<u32, Vec<f32>>,
+    cache: Mutex<LruCache<u32, Arc<[f32]>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ColdVectors {
+    /// Write `vectors` to `path` and reopen them memory-mapped with a
+    /// cache of at most `cache_bytes`
+    pub fn spill(vectors: &[Vec<f32>], path: &Path, cache_bytes: usize) -> Result<Self> {
+        Self::spill_as(vectors, Precision::F32, path, cache_bytes)
+    }
+
+    /// [`ColdVectors::spill`], writing each component in `precision`
+    pub fn spill_as(vectors: &[Vec<f32>], precision: Precision, path: &Path, cache_bytes: usize) -> Result<Self> {
+        if vectors.is_empty() {
+            return Err(KhadyotaError::InvalidConfig(
+                "Cannot spill an empty vector set".to_string()
+            ));
+        }
+
+        // Write beside the target and rename, so a file that is currently
+        // mapped is never truncated underneath its reader
+        let tmp = path.with_extension("spill");
+        let dims = vectors[0].len();
+        Serializer::save_rows_as(vectors.iter(), vectors.len(), dims, precision, &tmp)?;
+        replace_file(&tmp, path)?;
+        Self::open(path, cache_bytes)
+    }
+
+    /// Map a vector file written by [`Serializer::save_vectors`] as is.
+    ///
+    /// The file must not change while mapped; several processes may map
+    /// the same file and share its pages.
+    pub fn open(path: &Path, cache_bytes: usize) -> Result<Self> {
+        let rows = Arc::new(MmapVectors::open(path)?);
+
+        Ok(Self {
+            rows,
+            tail: Vec::new(),
+            overrides: HashMap::new(),
+            cache: Mutex::new(LruCache::new(cache_bytes, None)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// Look up a row, faulting it into the cache on a miss
+    pub fn get(&self, id: u32) -> Option<Arc<[f32]>> {
+        let index = id as usize;
+        if index >= self.rows.len() {
+            return self.tail.get(index - self.rows.len()).map(|v| Arc::from(v.as_slice()));
+        }
+
+        if let Some(row) = self.overrides.get(&id) {
+            return Some(Arc::from(row.as_slice()));
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(row) = cache.get(&id) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(row);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let row: Arc<[f32]> = Arc::from(&*self.rows.get(index)?);
+        let bytes = std::mem::size_of_val(&*row);
+        cache.insert(id, row.clone(), bytes);
+        Some(row)
+    }
+
+    /// Read a row without touching the cache
+    pub fn row(&self, id: u32) -> Option<Cow<'_, [f32]>> {
+        let index = id as usize;
+        if let Some(row) = self.overrides.get(&id) {
+            Some(Cow::Borrowed(row.as_slice()))
+        } else if index < self.rows.len() {
+            self.rows.get(index)
+        } else {
+            self.tail.get(index - self.rows.len()).map(|v| Cow::Borrowed(v.as_slice()))
+        }
+    }
+
+    /// How the file stores each component; rows written since the spill
+    /// are rounded to it too
+    pub fn precision(&self) -> Precision {
+        self.rows.precision()
+    }
+
+    /// Append a row; it stays in memory until the next spill
+    pub fn push(&mut self, mut vector: Vec<f32>) {
+        self.precision().round(&mut vector);
+        self.tail.push(vector);
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.tail.reserve(additional);
+    }
+
+    /// Replace an existing row; it stays in memory until the next spill
+    pub fn set(&mut self, id: u32, mut vector: Vec<f32>) {
+        self.precision().round(&mut vector);
+        let index = id as usize;
+        if index < self.rows.len() {
+            self.invalidate(id);
+            self.overrides.insert(id, vector);
+        } else if let Some(row) = self.tail.get_mut(index - self.rows.len()) {
+            *row = vector;
+        }
+    }
+
+    /// Drop a cached row so the next lookup rereads it
+    pub fn invalidate(&self, id: u32) {
+        self.cache.lock().unwrap().remove(&id);
+    }
+
+    /// Drop every cached row
+    pub fn invalidate_all(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn cache_stats(&self) -> ColdCacheStats {
+        let cache = self.cache.lock().unwrap();
+
+        ColdCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            cached_rows: cache.len(),
+            cached_bytes: cache.used_bytes(),
+            capacity_bytes: cache.capacity_bytes(),
+        }
+    }
+
+    /// A copy sharing the mapped file, with the in-memory rows copied and
+    /// a cache of its own, empty but as large
+    pub(crate) fn snapshot(&self) -> Self {
+        Self {
+            rows: Arc::clone(&self.rows),
+            tail: self.tail.clone(),
+            overrides: self.overrides.clone(),
+            cache: Mutex::new(LruCache::new(self.cache.lock().unwrap().capacity_bytes(), None)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Bytes of rows in the mapped file, which the OS pages in and out
+    pub fn mapped_bytes(&self) -> usize {
+        self.rows.len() * self.rows.dimensions() * self.precision().bytes()
+    }
+
+    /// Bytes of rows held in memory: written since the spill, or cached
+    pub fn heap_bytes(&self) -> usize {
+        let written: usize = self.tail.iter().chain(self.overrides.values()).map(Vec::len).sum();
+        written * std::mem::size_of::<f32>() + self.cache.lock().unwrap().used_bytes()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len() + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_lru_evicts_oldest_within_budget() {
+        let temp = NamedTempFile::new().unwrap();
+        let vectors: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32; 4]).collect();
+
+        // Room for two 16-byte rows
+        let mut cold = ColdVectors::spill(&vectors, temp.path(), 32).unwrap();
+
+        assert_eq!(&*cold.get(0).unwrap(), &[0.0; 4]);
+        cold.get(1).unwrap();
+        cold.get(0).unwrap(); // hit, 0 is now most recent
+        cold.get(2).unwrap(); // evicts 1
+        cold.get(0).unwrap(); // hit
+
+        let stats = cold.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 3));
+        assert_eq!(stats.cached_rows, 2);
+        assert!(stats.cached_bytes <= 32);
+
+        cold.invalidate(0);
+        cold.get(0).unwrap();
+        assert_eq!(cold.cache_stats().misses, 4);
+
+        // Appended rows are served from memory
+        cold.push(vec![9.5; 4]);
+        assert_eq!(cold.len(), 11);
+        assert_eq!(*cold.row(10).unwrap(), [9.5; 4]);
+        assert_eq!(*cold.row(3).unwrap(), [3.0; 4]);
+        assert!(cold.get(11).is_none());
+    }
+}
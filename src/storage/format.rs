@@ -1,10 +1,33 @@
+use crate::config::Config;
+use crate::error::{KhadyotaError, Result};
 use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
 
 /// Magic bytes to identify Khadyota files
 pub const MAGIC: &[u8; 4] = b"KHDY";
-pub const VERSION: u32 = 1;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Format version written by this build.
+///
+/// - 1: vectors are always stored inline
+/// - 2: the rows may be left out of the state and kept in a flat file
+///   beside it ([`VectorDB::save_mapped`](crate::vector_db::VectorDB::save_mapped));
+///   the header still counts them
+/// - 3: PQ codes are packed into one binary per database, at the bit
+///   width their codec records; older files hold a byte array per vector
+/// - 4: the state always has all its fields and ends with a build id
+///   hashing the vectors, codes and index, so
+///   [`VectorDB::save_incremental`](crate::vector_db::VectorDB::save_incremental)
+///   can tell whether a file's sections are the ones it recorded
+pub const VERSION: u32 = 4;
+
+/// Oldest format version this build still reads
+pub const MIN_VERSION: u32 = 1;
+
+/// How an encoded header starts: a 5-element MessagePack array whose first
+/// element is the magic, itself an array of 4 small integers
+const ENCODED_PREFIX: [u8; 6] = [0x95, 0x94, MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3]];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileHeader {
     pub magic: [u8; 4],
     pub version: u32,
@@ -24,6 +47,52 @@ impl FileHeader {
         }
     }
     
+    /// Write the header at the current position of `writer`
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        rmp_serde::encode::write(writer, self)?;
+        Ok(())
+    }
+    
+    /// Read and validate a header from the start of `reader`.
+    ///
+    /// Returns `None`, consuming nothing, for files written before headers
+    /// were introduced.
+    pub fn read_from<R: BufRead>(reader: &mut R) -> Result<Option<Self>> {
+        if !reader.fill_buf()?.starts_with(&ENCODED_PREFIX) {
+            return Ok(None);
+        }
+        
+        let header: Self = rmp_serde::from_read(reader).map_err(|e| {
+            KhadyotaError::SerializationError(format!("File header is truncated or corrupt: {}", e))
+        })?;
+        header.validate()?;
+        Ok(Some(header))
+    }
+    
+    /// Check the header against the configuration stored after it
+    pub fn check_config(&self, config: &Config, vector_count: usize) -> Result<()> {
+        let corrupt = |field: &str, header: String, stored: String| {
+            Err(KhadyotaError::SerializationError(format!(
+                "File header records {} {} but the stored data has {}",
+                field, header, stored
+            )))
+        };
+        
+        if self.dimensions as usize != config.dimensions {
+            return corrupt("dimensions", self.dimensions.to_string(), config.dimensions.to_string());
+        }
+        
+        if self.metric != config.metric {
+            return corrupt("metric", format!("{:?}", self.metric), format!("{:?}", config.metric));
+        }
+        
+        if self.vector_count as usize != vector_count {
+            return corrupt("vector_count", self.vector_count.to_string(), vector_count.to_string());
+        }
+        
+        Ok(())
+    }
+    
     pub fn validate(&self) -> crate::error::Result<()> {
         if &self.magic != MAGIC {
             return Err(crate::error::KhadyotaError::SerializationError(
@@ -31,12 +100,101 @@ impl FileHeader {
             ));
         }
         
-        if self.version != VERSION {
-            return Err(crate::error::KhadyotaError::SerializationError(
-                format!("Unsupported version: {}", self.version)
-            ));
+        if !(MIN_VERSION..=VERSION).contains(&self.version) {
+            return Err(crate::error::KhadyotaError::UnsupportedVersion {
+                version: self.version,
+                oldest: MIN_VERSION,
+                newest: VERSION,
+            });
         }
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// A named block stored after a database's core state.
+///
+/// Each section records the cargo feature needed to interpret it. A build
+/// without that feature refuses the file, unless the section is optional
+/// to serving, in which case it is skipped with a load warning.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    
+    /// Cargo feature needed to interpret the payload, if any
+    pub requires: Option<String>,
+    
+    /// Whether the database can serve queries without this section
+    pub optional: bool,
+    
+    pub payload: Vec<u8>,
+}
+
+impl Section {
+    pub fn new(name: impl Into<String>, payload: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            requires: None,
+            optional: false,
+            payload,
+        }
+    }
+    
+    pub fn requires(mut self, feature: impl Into<String>) -> Self {
+        self.requires = Some(feature.into());
+        self
+    }
+    
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+    
+    /// Write the section table that follows the core state
+    pub fn write_table<W: Write>(writer: &mut W, sections: &[Section]) -> Result<()> {
+        rmp_serde::encode::write(writer, sections)?;
+        Ok(())
+    }
+    
+    /// Read the section table, if any; files without one have none
+    pub fn read_table<R: BufRead>(reader: &mut R) -> Result<Vec<Section>> {
+        if reader.fill_buf()?.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        Ok(rmp_serde::from_read(reader)?)
+    }
+    
+    /// Keep the sections this build can interpret.
+    ///
+    /// Fails with [`KhadyotaError::FeatureRequired`] on the first required
+    /// section whose feature is missing; optional ones are dropped, with a
+    /// warning per section.
+    pub(crate) fn gate(sections: Vec<Section>) -> Result<(Vec<Section>, Vec<String>)> {
+        let mut kept = Vec::with_capacity(sections.len());
+        let mut warnings = Vec::new();
+        
+        for section in sections {
+            match section.requires.as_deref() {
+                Some(feature) if !feature_enabled(feature) => {
+                    if !section.optional {
+                        return Err(KhadyotaError::FeatureRequired(feature.to_string()));
+                    }
+                    warnings.push(format!(
+                        "Skipped section \"{}\": it needs the \"{}\" feature, which this build lacks",
+                        section.name, feature
+                    ));
+                }
+                _ => kept.push(section),
+            }
+        }
+        
+        Ok((kept, warnings))
+    }
+}
+
+/// Whether this build was compiled with the cargo feature `name`
+pub fn feature_enabled(name: &str) -> bool {
+    const FEATURES: &[(&str, bool)] = &[("arrow", cfg!(feature = "arrow"))];
+    
+    FEATURES.iter().any(|&(feature, enabled)| feature == name && enabled)
+}
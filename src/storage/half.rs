@@ -0,0 +1,90 @@
+use crate::config::Precision;
+
+/// Rows stored at half precision, packed one after another
+#[derive(Debug, Clone)]
+pub struct HalfRows {
+    precision: Precision,
+    dimensions: usize,
+    bits: Vec<u16>,
+}
+
+impl HalfRows {
+    /// Empty storage for rows of `dimensions` in `precision`, which must
+    /// be [`Precision::F16`] or [`Precision::BF16`]
+    pub fn new(precision: Precision, dimensions: usize) -> Self {
+        assert_ne!(precision, Precision::F32, "HalfRows holds 16-bit rows");
+        Self {
+            precision,
+            dimensions,
+            bits: Vec::new(),
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Append a row, rounding it to the storage precision
+    pub fn push(&mut self, vector: &[f32]) {
+        debug_assert_eq!(vector.len(), self.dimensions);
+        self.bits.extend(vector.iter().map(|&x| self.precision.narrow(x)));
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.bits.reserve(additional * self.dimensions);
+    }
+
+    /// Replace row `id`, which must already exist
+    pub fn set(&mut self, id: u32, vector: &[f32]) {
+        let start = id as usize * self.dimensions;
+        for (stored, &x) in self.bits[start..start + self.dimensions].iter_mut().zip(vector) {
+            *stored = self.precision.narrow(x);
+        }
+    }
+
+    /// Row `id` widened to `f32`
+    pub fn get(&self, id: u32) -> Option<Vec<f32>> {
+        let start = id as usize * self.dimensions;
+        let row = self.bits.get(start..start + self.dimensions)?;
+        Some(row.iter().map(|&bits| self.precision.widen(bits)).collect())
+    }
+
+    /// Bytes held by the rows
+    pub fn heap_bytes(&self) -> usize {
+        self.bits.len() * std::mem::size_of::<u16>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.len().checked_div(self.dimensions).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_round_to_their_precision() {
+        let row = [1.0, -0.5, 1.234_567_8, 1e-3, 70_000.0];
+        for (precision, tolerance) in [(Precision::F16, 1e-3), (Precision::BF16, 8e-3)] {
+            let mut rows = HalfRows::new(precision, row.len());
+            rows.push(&row);
+            rows.push(&[0.0; 5]);
+            rows.set(1, &row);
+            assert_eq!((rows.len(), rows.heap_bytes()), (2, 20));
+
+            let stored = rows.get(1).unwrap();
+            assert_eq!(stored[..2], [1.0, -0.5]);
+            assert!((stored[2] - row[2]).abs() < tolerance * row[2], "{:?}: {}", precision, stored[2]);
+            assert!(rows.get(2).is_none());
+        }
+
+        // Past the f16 range, but within bf16's
+        assert!(Precision::F16.widen(Precision::F16.narrow(70_000.0)).is_infinite());
+        assert!((Precision::BF16.widen(Precision::BF16.narrow(70_000.0)) - 70_000.0).abs() < 300.0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Least-recently-used map bounded by total byte size and, optionally, by
+/// entry count. Callers supply each value's size on insert.
+#[derive(Debug)]
+pub(crate) struct LruCache<K, V> {
+    capacity_bytes: usize,
+    max_entries: Option<usize>,
+    used_bytes: usize,
+    tick: u64,
+    /// key -> (value, size in bytes, last access tick)
+    entries: HashMap<K, (V, usize, u64)>,
+    /// last access tick -> key, oldest first
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub(crate) fn new(capacity_bytes: usize, max_entries: Option<usize>) -> Self {
+        Self {
+            capacity_bytes,
+            max_entries,
+            used_bytes: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (value, _, last) = self.entries.get_mut(key)?;
+        self.order.remove(last);
+        self.order.insert(tick, key.clone());
+        *last = tick;
+        Some(value.clone())
+    }
+
+    /// Insert, evicting the least recently used entries to make room.
+    /// Values larger than the whole cache are not stored.
+    pub(crate) fn insert(&mut self, key: K, value: V, bytes: usize) {
+        if bytes > self.capacity_bytes || self.max_entries == Some(0) {
+            return;
+        }
+
+        self.remove(&key);
+        while self.used_bytes + bytes > self.capacity_bytes
+            || self.max_entries.is_some_and(|max| self.entries.len() >= max)
+        {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((_, evicted, _)) = self.entries.remove(&oldest) {
+                self.used_bytes -= evicted;
+            }
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, bytes, self.tick));
+        self.used_bytes += bytes;
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) {
+        if let Some((_, bytes, tick)) = self.entries.remove(key) {
+            self.order.remove(&tick);
+            self.used_bytes -= bytes;
+        }
+    }
+
+    /// Visit up to `limit` entries in access order, starting after tick
+    /// `after`, removing those `keep` rejects. Returns the tick to resume
+    /// after, or `None` once every entry has been visited, and the number
+    /// removed.
+    pub(crate) fn sweep(&mut self, after: u64, limit: usize, keep: impl Fn(&V) -> bool) -> (Option<u64>, usize) {
+        let visited: Vec<(u64, K)> = self
+            .order
+            .range(after + 1..)
+            .take(limit)
+            .map(|(&tick, key)| (tick, key.clone()))
+            .collect();
+
+        let mut removed = 0;
+        for (_, key) in &visited {
+            if self.entries.get(key).is_some_and(|(value, _, _)| !keep(value)) {
+                self.remove(key);
+                removed += 1;
+            }
+        }
+
+        let resume = visited.last().map(|&(tick, _)| tick).filter(|_| visited.len() == limit);
+        (resume, removed)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.used_bytes = 0;
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|(value, _, _)| value)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub(crate) fn capacity_bytes(&self) -> usize {
+        self.capacity_bytes
+    }
+}
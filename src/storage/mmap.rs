@@ -1,91 +1,471 @@
-use crate::error::Result;
+use crate::config::Precision;
+use crate::error::{KhadyotaError, Result};
+use crate::storage::format::MAGIC;
 use memmap2::Mmap;
-use std::fs::File;
-use std::path::Path;
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-/// Memory-mapped vector storage for zero-copy access
+/// Layout version of flat vector files.
+///
+/// - 1: a 32-byte header (magic, version, dimensions, row count) and the
+///   rows from byte 32 on
+/// - 2: the element type at byte 12, so rows may be 16-bit floats
+///
+/// `f32` files are still written as version 1, so builds that predate
+/// version 2 can read them. Files from before the header start with the
+/// row count and dimensions as 12 bare bytes; they are still read.
+pub const ROWS_VERSION: u32 = 2;
+
+/// Version written for `f32` rows, whose header needs no element type
+const F32_ROWS_VERSION: u32 = 1;
+
+/// Where rows start. A multiple of the `f32` alignment, and of 32 so
+/// rows of 8 components line up for AVX2 loads.
+const DATA_OFFSET: usize = 32;
+
+/// Byte offset of the row count within the header
+const COUNT_OFFSET: u64 = 16;
+
+/// Where rows start in files written before the header
+const LEGACY_DATA_OFFSET: usize = 12;
+
+/// Code for each element type at byte 12 of the header
+fn dtype_code(precision: Precision) -> u32 {
+    match precision {
+        Precision::F32 => 0,
+        Precision::F16 => 1,
+        Precision::BF16 => 2,
+    }
+}
+
+/// Header of a flat vector file for `count` rows of `dimensions`, each
+/// component stored in `precision`
+pub(crate) fn rows_header(dimensions: usize, count: usize, precision: Precision) -> [u8; DATA_OFFSET] {
+    let version = match precision {
+        Precision::F32 => F32_ROWS_VERSION,
+        _ => ROWS_VERSION,
+    };
+    let mut header = [0u8; DATA_OFFSET];
+    header[0..4].copy_from_slice(MAGIC);
+    header[4..8].copy_from_slice(&version.to_le_bytes());
+    header[8..12].copy_from_slice(&(dimensions as u32).to_le_bytes());
+    header[12..16].copy_from_slice(&dtype_code(precision).to_le_bytes());
+    header[16..24].copy_from_slice(&(count as u64).to_le_bytes());
+    header
+}
+
+/// Write `row` as `precision` little-endian components
+pub(crate) fn write_row(writer: &mut impl Write, row: &[f32], precision: Precision) -> std::io::Result<()> {
+    for &value in row {
+        match precision {
+            Precision::F32 => writer.write_all(&value.to_le_bytes())?,
+            half => writer.write_all(&half.narrow(value).to_le_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+/// Dimensions, row count, data offset and element type read from the
+/// start of a file
+fn parse_header(bytes: &[u8], path: &Path) -> Result<(usize, usize, usize, Precision)> {
+    let malformed = |why: String| KhadyotaError::SerializationError(format!("{:?} is not a vector file: {}", path, why));
+    let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    let u64_at = |at: usize| {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&bytes[at..at + 8]);
+        u64::from_le_bytes(word)
+    };
+
+    let (dimensions, count, offset, precision) = if bytes.starts_with(MAGIC) {
+        if bytes.len() < DATA_OFFSET {
+            return Err(malformed(format!("{} bytes is too short for its header", bytes.len())));
+        }
+        let version = u32_at(4);
+        if !(F32_ROWS_VERSION..=ROWS_VERSION).contains(&version) {
+            return Err(KhadyotaError::UnsupportedVersion {
+                version,
+                oldest: F32_ROWS_VERSION,
+                newest: ROWS_VERSION,
+            });
+        }
+        let precision = match (version, u32_at(12)) {
+            (F32_ROWS_VERSION, _) | (_, 0) => Precision::F32,
+            (_, 1) => Precision::F16,
+            (_, 2) => Precision::BF16,
+            (_, code) => return Err(malformed(format!("unknown element type {}", code))),
+        };
+        (u32_at(8) as usize, u64_at(COUNT_OFFSET as usize), DATA_OFFSET, precision)
+    } else {
+        // Headerless files hold nothing to check but their length, which
+        // must match exactly. Empty sets were written as a bare count.
+        if bytes.len() == 8 && u64_at(0) == 0 {
+            return Ok((0, 0, 8, Precision::F32));
+        }
+        if bytes.len() < LEGACY_DATA_OFFSET {
+            return Err(malformed(format!("{} bytes is too short for a header", bytes.len())));
+        }
+        let (count, dimensions) = (u64_at(0), u32_at(8) as usize);
+        let expected = count as u128 * dimensions as u128 * 4 + LEGACY_DATA_OFFSET as u128;
+        if bytes.len() as u128 != expected {
+            return Err(malformed(format!(
+                "{} rows of {} would take {} bytes, found {}",
+                count,
+                dimensions,
+                expected,
+                bytes.len()
+            )));
+        }
+        (dimensions, count, LEGACY_DATA_OFFSET, Precision::F32)
+    };
+
+    let expected = count as u128 * dimensions as u128 * precision.bytes() as u128 + offset as u128;
+    if (bytes.len() as u128) < expected {
+        return Err(KhadyotaError::SerializationError(format!(
+            "{:?} holds {} bytes but its header promises {}",
+            path,
+            bytes.len(),
+            expected
+        )));
+    }
+    Ok((dimensions, count as usize, offset, precision))
+}
+
+/// Memory-mapped vector storage for zero-copy access.
+///
+/// `Send + Sync`: the mapping is read-only and never remapped, so any
+/// number of threads may read rows at once.
 pub struct MmapVectors {
     _file: File,
     mmap: Mmap,
     dimensions: usize,
     count: usize,
+    /// Where the first row starts
+    offset: usize,
+    /// How each component is stored
+    precision: Precision,
 }
 
 impl MmapVectors {
+    /// Start a new vector file at `path`, replacing any file there. Rows
+    /// are appended through the returned writer.
+    pub fn create(path: &Path, dimensions: usize) -> Result<MmapVectorsWriter> {
+        Self::create_with(path, dimensions, Precision::F32)
+    }
+
+    /// [`MmapVectors::create`] for rows stored in `precision`; appended
+    /// rows are rounded to it
+    pub fn create_with(path: &Path, dimensions: usize, precision: Precision) -> Result<MmapVectorsWriter> {
+        let mut file = File::create(path)?;
+        file.write_all(&rows_header(dimensions, 0, precision))?;
+        Ok(MmapVectorsWriter {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            dimensions,
+            count: 0,
+            precision,
+        })
+    }
+
     /// Open an existing memory-mapped vector file
     pub fn open(path: &Path) -> Result<Self> {
         let file = File::open(path)?;
+        // SAFETY: the mapping is only read. Callers must not modify or
+        // truncate the file while it is open, as documented on the loaders
+        // that map one.
         let mmap = unsafe { Mmap::map(&file)? };
-        
-        // Read header (count + dimensions)
-        let count = u64::from_le_bytes(mmap[0..8].try_into().unwrap()) as usize;
-        let dimensions = u32::from_le_bytes(mmap[8..12].try_into().unwrap()) as usize;
-        
+        let (dimensions, count, offset, precision) = parse_header(&mmap, path)?;
+
+        // Mappings start on a page boundary, so this holds on every
+        // platform memmap2 supports; `get` relies on it
+        if !(mmap.as_ptr() as usize + offset).is_multiple_of(std::mem::align_of::<f32>()) {
+            return Err(KhadyotaError::SerializationError(format!(
+                "{:?} mapped at an address rows cannot be read from in place",
+                path
+            )));
+        }
+
         Ok(Self {
             _file: file,
             mmap,
             dimensions,
             count,
+            offset,
+            precision,
         })
     }
-    
-    /// Get a vector by index (zero-copy)
-    pub fn get(&self, index: usize) -> Option<&[f32]> {
+
+    /// Get a vector by index: zero-copy for `f32` rows, widened from
+    /// 16-bit ones
+    pub fn get(&self, index: usize) -> Option<Cow<'_, [f32]>> {
         if index >= self.count {
             return None;
         }
-        
-        let offset = 12 + index * self.dimensions * 4;
-        let slice = &self.mmap[offset..offset + self.dimensions * 4];
-        
-        // SAFETY: We know the data is properly aligned f32 values
-        // because we wrote it that way
+
+        let row_bytes = self.dimensions * self.precision.bytes();
+        let start = self.offset + index * row_bytes;
+        let slice = &self.mmap[start..start + row_bytes];
+        if self.precision != Precision::F32 {
+            return Some(Cow::Owned(
+                slice
+                    .chunks_exact(2)
+                    .map(|bits| self.precision.widen(u16::from_le_bytes([bits[0], bits[1]])))
+                    .collect(),
+            ));
+        }
+
+        // SAFETY: `open` checked that the file holds `count` full rows, so
+        // the slice is in bounds, and that the first row is aligned for
+        // f32; every row is a multiple of 4 bytes long, so the rest are
+        // too. Every bit pattern is a valid f32. The rows borrow `self`,
+        // which keeps the mapping alive.
         unsafe {
-            Some(std::slice::from_raw_parts(
+            Some(Cow::Borrowed(std::slice::from_raw_parts(
                 slice.as_ptr() as *const f32,
                 self.dimensions
-            ))
+            )))
         }
     }
-    
+
     pub fn len(&self) -> usize {
         self.count
     }
-    
+
     pub fn is_empty(&self) -> bool {
         self.count == 0
     }
-    
+
     pub fn dimensions(&self) -> usize {
         self.dimensions
     }
+
+    /// How the file stores each component
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+}
+
+/// Appends rows to a vector file, from [`MmapVectors::create`] or
+/// [`MmapVectorsWriter::append_to`].
+///
+/// The header's row count is only brought up to date by
+/// [`MmapVectorsWriter::flush`] and [`MmapVectorsWriter::finalize`]; a
+/// file opened in between reads the rows counted at the last flush.
+pub struct MmapVectorsWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    dimensions: usize,
+    count: usize,
+    precision: Precision,
+}
+
+impl MmapVectorsWriter {
+    /// Reopen a file written with a header to add rows after its last
+    /// counted one. Bytes past that row, left by a writer that never
+    /// flushed, are cut off.
+    pub fn append_to(path: &Path) -> Result<Self> {
+        let (dimensions, count, precision) = {
+            let rows = MmapVectors::open(path)?;
+            if rows.offset != DATA_OFFSET {
+                return Err(KhadyotaError::SerializationError(format!(
+                    "{:?} has no header; rewrite it before appending",
+                    path
+                )));
+            }
+            (rows.dimensions, rows.count, rows.precision)
+        };
+
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let end = (DATA_OFFSET + count * dimensions * precision.bytes()) as u64;
+        file.set_len(end)?;
+        file.seek(SeekFrom::Start(end))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            dimensions,
+            count,
+            precision,
+        })
+    }
+
+    /// Add a row, returning its index
+    pub fn append(&mut self, vector: &[f32]) -> Result<u32> {
+        if vector.len() != self.dimensions {
+            return Err(KhadyotaError::DimensionMismatch {
+                expected: self.dimensions,
+                got: vector.len(),
+            });
+        }
+        let index = u32::try_from(self.count).map_err(|_| {
+            KhadyotaError::InvalidConfig(format!("{:?} already holds {} rows", self.path, self.count))
+        })?;
+        write_row(&mut self.writer, vector, self.precision)?;
+        self.count += 1;
+        Ok(index)
+    }
+
+    /// Write buffered rows and record them in the header
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        let end = file.stream_position()?;
+        file.seek(SeekFrom::Start(COUNT_OFFSET))?;
+        file.write_all(&(self.count as u64).to_le_bytes())?;
+        file.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+
+    /// Flush, sync the file to disk and map it for reading
+    pub fn finalize(mut self) -> Result<MmapVectors> {
+        self.flush()?;
+        self.writer.get_ref().sync_all()?;
+        MmapVectors::open(&self.path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::serialization::Serializer;
-    use tempfile::NamedTempFile;
-    
+    use tempfile::{NamedTempFile, TempDir};
+
     #[test]
     fn test_mmap_vectors() {
         let temp = NamedTempFile::new().unwrap();
         let path = temp.path();
-        
+
         // Create and save vectors
         let vectors = vec![
             vec![1.0, 2.0, 3.0, 4.0],
             vec![5.0, 6.0, 7.0, 8.0],
         ];
         Serializer::save_vectors(&vectors, path).unwrap();
-        
+
         // Load with mmap
         let mmap_vecs = MmapVectors::open(path).unwrap();
-        
+
         assert_eq!(mmap_vecs.len(), 2);
         assert_eq!(mmap_vecs.dimensions(), 4);
-        
+
         let vec0 = mmap_vecs.get(0).unwrap();
-        assert_eq!(vec0, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(*vec0, [1.0, 2.0, 3.0, 4.0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_create_append_reopen() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("rows.vectors");
+        let vectors: Vec<Vec<f32>> = (0..1000).map(|i| (0..7).map(|j| (i * 7 + j) as f32 * 0.25).collect()).collect();
+
+        let mut writer = MmapVectors::create(&path, 7).unwrap();
+        for (i, vector) in vectors[..600].iter().enumerate() {
+            assert_eq!(writer.append(vector).unwrap(), i as u32);
+        }
+        assert!(matches!(
+            writer.append(&[1.0; 3]),
+            Err(KhadyotaError::DimensionMismatch { expected: 7, got: 3 })
+        ));
+        writer.flush().unwrap();
+        assert_eq!(MmapVectors::open(&path).unwrap().len(), 600);
+        drop(writer.finalize().unwrap());
+
+        // Rows appended but never flushed are dropped on the next append
+        let mut writer = MmapVectorsWriter::append_to(&path).unwrap();
+        writer.append(&vectors[600]).unwrap();
+        writer.writer.flush().unwrap();
+        drop(writer);
+        let mut writer = MmapVectorsWriter::append_to(&path).unwrap();
+        assert_eq!(writer.len(), 600);
+        for vector in &vectors[600..] {
+            writer.append(vector).unwrap();
+        }
+        let rows = writer.finalize().unwrap();
+
+        assert_eq!(rows.len(), 1000);
+        assert_eq!(rows.dimensions(), 7);
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(*rows.get(i).unwrap(), **vector);
+        }
+        assert!(rows.get(1000).is_none());
+        assert_eq!(Serializer::load_vectors(&path).unwrap(), vectors);
+    }
+
+    #[test]
+    fn test_malformed_files_are_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("rows.vectors");
+        Serializer::save_vectors(&[vec![1.0, 2.0], vec![3.0, 4.0]], &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        for (name, contents) in [
+            ("truncated rows", bytes[..bytes.len() - 2].to_vec()),
+            ("truncated header", bytes[..20].to_vec()),
+            ("empty", Vec::new()),
+            ("text", b"these are not vectors at all".to_vec()),
+            ("future version", [&bytes[..4], &7u32.to_le_bytes(), &bytes[8..]].concat()),
+            ("unknown element type", [&bytes[..4], &2u32.to_le_bytes(), &bytes[8..12], &9u32.to_le_bytes(), &bytes[16..]].concat()),
+        ] {
+            std::fs::write(&path, &contents).unwrap();
+            assert!(MmapVectors::open(&path).is_err(), "{}", name);
+        }
+
+        // Headerless files from earlier builds still open
+        let mut legacy = 2u64.to_le_bytes().to_vec();
+        legacy.extend(2u32.to_le_bytes());
+        for value in [1.0f32, 2.0, 3.0, 4.0] {
+            legacy.extend(value.to_le_bytes());
+        }
+        std::fs::write(&path, &legacy).unwrap();
+        let rows = MmapVectors::open(&path).unwrap();
+        assert_eq!(*rows.get(1).unwrap(), [3.0, 4.0]);
+        assert!(MmapVectorsWriter::append_to(&path).is_err());
+        std::fs::write(&path, 0u64.to_le_bytes()).unwrap();
+        assert!(MmapVectors::open(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_half_precision_rows() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("rows.vectors");
+        let vectors: Vec<Vec<f32>> = (0..50).map(|i| (0..6).map(|j| (i * 6 + j) as f32 * 0.5).collect()).collect();
+
+        for precision in [Precision::F16, Precision::BF16] {
+            let mut writer = MmapVectors::create_with(&path, 6, precision).unwrap();
+            for vector in &vectors[..20] {
+                writer.append(vector).unwrap();
+            }
+            drop(writer.finalize().unwrap());
+            let mut writer = MmapVectorsWriter::append_to(&path).unwrap();
+            assert_eq!(writer.precision(), precision);
+            for vector in &vectors[20..] {
+                writer.append(vector).unwrap();
+            }
+            let rows = writer.finalize().unwrap();
+
+            assert_eq!(std::fs::metadata(&path).unwrap().len(), 32 + 50 * 6 * 2);
+            assert_eq!((rows.len(), rows.precision()), (50, precision));
+            // Halves of small integers are exact in both formats
+            for (i, vector) in vectors.iter().enumerate().take(40) {
+                assert_eq!(*rows.get(i).unwrap(), **vector, "{:?}", precision);
+            }
+            assert_eq!(Serializer::load_vectors(&path).unwrap().len(), 50);
+        }
+    }
+}
@@ -1,9 +1,19 @@
+pub mod cold;
 pub mod format;
+pub mod half;
+pub(crate) mod lru;
 pub mod mmap;
 pub mod serialization;
 pub mod quantized;
+pub mod replace;
+pub mod vectors;
 
-pub use format::{FileHeader, MAGIC, VERSION};
-pub use mmap::MmapVectors;
+pub use cold::{ColdCacheStats, ColdVectors};
+pub use format::{feature_enabled, FileHeader, Section, MAGIC, MIN_VERSION, VERSION};
+pub use half::HalfRows;
+pub use mmap::{MmapVectors, MmapVectorsWriter};
 pub use serialization::Serializer;
-pub use quantized::QuantizedVectors;
\ No newline at end of file
+pub use quantized::QuantizedVectors;
+pub(crate) use quantized::ScanScratch;
+pub use replace::ReplaceStrategy;
+pub use vectors::{VectorRef, VectorStorage};
\ No newline at end of file
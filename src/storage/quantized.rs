@@ -1,47 +1,183 @@
-use crate::quantization::PQCodec;
+use crate::compat::{CompatibilityReport, Violation};
+use crate::error::{KhadyotaError, Result};
+use crate::parallel::chunk_len;
+use crate::quantization::Quantizer;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Vectors each rayon task encodes; keeps task overhead small next to
+/// the encoding work
+const ENCODE_CHUNK: usize = 256;
+
+/// Buffers a block scan works in, kept from scan to scan so a search
+/// allocates them once rather than once per cluster it scans
+#[derive(Default)]
+pub(crate) struct ScanScratch {
+    /// Code rows of the ids scanned
+    block: Vec<u8>,
+    /// Distance tables laid end to end
+    table: Vec<f32>,
+}
 
 /// Storage for quantized vectors
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuantizedVectors {
-    /// PQ codes for each vector
-    codes: Vec<Vec<u8>>,
+    /// Codes for each vector, packed `codec.code_bytes()` per vector
+    #[serde(with = "packed")]
+    codes: Vec<u8>,
     
     /// Original vectors (kept for reranking if needed)
     original_vectors: Option<Vec<Vec<f32>>>,
     
-    /// PQ codec
-    codec: PQCodec,
+    /// PQ or SQ8 codec
+    codec: Quantizer,
+    
+    /// Whether codes encode each row's offset from its IVF centroid
+    /// rather than the row itself; absent, and so false, in files saved
+    /// before residual codes existed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    residual: bool,
 }
 
 impl QuantizedVectors {
     /// Create new quantized storage
-    pub fn new(codec: PQCodec) -> Self {
+    pub fn new(codec: impl Into<Quantizer>) -> Self {
         Self {
             codes: Vec::new(),
             original_vectors: None,
-            codec,
+            codec: codec.into(),
+            residual: false,
+        }
+    }
+    
+    /// Empty storage for residual codes: what is added, replaced or
+    /// scored is each row's offset from its cluster centroid, prepared for
+    /// the metric by the caller, and `codec` was trained on such offsets
+    pub fn new_residual(codec: impl Into<Quantizer>) -> Self {
+        Self {
+            residual: true,
+            ..Self::new(codec)
         }
     }
     
+    /// Whether the codes are of residuals, see
+    /// [`QuantizedVectors::new_residual`]
+    pub fn is_residual(&self) -> bool {
+        self.residual
+    }
+    
+    pub(crate) fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        match (&self.codec, self.residual) {
+            (Quantizer::PQ(codec), true) => codec.encode_prepared(vector),
+            (Quantizer::OPQ(codec), true) => codec.encode_prepared(vector),
+            _ => self.codec.encode(vector),
+        }
+    }
+    
+    /// Bytes per vector; never 0, so rows can be counted and sliced
+    fn stride(&self) -> usize {
+        self.codec.code_bytes().max(1)
+    }
+    
     /// Add a vector (will be quantized)
     pub fn add(&mut self, vector: Vec<f32>) -> u32 {
-        let code = self.codec.encode(&vector);
-        let id = self.codes.len() as u32;
-        self.codes.push(code);
+        let id = self.len() as u32;
+        self.codes.extend(self.encode(&vector));
         id
     }
     
-    /// Add multiple vectors in batch
-    pub fn add_batch(&mut self, vectors: Vec<Vec<f32>>) {
-        for vector in vectors {
-            self.add(vector);
+    /// Wrap codes encoded elsewhere with `codec`, one packed row per id.
+    /// Rows must all be [`Quantizer::code_bytes`] wide; their codes are
+    /// taken as they are, so check them with
+    /// [`CompatibilityReport`](crate::compat::CompatibilityReport).
+    pub fn from_codes(codec: impl Into<Quantizer>, codes: Vec<Vec<u8>>) -> Result<Self> {
+        let codec = codec.into();
+        let expected = codec.code_bytes();
+        let narrow: Vec<(u32, usize)> = codes
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.len() != expected)
+            .map(|(id, row)| (id as u32, row.len()))
+            .collect();
+        if let Some(&(first_id, found)) = narrow.first() {
+            return Err(KhadyotaError::IncompatibleComponents(CompatibilityReport {
+                violations: vec![Violation::CodeWidth {
+                    expected,
+                    found,
+                    rows: narrow.len(),
+                    first_id,
+                }],
+            }));
+        }
+        
+        Ok(Self {
+            codes: codes.concat(),
+            original_vectors: None,
+            codec,
+            residual: false,
+        })
+    }
+    
+    /// Re-encode the vector stored at `id`
+    pub fn replace(&mut self, id: u32, vector: &[f32]) {
+        let codes = self.encode(vector);
+        self.put_codes(id, codes);
+    }
+    
+    /// Store precomputed codes at `id`, appending when `id` is the next one.
+    ///
+    /// # Panics
+    ///
+    /// If `codes` is not [`Quantizer::code_bytes`] long.
+    pub fn put_codes(&mut self, id: u32, codes: Vec<u8>) {
+        assert_eq!(codes.len(), self.codec.code_bytes(), "PQ code row has the wrong width");
+        let start = id as usize * self.stride();
+        if start == self.codes.len() {
+            self.codes.extend(codes);
+        } else {
+            self.codes[start..start + codes.len()].copy_from_slice(&codes);
         }
     }
     
-    /// Get quantized codes for a vector
+    /// Keep only the codes of `kept`, ascending ids, renumbered from 0 in
+    /// that order. Ids past the last encoded row are skipped.
+    pub fn retain_rows(&mut self, kept: &[u32]) {
+        let stride = self.stride();
+        let rows = self.len() as u32;
+        let mut codes = Vec::with_capacity(kept.len() * stride);
+        for &id in kept.iter().take_while(|&&id| id < rows) {
+            codes.extend_from_slice(self.get_codes(id));
+        }
+        self.codes = codes;
+    }
+    
+    /// Add multiple vectors, encoding them in parallel. Returns the ids
+    /// assigned, in input order.
+    pub fn add_batch(&mut self, vectors: Vec<Vec<f32>>) -> Range<u32> {
+        self.add_batch_from(vectors.iter().map(Vec::as_slice))
+    }
+    
+    /// [`QuantizedVectors::add_batch`] for borrowed vectors
+    pub fn add_batch_from<'a>(&mut self, vectors: impl IntoIterator<Item = &'a [f32]>) -> Range<u32> {
+        let vectors: Vec<&[f32]> = vectors.into_iter().collect();
+        let codes: Vec<u8> = vectors
+            .par_chunks(ENCODE_CHUNK)
+            .flat_map_iter(|chunk| chunk.iter().flat_map(|vector| self.encode(vector)))
+            .collect();
+        
+        let start = self.len() as u32;
+        self.codes.extend(codes);
+        start..self.len() as u32
+    }
+    
+    /// Get quantized codes for a vector: packed as
+    /// [`PQCodec::code`](crate::quantization::PQCodec::code) reads them, or
+    /// a byte per component for SQ8
     pub fn get_codes(&self, id: u32) -> &[u8] {
-        &self.codes[id as usize]
+        let stride = self.stride();
+        let start = id as usize * stride;
+        &self.codes[start..start + stride]
     }
     
     /// Compute distance using PQ
@@ -55,17 +191,205 @@ impl QuantizedVectors {
         self.codec.precompute_distance_table(query)
     }
     
+    /// [`QuantizedVectors::precompute_distance_table`] into `tables`,
+    /// reusing the allocations of a table made for an earlier query
+    pub fn precompute_distance_table_into(&self, query: &[f32], tables: &mut Vec<Vec<f32>>) {
+        self.codec.precompute_distance_table_into(query, tables);
+    }
+    
     /// Fast distance lookup using precomputed table
     pub fn table_lookup_distance(&self, dist_table: &[Vec<f32>], id: u32) -> f32 {
         let codes = self.get_codes(id);
         self.codec.table_lookup_distance(dist_table, codes)
     }
     
-    pub fn len(&self) -> usize {
+    /// [`QuantizedVectors::table_lookup_distance`] for each of `ids`, into
+    /// `out`: their rows are copied into one block and scanned together
+    /// with [`Quantizer::scan_codes`]
+    pub fn scan(&self, dist_table: &[Vec<f32>], ids: &[u32], out: &mut [f32]) {
+        let mut block = Vec::new();
+        self.gather(ids, out.len(), &mut block);
+        self.codec.scan_codes(dist_table, &block, self.stride(), out);
+    }
+    
+    /// [`QuantizedVectors::scan`] with Euclidean distances left squared,
+    /// for ranking, working in `scratch`
+    pub(crate) fn scan_keys(&self, dist_table: &[Vec<f32>], ids: &[u32], out: &mut [f32], scratch: &mut ScanScratch) {
+        self.gather(ids, out.len(), &mut scratch.block);
+        self.codec.scan_keys(dist_table, &scratch.block, self.stride(), out, &mut scratch.table);
+    }
+    
+    /// Code rows of `ids` copied into `block`, replacing what it held
+    fn gather(&self, ids: &[u32], outputs: usize, block: &mut Vec<u8>) {
+        assert_eq!(ids.len(), outputs);
+        block.clear();
+        block.reserve(ids.len() * self.stride());
+        for &id in ids {
+            block.extend_from_slice(self.get_codes(id));
+        }
+    }
+    
+    /// [`QuantizedVectors::table_lookup_distance`] for each of `ids`, in
+    /// parallel, in the order given. Each task takes enough ids for about
+    /// [`TARGET_TASK_COST`](crate::parallel::TARGET_TASK_COST) lookups.
+    pub fn score_parallel(&self, dist_table: &[Vec<f32>], ids: &[u32]) -> Vec<(u32, f32)> {
+        ids.par_iter()
+            .with_min_len(chunk_len(dist_table.len()))
+            .map(|&id| (id, self.table_lookup_distance(dist_table, id)))
+            .collect()
+    }
+    
+    /// Per-subquantizer terms of [`QuantizedVectors::table_lookup_distance`]
+    pub fn distance_terms(&self, dist_table: &[Vec<f32>], id: u32) -> Vec<f32> {
+        self.codec.distance_terms(dist_table, self.get_codes(id))
+    }
+    
+    /// Codec the codes were produced with
+    pub fn codec(&self) -> &Quantizer {
+        &self.codec
+    }
+    
+    /// Codes for every id, in id order. A partial row left at the end of
+    /// a corrupt file comes out short.
+    pub fn iter_codes(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.codes.chunks(self.stride())
+    }
+    
+    /// Bytes held by the packed codes
+    pub fn code_bytes(&self) -> usize {
         self.codes.len()
     }
+
+    /// Bytes held by the codes and the codec that reads them
+    pub fn memory_usage(&self) -> usize {
+        let originals = self.original_vectors.as_ref().map_or(0, |rows| {
+            rows.iter().map(|row| row.len() * std::mem::size_of::<f32>()).sum()
+        });
+        self.codes.len() + self.codec.memory_usage() + originals
+    }
+    
+    pub fn len(&self) -> usize {
+        self.codes.len().div_ceil(self.stride())
+    }
     
     pub fn is_empty(&self) -> bool {
         self.codes.is_empty()
     }
-}
\ No newline at end of file
+}
+
+/// Codes are saved as a single MessagePack binary. Files written before
+/// packing hold an array of codes per vector, one byte each; those are
+/// 8-bit codes, so joining the rows gives the packed layout.
+mod packed {
+    use serde::de::{Error, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(codes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(codes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_any(PackedVisitor)
+    }
+
+    struct PackedVisitor;
+
+    impl<'de> Visitor<'de> for PackedVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("packed PQ codes, or an array of code rows")
+        }
+
+        fn visit_bytes<E: Error>(self, codes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(codes.to_vec())
+        }
+
+        fn visit_byte_buf<E: Error>(self, codes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(codes)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut rows: A) -> Result<Vec<u8>, A::Error> {
+            let mut codes = Vec::new();
+            let mut width = None;
+            while let Some(row) = rows.next_element::<Vec<u8>>()? {
+                let width = *width.get_or_insert(row.len());
+                if row.len() != width {
+                    return Err(A::Error::custom(format!(
+                        "PQ code rows are {} and {} bytes wide",
+                        width,
+                        row.len()
+                    )));
+                }
+                codes.extend(row);
+            }
+            Ok(codes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantization::PQCodec;
+
+    fn dataset(n: usize, dims: usize) -> Vec<Vec<f32>> {
+        (0..n)
+            .map(|i| (0..dims).map(|j| ((i * dims + j) as f32 * 0.37).sin()).collect())
+            .collect()
+    }
+
+    /// Layout written before codes were packed
+    #[derive(Serialize)]
+    struct LegacyQuantized {
+        codes: Vec<Vec<u8>>,
+        original_vectors: Option<Vec<Vec<f32>>>,
+        codec: PQCodec,
+    }
+
+    #[test]
+    fn test_packed_and_legacy_codes_load() {
+        let training = dataset(600, 32);
+        let codec = PQCodec::train_seeded(&training, 4, Some(3)).unwrap();
+        let mut quantized = QuantizedVectors::new(codec.clone());
+        quantized.add_batch(training.clone());
+
+        let saved = rmp_serde::to_vec(&quantized).unwrap();
+        let loaded: QuantizedVectors = rmp_serde::from_slice(&saved).unwrap();
+        assert!(loaded.iter_codes().eq(quantized.iter_codes()));
+
+        let legacy = LegacyQuantized {
+            codes: quantized.iter_codes().map(<[u8]>::to_vec).collect(),
+            original_vectors: None,
+            codec,
+        };
+        let loaded: QuantizedVectors = rmp_serde::from_slice(&rmp_serde::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!(loaded.codec().pq().unwrap().bits, 8);
+        assert_eq!(loaded.len(), 600);
+        assert!(loaded.iter_codes().eq(quantized.iter_codes()));
+    }
+
+    #[test]
+    fn test_add_batch_matches_serial_adds() {
+        let training = dataset(600, 32);
+        let codec = PQCodec::train_seeded(&training, 4, Some(3)).unwrap();
+        let vectors = dataset(2_000, 32);
+
+        let mut serial = QuantizedVectors::new(codec.clone());
+        for vector in &vectors {
+            serial.add(vector.clone());
+        }
+
+        // Split across calls so the second range starts past the first
+        let mut batched = QuantizedVectors::new(codec);
+        assert_eq!(batched.add_batch(vectors[..700].to_vec()), 0..700);
+        assert_eq!(batched.add_batch_from(vectors[700..].iter().map(Vec::as_slice)), 700..2_000);
+        assert_eq!(batched.add_batch(Vec::new()), 2_000..2_000);
+
+        assert_eq!(batched.len(), serial.len());
+        for id in 0..2_000 {
+            assert_eq!(batched.get_codes(id), serial.get_codes(id), "id {}", id);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// How a file written beside its target was moved over it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplaceStrategy {
+    /// Unix: the new file is synced, renamed over the target, and the
+    /// directory is synced. Once it returns, a crash leaves the new file;
+    /// before, the old one, whole.
+    RenameSyncDir,
+
+    /// Windows: the new file is synced, then `ReplaceFileW` swaps it in
+    /// for an existing target, or `MoveFileExW` with write-through moves
+    /// it into place. A crash leaves the old file or the new one, whole.
+    ReplaceFile,
+
+    /// Elsewhere, or where the directory cannot be synced (some network
+    /// filesystems): the new file is synced and renamed over the target.
+    /// Readers still see one file or the other, but a crash soon after
+    /// may undo the rename.
+    BestEffortRename,
+}
+
+impl ReplaceStrategy {
+    /// What this platform does
+    pub const PLATFORM: Self = if cfg!(unix) {
+        ReplaceStrategy::RenameSyncDir
+    } else if cfg!(windows) {
+        ReplaceStrategy::ReplaceFile
+    } else {
+        ReplaceStrategy::BestEffortRename
+    };
+}
+
+impl Default for ReplaceStrategy {
+    fn default() -> Self {
+        Self::PLATFORM
+    }
+}
+
+/// The filesystem operations a replacement is made of, so tests can fail
+/// them one at a time
+pub(crate) trait FileSystem {
+    fn sync_file(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+
+    /// Swap `from` in for `to`, keeping the old file until the new one is
+    /// in place; Windows only
+    fn replace_file(&self, from: &Path, to: &Path) -> io::Result<()>;
+}
+
+/// The real filesystem
+pub(crate) struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        File::open(path)?.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        File::open(dir)?.sync_all()
+    }
+
+    #[cfg(windows)]
+    fn replace_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        windows::replace_file(from, to)
+    }
+
+    #[cfg(not(windows))]
+    fn replace_file(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "ReplaceFileW is Windows only"))
+    }
+}
+
+/// Move `tmp`, fully written, over `target` so that readers and crashes
+/// see the old file or the new one, never neither. Returns how.
+///
+/// On failure `tmp` may remain and the caller removes it; `target` holds
+/// the old contents unless the failure came after the move, when it holds
+/// the new ones.
+pub(crate) fn replace_file(tmp: &Path, target: &Path) -> io::Result<ReplaceStrategy> {
+    replace_with(&OsFileSystem, ReplaceStrategy::PLATFORM, tmp, target)
+}
+
+pub(crate) fn replace_with(
+    fs: &dyn FileSystem,
+    strategy: ReplaceStrategy,
+    tmp: &Path,
+    target: &Path,
+) -> io::Result<ReplaceStrategy> {
+    fs.sync_file(tmp)?;
+    match strategy {
+        ReplaceStrategy::ReplaceFile => {
+            fs.replace_file(tmp, target)?;
+            Ok(strategy)
+        }
+        ReplaceStrategy::RenameSyncDir => {
+            fs.rename(tmp, target)?;
+            let dir = match target.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            match fs.sync_dir(dir) {
+                Ok(()) => Ok(strategy),
+                // Filesystems that cannot sync a directory say so; the
+                // rename stands, without the durability promise
+                Err(e) if matches!(e.kind(), io::ErrorKind::Unsupported | io::ErrorKind::InvalidInput) => {
+                    Ok(ReplaceStrategy::BestEffortRename)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        ReplaceStrategy::BestEffortRename => {
+            fs.rename(tmp, target)?;
+            Ok(strategy)
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    const REPLACEFILE_IGNORE_MERGE_ERRORS: u32 = 0x2;
+    const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+    const MOVEFILE_WRITE_THROUGH: u32 = 0x8;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn ReplaceFileW(
+            replaced: *const u16,
+            replacement: *const u16,
+            backup: *const u16,
+            flags: u32,
+            exclude: *mut c_void,
+            reserved: *mut c_void,
+        ) -> i32;
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    pub(super) fn replace_file(from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to_exists, to) = (wide(from), to.exists(), wide(to));
+        // SAFETY: both paths are NUL-terminated UTF-16 that outlive the
+        // calls; the optional arguments are null as documented
+        let ok = unsafe {
+            if to_exists {
+                ReplaceFileW(
+                    to.as_ptr(),
+                    from.as_ptr(),
+                    std::ptr::null(),
+                    REPLACEFILE_IGNORE_MERGE_ERRORS,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                )
+            } else {
+                MoveFileExW(from.as_ptr(), to.as_ptr(), MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH)
+            }
+        };
+        if ok == 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Step {
+        SyncFile,
+        Move,
+        SyncDir,
+    }
+
+    /// The real filesystem, crashing at one step: that step and every
+    /// later one fail without taking effect
+    struct CrashingFs {
+        crash_at: Option<Step>,
+        dir_sync: io::ErrorKind,
+        crashed: RefCell<bool>,
+    }
+
+    impl CrashingFs {
+        fn new(crash_at: Option<Step>) -> Self {
+            Self {
+                crash_at,
+                dir_sync: io::ErrorKind::Other,
+                crashed: RefCell::new(false),
+            }
+        }
+
+        fn step(&self, step: Step) -> io::Result<()> {
+            if *self.crashed.borrow() || self.crash_at == Some(step) {
+                *self.crashed.borrow_mut() = true;
+                return Err(io::Error::other(format!("crashed at {:?}", step)));
+            }
+            Ok(())
+        }
+    }
+
+    impl FileSystem for CrashingFs {
+        fn sync_file(&self, path: &Path) -> io::Result<()> {
+            self.step(Step::SyncFile)?;
+            OsFileSystem.sync_file(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.step(Step::Move)?;
+            OsFileSystem.rename(from, to)
+        }
+
+        fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+            self.step(Step::SyncDir)?;
+            if self.dir_sync != io::ErrorKind::Other {
+                return Err(self.dir_sync.into());
+            }
+            OsFileSystem.sync_dir(dir)
+        }
+
+        // Stands in for ReplaceFileW, which swaps in one step
+        fn replace_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.step(Step::Move)?;
+            std::fs::rename(from, to)
+        }
+    }
+
+    fn files(dir: &TempDir) -> (std::path::PathBuf, std::path::PathBuf) {
+        let (tmp, target) = (dir.path().join("db.kdb.partial"), dir.path().join("db.kdb"));
+        std::fs::write(&target, b"old").unwrap();
+        std::fs::write(&tmp, b"new").unwrap();
+        (tmp, target)
+    }
+
+    #[test]
+    fn test_a_crash_leaves_the_old_file_or_the_new_one() {
+        let cases = [
+            (ReplaceStrategy::RenameSyncDir, None, b"new"),
+            (ReplaceStrategy::RenameSyncDir, Some(Step::SyncFile), b"old"),
+            (ReplaceStrategy::RenameSyncDir, Some(Step::Move), b"old"),
+            // Renamed but not yet durable: visible now, though a power
+            // loss could still undo it
+            (ReplaceStrategy::RenameSyncDir, Some(Step::SyncDir), b"new"),
+            (ReplaceStrategy::ReplaceFile, None, b"new"),
+            (ReplaceStrategy::ReplaceFile, Some(Step::SyncFile), b"old"),
+            (ReplaceStrategy::ReplaceFile, Some(Step::Move), b"old"),
+            (ReplaceStrategy::BestEffortRename, Some(Step::Move), b"old"),
+            (ReplaceStrategy::BestEffortRename, None, b"new"),
+        ];
+        for (strategy, crash_at, expected) in cases {
+            let dir = TempDir::new().unwrap();
+            let (tmp, target) = files(&dir);
+            let result = replace_with(&CrashingFs::new(crash_at), strategy, &tmp, &target);
+
+            assert_eq!(result.is_ok(), crash_at.is_none(), "{:?} at {:?}", strategy, crash_at);
+            assert_eq!(std::fs::read(&target).unwrap(), expected, "{:?} at {:?}", strategy, crash_at);
+            // The new file is either in place or still beside it, whole
+            let moved = expected == b"new";
+            assert_eq!(tmp.exists(), !moved);
+            if !moved {
+                assert_eq!(std::fs::read(&tmp).unwrap(), b"new");
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsyncable_directories_fall_back_to_best_effort() {
+        let dir = TempDir::new().unwrap();
+        let (tmp, target) = files(&dir);
+        let fs = CrashingFs {
+            dir_sync: io::ErrorKind::Unsupported,
+            ..CrashingFs::new(None)
+        };
+        let strategy = replace_with(&fs, ReplaceStrategy::RenameSyncDir, &tmp, &target).unwrap();
+        assert_eq!(strategy, ReplaceStrategy::BestEffortRename);
+        assert_eq!(std::fs::read(&target).unwrap(), b"new");
+
+        // A target that does not exist yet is created
+        let fresh = dir.path().join("fresh.kdb");
+        std::fs::write(&tmp, b"first").unwrap();
+        assert_eq!(replace_file(&tmp, &fresh).unwrap(), ReplaceStrategy::PLATFORM);
+        assert_eq!(std::fs::read(&fresh).unwrap(), b"first");
+    }
+}
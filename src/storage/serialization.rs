@@ -1,80 +1,100 @@
 use crate::error::Result;
+use crate::config::Precision;
+use crate::storage::mmap::{rows_header, write_row, MmapVectors};
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
 pub struct Serializer;
 
+/// A writer counting the bytes it passes on
+struct Counted<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 impl Serializer {
     /// Serialize data to a file
     pub fn save<T: serde::Serialize>(data: &T, path: &Path) -> Result<()> {
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
-        rmp_serde::encode::write(&mut writer, data)?;
+        Self::save_to(data, File::create(path)?)?;
         Ok(())
     }
     
+    /// Serialize data to `writer`, returning the number of bytes written
+    pub fn save_to<T: serde::Serialize, W: Write>(data: &T, writer: W) -> Result<u64> {
+        let mut writer = Counted { inner: BufWriter::new(writer), written: 0 };
+        rmp_serde::encode::write(&mut writer, data)?;
+        writer.inner.into_inner().map_err(|e| e.into_error())?;
+        Ok(writer.written)
+    }
+    
     /// Deserialize data from a file
     pub fn load<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let deserialized = rmp_serde::from_read(reader)?;
+        Self::load_from(File::open(path)?)
+    }
+    
+    /// Deserialize data from `reader`
+    pub fn load_from<T: serde::de::DeserializeOwned, R: Read>(reader: R) -> Result<T> {
+        let deserialized = rmp_serde::from_read(BufReader::new(reader))?;
         Ok(deserialized)
     }
     
-    /// Save vectors in efficient binary format
+    /// Save vectors in the flat format [`MmapVectors`] maps
     pub fn save_vectors(vectors: &[Vec<f32>], path: &Path) -> Result<()> {
+        let dims = vectors.first().map_or(0, |first| first.len());
+        Self::save_rows(vectors.iter().map(|v| v.as_slice()), vectors.len(), dims, path)
+    }
+    
+    /// [`Serializer::save_vectors`] for `count` rows of `dims` streamed
+    /// from an iterator, so they need not all be in memory at once
+    pub fn save_rows<'a>(
+        rows: impl Iterator<Item = &'a [f32]>,
+        count: usize,
+        dims: usize,
+        path: &Path,
+    ) -> Result<()> {
+        Self::save_rows_as(rows, count, dims, Precision::F32, path)
+    }
+    
+    /// [`Serializer::save_rows`] with each component stored in
+    /// `precision`, rounding rows that are not already
+    pub fn save_rows_as<R: AsRef<[f32]>>(
+        rows: impl Iterator<Item = R>,
+        count: usize,
+        dims: usize,
+        precision: Precision,
+        path: &Path,
+    ) -> Result<()> {
         let file = File::create(path)?;
         let mut writer = BufWriter::new(file);
         
-        // Write count
-        let count = vectors.len() as u64;
-        writer.write_all(&count.to_le_bytes())?;
-        
-        // Write dimensions (assuming all same size)
-        if let Some(first) = vectors.first() {
-            let dims = first.len() as u32;
-            writer.write_all(&dims.to_le_bytes())?;
-            
-            // Write all vectors
-            for vec in vectors {
-                for &val in vec {
-                    writer.write_all(&val.to_le_bytes())?;
-                }
-            }
+        writer.write_all(&rows_header(dims, count, precision))?;
+        for row in rows {
+            write_row(&mut writer, row.as_ref(), precision)?;
         }
         
+        writer.flush()?;
         Ok(())
     }
     
-    /// Load vectors from binary format
+    /// Load vectors saved by [`Serializer::save_vectors`], or by builds
+    /// that wrote them without a header
     pub fn load_vectors(path: &Path) -> Result<Vec<Vec<f32>>> {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
-        
-        // Read count
-        let mut count_bytes = [0u8; 8];
-        reader.read_exact(&mut count_bytes)?;
-        let count = u64::from_le_bytes(count_bytes) as usize;
-        
-        // Read dimensions
-        let mut dims_bytes = [0u8; 4];
-        reader.read_exact(&mut dims_bytes)?;
-        let dims = u32::from_le_bytes(dims_bytes) as usize;
-        
-        // Read vectors
-        let mut vectors = Vec::with_capacity(count);
-        for _ in 0..count {
-            let mut vec = Vec::with_capacity(dims);
-            for _ in 0..dims {
-                let mut val_bytes = [0u8; 4];
-                reader.read_exact(&mut val_bytes)?;
-                vec.push(f32::from_le_bytes(val_bytes));
-            }
-            vectors.push(vec);
-        }
-        
-        Ok(vectors)
+        let rows = MmapVectors::open(path)?;
+        Ok((0..rows.len()).filter_map(|i| rows.get(i)).map(Cow::into_owned).collect())
     }
 }
 
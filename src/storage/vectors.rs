@@ -0,0 +1,210 @@
+use crate::config::Precision;
+use crate::storage::cold::ColdVectors;
+use crate::storage::half::HalfRows;
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use std::borrow::Cow;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Where a database keeps its original (full-precision) vectors
+pub enum VectorStorage {
+    /// Every row in memory
+    Memory(Vec<Vec<f32>>),
+
+    /// Rows on disk behind an LRU cache
+    Cold(Box<ColdVectors>),
+
+    /// Every row in memory at half precision, widened as it is read
+    Half(HalfRows),
+}
+
+/// A row borrowed from memory, shared out of the cold cache, or widened
+/// from half precision
+pub enum VectorRef<'a> {
+    Borrowed(&'a [f32]),
+    Shared(Arc<[f32]>),
+    Owned(Vec<f32>),
+}
+
+impl AsRef<[f32]> for VectorRef<'_> {
+    fn as_ref(&self) -> &[f32] {
+        self
+    }
+}
+
+impl Deref for VectorRef<'_> {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            VectorRef::Borrowed(row) => row,
+            VectorRef::Shared(row) => row,
+            VectorRef::Owned(row) => row,
+        }
+    }
+}
+
+impl<'a> VectorRef<'a> {
+    /// The row, still borrowed if it was borrowed from memory
+    pub fn into_cow(self) -> Cow<'a, [f32]> {
+        match self {
+            VectorRef::Borrowed(row) => Cow::Borrowed(row),
+            VectorRef::Shared(row) => Cow::Owned(row.to_vec()),
+            VectorRef::Owned(row) => Cow::Owned(row),
+        }
+    }
+}
+
+impl Default for VectorStorage {
+    fn default() -> Self {
+        VectorStorage::Memory(Vec::new())
+    }
+}
+
+impl VectorStorage {
+    /// Empty in-memory storage for rows of `dimensions` kept in
+    /// `precision`
+    pub fn new(precision: Precision, dimensions: usize) -> Self {
+        match precision {
+            Precision::F32 => VectorStorage::Memory(Vec::new()),
+            half => VectorStorage::Half(HalfRows::new(half, dimensions)),
+        }
+    }
+
+    /// In-memory storage holding `rows`, rounded to `precision`
+    pub fn from_rows(rows: Vec<Vec<f32>>, precision: Precision, dimensions: usize) -> Self {
+        match precision {
+            Precision::F32 => VectorStorage::Memory(rows),
+            half => {
+                let mut storage = HalfRows::new(half, dimensions);
+                storage.reserve(rows.len());
+                for row in &rows {
+                    storage.push(row);
+                }
+                VectorStorage::Half(storage)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            VectorStorage::Memory(rows) => rows.len(),
+            VectorStorage::Cold(cold) => cold.len(),
+            VectorStorage::Half(rows) => rows.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, vector: Vec<f32>) {
+        match self {
+            VectorStorage::Memory(rows) => rows.push(vector),
+            VectorStorage::Cold(cold) => cold.push(vector),
+            VectorStorage::Half(rows) => rows.push(&vector),
+        }
+    }
+
+    /// Make room for `additional` more rows
+    pub fn reserve(&mut self, additional: usize) {
+        match self {
+            VectorStorage::Memory(rows) => rows.reserve(additional),
+            VectorStorage::Cold(cold) => cold.reserve(additional),
+            VectorStorage::Half(rows) => rows.reserve(additional),
+        }
+    }
+
+    /// Replace the row at `id`, which must already exist
+    pub fn set(&mut self, id: u32, vector: Vec<f32>) {
+        match self {
+            VectorStorage::Memory(rows) => rows[id as usize] = vector,
+            VectorStorage::Cold(cold) => cold.set(id, vector),
+            VectorStorage::Half(rows) => rows.set(id, &vector),
+        }
+    }
+
+    /// Point lookup; cold rows are faulted into the cache
+    pub fn get(&self, id: u32) -> Option<VectorRef<'_>> {
+        match self {
+            VectorStorage::Memory(rows) => rows.get(id as usize).map(|v| VectorRef::Borrowed(v)),
+            VectorStorage::Cold(cold) => cold.get(id).map(VectorRef::Shared),
+            VectorStorage::Half(rows) => rows.get(id).map(VectorRef::Owned),
+        }
+    }
+
+    /// Sequential scan that bypasses the cold cache
+    pub fn iter(&self) -> impl Iterator<Item = VectorRef<'_>> + '_ {
+        (0..self.len() as u32).map(move |id| match self {
+            VectorStorage::Memory(rows) => VectorRef::Borrowed(&rows[id as usize]),
+            VectorStorage::Cold(cold) => match cold.row(id).unwrap() {
+                Cow::Borrowed(row) => VectorRef::Borrowed(row),
+                Cow::Owned(row) => VectorRef::Owned(row),
+            },
+            VectorStorage::Half(rows) => VectorRef::Owned(rows.get(id).unwrap()),
+        })
+    }
+
+    /// All rows as a slice, materializing cold and half-precision rows
+    /// temporarily
+    pub fn as_rows(&self) -> Cow<'_, [Vec<f32>]> {
+        match self {
+            VectorStorage::Memory(rows) => Cow::Borrowed(rows.as_slice()),
+            _ => Cow::Owned(self.iter().map(|row| row.to_vec()).collect()),
+        }
+    }
+
+    pub fn into_rows(self) -> Vec<Vec<f32>> {
+        match self {
+            VectorStorage::Memory(rows) => rows,
+            other => other.as_rows().into_owned(),
+        }
+    }
+
+    /// Precision rows are kept in: as stored in memory, or `f32` for
+    /// spilled rows, which are written as `f32`
+    pub fn precision(&self) -> Precision {
+        match self {
+            VectorStorage::Half(rows) => rows.precision(),
+            _ => Precision::F32,
+        }
+    }
+
+    /// Bytes of rows held on the heap; for spilled rows, only those
+    /// written since and those cached
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            VectorStorage::Memory(rows) => rows.iter().map(Vec::len).sum::<usize>() * std::mem::size_of::<f32>(),
+            VectorStorage::Cold(cold) => cold.heap_bytes(),
+            VectorStorage::Half(rows) => rows.heap_bytes(),
+        }
+    }
+
+    /// A copy for a read-only snapshot: memory rows are copied, spilled
+    /// rows share their mapping
+    pub(crate) fn snapshot(&self) -> Self {
+        match self {
+            VectorStorage::Memory(rows) => VectorStorage::Memory(rows.clone()),
+            VectorStorage::Cold(cold) => VectorStorage::Cold(Box::new(cold.snapshot())),
+            VectorStorage::Half(rows) => VectorStorage::Half(rows.clone()),
+        }
+    }
+
+    pub fn cold(&self) -> Option<&ColdVectors> {
+        match self {
+            VectorStorage::Cold(cold) => Some(cold),
+            VectorStorage::Memory(_) | VectorStorage::Half(_) => None,
+        }
+    }
+}
+
+/// Serialized as a plain sequence of rows, whatever the backing
+impl Serialize for VectorStorage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for row in self.iter() {
+            seq.serialize_element(&*row)?;
+        }
+        seq.end()
+    }
+}
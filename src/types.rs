@@ -1,3 +1,4 @@
+use crate::external_ids::ExternalId;
 use serde::{Deserialize, Serialize};
 
 /// Search result with distance and metadata
@@ -6,6 +7,20 @@ pub struct SearchResult {
     pub id: u32,
     pub distance: f32,
     pub metadata: Option<serde_json::Value>,
+
+    /// The id the entry was inserted under with
+    /// [`crate::VectorDB::insert_with_id`], if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<ExternalId>,
+}
+
+impl SearchResult {
+    /// The distance as a score where higher is better, under `metric`,
+    /// the metric of the database searched; see
+    /// [`DistanceMetric::similarity`](crate::config::DistanceMetric::similarity)
+    pub fn similarity(&self, metric: crate::config::DistanceMetric) -> f32 {
+        metric.similarity(self.distance)
+    }
 }
 
 /// Vector with metadata
@@ -14,4 +29,21 @@ pub struct VectorEntry {
     pub id: u32,
     pub vector: Vec<f32>,
     pub metadata: Option<serde_json::Value>,
-}
\ No newline at end of file
+}
+
+/// Per-entry options recorded by [`crate::VectorDB::insert_opts`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntryAttributes {
+    /// Unix timestamp (seconds) after which the entry is excluded from
+    /// search results
+    pub expires_at_unix_secs: Option<u64>,
+
+    /// Caller-defined importance, carried with the entry
+    pub weight: Option<f32>,
+
+    /// Owning tenant
+    pub tenant: Option<String>,
+
+    /// Suggested IVF cluster; advisory, the index assigns by distance
+    pub cluster_hint: Option<u32>,
+}
@@ -1,22 +1,264 @@
-use crate::config::Config;
+use crate::access::AccessTracker;
+use crate::admission::{AdmissionConfig, AdmissionController, AdmissionStats};
+use crate::changelog::{ChangeOp, ChangelogSink};
+use crate::changeset::ChangeSet;
+use crate::compat::CompatibilityReport;
+use crate::distance::{distance_key, distance_to_key, key_to_distance, unit_cosine_distance};
+use crate::config::{Config, DistanceMetric, IndexKind, QuantizerKind};
+use crate::encoding::InsertRate;
 use crate::error::Result;
-use crate::indexing::IVFIndex;
-use crate::quantization::PQCodec;
-use crate::storage::QuantizedVectors;
-use crate::types::SearchResult;
+use crate::external_ids::ExternalIds;
+use crate::filter::CandidateFilter;
+use crate::indexing::{FlatIndex, IVFIndex};
+use crate::indexing::ivf::{IVFStats, RebalanceReport};
+use crate::quantization::{OPQCodec, PQCodec, Quantizer, SQCodec};
+use crate::query_cache::{QueryCache, QueryCacheConfig, QueryCacheStats};
+use crate::search_params::SearchParams;
+use crate::batch::SearchScratch;
+use crate::select::TopK;
+use crate::shared::SharedHandle;
+use crate::storage::{ColdCacheStats, ColdVectors, FileHeader, QuantizedVectors, Section, Serializer, VectorStorage};
+use crate::insert_options::InsertOptions;
+use crate::journal::Journal;
+use crate::latency::{Histogram, LatencyRecorder};
+use crate::maintenance::IndexLag;
+use crate::metadata_index::MetadataIndex;
+use crate::parallel::chunk_len;
+use crate::progress::{BuildEvent, ProgressCallback, Silent};
+use crate::types::{EntryAttributes, SearchResult, VectorEntry};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::path::Path;
+use crate::persist::{self, DirtySections, Layout, PersistSection, Tally, Tallied};
+use serde::de::{self, DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-/// Main Vector Database structure
-pub struct VectorDB {
+/// On-disk layout of a saved database, in serialization order.
+///
+/// MessagePack encodes this as an array, so fields may only be appended,
+/// and appended fields must default when absent from older files. It is
+/// written field by field by [`VectorDB::write_sections`] and read by
+/// [`StateSeed`], which record where each section lies.
+struct SavedState {
     config: Config,
     vectors: Vec<Vec<f32>>,
     quantized: Option<QuantizedVectors>,
     ivf_index: Option<IVFIndex>,
-    metadata: HashMap<u32, serde_json::Value>,
+    metadata: BTreeMap<u32, serde_json::Value>,
     next_id: u32,
     index_built: bool,
+    attributes: BTreeMap<u32, EntryAttributes>,
+    deleted: BTreeSet<u32>,
+    seq: u64,
+    /// Hash of the vectors, codes and index sections; see
+    /// [`persist::build_id`]
+    build_id: Option<u64>,
+}
+
+/// Reads a [`SavedState`] from a [`Tallied`] stream, noting where each
+/// section ends and hashing the ones a later save may reuse
+struct StateSeed<'a> {
+    tally: &'a Tally,
+}
+
+/// Where the sections of a [`SavedState`] ended, and their hashes
+struct StateBounds {
+    /// Ends of the config, vectors, codes, index and the whole state
+    ends: [u64; 5],
+    hashes: [u64; 3],
+    build_id_at: u64,
+}
+
+impl<'de> DeserializeSeed<'de> for StateSeed<'_> {
+    type Value = (SavedState, StateBounds);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StateSeed<'_> {
+    type Value = (SavedState, StateBounds);
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a saved database state")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        // Older files end early; the fields they lack take their defaults
+        let missing = |index| de::Error::invalid_length(index, &"at least 7 fields");
+        let config = seq.next_element()?.ok_or_else(|| missing(0))?;
+        let config_end = self.tally.position();
+        self.tally.take_hash();
+        let vectors = seq.next_element()?.ok_or_else(|| missing(1))?;
+        let (vectors_end, vectors_hash) = (self.tally.position(), self.tally.take_hash());
+        let quantized = seq.next_element()?.ok_or_else(|| missing(2))?;
+        let (codes_end, codes_hash) = (self.tally.position(), self.tally.take_hash());
+        let ivf_index = seq.next_element()?.ok_or_else(|| missing(3))?;
+        let index_end = self.tally.position();
+        let index_hash = self.tally.take_hash();
+        let metadata = seq.next_element()?.ok_or_else(|| missing(4))?;
+        let next_id = seq.next_element()?.ok_or_else(|| missing(5))?;
+        let index_built = seq.next_element()?.ok_or_else(|| missing(6))?;
+        let attributes = seq.next_element()?.unwrap_or_default();
+        let deleted = seq.next_element()?.unwrap_or_default();
+        let saved_seq = seq.next_element()?.unwrap_or_default();
+        let build_id_at = self.tally.position();
+        let build_id = seq.next_element()?;
+
+        let state = SavedState {
+            config,
+            vectors,
+            quantized,
+            ivf_index,
+            metadata,
+            next_id,
+            index_built,
+            attributes,
+            deleted,
+            seq: saved_seq,
+            build_id,
+        };
+        let bounds = StateBounds {
+            ends: [config_end, vectors_end, codes_end, index_end, self.tally.position()],
+            hashes: [vectors_hash, codes_hash, index_hash],
+            build_id_at,
+        };
+        Ok((state, bounds))
+    }
+}
+
+/// Vector file written beside `path` by [`VectorDB::save_mapped`]
+fn mapped_vectors_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".vectors");
+    PathBuf::from(name)
+}
+
+/// Rows to train on: all of `rows`, or a seeded random subset of
+/// `max_training_vectors` of them, in id order
+fn training_sample<'a>(config: &Config, rows: &'a [Vec<f32>]) -> Cow<'a, [Vec<f32>]> {
+    match config.max_training_vectors {
+        Some(max) if rows.len() > max => {
+            let mut rng = match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            let mut picked = rand::seq::index::sample(&mut rng, rows.len(), max).into_vec();
+            picked.sort_unstable();
+            Cow::Owned(picked.into_iter().map(|i| rows[i].clone()).collect())
+        }
+        _ => Cow::Borrowed(rows),
+    }
+}
+
+/// A quantizer and IVF index trained by [`VectorDB::train_index`], not
+/// yet in place
+pub(crate) struct TrainedIndex {
+    quantized: Option<QuantizedVectors>,
+    ivf: IVFIndex,
+    /// Rows it was trained on and covers, ids below this
+    indexed_rows: u32,
+}
+
+/// Main Vector Database structure
+pub struct VectorDB {
+    pub(crate) config: Config,
+    pub(crate) vectors: VectorStorage,
+    pub(crate) quantized: Option<QuantizedVectors>,
+    pub(crate) ivf_index: Option<IVFIndex>,
+    /// Ordered by id so saved files are byte-for-byte reproducible
+    pub(crate) metadata: BTreeMap<u32, serde_json::Value>,
+    pub(crate) next_id: u32,
+    pub(crate) index_built: bool,
+    
+    /// Per-entry options recorded at insert time; only entries that set any
+    pub(crate) attributes: BTreeMap<u32, EntryAttributes>,
+    
+    /// Tombstoned ids: their rows stay in place so ids remain dense, but
+    /// they never appear in results
+    pub(crate) deleted: BTreeSet<u32>,
+    
+    /// When the current index was built in this process (not persisted)
+    pub(crate) last_build: Option<SystemTime>,
+    
+    /// Concurrency limiter for searches (runtime only, off by default)
+    pub(crate) admission: Option<AdmissionController>,
+    
+    /// Bumped by every mutation; cached results from older generations
+    /// are never served (runtime only)
+    pub(crate) generation: u64,
+    
+    /// Result cache for repeated identical queries (runtime only, off by
+    /// default)
+    pub(crate) query_cache: Option<QueryCache>,
+    
+    /// Per-query search latencies (runtime only, off by default)
+    pub(crate) latency: Option<LatencyRecorder>,
+    
+    /// Extra sections carried through load and save
+    pub(crate) sections: Vec<Section>,
+    
+    /// Optional sections skipped by the last load (runtime only)
+    pub(crate) load_warnings: Vec<String>,
+    
+    /// Sequence number of the last change made or applied
+    pub(crate) seq: u64,
+    
+    /// Where change records are streamed, if anywhere (runtime only)
+    pub(crate) changelog: Option<ChangelogSink>,
+    
+    /// Where changes are journaled, under `open_durable` (runtime only)
+    pub(crate) journal: Option<Journal>,
+    
+    /// Rows a stale index has yet to cover, when they are known
+    /// (runtime only)
+    pub(crate) index_lag: Option<IndexLag>,
+    
+    /// Query and probe counters (off by default; persisted in a section
+    /// when configured to be)
+    pub(crate) access: Option<AccessTracker>,
+    
+    /// Published directory this database was opened from (runtime only)
+    pub(crate) shared: Option<SharedHandle>,
+    
+    /// Metadata over `max_metadata_bytes`, under `external_metadata`
+    /// (saved in its own section)
+    pub(crate) external_metadata: BTreeMap<u32, serde_json::Value>,
+    
+    /// Ids given by callers for live entries (saved in its own section)
+    pub(crate) external_ids: ExternalIds,
+    
+    /// Receives build and save events (runtime only, silent by default)
+    pub(crate) progress: Option<Arc<dyn ProgressCallback>>,
+    
+    /// Recent inserts per second, for `EncodePolicy::Auto` (runtime only)
+    pub(crate) insert_rate: InsertRate,
+    
+    /// Sections changed since `layout` was recorded (runtime only)
+    pub(crate) dirty: DirtySections,
+    
+    /// Where the sections of the file last loaded or incrementally saved
+    /// lie (runtime only)
+    pub(crate) layout: Option<Layout>,
+    
+    /// Named collections beside this one (saved in their own section)
+    pub(crate) collections: BTreeMap<String, VectorDB>,
+    
+    /// Metadata keys indexed for filters so far (runtime only)
+    pub(crate) metadata_index: MetadataIndex,
+    
+    /// A background build is training from a snapshot of the rows, and
+    /// will install its index when done (runtime only)
+    pub(crate) build_in_flight: bool,
+    
+    /// Contiguous copy of the rows under [`IndexKind::Flat`] (runtime
+    /// only; loads copy the rows again)
+    pub(crate) flat_index: Option<FlatIndex>,
 }
 
 impl VectorDB {
@@ -25,18 +267,101 @@ impl VectorDB {
         config.validate()?;
         
         Ok(Self {
+            vectors: VectorStorage::new(config.raw_precision, config.dimensions),
             config,
-            vectors: Vec::new(),
             quantized: None,
             ivf_index: None,
-            metadata: HashMap::new(),
+            metadata: BTreeMap::new(),
             next_id: 0,
             index_built: false,
+            attributes: BTreeMap::new(),
+            deleted: BTreeSet::new(),
+            last_build: None,
+            admission: None,
+            generation: 0,
+            query_cache: None,
+            latency: None,
+            sections: Vec::new(),
+            load_warnings: Vec::new(),
+            seq: 0,
+            changelog: None,
+            journal: None,
+            index_lag: None,
+            access: None,
+            shared: None,
+            external_metadata: BTreeMap::new(),
+            external_ids: ExternalIds::default(),
+            progress: None,
+            insert_rate: InsertRate::default(),
+            dirty: DirtySections::ALL,
+            layout: None,
+            collections: BTreeMap::new(),
+            metadata_index: MetadataIndex::default(),
+            build_in_flight: false,
+            flat_index: None,
         })
     }
     
+    /// Assemble a database from stored vectors and pretrained index parts.
+    ///
+    /// The parts are checked against each other and the collection first;
+    /// every broken constraint is listed in
+    /// [`KhadyotaError::IncompatibleComponents`]. With an IVF index the
+    /// database is ready to search.
+    pub fn from_parts(
+        config: Config,
+        vectors: Vec<Vec<f32>>,
+        quantized: Option<QuantizedVectors>,
+        ivf_index: Option<IVFIndex>,
+    ) -> Result<Self> {
+        let mut db = Self::new(config)?;
+        if let Some(vector) = vectors.iter().find(|v| v.len() != db.config.dimensions) {
+            return Err(crate::error::KhadyotaError::DimensionMismatch {
+                expected: db.config.dimensions,
+                got: vector.len(),
+            });
+        }
+        vectors.iter().try_for_each(|vector| db.check_row(vector))?;
+        CompatibilityReport::check(&db.config, vectors.len(), quantized.as_ref(), ivf_index.as_ref())
+            .into_result()?;
+        
+        db.next_id = vectors.len() as u32;
+        let vectors = vectors.into_iter().map(|vector| db.stored_row(vector)).collect();
+        db.vectors = VectorStorage::from_rows(vectors, db.config.raw_precision, db.config.dimensions);
+        db.quantized = quantized;
+        db.index_built = ivf_index.is_some();
+        db.ivf_index = ivf_index;
+        Ok(db)
+    }
+    
+    /// Check the stored index parts against the collection and each other
+    pub fn check_compatibility(&self) -> CompatibilityReport {
+        CompatibilityReport::check(
+            &self.config,
+            self.vectors.len(),
+            self.quantized.as_ref(),
+            self.ivf_index.as_ref(),
+        )
+    }
+    
     /// Insert a vector with optional metadata
     pub fn insert(&mut self, vector: Vec<f32>, metadata: Option<serde_json::Value>) -> Result<u32> {
+        self.insert_opts(vector, InsertOptions {
+            metadata,
+            ..Default::default()
+        })
+    }
+    
+    /// Insert a vector with explicit options.
+    ///
+    /// Ids are dense: an explicit id must be the next free id, or an
+    /// existing id when `upsert` is set, in which case the vector, metadata
+    /// and attributes are all replaced.
+    ///
+    /// Into a built index, the row is encoded at once or left for the
+    /// next build as [`Config::encode_policy`] decides; searches find it
+    /// either way.
+    pub fn insert_opts(&mut self, vector: Vec<f32>, opts: InsertOptions) -> Result<u32> {
         if vector.len() != self.config.dimensions {
             return Err(crate::error::KhadyotaError::DimensionMismatch {
                 expected: self.config.dimensions,
@@ -44,259 +369,1368 @@ impl VectorDB {
             });
         }
         
-        let id = self.next_id;
-        self.vectors.push(vector);
+        opts.validate(self)?;
+        self.check_row(&vector)?;
+        let vector = self.stored_row(vector);
         
-        if let Some(meta) = metadata {
-            self.metadata.insert(id, meta);
+        let rows_before = self.next_id;
+        let id = opts
+            .id
+            .or_else(|| opts.external_id.as_ref().and_then(|e| self.external_ids.id_of(e)))
+            .unwrap_or(self.next_id);
+        let attributes = opts.attributes();
+        self.journal_next(|db| vec![ChangeOp::Put {
+            id,
+            vector: vector.clone(),
+            metadata: opts.metadata.clone(),
+            attributes: (attributes != EntryAttributes::default()).then(|| attributes.clone()),
+            index: None,
+            external_id: opts.external_id.clone().or_else(|| db.external_ids.external(id).cloned()),
+        }])?;
+        
+        let eager = self.encode_on_insert(1);
+        self.mark_dirty(&[PersistSection::Vectors]);
+        if id < self.next_id {
+            self.vectors.set(id, vector);
+            self.attributes.remove(&id);
+            self.deleted.remove(&id);
+        } else {
+            self.vectors.push(vector);
+            self.next_id += 1;
         }
         
-        self.next_id += 1;
-        self.index_built = false; // Need to rebuild index
+        self.set_metadata(id, opts.metadata);
+        if attributes != EntryAttributes::default() {
+            self.attributes.insert(id, attributes);
+        }
+        if opts.external_id.is_some() {
+            self.bind_external_id(id, opts.external_id);
+        }
+        
+        let clusters = if eager {
+            let replaced: BTreeSet<u32> = (id < rows_before).then_some(id).into_iter().collect();
+            let reencode: Vec<u32> = replaced.iter().copied().collect();
+            self.maintain_index(rows_before, &reencode, &replaced)
+        } else {
+            self.mark_index_stale(rows_before, [id]);
+            Default::default()
+        };
+        self.generation += 1;
+        self.log_change(|db| vec![db.put_op(id, eager.then(|| db.index_entry(id, &clusters)))])?;
+        if opts.durable {
+            self.sync_journal()?;
+        }
         
         Ok(id)
     }
     
+    /// Insert a vector into a built index without invalidating it.
+    ///
+    /// The vector is PQ-encoded with the existing codec and appended to
+    /// its nearest cluster; centroids and codebooks are not retrained, so
+    /// call `build_index()` once enough data has drifted from them. A
+    /// flat index copies the row.
+    pub fn add_to_index(&mut self, vector: Vec<f32>, metadata: Option<serde_json::Value>) -> Result<u32> {
+        if !self.index_built || !self.has_index() {
+            return Err(crate::error::KhadyotaError::IndexNotBuilt);
+        }
+        
+        let mut changeset = ChangeSet::new();
+        changeset.insert(vector, metadata);
+        match self.apply_changeset(changeset) {
+            Ok(report) => Ok(report.inserted[0]),
+            Err(crate::error::KhadyotaError::InvalidChange { source, .. }) => Err(*source),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// Remove an entry: it stops appearing in searches and `get()`.
+    ///
+    /// The row is tombstoned rather than reclaimed, so ids stay dense. A
+    /// built index stays built; the id is dropped from its inverted list.
+    pub fn delete(&mut self, id: u32) -> Result<()> {
+        let mut changeset = ChangeSet::new();
+        changeset.delete(id);
+        self.apply_one(changeset)
+    }
+    
+    /// Replace an entry's vector and metadata, keeping its attributes.
+    ///
+    /// A built index stays built: the vector is re-encoded and moved to
+    /// its nearest cluster without retraining.
+    pub fn update(&mut self, id: u32, vector: Vec<f32>, metadata: Option<serde_json::Value>) -> Result<()> {
+        let mut changeset = ChangeSet::new();
+        changeset.update(id, vector, metadata);
+        self.apply_one(changeset)
+    }
+    
+    /// Apply a one-op changeset, reporting its error unwrapped
+    fn apply_one(&mut self, changeset: ChangeSet) -> Result<()> {
+        match self.apply_changeset(changeset) {
+            Ok(_) => Ok(()),
+            Err(crate::error::KhadyotaError::InvalidChange { source, .. }) => Err(*source),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// Options recorded for an entry at insert time, if any were set
+    pub fn attributes(&self, id: u32) -> Option<&EntryAttributes> {
+        self.attributes.get(&id)
+    }
+    
     /// Build the search index (PQ + IVF)
     pub fn build_index(&mut self) -> Result<()> {
+        if self.build_in_flight {
+            return Err(crate::error::KhadyotaError::BuildInProgress);
+        }
         if self.vectors.is_empty() {
             return Err(crate::error::KhadyotaError::InvalidConfig(
                 "Cannot build index with no vectors".to_string()
             ));
         }
         
-        println!("\n=== Building Search Index ===");
-        println!("Vectors: {}", self.vectors.len());
-        println!("Dimensions: {}", self.config.dimensions);
+        let progress = self.progress.clone().unwrap_or_else(|| Arc::new(Silent));
+        progress.on_event(BuildEvent::IndexBuildStarted {
+            vectors: self.vectors.len(),
+            dimensions: self.config.dimensions,
+        });
+        if self.config.index == IndexKind::Flat {
+            return self.install_flat_index(progress.as_ref());
+        }
         
-        // Step 1: Train and apply Product Quantization
-        if self.config.use_pq {
-            println!("\n[1/2] Training Product Quantization...");
-            let pq_codec = PQCodec::train(&self.vectors, self.config.pq_subvectors)?;
-            
-            let mut quantized = QuantizedVectors::new(pq_codec);
-            for vector in &self.vectors {
-                quantized.add(vector.clone());
+        let rows = self.vectors.as_rows();
+        let trained = Self::train_index(&self.config, &rows, progress.as_ref())?;
+        self.install_index(trained, Vec::new(), progress.as_ref())
+    }
+    
+    /// Train a quantizer and IVF index for `config` over `rows`, without
+    /// touching any database, so it can run on a snapshot while the
+    /// database keeps serving. Fails with
+    /// [`KhadyotaError::Cancelled`](crate::error::KhadyotaError::Cancelled)
+    /// once `progress` reports cancellation.
+    pub(crate) fn train_index(config: &Config, rows: &[Vec<f32>], progress: &dyn ProgressCallback) -> Result<TrainedIndex> {
+        let training = training_sample(config, rows);
+        let cancelled = || {
+            if progress.cancelled() {
+                Err(crate::error::KhadyotaError::Cancelled)
+            } else {
+                Ok(())
             }
-            
-            self.quantized = Some(quantized);
-            println!("✓ PQ training complete");
-        }
+        };
+        
+        // Step 1: Train and apply the quantizer, unless it is trained on
+        // residuals from the clusters
+        let residuals = config.uses_residuals();
+        let quantizer: Option<Quantizer> = match config.quantizer {
+            QuantizerKind::PQ if residuals => None,
+            QuantizerKind::PQ if config.use_opq => {
+                let opq_codec = OPQCodec::train_with_params(
+                    &training,
+                    config.pq_subvectors,
+                    config.seed,
+                    config.metric,
+                    config.pq_bits,
+                    &config.pq_kmeans,
+                    progress,
+                )?;
+                progress.on_event(BuildEvent::PqTrained);
+                Some(opq_codec.into())
+            }
+            QuantizerKind::PQ => {
+                let pq_codec = PQCodec::train_with_params(
+                    &training,
+                    config.pq_subvectors,
+                    config.seed,
+                    config.metric,
+                    config.pq_bits,
+                    &config.pq_kmeans,
+                    progress,
+                )?;
+                progress.on_event(BuildEvent::PqTrained);
+                Some(pq_codec.into())
+            }
+            QuantizerKind::SQ8 => Some(SQCodec::train_for_metric(&training, config.metric)?.into()),
+            QuantizerKind::None => None,
+        };
+        let mut quantized = quantizer.map(|quantizer| {
+            let mut quantized = QuantizedVectors::new(quantizer);
+            quantized.add_batch_from(rows.iter().map(Vec::as_slice));
+            quantized
+        });
+        cancelled()?;
         
-        // Step 2: Build IVF index
-        println!("\n[2/2] Building IVF Index...");
-        let mut ivf = IVFIndex::new(
-            self.config.dimensions,
-            self.config.num_clusters,
-            self.config.num_probe,
+        // Step 2: Build IVF index, with no more clusters than training rows
+        let num_clusters = config.num_clusters.clamp(1, training.len());
+        if num_clusters < config.num_clusters {
+            progress.on_event(BuildEvent::ClustersClamped {
+                requested: config.num_clusters,
+                used: num_clusters,
+            });
+        }
+        let mut ivf = IVFIndex::with_metric(
+            config.dimensions,
+            num_clusters,
+            config.num_probe.clamp(1, num_clusters),
+            config.metric,
         );
+        ivf.set_assign_replicas(config.ivf_assign_replicas);
+        ivf.set_kmeans_params(config.ivf_kmeans);
         
-        ivf.build(&self.vectors, self.config.num_clusters);
+        ivf.build_sampled(rows, &training, num_clusters, config.seed, progress);
+        cancelled()?;
+        if residuals {
+            quantized = Some(Self::train_residual_codes(config, &ivf, rows, &training, progress)?);
+            progress.on_event(BuildEvent::PqTrained);
+        }
         
-        let stats = ivf.stats();
-        println!("\n{}", stats);
+        Ok(TrainedIndex {
+            quantized,
+            ivf,
+            indexed_rows: rows.len() as u32,
+        })
+    }
+    
+    /// Put `trained` in place of the current index. `changed` lists rows
+    /// written since the rows it was trained on were taken, which are
+    /// then encoded and assigned as inserts into a built index are.
+    pub(crate) fn install_index(
+        &mut self,
+        trained: TrainedIndex,
+        changed: Vec<u32>,
+        progress: &dyn ProgressCallback,
+    ) -> Result<()> {
+        let TrainedIndex { quantized, mut ivf, indexed_rows } = trained;
+        if !self.deleted.is_empty() {
+            ivf.remove_ids(&self.deleted);
+            ivf.mark_built();
+        }
         
+        let previous = self.take_index_state();
+        self.quantized = quantized;
         self.ivf_index = Some(ivf);
         self.index_built = true;
+        if !changed.is_empty() {
+            let rows = changed.len();
+            self.mark_index_stale(indexed_rows, changed);
+            self.catch_up_index(rows);
+        }
+        self.journal_or_restore(Some(previous), |db| vec![ChangeOp::IndexSwap {
+            ivf: db.ivf_index.clone().unwrap(),
+            quantized: db.quantized.clone(),
+        }])?;
+        let stats = self.ivf_index.as_ref().unwrap().stats();
         
-        println!("\n✓ Index built successfully!\n");
+        self.mark_dirty(&[PersistSection::Codes, PersistSection::Index, PersistSection::Metadata]);
+        if let Some(access) = &self.access {
+            access.reset_clusters();
+        }
+        self.generation += 1;
+        self.last_build = Some(SystemTime::now());
+        self.log_change(|db| vec![ChangeOp::IndexSwap {
+            ivf: db.ivf_index.clone().unwrap(),
+            quantized: db.quantized.clone(),
+        }])?;
         
+        progress.on_event(BuildEvent::IndexBuilt { stats });
         Ok(())
     }
     
-    /// Search for k nearest neighbors
-    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
-        if query.len() != self.config.dimensions {
-            return Err(crate::error::KhadyotaError::DimensionMismatch {
-                expected: self.config.dimensions,
-                got: query.len(),
-            });
-        }
+    /// Put a flat index over the current rows in place of any index
+    pub(crate) fn install_flat_index(&mut self, progress: &dyn ProgressCallback) -> Result<()> {
+        let previous = self.take_index_state();
+        self.flat_index = Some(self.flat_copy());
+        self.index_built = true;
+        self.journal_or_restore(Some(previous), |_| vec![ChangeOp::FlatIndexBuilt])?;
         
-        if !self.index_built {
+        self.mark_dirty(&[PersistSection::Codes, PersistSection::Index, PersistSection::Metadata]);
+        self.generation += 1;
+        self.last_build = Some(SystemTime::now());
+        self.log_change(|_| vec![ChangeOp::FlatIndexBuilt])?;
+        
+        progress.on_event(BuildEvent::FlatIndexBuilt { rows: self.vectors.len() });
+        Ok(())
+    }
+    
+    /// The rows as a [`FlatIndex`]
+    pub(crate) fn flat_copy(&self) -> FlatIndex {
+        FlatIndex::from_rows(self.vectors.iter(), self.config.dimensions)
+    }
+    
+    /// Split oversized IVF clusters and drop empty ones once the largest
+    /// holds more than [`Config::max_cluster_imbalance`] times the mean,
+    /// without retraining anything else; see [`IVFIndex::rebalance`].
+    /// Residual codes of the rows whose clusters changed are re-encoded
+    /// against their new centroids. Does nothing, reporting no splits,
+    /// while the index is within the bound.
+    pub fn optimize_index(&mut self) -> Result<RebalanceReport> {
+        if !self.is_searchable() {
+            return Err(crate::error::KhadyotaError::IndexNotBuilt);
+        }
+        if self.ivf_index.is_none() {
             return Err(crate::error::KhadyotaError::IndexNotBuilt);
         }
+        // Rebalancing works in place, so keep a copy to put back
+        let previous = self.journal.is_some().then(|| self.clone_index_state());
+        let ivf = self.ivf_index.as_mut().unwrap();
+        let report = ivf.rebalance(&self.vectors.as_rows(), self.config.max_cluster_imbalance);
+        if report.splits == 0 && report.dropped == 0 {
+            return Ok(report);
+        }
         
-        // Use IVF + PQ search if available
-        if let (Some(ivf), Some(quantized)) = (&self.ivf_index, &self.quantized) {
-            self.search_with_index(query, k, ivf, quantized)
-        } else {
-            // Fallback to linear scan
-            self.search_linear(query, k)
+        if self.residual_codes() {
+            self.encode_residuals(report.moved.iter().copied());
+        }
+        self.journal_or_restore(previous, |db| vec![ChangeOp::IndexSwap {
+            ivf: db.ivf_index.clone().unwrap(),
+            quantized: db.quantized.clone(),
+        }])?;
+        self.mark_dirty(&[PersistSection::Codes, PersistSection::Index, PersistSection::Metadata]);
+        if let Some(access) = &self.access {
+            access.reset_clusters();
         }
+        self.generation += 1;
+        self.log_change(|db| vec![ChangeOp::IndexSwap {
+            ivf: db.ivf_index.clone().unwrap(),
+            quantized: db.quantized.clone(),
+        }])?;
+        Ok(report)
     }
     
-    /// Search using IVF + PQ
-    fn search_with_index(
+    /// Search for k nearest neighbors
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        self.search_with_params(query, k, &SearchParams::default())
+    }
+    
+    /// Search with per-query parameters, validated before any work is done
+    pub fn search_with_params(
         &self,
         query: &[f32],
         k: usize,
-        ivf: &IVFIndex,
-        quantized: &QuantizedVectors,
+        params: &SearchParams,
     ) -> Result<Vec<SearchResult>> {
-        // Step 1: Probe IVF to get candidate clusters
-        let clusters = ivf.probe(query);
-        let candidates = ivf.get_candidates(&clusters);
+        self.search_admitting(query, k, params, true)
+    }
+    
+    /// [`VectorDB::search_with_params`] for one query of a batch whose
+    /// caller holds an admission slot for all of them
+    #[cfg(feature = "arrow")]
+    pub(crate) fn search_in_batch(&self, query: &[f32], k: usize, params: &SearchParams) -> Result<Vec<SearchResult>> {
+        self.search_admitting(query, k, params, false)
+    }
+    
+    fn search_admitting(&self, query: &[f32], k: usize, params: &SearchParams, admit: bool) -> Result<Vec<SearchResult>> {
+        let query = self.check_prefix_query(query)?;
+        params.validate(self)?;
         
-        // Step 2: Precompute PQ distance table
-        let dist_table = quantized.precompute_distance_table(query);
+        let start = self.latency.as_ref().map(|_| Instant::now());
+        let results = self.search_checked(&query, k, params, admit)?;
+        self.record_latency(start);
+        Ok(results)
+    }
+    
+    /// Search among the entries `filter` admits, applied to candidates
+    /// before they are scored: `db.metadata_filter("category", &json!("A"))`
+    /// combined with `.and(db.metadata_range_filter("year", 2020.0..))`,
+    /// say. Shorthand for [`VectorDB::search_with_params`] with only the
+    /// filter set.
+    pub fn search_filtered(&self, query: &[f32], k: usize, filter: CandidateFilter) -> Result<Vec<SearchResult>> {
+        self.search_with_params(query, k, &SearchParams::builder().filter(filter).build())
+    }
+
+    /// The `k` entries nearest the one stored under `id`, leaving out `id`
+    /// itself. Uses the original vector, or decodes its codes if the row
+    /// cannot be read, then searches as [`VectorDB::search`] would, so an
+    /// unbuilt index is refused the same way.
+    pub fn search_by_id(&self, id: u32, k: usize) -> Result<Vec<SearchResult>> {
+        if !self.contains(id) {
+            return Err(crate::error::KhadyotaError::VectorNotFound(id));
+        }
+        let query = match self.vectors.get(id) {
+            Some(vector) => vector.to_vec(),
+            None => self.decoded_row(id).ok_or(crate::error::KhadyotaError::VectorNotFound(id))?,
+        };
+        self.search_with_params(&query, k, &SearchParams::builder().exclude([id]).build())
+    }
+
+    /// Search a validated query, through the cache and, if `admit` is
+    /// set, admission control
+    fn search_checked(&self, query: &[f32], k: usize, params: &SearchParams, admit: bool) -> Result<Vec<SearchResult>> {
+        let admit = || if admit { self.admit() } else { Ok(None) };
+        let Some(cache) = self.query_cache.as_ref().filter(|_| params.filter.is_none()) else {
+            let _permit = admit()?;
+            return self.search_validated(query, k, params);
+        };
         
-        // Step 3: Compute distances to candidates
-        let mut scored: Vec<(u32, f32)> = candidates
-            .iter()
-            .map(|&vec_id| {
-                let distance = quantized.table_lookup_distance(&dist_table, vec_id);
-                (vec_id, distance)
-            })
-            .collect();
+        let fingerprint = params.fingerprint();
+        if let Some(results) = cache.get(query, k, fingerprint, self.generation) {
+            self.record_query();
+            return Ok(results);
+        }
         
-        // Step 4: Sort and take top-k
-        scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        scored.truncate(k);
+        let _permit = admit()?;
+        let results = self.search_validated(query, k, params)?;
+        cache.insert(query, k, fingerprint, self.generation, &results);
+        Ok(results)
+    }
+    
+    /// Record the time since `start`, if latency recording was on when it
+    /// was taken
+    pub(crate) fn record_latency(&self, start: Option<Instant>) -> Duration {
+        let Some(start) = start else {
+            return Duration::ZERO;
+        };
+        let elapsed = start.elapsed();
+        if let Some(latency) = &self.latency {
+            latency.record(elapsed);
+        }
+        elapsed
+    }
+    
+    /// Record per-query latencies of `search` and `batch_search`, or stop
+    /// recording. Turning recording on again starts from an empty histogram.
+    pub fn set_latency_recording(&mut self, enabled: bool) {
+        self.latency = enabled.then(LatencyRecorder::new);
+    }
+    
+    /// Latencies recorded so far; empty if recording is off.
+    ///
+    /// Cache hits are included, since they are what callers observe.
+    pub fn latency_histogram(&self) -> Histogram {
+        self.latency.as_ref().map(|l| l.snapshot()).unwrap_or_default()
+    }
+    
+    /// Clear recorded latencies, keeping recording on
+    pub fn reset_latency(&self) {
+        if let Some(latency) = &self.latency {
+            latency.reset();
+        }
+    }
+    
+    /// Report index builds and saves to `callback`, or go back to silence
+    /// with `None`. The library never prints; see
+    /// [`PrintProgress`](crate::PrintProgress) for the old console output.
+    pub fn set_progress_callback(&mut self, callback: Option<Arc<dyn ProgressCallback>>) {
+        self.progress = callback;
+    }
+    
+    /// Cache results of repeated identical searches, or turn caching off
+    /// with `None`.
+    ///
+    /// Entries are keyed by the exact query, `k` and search parameters,
+    /// and any insert, update or index build invalidates them all.
+    pub fn set_query_cache(&mut self, config: Option<QueryCacheConfig>) {
+        self.query_cache = config.map(QueryCache::new);
+    }
+    
+    /// Result cache counters, if caching is enabled
+    pub fn query_cache_stats(&self) -> Option<QueryCacheStats> {
+        self.query_cache.as_ref().map(|c| c.stats())
+    }
+    
+    /// Drop every cached result, keeping the counters
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.query_cache {
+            cache.clear();
+        }
+    }
+    
+    /// Limit concurrent searches, or remove the limit with `None`.
+    ///
+    /// Searches queue for a slot in arrival order. One called from a rayon
+    /// worker thread is rejected rather than queued when no slot is free;
+    /// batch searches take their one slot before fanning out.
+    pub fn set_admission_control(&mut self, config: Option<AdmissionConfig>) -> Result<()> {
+        self.admission = config.map(AdmissionController::new).transpose()?;
+        Ok(())
+    }
+    
+    /// Probe `num_probe` clusters in later searches that do not say
+    /// otherwise, and in indexes built later. The built index caps it at
+    /// its number of clusters; both are saved with the database.
+    pub fn set_num_probe(&mut self, num_probe: usize) -> Result<()> {
+        if num_probe == 0 {
+            return Err(crate::error::KhadyotaError::InvalidConfig("num_probe must be > 0".to_string()));
+        }
+        self.config.num_probe = num_probe;
+        if let Some(ivf) = &mut self.ivf_index {
+            ivf.set_num_probe(num_probe);
+            self.mark_dirty(&[PersistSection::Index]);
+        }
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// The active concurrency limiter, if any
+    pub fn admission(&self) -> Option<&AdmissionController> {
+        self.admission.as_ref()
+    }
+    
+    /// Admission counters, if a limiter is configured
+    pub fn admission_stats(&self) -> Option<AdmissionStats> {
+        self.admission.as_ref().map(|a| a.stats())
+    }
+    
+    /// Cluster sizes and balance of the IVF index, including how far
+    /// deletes have thinned it since it was built
+    pub fn index_stats(&self) -> Option<IVFStats> {
+        self.ivf_index.as_ref().map(|ivf| ivf.stats())
+    }
+    
+    pub(crate) fn admit(&self) -> Result<Option<crate::admission::AdmissionPermit<'_>>> {
+        self.admission.as_ref().map(|a| a.acquire()).transpose()
+    }
+    
+    /// The query a search scores with, once it meets the input contract
+    /// at full width and the index is built
+    pub(crate) fn check_query<'a>(&self, query: &'a [f32]) -> Result<Cow<'a, [f32]>> {
+        let query = self.check_prefix_query(query)?;
+        self.require_full_width(&query)?;
+        Ok(query)
+    }
+    
+    /// [`VectorDB::check_query`], also accepting a Matryoshka prefix
+    pub(crate) fn check_prefix_query<'a>(&self, query: &'a [f32]) -> Result<Cow<'a, [f32]>> {
+        let query = self.prepared_query(query)?;
+        
+        // A first build still training leaves searches to scan
+        if !self.is_searchable() && !self.build_in_flight {
+            return Err(crate::error::KhadyotaError::IndexNotBuilt);
+        }
         
-        // Step 5: Build results
-        Ok(scored
+        Ok(query)
+    }
+    
+    /// Search pipeline; `params` must already be validated against `self`
+    pub(crate) fn search_validated(&self, query: &[f32], k: usize, params: &SearchParams) -> Result<Vec<SearchResult>> {
+        self.search_in(query, k, params, None, &mut SearchScratch::default())
+    }
+    
+    /// [`VectorDB::search_validated`] working in `scratch`, and probing an
+    /// IVF + PQ index by `centroid_distances` when given: the query's row
+    /// of [`IVFIndex::centroid_distance_matrix`]
+    pub(crate) fn search_in(
+        &self,
+        query: &[f32],
+        k: usize,
+        params: &SearchParams,
+        centroid_distances: Option<&[f32]>,
+        scratch: &mut SearchScratch,
+    ) -> Result<Vec<SearchResult>> {
+        #[cfg(test)]
+        crate::contract::SCORED_QUERY.with(|seen| *seen.borrow_mut() = Some(query.to_vec()));
+        if let Some(dims) = params.coarse_dims.filter(|&dims| dims < query.len()) {
+            return self.search_refined(query, dims, k, params);
+        }
+        self.record_query();
+        match (&self.ivf_index, &self.quantized, &self.flat_index) {
+            // Use IVF + PQ search if available
+            (Some(ivf), Some(quantized), _) if self.tables_score(query) => {
+                // Step 1: Probe IVF to get candidate clusters
+                self.probe_into(query, ivf, params, centroid_distances, &mut scratch.candidates)?;
+                self.search_with_index(query, k, quantized, params, scratch)?
+            }
+            // Exact scan over the probed clusters when a probe count is
+            // given, or of a prefix the PQ tables cannot score
+            (Some(ivf), quantized, _) if params.num_probe.is_some() || quantized.is_some() => {
+                let candidates = self.candidates(query, ivf, params)?;
+                scratch.scored = self.score_exact_keys(query, candidates, params)?;
+            }
+            // Every row the flat index covers, exactly
+            (_, _, Some(flat)) => scratch.scored = self.search_flat_keys(query, flat, params)?,
+            // Fallback to linear scan, which covers unencoded rows already
+            _ => return Ok(self.top_results_by_key(self.search_linear_keys(query, params)?, k, params)),
+        };
+        // Rows a lagging index does not cover yet are scored exactly
+        scratch.scored.extend(self.score_exact_keys(query, self.unencoded_ids(params)?, params)?);
+        
+        let metric = params.metric.unwrap_or(self.config.metric);
+        Ok(self.top_results_in(&mut scratch.scored, k, params, |key| key_to_distance(metric, key), &mut scratch.top))
+    }
+    
+    /// Drop expired entries, then take the `k` nearest as results
+    pub(crate) fn top_results(&self, scored: Vec<(u32, f32)>, k: usize, params: &SearchParams) -> Vec<SearchResult> {
+        self.top_results_with(scored, k, params, |distance| distance)
+    }
+    
+    /// [`VectorDB::top_results`] from [`distance_key`](crate::distance::distance_key)s
+    /// of the search's metric, turned into distances for the `k` kept
+    pub(crate) fn top_results_by_key(&self, scored: Vec<(u32, f32)>, k: usize, params: &SearchParams) -> Vec<SearchResult> {
+        let metric = params.metric.unwrap_or(self.config.metric);
+        self.top_results_with(scored, k, params, |key| key_to_distance(metric, key))
+    }
+    
+    fn top_results_with(
+        &self,
+        mut scored: Vec<(u32, f32)>,
+        k: usize,
+        params: &SearchParams,
+        distance: impl Fn(f32) -> f32,
+    ) -> Vec<SearchResult> {
+        self.top_results_in(&mut scored, k, params, distance, &mut TopK::default())
+    }
+    
+    /// [`VectorDB::top_results_with`] selecting through `top`, leaving
+    /// `scored` empty
+    fn top_results_in(
+        &self,
+        scored: &mut Vec<(u32, f32)>,
+        k: usize,
+        params: &SearchParams,
+        distance: impl Fn(f32) -> f32,
+        top: &mut TopK,
+    ) -> Vec<SearchResult> {
+        if !self.attributes.is_empty() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            scored.retain(|(id, _)| !self.is_expired(*id, now));
+        }
+        
+        top.select(scored.drain(..), k)
             .into_iter()
-            .map(|(id, distance)| SearchResult {
+            .map(|(id, key)| SearchResult {
                 id,
-                distance,
-                metadata: self.metadata.get(&id).cloned(),
+                distance: distance(key),
+                metadata: match (params.include_metadata, params.include_external_metadata) {
+                    (false, _) => None,
+                    (true, false) => self.metadata.get(&id).cloned(),
+                    (true, true) => self.metadata_value(id).cloned(),
+                },
+                external_id: self.external_ids.external(id).cloned(),
             })
-            .collect())
+            .collect()
     }
     
-    /// Fallback linear scan (for small datasets or when index not built)
-    fn search_linear(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
-        use crate::distance::compute_distance;
+    pub(crate) fn is_expired(&self, id: u32, now_unix_secs: u64) -> bool {
+        self.attributes
+            .get(&id)
+            .and_then(|a| a.expires_at_unix_secs)
+            .is_some_and(|expires| now_unix_secs >= expires)
+    }
+    
+    /// Candidate ids from the probed clusters, minus exclusions and those
+    /// the filter rejects
+    pub(crate) fn candidates(&self, query: &[f32], ivf: &IVFIndex, params: &SearchParams) -> Result<Vec<u32>> {
+        let (clusters, candidates) = self.probed_candidates(query, ivf, params)?;
+        self.record_scan(&clusters, candidates.len());
+        Ok(candidates)
+    }
+    
+    /// Clusters a search probes and the candidates listed in them, minus
+    /// exclusions and those the filter rejects, without counting the scan
+    pub(crate) fn probed_candidates(
+        &self,
+        query: &[f32],
+        ivf: &IVFIndex,
+        params: &SearchParams,
+    ) -> Result<(Vec<usize>, Vec<u32>)> {
+        let clusters = self.probe_clusters(ivf, params, |num_probe| ivf.probe_n(query, num_probe));
+        let mut candidates = ivf.get_candidates(&clusters);
+        self.retain_candidates(&mut candidates, params)?;
+        Ok((clusters, candidates))
+    }
+    
+    /// Clusters a search probes, from `probe_n` ranking the given number
+    /// of nearest clusters, nearest first
+    fn probe_clusters(&self, ivf: &IVFIndex, params: &SearchParams, probe_n: impl FnOnce(usize) -> Vec<usize>) -> Vec<usize> {
+        let num_probe = params.num_probe.unwrap_or(ivf.num_probe());
+        match self.config.probe_compensation {
+            Some(compensation) => ivf.compensate(probe_n((num_probe * compensation.max_factor).max(num_probe)), num_probe),
+            None => probe_n(num_probe),
+        }
+    }
+    
+    /// Drop exclusions and those the filter rejects from probed
+    /// `candidates`, then cap them at the search's maximum
+    fn retain_candidates(&self, candidates: &mut Vec<u32>, params: &SearchParams) -> Result<()> {
+        // Rows pending in a lagging index are listed under their old vectors
+        let pending = self.index_lag.as_ref().map(|lag| &lag.pending);
+        if !params.exclude.is_empty() || !self.deleted.is_empty() || pending.is_some() || params.filter.is_some() {
+            let mut filter = params.filter_pass();
+            candidates.retain(|&id| {
+                !params.exclude.contains(&id)
+                    && !self.deleted.contains(&id)
+                    && !pending.is_some_and(|pending| pending.contains(&id))
+                    && filter.admits(id)
+            });
+            filter.finish()?;
+        }
+        if let Some(max) = params.max_candidates {
+            candidates.truncate(max);
+        }
+        
+        Ok(())
+    }
+    
+    /// Exact distances from `query` to the stored rows of `ids`. A row
+    /// that cannot be read back, such as a spilled one whose file has
+    /// gone, fails with [`KhadyotaError::VectorNotFound`].
+    ///
+    /// [`KhadyotaError::VectorNotFound`]: crate::error::KhadyotaError::VectorNotFound
+    pub(crate) fn score_exact(&self, query: &[f32], ids: Vec<u32>, params: &SearchParams) -> Result<Vec<(u32, f32)>> {
+        let metric = params.metric.unwrap_or(self.config.metric);
+        let mut scored = self.score_exact_keys(query, ids, params)?;
+        scored.iter_mut().for_each(|(_, key)| *key = key_to_distance(metric, *key));
+        Ok(scored)
+    }
+    
+    /// [`VectorDB::score_exact`] as [`distance_key`]s, Euclidean
+    /// distances left squared
+    pub(crate) fn score_exact_keys(&self, query: &[f32], ids: Vec<u32>, params: &SearchParams) -> Result<Vec<(u32, f32)>> {
+        let key = self.exact_key_fn(query, params.metric.unwrap_or(self.config.metric));
+        // A Matryoshka prefix is scored against the same prefix of each row
+        ids.into_iter()
+            .map(|id| {
+                let row = self.vectors.get(id).ok_or(crate::error::KhadyotaError::VectorNotFound(id))?;
+                Ok((id, key(query, &row[..query.len()])))
+            })
+            .collect()
+    }
+    
+    /// Whether exact cosine distances for `query` can skip the norms:
+    /// under [`Config::normalize`], stored rows and full-width queries
+    /// are unit length
+    pub(crate) fn scores_unit_cosine(&self, query: &[f32], metric: DistanceMetric) -> bool {
+        metric == DistanceMetric::Cosine && self.config.normalizes() && query.len() == self.config.dimensions
+    }
+    
+    /// [`distance_key`] for `query` against stored rows, through the dot
+    /// product kernel where [`VectorDB::scores_unit_cosine`] allows
+    pub(crate) fn exact_key_fn(&self, query: &[f32], metric: DistanceMetric) -> impl Fn(&[f32], &[f32]) -> f32 + use<> {
+        let unit = self.scores_unit_cosine(query, metric);
+        move |a, b| if unit { unit_cosine_distance(a, b) } else { distance_key(a, b, metric) }
+    }
+    
+    /// [`VectorDB::candidates`] into `candidates`, probing by
+    /// `centroid_distances` when given: `query`'s row of
+    /// [`IVFIndex::centroid_distance_matrix`]
+    fn probe_into(
+        &self,
+        query: &[f32],
+        ivf: &IVFIndex,
+        params: &SearchParams,
+        centroid_distances: Option<&[f32]>,
+        candidates: &mut Vec<u32>,
+    ) -> Result<()> {
+        let clusters = match centroid_distances {
+            Some(row) => self.probe_clusters(ivf, params, |num_probe| ivf.probe_row(row, num_probe)),
+            None => self.probe_clusters(ivf, params, |num_probe| ivf.probe_n(query, num_probe)),
+        };
+        ivf.candidates_into(&clusters, candidates);
+        self.retain_candidates(candidates, params)?;
+        self.record_scan(&clusters, candidates.len());
+        Ok(())
+    }
+    
+    /// Search using IVF + PQ, optionally reranking with exact distances:
+    /// scores `scratch.candidates`, as [`VectorDB::probe_into`] left
+    /// them, into `scratch.scored`
+    fn search_with_index(
+        &self,
+        query: &[f32],
+        k: usize,
+        quantized: &QuantizedVectors,
+        params: &SearchParams,
+        scratch: &mut SearchScratch,
+    ) -> Result<()> {
+        let SearchScratch { candidates, keys, scan, scored, table, top } = scratch;
         
+        // Step 2: Precompute PQ distance tables, one per cluster for
+        // residual codes
+        let scorer = self.code_scorer_reusing(quantized, query, candidates, std::mem::take(table));
+        
+        // Step 3: Compute distances to candidates, as keys that rank
+        // the same, so Euclidean ones skip the sqrt
+        scorer.score_keys_into(candidates, keys, scan, scored);
+        
+        // Step 4: Re-score the best approximate candidates exactly
+        let metric = params.metric.unwrap_or(self.config.metric);
+        let mut reranked = Ok(());
+        if let Some(rerank) = params.rerank {
+            let ids = top.select(scored.drain(..), rerank.max(k)).into_iter().map(|(id, _)| id).collect();
+            reranked = self.score_exact_keys(query, ids, params).map(|exact| scored.extend(exact));
+        } else if scorer.metric != metric {
+            // A codec saved before its metric was recorded is Euclidean
+            for (_, key) in scored.iter_mut() {
+                *key = distance_to_key(metric, key_to_distance(scorer.metric, *key));
+            }
+        }
+        *table = scorer.into_table();
+        reranked
+    }
+    
+    /// Fallback linear scan (for small datasets or when index not built)
+    pub(crate) fn search_linear(&self, query: &[f32], params: &SearchParams) -> Result<Vec<(u32, f32)>> {
+        let metric = params.metric.unwrap_or(self.config.metric);
+        let mut scored = self.search_linear_keys(query, params)?;
+        scored.iter_mut().for_each(|(_, key)| *key = key_to_distance(metric, *key));
+        Ok(scored)
+    }
+    
+    /// [`VectorDB::search_linear`] as [`distance_key`]s, Euclidean
+    /// distances left squared
+    pub(crate) fn search_linear_keys(&self, query: &[f32], params: &SearchParams) -> Result<Vec<(u32, f32)>> {
+        let key = self.exact_key_fn(query, params.metric.unwrap_or(self.config.metric));
+        let mut filter = params.filter_pass();
         let mut scored: Vec<(u32, f32)> = self.vectors
             .iter()
             .enumerate()
-            .map(|(i, vector)| {
-                let distance = compute_distance(query, vector, self.config.metric);
-                (i as u32, distance)
+            .filter(|(i, _)| {
+                let id = *i as u32;
+                !params.exclude.contains(&id) && !self.deleted.contains(&id) && filter.admits(id)
             })
+            .map(|(i, vector)| (i as u32, key(query, &vector[..query.len()])))
             .collect();
         
-        scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        scored.truncate(k);
+        if let Some(max) = params.max_candidates {
+            scored.truncate(max);
+        }
+        filter.finish()?;
+        self.record_scan(&[], scored.len());
         
-        Ok(scored
-            .into_iter()
-            .map(|(id, distance)| SearchResult {
-                id,
-                distance,
-                metadata: self.metadata.get(&id).cloned(),
-            })
-            .collect())
+        Ok(scored)
     }
     
-    /// Save database to disk
+    /// Scan of the rows `flat` covers, as [`distance_key`]s, minus
+    /// exclusions, tombstones, rows written since it was copied and those
+    /// the filter rejects
+    fn search_flat_keys(&self, query: &[f32], flat: &FlatIndex, params: &SearchParams) -> Result<Vec<(u32, f32)>> {
+        let key = self.exact_key_fn(query, params.metric.unwrap_or(self.config.metric));
+        let pending = self.index_lag.as_ref().map(|lag| &lag.pending);
+        let listed = |id: u32| {
+            !params.exclude.contains(&id)
+                && !self.deleted.contains(&id)
+                && !pending.is_some_and(|pending| pending.contains(&id))
+        };
+        let scored = if params.filter.is_none() && params.max_candidates.is_none() {
+            flat.scan_keys(query, &key, &listed)
+        } else {
+            // Filters see rows one at a time, in order
+            let mut filter = params.filter_pass();
+            let mut scored: Vec<(u32, f32)> = flat
+                .rows()
+                .enumerate()
+                .map(|(id, row)| (id as u32, row))
+                .filter(|&(id, _)| listed(id) && filter.admits(id))
+                .map(|(id, row)| (id, key(query, &row[..query.len()])))
+                .collect();
+            if let Some(max) = params.max_candidates {
+                scored.truncate(max);
+            }
+            filter.finish()?;
+            scored
+        };
+        self.record_scan(&[], scored.len());
+        Ok(scored)
+    }
+    
+    /// Save database to disk.
+    ///
+    /// The output is deterministic: saving an unchanged database produces
+    /// byte-identical files across runs and platforms. Metadata is written
+    /// in id order, and nothing time- or host-dependent is recorded.
+    ///
+    /// Saving a durable database where it was opened is a
+    /// [`VectorDB::checkpoint`]: the file is replaced whole rather than
+    /// rewritten in place, and the journal is emptied.
     pub fn save(&self, path: &Path) -> Result<()> {
-        use std::fs::File;
-        
-        println!("Saving database to {:?}...", path);
-        
-        let file = File::create(path)?;
-        let mut writer = std::io::BufWriter::new(file);
+        let bytes = if self.saves_snapshot(path) {
+            self.write_snapshot(path)?
+        } else {
+            self.write_state(path, true)?
+        };
+        if let Some(progress) = &self.progress {
+            progress.on_event(BuildEvent::Saved { path: path.to_path_buf(), bytes });
+        }
+        Ok(())
+    }
+    
+    /// Write what [`VectorDB::save`] would put in a file to `writer`,
+    /// returning the number of bytes written. The bytes are the same as
+    /// the file's, so either can be read by [`VectorDB::load`] or
+    /// [`VectorDB::load_from`]. Writes are buffered, so `writer` need not
+    /// be; no `Saved` event is sent, having no path to name.
+    pub fn save_to<W: std::io::Write>(&self, writer: W) -> Result<u64> {
+        let (layout, _) = self.write_sections(writer, true, None)?;
+        Ok(layout.len)
+    }
+    
+    /// Write the saved-file layout to `path`, returning its size. Without
+    /// `inline_vectors` the rows are left out, for a caller that stores
+    /// them separately; the header still records how many there are.
+    pub(crate) fn write_state(&self, path: &Path, inline_vectors: bool) -> Result<u64> {
+        let (layout, _) = self.write_sections(std::fs::File::create(path)?, inline_vectors, None)?;
+        Ok(layout.len)
+    }
+    
+    /// Load database from disk.
+    ///
+    /// The file header, when present, must agree with the stored config;
+    /// files saved before headers existed load without the check. Files
+    /// of any format version from [`MIN_VERSION`] up are read, and the
+    /// next save writes the current [`VERSION`].
+    ///
+    /// [`MIN_VERSION`]: crate::storage::MIN_VERSION
+    /// [`VERSION`]: crate::storage::VERSION
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::read_state(path, None)
+    }
+    
+    /// Read a database from the bytes [`VectorDB::save_to`] or
+    /// [`VectorDB::save`] wrote, checked as `load()` checks a file. Reads
+    /// are buffered, so `reader` need not be. A database read this way
+    /// has no file for [`VectorDB::save_incremental`] to reuse.
+    pub fn load_from<R: std::io::Read>(reader: R) -> Result<Self> {
+        Self::read_from(reader, None, None)
+    }
+    
+    /// Read a file written by [`VectorDB::write_state`]. `rows` supplies
+    /// the vectors when they were left out of the file.
+    pub(crate) fn read_state(path: &Path, rows: Option<ColdVectors>) -> Result<Self> {
+        Self::read_from(std::fs::File::open(path)?, Some(path), rows)
+    }
+    
+    /// [`VectorDB::read_state`] from any reader. With the `path` it came
+    /// from, a database holding its rows records the file's layout for
+    /// incremental saves.
+    fn read_from(reader: impl std::io::Read, path: Option<&Path>, rows: Option<ColdVectors>) -> Result<Self> {
+        let inline = rows.is_none();
+        let tally = Tally::default();
+        let mut reader = Tallied::new(std::io::BufReader::new(reader), &tally);
         
-        // Serialize everything
-        rmp_serde::encode::write(&mut writer, &(
+        let header = FileHeader::read_from(&mut reader)?;
+        let (state, bounds) = StateSeed { tally: &tally }
+            .deserialize(&mut rmp_serde::Deserializer::new(&mut reader))
+            .map_err(|e| {
+                crate::error::KhadyotaError::SerializationError(match header {
+                    Some(_) => format!("Database file is truncated or corrupt: {}", e),
+                    None => format!("Not a Khadyota database: no file header, and not a file saved before headers existed ({})", e),
+                })
+            })?;
+        if let Some(header) = &header
+            && rows.is_none()
+            && state.vectors.is_empty()
+            && header.vector_count > 0
+        {
+            return Err(crate::error::KhadyotaError::SerializationError(match path {
+                Some(path) => format!(
+                    "the {} vectors are stored in {:?}; open this file with load_mapped()",
+                    header.vector_count,
+                    mapped_vectors_path(path)
+                ),
+                None => format!(
+                    "the {} vectors are stored in a separate file; open this one with load_mapped()",
+                    header.vector_count
+                ),
+            }));
+        }
+        let vectors = match rows {
+            None => VectorStorage::from_rows(state.vectors, state.config.raw_precision, state.config.dimensions),
+            Some(rows) if rows.precision() != state.config.raw_precision => {
+                return Err(crate::error::KhadyotaError::SerializationError(format!(
+                    "vector file holds {:?} rows but the database stores {:?}",
+                    rows.precision(),
+                    state.config.raw_precision
+                )));
+            }
+            Some(rows) if state.vectors.is_empty() => VectorStorage::Cold(Box::new(rows)),
+            Some(_) => {
+                return Err(crate::error::KhadyotaError::SerializationError(
+                    "file stores its vectors inline as well as separately".to_string()
+                ));
+            }
+        };
+        if let Some(header) = header {
+            header.check_config(&state.config, vectors.len())?;
+        }
+        // Searches assume every row has the configured width
+        if let Some(row) = vectors.iter().find(|row| row.len() != state.config.dimensions) {
+            return Err(crate::error::KhadyotaError::DimensionMismatch {
+                expected: state.config.dimensions,
+                got: row.len(),
+            });
+        }
+        CompatibilityReport::check(
+            &state.config,
+            vectors.len(),
+            state.quantized.as_ref(),
+            state.ivf_index.as_ref(),
+        )
+        .into_result()?;
+        let (sections, mut load_warnings) = Section::gate(Section::read_table(&mut reader)?)?;
+        // Only files holding their rows can be saved over incrementally
+        let [config_end, vectors_end, codes_end, index_end, state_end] = bounds.ends;
+        let len = tally.position();
+        let layout = path.filter(|_| inline).map(|path| Layout {
+            path: persist::identity(path),
+            len,
+            ranges: [
+                config_end..vectors_end,
+                vectors_end..codes_end,
+                codes_end..index_end,
+                index_end..state_end,
+                state_end..len,
+            ],
+            hashes: bounds.hashes,
+            build_id: state
+                .build_id
+                .filter(|&id| id == persist::build_id(&bounds.hashes))
+                .map(|id| (bounds.build_id_at, id)),
+        });
+        let legacy_index = state.ivf_index.as_ref().is_some_and(|ivf| ivf.recorded_metric().is_none())
+            || state.quantized.as_ref().is_some_and(|q| q.codec().recorded_metric().is_none());
+        if legacy_index && state.config.metric != crate::config::DistanceMetric::Euclidean {
+            load_warnings.push(format!(
+                "index was saved before it recorded its metric and ranks by Euclidean distance; \
+                 rebuild it to rank by {:?}",
+                state.config.metric
+            ));
+        }
+        let mut db = Self {
+            config: state.config,
+            vectors,
+            quantized: state.quantized,
+            ivf_index: state.ivf_index,
+            metadata: state.metadata,
+            next_id: state.next_id,
+            index_built: state.index_built,
+            attributes: state.attributes,
+            deleted: state.deleted,
+            last_build: None,
+            admission: None,
+            generation: 0,
+            query_cache: None,
+            latency: None,
+            sections,
+            load_warnings,
+            seq: state.seq,
+            changelog: None,
+            journal: None,
+            index_lag: None,
+            access: None,
+            shared: None,
+            external_metadata: BTreeMap::new(),
+            external_ids: ExternalIds::default(),
+            progress: None,
+            insert_rate: InsertRate::default(),
+            dirty: DirtySections::NONE,
+            layout,
+            collections: BTreeMap::new(),
+            metadata_index: MetadataIndex::default(),
+            build_in_flight: false,
+            flat_index: None,
+        };
+        db.take_app_sections()?;
+        db.restore_flat_index();
+        Ok(db)
+    }
+    
+    /// Move the sections this build interprets out of `sections` and into
+    /// the state they hold. Access statistics are written afresh by the
+    /// next save, if tracking is still on then.
+    pub(crate) fn take_app_sections(&mut self) -> Result<()> {
+        let mut take = |name: &str| self.sections.iter().position(|s| s.name == name).map(|i| self.sections.remove(i));
+        let access = take(crate::access::SECTION_NAME);
+        let external = take(crate::metadata::SECTION_NAME);
+        let external_ids = take(crate::external_ids::SECTION_NAME);
+        let collections = take(crate::collections::SECTION_NAME);
+        if let Some(section) = access {
+            self.access = Some(AccessTracker::from_section(&section)?);
+        }
+        if let Some(section) = external {
+            self.external_metadata = crate::metadata::external_metadata_from_section(&section)?;
+        }
+        if let Some(section) = external_ids {
+            self.external_ids = crate::external_ids::external_ids_from_section(&section)?;
+        }
+        if let Some(section) = collections {
+            self.collections = crate::collections::collections_from_section(&section)?;
+        }
+        Ok(())
+    }
+    
+    /// Copy the rows for a loaded database's flat index, if it was built
+    pub(crate) fn restore_flat_index(&mut self) {
+        if self.config.index == IndexKind::Flat && self.index_built {
+            self.flat_index = Some(self.flat_copy());
+        }
+    }
+    
+    /// The section table a save writes after the core state: sections
+    /// carried through from the last load, then this build's own
+    pub(crate) fn app_sections(&self) -> Result<Vec<Section>> {
+        let access = self.access.as_ref().map(|a| a.to_section()).transpose()?.flatten();
+        let external = self.external_metadata_section()?;
+        let external_ids = self.external_ids_section()?;
+        let collections = self.collections_section()?;
+        Ok(self
+            .sections
+            .iter()
+            .cloned()
+            .chain(access)
+            .chain(external)
+            .chain(external_ids)
+            .chain(collections)
+            .collect())
+    }
+    
+    /// Save with the original vectors in a flat file beside `path`, named
+    /// `path` plus `.vectors`, for [`VectorDB::load_mapped`]. Rows are
+    /// streamed to disk rather than encoded in one pass with the rest of
+    /// the state.
+    pub fn save_mapped(&self, path: &Path) -> Result<()> {
+        let vectors_path = mapped_vectors_path(path);
+        if self.vectors.is_empty() {
+            // A stale file would be mapped by the next load
+            match std::fs::remove_file(&vectors_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        } else {
+            // Write beside the target and rename: this database may be
+            // mapping the file it replaces
+            let tmp = vectors_path.with_extension("vectors.tmp");
+            Serializer::save_rows_as(
+                self.vectors.iter(),
+                self.vectors.len(),
+                self.config.dimensions,
+                self.config.raw_precision,
+                &tmp,
+            )?;
+            crate::storage::replace::replace_file(&tmp, &vectors_path)?;
+        }
+        let bytes = self.write_state(path, false)?;
+        if let Some(progress) = &self.progress {
+            progress.on_event(BuildEvent::Saved { path: path.to_path_buf(), bytes });
+        }
+        Ok(())
+    }
+
+    /// Load a database saved with [`VectorDB::save_mapped`], with its
+    /// original vectors memory-mapped instead of read into memory.
+    ///
+    /// Only PQ codes, the IVF index and metadata are loaded; exact scans,
+    /// reranking and `get()` read rows from the mapping, keeping at most
+    /// `cache_bytes` of recently used ones in memory as
+    /// [`VectorDB::spill_originals`] does. The vector file must not change
+    /// while the database is open.
+    pub fn load_mapped(path: &Path, cache_bytes: usize) -> Result<Self> {
+        let vectors_path = mapped_vectors_path(path);
+        let rows = match ColdVectors::open(&vectors_path, cache_bytes) {
+            Ok(rows) => Some(rows),
+            // Empty databases are saved without one
+            Err(crate::error::KhadyotaError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Self::read_state(path, rows)
+    }
+
+    /// Optional sections the last `load()` skipped because this build
+    /// lacks the features they need, and journal records
+    /// [`VectorDB::open_durable`] dropped as truncated or corrupt
+    pub fn load_warnings(&self) -> &[String] {
+        &self.load_warnings
+    }
+    
+    /// Load a database, failing with [`KhadyotaError::ConfigMismatch`] if
+    /// its dimensions, metric or PQ layout differ from `expected`.
+    ///
+    /// [`KhadyotaError::ConfigMismatch`]: crate::error::KhadyotaError::ConfigMismatch
+    pub fn load_expecting(path: &Path, expected: &Config) -> Result<Self> {
+        let db = Self::load(path)?;
+        expected.check_matches(&db.config)?;
+        Ok(db)
+    }
+    
+    /// Number of live (not deleted) entries
+    pub fn len(&self) -> usize {
+        self.vectors.len() - self.deleted.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Fingerprint of the logical contents: config, vectors, metadata,
+    /// entry attributes, external ids, tombstones and the id counter.
+    ///
+    /// Index structures are excluded, so rebuilding does not change it,
+    /// while any insert or update does. Stable across save/load.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        rmp_serde::encode::write(&mut hasher, &(
             &self.config,
             &self.vectors,
-            &self.quantized,
-            &self.ivf_index,
             &self.metadata,
+            &self.attributes,
             self.next_id,
-            self.index_built,
-        ))?;
-        
-        let bytes_written = writer.get_ref().metadata()?.len();
-        println!("✓ Database saved ({} bytes)", bytes_written);
-        
-        Ok(())
+            &self.deleted,
+        ))
+        .expect("encoding into a hasher cannot fail");
+        // Only hashed when present, so checksums without it are unchanged
+        if !self.external_metadata.is_empty() {
+            rmp_serde::encode::write(&mut hasher, &self.external_metadata)
+                .expect("encoding into a hasher cannot fail");
+        }
+        if !self.external_ids.is_empty() {
+            rmp_serde::encode::write(&mut hasher, self.external_ids.by_id())
+                .expect("encoding into a hasher cannot fail");
+        }
+        hasher.0
     }
     
-    /// Load database from disk
-    pub fn load(path: &Path) -> Result<Self> {
-        use std::fs::File;
-        
-        println!("Loading database from {:?}...", path);
+    /// Fetch a stored vector and its metadata. Metadata kept apart under
+    /// [`Config::external_metadata`] is left out; see
+    /// [`VectorDB::external_metadata`].
+    pub fn get(&self, id: u32) -> Result<VectorEntry> {
+        let vector = self.vectors
+            .get(id)
+            .filter(|_| !self.deleted.contains(&id))
+            .ok_or(crate::error::KhadyotaError::VectorNotFound(id))?;
         
-        let file = File::open(path)?;
-        let reader = std::io::BufReader::new(file);
-        
-        let (config, vectors, quantized, ivf_index, metadata, next_id, index_built): (Config, Vec<Vec<f32>>, Option<QuantizedVectors>, Option<IVFIndex>, HashMap<u32, serde_json::Value>, u32, bool) =
-            rmp_serde::from_read(reader)?;
-        
-        println!("✓ Database loaded ({} vectors)", vectors.len());
-        
-        Ok(Self {
-            config,
-            vectors,
-            quantized,
-            ivf_index,
-            metadata,
-            next_id,
-            index_built,
+        Ok(VectorEntry {
+            id,
+            vector: vector.to_vec(),
+            metadata: self.metadata.get(&id).cloned(),
         })
     }
     
-    pub fn len(&self) -> usize {
-        self.vectors.len()
+    /// [`VectorDB::get`], with `None` for a deleted or unknown id
+    pub fn get_entry(&self, id: u32) -> Option<VectorEntry> {
+        self.get(id).ok()
     }
     
-    pub fn is_empty(&self) -> bool {
-        self.vectors.is_empty()
+    /// The stored vector of a live `id`, without its metadata. Rows held
+    /// in memory at full precision are borrowed; spilled, mapped and
+    /// half-precision rows are read back into an owned copy.
+    pub fn get_vector(&self, id: u32) -> Option<Cow<'_, [f32]>> {
+        if self.deleted.contains(&id) {
+            return None;
+        }
+        self.vectors.get(id).map(|row| row.into_cow())
+    }
+    
+    /// Whether `id` names a live entry
+    pub fn contains(&self, id: u32) -> bool {
+        id < self.next_id && !self.deleted.contains(&id)
+    }
+    
+    /// Every live entry in id order, as [`VectorDB::get`] returns them.
+    /// Spilled or mapped rows are read back one at a time.
+    pub fn iter(&self) -> impl Iterator<Item = VectorEntry> + '_ {
+        (0..self.next_id).filter_map(|id| self.get(id).ok())
+    }
+    
+    /// Move original vectors to `path`, keeping at most `cache_bytes` of
+    /// recently used rows in memory.
+    ///
+    /// Searches still score PQ codes from memory; reranking and `get()`
+    /// fault rows in through the cache. Vectors inserted afterwards stay in
+    /// memory until the next spill, and `build_index()` reads every row
+    /// back temporarily while training. Calling this again rewrites the
+    /// file and clears the cache.
+    pub fn spill_originals(&mut self, path: &Path, cache_bytes: usize) -> Result<()> {
+        let rows = std::mem::take(&mut self.vectors).into_rows();
+        
+        match ColdVectors::spill_as(&rows, self.config.raw_precision, path, cache_bytes) {
+            Ok(cold) => {
+                self.vectors = VectorStorage::Cold(Box::new(cold));
+                Ok(())
+            }
+            Err(e) => {
+                self.vectors = VectorStorage::from_rows(rows, self.config.raw_precision, self.config.dimensions);
+                Err(e)
+            }
+        }
+    }
+    
+    /// Cache counters for spilled originals, if they have been spilled
+    pub fn cold_cache_stats(&self) -> Option<ColdCacheStats> {
+        self.vectors.cold().map(|cold| cold.cache_stats())
     }
 
-    /// Batch search multiple queries in parallel
+    /// Batch search multiple queries in parallel.
+    ///
+    /// Under admission control the batch occupies a single search slot.
     pub fn batch_search(&self, queries: &[Vec<f32>], k: usize) -> Result<Vec<Vec<SearchResult>>> {
-        if !self.index_built {
-            return Err(crate::error::KhadyotaError::IndexNotBuilt);
-        }
-        
-        queries
-            .par_iter()
-            .map(|query| self.search(query, k))
-            .collect()
+        let timed = self.batch_search_inner(queries, k, false)?;
+        Ok(timed.into_iter().map(|(results, _)| results).collect())
+    }
+    
+    /// [`VectorDB::batch_search`], also returning how long each query took,
+    /// so slow queries can be matched to their inputs
+    pub fn batch_search_timed(&self, queries: &[Vec<f32>], k: usize) -> Result<Vec<(Vec<SearchResult>, Duration)>> {
+        self.batch_search_inner(queries, k, true)
     }
     
-    /// Parallel candidate scoring for large result sets
-    fn search_with_index_parallel(
+    /// Durations are zero unless `timed` is set or latency recording is on
+    fn batch_search_inner(
         &self,
-        query: &[f32],
+        queries: &[Vec<f32>],
         k: usize,
-        ivf: &IVFIndex,
-        quantized: &QuantizedVectors,
-    ) -> Result<Vec<SearchResult>> {
-        // Probe IVF
-        let clusters = ivf.probe(query);
-        let candidates = ivf.get_candidates(&clusters);
+        timed: bool,
+    ) -> Result<Vec<(Vec<SearchResult>, Duration)>> {
+        if !self.is_searchable() {
+            return Err(crate::error::KhadyotaError::IndexNotBuilt);
+        }
         
-        // Precompute distance table
-        let dist_table = quantized.precompute_distance_table(query);
+        let queries = queries
+            .iter()
+            .map(|query| self.check_query(query))
+            .collect::<Result<Vec<_>>>()?;
         
-        // Parallel distance computation
-        let mut scored: Vec<(u32, f32)> = candidates
+        // One slot covers the whole batch
+        let _permit = self.admit()?;
+        let params = SearchParams::default();
+        let fingerprint = params.fingerprint();
+        let timed = timed || self.latency.is_some();
+        queries
             .par_iter()
-            .map(|&vec_id| {
-                let distance = quantized.table_lookup_distance(&dist_table, vec_id);
-                (vec_id, distance)
-            })
-            .collect();
-        
-        scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        scored.truncate(k);
-        
-        Ok(scored
-            .into_iter()
-            .map(|(id, distance)| SearchResult {
-                id,
-                distance,
-                metadata: self.metadata.get(&id).cloned(),
+            .with_min_len(chunk_len(self.query_cost()))
+            .map(|query| {
+                let start = timed.then(Instant::now);
+                let results = match &self.query_cache {
+                    None => self.search_validated(query, k, &params)?,
+                    Some(cache) => match cache.get(query, k, fingerprint, self.generation) {
+                        Some(results) => {
+                            self.record_query();
+                            results
+                        }
+                        None => {
+                            let results = self.search_validated(query, k, &params)?;
+                            cache.insert(query, k, fingerprint, self.generation, &results);
+                            results
+                        }
+                    },
+                };
+                Ok((results, self.record_latency(start)))
             })
-            .collect())
+            .collect()
+    }
+}
+
+/// 64-bit FNV-1a, fed through `io::Write` so values can be hashed as they
+/// are encoded
+pub(crate) struct Fnv1a(pub(crate) u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl std::io::Write for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(bytes.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }
 
@@ -309,7 +1743,7 @@ mod tests {
     fn test_vector_db_end_to_end() {
         let config = Config {
             dimensions: 128,
-            use_pq: true,
+            quantizer: QuantizerKind::PQ,
             pq_subvectors: 8,
             num_clusters: 10,
             num_probe: 3,
@@ -346,4 +1780,125 @@ mod tests {
         let results2 = loaded.search(&query, 10).unwrap();
         assert_eq!(results2.len(), 10);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_save_is_deterministic() {
+        let config = Config {
+            dimensions: 16,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 2,
+            ..Default::default()
+        };
+        
+        let mut db = VectorDB::new(config).unwrap();
+        for i in 0..300 {
+            let vector: Vec<f32> = (0..16).map(|j| ((i * 16 + j) as f32).sin()).collect();
+            let metadata = (i % 3 != 0).then(|| serde_json::json!({"z": i, "a": {"y": 1, "b": 2}}));
+            db.insert(vector, metadata).unwrap();
+        }
+        db.build_index().unwrap();
+        
+        let first = NamedTempFile::new().unwrap();
+        let second = NamedTempFile::new().unwrap();
+        db.save(first.path()).unwrap();
+        db.save(second.path()).unwrap();
+        
+        let bytes = std::fs::read(first.path()).unwrap();
+        assert_eq!(bytes, std::fs::read(second.path()).unwrap());
+        
+        // A load/save round trip reproduces the same file
+        let reloaded = NamedTempFile::new().unwrap();
+        VectorDB::load(first.path()).unwrap().save(reloaded.path()).unwrap();
+        assert_eq!(bytes, std::fs::read(reloaded.path()).unwrap());
+    }
+    
+    #[test]
+    fn test_seeded_builds_are_identical() {
+        let build = |seed| {
+            let mut db = VectorDB::new(Config {
+                dimensions: 16,
+                quantizer: QuantizerKind::PQ,
+                pq_subvectors: 4,
+                num_clusters: 8,
+                num_probe: 2,
+                seed,
+                ..Default::default()
+            })
+            .unwrap();
+            for i in 0..600 {
+                db.insert((0..16).map(|j| ((i * 16 + j) as f32 * 0.7).sin()).collect(), None).unwrap();
+            }
+            db.build_index().unwrap();
+            let file = NamedTempFile::new().unwrap();
+            db.save(file.path()).unwrap();
+            (db, std::fs::read(file.path()).unwrap())
+        };
+        
+        // Centroids, codebooks and codes all land in the saved file
+        let (first, bytes) = build(Some(11));
+        let (second, again) = build(Some(11));
+        assert_eq!(bytes, again);
+        assert_eq!(
+            first.ivf_index.as_ref().unwrap().centroids(),
+            second.ivf_index.as_ref().unwrap().centroids()
+        );
+        assert!(first.quantized.as_ref().unwrap().iter_codes().eq(second.quantized.as_ref().unwrap().iter_codes()));
+        
+        assert_ne!(build(Some(12)).1, bytes);
+    }
+    
+    #[test]
+    fn test_disabled_latency_recording_is_nearly_free() {
+        let db = VectorDB::new(Config {
+            dimensions: 4,
+            quantizer: QuantizerKind::None,
+            ..Default::default()
+        })
+        .unwrap();
+        
+        // The whole per-query cost when recording is off
+        let iterations = 1_000_000u32;
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let db = std::hint::black_box(&db);
+            let timer = db.latency.as_ref().map(|_| Instant::now());
+            std::hint::black_box(db.record_latency(timer));
+        }
+        let per_query = start.elapsed() / iterations;
+        // Unoptimized test builds keep the calls and checks inlining removes
+        let bound = Duration::from_nanos(if cfg!(debug_assertions) { 200 } else { 20 });
+        assert!(per_query < bound, "{:?} per query", per_query);
+    }
+    
+    #[test]
+    fn test_unreadable_rows_fail_searches_instead_of_panicking() {
+        let mut db = VectorDB::new(Config {
+            dimensions: 16,
+            quantizer: QuantizerKind::None,
+            num_clusters: 4,
+            num_probe: 4,
+            ..Default::default()
+        })
+        .unwrap();
+        for i in 0..200 {
+            db.insert((0..16).map(|j| ((i * 16 + j) as f32 * 0.7).sin()).collect(), None).unwrap();
+        }
+        db.build_index().unwrap();
+        
+        // As if the last row's spilled file had been cut short
+        let mut rows = std::mem::take(&mut db.vectors).into_rows();
+        let query = rows.pop().unwrap();
+        db.vectors = VectorStorage::Memory(rows);
+        
+        let params = SearchParams::builder().num_probe(4).build();
+        assert!(matches!(
+            db.search_with_params(&query, 5, &params),
+            Err(crate::error::KhadyotaError::VectorNotFound(199))
+        ));
+        assert!(matches!(
+            db.range_search_with_params(&query, 10.0, &params),
+            Err(crate::error::KhadyotaError::VectorNotFound(199))
+        ));
+    }
+}
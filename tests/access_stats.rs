@@ -0,0 +1,147 @@
+use khadyota::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tempfile::TempDir;
+
+const DIMS: usize = 16;
+const BLOBS: usize = 8;
+
+fn blob_point(blob: usize, rng: &mut StdRng) -> Vec<f32> {
+    (0..DIMS)
+        .map(|j| if j == blob { 10.0 } else { 0.0 } + rng.gen_range(-0.5..0.5))
+        .collect()
+}
+
+fn build_db() -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMS,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 4,
+        num_clusters: BLOBS,
+        num_probe: 1,
+        seed: Some(3),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let mut rng = StdRng::seed_from_u64(1);
+    for i in 0..BLOBS * 100 {
+        db.insert(blob_point(i % BLOBS, &mut rng), None).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+/// Nine in ten queries land near blobs 0 and 1
+fn run_skewed_workload(db: &VectorDB, queries: usize) {
+    let mut rng = StdRng::seed_from_u64(2);
+    for i in 0..queries {
+        let blob = if i % 10 == 9 { 2 + i % BLOBS.saturating_sub(2) } else { i % 2 };
+        db.search(&blob_point(blob, &mut rng), 5).unwrap();
+    }
+}
+
+#[test]
+fn test_tracking_is_off_by_default() {
+    let db = build_db();
+    run_skewed_workload(&db, 10);
+    assert!(db.access_stats().is_none());
+    assert!(db.suggest_num_probe(1_000).is_none());
+}
+
+#[test]
+fn test_hot_clusters_dominate_skewed_workload() {
+    let mut db = build_db();
+    db.set_access_tracking(Some(AccessTrackingConfig::default()));
+    run_skewed_workload(&db, 500);
+
+    let stats = db.access_stats().unwrap();
+    assert_eq!(stats.queries, 500);
+    assert_eq!(stats.clusters_probed, 500);
+    assert!(stats.last_access_unix_secs.is_some());
+    assert!(stats.mean_candidates() > 50.0);
+    assert_eq!(stats.cluster_frequency.len(), BLOBS);
+
+    let total: f64 = stats.cluster_frequency.iter().sum();
+    let hot: f64 = stats.hottest_clusters(2).iter().map(|&(_, f)| f).sum();
+    assert!(hot > 0.8 * total, "hot clusters took {} of {}", hot, total);
+
+    // About 100 rows per probed list
+    assert_eq!(db.suggest_num_probe(250), Some(2));
+    assert_eq!(db.suggest_num_probe(10), Some(1));
+    assert_eq!(db.suggest_num_probe(usize::MAX / 2), Some(BLOBS));
+}
+
+#[test]
+fn test_cache_hits_count_as_queries_but_not_scans() {
+    let mut db = build_db();
+    db.set_access_tracking(Some(AccessTrackingConfig::default()));
+    db.set_query_cache(Some(QueryCacheConfig::default()));
+
+    let query = vec![10.0; DIMS];
+    db.search(&query, 5).unwrap();
+    let scanned = db.access_stats().unwrap().candidates_scanned;
+    db.search(&query, 5).unwrap();
+
+    let stats = db.access_stats().unwrap();
+    assert_eq!(stats.queries, 2);
+    assert_eq!(stats.clusters_probed, 1);
+    assert_eq!(stats.candidates_scanned, scanned);
+}
+
+#[test]
+fn test_rebuild_resets_cluster_frequencies() {
+    let mut db = build_db();
+    db.set_access_tracking(Some(AccessTrackingConfig::default()));
+    run_skewed_workload(&db, 20);
+
+    db.build_index().unwrap();
+    let stats = db.access_stats().unwrap();
+    assert_eq!(stats.queries, 20);
+    assert!(stats.cluster_frequency.is_empty());
+    assert!(db.suggest_num_probe(250).is_none());
+}
+
+#[test]
+fn test_counters_survive_save_and_load() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.khadyota");
+
+    let mut db = build_db();
+    db.set_access_tracking(Some(AccessTrackingConfig::default()));
+    run_skewed_workload(&db, 200);
+    db.save(&path).unwrap();
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert!(loaded.load_warnings().is_empty());
+    assert_eq!(loaded.access_stats(), db.access_stats());
+    assert_eq!(loaded.suggest_num_probe(250), db.suggest_num_probe(250));
+
+    // Tracking carries on from the restored counts
+    run_skewed_workload(&loaded, 10);
+    assert_eq!(loaded.access_stats().unwrap().queries, 210);
+}
+
+#[test]
+fn test_unpersisted_or_disabled_tracking_is_not_saved() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.khadyota");
+
+    let mut db = build_db();
+    db.set_access_tracking(Some(AccessTrackingConfig {
+        persist: false,
+        ..Default::default()
+    }));
+    run_skewed_workload(&db, 20);
+    db.save(&path).unwrap();
+    assert!(VectorDB::load(&path).unwrap().access_stats().is_none());
+
+    db.set_access_tracking(Some(AccessTrackingConfig::default()));
+    db.save(&path).unwrap();
+    let mut loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.access_stats().unwrap().queries, 0);
+
+    loaded.set_access_tracking(None);
+    loaded.save(&path).unwrap();
+    assert!(VectorDB::load(&path).unwrap().access_stats().is_none());
+}
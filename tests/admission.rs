@@ -0,0 +1,135 @@
+use khadyota::*;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn build_db() -> VectorDB {
+    let config = Config {
+        dimensions: 32,
+        quantizer: QuantizerKind::None,
+        num_clusters: 8,
+        num_probe: 2,
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..2000 {
+        let vector: Vec<f32> = (0..32).map(|j| ((i * 32 + j) as f32).sin()).collect();
+        db.insert(vector, None).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn query(i: usize) -> Vec<f32> {
+    (0..32).map(|j| ((i + j) as f32).cos()).collect()
+}
+
+#[test]
+fn test_rejects_beyond_queue_and_completes_admitted() {
+    let mut db = build_db();
+    let expected: Vec<Vec<u32>> = (0..8)
+        .map(|i| db.search(&query(i), 5).unwrap().iter().map(|r| r.id).collect())
+        .collect();
+
+    db.set_admission_control(Some(AdmissionConfig {
+        max_in_flight: 1,
+        max_queued: 2,
+        queue_timeout: Duration::from_secs(30),
+    }))
+    .unwrap();
+    let db = Arc::new(db);
+
+    // Occupy the only slot so every search below has to queue or be rejected
+    let blocker = db.admission().unwrap().acquire().unwrap();
+
+    let barrier = Arc::new(Barrier::new(8));
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let db = Arc::clone(&db);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                (i, db.search(&query(i), 5))
+            })
+        })
+        .collect();
+
+    // Six searches find the queue full; two wait for the slot
+    let start = Instant::now();
+    while db.admission_stats().unwrap().rejected < 6 {
+        assert!(start.elapsed() < Duration::from_secs(30), "{:?}", db.admission_stats());
+        thread::sleep(Duration::from_millis(1));
+    }
+    drop(blocker);
+
+    let mut admitted = 0;
+    for handle in handles {
+        match handle.join().unwrap() {
+            (i, Ok(results)) => {
+                let ids: Vec<u32> = results.iter().map(|r| r.id).collect();
+                assert_eq!(ids, expected[i]);
+                admitted += 1;
+            }
+            (_, Err(KhadyotaError::Overloaded(_))) => {}
+            (_, Err(e)) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    let stats = db.admission_stats().unwrap();
+    assert_eq!(admitted, 2);
+    assert_eq!(stats.rejected, 6);
+    assert_eq!(stats.queued, 2);
+    // Two queued searches plus the blocker
+    assert_eq!(stats.executed, 3);
+    assert_eq!(stats.in_flight, 0);
+}
+
+#[test]
+fn test_stress_more_searches_than_limit() {
+    let mut db = build_db();
+    db.set_admission_control(Some(AdmissionConfig {
+        max_in_flight: 2,
+        max_queued: 2,
+        queue_timeout: Duration::from_millis(1),
+    }))
+    .unwrap();
+    let db = Arc::new(db);
+
+    let threads = 16;
+    let per_thread = 25;
+    let barrier = Arc::new(Barrier::new(threads));
+
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let db = Arc::clone(&db);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let mut ok = 0u64;
+                for i in 0..per_thread {
+                    match db.search(&query(t * per_thread + i), 10) {
+                        Ok(results) => {
+                            assert_eq!(results.len(), 10);
+                            ok += 1;
+                        }
+                        Err(KhadyotaError::Overloaded(_)) => {}
+                        Err(e) => panic!("unexpected error: {}", e),
+                    }
+                }
+                ok
+            })
+        })
+        .collect();
+
+    let ok: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    let stats = db.admission_stats().unwrap();
+
+    assert_eq!(stats.executed, ok);
+    assert_eq!(stats.executed + stats.rejected, (threads * per_thread) as u64);
+    assert_eq!(stats.in_flight, 0);
+    assert_eq!(stats.waiting, 0);
+
+    // Limits are off by default
+    assert!(build_db().admission_stats().is_none());
+}
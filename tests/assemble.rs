@@ -0,0 +1,145 @@
+use khadyota::distance::normalized;
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use serde_json::json;
+use tempfile::TempDir;
+
+const DIMS: usize = 16;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMS,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 3,
+        seed: Some(5),
+        // Parts carry codes of the rows themselves
+        use_residuals: false,
+        ..Default::default()
+    }
+}
+
+fn trained_db() -> VectorDB {
+    let mut db = VectorDB::new(config()).unwrap();
+    for (i, vector) in clustered_vectors(600, DIMS, 8, 0.2, 5).into_iter().enumerate() {
+        let metadata = (i % 3 == 0).then(|| json!({"i": i}));
+        db.insert(vector, metadata).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn hits(results: Vec<SearchResult>) -> Vec<(u32, f32)> {
+    results.into_iter().map(|r| (r.id, r.distance)).collect()
+}
+
+fn assemble(parts: PrebuiltParts) -> Result<VectorDB> {
+    VectorDB::assemble(
+        config(),
+        parts.vectors,
+        parts.codec,
+        parts.codes,
+        parts.centroids,
+        parts.assignments,
+        parts.metadata,
+    )
+}
+
+#[test]
+fn test_assembled_db_matches_the_original() {
+    let original = trained_db();
+    let db = assemble(original.export_parts(true).unwrap()).unwrap();
+
+    assert_eq!(db.checksum(), original.checksum());
+    assert_eq!(db.len(), original.len());
+    for query in clustered_vectors(10, DIMS, 8, 0.3, 9) {
+        assert_eq!(hits(db.search(&query, 10).unwrap()), hits(original.search(&query, 10).unwrap()));
+    }
+    assert_eq!(db.get(3).unwrap().metadata, Some(json!({"i": 3})));
+
+    // Saves and loads like any built database
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.khadyota");
+    db.save(&path).unwrap();
+    assert_eq!(VectorDB::load(&path).unwrap().checksum(), original.checksum());
+}
+
+#[test]
+fn test_assemble_without_vectors_decodes_codes() {
+    let original = trained_db();
+    let db = assemble(original.export_parts(false).unwrap()).unwrap();
+
+    let entry = db.get(7).unwrap();
+    let codec = original.export_parts(false).unwrap().codec;
+    let codes = codec.encode(&original.get(7).unwrap().vector);
+    // Cosine rows are stored at unit length, decoded ones included
+    assert_eq!(entry.vector, normalized(&codec.decode(&codes)));
+
+    // PQ search without reranking never reads the originals
+    let query = original.get(42).unwrap().vector;
+    assert_eq!(hits(db.search(&query, 5).unwrap()), hits(original.search(&query, 5).unwrap()));
+}
+
+#[test]
+fn test_assemble_rejects_mismatched_parts() {
+    let original = trained_db();
+
+    let mut parts = original.export_parts(true).unwrap();
+    parts.codes.truncate(500);
+    match assemble(parts) {
+        Err(KhadyotaError::IncompatibleComponents(report)) => assert!(
+            report.violations.contains(&Violation::CodeCount { expected: 600, found: 500 }),
+            "{}",
+            report
+        ),
+        other => panic!("expected IncompatibleComponents, got {:?}", other.map(|_| ())),
+    }
+
+    let mut parts = original.export_parts(true).unwrap();
+    parts.metadata.insert(600, json!("past the end"));
+    assert!(matches!(assemble(parts), Err(KhadyotaError::VectorNotFound(600))));
+
+    let parts = original.export_parts(true).unwrap();
+    let flat = Config { quantizer: QuantizerKind::None, ..config() };
+    let result = VectorDB::assemble(
+        flat,
+        parts.vectors,
+        parts.codec,
+        parts.codes,
+        parts.centroids,
+        parts.assignments,
+        parts.metadata,
+    );
+    assert!(matches!(result, Err(KhadyotaError::InvalidConfig(_))));
+}
+
+#[test]
+fn test_segments_stream_in_without_rebuilding() {
+    let dir = TempDir::new().unwrap();
+    let mut store = SegmentedDB::new(config(), 10_000).unwrap();
+    store.set_auto_merge(false);
+
+    let original = trained_db();
+    let mut expected = Vec::new();
+    for _ in 0..3 {
+        let chunk = assemble(original.export_parts(true).unwrap()).unwrap();
+        expected.push(store.add_segment(chunk).unwrap());
+    }
+    assert_eq!(expected, [0..600, 600..1200, 1200..1800]);
+    assert_eq!(store.segment_sizes(), [600, 600, 600]);
+    store.save(dir.path()).unwrap();
+
+    let reopened = SegmentedDB::open(dir.path()).unwrap();
+    assert_eq!(reopened.len(), 1800);
+    let query = original.get(11).unwrap().vector;
+    let ids: Vec<u32> = reopened.search(&query, 3).unwrap().iter().map(|r| r.id).collect();
+    assert_eq!(ids.len(), 3);
+    assert!(ids.iter().all(|id| id % 600 == 11), "{:?}", ids);
+
+    // Only databases built for the store's layout are accepted
+    let other = VectorDB::new(Config::for_dimensions(8)).unwrap();
+    let mut store = SegmentedDB::new(config(), 100).unwrap();
+    assert!(matches!(store.add_segment(other), Err(KhadyotaError::ConfigMismatch { .. })));
+    let unbuilt = VectorDB::new(config()).unwrap();
+    assert!(matches!(store.add_segment(unbuilt), Err(KhadyotaError::IndexNotBuilt)));
+}
@@ -0,0 +1,156 @@
+//! Index builds on a helper thread. Needs `cargo test --features std-thread`.
+#![cfg(feature = "std-thread")]
+
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use std::sync::{Arc, Barrier, RwLock};
+
+const DIMENSIONS: usize = 16;
+const ROWS: usize = 50_000;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 4,
+        num_clusters: 64,
+        num_probe: 64,
+        seed: Some(7),
+        ..Default::default()
+    }
+}
+
+fn ranked(results: Vec<SearchResult>) -> Vec<(u32, f32)> {
+    results.into_iter().map(|r| (r.id, r.distance)).collect()
+}
+
+fn shared_db(vectors: &[Vec<f32>]) -> Arc<RwLock<VectorDB>> {
+    let mut db = VectorDB::new(config()).unwrap();
+    db.insert_batch(vectors.iter().map(|v| (v.clone(), None))).unwrap();
+    Arc::new(RwLock::new(db))
+}
+
+/// Holds the build when IVF training starts, codebooks done, until the
+/// test lets it go
+struct Gate(Barrier);
+
+impl Gate {
+    fn install(db: &Arc<RwLock<VectorDB>>) -> Arc<Gate> {
+        let gate = Arc::new(Gate(Barrier::new(2)));
+        db.write().unwrap().set_progress_callback(Some(gate.clone()));
+        gate
+    }
+
+    /// Wait for the build to reach the gate
+    fn reached(&self) {
+        self.0.wait();
+    }
+
+    /// Let the build carry on
+    fn release(&self) {
+        self.0.wait();
+    }
+}
+
+impl ProgressCallback for Gate {
+    fn on_event(&self, event: BuildEvent) {
+        if let BuildEvent::IvfBuildStarted { .. } = event {
+            self.0.wait();
+            self.0.wait();
+        }
+    }
+}
+
+/// Search for a few stored rows from several threads at once, each
+/// expecting its own row among the nearest
+fn search_concurrently(db: &Arc<RwLock<VectorDB>>, vectors: &[Vec<f32>]) {
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            scope.spawn(move || {
+                for id in (thread..200).step_by(4).map(|i| i * 241 % ROWS) {
+                    let results = db.read().unwrap().search(&vectors[id], 5).unwrap();
+                    assert!(results.iter().any(|r| r.id == id as u32), "{}", id);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn test_searches_and_writes_continue_during_a_build() {
+    let vectors = clustered_vectors(ROWS + 100, DIMENSIONS, 64, 0.3, 1);
+    let db = shared_db(&vectors[..ROWS]);
+    let gate = Gate::install(&db);
+
+    let handle = VectorDB::build_index_background(&db).unwrap();
+    gate.reached();
+
+    // No index yet, so searches scan
+    search_concurrently(&db, &vectors);
+    assert!(matches!(VectorDB::build_index_background(&db), Err(KhadyotaError::BuildInProgress)));
+    assert!(matches!(db.write().unwrap().build_index(), Err(KhadyotaError::BuildInProgress)));
+
+    // Rows added and rewritten mid-build are indexed as it goes in
+    {
+        let mut db = db.write().unwrap();
+        db.insert_batch(vectors[ROWS..].iter().map(|v| (v.clone(), None))).unwrap();
+        db.update(3, vectors[ROWS + 50].clone(), None).unwrap();
+    }
+    gate.release();
+    handle.wait().unwrap();
+
+    let db = db.read().unwrap();
+    let status = db.index_status();
+    assert!(status.current && status.unencoded == 0, "{:?}", status);
+    assert_eq!(db.index_stats().unwrap().total_vectors, ROWS + 100);
+    for id in [ROWS, ROWS + 42, ROWS + 99] {
+        assert_eq!(db.search(&vectors[id], 1).unwrap()[0].id, id as u32);
+    }
+    let ids: Vec<u32> = db.search(&vectors[ROWS + 50], 2).unwrap().iter().map(|r| r.id).collect();
+    assert!(ids.contains(&3) && ids.contains(&(ROWS as u32 + 50)), "{:?}", ids);
+}
+
+#[test]
+fn test_a_cancelled_build_leaves_the_old_index_serving() {
+    let vectors = clustered_vectors(ROWS, DIMENSIONS, 64, 0.3, 2);
+    let db = shared_db(&vectors);
+    db.write().unwrap().build_index().unwrap();
+    let query = &vectors[1_234];
+    let before = ranked(db.read().unwrap().search(query, 10).unwrap());
+    let gate = Gate::install(&db);
+
+    let handle = VectorDB::build_index_background(&db).unwrap();
+    gate.reached();
+    search_concurrently(&db, &vectors);
+    handle.cancel();
+    gate.release();
+    assert!(matches!(handle.wait(), Err(KhadyotaError::Cancelled)));
+
+    // The old index is untouched and builds start again
+    assert_eq!(ranked(db.read().unwrap().search(query, 10).unwrap()), before);
+    db.write().unwrap().set_progress_callback(None);
+    let handle = VectorDB::build_index_background(&db).unwrap();
+    handle.wait().unwrap();
+    assert_eq!(db.read().unwrap().search(query, 1).unwrap()[0].id, 1_234);
+}
+
+#[test]
+fn test_a_build_cancelled_at_once_installs_nothing() {
+    let vectors = clustered_vectors(2_000, DIMENSIONS, 8, 0.3, 3);
+    let db = shared_db(&vectors);
+    let handle = VectorDB::build_index_background(&db).unwrap();
+    handle.cancel();
+    match handle.wait() {
+        // The build may finish before it sees the flag
+        Ok(()) => assert!(db.read().unwrap().index_status().current),
+        Err(e) => {
+            assert!(matches!(e, KhadyotaError::Cancelled), "{:?}", e);
+            assert!(!db.read().unwrap().index_status().searchable);
+        }
+    }
+    assert!(matches!(
+        VectorDB::build_index_background(&Arc::new(RwLock::new(VectorDB::new(config()).unwrap()))),
+        Err(KhadyotaError::InvalidConfig(_))
+    ));
+}
@@ -0,0 +1,53 @@
+//! Segment merges on a helper thread. Needs `cargo test --features std-thread`.
+#![cfg(feature = "std-thread")]
+
+use khadyota::*;
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+
+fn vector(i: usize) -> Vec<f32> {
+    (0..16).map(|j| ((i * 16 + j) as f32 * 0.37).sin()).collect()
+}
+
+fn config() -> Config {
+    Config {
+        dimensions: 16,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::None,
+        num_clusters: 8,
+        num_probe: 8,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_merges_run_beside_inserts_and_searches() {
+    let mut store = SegmentedDB::new(config(), 100).unwrap();
+    for i in 0..2_000 {
+        store.insert(vector(i), Some(json!({"n": i}))).unwrap();
+    }
+    // Nothing merges on the insert path by default
+    assert_eq!(store.segment_sizes().len(), 20);
+    let store = Arc::new(RwLock::new(store));
+
+    let handle = SegmentedDB::merge_background(&store);
+    for i in 2_000..2_500 {
+        let id = store.write().unwrap().insert(vector(i), Some(json!({"n": i}))).unwrap();
+        assert_eq!(id as usize, i);
+        let results = store.read().unwrap().search(&vector(i / 2), 1).unwrap();
+        assert_eq!(results[0].id as usize, i / 2);
+    }
+    assert!(handle.wait().unwrap() > 0);
+
+    let mut store = store.write().unwrap();
+    assert_eq!(store.len(), 2_500);
+    assert!(store.segment_sizes().len() < 20, "{:?}", store.segment_sizes());
+    // Segments sealed during the merges may have left one more to do
+    store.merge_pending().unwrap();
+    assert_eq!(store.merge_pending().unwrap(), 0);
+    for target in [0, 777, 1_999, 2_499] {
+        let results = store.search(&vector(target), 1).unwrap();
+        assert_eq!(results[0].id as usize, target);
+        assert_eq!(results[0].metadata.as_ref().unwrap()["n"], target);
+    }
+}
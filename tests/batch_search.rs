@@ -0,0 +1,98 @@
+//! Flat query batches scored with per-thread buffers, against one search
+//! per query
+
+use khadyota::harness::{build_db, clustered_vectors, random_vectors};
+use khadyota::*;
+
+const DIMENSIONS: usize = 16;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        pq_subvectors: 4,
+        num_clusters: 12,
+        num_probe: 3,
+        seed: Some(7),
+        ..Default::default()
+    }
+}
+
+/// Ids and distances, bit for bit
+fn hits(results: &[SearchResult]) -> Vec<(u32, u32)> {
+    results.iter().map(|r| (r.id, r.distance.to_bits())).collect()
+}
+
+fn assert_batch_matches(db: &VectorDB, queries: &[Vec<f32>], k: usize) {
+    let flat: Vec<f32> = queries.concat();
+    let batch = db.batch_search_flat(&flat, queries.len(), k).unwrap();
+    assert_eq!(batch.len(), queries.len());
+    for (q, (query, results)) in queries.iter().zip(&batch).enumerate() {
+        assert!(!results.is_empty() && results.len() <= k, "query {}", q);
+        assert_eq!(hits(results), hits(&db.search(query, k).unwrap()), "query {}", q);
+    }
+}
+
+#[test]
+fn test_flat_batches_match_single_searches() {
+    let vectors = clustered_vectors(1_500, DIMENSIONS, 12, 0.3, 1);
+    let queries = random_vectors(70, DIMENSIONS, 2);
+    let configs = [
+        config(),
+        Config { use_residuals: true, ..config() },
+        Config { quantizer: QuantizerKind::SQ8, ..config() },
+        Config { use_opq: true, ..config() },
+        Config { metric: DistanceMetric::Cosine, ..config() },
+        Config { metric: DistanceMetric::DotProduct, use_residuals: true, ..config() },
+        Config { ivf_assign_replicas: 2, ..config() },
+        Config { quantizer: QuantizerKind::None, ..config() },
+        Config { index: IndexKind::Flat, ..config() },
+    ];
+    for config in configs {
+        let db = build_db(config, vectors.clone()).unwrap();
+        assert_batch_matches(&db, &queries, 10);
+        assert_batch_matches(&db, &queries[..1], 1_000);
+    }
+}
+
+#[test]
+fn test_flat_batches_match_after_writes() {
+    let vectors = clustered_vectors(1_200, DIMENSIONS, 12, 0.3, 3);
+    let queries = random_vectors(40, DIMENSIONS, 4);
+    let config = Config {
+        probe_compensation: Some(ProbeCompensation::default()),
+        ..config()
+    };
+    let mut db = build_db(config, vectors[..1_000].to_vec()).unwrap();
+    for id in (0..1_000).step_by(3) {
+        db.delete(id).unwrap();
+    }
+    assert_batch_matches(&db, &queries, 10);
+
+    // Rows inserted since the build are scored exactly, as a search would
+    db.insert_batch(vectors[1_000..].iter().map(|v| (v.clone(), None))).unwrap();
+    assert_batch_matches(&db, &queries, 10);
+    db.set_query_cache(Some(QueryCacheConfig::default()));
+    assert_batch_matches(&db, &queries, 10);
+    assert_batch_matches(&db, &queries, 10);
+}
+
+#[test]
+fn test_flat_batches_are_checked_once() {
+    let db = build_db(config(), clustered_vectors(300, DIMENSIONS, 12, 0.3, 5)).unwrap();
+    let queries: Vec<f32> = random_vectors(3, DIMENSIONS, 6).concat();
+
+    assert!(db.batch_search_flat(&queries, 3, 5).is_ok());
+    assert!(db.batch_search_flat(&[], 0, 5).unwrap().is_empty());
+    for (len, num_queries) in [(queries.len() - 1, 3), (queries.len(), 2), (queries.len(), 4)] {
+        assert!(matches!(
+            db.batch_search_flat(&queries[..len], num_queries, 5),
+            Err(KhadyotaError::InvalidConfig(_))
+        ));
+    }
+
+    let unbuilt = VectorDB::new(config()).unwrap();
+    assert!(matches!(
+        unbuilt.batch_search_flat(&queries, 3, 5),
+        Err(KhadyotaError::IndexNotBuilt)
+    ));
+}
@@ -0,0 +1,81 @@
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use std::time::Duration;
+
+fn db(n: usize, use_pq: bool) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: 32,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 4,
+        num_clusters: 16,
+        seed: Some(1),
+        ..Default::default()
+    })
+    .unwrap();
+    db.insert_batch(clustered_vectors(n, 32, 16, 0.2, 1).into_iter().map(|v| (v, None)))
+        .unwrap();
+    db
+}
+
+fn mid(range: &std::ops::RangeInclusive<Duration>) -> f64 {
+    (range.start().as_secs_f64() + range.end().as_secs_f64()) / 2.0
+}
+
+#[test]
+fn test_estimate_structure() {
+    let estimate = db(2_000, true).estimate_build().unwrap();
+    assert_eq!((estimate.vectors, estimate.dimensions, estimate.clusters), (2_000, 32, 16));
+    assert_eq!(estimate.pq_subvectors, Some(4));
+    assert!(estimate.distance_cost > Duration::ZERO);
+
+    let pq = estimate.pq_time.clone().unwrap();
+    for range in [&estimate.ivf_time, &pq, &estimate.total_time] {
+        assert!(range.start() > &Duration::ZERO && range.start() < range.end());
+    }
+    assert_eq!(*estimate.total_time.end(), *estimate.ivf_time.end() + *pq.end());
+    assert!(estimate.peak_memory_bytes.start() <= estimate.peak_memory_bytes.end());
+    assert!(estimate.file_bytes.start() <= estimate.file_bytes.end());
+    assert!(!estimate.assumptions.is_empty());
+
+    let report = estimate.to_string();
+    assert!(report.contains("PQ training (4 subvectors)"), "{}", report);
+    assert!(report.contains("Assuming:"), "{}", report);
+
+    let flat = db(2_000, false).estimate_build().unwrap();
+    assert!(flat.pq_time.is_none());
+    assert_eq!(flat.total_time, flat.ivf_time);
+    assert!(flat.file_bytes.end() < estimate.file_bytes.start());
+
+    assert!(matches!(db(0, false).estimate_build(), Err(KhadyotaError::InvalidConfig(_))));
+}
+
+#[test]
+fn test_estimates_scale_with_rows() {
+    let small = db(2_000, true).estimate_build().unwrap();
+    let large = db(8_000, true).estimate_build().unwrap();
+
+    // 4x the rows. Each estimate calibrates on its own and other tests
+    // compete for the CPU, so time is compared in units of the measured
+    // distance cost rather than in seconds.
+    let work = |e: &BuildEstimate| mid(&e.ivf_time) / e.distance_cost.as_secs_f64();
+    let time = work(&large) / work(&small);
+    assert!((3.0..5.0).contains(&time), "time grew {}x", time);
+    assert!(large.total_time.start() > &Duration::ZERO);
+    let memory = *large.peak_memory_bytes.end() as f64 / *small.peak_memory_bytes.end() as f64;
+    assert!((3.0..4.5).contains(&memory), "memory grew {}x", memory);
+    let file = *large.file_bytes.end() as f64 / *small.file_bytes.end() as f64;
+    assert!((3.0..4.5).contains(&file), "file grew {}x", file);
+}
+
+/// The saved size lands in the estimated range
+#[test]
+fn test_file_size_estimate_holds() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let mut db = db(1_000, true);
+    let estimate = db.estimate_build().unwrap();
+    db.build_index().unwrap();
+    db.save(&path).unwrap();
+    let size = std::fs::metadata(&path).unwrap().len();
+    assert!(estimate.file_bytes.contains(&size), "{} not in {:?}", size, estimate.file_bytes);
+}
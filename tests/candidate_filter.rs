@@ -0,0 +1,126 @@
+use khadyota::harness::{build_db, clustered_vectors, random_vectors};
+use khadyota::*;
+use std::ffi::c_void;
+use std::time::Duration;
+
+const DIMS: usize = 16;
+
+fn config(quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions: DIMS,
+        quantizer,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 2,
+        seed: Some(5),
+        ..Default::default()
+    }
+}
+
+/// Admits ids divisible by the `u32` that `user_data` points to
+extern "C" fn multiple_of(id: u32, user_data: *mut c_void) -> bool {
+    // SAFETY: the tests pass a pointer to a live, unmutated u32
+    let modulus = unsafe { *(user_data as *const u32) };
+    id.is_multiple_of(modulus)
+}
+
+extern "C" fn slow_admit_all(_id: u32, _user_data: *mut c_void) -> bool {
+    std::thread::sleep(Duration::from_micros(100));
+    true
+}
+
+fn native_multiples(modulus: &'static u32) -> CandidateFilter {
+    // SAFETY: `multiple_of` only reads the static
+    unsafe { CandidateFilter::from_native(multiple_of, modulus as *const u32 as *mut c_void) }
+}
+
+fn result_ids(results: &[SearchResult]) -> Vec<u32> {
+    results.iter().map(|r| r.id).collect()
+}
+
+#[test]
+fn test_native_filter_matches_excluding_the_rest() {
+    static MODULUS: u32 = 3;
+    let vectors = clustered_vectors(1_200, DIMS, 8, 0.2, 3);
+    let queries = random_vectors(10, DIMS, 4);
+
+    for quantizer in [QuantizerKind::None, QuantizerKind::PQ] {
+        let db = build_db(config(quantizer), vectors.clone()).unwrap();
+        let filtered = SearchParams::builder().filter(native_multiples(&MODULUS)).build();
+        let excluded = SearchParams::builder()
+            .exclude((0..vectors.len() as u32).filter(|id| id % MODULUS != 0))
+            .build();
+
+        for query in &queries {
+            let results = db.search_with_params(query, 10, &filtered).unwrap();
+            assert_eq!(results.len(), 10);
+            assert!(results.iter().all(|r| r.id % MODULUS == 0));
+            assert_eq!(result_ids(&results), result_ids(&db.search_with_params(query, 10, &excluded).unwrap()));
+        }
+    }
+
+    // Without PQ or a probe count the search is exact over the admitted rows
+    let db = build_db(config(QuantizerKind::None), vectors.clone()).unwrap();
+    let filtered = SearchParams::builder().filter(native_multiples(&MODULUS)).build();
+    let query = &queries[0];
+    let mut expected: Vec<(u32, f32)> = (0..vectors.len() as u32)
+        .filter(|id| id % MODULUS == 0)
+        .map(|id| (id, distance::compute_distance(query, &vectors[id as usize], DistanceMetric::Euclidean)))
+        .collect();
+    expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let found = db.search_with_params(query, 5, &filtered).unwrap();
+    assert_eq!(result_ids(&found), expected[..5].iter().map(|e| e.0).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_rust_filter_is_applied_everywhere_candidates_are_collected() {
+    let vectors = clustered_vectors(800, DIMS, 8, 0.2, 5);
+    let db = build_db(config(QuantizerKind::PQ), vectors).unwrap();
+    let odd = SearchParams::builder().filter(CandidateFilter::new(|id| id % 2 == 1)).build();
+    let query = db.get(10).unwrap().vector;
+
+    assert!(db.search_with_params(&query, 20, &odd).unwrap().iter().all(|r| r.id % 2 == 1));
+    assert!(db.range_search_with_params(&query, 10.0, &odd).unwrap().iter().all(|r| r.id % 2 == 1));
+    let ranked = db.rank_candidates_with_params(&query, &[10, 11, 12, 13], 4, true, &odd).unwrap();
+    assert_eq!(ranked.len(), 2);
+    assert_eq!(db.explain_pair_with_params(&query, 10, &odd).unwrap().missed, Some(MissReason::Filtered));
+}
+
+#[test]
+fn test_budget_guard_fails_slow_filters() {
+    let db = build_db(config(QuantizerKind::PQ), clustered_vectors(2_000, DIMS, 8, 0.2, 3)).unwrap();
+    let query = db.get(0).unwrap().vector;
+
+    // SAFETY: the callback ignores its user data
+    let slow = unsafe { CandidateFilter::from_native(slow_admit_all, std::ptr::null_mut()) };
+    let guarded = SearchParams::builder().filter(slow.clone().with_budget(Duration::from_millis(5))).build();
+    match db.search_with_params(&query, 10, &guarded) {
+        Err(KhadyotaError::FilterBudgetExceeded { budget, calls }) => {
+            assert_eq!(budget, Duration::from_millis(5));
+            assert!(calls < 2_000, "{}", calls);
+        }
+        other => panic!("expected the budget to run out, got {:?}", other),
+    }
+
+    // A generous budget, or none, lets the same filter finish
+    let relaxed = SearchParams::builder().filter(slow.with_budget(Duration::from_secs(60))).build();
+    assert_eq!(db.search_with_params(&query, 10, &relaxed).unwrap().len(), 10);
+}
+
+#[test]
+fn test_filtered_searches_bypass_the_cache() {
+    static MODULUS: u32 = 2;
+    let mut db = build_db(config(QuantizerKind::PQ), clustered_vectors(500, DIMS, 8, 0.2, 3)).unwrap();
+    db.set_query_cache(Some(QueryCacheConfig::default()));
+    let query = db.get(1).unwrap().vector;
+
+    let unfiltered = db.search(&query, 5).unwrap();
+    let filtered = SearchParams::builder().filter(native_multiples(&MODULUS)).build();
+    for _ in 0..2 {
+        assert!(db.search_with_params(&query, 5, &filtered).unwrap().iter().all(|r| r.id % 2 == 0));
+    }
+    assert_eq!(result_ids(&db.search(&query, 5).unwrap()), result_ids(&unfiltered));
+    let stats = db.query_cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+}
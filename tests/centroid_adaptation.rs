@@ -0,0 +1,143 @@
+use khadyota::harness::{exact_neighbors, random_vectors, recall};
+use khadyota::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+use tempfile::TempDir;
+
+const DIMS: usize = 32;
+const CENTRES: usize = 16;
+const INITIAL: usize = 2_000;
+const STREAM: usize = 4_000;
+
+/// Rows around centres that each move in a straight line to the
+/// midpoint between their starting position and the next centre's, which
+/// is on the boundary between the clusters an index built at the start
+/// finds
+struct Drift {
+    start: Vec<Vec<f32>>,
+    end: Vec<Vec<f32>>,
+    noise: Normal<f32>,
+    rng: StdRng,
+}
+
+impl Drift {
+    fn new() -> Self {
+        let start = random_vectors(CENTRES, DIMS, 1);
+        let end = (0..CENTRES)
+            .map(|i| {
+                let next = &start[(i + 1) % CENTRES];
+                start[i].iter().zip(next).map(|(a, b)| (a + b) / 2.0).collect()
+            })
+            .collect();
+        Self {
+            start,
+            end,
+            noise: Normal::new(0.0, 0.1).unwrap(),
+            rng: StdRng::seed_from_u64(3),
+        }
+    }
+
+    fn row(&mut self, i: usize, t: f32) -> Vec<f32> {
+        let (start, end) = (&self.start[i % CENTRES], &self.end[i % CENTRES]);
+        start
+            .iter()
+            .zip(end)
+            .map(|(s, e)| s + t * (e - s) + self.noise.sample(&mut self.rng))
+            .collect()
+    }
+}
+
+fn config(adaptation: Option<CentroidAdaptation>) -> Config {
+    Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::None,
+        num_clusters: CENTRES,
+        num_probe: 1,
+        seed: Some(5),
+        encode_policy: EncodePolicy::EagerOnInsert,
+        online_centroid_adaptation: adaptation,
+        ..Default::default()
+    }
+}
+
+/// Build on the starting distribution, then insert a stream that drifts
+/// away from it; returns the database and queries from where it ended
+fn drifted_db(adaptation: Option<CentroidAdaptation>) -> (VectorDB, Vec<Vec<f32>>) {
+    let mut drift = Drift::new();
+    let mut db = VectorDB::new(config(adaptation)).unwrap();
+    db.insert_batch((0..INITIAL).map(|i| (drift.row(i, 0.0), None))).unwrap();
+    db.build_index().unwrap();
+    for i in 0..STREAM {
+        db.insert(drift.row(i, i as f32 / STREAM as f32), None).unwrap();
+    }
+    let queries = (0..100).map(|i| drift.row(i, 1.0)).collect();
+    (db, queries)
+}
+
+/// Mean recall@10 probing a single cluster
+fn probed_recall(db: &VectorDB, queries: &[Vec<f32>]) -> f64 {
+    let params = SearchParams::builder().num_probe(1).build();
+    let total: f64 = queries
+        .iter()
+        .map(|q| recall(&db.search_with_params(q, 10, &params).unwrap(), &exact_neighbors(db, q, 10).unwrap()))
+        .sum();
+    total / queries.len() as f64
+}
+
+#[test]
+fn test_adaptation_keeps_recall_under_drift() {
+    let (fixed, queries) = drifted_db(None);
+    let (adapted, _) = drifted_db(Some(CentroidAdaptation::default()));
+
+    let fixed_recall = probed_recall(&fixed, &queries);
+    let adapted_recall = probed_recall(&adapted, &queries);
+    println!("recall@10 at num_probe=1: fixed {:.3}, adapted {:.3}", fixed_recall, adapted_recall);
+    assert!(adapted_recall > fixed_recall + 0.05, "{} vs {}", adapted_recall, fixed_recall);
+}
+
+fn centroids(db: &VectorDB) -> Vec<Vec<f32>> {
+    let options = OverviewOptions {
+        include_vectors: true,
+        ..Default::default()
+    };
+    let mut clusters = db.cluster_overview_with(&options).unwrap().clusters;
+    clusters.sort_by_key(|c| c.cluster);
+    clusters.into_iter().map(|c| c.centroid.unwrap()).collect()
+}
+
+#[test]
+fn test_adapted_centroids_are_finite_and_saved() {
+    let (fixed, queries) = drifted_db(None);
+    let (mut adapted, _) = drifted_db(Some(CentroidAdaptation {
+        min_learning_rate: 0.01,
+        reassign_checks: 8,
+    }));
+
+    let moved = centroids(&adapted);
+    assert!(moved.iter().flatten().all(|x| x.is_finite()));
+    assert_ne!(moved, centroids(&fixed));
+    // Every live row is still listed exactly once
+    let listed: usize = adapted.cluster_overview(0).unwrap().clusters.iter().map(|c| c.size).sum();
+    assert_eq!(listed, INITIAL + STREAM);
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("adapted.kdb");
+    adapted.save(&path).unwrap();
+    let mut loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(centroids(&loaded), moved);
+    let params = SearchParams::builder().num_probe(1).build();
+    let hits = |db: &VectorDB, query: &[f32]| -> Vec<(u32, f32)> {
+        db.search_with_params(query, 10, &params).unwrap().iter().map(|r| (r.id, r.distance)).collect()
+    };
+    for query in &queries[..10] {
+        assert_eq!(hits(&loaded, query), hits(&adapted, query));
+    }
+
+    // Both keep adapting the same way after the round trip
+    let row = vec![0.25; DIMS];
+    adapted.insert(row.clone(), None).unwrap();
+    loaded.insert(row, None).unwrap();
+    assert_eq!(centroids(&loaded), centroids(&adapted));
+}
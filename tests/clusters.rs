@@ -0,0 +1,99 @@
+//! The IVF coarse quantizer as a clustering of the corpus
+
+use khadyota::harness::clustered_vectors;
+use khadyota::indexing::IVFIndex;
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 16;
+
+/// Vector `i` lies in blob `i % 3`
+fn blobs(count: usize) -> Vec<Vec<f32>> {
+    clustered_vectors(count, DIMENSIONS, 3, 0.05, 11)
+}
+
+fn three_blob_db() -> VectorDB {
+    let config = Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::None,
+        num_clusters: 3,
+        num_probe: 1,
+        seed: Some(5),
+        ..Default::default()
+    };
+    let mut db = VectorDB::new(config).unwrap();
+    db.insert_batch(blobs(600).into_iter().map(|v| (v, None))).unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+/// Cluster of each blob, checking every member agrees
+fn blob_clusters(db: &VectorDB, rows: u32) -> [usize; 3] {
+    let clusters = [0, 1, 2].map(|blob| db.cluster_of(blob).unwrap());
+    for id in 0..rows {
+        if db.contains(id) {
+            assert_eq!(db.cluster_of(id).unwrap(), clusters[id as usize % 3], "id {}", id);
+        }
+    }
+    clusters
+}
+
+#[test]
+fn test_blobs_share_a_cluster_and_supply_its_exemplars() {
+    let mut db = three_blob_db();
+    let clusters = blob_clusters(&db, 600);
+    let mut distinct = clusters;
+    distinct.sort();
+    assert_eq!(distinct, [0, 1, 2]);
+
+    let summary = db.cluster_summary(5).unwrap();
+    assert_eq!(summary.iter().map(|s| s.cluster).collect::<Vec<_>>(), [0, 1, 2]);
+    for (blob, &cluster) in clusters.iter().enumerate() {
+        let s = &summary[cluster];
+        assert_eq!(s.size, 200);
+        assert_eq!(s.exemplars.len(), 5);
+        assert!(s.exemplars.iter().all(|&id| id as usize % 3 == blob), "{:?}", s.exemplars);
+        assert!(s.sample.is_empty() && s.centroid.as_ref().unwrap().len() == DIMENSIONS);
+    }
+
+    // Deleted members drop out
+    db.delete(0).unwrap();
+    assert!(matches!(db.cluster_of(0), Err(KhadyotaError::VectorNotFound(0))));
+    assert_eq!(db.cluster_summary(1).unwrap()[clusters[0]].size, 199);
+
+    // Rows the index has yet to list, new or moved, report the cluster
+    // they would join
+    let id = db.insert(blobs(601)[600].clone(), None).unwrap();
+    db.update(1, blobs(3)[2].clone(), None).unwrap();
+    assert_eq!((db.cluster_of(id).unwrap(), db.cluster_of(1).unwrap()), (clusters[0], clusters[2]));
+    assert!(matches!(db.cluster_summary(1), Err(KhadyotaError::IndexNotBuilt)));
+
+    let unbuilt = VectorDB::new(Config { dimensions: DIMENSIONS, ..Default::default() }).unwrap();
+    assert!(matches!(unbuilt.cluster_of(0), Err(KhadyotaError::IndexNotBuilt)));
+}
+
+#[test]
+fn test_assignments_and_summaries_survive_save_and_load() {
+    let dir = TempDir::new().unwrap();
+    let db = three_blob_db();
+    let path = dir.path().join("blobs.kdb");
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(blob_clusters(&loaded, 600), blob_clusters(&db, 600));
+    assert_eq!(loaded.cluster_summary(3).unwrap(), db.cluster_summary(3).unwrap());
+}
+
+#[test]
+fn test_index_exposes_centroids_and_members() {
+    let vectors = blobs(300);
+    let mut ivf = IVFIndex::new(DIMENSIONS, 3, 1);
+    ivf.build_seeded(&vectors, 3, Some(5));
+
+    let cluster = ivf.assign(&vectors[0]);
+    assert_eq!(ivf.centroid(cluster), Some(ivf.centroids()[cluster].as_slice()));
+    assert_eq!((ivf.centroid(3), ivf.cluster_members(3)), (None, &[][..]));
+    let members = ivf.cluster_members(cluster);
+    assert_eq!(members.len(), 100);
+    assert!(members.iter().all(|&id| id % 3 == 0 && ivf.cluster_of(id) == Some(cluster)));
+}
@@ -0,0 +1,178 @@
+use khadyota::*;
+use tempfile::TempDir;
+
+fn vector(i: usize) -> Vec<f32> {
+    (0..32).map(|j| ((i * 32 + j) as f32 * 0.11).sin()).collect()
+}
+
+fn build_db() -> VectorDB {
+    let config = Config {
+        dimensions: 32,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 4,
+        num_clusters: 16,
+        num_probe: 4,
+        normalize: false,
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..1500 {
+        db.insert(vector(i), Some(serde_json::json!({"i": i}))).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+#[test]
+fn test_reranked_search_through_tiny_cache() {
+    let dir = TempDir::new().unwrap();
+    let mut db = build_db();
+
+    let params = SearchParams::builder().rerank(40).build();
+    let queries: Vec<Vec<f32>> = (0..20).map(|i| vector(i * 7 + 3)).collect();
+    let expected: Vec<Vec<(u32, f32)>> = queries
+        .iter()
+        .map(|q| {
+            db.search_with_params(q, 5, &params)
+                .unwrap()
+                .iter()
+                .map(|r| (r.id, r.distance))
+                .collect()
+        })
+        .collect();
+
+    // 64 rows of 32 floats
+    let cache_bytes = 64 * 32 * 4;
+    db.spill_originals(&dir.path().join("originals.bin"), cache_bytes).unwrap();
+    assert_eq!(db.len(), 1500);
+
+    // Repeat each query so its rerank candidates are still cached
+    for (query, expected) in queries.iter().zip(&expected) {
+        for _ in 0..3 {
+            let results: Vec<(u32, f32)> = db
+                .search_with_params(query, 5, &params)
+                .unwrap()
+                .iter()
+                .map(|r| (r.id, r.distance))
+                .collect();
+            assert_eq!(&results, expected);
+        }
+    }
+
+    let stats = db.cold_cache_stats().unwrap();
+    assert!(stats.hit_rate() > 0.0, "{:?}", stats);
+    assert!(stats.cached_bytes <= cache_bytes);
+    assert!(stats.misses > 0);
+
+    let entry = db.get(42).unwrap();
+    assert_eq!(entry.vector, vector(42));
+    assert_eq!(entry.metadata.unwrap()["i"], 42);
+    assert!(matches!(db.get(5000), Err(KhadyotaError::VectorNotFound(5000))));
+
+    let health = db.health();
+    assert!(health.is_ready());
+    assert!(health.component("storage").unwrap().detail.contains("on disk"));
+}
+
+#[test]
+fn test_spilled_db_accepts_inserts_and_saves() {
+    let dir = TempDir::new().unwrap();
+    let mut db = build_db();
+    db.spill_originals(&dir.path().join("originals.bin"), 4096).unwrap();
+
+    let id = db.insert(vector(9999), None).unwrap();
+    assert_eq!(db.get(id).unwrap().vector, vector(9999));
+
+    // Rebuilding reads originals back from disk
+    db.build_index().unwrap();
+    let params = SearchParams::builder().rerank(50).build();
+    let results = db.search_with_params(&vector(9999), 1, &params).unwrap();
+    assert_eq!(results[0].id, id);
+
+    // Spilling again folds the new row into the file
+    db.spill_originals(&dir.path().join("originals.bin"), 4096).unwrap();
+    assert_eq!(db.get(id).unwrap().vector, vector(9999));
+
+    // Saved files always hold the originals inline
+    let path = dir.path().join("db.kdb");
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.len(), 1501);
+    assert!(loaded.cold_cache_stats().is_none());
+    assert_eq!(loaded.get(7).unwrap().vector, vector(7));
+}
+
+#[test]
+fn test_load_mapped_matches_in_memory() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.khadyota");
+    let db = build_db();
+    db.save_mapped(&path).unwrap();
+
+    // The state file holds no rows; they are in the flat file beside it
+    let flat = 32 + 1500 * 32 * 4;
+    assert_eq!(std::fs::metadata(dir.path().join("db.khadyota.vectors")).unwrap().len(), flat);
+    assert!(std::fs::metadata(&path).unwrap().len() < flat / 2);
+
+    let mut mapped = VectorDB::load_mapped(&path, 64 * 32 * 4).unwrap();
+    assert!(mapped.cold_cache_stats().is_some());
+    assert_eq!(mapped.checksum(), db.checksum());
+
+    let rerank = SearchParams::builder().rerank(40).build();
+    let ids = |db: &VectorDB, query: &[f32], params: &SearchParams| -> Vec<(u32, f32)> {
+        db.search_with_params(query, 5, params).unwrap().iter().map(|r| (r.id, r.distance)).collect()
+    };
+    for i in 0..20 {
+        let query = vector(i * 7 + 3);
+        assert_eq!(ids(&mapped, &query, &SearchParams::default()), ids(&db, &query, &SearchParams::default()));
+        assert_eq!(ids(&mapped, &query, &rerank), ids(&db, &query, &rerank));
+    }
+    assert_eq!(mapped.get(42).unwrap().vector, vector(42));
+
+    // Saving over the files it maps, then rebuilding from mapped rows
+    mapped.insert(vector(1500), None).unwrap();
+    mapped.save_mapped(&path).unwrap();
+    let mut reloaded = VectorDB::load_mapped(&path, 0).unwrap();
+    assert_eq!(reloaded.len(), 1501);
+    assert_eq!(reloaded.get(1500).unwrap().vector, vector(1500));
+    reloaded.build_index().unwrap();
+    let exhaustive = SearchParams::builder().num_probe(16).rerank(200).build();
+    assert_eq!(reloaded.search_with_params(&vector(1500), 1, &exhaustive).unwrap()[0].id, 1500);
+}
+
+#[test]
+fn test_empty_database_saves_mapped_without_vector_file() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.khadyota");
+    build_db().save_mapped(&path).unwrap();
+
+    VectorDB::new(Config::for_dimensions(32)).unwrap().save_mapped(&path).unwrap();
+    assert!(!dir.path().join("db.khadyota.vectors").exists());
+    assert!(VectorDB::load_mapped(&path, 0).unwrap().is_empty());
+}
+
+#[test]
+fn test_reader_of_spilled_db_shares_the_file() {
+    let dir = TempDir::new().unwrap();
+    let mut db = build_db();
+    db.spill_originals(&dir.path().join("originals.bin"), 64 * 32 * 4).unwrap();
+    db.insert(vector(1500), None).unwrap();
+
+    let rows: Vec<Vec<f32>> = [3, 1500, 7].iter().map(|&id| db.get(id).unwrap().vector).collect();
+
+    let reader = db.reader();
+    db.update(7, vector(9), None).unwrap();
+
+    // The spilled rows, the unspilled tail, and the row as it was
+    assert_eq!(reader.get(3).unwrap().vector, rows[0]);
+    assert_eq!(reader.get(1500).unwrap().vector, rows[1]);
+    assert_eq!(reader.get(7).unwrap().vector, rows[2]);
+    assert_ne!(db.get(7).unwrap().vector, rows[2]);
+
+    // Every cluster is probed: the unseeded build may list 40 anywhere
+    let params = SearchParams::builder().num_probe(16).rerank(40).build();
+    let results = reader.search_with_params(&vector(40), 5, &params).unwrap();
+    assert_eq!(results[0].id, 40);
+    assert_eq!(reader.as_db().cold_cache_stats().unwrap().capacity_bytes, 64 * 32 * 4);
+}
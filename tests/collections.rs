@@ -0,0 +1,107 @@
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use tempfile::TempDir;
+
+fn config(dimensions: usize, quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions,
+        metric: DistanceMetric::Euclidean,
+        quantizer,
+        pq_subvectors: 4,
+        num_clusters: 4,
+        num_probe: 4,
+        seed: Some(11),
+        ..Default::default()
+    }
+}
+
+/// A database of 64-dim bodies with a 16-dim "titles" collection, both
+/// built, with ids aligned between them
+fn titles_and_bodies() -> VectorDB {
+    let mut db = VectorDB::new(config(64, QuantizerKind::PQ)).unwrap();
+    let titles = db.create_collection("titles", config(16, QuantizerKind::None)).unwrap();
+    for (i, title) in clustered_vectors(300, 16, 4, 0.05, 1).into_iter().enumerate() {
+        titles.insert(title, Some(serde_json::json!({"doc": i}))).unwrap();
+    }
+    titles.build_index().unwrap();
+
+    for (i, body) in clustered_vectors(300, 64, 4, 0.05, 2).into_iter().enumerate() {
+        db.insert(body, Some(serde_json::json!({"doc": i}))).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn ids(results: &[SearchResult]) -> Vec<u32> {
+    results.iter().map(|r| r.id).collect()
+}
+
+#[test]
+fn test_collections_of_different_widths_search_independently() {
+    let db = titles_and_bodies();
+    let titles = db.collection("titles").unwrap();
+    let bodies = db.collection(DEFAULT_COLLECTION).unwrap();
+    assert_eq!((titles.len(), bodies.len()), (300, 300));
+
+    let title = titles.get(42).unwrap().vector;
+    let body = bodies.get(42).unwrap().vector;
+    assert_eq!(titles.search(&title, 1).unwrap()[0].id, 42);
+    assert!(bodies.search(&body, 5).unwrap().iter().any(|r| r.id == 42));
+
+    // Each collection checks queries against its own width
+    assert!(matches!(titles.search(&body, 1), Err(KhadyotaError::DimensionMismatch { expected: 16, got: 64 })));
+    assert!(matches!(db.search(&title, 1), Err(KhadyotaError::DimensionMismatch { expected: 64, got: 16 })));
+}
+
+#[test]
+fn test_collections_round_trip_in_one_file() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("docs.kdb");
+    let mut db = titles_and_bodies();
+    db.create_collection("empty", config(8, QuantizerKind::None)).unwrap();
+    db.collection_mut("titles").unwrap().delete(7).unwrap();
+    db.save(&path).unwrap();
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.collection_names().collect::<Vec<_>>(), db.collection_names().collect::<Vec<_>>());
+    for name in db.collection_names() {
+        let (before, after) = (db.collection(name).unwrap(), loaded.collection(name).unwrap());
+        assert_eq!(after.len(), before.len(), "{}", name);
+        assert_eq!(after.get_metadata(3), before.get_metadata(3), "{}", name);
+        if before.is_empty() {
+            continue;
+        }
+        let query = before.get(3).unwrap().vector;
+        assert_eq!(ids(&after.search(&query, 10).unwrap()), ids(&before.search(&query, 10).unwrap()), "{}", name);
+    }
+    assert!(!loaded.collection("titles").unwrap().contains(7));
+
+    // Incremental saves rewrite collections changed through their handles
+    let mut loaded = loaded;
+    let titles = loaded.collection_mut("titles").unwrap();
+    let added = titles.insert(vec![0.5; 16], None).unwrap();
+    loaded.save_incremental(&path).unwrap();
+    assert!(VectorDB::load(&path).unwrap().collection("titles").unwrap().contains(added));
+}
+
+#[test]
+fn test_single_collection_files_open_as_the_default() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("plain.kdb");
+    let mut plain = VectorDB::new(config(16, QuantizerKind::None)).unwrap();
+    plain.insert(vec![1.0; 16], None).unwrap();
+    plain.save(&path).unwrap();
+
+    let mut loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.collection_names().collect::<Vec<_>>(), [DEFAULT_COLLECTION]);
+    assert_eq!(loaded.collection(DEFAULT_COLLECTION).unwrap().len(), 1);
+
+    // Adding a collection leaves the default one where it was
+    loaded.create_collection("extra", config(4, QuantizerKind::None)).unwrap();
+    loaded.save(&path).unwrap();
+    let reloaded = VectorDB::load(&path).unwrap();
+    assert_eq!(reloaded.get(0).unwrap().vector, vec![1.0; 16]);
+    assert!(reloaded.collection("extra").unwrap().is_empty());
+    assert!(reloaded.collection("missing").is_err());
+}
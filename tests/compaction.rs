@@ -0,0 +1,121 @@
+//! Compacting away deleted rows: ids renumbered, files shrunk, nothing
+//! retrained
+
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use serde_json::json;
+use std::io::BufReader;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 16;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 4,
+        num_clusters: 16,
+        num_probe: 16,
+        seed: Some(3),
+        ..Default::default()
+    }
+}
+
+/// A built database over `vectors`, each tagged with its position
+fn build(config: Config, vectors: &[Vec<f32>]) -> VectorDB {
+    let mut db = VectorDB::new(config).unwrap();
+    for (i, vector) in vectors.iter().enumerate() {
+        db.insert(vector.clone(), Some(json!({ "i": i }))).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn saved_size(db: &VectorDB, dir: &TempDir, name: &str) -> u64 {
+    let path = dir.path().join(name);
+    db.save(&path).unwrap();
+    std::fs::metadata(&path).unwrap().len()
+}
+
+#[test]
+fn test_compaction_drops_deleted_rows_and_renumbers_the_rest() {
+    let dir = TempDir::new().unwrap();
+    let vectors = clustered_vectors(4_000, DIMENSIONS, 16, 0.3, 1);
+
+    for config in [config(), Config { use_residuals: true, ..config() }] {
+        let mut db = build(config, &vectors);
+        for id in (0..vectors.len() as u32).filter(|id| id % 2 == 1) {
+            db.delete(id).unwrap();
+        }
+        let before = saved_size(&db, &dir, "before.kdb");
+        let clusters = db.index_stats().unwrap().num_clusters;
+
+        let report = db.compact().unwrap();
+        assert_eq!(report.removed, 2_000);
+        assert_eq!(db.len(), 2_000);
+        assert_eq!((report.new_id(0), report.new_id(1), report.new_id(3_998)), (Some(0), None, Some(1_999)));
+        assert!(db.index_status().current);
+        assert_eq!(db.index_stats().unwrap().num_clusters, clusters);
+        assert_eq!(db.index_stats().unwrap().total_vectors, 2_000);
+
+        // Every row answers to its new id, metadata along with it, and
+        // searches only ever return live rows
+        for old in (0..vectors.len()).step_by(50) {
+            let query = &vectors[old];
+            let results = db.search(query, 10).unwrap();
+            assert!(results.iter().all(|r| db.contains(r.id)), "{:?}", results);
+            let Some(new) = report.new_id(old as u32) else {
+                continue;
+            };
+            assert_eq!(results[0].id, new);
+            let entry = db.get(new).unwrap();
+            assert_eq!(entry.vector, *query);
+            assert_eq!(entry.metadata, Some(json!({ "i": old })));
+        }
+        assert!(db.get(2_000).is_err());
+        assert_eq!(db.insert(vectors[1].clone(), None).unwrap(), 2_000);
+
+        let after = saved_size(&db, &dir, "after.kdb");
+        assert!(after * 10 < before * 6, "{} bytes from {}", after, before);
+        let loaded = VectorDB::load(&dir.path().join("after.kdb")).unwrap();
+        assert_eq!(loaded.checksum(), db.checksum());
+    }
+}
+
+#[test]
+fn test_nothing_to_compact() {
+    let vectors = clustered_vectors(500, DIMENSIONS, 16, 0.3, 2);
+    let mut db = build(config(), &vectors);
+    let checksum = db.checksum();
+    let seq = db.applied_seq();
+
+    let report = db.compact().unwrap();
+    assert_eq!(report.removed, 0);
+    assert_eq!(report.id_map.len(), 500);
+    assert!(report.id_map.iter().enumerate().all(|(old, new)| *new == Some(old as u32)));
+    assert_eq!((db.checksum(), db.applied_seq()), (checksum, seq));
+}
+
+#[test]
+fn test_changelog_readers_compact_alike() {
+    let dir = TempDir::new().unwrap();
+    let snapshot = dir.path().join("db.kdb");
+    let log = dir.path().join("changes.log");
+    let vectors = clustered_vectors(1_000, DIMENSIONS, 16, 0.3, 3);
+
+    let mut db = build(config(), &vectors);
+    db.save(&snapshot).unwrap();
+    db.set_changelog(Some(Box::new(std::fs::File::create(&log).unwrap()))).unwrap();
+    for id in [3, 10, 500, 999] {
+        db.delete(id).unwrap();
+    }
+    db.compact().unwrap();
+    db.insert(vectors[10].clone(), Some(json!({ "i": "again" }))).unwrap();
+
+    let mut reader = VectorDB::load(&snapshot).unwrap();
+    reader.apply_changelog(BufReader::new(std::fs::File::open(&log).unwrap())).unwrap();
+    assert_eq!(reader.checksum(), db.checksum());
+    assert_eq!(reader.len(), 997);
+    assert_eq!(reader.get(996).unwrap().metadata, Some(json!({ "i": "again" })));
+    assert_eq!(reader.search(&vectors[998], 1).unwrap()[0].id, 995);
+}
@@ -0,0 +1,195 @@
+use khadyota::indexing::IVFIndex;
+use khadyota::quantization::PQCodec;
+use khadyota::storage::QuantizedVectors;
+use khadyota::*;
+
+const DIMS: usize = 32;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 8,
+        num_clusters: 4,
+        num_probe: 2,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+fn dataset(n: usize, dims: usize) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|i| (0..dims).map(|j| ((i * dims + j) as f32 * 0.41).sin()).collect())
+        .collect()
+}
+
+fn codes(vectors: &[Vec<f32>], subvectors: usize) -> QuantizedVectors {
+    let codec = PQCodec::train_seeded(vectors, subvectors, Some(2)).unwrap();
+    let mut quantized = QuantizedVectors::new(codec);
+    quantized.add_batch(vectors.to_vec());
+    quantized
+}
+
+fn ivf(vectors: &[Vec<f32>]) -> IVFIndex {
+    let dims = vectors[0].len();
+    let mut ivf = IVFIndex::new(dims, 4, 2);
+    ivf.build_seeded(vectors, 4, Some(3));
+    ivf
+}
+
+/// Compose the parts and return the violations reported
+fn violations(
+    vectors: Vec<Vec<f32>>,
+    quantized: Option<QuantizedVectors>,
+    ivf: Option<IVFIndex>,
+) -> Vec<Violation> {
+    match VectorDB::from_parts(config(), vectors, quantized, ivf) {
+        Err(KhadyotaError::IncompatibleComponents(report)) => report.violations,
+        Ok(_) => panic!("parts were accepted"),
+        Err(other) => panic!("expected IncompatibleComponents, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_matching_parts_compose() {
+    let vectors = dataset(400, DIMS);
+    let db = VectorDB::from_parts(config(), vectors.clone(), Some(codes(&vectors, 8)), Some(ivf(&vectors))).unwrap();
+
+    assert!(db.check_compatibility().is_compatible());
+    assert_eq!(db.len(), 400);
+    assert_eq!(db.search(&vectors[17], 1).unwrap()[0].id, 17);
+
+    // Codes may lag behind rows inserted since the last build
+    let mut db = VectorDB::from_parts(config(), vectors[..300].to_vec(), Some(codes(&vectors[..200], 8)), Some(ivf(&vectors[..200]))).unwrap();
+    db.insert(vectors[300].clone(), None).unwrap();
+    assert!(db.check_compatibility().is_compatible());
+}
+
+#[test]
+fn test_codec_mismatches() {
+    let vectors = dataset(400, DIMS);
+
+    // Trained at 4 subvectors; the config wants 8
+    assert_eq!(
+        violations(vectors.clone(), Some(codes(&vectors, 4)), None),
+        [Violation::SubvectorCount { expected: 8, found: 4 }]
+    );
+
+    // 4-bit codes where the config asks for 8
+    let codec = PQCodec::train_with_progress(&vectors, 8, Some(2), DistanceMetric::Euclidean, 4, &Silent).unwrap();
+    assert_eq!(
+        violations(vectors.clone(), Some(QuantizedVectors::new(codec)), None),
+        [Violation::CodeBits { expected: 8, found: 4 }]
+    );
+
+    // Trained on 16-dimensional data
+    let narrow = dataset(400, 16);
+    assert_eq!(
+        violations(vectors.clone(), Some(codes(&narrow, 8)), None),
+        [Violation::CodecDimensions { expected: 32, found: 16 }]
+    );
+
+    // Broken codebooks
+    let mut codec = PQCodec::train_seeded(&vectors, 8, Some(2)).unwrap();
+    codec.codebooks[5].centroids[0].push(0.0);
+    codec.codebooks.pop();
+    assert_eq!(
+        violations(Vec::new(), Some(QuantizedVectors::new(codec)), None),
+        [
+            Violation::CodebookCount { expected: 8, found: 7 },
+            Violation::CodebookDimensions { subvector: 5, expected: 4, found: 5 },
+        ]
+    );
+}
+
+#[test]
+fn test_code_mismatches() {
+    let vectors = dataset(400, DIMS);
+
+    // Codes from a 4-subvector codec under an 8-subvector one cannot be
+    // packed together
+    let quantized = codes(&vectors, 8);
+    let mut rows: Vec<Vec<u8>> = quantized.iter_codes().map(<[u8]>::to_vec).collect();
+    for id in [10, 11, 12] {
+        rows[id] = vec![0; 4];
+    }
+    match QuantizedVectors::from_codes(quantized.codec().clone(), rows) {
+        Err(KhadyotaError::IncompatibleComponents(report)) => assert_eq!(
+            report.violations,
+            [Violation::CodeWidth { expected: 8, found: 4, rows: 3, first_id: 10 }]
+        ),
+        other => panic!("expected IncompatibleComponents, got {:?}", other.err()),
+    }
+
+    // Codebooks trained on 100 vectors have 100 centroids
+    let codec = PQCodec::train_seeded(&vectors[..100], 8, Some(2)).unwrap();
+    let mut quantized = QuantizedVectors::new(codec);
+    quantized.add_batch(vectors.clone());
+    quantized.put_codes(7, vec![0, 0, 0, 200, 0, 0, 0, 0]);
+    assert_eq!(
+        violations(vectors.clone(), Some(quantized), None),
+        [Violation::CodeOutOfRange { subvector: 3, code: 200, centroids: 100, rows: 1, first_id: 7 }]
+    );
+
+    // More codes than vectors
+    assert_eq!(
+        violations(vectors[..300].to_vec(), Some(codes(&vectors, 8)), None),
+        [Violation::CodeCount { expected: 300, found: 400 }]
+    );
+}
+
+#[test]
+fn test_index_mismatches() {
+    let vectors = dataset(400, DIMS);
+
+    let narrow = dataset(400, 16);
+    assert_eq!(
+        violations(vectors.clone(), None, Some(ivf(&narrow))),
+        [
+            Violation::IndexDimensions { expected: 32, found: 16 },
+            Violation::CentroidDimensions { expected: 32, found: 16, centroids: 4, first: 0 },
+        ]
+    );
+
+    // Built over 400 vectors, paired with 300
+    let violations_found = violations(vectors[..300].to_vec(), None, Some(ivf(&vectors)));
+    assert!(matches!(
+        violations_found.as_slice(),
+        [Violation::IdOutOfRange { vectors: 300, ids: 100, .. }]
+    ));
+
+    // Listed ids without codes
+    let violations_found = violations(vectors.clone(), Some(codes(&vectors[..300], 8)), Some(ivf(&vectors)));
+    assert!(matches!(
+        violations_found.as_slice(),
+        [Violation::UncodedIds { codes: 300, ids: 100, .. }]
+    ));
+
+    // An untrained index has lists but no centroids
+    assert_eq!(
+        violations(vectors, None, Some(IVFIndex::new(DIMS, 4, 2))),
+        [Violation::ListCount { centroids: 0, lists: 4 }]
+    );
+}
+
+#[test]
+fn test_every_violation_is_reported() {
+    let vectors = dataset(400, DIMS);
+    let narrow = dataset(400, 16);
+
+    let Err(err) = VectorDB::from_parts(config(), vectors[..300].to_vec(), Some(codes(&vectors, 4)), Some(ivf(&narrow))) else {
+        panic!("parts were accepted");
+    };
+    let KhadyotaError::IncompatibleComponents(report) = &err else {
+        panic!("expected IncompatibleComponents, got {:?}", err);
+    };
+    assert_eq!(report.violations.len(), 5, "{}", err);
+    assert!(err.to_string().starts_with(
+        "Incompatible components: codec has 4 subvectors, config has pq_subvectors = 8; \
+         400 code rows for 300 vectors; \
+         IVF index has 16 dimensions, collection has 32; \
+         4 IVF centroids have 16 dimensions instead of 32 (first: centroid 0); \
+         100 inverted-list entries point past the 300 stored vectors"
+    ));
+}
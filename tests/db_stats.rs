@@ -0,0 +1,101 @@
+//! Database-wide size and memory figures
+
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use serde_json::json;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 32;
+const ROWS: usize = 2_000;
+
+fn config(quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        quantizer,
+        pq_subvectors: 8,
+        num_clusters: 16,
+        num_probe: 4,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+fn filled(quantizer: QuantizerKind) -> VectorDB {
+    let mut db = VectorDB::new(config(quantizer)).unwrap();
+    for (i, vector) in clustered_vectors(ROWS, DIMENSIONS, 16, 0.3, 1).into_iter().enumerate() {
+        db.insert(vector, Some(json!({ "i": i }))).unwrap();
+    }
+    db
+}
+
+#[test]
+fn test_empty_and_unbuilt_databases() {
+    let stats = VectorDB::new(config(QuantizerKind::PQ)).unwrap().stats();
+    assert_eq!((stats.vectors, stats.dimensions, stats.index_built), (0, DIMENSIONS, false));
+    assert_eq!(stats.heap_bytes(), 0);
+    assert_eq!(stats.compression_ratio, None);
+
+    let stats = filled(QuantizerKind::PQ).stats();
+    assert_eq!(stats.vectors, ROWS);
+    assert!(!stats.index_built);
+    assert_eq!(stats.vector_bytes, ROWS * DIMENSIONS * 4);
+    assert_eq!(stats.index_bytes(), 0);
+    assert!(stats.metadata_bytes >= ROWS * 7, "{}", stats.metadata_bytes);
+    assert_eq!(stats.compression_ratio, None);
+}
+
+#[test]
+fn test_built_indexes_report_their_parts() {
+    let mut db = filled(QuantizerKind::PQ);
+    db.build_index().unwrap();
+    db.delete(5).unwrap();
+    let stats = db.stats();
+
+    assert!(stats.index_built);
+    assert_eq!((stats.vectors, stats.deleted), (ROWS - 1, 1));
+    // Deleted rows keep their vector and codes until compacted
+    assert_eq!(stats.vector_bytes, ROWS * DIMENSIONS * 4);
+    assert_eq!(stats.code_bytes, ROWS * 8);
+    // 256 centroids per 4-wide subvector, per subvector
+    assert_eq!(stats.codebook_bytes, 8 * 256 * 4 * 4);
+    assert_eq!(stats.centroid_bytes, 16 * DIMENSIONS * 4);
+    assert!(stats.inverted_list_bytes >= (ROWS - 1) * 4);
+    let ratio = stats.compression_ratio.unwrap();
+    assert!((1.0..16.0).contains(&ratio), "{}", ratio);
+    assert_eq!(
+        stats.heap_bytes(),
+        stats.vector_bytes + stats.index_bytes() + stats.metadata_bytes + stats.external_metadata_bytes
+    );
+
+    let sq8 = {
+        let mut db = filled(QuantizerKind::SQ8);
+        db.build_index().unwrap();
+        db.stats()
+    };
+    assert_eq!(sq8.code_bytes, ROWS * DIMENSIONS);
+    assert_eq!(sq8.codebook_bytes, 2 * DIMENSIONS * 4);
+
+    let text = stats.to_string();
+    assert!(text.starts_with("1999 vectors (1 deleted) x 32 dimensions"), "{}", text);
+    assert!(text.contains("compression"), "{}", text);
+    let value = serde_json::to_value(&stats).unwrap();
+    assert_eq!(value["code_bytes"], json!(ROWS * 8));
+    assert_eq!(serde_json::from_value::<DbStats>(value).unwrap(), stats);
+}
+
+#[test]
+fn test_spilled_rows_count_as_mapped() {
+    let dir = TempDir::new().unwrap();
+    let mut db = filled(QuantizerKind::PQ);
+    db.build_index().unwrap();
+    let in_memory = db.stats();
+    db.spill_originals(&dir.path().join("originals.bin"), 4096).unwrap();
+    db.get(3).unwrap();
+
+    let stats = db.stats();
+    assert_eq!(stats.mapped_vector_bytes, ROWS * DIMENSIONS * 4);
+    assert!(stats.vector_bytes <= 4096, "{}", stats.vector_bytes);
+    assert_eq!(stats.index_bytes(), in_memory.index_bytes());
+    assert_eq!(stats.compression_ratio, in_memory.compression_ratio);
+}
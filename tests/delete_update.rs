@@ -0,0 +1,264 @@
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMS: usize = 32;
+
+fn vector(i: u32) -> Vec<f32> {
+    (0..DIMS as u32).map(|j| ((i * 31 + j * 7) as f32 * 0.29).sin()).collect()
+}
+
+fn build_db(use_pq: bool) -> VectorDB {
+    let config = Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 8,
+        num_clusters: 10,
+        num_probe: 10,
+        seed: Some(4),
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..1_000 {
+        db.insert(vector(i), Some(serde_json::json!({"i": i}))).unwrap();
+    }
+    db
+}
+
+/// `batch_search` refuses to run on an unbuilt or stale index
+fn index_built(db: &VectorDB) -> bool {
+    db.batch_search(&[vector(0)], 1).is_ok()
+}
+
+fn top_ids(db: &VectorDB, query: &[f32], k: usize) -> Vec<u32> {
+    let params = SearchParams::builder().rerank(k * 4).build();
+    ids_with(db, query, k, &params)
+}
+
+fn ids_with(db: &VectorDB, query: &[f32], k: usize, params: &SearchParams) -> Vec<u32> {
+    db.search_with_params(query, k, params).unwrap().iter().map(|r| r.id).collect()
+}
+
+#[test]
+fn test_deleted_ids_never_returned() {
+    // Without PQ, default params fall back to a linear scan and an explicit
+    // probe count scans the probed clusters exactly
+    let linear = SearchParams::default();
+    let probed = SearchParams::builder().num_probe(10).build();
+    let pq = SearchParams::builder().rerank(80).build();
+
+    for (use_pq, params) in [(false, linear), (false, probed), (true, pq)] {
+        let mut db = build_db(use_pq);
+        db.build_index().unwrap();
+
+        for id in (0..1_000).step_by(3) {
+            db.delete(id).unwrap();
+        }
+        assert_eq!(db.len(), 666);
+        assert!(index_built(&db));
+
+        for i in (0..1_000).step_by(50) {
+            let ids = ids_with(&db, &vector(i), 20, &params);
+            assert!(ids.iter().all(|id| id % 3 != 0), "{:?}", ids);
+            if i % 3 != 0 {
+                assert_eq!(ids[0], i);
+            }
+        }
+        assert!(matches!(db.get(0), Err(KhadyotaError::VectorNotFound(0))));
+    }
+}
+
+#[test]
+fn test_unknown_ids_are_not_found() {
+    let mut db = build_db(false);
+    db.delete(5).unwrap();
+
+    for id in [5, 1_000, u32::MAX] {
+        assert!(matches!(db.delete(id), Err(KhadyotaError::VectorNotFound(found)) if found == id));
+        assert!(matches!(db.update(id, vector(0), None), Err(KhadyotaError::VectorNotFound(found)) if found == id));
+    }
+    assert!(matches!(
+        db.update(6, vec![0.0; 3], None),
+        Err(KhadyotaError::DimensionMismatch { expected: 32, got: 3 })
+    ));
+}
+
+#[test]
+fn test_update_moves_vector_without_rebuild() {
+    let mut db = build_db(true);
+    db.build_index().unwrap();
+
+    // Entry 10 takes on entry 900's vector
+    db.update(10, vector(900), Some(serde_json::json!({"moved": true}))).unwrap();
+    assert!(index_built(&db));
+
+    let ids = top_ids(&db, &vector(900), 2);
+    assert!(ids.contains(&10) && ids.contains(&900), "{:?}", ids);
+    assert!(!top_ids(&db, &vector(10), 5).contains(&10));
+
+    let entry = db.get(10).unwrap();
+    assert_eq!(entry.vector, vector(900));
+    assert_eq!(entry.metadata, Some(serde_json::json!({"moved": true})));
+}
+
+#[test]
+fn test_tombstones_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+
+    let mut db = build_db(true);
+    db.build_index().unwrap();
+    db.delete(42).unwrap();
+    db.update(43, vector(500), None).unwrap();
+    db.save(&path).unwrap();
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    assert_eq!(loaded.len(), 999);
+    assert!(loaded.get(42).is_err());
+    assert_eq!(top_ids(&loaded, &vector(42), 10), top_ids(&db, &vector(42), 10));
+    assert!(!top_ids(&loaded, &vector(42), 10).contains(&42));
+}
+
+#[test]
+fn test_add_to_index_keeps_index_built() {
+    for use_pq in [false, true] {
+        let mut db = build_db(use_pq);
+        assert!(matches!(
+            db.add_to_index(vector(1_000), None),
+            Err(KhadyotaError::IndexNotBuilt)
+        ));
+
+        db.build_index().unwrap();
+        for i in 1_000..1_100 {
+            let id = db.add_to_index(vector(i), Some(serde_json::json!({"i": i}))).unwrap();
+            assert_eq!(id, i);
+        }
+        assert_eq!(db.len(), 1_100);
+        assert!(index_built(&db));
+
+        // Added vectors are found through the index, metadata included
+        let params = if use_pq {
+            SearchParams::builder().rerank(40).build()
+        } else {
+            SearchParams::builder().num_probe(10).build()
+        };
+        for i in (1_000..1_100).step_by(7) {
+            let results = db.search_with_params(&vector(i), 1, &params).unwrap();
+            assert_eq!(results[0].id, i);
+            assert_eq!(results[0].metadata, Some(serde_json::json!({"i": i})));
+        }
+
+        assert!(matches!(
+            db.add_to_index(vec![0.0; 3], None),
+            Err(KhadyotaError::DimensionMismatch { expected: 32, got: 3 })
+        ));
+    }
+}
+
+#[test]
+fn test_entries_read_back_after_save_and_load() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+
+    let mut db = build_db(true);
+    db.build_index().unwrap();
+    db.delete(7).unwrap();
+    db.save(&path).unwrap();
+    db.save_mapped(&dir.path().join("mapped.kdb")).unwrap();
+
+    let loaded = VectorDB::load(&path).unwrap();
+    let mapped = VectorDB::load_mapped(&dir.path().join("mapped.kdb"), 1 << 16).unwrap();
+    let stored = db.get(6).unwrap().vector;
+    for db in [&db, &loaded, &mapped] {
+        assert!(db.contains(6) && !db.contains(7) && !db.contains(1_000));
+        assert_eq!(db.get(6).unwrap().vector, stored);
+        assert_eq!(db.get_entry(6).unwrap().vector, stored);
+        assert!(db.get_entry(7).is_none() && db.get_entry(1_000).is_none());
+        assert_eq!(db.get_metadata(6), Some(&serde_json::json!({"i": 6})));
+        assert_eq!(db.get_metadata(7), None);
+        assert_eq!(db.get_metadata(1_000), None);
+
+        let entries: Vec<VectorEntry> = db.iter().collect();
+        assert_eq!(entries.len(), 999);
+        assert!(entries.windows(2).all(|w| w[0].id < w[1].id));
+        assert!(entries.iter().all(|e| e.id != 7));
+        assert_eq!(entries[6].vector, stored);
+        assert_eq!(entries[500].metadata, Some(serde_json::json!({"i": 501})));
+    }
+
+    // Metadata kept apart for its size is still the entry's metadata
+    let mut apart = VectorDB::new(Config {
+        dimensions: DIMS,
+        quantizer: QuantizerKind::None,
+        num_clusters: 2,
+        max_metadata_bytes: 64,
+        external_metadata: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let big = serde_json::json!({"blob": "x".repeat(200)});
+    apart.insert(vector(0), Some(big.clone())).unwrap();
+    apart.insert(vector(1), Some(big.clone())).unwrap();
+    apart.delete(1).unwrap();
+    apart.save(&dir.path().join("apart.kdb")).unwrap();
+    apart.save_mapped(&dir.path().join("apart-mapped.kdb")).unwrap();
+
+    let loaded = VectorDB::load(&dir.path().join("apart.kdb")).unwrap();
+    let mapped = VectorDB::load_mapped(&dir.path().join("apart-mapped.kdb"), 1 << 16).unwrap();
+    for db in [&apart, &loaded, &mapped] {
+        assert_eq!(db.get_metadata(0), Some(&big));
+        assert_eq!(db.get_metadata(1), None);
+    }
+}
+
+#[test]
+fn test_search_by_id_returns_others() {
+    for use_pq in [false, true] {
+        let mut db = build_db(use_pq);
+        // Unbuilt, it refuses just as `search` does
+        assert!(matches!(db.search_by_id(7, 5), Err(KhadyotaError::IndexNotBuilt)));
+        db.build_index().unwrap();
+
+        let stored = db.get(7).unwrap().vector;
+        let params = SearchParams::builder().exclude([7]).build();
+        let expected: Vec<u32> = db.search_with_params(&stored, 5, &params).unwrap().iter().map(|r| r.id).collect();
+        let found: Vec<u32> = db.search_by_id(7, 5).unwrap().iter().map(|r| r.id).collect();
+        assert_eq!(found, expected, "pq {}", use_pq);
+        assert_eq!(found.len(), 5);
+        assert!(!found.contains(&7));
+
+        // Rows inserted after the build are searched from too
+        let late = db.insert(vector(7), None).unwrap();
+        assert_eq!(db.search_by_id(late, 1).unwrap()[0].id, 7);
+
+        db.delete(7).unwrap();
+        assert!(matches!(db.search_by_id(7, 5), Err(KhadyotaError::VectorNotFound(7))));
+        assert!(matches!(db.search_by_id(5_000, 5), Err(KhadyotaError::VectorNotFound(5_000))));
+    }
+}
+
+#[test]
+fn test_vectors_borrow_from_memory_and_copy_otherwise() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+
+    let mut db = build_db(false);
+    db.build_index().unwrap();
+    db.delete(7).unwrap();
+    db.save(&path).unwrap();
+    db.save_mapped(&dir.path().join("mapped.kdb")).unwrap();
+    assert!(matches!(db.get_vector(6), Some(std::borrow::Cow::Borrowed(_))));
+
+    let loaded = VectorDB::load(&path).unwrap();
+    let mapped = VectorDB::load_mapped(&dir.path().join("mapped.kdb"), 1 << 16).unwrap();
+    assert!(matches!(mapped.get_vector(6), Some(std::borrow::Cow::Owned(_))));
+    for db in [&db, &loaded, &mapped] {
+        let stored = db.get_vector(6).unwrap();
+        assert_eq!(*stored, vector(6)[..]);
+        assert!(db.get_vector(7).is_none() && db.get_vector(1_000).is_none());
+        // Read back to be searched for again
+        assert_eq!(db.search(&stored, 1).unwrap()[0].id, 6);
+    }
+}
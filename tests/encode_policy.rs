@@ -0,0 +1,129 @@
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMS: usize = 16;
+
+fn built_db(policy: EncodePolicy, use_pq: bool) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 2,
+        seed: Some(3),
+        encode_policy: policy,
+        ..Default::default()
+    })
+    .unwrap();
+    db.insert_batch(clustered_vectors(500, DIMS, 8, 0.1, 1).into_iter().map(|v| (v, None)))
+        .unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+/// Each inserted row, searched for, comes back first
+fn assert_findable(db: &VectorDB, rows: &[(u32, Vec<f32>)], use_pq: bool) {
+    let params = if use_pq {
+        SearchParams::builder().rerank(20).build()
+    } else {
+        SearchParams::builder().num_probe(2).build()
+    };
+    for (id, row) in rows {
+        let results = db.search_with_params(row, 1, &params).unwrap();
+        assert_eq!(results[0].id, *id, "row {} not found", id);
+    }
+}
+
+/// Rows far from the initial clusters, so each is its own nearest
+/// neighbour whichever clusters are probed
+fn new_rows(db: &mut VectorDB, n: usize) -> Vec<(u32, Vec<f32>)> {
+    clustered_vectors(n, DIMS, n, 0.0, 7)
+        .into_iter()
+        .map(|v| v.into_iter().map(|x| x * 10.0).collect::<Vec<f32>>())
+        .map(|v| (db.insert(v.clone(), None).unwrap(), v))
+        .collect()
+}
+
+#[test]
+fn test_inserts_are_findable_under_each_policy() {
+    for use_pq in [false, true] {
+        let mut eager = built_db(EncodePolicy::EagerOnInsert, use_pq);
+        let rows = new_rows(&mut eager, 50);
+        let status = eager.index_status();
+        assert!(status.current && status.searchable);
+        assert_eq!(status.unencoded, 0);
+        assert_findable(&eager, &rows, use_pq);
+
+        let mut deferred = built_db(EncodePolicy::DeferredToBuild, use_pq);
+        let rows = new_rows(&mut deferred, 50);
+        let status = deferred.index_status();
+        assert!(!status.current && status.searchable);
+        assert_eq!(status.unencoded, 50);
+        assert_findable(&deferred, &rows, use_pq);
+
+        // Deleted and excluded pending rows stay out of results
+        deferred.delete(rows[0].0).unwrap();
+        assert_eq!(deferred.index_status().unencoded, 49);
+        assert_ne!(deferred.search(&rows[0].1, 1).unwrap()[0].id, rows[0].0);
+        let params = SearchParams::builder().exclude([rows[1].0]).build();
+        assert_ne!(deferred.search_with_params(&rows[1].1, 1, &params).unwrap()[0].id, rows[1].0);
+
+        deferred.build_index().unwrap();
+        assert_eq!(deferred.index_status().unencoded, 0);
+        assert_findable(&deferred, &rows[1..], use_pq);
+    }
+}
+
+#[test]
+fn test_upserts_follow_the_policy() {
+    for policy in [EncodePolicy::EagerOnInsert, EncodePolicy::DeferredToBuild] {
+        let mut db = built_db(policy, true);
+        let moved = vec![25.0; DIMS];
+        db.insert_opts(moved.clone(), InsertOptions::new().id(7).upsert(true)).unwrap();
+        assert_eq!(db.index_status().current, policy == EncodePolicy::EagerOnInsert);
+        assert_findable(&db, &[(7, moved)], true);
+        assert_eq!(db.check_compatibility().violations, []);
+    }
+}
+
+#[test]
+fn test_auto_defers_bulk_loads() {
+    // The insert rate is not saved, so a loaded database starts idle
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    built_db(EncodePolicy::Auto, true).save(&path).unwrap();
+    let mut db = VectorDB::load(&path).unwrap();
+
+    // A lone insert is encoded at once
+    let rows = new_rows(&mut db, 1);
+    assert!(db.index_status().current);
+    assert_findable(&db, &rows, true);
+
+    // A large batch is well above the eager rate
+    let batch = clustered_vectors(500, DIMS, 4, 0.1, 9);
+    db.insert_batch(batch.into_iter().map(|v| (v, None))).unwrap();
+    let status = db.index_status();
+    assert!(status.insert_rate > AUTO_EAGER_MAX_RATE);
+    assert!(!status.current && status.searchable);
+    assert_eq!(status.unencoded, 500);
+
+    db.build_index().unwrap();
+    assert!(db.index_status().current);
+}
+
+#[test]
+fn test_unbuilt_databases_stay_unsearchable() {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMS,
+        encode_policy: EncodePolicy::EagerOnInsert,
+        ..Default::default()
+    })
+    .unwrap();
+    db.insert(vec![1.0; DIMS], None).unwrap();
+    let status = db.index_status();
+    assert!(!status.searchable && !status.current);
+    assert_eq!(status.unencoded, 1);
+    assert!(matches!(db.search(&[1.0; DIMS], 1), Err(KhadyotaError::IndexNotBuilt)));
+}
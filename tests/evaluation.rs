@@ -0,0 +1,102 @@
+use khadyota::evaluation::{compute_ground_truth, recall_at_k};
+use khadyota::harness::{self, build_db, clustered_vectors, random_vectors};
+use khadyota::*;
+
+const DIMS: usize = 32;
+
+fn config(quantizer: QuantizerKind, num_probe: usize) -> Config {
+    Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        quantizer,
+        pq_subvectors: 8,
+        num_clusters: 16,
+        num_probe,
+        seed: Some(4),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_evaluate_reports_recall_latency_and_work() {
+    let vectors = clustered_vectors(3_000, DIMS, 16, 0.2, 1);
+    let db = build_db(config(QuantizerKind::PQ, 2), vectors.clone()).unwrap();
+    let queries = clustered_vectors(40, DIMS, 16, 0.2, 7);
+
+    let report = db.evaluate(&queries, 10).unwrap();
+    println!("{}", report);
+    assert_eq!(report.queries, 40);
+    assert_eq!(report.latency.count(), 40);
+    assert!(report.min_recall <= report.recall && report.recall <= 1.0);
+    let expected = harness::recall_at_k(&db, &queries, 10).unwrap() as f32;
+    assert!((report.recall - expected).abs() < 1e-5, "{} vs {}", report.recall, expected);
+
+    // Two of sixteen clusters scanned
+    assert_eq!(report.candidates.live_rows, 3_000);
+    assert!(report.candidates.min <= report.candidates.max);
+    assert!(report.candidates.mean < 3_000.0 / 4.0, "{:?}", report.candidates);
+    assert!(report.to_string().starts_with("recall@10 "));
+
+    // The free functions agree with it, given the same ground truth
+    let results: Vec<Vec<SearchResult>> = queries.iter().map(|q| db.search(q, 10).unwrap()).collect();
+    let truth = compute_ground_truth(&vectors, &queries, 10, DistanceMetric::Euclidean);
+    assert_eq!(recall_at_k(&results, &truth, 10), report.recall);
+}
+
+#[test]
+fn test_exhaustive_search_evaluates_to_full_recall() {
+    let db = build_db(config(QuantizerKind::None, 16), random_vectors(500, DIMS, 2)).unwrap();
+    let report = db.evaluate(&random_vectors(10, DIMS, 3), 5).unwrap();
+    assert_eq!(report.recall, 1.0);
+    assert_eq!(report.min_recall, 1.0);
+    assert_eq!(report.candidates.mean, 500.0);
+}
+
+#[test]
+fn test_tuned_probe_reaches_the_target_on_held_out_queries() {
+    let mut db = build_db(
+        Config { num_clusters: 32, ..config(QuantizerKind::SQ8, 1) },
+        clustered_vectors(4_000, DIMS, 32, 0.3, 1),
+    )
+    .unwrap();
+    let sample = clustered_vectors(60, DIMS, 32, 0.3, 7);
+    let held_out = clustered_vectors(200, DIMS, 32, 0.3, 8);
+
+    let report = db.tune_probe(&sample, 0.9, 10).unwrap();
+    println!("{}", report);
+    let probes: Vec<usize> = report.sweep.iter().map(|t| t.num_probe).collect();
+    assert_eq!(probes, [1, 2, 4, 8, 16, 32]);
+    assert!(report.target_met && report.recall >= 0.9);
+    assert!(report.num_probe < 32, "{}", report);
+    // The smallest probe reaching the target, so the one before misses it
+    let chosen = probes.iter().position(|&p| p == report.num_probe).unwrap();
+    assert!(chosen == 0 || report.sweep[chosen - 1].recall < 0.9);
+    assert!(report.sweep.last().unwrap().recall > 0.95);
+
+    // Plain searches now probe that many clusters, and reach the target
+    // on queries the tuning never saw
+    let eval = db.evaluate(&held_out, 10).unwrap();
+    assert_eq!(eval.candidates.mean_clusters, report.num_probe as f64);
+    assert!(eval.recall >= 0.9, "{} at num_probe {}", eval.recall, report.num_probe);
+
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(serde_json::from_str::<TuneReport>(&json).unwrap(), report);
+}
+
+#[test]
+fn test_unreachable_targets_settle_for_the_best_recall() {
+    let mut db = build_db(config(QuantizerKind::PQ, 2), clustered_vectors(2_000, DIMS, 16, 0.3, 2)).unwrap();
+    let report = db.tune_probe(&clustered_vectors(30, DIMS, 16, 0.3, 3), 1.0, 10).unwrap();
+    assert!(!report.target_met, "{}", report);
+    assert!(report.to_string().contains("not met"));
+    let best = report.sweep.iter().map(|t| t.recall).fold(0.0, f32::max);
+    assert_eq!(report.recall, best);
+    let first_best = report.sweep.iter().find(|t| t.recall == best).unwrap();
+    assert_eq!(report.num_probe, first_best.num_probe);
+    assert_eq!(db.evaluate(&clustered_vectors(5, DIMS, 16, 0.3, 3), 10).unwrap().candidates.mean_clusters, report.num_probe as f64);
+
+    assert!(db.tune_probe(&[], 0.9, 10).is_err());
+    assert!(db.tune_probe(&random_vectors(3, DIMS, 4), 1.5, 10).is_err());
+    let mut flat = build_db(Config { index: IndexKind::Flat, ..config(QuantizerKind::None, 2) }, random_vectors(100, DIMS, 5)).unwrap();
+    assert!(matches!(flat.tune_probe(&random_vectors(3, DIMS, 4), 0.9, 10), Err(KhadyotaError::InvalidConfig(_))));
+}
@@ -0,0 +1,108 @@
+use khadyota::harness::{build_db, clustered_vectors};
+use khadyota::*;
+
+const DIMS: usize = 16;
+
+fn config(quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions: DIMS,
+        quantizer,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 1,
+        seed: Some(5),
+        ..Default::default()
+    }
+}
+
+fn vectors() -> Vec<Vec<f32>> {
+    clustered_vectors(1_000, DIMS, 8, 0.05, 3)
+}
+
+fn result_ids(db: &VectorDB, query: &[f32], params: &SearchParams) -> Vec<u32> {
+    db.search_with_params(query, db.len(), params).unwrap().iter().map(|r| r.id).collect()
+}
+
+#[test]
+fn test_id_missed_by_probing_is_explained() {
+    let db = build_db(config(QuantizerKind::PQ), vectors()).unwrap();
+    let query = db.get(0).unwrap().vector;
+    let found = result_ids(&db, &query, &SearchParams::default());
+
+    // An id from a cluster a one-cluster probe does not reach
+    let missing = (0..db.len() as u32).find(|id| !found.contains(id)).unwrap();
+    let explanation = db.explain_pair(&query, missing).unwrap();
+    assert_eq!(explanation.missed, Some(MissReason::NotProbed));
+    assert_eq!(explanation.num_probe, 1);
+    let depth = explanation.probe_depth.unwrap();
+    assert!(depth > 1, "{:?}", explanation);
+    assert!(!explanation.full_scan && !explanation.unencoded);
+
+    // Probing as deep as it says reaches it, at the rank it predicts
+    let params = SearchParams::builder().num_probe(depth).build();
+    let deeper = db.explain_pair_with_params(&query, missing, &params).unwrap();
+    assert_eq!(deeper.missed, None);
+    assert_eq!(deeper.cluster, explanation.cluster);
+    let found = result_ids(&db, &query, &params);
+    assert_eq!(found.len(), deeper.candidates);
+    let position = found.iter().position(|&id| id == missing).unwrap();
+    assert_eq!(Some(position), deeper.approximate_rank);
+    assert!(deeper.exact_rank.unwrap() < deeper.candidates);
+
+    // The approximate distance is its subquantizers' terms, finished
+    let sum: f32 = deeper.contributions.iter().sum();
+    assert_eq!(deeper.contributions.len(), 4);
+    assert!((sum.sqrt() - deeper.approximate_distance.unwrap()).abs() < 1e-4);
+    let exact = distance::compute_distance(&query, &db.get(missing).unwrap().vector, DistanceMetric::Euclidean);
+    assert_eq!(deeper.exact_distance, Some(exact));
+}
+
+#[test]
+fn test_filters_and_limits_are_explained() {
+    let db = build_db(config(QuantizerKind::PQ), vectors()).unwrap();
+    let query = db.get(0).unwrap().vector;
+
+    let top = db.explain_pair(&query, 0).unwrap();
+    assert_eq!(top.missed, None);
+    assert_eq!(top.exact_rank, Some(0));
+    assert_eq!(top.probe_depth, Some(1));
+
+    let excluded = SearchParams::builder().exclude([0]).build();
+    assert_eq!(
+        db.explain_pair_with_params(&query, 0, &excluded).unwrap().missed,
+        Some(MissReason::Excluded)
+    );
+
+    let capped = SearchParams::builder().max_candidates(1).build();
+    let explanation = db.explain_pair_with_params(&query, 0, &capped).unwrap();
+    assert_eq!(explanation.candidates, 1);
+    let cut = (0..db.len() as u32)
+        .map(|id| db.explain_pair_with_params(&query, id, &capped).unwrap())
+        .find(|e| e.probe_depth == Some(1) && e.missed.is_some())
+        .unwrap();
+    assert_eq!(cut.missed, Some(MissReason::BeyondMaxCandidates));
+
+    assert!(matches!(db.explain_pair(&query, 5_000), Err(KhadyotaError::VectorNotFound(5_000))));
+}
+
+#[test]
+fn test_explains_without_quantized_codes() {
+    let mut db = build_db(config(QuantizerKind::None), vectors()).unwrap();
+    let query = db.get(0).unwrap().vector;
+
+    // Without PQ and a probe count the search is a full scan
+    let explanation = db.explain_pair(&query, 999).unwrap();
+    assert!(explanation.full_scan);
+    assert_eq!(explanation.missed, None);
+    assert_eq!(explanation.approximate_distance, None);
+    assert!(explanation.contributions.is_empty());
+    assert_eq!(explanation.approximate_rank, None);
+    assert_eq!(explanation.candidates, 1_000);
+
+    // Spilled originals are read back for the exact distance
+    let dir = tempfile::TempDir::new().unwrap();
+    let expected = explanation.exact_distance;
+    db.spill_originals(&dir.path().join("rows.bin"), 1 << 16).unwrap();
+    assert_eq!(db.explain_pair(&query, 999).unwrap().exact_distance, expected);
+}
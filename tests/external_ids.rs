@@ -0,0 +1,115 @@
+//! Entries keyed by the caller's own ids
+
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use serde_json::json;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 16;
+
+fn uuid(i: usize) -> String {
+    format!("{:08x}-4b1e-4c2a-9d3f-{:012x}", i * 2_654_435_761 % (1 << 32), i)
+}
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 8,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+fn keyed(rows: usize) -> (VectorDB, Vec<Vec<f32>>) {
+    let vectors = clustered_vectors(rows, DIMENSIONS, 8, 0.2, 1);
+    let mut db = VectorDB::new(config()).unwrap();
+    for (i, vector) in vectors.iter().enumerate() {
+        db.insert_with_id(uuid(i), vector.clone(), Some(json!({"n": i}))).unwrap();
+    }
+    db.build_index().unwrap();
+    (db, vectors)
+}
+
+#[test]
+fn test_search_and_fetch_by_uuid_after_reload() {
+    let dir = TempDir::new().unwrap();
+    let (db, vectors) = keyed(500);
+    let top = &db.search(&vectors[42], 1).unwrap()[0];
+    assert_eq!(top.external_id, Some(ExternalId::Str(uuid(42))));
+    assert_eq!(db.id_of(uuid(42)), Some(top.id));
+    assert_eq!(db.external_id(top.id), top.external_id.as_ref());
+
+    let path = dir.path().join("keyed.khd");
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    let entry = loaded.get_by_external_id(uuid(42)).unwrap();
+    assert_eq!((entry.vector, entry.metadata), (vectors[42].clone(), Some(json!({"n": 42}))));
+    let results = loaded.search(&vectors[7], 5).unwrap();
+    assert_eq!(results[0].external_id, Some(uuid(7).into()));
+    assert!(results.iter().all(|r| r.external_id.as_ref() == db.external_id(r.id)));
+    assert!(matches!(
+        loaded.get_by_external_id("missing"),
+        Err(KhadyotaError::ExternalIdNotFound(ExternalId::Str(id))) if id == "missing"
+    ));
+}
+
+#[test]
+fn test_duplicates_upserts_updates_and_deletes() {
+    let (mut db, vectors) = keyed(100);
+    match db.insert_with_id(uuid(3), vectors[0].clone(), None) {
+        Err(KhadyotaError::DuplicateExternalId(id)) => assert_eq!(id, uuid(3).into()),
+        other => panic!("expected a duplicate, got {:?}", other),
+    }
+    assert_eq!(db.len(), 100);
+
+    // Upserting replaces the entry it names in place
+    let id = db.id_of(uuid(3)).unwrap();
+    let opts = InsertOptions::new().external_id(uuid(3)).upsert(true).metadata(json!({"n": "new"}));
+    assert_eq!(db.insert_opts(vectors[0].clone(), opts).unwrap(), id);
+    assert_eq!(db.get_by_external_id(uuid(3)).unwrap().metadata, Some(json!({"n": "new"})));
+
+    db.update_by_external_id(uuid(5), vectors[6].clone(), None).unwrap();
+    assert_eq!(db.get_by_external_id(uuid(5)).unwrap().vector, vectors[6]);
+
+    let deleted = db.id_of(uuid(9)).unwrap();
+    db.delete_by_external_id(uuid(9)).unwrap();
+    assert_eq!((db.id_of(uuid(9)), db.external_id(deleted)), (None, None));
+    assert!(db.delete_by_external_id(uuid(9)).is_err());
+    // A deleted entry's id is free again, and numeric ids work alike
+    db.insert_with_id(uuid(9), vectors[9].clone(), None).unwrap();
+    let numeric = db.insert_with_id(9u64, vectors[9].clone(), None).unwrap();
+    assert_eq!(db.id_of(9u64), Some(numeric));
+
+    // Compaction renumbers the mapping with the rows
+    db.compact().unwrap();
+    for i in 0..100 {
+        let entry = db.get_by_external_id(uuid(i)).unwrap();
+        assert_eq!(db.external_id(entry.id), Some(&uuid(i).into()));
+    }
+    assert_eq!(db.get_by_external_id(9u64).unwrap().vector, vectors[9]);
+}
+
+#[test]
+fn test_files_without_external_ids_load_as_before() {
+    let dir = TempDir::new().unwrap();
+    let vectors = clustered_vectors(200, DIMENSIONS, 8, 0.2, 2);
+    let mut db = VectorDB::new(config()).unwrap();
+    db.insert_batch(vectors.iter().map(|v| (v.clone(), None))).unwrap();
+    db.build_index().unwrap();
+    let checksum = db.checksum();
+
+    let path = dir.path().join("plain.khd");
+    db.save(&path).unwrap();
+    let mut loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), checksum);
+    assert!(loaded.search(&vectors[0], 10).unwrap().iter().all(|r| r.external_id.is_none()));
+
+    // Keys can be added to such a database afterwards
+    let id = loaded.insert_with_id("late", vectors[0].clone(), None).unwrap();
+    assert_eq!(id, 200);
+    assert_ne!(loaded.checksum(), checksum);
+}
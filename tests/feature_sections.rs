@@ -0,0 +1,119 @@
+//! Files carrying sections that need optional cargo features. Run under
+//! both `cargo test` and `cargo test --features arrow`: the assertions for
+//! "arrow" sections flip with the build.
+
+use khadyota::storage::{feature_enabled, Section};
+use khadyota::*;
+use std::fs::OpenOptions;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn saved_db(path: &Path) -> VectorDB {
+    let config = Config {
+        dimensions: 4,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::None,
+        num_clusters: 1,
+        num_probe: 1,
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..5 {
+        db.insert(vec![i as f32; 4], None).unwrap();
+    }
+    db.build_index().unwrap();
+    db.save(path).unwrap();
+    db
+}
+
+/// Append a section table, as a build with more features would have written
+fn append_sections(path: &Path, sections: &[Section]) {
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    Section::write_table(&mut file, sections).unwrap();
+}
+
+fn feature_required(path: &Path) -> String {
+    match VectorDB::load(path) {
+        Err(KhadyotaError::FeatureRequired(feature)) => feature,
+        other => panic!("expected FeatureRequired, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_required_section_for_unknown_feature_fails() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    saved_db(&path);
+
+    append_sections(&path, &[Section::new("graph", vec![1, 2, 3]).requires("hnsw")]);
+    assert_eq!(feature_required(&path), "hnsw");
+
+    let Err(err) = VectorDB::load(&path) else {
+        panic!("expected an error");
+    };
+    assert_eq!(
+        err.to_string(),
+        "This file needs the \"hnsw\" cargo feature, which this build was compiled without"
+    );
+}
+
+#[test]
+fn test_optional_section_is_skipped_with_warning() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = saved_db(&path);
+
+    append_sections(&path, &[
+        Section::new("plain", vec![9]),
+        Section::new("sparse_export", vec![0; 16]).requires("sparse").optional(true),
+    ]);
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    assert_eq!(loaded.search(&[3.1; 4], 1).unwrap()[0].id, 3);
+    assert_eq!(
+        loaded.load_warnings(),
+        ["Skipped section \"sparse_export\": it needs the \"sparse\" feature, which this build lacks"]
+    );
+
+    // Kept sections survive a re-save; skipped ones are gone for good
+    loaded.save(&path).unwrap();
+    let reloaded = VectorDB::load(&path).unwrap();
+    assert!(reloaded.load_warnings().is_empty());
+}
+
+#[test]
+fn test_files_without_sections_have_no_warnings() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    saved_db(&path);
+
+    assert!(VectorDB::load(&path).unwrap().load_warnings().is_empty());
+}
+
+#[test]
+fn test_arrow_sections_follow_the_build() {
+    let dir = TempDir::new().unwrap();
+    let required = dir.path().join("required.kdb");
+    let optional = dir.path().join("optional.kdb");
+    saved_db(&required);
+    saved_db(&optional);
+
+    append_sections(&required, &[Section::new("ipc_schema", vec![1]).requires("arrow")]);
+    append_sections(&optional, &[Section::new("ipc_export", vec![1]).requires("arrow").optional(true)]);
+
+    #[cfg(feature = "arrow")]
+    {
+        assert!(feature_enabled("arrow"));
+        assert!(VectorDB::load(&required).unwrap().load_warnings().is_empty());
+        assert!(VectorDB::load(&optional).unwrap().load_warnings().is_empty());
+    }
+
+    #[cfg(not(feature = "arrow"))]
+    {
+        assert!(!feature_enabled("arrow"));
+        assert_eq!(feature_required(&required), "arrow");
+        assert_eq!(VectorDB::load(&optional).unwrap().load_warnings().len(), 1);
+    }
+}
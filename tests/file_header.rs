@@ -0,0 +1,240 @@
+use khadyota::storage::{FileHeader, MIN_VERSION, VERSION};
+use khadyota::*;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use tempfile::TempDir;
+
+fn config(metric: DistanceMetric) -> Config {
+    Config {
+        dimensions: 8,
+        metric,
+        quantizer: QuantizerKind::None,
+        num_clusters: 2,
+        num_probe: 2,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+fn saved_db(path: &Path, metric: DistanceMetric) -> VectorDB {
+    let mut db = VectorDB::new(config(metric)).unwrap();
+    for i in 0..10 {
+        let vector: Vec<f32> = (0..8).map(|j| ((i * 8 + j) as f32).sin()).collect();
+        db.insert(vector, None).unwrap();
+    }
+    db.build_index().unwrap();
+    db.save(path).unwrap();
+    db
+}
+
+/// Split a saved file into its header and the state that follows
+fn split(path: &Path) -> (FileHeader, Vec<u8>) {
+    let mut reader = BufReader::new(std::fs::File::open(path).unwrap());
+    let header = FileHeader::read_from(&mut reader).unwrap().expect("file has a header");
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    (header, rest)
+}
+
+fn assemble(path: &Path, header: Option<&FileHeader>, rest: &[u8]) {
+    let mut bytes = Vec::new();
+    if let Some(header) = header {
+        header.write_to(&mut bytes).unwrap();
+    }
+    bytes.extend_from_slice(rest);
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn load_error(path: &Path) -> String {
+    match VectorDB::load(path) {
+        Err(KhadyotaError::SerializationError(msg)) => msg,
+        other => panic!("expected SerializationError, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_header_written_and_checked() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = saved_db(&path, DistanceMetric::Euclidean);
+
+    let (header, rest) = split(&path);
+    assert_eq!(header, FileHeader::new(8, 10, DistanceMetric::Euclidean));
+
+    // Hand-assembled headers that contradict the stored config
+    let mut wrong = header.clone();
+    wrong.metric = DistanceMetric::Cosine;
+    assemble(&path, Some(&wrong), &rest);
+    assert_eq!(
+        load_error(&path),
+        "File header records metric Cosine but the stored data has Euclidean"
+    );
+
+    let mut wrong = header.clone();
+    wrong.dimensions = 16;
+    assemble(&path, Some(&wrong), &rest);
+    assert_eq!(load_error(&path), "File header records dimensions 16 but the stored data has 8");
+
+    let mut wrong = header.clone();
+    wrong.vector_count = 11;
+    assemble(&path, Some(&wrong), &rest);
+    assert_eq!(load_error(&path), "File header records vector_count 11 but the stored data has 10");
+
+    // Files from before headers existed still load
+    assemble(&path, None, &rest);
+    let legacy = VectorDB::load(&path).unwrap();
+    assert_eq!(legacy.checksum(), db.checksum());
+}
+
+#[test]
+fn test_load_expecting_reports_mismatch() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = saved_db(&path, DistanceMetric::Euclidean);
+
+    // Matching files load unchanged; tuning knobs may differ
+    let expected = Config {
+        num_clusters: 50,
+        num_probe: 5,
+        seed: None,
+        ..config(DistanceMetric::Euclidean)
+    };
+    let loaded = VectorDB::load_expecting(&path, &expected).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    let query = vec![0.5; 8];
+    let ids = |db: &VectorDB| db.search(&query, 3).unwrap().iter().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids(&loaded), ids(&db));
+
+    let mismatch = |expected: Config| match VectorDB::load_expecting(&path, &expected) {
+        Err(KhadyotaError::ConfigMismatch { field, expected, found }) => (field, expected, found),
+        other => panic!("expected ConfigMismatch, got {:?}", other.err()),
+    };
+
+    assert_eq!(
+        mismatch(config(DistanceMetric::Cosine)),
+        ("metric", "Cosine".to_string(), "Euclidean".to_string())
+    );
+    assert_eq!(
+        mismatch(Config { dimensions: 16, ..config(DistanceMetric::Euclidean) }),
+        ("dimensions", "16".to_string(), "8".to_string())
+    );
+    assert_eq!(
+        mismatch(Config { quantizer: QuantizerKind::PQ, ..config(DistanceMetric::Euclidean) }),
+        ("quantizer", "PQ".to_string(), "None".to_string())
+    );
+
+    let Err(err) = VectorDB::load_expecting(&path, &config(DistanceMetric::DotProduct)) else {
+        panic!("expected a mismatch");
+    };
+    assert_eq!(err.to_string(), "Config mismatch on metric: expected DotProduct, found Euclidean");
+}
+
+#[test]
+fn test_other_versions() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = saved_db(&path, DistanceMetric::Euclidean);
+    let (header, rest) = split(&path);
+    assert_eq!(header.version, VERSION);
+
+    // Newer and older than this build reads
+    for version in [VERSION + 1, MIN_VERSION - 1] {
+        let mut other = header.clone();
+        other.version = version;
+        assemble(&path, Some(&other), &rest);
+        match VectorDB::load(&path) {
+            Err(err @ KhadyotaError::UnsupportedVersion { .. }) => assert_eq!(
+                err.to_string(),
+                format!(
+                    "Unsupported file format version {}; this build reads versions {} to {}",
+                    version, MIN_VERSION, VERSION
+                )
+            ),
+            other => panic!("expected UnsupportedVersion, got {:?}", other.err()),
+        }
+    }
+
+    // Version 1 files load unchanged and are rewritten as the current version
+    let mut v1 = header.clone();
+    v1.version = 1;
+    assemble(&path, Some(&v1), &rest);
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    loaded.save(&path).unwrap();
+    assert_eq!(split(&path).0.version, VERSION);
+}
+
+#[test]
+fn test_unreadable_files_are_named() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    saved_db(&path, DistanceMetric::Euclidean);
+    let bytes = std::fs::read(&path).unwrap();
+
+    std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    assert!(load_error(&path).starts_with("Database file is truncated or corrupt: "));
+
+    std::fs::write(&path, &bytes[..10]).unwrap();
+    assert!(load_error(&path).starts_with("File header is truncated or corrupt: "));
+
+    std::fs::write(&path, b"PK\x03\x04 certainly not a database").unwrap();
+    assert!(load_error(&path).starts_with("Not a Khadyota database: "));
+
+    // A valid header with the magic changed is not recognised as one
+    let mut bytes = bytes;
+    bytes[2] = b'X';
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(load_error(&path).starts_with("Not a Khadyota database: "));
+
+    let mut header = FileHeader::new(8, 10, DistanceMetric::Euclidean);
+    header.magic = *b"KHDX";
+    assert!(matches!(header.validate(), Err(KhadyotaError::SerializationError(_))));
+}
+
+#[test]
+fn test_plain_load_of_a_mapped_save_points_to_load_mapped() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = saved_db(&path, DistanceMetric::Euclidean);
+    db.save_mapped(&path).unwrap();
+
+    let msg = load_error(&path);
+    assert!(msg.starts_with("the 10 vectors are stored in "), "{}", msg);
+    assert!(msg.ends_with("open this file with load_mapped()"), "{}", msg);
+    assert_eq!(VectorDB::load_mapped(&path, 0).unwrap().checksum(), db.checksum());
+}
+
+#[test]
+fn test_rows_of_the_wrong_width_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    saved_db(&path, DistanceMetric::Euclidean);
+    let (_, rest) = split(&path);
+
+    // Rewrite the stored config to claim 4 dimensions over 8-wide rows
+    let mut state: serde_json::Value = rmp_serde::from_slice(&rest).unwrap();
+    state[0][0] = 4.into();
+    assemble(&path, None, &rmp_serde::to_vec(&state).unwrap());
+    assert!(matches!(
+        VectorDB::load(&path),
+        Err(KhadyotaError::DimensionMismatch { expected: 4, got: 8 })
+    ));
+}
+
+#[test]
+fn test_searching_a_db_saved_with_other_dimensions() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    saved_db(&path, DistanceMetric::Euclidean);
+
+    // A caller expecting 16 dimensions gets a clear error, not results
+    let db = VectorDB::load(&path).unwrap();
+    assert!(matches!(
+        db.search(&[0.5; 16], 3),
+        Err(KhadyotaError::DimensionMismatch { expected: 8, got: 16 })
+    ));
+    assert!(matches!(
+        VectorDB::load_expecting(&path, &Config { dimensions: 16, ..config(DistanceMetric::Euclidean) }),
+        Err(KhadyotaError::ConfigMismatch { field: "dimensions", .. })
+    ));
+}
@@ -0,0 +1,99 @@
+//! Exact search through a flat index
+
+use khadyota::harness::{exact_neighbors, random_vectors};
+use khadyota::indexing::flat::FLAT_PARALLEL_ROWS;
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 20;
+
+fn config(metric: DistanceMetric) -> Config {
+    // 20 dimensions do not split into the default 8 subvectors, which a
+    // flat index never trains
+    Config {
+        dimensions: DIMENSIONS,
+        metric,
+        index: IndexKind::Flat,
+        ..Default::default()
+    }
+}
+
+fn built(metric: DistanceMetric, rows: usize) -> VectorDB {
+    let mut db = VectorDB::new(config(metric)).unwrap();
+    db.insert_batch(random_vectors(rows, DIMENSIONS, 1).into_iter().map(|v| (v, None))).unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+fn assert_exact(db: &VectorDB, queries: &[Vec<f32>], k: usize) {
+    for query in queries {
+        let found = db.search(query, k).unwrap();
+        let exact = exact_neighbors(db, query, k).unwrap();
+        let pairs = |results: &[SearchResult]| results.iter().map(|r| (r.id, r.distance)).collect::<Vec<_>>();
+        assert_eq!(pairs(&found), pairs(&exact));
+    }
+}
+
+#[test]
+fn test_flat_search_is_exact_for_every_metric() {
+    let queries = random_vectors(10, DIMENSIONS, 2);
+    for metric in [DistanceMetric::Cosine, DistanceMetric::Euclidean, DistanceMetric::DotProduct, DistanceMetric::Manhattan] {
+        // Both sides of the parallel threshold
+        for rows in [500, FLAT_PARALLEL_ROWS + 100] {
+            let db = built(metric, rows);
+            assert_exact(&db, &queries, 25);
+            let stats = db.stats();
+            assert!(stats.index_built);
+            assert_eq!(stats.flat_index_bytes, rows * DIMENSIONS * 4);
+            assert_eq!((stats.code_bytes, stats.centroid_bytes, stats.compression_ratio), (0, 0, None));
+        }
+    }
+}
+
+#[test]
+fn test_writes_after_a_build_stay_exact() {
+    let queries = random_vectors(10, DIMENSIONS, 3);
+    let extra = random_vectors(200, DIMENSIONS, 4);
+    for policy in [EncodePolicy::EagerOnInsert, EncodePolicy::DeferredToBuild] {
+        let mut db = VectorDB::new(Config { encode_policy: policy, ..config(DistanceMetric::Euclidean) }).unwrap();
+        db.insert_batch(random_vectors(1_000, DIMENSIONS, 1).into_iter().map(|v| (v, None))).unwrap();
+        db.build_index().unwrap();
+
+        for vector in &extra[..100] {
+            db.insert(vector.clone(), None).unwrap();
+        }
+        db.insert_batch(extra[100..].iter().map(|v| (v.clone(), None))).unwrap();
+        for id in (0..1_200).step_by(7) {
+            db.delete(id).unwrap();
+        }
+        db.update(3, queries[0].clone(), None).unwrap();
+        assert_eq!(db.index_status().current, policy == EncodePolicy::EagerOnInsert);
+        assert_exact(&db, &queries, 30);
+        assert_eq!(db.search(&queries[0], 1).unwrap()[0].id, 3);
+
+        db.compact().unwrap();
+        assert_exact(&db, &queries, 30);
+        db.maintenance_tick(std::time::Duration::from_secs(60));
+        assert!(db.index_status().current);
+        assert_exact(&db, &queries, 30);
+    }
+}
+
+#[test]
+fn test_flat_index_is_copied_again_on_load() {
+    let dir = TempDir::new().unwrap();
+    let queries = random_vectors(5, DIMENSIONS, 5);
+    let db = built(DistanceMetric::Cosine, 2_000);
+    let path = dir.path().join("flat.khd");
+    db.save(&path).unwrap();
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.stats().flat_index_bytes, db.stats().flat_index_bytes);
+    assert_exact(&loaded, &queries, 10);
+    let filtered = SearchParams::builder().exclude([0, 1, 2]).build();
+    for query in &queries {
+        let ids = |db: &VectorDB| db.search_with_params(query, 10, &filtered).unwrap().iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&loaded), ids(&db));
+        assert!(ids(&loaded).iter().all(|&id| id > 2));
+    }
+}
@@ -0,0 +1,144 @@
+use khadyota::harness::{build_db, clustered_vectors};
+use khadyota::*;
+use serde_json::json;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const DIMS: usize = 64;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMS,
+        pq_subvectors: 8,
+        num_clusters: 32,
+        num_probe: 4,
+        max_training_vectors: Some(2_000),
+        seed: Some(9),
+        ..Default::default()
+    }
+}
+
+fn large_db() -> VectorDB {
+    build_db(config(), clustered_vectors(10_000, DIMS, 32, 0.3, 1)).unwrap()
+}
+
+fn set_metadata(db: &mut VectorDB, id: u32, value: serde_json::Value) {
+    let mut changeset = ChangeSet::new();
+    changeset.update_metadata(id, Some(value));
+    db.apply_changeset(changeset).unwrap();
+}
+
+fn fastest(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..3).map(|_| run()).min().unwrap()
+}
+
+fn reused(report: &PersistReport) -> Vec<PersistSection> {
+    report.sections.iter().filter(|s| s.reused).map(|s| s.section).collect()
+}
+
+#[test]
+fn test_metadata_change_rewrites_only_the_tail() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let full_path = dir.path().join("full.kdb");
+    let mut db = large_db();
+
+    let first = db.save_incremental(&path).unwrap();
+    assert!(first.full_rewrite.is_some());
+    assert_eq!(first.bytes_reused, 0);
+    assert_eq!(first.file_bytes(), std::fs::metadata(&path).unwrap().len());
+
+    let full_time = fastest(|| {
+        let start = Instant::now();
+        db.save(&full_path).unwrap();
+        start.elapsed()
+    });
+
+    let mut report = None;
+    let mut round = 0;
+    let incremental_time = fastest(|| {
+        round += 1;
+        set_metadata(&mut db, 17, json!({"tag": "changed", "round": round}));
+        let start = Instant::now();
+        report = Some(db.save_incremental(&path).unwrap());
+        start.elapsed()
+    });
+    let report = report.unwrap();
+    println!("full save {:?}, incremental {:?}: {:?}", full_time, incremental_time, report);
+
+    assert_eq!(report.full_rewrite, None);
+    // A temp directory on a local disk can always be synced
+    assert_eq!(report.replace, ReplaceStrategy::PLATFORM);
+    assert_eq!(
+        reused(&report),
+        [PersistSection::Vectors, PersistSection::Codes, PersistSection::Index, PersistSection::AppState]
+    );
+    assert!(report.bytes_written * 20 < report.file_bytes(), "{:?}", report);
+    assert!(incremental_time * 2 < full_time, "{:?} vs {:?}", incremental_time, full_time);
+
+    // The result is exactly what a full save writes, and loads the same
+    db.save(&full_path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), std::fs::read(&full_path).unwrap());
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    assert_eq!(loaded.get(17).unwrap().metadata, db.get(17).unwrap().metadata);
+    let query = db.get(3).unwrap().vector;
+    let hits = |db: &VectorDB| -> Vec<(u32, f32)> {
+        db.search(&query, 10).unwrap().iter().map(|r| (r.id, r.distance)).collect()
+    };
+    assert_eq!(hits(&loaded), hits(&db));
+}
+
+#[test]
+fn test_loaded_database_saves_incrementally() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    large_db().save(&path).unwrap();
+
+    // Nothing changed: everything but the header and config is copied
+    let mut db = VectorDB::load(&path).unwrap();
+    let report = db.save_incremental(&path).unwrap();
+    assert_eq!(reused(&report), PersistSection::ALL);
+
+    // A row left for the next build changes only the vectors and tail
+    db.insert(vec![0.5; DIMS], Some(json!({"new": true}))).unwrap();
+    let report = db.save_incremental(&path).unwrap();
+    assert_eq!(reused(&report), [PersistSection::Codes, PersistSection::Index, PersistSection::AppState]);
+
+    // A rebuild rewrites the index parts but not the rows
+    db.build_index().unwrap();
+    let report = db.save_incremental(&path).unwrap();
+    assert_eq!(reused(&report), [PersistSection::Vectors, PersistSection::AppState]);
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    assert_eq!(loaded.len(), 10_001);
+}
+
+#[test]
+fn test_other_files_are_never_reused() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let other = dir.path().join("other.kdb");
+    let mut db = large_db();
+    db.save_incremental(&path).unwrap();
+
+    // A file the database was not read from or saved to
+    db.save(&other).unwrap();
+    let report = db.save_incremental(&other).unwrap();
+    assert!(report.full_rewrite.unwrap().contains("last loaded or saved"));
+    db.save_incremental(&path).unwrap();
+
+    // The recorded file replaced by another build of the same shape
+    let mut lookalike = build_db(config(), clustered_vectors(10_000, DIMS, 32, 0.3, 2)).unwrap();
+    set_metadata(&mut lookalike, 1, json!("a"));
+    lookalike.save(&path).unwrap();
+    set_metadata(&mut db, 1, json!("a"));
+    let report = db.save_incremental(&path).unwrap();
+    println!("{:?}", report.full_rewrite);
+    assert!(report.full_rewrite.is_some());
+    assert_eq!(report.bytes_reused, 0);
+    assert_eq!(VectorDB::load(&path).unwrap().checksum(), db.checksum());
+    assert!(!Path::new(&format!("{}.partial", path.display())).exists());
+}
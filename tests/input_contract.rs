@@ -0,0 +1,115 @@
+use khadyota::*;
+
+fn build_db(metric: DistanceMetric) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: 4,
+        metric,
+        quantizer: QuantizerKind::None,
+        num_clusters: 2,
+        num_probe: 1,
+        seed: Some(1),
+        ..Default::default()
+    })
+    .unwrap();
+    for i in 0..10 {
+        db.insert(vec![1.0 + i as f32, 0.5, -1.0, 0.0], None).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn invalid_query(result: Result<Vec<SearchResult>>) -> String {
+    match result {
+        Err(KhadyotaError::InvalidQuery(reason)) => reason,
+        other => panic!("expected InvalidQuery, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_contract_describes_the_collection() {
+    let cosine = build_db(DistanceMetric::Cosine).input_contract();
+    assert_eq!(cosine.dimensions, 4);
+    assert_eq!(cosine.metric, DistanceMetric::Cosine);
+    assert!(cosine.finite && cosine.nonzero && cosine.scale_invariant);
+    assert_eq!(cosine.bit_threshold, None);
+    assert_eq!(cosine.dtypes, [Dtype::F32, Dtype::F64]);
+
+    let euclidean = build_db(DistanceMetric::Euclidean).input_contract();
+    assert!(!euclidean.nonzero && !euclidean.scale_invariant);
+
+    let hamming = build_db(DistanceMetric::Hamming).input_contract();
+    assert_eq!(hamming.bit_threshold, Some(distance::HAMMING_THRESHOLD));
+}
+
+#[test]
+fn test_wrong_dimension() {
+    let db = build_db(DistanceMetric::Euclidean);
+    for query in [vec![1.0; 3], vec![1.0; 5]] {
+        assert!(matches!(
+            db.validate_query(&query),
+            Err(KhadyotaError::DimensionMismatch { expected: 4, got }) if got == query.len()
+        ));
+        assert!(matches!(db.search(&query, 1), Err(KhadyotaError::DimensionMismatch { .. })));
+    }
+}
+
+#[test]
+fn test_non_finite_components_name_the_offender() {
+    let db = build_db(DistanceMetric::Euclidean);
+    for (bad, shown) in [(f32::NAN, "NaN"), (f32::INFINITY, "inf"), (f32::NEG_INFINITY, "-inf")] {
+        let query = vec![1.0, 2.0, bad, 0.0];
+        assert!(db.validate_query(&query).is_err());
+        let reason = invalid_query(db.search(&query, 1));
+        assert!(reason.contains(&format!("component 2 is {}", shown)), "{}", reason);
+
+        assert!(matches!(db.batch_search(&[vec![1.0; 4], query.clone()], 1), Err(KhadyotaError::InvalidQuery(_))));
+        assert!(matches!(db.rank_candidates(&query, &[0, 1], 1, true), Err(KhadyotaError::InvalidQuery(_))));
+    }
+}
+
+#[test]
+fn test_zero_query_is_rejected_for_cosine_only() {
+    let zero = vec![0.0; 4];
+    let reason = invalid_query(build_db(DistanceMetric::Cosine).search(&zero, 1));
+    assert!(reason.contains("zero vector"), "{}", reason);
+
+    for metric in [DistanceMetric::Euclidean, DistanceMetric::DotProduct, DistanceMetric::Manhattan] {
+        assert_eq!(build_db(metric).search(&zero, 1).unwrap().len(), 1);
+    }
+}
+
+#[test]
+fn test_f64_queries_are_narrowed_or_rejected() {
+    let db = build_db(DistanceMetric::Euclidean);
+    assert_eq!(db.prepare_query_f64(&[1.5, -2.0, 0.25, 1e-3]).unwrap(), [1.5, -2.0, 0.25, 1e-3f32]);
+
+    let Err(KhadyotaError::InvalidQuery(reason)) = db.prepare_query_f64(&[1.0, 1e300, 0.0, 0.0]) else {
+        panic!("an out-of-range f64 was accepted");
+    };
+    assert!(reason.contains("component 1"), "{}", reason);
+    assert!(matches!(db.prepare_query_f64(&[f64::NAN, 0.0, 0.0, 0.0]), Err(KhadyotaError::InvalidQuery(_))));
+    assert!(matches!(db.prepare_query_f64(&[1.0; 3]), Err(KhadyotaError::DimensionMismatch { .. })));
+}
+
+#[test]
+fn test_prepare_query_matches_search() {
+    let db = build_db(DistanceMetric::Cosine);
+    let query = vec![2.0, 0.5, -1.0, 0.0];
+    let prepared = db.prepare_query(&query).unwrap();
+    let scored = |q: &[f32]| db.search(q, 3).unwrap().iter().map(|r| (r.id, r.distance)).collect::<Vec<_>>();
+    assert_eq!(scored(&prepared), scored(&query));
+
+    // Contract violations surface identically from both
+    let bad = vec![f32::NAN; 4];
+    assert_eq!(
+        db.prepare_query(&bad).unwrap_err().to_string(),
+        db.search(&bad, 1).unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn test_segmented_search_enforces_the_contract() {
+    let mut db = SegmentedDB::new(Config::for_dimensions(4), 100).unwrap();
+    db.insert(vec![1.0; 4], None).unwrap();
+    assert!(matches!(db.search(&[f32::NAN; 4], 1), Err(KhadyotaError::InvalidQuery(_))));
+}
@@ -1,5 +1,4 @@
 use khadyota::*;
-use tempfile::NamedTempFile;
 
 #[test]
 fn test_pq_end_to_end() {
@@ -33,5 +32,5 @@ fn test_simd_speedup() {
     let scalar_result = distance::scalar::cosine_distance_scalar(&a, &b);
     let auto_result = distance::cosine_distance(&a, &b);
     
-    assert!((scalar_result - auto_result).abs() < 1e-5);
+    assert!(ToleranceConfig::default().approx_eq(scalar_result, auto_result));
 }
\ No newline at end of file
@@ -0,0 +1,102 @@
+//! Listing each vector in several IVF clusters
+
+use khadyota::harness::{clustered_vectors, exact_neighbors, random_vectors, recall};
+use khadyota::*;
+use std::collections::BTreeSet;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 32;
+const ROWS: usize = 4_000;
+
+fn built(quantizer: QuantizerKind, use_residuals: bool, replicas: usize) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        quantizer,
+        use_residuals,
+        pq_subvectors: 8,
+        num_clusters: 32,
+        num_probe: 2,
+        seed: Some(1),
+        ivf_assign_replicas: replicas,
+        ..Default::default()
+    })
+    .unwrap();
+    db.insert_batch(random_vectors(ROWS, DIMENSIONS, 1).into_iter().map(|v| (v, None))).unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+fn assert_distinct(results: &[SearchResult]) {
+    let ids: BTreeSet<u32> = results.iter().map(|r| r.id).collect();
+    assert_eq!(ids.len(), results.len(), "{:?}", results.iter().map(|r| r.id).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_replicas_raise_recall_at_a_fixed_probe() {
+    // Uniform data puts many rows near a cluster boundary
+    let queries = random_vectors(100, DIMENSIONS, 2);
+    let single = built(QuantizerKind::None, false, 1);
+    let double = built(QuantizerKind::None, false, 2);
+
+    // Without codes, only a search given num_probe goes through the index
+    let probe = SearchParams::builder().num_probe(2).build();
+    let recall_of = |db: &VectorDB| {
+        queries
+            .iter()
+            .map(|q| recall(&db.search_with_params(q, 10, &probe).unwrap(), &exact_neighbors(db, q, 10).unwrap()))
+            .sum::<f64>()
+            / queries.len() as f64
+    };
+    let (before, after) = (recall_of(&single), recall_of(&double));
+    assert!(after > before + 0.05, "recall {} with replicas, {} without", after, before);
+    let (single, double) = (single.stats(), double.stats());
+    assert!(double.inverted_list_bytes > single.inverted_list_bytes + (ROWS - 1) * 4);
+    assert_eq!(double.centroid_bytes, single.centroid_bytes);
+}
+
+#[test]
+fn test_results_never_repeat_an_id() {
+    let queries = clustered_vectors(30, DIMENSIONS, 8, 0.5, 3);
+    let rerank = SearchParams::builder().rerank(40).num_probe(6).build();
+    for (quantizer, residuals) in [(QuantizerKind::PQ, true), (QuantizerKind::PQ, false), (QuantizerKind::SQ8, false)] {
+        let mut db = built(quantizer, residuals, 3);
+        db.delete(5).unwrap();
+        for query in &queries {
+            let results = db.search(query, 50).unwrap();
+            assert_eq!(results.len(), 50);
+            assert_distinct(&results);
+            assert_distinct(&db.search_with_params(query, 50, &rerank).unwrap());
+        }
+        for results in db.batch_search(&queries, 20).unwrap() {
+            assert_distinct(&results);
+        }
+        assert!(queries.iter().all(|q| db.search(q, 50).unwrap().iter().all(|r| r.id != 5)));
+    }
+}
+
+#[test]
+fn test_replicas_survive_save_compaction_and_rebalance() {
+    let dir = TempDir::new().unwrap();
+    let queries = random_vectors(20, DIMENSIONS, 4);
+    let mut db = built(QuantizerKind::PQ, true, 2);
+    let path = dir.path().join("replicas.khd");
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load(&path).unwrap();
+    for query in &queries {
+        let ids = |db: &VectorDB| db.search(query, 10).unwrap().iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&loaded), ids(&db));
+    }
+
+    for id in (0..ROWS as u32).step_by(3) {
+        db.delete(id).unwrap();
+    }
+    db.compact().unwrap();
+    db.optimize_index().unwrap();
+    for query in &queries {
+        let results = db.search(query, 30).unwrap();
+        assert_eq!(results.len(), 30);
+        assert_distinct(&results);
+        assert!(results.iter().all(|r| (r.id as usize) < db.len()));
+    }
+}
@@ -1,5 +1,7 @@
 use khadyota::indexing::IVFIndex;
 use khadyota::distance::cosine_distance;
+use khadyota::evaluation::{compute_ground_truth, recall_at_k};
+use khadyota::{DistanceMetric, SearchResult};
 use std::time::Instant;
 
 #[test]
@@ -19,56 +21,50 @@ fn test_ivf_speedup() {
     // Naive linear scan baseline
     println!("\n--- Naive Linear Scan ---");
     let start = Instant::now();
-    
-    let mut distances: Vec<(u32, f32)> = vectors
-        .iter()
-        .enumerate()
-        .map(|(i, v)| {
-            let dist = cosine_distance(&query, v);
-            (i as u32, dist)
-        })
-        .collect();
-    
-    distances.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-    let naive_top10: Vec<u32> = distances.iter().take(10).map(|(i, _)| *i).collect();
-    
+    let ground_truth = compute_ground_truth(&vectors, std::slice::from_ref(&query), 10, DistanceMetric::Cosine);
     let naive_time = start.elapsed();
     println!("Time: {:?}", naive_time);
     
+    let mut index = IVFIndex::new(512, 100, 1);
+    let build_start = Instant::now();
+    index.build(&vectors, 100);
+    println!("Build time: {:?}", build_start.elapsed());
+    
     // IVF search with different probe values
-    for num_probe in [1, 3, 5, 10] {
+    let mut last_recall = 0.0;
+    for num_probe in [1, 3, 5, 10, 100] {
         println!("\n--- IVF Search (probe={}) ---", num_probe);
-        
-        let mut index = IVFIndex::new(512, 100, num_probe);
-        
-        let build_start = Instant::now();
-        index.build(&vectors, 100);
-        println!("Build time: {:?}", build_start.elapsed());
+        index.set_num_probe(num_probe);
         
         let search_start = Instant::now();
         let clusters = index.probe(&query);
         let candidates = index.get_candidates(&clusters);
         
-        let mut distances: Vec<(u32, f32)> = candidates
+        let mut results: Vec<SearchResult> = candidates
             .iter()
-            .map(|&vec_id| {
-                let dist = cosine_distance(&query, &vectors[vec_id as usize]);
-                (vec_id, dist)
+            .map(|&id| SearchResult {
+                id,
+                distance: cosine_distance(&query, &vectors[id as usize]),
+                metadata: None,
+                external_id: None,
             })
             .collect();
         
-        distances.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        let ivf_top10: Vec<u32> = distances.iter().take(10).map(|(i, _)| *i).collect();
+        results.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.id.cmp(&b.id)));
+        results.truncate(10);
         
         let ivf_time = search_start.elapsed();
         println!("Search time: {:?}", ivf_time);
         println!("Speedup: {:.2}x", naive_time.as_secs_f64() / ivf_time.as_secs_f64());
         
-        // Calculate recall
-        let recall = ivf_top10.iter()
-            .filter(|id| naive_top10.contains(id))
-            .count() as f32 / 10.0;
+        let recall = recall_at_k(&[results], &ground_truth, 10);
         println!("Recall@10: {:.1}%", recall * 100.0);
         println!("Candidates searched: {} / {}", candidates.len(), vectors.len());
+        
+        // Probing more clusters only adds candidates, and probing all of
+        // them is an exhaustive search
+        assert!(recall >= last_recall, "recall fell from {} to {} at probe={}", last_recall, recall, num_probe);
+        last_recall = recall;
     }
-}
\ No newline at end of file
+    assert_eq!(last_recall, 1.0);
+}
@@ -0,0 +1,153 @@
+//! Journaled writes surviving a process that dies between saves
+
+use khadyota::harness::clustered_vectors;
+use khadyota::journal::journal_path;
+use khadyota::*;
+use serde_json::json;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 16;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 8,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+fn journal_len(path: &Path) -> u64 {
+    fs::metadata(journal_path(path)).unwrap().len()
+}
+
+#[test]
+fn test_journaled_writes_survive_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("durable.kdb");
+    let vectors = clustered_vectors(400, DIMENSIONS, 8, 0.2, 1);
+
+    let mut db = VectorDB::open_durable(&path, config()).unwrap();
+    assert!(db.is_durable());
+    for (i, vector) in vectors[..300].iter().enumerate() {
+        db.insert(vector.clone(), Some(json!({"n": i}))).unwrap();
+    }
+    db.build_index().unwrap();
+    db.insert_batch(vectors[300..].iter().map(|v| (v.clone(), None))).unwrap();
+    db.delete(5).unwrap();
+    db.update(6, vectors[7].clone(), Some(json!({"n": "moved"}))).unwrap();
+    db.insert_with_id("late", vectors[0].clone(), None).unwrap();
+    let checksum = db.checksum();
+    let top = |db: &VectorDB| {
+        [0, 150, 299, 350, 399].map(|id| db.search(&vectors[id], 5).unwrap().iter().map(|r| r.id).collect::<Vec<_>>())
+    };
+    let expected = top(&db);
+    // Dropped without a save: only the journal holds any of it
+    drop(db);
+    assert!(!path.exists());
+
+    let db = VectorDB::open_durable(&path, config()).unwrap();
+    assert!(db.load_warnings().is_empty());
+    assert_eq!((db.len(), db.checksum()), (400, checksum));
+    assert!(db.index_status().searchable);
+    assert_eq!(db.get(6).unwrap().metadata, Some(json!({"n": "moved"})));
+    assert!(db.get(5).is_err());
+    assert_eq!(top(&db), expected);
+    assert!(expected[3].contains(&350));
+    assert_eq!(db.id_of("late"), Some(400));
+}
+
+#[test]
+fn test_checkpoints_empty_the_journal() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("durable.kdb");
+    let vectors = clustered_vectors(200, DIMENSIONS, 8, 0.2, 2);
+
+    let mut db = VectorDB::open_durable(&path, config()).unwrap();
+    db.set_sync_policy(SyncPolicy::EveryN(16)).unwrap();
+    let empty = journal_len(&path);
+    db.insert_batch(vectors[..100].iter().map(|v| (v.clone(), None))).unwrap();
+    assert!(journal_len(&path) > empty);
+    db.checkpoint().unwrap();
+    assert_eq!(journal_len(&path), empty);
+
+    // Saving elsewhere leaves the journal be; saving over the snapshot
+    // is a checkpoint
+    db.insert(vectors[100].clone(), None).unwrap();
+    let grown = journal_len(&path);
+    db.save(&dir.path().join("copy.kdb")).unwrap();
+    assert_eq!(journal_len(&path), grown);
+    db.save(&path).unwrap();
+    assert_eq!(journal_len(&path), empty);
+
+    db.set_sync_policy(SyncPolicy::Manual).unwrap();
+    db.insert_batch(vectors[101..].iter().map(|v| (v.clone(), None))).unwrap();
+    db.sync_journal().unwrap();
+    let checksum = db.checksum();
+    drop(db);
+
+    // The snapshot and the journal written after it make up the database
+    assert_eq!(VectorDB::load(&path).unwrap().len(), 101);
+    let db = VectorDB::open_durable(&path, config()).unwrap();
+    assert_eq!((db.len(), db.checksum()), (200, checksum));
+    assert!(matches!(
+        VectorDB::open_durable(&path, Config { dimensions: 8, ..config() }),
+        Err(KhadyotaError::ConfigMismatch { field: "dimensions", .. })
+    ));
+}
+
+#[test]
+fn test_a_torn_last_record_is_dropped_with_a_warning() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("durable.kdb");
+    let vectors = clustered_vectors(50, DIMENSIONS, 8, 0.2, 3);
+
+    let mut db = VectorDB::open_durable(&path, config()).unwrap();
+    for vector in &vectors[..40] {
+        db.insert(vector.clone(), None).unwrap();
+    }
+    let before_last = journal_len(&path);
+    db.insert(vectors[40].clone(), None).unwrap();
+    drop(db);
+
+    // A crash part way through writing the last record
+    let journal = OpenOptions::new().write(true).open(journal_path(&path)).unwrap();
+    journal.set_len(journal_len(&path) - 3).unwrap();
+    drop(journal);
+
+    let mut db = VectorDB::open_durable(&path, config()).unwrap();
+    assert_eq!(db.len(), 40);
+    assert_eq!(db.load_warnings().len(), 1);
+    assert!(db.load_warnings()[0].contains(&format!("at offset {}", before_last)));
+    assert_eq!(journal_len(&path), before_last);
+
+    // Later writes follow the last good record
+    for vector in &vectors[40..] {
+        db.insert_opts(vector.clone(), InsertOptions::new().durable(true)).unwrap();
+    }
+    drop(db);
+    let db = VectorDB::open_durable(&path, config()).unwrap();
+    assert!(db.load_warnings().is_empty());
+    assert_eq!(db.len(), 50);
+    assert_eq!(db.get(40).unwrap().vector, vectors[40]);
+
+    // Garbage where a record should be is dropped the same way
+    fs::write(journal_path(&path), b"not a journal").unwrap();
+    let db = VectorDB::open_durable(&path, config()).unwrap();
+    assert_eq!((db.len(), db.load_warnings().len()), (0, 1));
+}
+
+#[test]
+fn test_journal_calls_need_a_durable_database() {
+    let mut db = VectorDB::new(config()).unwrap();
+    assert!(!db.is_durable());
+    assert!(matches!(db.set_sync_policy(SyncPolicy::Manual), Err(KhadyotaError::InvalidConfig(_))));
+    assert!(db.sync_journal().is_err());
+    assert!(db.checkpoint().is_err());
+    assert!(db.insert_opts(vec![0.0; DIMENSIONS], InsertOptions::new().durable(true)).is_err());
+}
@@ -0,0 +1,115 @@
+use khadyota::*;
+use std::time::Duration;
+
+fn build_db(seed: u64) -> VectorDB {
+    let config = Config {
+        dimensions: 32,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::None,
+        num_clusters: 8,
+        num_probe: 2,
+        seed: Some(seed),
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..2_000 {
+        let vector: Vec<f32> = (0..32).map(|j| ((i * 32 + j) as f32 * 0.37).sin()).collect();
+        db.insert(vector, None).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn queries(n: usize) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|i| (0..32).map(|j| ((i * 7 + j) as f32 * 0.11).cos()).collect())
+        .collect()
+}
+
+#[test]
+fn test_recording_is_off_by_default() {
+    let db = build_db(1);
+    for query in queries(10) {
+        db.search(&query, 5).unwrap();
+    }
+    db.batch_search(&queries(10), 5).unwrap();
+    assert!(db.latency_histogram().is_empty());
+}
+
+#[test]
+fn test_search_and_batch_search_record_every_query() {
+    let mut db = build_db(1);
+    db.set_latency_recording(true);
+
+    for query in queries(25) {
+        db.search(&query, 5).unwrap();
+    }
+    db.batch_search(&queries(40), 5).unwrap();
+
+    // Rejected queries are not timed
+    assert!(db.search(&[0.0; 3], 5).is_err());
+
+    let histogram = db.latency_histogram();
+    assert_eq!(histogram.count(), 65);
+    assert!(histogram.min() > Duration::ZERO);
+    assert!(histogram.min() <= histogram.p50());
+    assert!(histogram.p50() <= histogram.p99());
+    assert!(histogram.p99() <= histogram.max());
+
+    db.reset_latency();
+    assert!(db.latency_histogram().is_empty());
+    db.search(&queries(1)[0], 5).unwrap();
+    assert_eq!(db.latency_histogram().count(), 1);
+
+    db.set_latency_recording(false);
+    assert!(db.latency_histogram().is_empty());
+}
+
+#[test]
+fn test_batch_search_timed_lines_up_with_results() {
+    let mut db = build_db(2);
+    let queries = queries(30);
+
+    let timed = db.batch_search_timed(&queries, 5).unwrap();
+    assert_eq!(timed.len(), queries.len());
+    assert!(timed.iter().all(|(_, took)| *took > Duration::ZERO));
+
+    let plain = db.batch_search(&queries, 5).unwrap();
+    for ((results, _), expected) in timed.iter().zip(&plain) {
+        let ids: Vec<u32> = results.iter().map(|r| r.id).collect();
+        let expected: Vec<u32> = expected.iter().map(|r| r.id).collect();
+        assert_eq!(ids, expected);
+    }
+
+    // With recording on, the returned durations are what was recorded
+    db.set_latency_recording(true);
+    let timed = db.batch_search_timed(&queries, 5).unwrap();
+    let slowest = timed.iter().map(|(_, took)| *took).max().unwrap();
+    let histogram = db.latency_histogram();
+    assert_eq!(histogram.count(), 30);
+    assert_eq!(histogram.max(), slowest);
+}
+
+#[test]
+fn test_histograms_merge_across_shards() {
+    let shards: Vec<VectorDB> = (0..3)
+        .map(|seed| {
+            let mut db = build_db(seed);
+            db.set_latency_recording(true);
+            db
+        })
+        .collect();
+
+    for (i, shard) in shards.iter().enumerate() {
+        shard.batch_search(&queries(10 * (i + 1)), 5).unwrap();
+    }
+
+    let mut total = Histogram::new();
+    for shard in &shards {
+        total.merge(&shard.latency_histogram());
+    }
+    assert_eq!(total.count(), 60);
+    let slowest = shards.iter().map(|s| s.latency_histogram().max()).max().unwrap();
+    assert_eq!(total.max(), slowest);
+}
@@ -0,0 +1,159 @@
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+
+const FULL: usize = 64;
+const COARSE: usize = 16;
+
+/// Rows whose first `COARSE` components are an embedding of their own,
+/// refined by the rest
+fn rows(count: usize, seed: u64) -> Vec<Vec<f32>> {
+    let coarse = clustered_vectors(count, COARSE, 8, 0.1, seed);
+    let detail = clustered_vectors(count, FULL - COARSE, 32, 0.1, seed + 7);
+    coarse.into_iter().zip(detail).map(|(mut row, detail)| {
+        row.extend(detail.iter().map(|x| x * 0.5));
+        row
+    }).collect()
+}
+
+fn config(dimensions: usize, quantizer: QuantizerKind, matryoshka_dims: Vec<usize>) -> Config {
+    Config {
+        dimensions,
+        metric: DistanceMetric::Euclidean,
+        quantizer,
+        pq_subvectors: dimensions / 8,
+        num_clusters: 8,
+        num_probe: 3,
+        seed: Some(5),
+        matryoshka_dims,
+        ..Default::default()
+    }
+}
+
+fn build(config: Config, rows: &[Vec<f32>]) -> VectorDB {
+    harness::build_db(config, rows.to_vec()).unwrap()
+}
+
+fn exact_top(rows: &[Vec<f32>], query: &[f32], k: usize) -> Vec<u32> {
+    let mut scored: Vec<(u32, f32)> = rows
+        .iter()
+        .enumerate()
+        .map(|(id, row)| (id as u32, distance::compute_distance(query, &row[..query.len()], DistanceMetric::Euclidean)))
+        .collect();
+    scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    scored.into_iter().take(k).map(|(id, _)| id).collect()
+}
+
+fn recall(db: &VectorDB, rows: &[Vec<f32>], queries: &[Vec<f32>], params: &SearchParams) -> f32 {
+    let k = 10;
+    let hits: usize = queries
+        .iter()
+        .map(|query| {
+            let truth = exact_top(rows, query, k);
+            let found = db.search_with_params(query, k, params).unwrap();
+            found.iter().filter(|r| truth.contains(&r.id)).count()
+        })
+        .sum();
+    hits as f32 / (queries.len() * k) as f32
+}
+
+fn ids(results: &[SearchResult]) -> Vec<u32> {
+    results.iter().map(|r| r.id).collect()
+}
+
+#[test]
+fn test_coarse_queries_recall_like_a_coarse_only_db() {
+    let data = rows(2_000, 1);
+    let prefixes: Vec<Vec<f32>> = data.iter().map(|row| row[..COARSE].to_vec()).collect();
+    let queries: Vec<Vec<f32>> = rows(50, 2).into_iter().map(|row| row[..COARSE].to_vec()).collect();
+
+    for quantizer in [QuantizerKind::PQ, QuantizerKind::SQ8, QuantizerKind::None] {
+        let nested = build(config(FULL, quantizer, vec![COARSE, FULL]), &data);
+        let coarse_only = build(config(COARSE, quantizer, vec![]), &prefixes);
+        let params = SearchParams::default();
+
+        let nested_recall = recall(&nested, &data, &queries, &params);
+        let coarse_recall = recall(&coarse_only, &prefixes, &queries, &params);
+        assert!(nested_recall >= coarse_recall - 0.1, "{:?}: {} vs {}", quantizer, nested_recall, coarse_recall);
+    }
+}
+
+#[test]
+fn test_full_queries_are_unchanged() {
+    let data = rows(1_000, 3);
+    let plain = build(config(FULL, QuantizerKind::PQ, vec![]), &data);
+    let nested = build(config(FULL, QuantizerKind::PQ, vec![COARSE, 32]), &data);
+    let params = SearchParams::builder().rerank(40).build();
+    for query in rows(20, 4) {
+        assert_eq!(ids(&nested.search(&query, 10).unwrap()), ids(&plain.search(&query, 10).unwrap()));
+        assert_eq!(
+            ids(&nested.search_with_params(&query, 10, &params).unwrap()),
+            ids(&plain.search_with_params(&query, 10, &params).unwrap())
+        );
+    }
+}
+
+#[test]
+fn test_refine_rescores_a_coarse_shortlist_at_full_width() {
+    let data = rows(2_000, 5);
+    let queries = rows(30, 6);
+    for quantizer in [QuantizerKind::PQ, QuantizerKind::None] {
+        let db = build(config(FULL, quantizer, vec![COARSE]), &data);
+        let refined = SearchParams::builder().coarse_dims(COARSE).rerank(100).build();
+        for query in &queries {
+            let results = db.search_with_params(query, 10, &refined).unwrap();
+            assert_eq!(results.len(), 10);
+            // Distances are at full width, whatever scored the shortlist
+            for result in &results {
+                let row = db.get(result.id).unwrap().vector;
+                assert_eq!(result.distance, distance::compute_distance(query, &row, DistanceMetric::Euclidean));
+            }
+        }
+        // Better than PQ distances at full width, close to an exact scan
+        let refined_recall = recall(&db, &data, &queries, &refined);
+        let plain_recall = recall(&db, &data, &queries, &SearchParams::default());
+        assert!(refined_recall >= plain_recall.min(0.8), "{:?}: {} vs {}", quantizer, refined_recall, plain_recall);
+    }
+}
+
+#[test]
+fn test_cosine_prefixes_are_scored_exactly() {
+    let data = rows(1_000, 7);
+    let db = build(
+        Config {
+            metric: DistanceMetric::Cosine,
+            ..config(FULL, QuantizerKind::PQ, vec![COARSE])
+        },
+        &data,
+    );
+    let query = &data[17][..COARSE];
+    let params = SearchParams::builder().num_probe(8).build();
+    let results = db.search_with_params(query, 5, &params).unwrap();
+    assert_eq!(results[0].id, 17);
+    let row = db.get(results[1].id).unwrap().vector;
+    assert_eq!(results[1].distance, distance::compute_distance(query, &row[..COARSE], DistanceMetric::Cosine));
+}
+
+#[test]
+fn test_only_configured_prefixes_are_accepted() {
+    let data = rows(300, 9);
+    let db = build(config(FULL, QuantizerKind::PQ, vec![COARSE, FULL]), &data);
+    assert!(db.validate_query(&data[0][..COARSE]).is_ok());
+    assert_eq!(db.input_contract().prefix_dimensions, [COARSE]);
+    assert!(db.input_contract().validate(&data[0][..COARSE]).is_ok());
+    for len in [8, 24, FULL + 8] {
+        let query = vec![0.5; len];
+        assert!(matches!(db.search(&query, 1), Err(KhadyotaError::DimensionMismatch { expected: FULL, .. })));
+    }
+    // Only searches take prefixes
+    assert!(matches!(db.range_search(&data[0][..COARSE], 1.0), Err(KhadyotaError::DimensionMismatch { .. })));
+    assert!(db.batch_search(&[data[0][..COARSE].to_vec()], 1).is_err());
+    let wrong = SearchParams::builder().coarse_dims(24).build();
+    assert!(matches!(db.search_with_params(&data[0], 1, &wrong), Err(KhadyotaError::InvalidConfig(_))));
+
+    // Prefixes must ascend below the full width and, with PQ, end on a
+    // subvector boundary
+    for dims in [vec![32, COARSE], vec![0], vec![FULL + 8], vec![12]] {
+        assert!(VectorDB::new(config(FULL, QuantizerKind::PQ, dims.clone())).is_err(), "{:?}", dims);
+    }
+    assert!(VectorDB::new(config(FULL, QuantizerKind::SQ8, vec![12])).is_ok());
+}
@@ -0,0 +1,203 @@
+use khadyota::harness::{build_db, clustered_vectors, random_vectors};
+use khadyota::*;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+const DIMS: usize = 8;
+const ROWS: usize = 20_000;
+
+/// Skewed metadata: `user_id` unique on every entry, `flag` rare on 1%,
+/// `region` one of eight on 30%, `tag` one of two hundred on 5%
+fn skewed(i: usize) -> Value {
+    let mut metadata = json!({
+        "user_id": format!("u{}", i),
+        "flag": if i.is_multiple_of(100) { "rare" } else { "common" },
+    });
+    if i % 10 < 3 {
+        metadata["region"] = json!(i % 8);
+    }
+    if i.is_multiple_of(20) {
+        metadata["tag"] = json!(format!("t{}", i % 200));
+    }
+    metadata
+}
+
+fn config(metadata_index: MetadataIndexConfig) -> Config {
+    Config {
+        dimensions: DIMS,
+        quantizer: QuantizerKind::None,
+        num_clusters: 8,
+        seed: Some(3),
+        metadata_index,
+        ..Default::default()
+    }
+}
+
+fn skewed_db(metadata_index: MetadataIndexConfig) -> VectorDB {
+    let mut db = VectorDB::new(config(metadata_index)).unwrap();
+    for (i, vector) in random_vectors(ROWS, DIMS, 1).into_iter().enumerate() {
+        db.insert(vector, Some(skewed(i))).unwrap();
+    }
+    db
+}
+
+/// Filters on a spread of values of every key, each checked against a
+/// scan over all ids
+fn assert_filters_match_brute_force(db: &VectorDB) {
+    let probes = [
+        ("user_id", json!("u0")),
+        ("user_id", json!("u12345")),
+        ("user_id", json!("missing")),
+        ("flag", json!("rare")),
+        ("flag", json!("common")),
+        ("region", json!(3)),
+        ("region", json!("3")),
+        ("tag", json!("t40")),
+        ("absent", json!(1)),
+    ];
+    for (key, value) in probes {
+        let filter = db.metadata_filter(key, &value);
+        let expected: Vec<u32> = (0..db.len() as u32)
+            .filter(|&id| db.get_metadata(id).and_then(|m| m.get(key)) == Some(&value))
+            .collect();
+        let admitted: Vec<u32> = (0..db.len() as u32).filter(|&id| filter.admits(id)).collect();
+        assert_eq!(admitted, expected, "{} = {}", key, value);
+    }
+}
+
+fn strategy(report: &MetadataIndexReport, key: &str) -> KeyIndexStrategy {
+    report.keys.iter().find(|k| k.key == key).unwrap().strategy
+}
+
+#[test]
+fn test_each_key_gets_the_structure_its_cardinality_suits() {
+    let db = skewed_db(MetadataIndexConfig::default());
+    assert_filters_match_brute_force(&db);
+
+    let report = db.metadata_index_report();
+    assert_eq!(
+        strategy(&report, "user_id"),
+        KeyIndexStrategy::NotUseful(IndexNotUseful::HighCardinality { distinct_values: ROWS, entries: ROWS })
+    );
+    assert_eq!(strategy(&report, "flag"), KeyIndexStrategy::Bitset);
+    assert_eq!(strategy(&report, "region"), KeyIndexStrategy::Bitset);
+    assert_eq!(strategy(&report, "tag"), KeyIndexStrategy::SortedIds);
+    assert_eq!(report.keys.len(), 5);
+    assert_eq!(report.keys.iter().map(|k| k.key.as_str()).collect::<Vec<_>>(), ["absent", "flag", "region", "tag", "user_id"]);
+    assert!(report.used_bytes <= report.budget_bytes);
+    assert_eq!(report.used_bytes, report.keys.iter().map(|k| k.bytes).sum::<usize>());
+}
+
+#[test]
+fn test_keys_past_the_budget_fall_back_to_scanning() {
+    for budget_bytes in [0, 1_000, 8_000, 40_000, 400_000] {
+        let db = skewed_db(MetadataIndexConfig {
+            budget_bytes,
+            always_index: ["user_id".to_string()].into(),
+            ..Default::default()
+        });
+        assert_filters_match_brute_force(&db);
+
+        let report = db.metadata_index_report();
+        assert!(report.used_bytes <= budget_bytes, "{} over {}", report.used_bytes, budget_bytes);
+        for key in &report.keys {
+            match key.strategy {
+                KeyIndexStrategy::NotUseful(IndexNotUseful::OverBudget { bytes, available }) => {
+                    assert!(bytes > available);
+                    assert_eq!(key.bytes, 0);
+                }
+                KeyIndexStrategy::NotUseful(reason) => panic!("{}: {:?}", key.key, reason),
+                _ => assert!(key.bytes > 0 || key.entries == 0),
+            }
+        }
+        if budget_bytes == 0 {
+            assert!(report.keys.iter().all(|k| k.key == "absent" || matches!(k.strategy, KeyIndexStrategy::NotUseful(_))));
+        }
+    }
+}
+
+#[test]
+fn test_always_indexed_keys_skip_the_cardinality_check() {
+    let db = skewed_db(MetadataIndexConfig {
+        always_index: ["user_id".to_string()].into(),
+        ..Default::default()
+    });
+    assert_filters_match_brute_force(&db);
+    assert_eq!(strategy(&db.metadata_index_report(), "user_id"), KeyIndexStrategy::SortedIds);
+}
+
+#[test]
+fn test_indexes_follow_changes_to_metadata() {
+    let vectors = clustered_vectors(2_000, DIMS, 8, 0.2, 5);
+    let mut db = build_db(config(MetadataIndexConfig::default()), vectors).unwrap();
+    for id in 0..2_000 {
+        db.update(id, db.get(id).unwrap().vector, Some(json!({"shard": id % 4}))).unwrap();
+    }
+    let query = random_vectors(1, DIMS, 6).remove(0);
+    let params = SearchParams::builder().filter(db.metadata_filter("shard", &json!(1))).build();
+    let results = db.search_with_params(&query, 10, &params).unwrap();
+    assert_eq!(results.len(), 10);
+    assert!(results.iter().all(|r| r.metadata.as_ref().unwrap()["shard"] == 1));
+
+    // A change drops what was planned; the next filter sees it
+    db.update(0, db.get(0).unwrap().vector, Some(json!({"shard": 1}))).unwrap();
+    db.delete(1).unwrap();
+    assert!(db.metadata_index_report().keys.is_empty());
+    let filter = db.metadata_filter("shard", &json!(1));
+    assert!(filter.admits(0) && !filter.admits(1) && filter.admits(5));
+    assert_eq!(db.metadata_index_report().keys[0].entries, 1_999);
+}
+
+#[test]
+fn test_range_filters_combine_with_equality_filters() {
+    let mut db = skewed_db(MetadataIndexConfig::default());
+    db.build_index().unwrap();
+    let value = |id: u32, key: &str| db.get_metadata(id).and_then(|m| m.get(key)).cloned();
+    let region = |id: u32| value(id, "region").and_then(|v| v.as_f64());
+    let admitted = |filter: &CandidateFilter| (0..ROWS as u32).filter(|&id| filter.admits(id)).collect::<Vec<_>>();
+    let expected = |keep: &dyn Fn(u32) -> bool| (0..ROWS as u32).filter(|&id| keep(id)).collect::<Vec<_>>();
+
+    assert_eq!(
+        admitted(&db.metadata_range_filter("region", 2.0..=4.0)),
+        expected(&|id| region(id).is_some_and(|r| (2.0..=4.0).contains(&r)))
+    );
+    // Strings and missing keys are not numbers in any range
+    assert_eq!(admitted(&db.metadata_range_filter("region", ..)), expected(&|id| region(id).is_some()));
+    assert!(admitted(&db.metadata_range_filter("flag", ..)).is_empty());
+
+    let common_east = db.metadata_filter("flag", &json!("common")).and(db.metadata_range_filter("region", 5.0..));
+    let rare_or_west = db.metadata_filter("flag", &json!("rare")).or(db.metadata_range_filter("region", ..1.0));
+    assert_eq!(
+        admitted(&common_east),
+        expected(&|id| value(id, "flag") == Some(json!("common")) && region(id).is_some_and(|r| r >= 5.0))
+    );
+    assert_eq!(
+        admitted(&rare_or_west),
+        expected(&|id| value(id, "flag") == Some(json!("rare")) || region(id).is_some_and(|r| r < 1.0))
+    );
+
+    // Without codes every row is scored, so the filtered top 10 are the
+    // first 10 admitted of the full ranking
+    let query = random_vectors(1, DIMS, 7).remove(0);
+    let ranked: Vec<u32> = db.search(&query, ROWS).unwrap().iter().map(|r| r.id).collect();
+    let results = db.search_filtered(&query, 10, common_east.clone()).unwrap();
+    let top: Vec<u32> = ranked.into_iter().filter(|&id| common_east.admits(id)).take(10).collect();
+    assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), top);
+
+    let budget = |ms| Duration::from_millis(ms);
+    let limited = db.metadata_filter("flag", &json!("rare")).with_budget(budget(5));
+    assert_eq!(limited.clone().and(rare_or_west.clone()).budget(), Some(budget(5)));
+    let nested = rare_or_west.or(limited.with_budget(budget(9)).and(common_east.with_budget(budget(2))));
+    assert_eq!(nested.budget(), Some(budget(2)));
+}
+
+#[test]
+fn test_invalid_cardinality_limit_is_rejected() {
+    for fraction in [0.0, 1.5, f32::NAN] {
+        let config = config(MetadataIndexConfig {
+            max_distinct_fraction: fraction,
+            ..Default::default()
+        });
+        assert!(matches!(VectorDB::new(config), Err(KhadyotaError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,154 @@
+use khadyota::metadata::metadata_size;
+use khadyota::*;
+use serde_json::json;
+use tempfile::TempDir;
+
+const DIMS: usize = 8;
+const LIMIT: usize = 64;
+
+fn config(external_metadata: bool) -> Config {
+    Config {
+        dimensions: DIMS,
+        quantizer: QuantizerKind::None,
+        num_clusters: 2,
+        num_probe: 2,
+        seed: Some(1),
+        max_metadata_bytes: LIMIT,
+        external_metadata,
+        ..Default::default()
+    }
+}
+
+fn vector(i: usize) -> Vec<f32> {
+    (0..DIMS).map(|j| ((i * DIMS + j) as f32 * 0.37).sin()).collect()
+}
+
+fn blob(len: usize) -> serde_json::Value {
+    json!({ "blob": "x".repeat(len) })
+}
+
+fn assert_too_large(result: Result<impl std::fmt::Debug>, value: &serde_json::Value) {
+    match result {
+        Err(KhadyotaError::MetadataTooLarge { size, limit }) => {
+            assert_eq!(size, metadata_size(value));
+            assert_eq!(limit, LIMIT);
+        }
+        other => panic!("expected MetadataTooLarge, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_oversized_metadata_is_rejected_everywhere() {
+    let mut db = VectorDB::new(config(false)).unwrap();
+    let id = db.insert(vector(0), Some(json!({"ok": true}))).unwrap();
+    let checksum = db.checksum();
+    let big = blob(LIMIT);
+
+    assert_too_large(db.insert(vector(1), Some(big.clone())), &big);
+    assert_too_large(db.insert_opts(vector(1), InsertOptions::new().metadata(big.clone())), &big);
+    assert_too_large(db.update(id, vector(1), Some(big.clone())), &big);
+
+    let mut changeset = ChangeSet::new();
+    changeset.update_metadata(id, Some(big.clone()));
+    match db.apply_changeset(changeset) {
+        Err(KhadyotaError::InvalidChange { op: 0, source }) => assert_too_large(Err::<(), _>(*source), &big),
+        other => panic!("expected InvalidChange, got {:?}", other),
+    }
+    assert_eq!(db.checksum(), checksum);
+
+    // Exactly at the limit is fine
+    let fits = blob(LIMIT - metadata_size(&blob(0)));
+    assert_eq!(metadata_size(&fits), LIMIT);
+    db.insert(vector(1), Some(fits)).unwrap();
+
+    let mut segmented = SegmentedDB::new(config(false), 100).unwrap();
+    assert_too_large(segmented.insert(vector(0), Some(big.clone())), &big);
+}
+
+#[test]
+fn test_memory_usage_accounts_for_every_value() {
+    let mut db = VectorDB::new(config(false)).unwrap();
+    let values = [
+        json!({"title": "a", "tags": ["x", "y"]}),
+        json!({"title": "a much longer title", "body": "z".repeat(10)}),
+        json!("not an object"),
+    ];
+    for (i, value) in values.iter().enumerate() {
+        db.insert(vector(i), Some(value.clone())).unwrap();
+    }
+    db.insert(vector(3), None).unwrap();
+
+    let usage = db.memory_usage();
+    let expected: usize = values.iter().map(|v| serde_json::to_string(v).unwrap().len()).sum();
+    assert_eq!(usage.metadata_bytes, expected);
+    assert_eq!(usage.external_metadata_bytes, 0);
+    assert_eq!(usage.vector_bytes, 4 * DIMS * 4);
+    assert_eq!(usage.code_bytes, 0);
+    assert_eq!(usage.largest_metadata, Some((1, metadata_size(&values[1]))));
+    assert_eq!(usage.total_bytes(), expected + 4 * DIMS * 4);
+
+    let keys = db.metadata_keys();
+    let names: Vec<&str> = keys.iter().map(|k| k.key.as_str()).collect();
+    assert_eq!(names, ["title", "body", "tags"]);
+    let title = &keys[0];
+    assert_eq!(title.entries, 2);
+    assert_eq!(title.bytes, metadata_size(&json!("a")) + metadata_size(&json!("a much longer title")));
+    assert_eq!(title.largest, (1, metadata_size(&json!("a much longer title"))));
+
+    // Deletes release their metadata
+    db.delete(1).unwrap();
+    assert_eq!(db.memory_usage().metadata_bytes, expected - metadata_size(&values[1]));
+    assert!(db.metadata_keys().iter().all(|k| k.key != "body"));
+}
+
+#[test]
+fn test_external_blobs_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.khadyota");
+
+    let mut db = VectorDB::new(config(true)).unwrap();
+    for i in 0..20 {
+        db.insert(vector(i), Some(json!({"i": i}))).unwrap();
+    }
+    let big = blob(10 * LIMIT);
+    let id = db.insert(vector(20), Some(big.clone())).unwrap();
+    db.build_index().unwrap();
+
+    let usage = db.memory_usage();
+    assert_eq!(usage.external_metadata_bytes, metadata_size(&big));
+    assert_eq!(usage.largest_metadata, Some((id, metadata_size(&big))));
+
+    // Only returned when asked for
+    assert_eq!(db.get(id).unwrap().metadata, None);
+    assert_eq!(db.external_metadata(id), Some(&big));
+    let nearest = |db: &VectorDB, params: &SearchParams| {
+        let results = db.search_with_params(&vector(20), 1, params).unwrap();
+        assert_eq!(results[0].id, id);
+        results[0].metadata.clone()
+    };
+    assert_eq!(nearest(&db, &SearchParams::default()), None);
+    let projected = SearchParams::builder().include_external_metadata(true).build();
+    assert_eq!(nearest(&db, &projected), Some(big.clone()));
+    assert_eq!(nearest(&db, &SearchParams::builder().include_metadata(false).build()), None);
+
+    db.save(&path).unwrap();
+    let mut loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    assert_eq!(loaded.external_metadata(id), Some(&big));
+    assert_eq!(nearest(&loaded, &projected), Some(big.clone()));
+    assert_eq!(loaded.get(3).unwrap().metadata, Some(json!({"i": 3})));
+
+    // Shrinking the value brings it back with ordinary metadata
+    loaded.update(id, vector(20), Some(json!({"i": 20}))).unwrap();
+    assert_eq!(loaded.external_metadata(id), None);
+    assert_eq!(nearest(&loaded, &SearchParams::default()), Some(json!({"i": 20})));
+    assert_eq!(loaded.memory_usage().external_metadata_bytes, 0);
+}
+
+#[test]
+fn test_configs_saved_before_limits_get_the_default() {
+    let old = rmp_serde::to_vec(&(16usize, DistanceMetric::Cosine, true, 8usize, 100usize, 10usize, Some(1u64))).unwrap();
+    let config: Config = rmp_serde::from_slice(&old).unwrap();
+    assert_eq!(config.max_metadata_bytes, DEFAULT_MAX_METADATA_BYTES);
+    assert!(!config.external_metadata);
+}
@@ -0,0 +1,284 @@
+use khadyota::distance::compute_distance;
+use khadyota::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+
+const DIMS: usize = 32;
+const K: usize = 10;
+
+/// Clustered directions with norms spread over a decade, so the three
+/// metrics disagree about neighbours
+fn dataset(n: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let centers: Vec<Vec<f32>> = (0..20)
+        .map(|_| (0..DIMS).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+
+    (0..n)
+        .map(|_| {
+            let center = &centers[rng.gen_range(0..centers.len())];
+            let scale = rng.gen_range(0.5..5.0);
+            center.iter().map(|c| (c + rng.gen_range(-0.3..0.3)) * scale).collect()
+        })
+        .collect()
+}
+
+fn build_db(metric: DistanceMetric, use_pq: bool, vectors: &[Vec<f32>]) -> VectorDB {
+    let config = Config {
+        dimensions: DIMS,
+        metric,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 8,
+        num_clusters: 20,
+        num_probe: 5,
+        seed: Some(11),
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for vector in vectors {
+        db.insert(vector.clone(), None).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn brute_force(vectors: &[Vec<f32>], query: &[f32], metric: DistanceMetric) -> Vec<(u32, f32)> {
+    let mut scored: Vec<(u32, f32)> = vectors
+        .iter()
+        .enumerate()
+        .map(|(id, v)| (id as u32, compute_distance(query, v, metric)))
+        .collect();
+    scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    scored.truncate(K);
+    scored
+}
+
+/// Mean recall@K of `params` against brute force, and the mean absolute
+/// error of the returned distances
+fn measure(db: &VectorDB, vectors: &[Vec<f32>], queries: &[Vec<f32>], metric: DistanceMetric, params: &SearchParams) -> (f64, f64) {
+    let mut recall = 0.0;
+    let mut error = 0.0;
+    let mut compared = 0;
+
+    for query in queries {
+        let expected: HashSet<u32> = brute_force(vectors, query, metric).iter().map(|&(id, _)| id).collect();
+        let results = db.search_with_params(query, K, params).unwrap();
+        recall += results.iter().filter(|r| expected.contains(&r.id)).count() as f64 / K as f64;
+
+        for result in &results {
+            let exact = compute_distance(query, &vectors[result.id as usize], metric);
+            error += (result.distance - exact).abs() as f64;
+            compared += 1;
+        }
+    }
+
+    (recall / queries.len() as f64, error / compared as f64)
+}
+
+#[test]
+fn test_pq_index_agrees_with_linear_scan() {
+    let vectors = dataset(2_000, 1);
+    let queries = dataset(30, 2);
+
+    for metric in [DistanceMetric::Euclidean, DistanceMetric::Cosine, DistanceMetric::DotProduct] {
+        let db = build_db(metric, true, &vectors);
+        let (recall, error) = measure(&db, &vectors, &queries, metric, &SearchParams::default());
+
+        // An index built for another metric ranks by the wrong distance
+        let other = if metric == DistanceMetric::Euclidean {
+            DistanceMetric::Cosine
+        } else {
+            DistanceMetric::Euclidean
+        };
+        let mismatched = build_db(other, true, &vectors);
+        let (mismatched_recall, _) = measure(&mismatched, &vectors, &queries, metric, &SearchParams::default());
+
+        // Approximate distances are in the metric's own units
+        let scale = brute_force(&vectors, &queries[0], metric)[0].1.abs().max(1.0) as f64;
+        println!(
+            "{:?}: recall@{} {:.3} ({:.3} built for {:?}), distance error {:.4} (scale {:.2})",
+            metric, K, recall, mismatched_recall, other, error, scale
+        );
+        assert!(recall >= 0.3, "{:?} recall@{} was {:.3}", metric, K, recall);
+        assert!(recall > mismatched_recall + 0.1, "{:?} recall@{} was {:.3}", metric, K, recall);
+        assert!(error <= 0.1 * scale, "{:?} distance error was {:.4}", metric, error);
+
+        // Reranking makes distances exact and recall near perfect
+        let rerank = SearchParams::builder().rerank(100).build();
+        let (recall, error) = measure(&db, &vectors, &queries, metric, &rerank);
+        assert!(recall >= 0.9, "{:?} reranked recall@{} was {:.3}", metric, K, recall);
+        assert!(error < 1e-4, "{:?} reranked distance error was {:.6}", metric, error);
+    }
+}
+
+#[test]
+fn test_ivf_probes_by_metric() {
+    let vectors = dataset(2_000, 3);
+    let queries = dataset(30, 4);
+
+    for metric in [
+        DistanceMetric::Euclidean,
+        DistanceMetric::Cosine,
+        DistanceMetric::DotProduct,
+        DistanceMetric::Manhattan,
+    ] {
+        let db = build_db(metric, false, &vectors);
+        let probed = SearchParams::builder().num_probe(5).build();
+        let (recall, error) = measure(&db, &vectors, &queries, metric, &probed);
+
+        // Clusters are formed by L2, and the rows of largest inner
+        // product, the longest in the query's direction, spread over more
+        // of them than a neighbourhood does
+        let floor = if metric == DistanceMetric::DotProduct { 0.8 } else { 0.9 };
+        println!("{:?}: probed recall@{} {:.3}", metric, K, recall);
+        assert!(recall >= floor, "{:?} probed recall@{} was {:.3}", metric, K, recall);
+        assert!(error < 1e-4);
+    }
+}
+
+#[test]
+fn test_cosine_index_ignores_scale() {
+    let vectors = dataset(2_000, 5);
+    let db = build_db(DistanceMetric::Cosine, true, &vectors);
+
+    // A scaled copy of a stored vector probes its cluster and matches it.
+    // Without rerank the approximate distances stay near cosine's range.
+    let probe_one = SearchParams::builder().num_probe(1).build();
+    let reranked = SearchParams::builder().num_probe(1).rerank(50).build();
+    for id in (0..2_000).step_by(97) {
+        let scaled: Vec<f32> = vectors[id].iter().map(|x| x * 40.0).collect();
+        let results = db.search_with_params(&scaled, 5, &probe_one).unwrap();
+        assert!(results.iter().all(|r| (-0.1..=2.1).contains(&r.distance)));
+
+        let results = db.search_with_params(&scaled, 1, &reranked).unwrap();
+        assert_eq!(results[0].id, id as u32);
+        assert!(results[0].distance < 1e-4);
+    }
+}
+
+#[test]
+fn test_dot_product_ranks_the_largest_inner_product_first() {
+    let vectors = dataset(2_000, 8);
+    let queries = dataset(20, 9);
+    let mut flat = VectorDB::new(Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::DotProduct,
+        index: IndexKind::Flat,
+        ..Default::default()
+    })
+    .unwrap();
+    flat.insert_batch(vectors.iter().map(|v| (v.clone(), None))).unwrap();
+    flat.build_index().unwrap();
+
+    // Without codes or a probe count searches scan every row; with every
+    // cluster probed, only the ordering decides the top result
+    let every_cluster = SearchParams::builder().num_probe(20).build();
+    let reranked = SearchParams::builder().num_probe(20).rerank(100).build();
+    let searches = [
+        ("linear", build_db(DistanceMetric::DotProduct, false, &vectors), SearchParams::default()),
+        ("flat", flat, SearchParams::default()),
+        ("ivf", build_db(DistanceMetric::DotProduct, false, &vectors), every_cluster),
+        ("pq", build_db(DistanceMetric::DotProduct, true, &vectors), reranked),
+    ];
+    for (name, db, params) in &searches {
+        let batch = db.batch_search(&queries, K).unwrap();
+        for (query, batched) in queries.iter().zip(&batch) {
+            let inner = |id: u32| query.iter().zip(&vectors[id as usize]).map(|(a, b)| a * b).sum::<f32>();
+            let best = (0..vectors.len() as u32).max_by(|&a, &b| inner(a).total_cmp(&inner(b))).unwrap();
+
+            let results = db.search_with_params(query, K, params).unwrap();
+            assert_eq!(results[0].id, best, "{}", name);
+            let top = results[0].similarity(DistanceMetric::DotProduct);
+            assert!((top - inner(best)).abs() <= 1e-4 * inner(best).abs().max(1.0));
+            assert!(results.windows(2).all(|pair| pair[0].distance <= pair[1].distance));
+            if params.rerank.is_none() && params.num_probe.is_none() {
+                assert_eq!(batched[0].id, best);
+            }
+        }
+    }
+
+    // Higher is better under every metric
+    let result = SearchResult { id: 0, distance: 0.25, metadata: None, external_id: None };
+    assert_eq!(result.similarity(DistanceMetric::Cosine), 0.75);
+    assert_eq!(result.similarity(DistanceMetric::Euclidean), -0.25);
+}
+
+#[test]
+fn test_parts_for_another_metric_are_rejected() {
+    let vectors = dataset(500, 6);
+    let cosine = build_db(DistanceMetric::Cosine, true, &vectors);
+    let path = tempfile::NamedTempFile::new().unwrap();
+    cosine.save(path.path()).unwrap();
+    let loaded = VectorDB::load(path.path()).unwrap();
+    assert!(loaded.load_warnings().is_empty());
+
+    let codec = quantization::PQCodec::train_for_metric(&vectors, 8, Some(1), DistanceMetric::Cosine).unwrap();
+    let mut quantized = storage::QuantizedVectors::new(codec);
+    quantized.add_batch(vectors.clone());
+    let mut ivf = indexing::IVFIndex::with_metric(DIMS, 4, 2, DistanceMetric::Cosine);
+    ivf.build_seeded(&vectors, 4, Some(1));
+
+    let config = Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 8,
+        ..Default::default()
+    };
+    let Err(KhadyotaError::IncompatibleComponents(report)) = VectorDB::from_parts(config, vectors, Some(quantized), Some(ivf)) else {
+        panic!("cosine parts were accepted for a Euclidean collection");
+    };
+    assert_eq!(
+        report.violations,
+        [
+            Violation::CodecMetric {
+                expected: DistanceMetric::Euclidean,
+                found: DistanceMetric::Cosine
+            },
+            Violation::IndexMetric {
+                expected: DistanceMetric::Euclidean,
+                found: DistanceMetric::Cosine
+            },
+        ]
+    );
+}
+
+/// Bit-packed hashes as 0.0/1.0 components: clustered codes with a few
+/// bits flipped
+fn binary_dataset(n: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let centers: Vec<Vec<bool>> = (0..20).map(|_| (0..DIMS).map(|_| rng.gen_bool(0.5)).collect()).collect();
+
+    (0..n)
+        .map(|_| {
+            let center = &centers[rng.gen_range(0..centers.len())];
+            center
+                .iter()
+                .map(|&bit| if bit != rng.gen_bool(0.05) { 1.0 } else { 0.0 })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_hamming_index_on_binary_codes() {
+    let vectors = binary_dataset(2_000, 7);
+    let queries = binary_dataset(30, 7 ^ 1);
+    let db = build_db(DistanceMetric::Hamming, true, &vectors);
+
+    // Ties are common, so compare distances rather than ids
+    let rerank = SearchParams::builder().rerank(100).build();
+    let mut matched = 0;
+    for query in &queries {
+        let results = db.search(query, K).unwrap();
+        assert!(results.iter().all(|r| r.distance.fract() == 0.0 && r.distance <= DIMS as f32));
+
+        let expected: Vec<f32> = brute_force(&vectors, query, DistanceMetric::Hamming).iter().map(|&(_, d)| d).collect();
+        let reranked: Vec<f32> = db.search_with_params(query, K, &rerank).unwrap().iter().map(|r| r.distance).collect();
+        if reranked == expected {
+            matched += 1;
+        }
+    }
+    assert!(matched >= 27, "reranked Hamming distances matched brute force for {}/30 queries", matched);
+}
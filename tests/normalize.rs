@@ -0,0 +1,116 @@
+//! Cosine collections that store unit-length rows under `Config::normalize`
+
+use khadyota::distance::dot_product;
+use khadyota::harness::{clustered_vectors, random_vectors};
+use khadyota::{Config, DistanceMetric, KhadyotaError, QuantizerKind, VectorDB};
+use tempfile::TempDir;
+
+fn config(normalize: bool, quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions: 16,
+        metric: DistanceMetric::Cosine,
+        quantizer,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 8,
+        seed: Some(3),
+        normalize,
+        ..Default::default()
+    }
+}
+
+/// Rows of very different lengths, so normalizing changes them
+fn scaled_vectors() -> Vec<Vec<f32>> {
+    clustered_vectors(800, 16, 8, 0.3, 1)
+        .into_iter()
+        .enumerate()
+        .map(|(i, v)| v.into_iter().map(|x| x * (1 + i % 7) as f32 * 3.0).collect())
+        .collect()
+}
+
+fn build(config: Config, vectors: &[Vec<f32>]) -> VectorDB {
+    let mut db = VectorDB::new(config).unwrap();
+    db.insert_batch(vectors.iter().map(|v| (v.clone(), None))).unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+#[test]
+fn test_results_match_unnormalized_storage() {
+    let vectors = scaled_vectors();
+    let queries = random_vectors(20, 16, 9);
+
+    for quantizer in [QuantizerKind::None, QuantizerKind::PQ, QuantizerKind::SQ8] {
+        let raw = build(config(false, quantizer), &vectors);
+        let unit = build(config(true, quantizer), &vectors);
+        for query in &queries {
+            let expected = raw.search(query, 10).unwrap();
+            let found = unit.search(query, 10).unwrap();
+            let ids = |results: &[khadyota::SearchResult]| results.iter().map(|r| r.id).collect::<Vec<_>>();
+            assert_eq!(ids(&found), ids(&expected), "{:?}", quantizer);
+            for (a, b) in found.iter().zip(&expected) {
+                assert!((a.distance - b.distance).abs() < 1e-5, "{:?}: {} vs {}", quantizer, a.distance, b.distance);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rows_are_stored_at_unit_length_and_scored_by_dot_product() {
+    let vectors = scaled_vectors();
+    let db = build(config(true, QuantizerKind::None), &vectors);
+    for id in [0, 5, 799] {
+        let stored = db.get(id).unwrap().vector;
+        assert!((dot_product(&stored, &stored) - 1.0).abs() < 1e-5);
+    }
+
+    // Distances are exactly one minus the dot product of the stored row
+    // and the normalized query, not the two-norm cosine kernel's value
+    for query in random_vectors(10, 16, 4) {
+        let prepared = db.prepare_query(&query).unwrap();
+        assert!((dot_product(&prepared, &prepared) - 1.0).abs() < 1e-5);
+        for result in db.search(&query, 10).unwrap() {
+            let row = db.get(result.id).unwrap().vector;
+            assert_eq!(result.distance.to_bits(), (1.0 - dot_product(&prepared, &row)).to_bits());
+        }
+    }
+
+    // Other metrics store rows as given
+    let mut euclidean = VectorDB::new(Config { metric: DistanceMetric::Euclidean, ..config(true, QuantizerKind::None) }).unwrap();
+    let id = euclidean.insert(vectors[3].clone(), None).unwrap();
+    assert_eq!(euclidean.get(id).unwrap().vector, vectors[3]);
+}
+
+#[test]
+fn test_zero_vectors_are_rejected() {
+    let mut db = VectorDB::new(config(true, QuantizerKind::None)).unwrap();
+    assert!(matches!(db.insert(vec![0.0; 16], None), Err(KhadyotaError::ZeroVector)));
+    match db.insert_batch([(vec![1.0; 16], None), (vec![0.0; 16], None)]) {
+        Err(KhadyotaError::InvalidChange { op: 1, source }) => assert!(matches!(*source, KhadyotaError::ZeroVector)),
+        other => panic!("expected InvalidChange, got {:?}", other),
+    }
+    assert!(db.is_empty());
+
+    let id = db.insert(vec![1.0; 16], None).unwrap();
+    assert!(matches!(db.update(id, vec![0.0; 16], None), Err(KhadyotaError::ZeroVector)));
+
+    let mut raw = VectorDB::new(config(false, QuantizerKind::None)).unwrap();
+    raw.insert(vec![0.0; 16], None).unwrap();
+}
+
+#[test]
+fn test_the_flag_is_saved_and_old_configs_load_without_it() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = build(config(true, QuantizerKind::None), &scaled_vectors());
+    db.save(&path).unwrap();
+    let mut loaded = VectorDB::load(&path).unwrap();
+    assert!(matches!(loaded.insert(vec![0.0; 16], None), Err(KhadyotaError::ZeroVector)));
+
+    // Rows saved before the flag existed were stored as given
+    let mut json = serde_json::to_value(Config::default()).unwrap();
+    json.as_object_mut().unwrap().remove("normalize");
+    let old: Config = serde_json::from_value(json).unwrap();
+    assert!(!old.normalize);
+    assert!(Config::default().normalize);
+}
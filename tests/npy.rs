@@ -0,0 +1,174 @@
+//! `.npy` import and export, against arrays laid out as NumPy saves them
+
+use khadyota::io::import_npy;
+use khadyota::{Config, KhadyotaError, QuantizerKind, VectorDB};
+use serde_json::json;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Bytes of `np.save` for a 2-D C-order array, in format `version` with
+/// `descr` `<f4` or `<f8`: the header dict padded with spaces to a
+/// multiple of 64 bytes and ended with a newline
+fn numpy_bytes(version: u8, descr: &str, rows: &[Vec<f64>]) -> Vec<u8> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}", descr, rows.len(), columns);
+    let prefix = if version == 1 { 10 } else { 12 };
+    while (prefix + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut out = b"\x93NUMPY".to_vec();
+    out.extend([version, 0]);
+    if version == 1 {
+        out.extend((header.len() as u16).to_le_bytes());
+    } else {
+        out.extend((header.len() as u32).to_le_bytes());
+    }
+    out.extend(header.as_bytes());
+    for row in rows {
+        for &value in row {
+            match descr {
+                "<f4" => out.extend((value as f32).to_le_bytes()),
+                "<f8" => out.extend(value.to_le_bytes()),
+                _ => unreachable!(),
+            }
+        }
+    }
+    out
+}
+
+fn fixture_rows() -> Vec<Vec<f64>> {
+    (0..50).map(|i| (0..6).map(|j| ((i * 6 + j) as f64 * 0.31).sin()).collect()).collect()
+}
+
+fn config(dimensions: usize) -> Config {
+    Config {
+        dimensions,
+        quantizer: QuantizerKind::None,
+        num_clusters: 4,
+        ..Default::default()
+    }
+}
+
+fn write(dir: &TempDir, name: &str, bytes: &[u8]) -> std::path::PathBuf {
+    let path = dir.path().join(name);
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn test_reads_float32_and_float64_in_v1_and_v2() {
+    let dir = TempDir::new().unwrap();
+    let rows = fixture_rows();
+
+    for (version, descr) in [(1, "<f4"), (1, "<f8"), (2, "<f4"), (2, "<f8")] {
+        let path = write(&dir, "fixture.npy", &numpy_bytes(version, descr, &rows));
+        let imported = import_npy(&path, None).unwrap();
+        assert_eq!(imported.len(), rows.len());
+        for ((vector, metadata), expected) in imported.iter().zip(&rows) {
+            let expected: Vec<f32> = expected.iter().map(|&x| x as f32).collect();
+            assert_eq!(vector, &expected, "v{} {}", version, descr);
+            assert!(metadata.is_none());
+        }
+    }
+}
+
+#[test]
+fn test_export_round_trips_vectors_and_metadata() {
+    let dir = TempDir::new().unwrap();
+    let mut db = VectorDB::new(config(6)).unwrap();
+    for (i, row) in fixture_rows().iter().enumerate() {
+        let metadata = (i % 3 == 0).then(|| json!({"row": i, "tag": "fixture"}));
+        db.insert(row.iter().map(|&x| x as f32).collect(), metadata).unwrap();
+    }
+    db.delete(7).unwrap();
+
+    let vectors_path = dir.path().join("vectors.npy");
+    let metadata_path = dir.path().join("metadata.jsonl");
+    assert_eq!(db.export_npy(&vectors_path, &metadata_path).unwrap(), 49);
+
+    // Rows are the live entries in id order; metadata lines carry the ids
+    let live: Vec<_> = db.iter().collect();
+    let imported = import_npy(&vectors_path, Some(&metadata_path)).unwrap();
+    assert_eq!(imported.len(), live.len());
+    for ((vector, metadata), entry) in imported.iter().zip(&live) {
+        assert_eq!(vector, &entry.vector);
+        assert_eq!(metadata, &entry.metadata);
+    }
+    let line = std::fs::read_to_string(&metadata_path).unwrap().lines().nth(7).unwrap().to_string();
+    assert_eq!(line, r#"{"id":8}"#);
+
+    // The written file reads back as NumPy's own layout does
+    let bytes = std::fs::read(&vectors_path).unwrap();
+    let rows: Vec<Vec<f64>> = live.iter().map(|e| e.vector.iter().map(|&x| x as f64).collect()).collect();
+    assert_eq!(bytes, numpy_bytes(1, "<f4", &rows));
+
+    let mut copy = VectorDB::new(config(6)).unwrap();
+    let ids = copy.import_npy(&vectors_path, Some(&metadata_path)).unwrap();
+    assert_eq!(ids, (0..49).collect::<Vec<u32>>());
+    assert_eq!(copy.get(7).unwrap().vector, db.get(8).unwrap().vector);
+    assert_eq!(copy.get(8).unwrap().metadata, db.get(9).unwrap().metadata);
+}
+
+#[test]
+fn test_export_keeps_metadata_stored_apart() {
+    let dir = TempDir::new().unwrap();
+    let mut db = VectorDB::new(Config {
+        max_metadata_bytes: 64,
+        external_metadata: true,
+        ..config(6)
+    })
+    .unwrap();
+    let big = json!({"blob": "x".repeat(200)});
+    for (i, row) in fixture_rows().iter().take(4).enumerate() {
+        let metadata = if i == 1 { big.clone() } else { json!({"row": i}) };
+        db.insert(row.iter().map(|&x| x as f32).collect(), Some(metadata)).unwrap();
+    }
+    assert_eq!(db.external_metadata(1), Some(&big));
+
+    let vectors_path = dir.path().join("vectors.npy");
+    let metadata_path = dir.path().join("metadata.jsonl");
+    db.export_npy(&vectors_path, &metadata_path).unwrap();
+    let imported = import_npy(&vectors_path, Some(&metadata_path)).unwrap();
+    assert_eq!(imported[1].1, Some(big));
+    assert_eq!(imported[2].1, Some(json!({"row": 2})));
+}
+
+#[test]
+fn test_rejects_wrong_dimensions_and_bad_files() {
+    let dir = TempDir::new().unwrap();
+    let path = write(&dir, "fixture.npy", &numpy_bytes(1, "<f4", &fixture_rows()));
+
+    let mut db = VectorDB::new(config(8)).unwrap();
+    match db.import_npy(&path, None) {
+        Err(KhadyotaError::DimensionMismatch { expected: 8, got: 6 }) => {}
+        other => panic!("expected DimensionMismatch, got {:?}", other),
+    }
+    assert_eq!(db.len(), 0);
+
+    // Truncated data, a metadata file of the wrong length, not npy at all
+    let bytes = numpy_bytes(1, "<f4", &fixture_rows());
+    let truncated = write(&dir, "truncated.npy", &bytes[..bytes.len() - 10]);
+    let err = import_npy(&truncated, None).unwrap_err();
+    assert!(err.to_string().contains("header promises 50 rows, file ends in row 49"), "{}", err);
+
+    let short = write(&dir, "short.jsonl", b"{\"id\": 0, \"metadata\": null}\n");
+    let err = import_npy(&path, Some(&short)).unwrap_err();
+    assert!(err.to_string().contains("1 metadata lines for 50 vectors"), "{}", err);
+
+    // A shape far past the data fails instead of allocating for it
+    let mut huge = numpy_bytes(1, "<f4", &fixture_rows());
+    let shape = huge.windows(7).position(|w| w == b"(50, 6)").unwrap();
+    huge.splice(shape..shape + 7, *b"(18446744073709551615, 4)");
+    // Take the 18 extra bytes out of the padding so the header length holds
+    let newline = huge.iter().position(|&b| b == b'\n').unwrap();
+    huge.drain(newline - 18..newline);
+    let huge = write(&dir, "huge.npy", &huge);
+    let err = import_npy(&huge, None).unwrap_err();
+    assert!(err.to_string().contains("header promises 18446744073709551615 rows"), "{}", err);
+
+    let text = write(&dir, "text.npy", b"0.1,0.2,0.3\n0.4,0.5,0.6\n");
+    assert!(import_npy(&text, None).is_err());
+    assert!(import_npy(Path::new("/nonexistent/vectors.npy"), None).is_err());
+}
@@ -0,0 +1,121 @@
+//! Optimized PQ against plain PQ on vectors whose dimensions are
+//! correlated across subvector boundaries
+
+use khadyota::distance::euclidean_distance_squared;
+use khadyota::harness::{build_db, recall_at_k};
+use khadyota::quantization::{OPQCodec, PQCodec};
+use khadyota::storage::QuantizedVectors;
+use khadyota::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 32;
+
+/// Vectors from a low-rank factor model: a few latent factors of falling
+/// variance, each spread over every dimension, plus a little noise. Each
+/// subvector then sees a slice of every factor, which plain PQ can only
+/// quantize piecewise.
+fn factor_vectors(count: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(1);
+    let factors = 6;
+    let loadings: Vec<Vec<f32>> = (0..factors)
+        .map(|_| (0..DIMENSIONS).map(|_| rng.sample::<f32, _>(StandardNormal)).collect())
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            let mut vector: Vec<f32> = (0..DIMENSIONS).map(|_| 0.05 * rng.sample::<f32, _>(StandardNormal)).collect();
+            for (f, loading) in loadings.iter().enumerate() {
+                let z = rng.sample::<f32, _>(StandardNormal) / (1 + f) as f32;
+                vector.iter_mut().zip(loading).for_each(|(x, l)| *x += z * l);
+            }
+            vector
+        })
+        .collect()
+}
+
+fn config(use_opq: bool) -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 8,
+        pq_bits: 4,
+        num_clusters: 4,
+        num_probe: 4,
+        seed: Some(3),
+        use_residuals: false,
+        use_opq,
+        ..Default::default()
+    }
+}
+
+fn reconstruction_error(vectors: &[Vec<f32>], round_trip: impl Fn(&[f32]) -> Vec<f32>) -> f32 {
+    vectors.iter().map(|v| euclidean_distance_squared(v, &round_trip(v))).sum::<f32>() / vectors.len() as f32
+}
+
+#[test]
+fn test_opq_reconstructs_and_recalls_better_than_pq() {
+    let training = factor_vectors(2_000, 2);
+    let held_out = factor_vectors(500, 3);
+
+    let pq = PQCodec::train_with_progress(&training, 8, Some(3), DistanceMetric::Euclidean, 4, &Silent).unwrap();
+    let opq = OPQCodec::train_with_progress(&training, 8, Some(3), DistanceMetric::Euclidean, 4, &Silent).unwrap();
+    let pq_error = reconstruction_error(&held_out, |v| pq.decode(&pq.encode(v)));
+    let opq_error = reconstruction_error(&held_out, |v| opq.decode(&opq.encode(v)));
+    assert!(opq_error < 0.5 * pq_error, "OPQ {} vs PQ {}", opq_error, pq_error);
+
+    // Every cluster probed and nothing reranked, so recall is down to the codes
+    let queries = factor_vectors(50, 4);
+    let plain = recall_at_k(&build_db(config(false), training.clone()).unwrap(), &queries, 10).unwrap();
+    let rotated = recall_at_k(&build_db(config(true), training).unwrap(), &queries, 10).unwrap();
+    assert!(rotated > plain + 0.1, "OPQ recall {} vs PQ {}", rotated, plain);
+}
+
+#[test]
+fn test_rotation_is_saved_and_residuals_rotate_too() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("opq.kdb");
+    let vectors = factor_vectors(1_000, 5);
+    let queries = factor_vectors(20, 6);
+    let ranked = |results: Vec<SearchResult>| results.into_iter().map(|r| (r.id, r.distance)).collect::<Vec<_>>();
+
+    for use_residuals in [false, true] {
+        let db = build_db(Config { use_residuals, ..config(true) }, vectors.clone()).unwrap();
+        db.save(&path).unwrap();
+        let loaded = VectorDB::load(&path).unwrap();
+        for query in &queries {
+            assert_eq!(ranked(db.search(query, 10).unwrap()), ranked(loaded.search(query, 10).unwrap()));
+        }
+        assert!(recall_at_k(&loaded, &queries, 10).unwrap() > 0.5, "use_residuals {}", use_residuals);
+    }
+}
+
+#[test]
+fn test_rotated_codes_need_the_flag() {
+    let vectors = factor_vectors(300, 7);
+    let codec = OPQCodec::train_with_progress(&vectors, 8, Some(1), DistanceMetric::Euclidean, 4, &Silent).unwrap();
+    let mut quantized = QuantizedVectors::new(codec);
+    quantized.add_batch(vectors.clone());
+
+    assert!(VectorDB::from_parts(config(true), vectors.clone(), Some(quantized.clone()), None).is_ok());
+    match VectorDB::from_parts(config(false), vectors, Some(quantized), None) {
+        Err(KhadyotaError::IncompatibleComponents(report)) => {
+            assert!(report.violations.contains(&Violation::Rotation { expected: false, found: true }), "{}", report)
+        }
+        other => panic!("expected IncompatibleComponents, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_matryoshka_prefixes_are_refused() {
+    let config = Config {
+        matryoshka_dims: vec![16],
+        ..config(true)
+    };
+    assert!(matches!(config.validate(), Err(KhadyotaError::InvalidConfig(_))));
+    assert!(Config { use_opq: false, ..config }.validate().is_ok());
+}
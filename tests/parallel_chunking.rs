@@ -0,0 +1,55 @@
+//! The chunk override is process-wide, so everything that sets it lives in
+//! this one test
+
+use khadyota::evaluation::compute_ground_truth;
+use khadyota::harness::{build_db, clustered_vectors, random_vectors};
+use khadyota::parallel::{chunk_override, set_chunk_override};
+use khadyota::*;
+
+#[test]
+fn test_results_do_not_depend_on_chunking() {
+    let vectors = clustered_vectors(3_000, 32, 12, 0.2, 3);
+    let queries = random_vectors(40, 32, 4);
+    let config = Config {
+        dimensions: 32,
+        pq_subvectors: 8,
+        num_clusters: 12,
+        num_probe: 3,
+        seed: Some(5),
+        use_residuals: false,
+        ..Default::default()
+    };
+    let db = build_db(config, vectors.clone()).unwrap();
+    let parts = db.export_parts(false).unwrap();
+    let quantized = storage::QuantizedVectors::from_codes(parts.codec, parts.codes).unwrap();
+    let table = quantized.precompute_distance_table(&queries[0]);
+    let ids: Vec<u32> = (0..vectors.len() as u32).rev().collect();
+
+    let run = || {
+        let batch: Vec<Vec<(u32, f32)>> = db
+            .batch_search(&queries, 10)
+            .unwrap()
+            .iter()
+            .map(|results| results.iter().map(|r| (r.id, r.distance)).collect())
+            .collect();
+        let truth = compute_ground_truth(&vectors, &queries, 10, DistanceMetric::Euclidean);
+        let report = db.evaluate(&queries[..5], 10).unwrap();
+        (batch, truth, quantized.score_parallel(&table, &ids), report.recall)
+    };
+
+    assert_eq!(chunk_override(), None);
+    let tuned = run();
+    assert_eq!(tuned.2.len(), ids.len());
+    assert!(tuned.2.iter().zip(&ids).all(|(scored, id)| scored.0 == *id));
+    for chunk in [1, 7, 100_000] {
+        set_chunk_override(Some(chunk));
+        assert_eq!(chunk_override(), Some(chunk));
+        assert_eq!(run(), tuned, "chunk {}", chunk);
+    }
+
+    // Zero means one item per task rather than unset
+    set_chunk_override(Some(0));
+    assert_eq!(chunk_override(), Some(1));
+    set_chunk_override(None);
+    assert_eq!(chunk_override(), None);
+}
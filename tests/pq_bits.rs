@@ -0,0 +1,71 @@
+use khadyota::harness::{build_db, clustered_vectors, recall_at_k};
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMS: usize = 32;
+
+fn config(pq_bits: usize) -> Config {
+    Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 8,
+        pq_bits,
+        // Every cluster is probed, so recall measures the codes alone
+        num_clusters: 4,
+        num_probe: 4,
+        seed: Some(5),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_recall_and_size_at_each_bit_width() {
+    let vectors = clustered_vectors(3_000, DIMS, 16, 0.4, 1);
+    let queries = clustered_vectors(50, DIMS, 16, 0.4, 2);
+
+    let mut recalls = Vec::new();
+    for (bits, row_bytes) in [(4, 4), (8, 8), (16, 16)] {
+        let db = build_db(config(bits), vectors.clone()).unwrap();
+        assert_eq!(db.memory_usage().code_bytes, 3_000 * row_bytes);
+        assert_eq!(db.check_compatibility().violations, []);
+        let recall = recall_at_k(&db, &queries, 10).unwrap();
+        println!("{}-bit codes: recall@10 {:.3}", bits, recall);
+        recalls.push(recall);
+    }
+
+    // More centroids per subvector give closer distances; at 16 bits
+    // every training row is its own centroid
+    assert!(recalls[0] > 0.1, "{:?}", recalls);
+    assert!(recalls[1] > recalls[0] + 0.1 && recalls[2] > recalls[1] + 0.1, "{:?}", recalls);
+    assert!(recalls[2] > 0.95, "{:?}", recalls);
+}
+
+#[test]
+fn test_bit_width_survives_save_and_load() {
+    let dir = TempDir::new().unwrap();
+    let vectors = clustered_vectors(500, DIMS, 8, 0.4, 3);
+    let query = &vectors[17];
+    let hits = |db: &VectorDB| -> Vec<(u32, f32)> {
+        db.search(query, 10).unwrap().iter().map(|r| (r.id, r.distance)).collect()
+    };
+
+    for bits in [4, 8, 16] {
+        let path = dir.path().join(format!("{}.kdb", bits));
+        let db = build_db(config(bits), vectors.clone()).unwrap();
+        db.save(&path).unwrap();
+        let loaded = VectorDB::load_expecting(&path, &config(bits)).unwrap();
+        assert_eq!(hits(&loaded), hits(&db));
+        assert_eq!(loaded.checksum(), db.checksum());
+
+        // Other widths describe different codes
+        let other = Config { pq_bits: if bits == 8 { 4 } else { 8 }, ..config(bits) };
+        assert!(matches!(
+            VectorDB::load_expecting(&path, &other),
+            Err(KhadyotaError::ConfigMismatch { field: "pq_bits", .. })
+        ));
+    }
+
+    for bits in [0, 2, 12, 32] {
+        assert!(matches!(VectorDB::new(config(bits)), Err(KhadyotaError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,54 @@
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+
+const CLUSTERS: usize = 10;
+
+fn build(compensation: Option<ProbeCompensation>, rows: &[Vec<f32>]) -> VectorDB {
+    let config = Config {
+        dimensions: 16,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::SQ8,
+        num_clusters: CLUSTERS,
+        num_probe: 2,
+        seed: Some(3),
+        probe_compensation: compensation,
+        ..Default::default()
+    };
+    harness::build_db(config, rows.to_vec()).unwrap()
+}
+
+#[test]
+fn test_compensation_keeps_recall_after_concentrated_deletes() {
+    // Row i belongs to centre i % CLUSTERS; the first seven centres lose
+    // every row, 70% of the collection
+    let rows = clustered_vectors(4_000, 16, CLUSTERS, 0.05, 8);
+    let doomed: Vec<u32> = (0..rows.len() as u32).filter(|&i| (i as usize % CLUSTERS) < 7).collect();
+    // Queries where the deleted rows were, whose nearest live
+    // neighbours now sit in clusters further off
+    let queries: Vec<Vec<f32>> = doomed.iter().step_by(97).map(|&i| rows[i as usize].clone()).collect();
+
+    let mut plain = build(None, &rows);
+    let mut compensated = build(Some(ProbeCompensation::default()), &rows);
+    let before = plain.evaluate(&queries, 10).unwrap();
+    assert_eq!(compensated.evaluate(&queries, 10).unwrap().candidates.compensated_queries, 0);
+
+    for db in [&mut plain, &mut compensated] {
+        let mut changes = ChangeSet::new();
+        for &id in &doomed {
+            changes.delete(id);
+        }
+        db.apply_changeset(changes).unwrap();
+    }
+
+    let stats = compensated.index_stats().unwrap();
+    assert_eq!((stats.total_vectors, stats.built_vectors), (1_200, 4_000));
+    assert_eq!(stats.min_live_fraction, 0.0);
+
+    let uncompensated = plain.evaluate(&queries, 10).unwrap();
+    let after = compensated.evaluate(&queries, 10).unwrap();
+    assert!(uncompensated.recall < before.recall - 0.3, "{} vs {}", uncompensated.recall, before.recall);
+    assert!(after.recall >= before.recall - 0.05, "{} vs {}", after.recall, before.recall);
+    assert_eq!(after.candidates.compensated_queries, queries.len());
+    assert!(after.candidates.mean_clusters > 2.0 && after.candidates.mean_clusters <= 8.0);
+    assert_eq!(uncompensated.candidates.mean_clusters, 2.0);
+}
@@ -0,0 +1,173 @@
+use khadyota::harness::clustered_vectors;
+use khadyota::*;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+fn config(use_pq: bool) -> Config {
+    Config {
+        dimensions: 16,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 3,
+        seed: Some(2),
+        ..Default::default()
+    }
+}
+
+/// A database reporting into a shared list of events
+fn recorded(config: Config) -> (VectorDB, Arc<Mutex<Vec<BuildEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    let mut db = VectorDB::new(config).unwrap();
+    db.set_progress_callback(Some(Arc::new(move |event| sink.lock().unwrap().push(event))));
+    for vector in clustered_vectors(300, 16, 8, 0.2, 4) {
+        db.insert(vector, None).unwrap();
+    }
+    (db, events)
+}
+
+/// Event names with k-means passes left out, whose number varies
+fn steps(events: &[BuildEvent]) -> Vec<String> {
+    events
+        .iter()
+        .filter(|e| !matches!(e, BuildEvent::KMeansIteration { .. } | BuildEvent::KMeansFinished { .. }))
+        .map(|e| match e {
+            BuildEvent::CodebookTrained { done, total } => format!("codebook {}/{}", done, total),
+            other => format!("{:?}", other).split([' ', '{']).next().unwrap().to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_build_reports_each_step_in_order() {
+    let (mut db, events) = recorded(Config {
+        use_residuals: false,
+        ..config(true)
+    });
+    db.build_index().unwrap();
+
+    let events = std::mem::take(&mut *events.lock().unwrap());
+    assert_eq!(
+        steps(&events),
+        [
+            "IndexBuildStarted",
+            "PqTrainingStarted",
+            "codebook 1/4",
+            "codebook 2/4",
+            "codebook 3/4",
+            "codebook 4/4",
+            "PqTrained",
+            "IvfBuildStarted",
+            "IndexBuilt",
+        ]
+    );
+
+    // One k-means run per codebook and one for the IVF centroids, each
+    // finishing after the passes it reports
+    let finished = events.iter().filter(|e| matches!(e, BuildEvent::KMeansFinished { .. })).count();
+    assert_eq!(finished, 5);
+    assert!(matches!(events[events.len() - 2], BuildEvent::KMeansFinished { .. }));
+    assert!(events.iter().any(|e| matches!(e, BuildEvent::KMeansIteration { iter: 1, .. })));
+
+    match &events[0] {
+        BuildEvent::IndexBuildStarted { vectors, dimensions } => assert_eq!((*vectors, *dimensions), (300, 16)),
+        other => panic!("unexpected first event {:?}", other),
+    }
+    match events.last().unwrap() {
+        BuildEvent::IndexBuilt { stats } => assert_eq!((stats.num_clusters, stats.total_vectors), (8, 300)),
+        other => panic!("unexpected last event {:?}", other),
+    }
+}
+
+#[test]
+fn test_kmeans_settings_reach_each_clustering() {
+    let (mut db, events) = recorded(Config {
+        use_residuals: false,
+        ivf_kmeans: KMeansParams { max_iterations: 3, n_redo: 2, ..Default::default() },
+        pq_kmeans: KMeansParams { max_iterations: 1, init: InitMethod::Random, ..Default::default() },
+        ..config(true)
+    });
+    db.build_index().unwrap();
+
+    // A single pass per codebook, then two IVF runs of at most three
+    let runs: Vec<usize> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|e| match e {
+            BuildEvent::KMeansFinished { iterations, .. } => Some(*iterations),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(runs[..4], [1; 4]);
+    assert_eq!(runs.len(), 6);
+    assert!(runs[4..].iter().all(|&iterations| (1..=3).contains(&iterations)));
+
+    let redo_none = KMeansParams { n_redo: 0, ..Default::default() };
+    assert!(matches!(
+        VectorDB::new(Config { ivf_kmeans: redo_none, ..config(true) }),
+        Err(KhadyotaError::InvalidConfig(message)) if message.contains("ivf_kmeans")
+    ));
+}
+
+#[test]
+fn test_residual_codebooks_train_after_clustering() {
+    let (mut db, events) = recorded(config(true));
+    db.build_index().unwrap();
+    assert_eq!(
+        steps(&events.lock().unwrap()),
+        [
+            "IndexBuildStarted",
+            "IvfBuildStarted",
+            "PqTrainingStarted",
+            "codebook 1/4",
+            "codebook 2/4",
+            "codebook 3/4",
+            "codebook 4/4",
+            "PqTrained",
+            "IndexBuilt",
+        ]
+    );
+}
+
+#[test]
+fn test_saves_are_reported_and_silence_is_the_default() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let (mut db, events) = recorded(config(false));
+    db.build_index().unwrap();
+    assert_eq!(steps(&events.lock().unwrap()), ["IndexBuildStarted", "IvfBuildStarted", "IndexBuilt"]);
+
+    events.lock().unwrap().clear();
+    db.save(&path).unwrap();
+    match events.lock().unwrap().as_slice() {
+        [BuildEvent::Saved { path: saved, bytes }] => {
+            assert_eq!(saved, &path);
+            assert_eq!(*bytes, std::fs::metadata(&path).unwrap().len());
+        }
+        other => panic!("expected one Saved event, got {:?}", other),
+    }
+
+    // Loaded databases start without a callback
+    events.lock().unwrap().clear();
+    db.set_progress_callback(None);
+    db.build_index().unwrap();
+    let mut loaded = VectorDB::load(&path).unwrap();
+    loaded.build_index().unwrap();
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_more_clusters_than_rows_are_clamped_with_a_warning() {
+    let (mut db, events) = recorded(Config {
+        num_clusters: 1000,
+        num_probe: 100,
+        ..config(false)
+    });
+    db.build_index().unwrap();
+    let events = events.lock().unwrap();
+    assert_eq!(steps(&events), ["IndexBuildStarted", "ClustersClamped", "IvfBuildStarted", "IndexBuilt"]);
+    assert!(matches!(events[1], BuildEvent::ClustersClamped { requested: 1000, used: 300 }));
+    assert_eq!(db.index_stats().unwrap().num_clusters, 300);
+}
@@ -0,0 +1,326 @@
+//! Generative tests: random configs, datasets and operation sequences,
+//! checked against a plain in-memory model after every step.
+
+use khadyota::distance::compute_distance;
+use khadyota::*;
+use proptest::prelude::*;
+use std::collections::HashSet;
+use tempfile::TempDir;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(Vec<f32>),
+    Update(usize, Vec<f32>),
+    Build,
+    SaveLoad,
+    Search { query: Vec<f32>, k: usize, exclude: Vec<usize> },
+}
+
+#[derive(Debug, Clone)]
+struct Case {
+    config: Config,
+    initial: Vec<Vec<f32>>,
+    ops: Vec<Op>,
+}
+
+/// Nonzero components on a coarse grid, so ties are common and cosine is
+/// always defined
+fn component() -> impl Strategy<Value = f32> {
+    (1i32..=8, any::<bool>()).prop_map(|(v, negative)| {
+        let v = v as f32 / 4.0;
+        if negative { -v } else { v }
+    })
+}
+
+fn vector(dims: usize) -> impl Strategy<Value = Vec<f32>> {
+    prop::collection::vec(component(), dims)
+}
+
+fn op(dims: usize) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => vector(dims).prop_map(Op::Insert),
+        1 => (any::<usize>(), vector(dims)).prop_map(|(i, v)| Op::Update(i, v)),
+        2 => Just(Op::Build),
+        1 => Just(Op::SaveLoad),
+        4 => (vector(dims), 1usize..20, prop::collection::vec(any::<usize>(), 0..4))
+            .prop_map(|(query, k, exclude)| Op::Search { query, k, exclude }),
+    ]
+}
+
+fn case() -> impl Strategy<Value = Case> {
+    let metric = prop_oneof![
+        Just(DistanceMetric::Cosine),
+        Just(DistanceMetric::Euclidean),
+        Just(DistanceMetric::DotProduct),
+        Just(DistanceMetric::Manhattan),
+        Just(DistanceMetric::Hamming),
+    ];
+    let pq_subvectors = prop_oneof![Just(1usize), Just(2), Just(4)];
+
+    // PQ codebooks hold up to 256 entries; smaller sets get fewer
+    (metric, pq_subvectors, 1usize..=4, prop::bool::weighted(0.25), 1usize..=8, any::<u64>())
+        .prop_flat_map(|(metric, pq_subvectors, sub_size, use_pq, clusters, seed)| {
+            let dims = pq_subvectors * sub_size;
+            let initial = if use_pq { 1usize..300 } else { 1usize..120 };
+
+            prop::collection::vec(vector(dims), initial).prop_flat_map(move |initial| {
+                let config = Config {
+                    dimensions: dims,
+                    metric,
+                    quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+                    pq_subvectors,
+                    num_clusters: clusters.min(initial.len()),
+                    num_probe: 1,
+                    seed: Some(seed),
+                    ..Default::default()
+                };
+                prop::collection::vec(op(dims), 1..12).prop_map(move |ops| Case {
+                    config: config.clone(),
+                    initial: initial.clone(),
+                    ops,
+                })
+            })
+        })
+}
+
+/// Brute-force top-k distances over the model
+fn expected_distances(model: &[Vec<f32>], query: &[f32], k: usize, exclude: &HashSet<u32>, metric: DistanceMetric) -> Vec<f32> {
+    let mut distances: Vec<f32> = model
+        .iter()
+        .enumerate()
+        .filter(|(id, _)| !exclude.contains(&(*id as u32)))
+        .map(|(_, v)| compute_distance(query, v, metric))
+        .collect();
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    distances.truncate(k);
+    distances
+}
+
+fn check_results(results: &[SearchResult], k: usize, len: usize, exclude: &HashSet<u32>) -> std::result::Result<(), TestCaseError> {
+    prop_assert!(results.len() <= k);
+    prop_assert!(results.windows(2).all(|w| w[0].distance <= w[1].distance), "unsorted: {:?}", results);
+
+    let ids: HashSet<u32> = results.iter().map(|r| r.id).collect();
+    prop_assert_eq!(ids.len(), results.len(), "duplicate ids: {:?}", results);
+    prop_assert!(ids.iter().all(|&id| (id as usize) < len), "unknown id: {:?}", results);
+    prop_assert!(ids.is_disjoint(exclude), "excluded id returned: {:?}", results);
+    Ok(())
+}
+
+fn run(case: Case) -> std::result::Result<(), TestCaseError> {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let metric = case.config.metric;
+    let num_clusters = case.config.num_clusters;
+
+    let mut db = VectorDB::new(case.config.clone()).unwrap();
+    let mut model: Vec<Vec<f32>> = Vec::new();
+    let mut built = false;
+    // Built before the last writes, which are scanned exactly until the
+    // next build; the lag is not saved
+    let mut lagging = false;
+
+    for v in case.initial {
+        db.insert(v.clone(), None).unwrap();
+        model.push(v);
+    }
+
+    for op in case.ops {
+        match op {
+            Op::Insert(v) => {
+                let id = db.insert(v.clone(), None).unwrap();
+                prop_assert_eq!(id as usize, model.len());
+                model.push(v);
+                lagging |= built;
+                built = false;
+            }
+            Op::Update(i, v) => {
+                let id = (i % model.len()) as u32;
+                db.insert_opts(v.clone(), InsertOptions::new().id(id).upsert(true)).unwrap();
+                model[id as usize] = v;
+                lagging |= built;
+                built = false;
+            }
+            Op::Build => {
+                db.build_index().unwrap();
+                built = true;
+                lagging = false;
+            }
+            Op::SaveLoad => {
+                let before = db.checksum();
+                db.save(&path).unwrap();
+                db = VectorDB::load(&path).unwrap();
+                lagging = false;
+                prop_assert_eq!(db.checksum(), before);
+                prop_assert_eq!(db.len(), model.len());
+            }
+            Op::Search { query, k, exclude } => {
+                let exclude: HashSet<u32> = exclude.iter().map(|i| (i % model.len()) as u32).collect();
+                let params = SearchParams::builder().exclude(exclude.iter().copied()).build();
+
+                if !built && !lagging {
+                    prop_assert!(matches!(
+                        db.search_with_params(&query, k, &params),
+                        Err(KhadyotaError::IndexNotBuilt)
+                    ));
+                    continue;
+                }
+
+                let results = db.search_with_params(&query, k, &params).unwrap();
+                check_results(&results, k, model.len(), &exclude)?;
+
+                // Requesting exactness must agree with brute force: a full
+                // probe, reranked over every candidate when PQ is on
+                let mut exact = SearchParams::builder()
+                    .num_probe(num_clusters)
+                    .exclude(exclude.iter().copied());
+                if case.config.uses_pq() {
+                    exact = exact.rerank(model.len());
+                }
+                let exact_results = db.search_with_params(&query, k, &exact.build()).unwrap();
+                check_results(&exact_results, k, model.len(), &exclude)?;
+
+                let expected = expected_distances(&model, &query, k, &exclude, metric);
+                let tolerance = case.config.tolerance;
+                let got: Vec<f32> = exact_results.iter().map(|r| r.distance).collect();
+                prop_assert!(tolerance.slices_approx_eq(&got, &expected), "{:?} vs {:?}", got, expected);
+
+                if !case.config.uses_pq() {
+                    prop_assert!(tolerance.results_equivalent(&results, &exact_results), "{:?} vs {:?}", results, exact_results);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(40))]
+
+    #[test]
+    fn search_invariants_hold(case in case()) {
+        run(case)?;
+    }
+}
+
+/// Any length, so the AVX2 kernels' scalar tails are exercised, with
+/// components that include 0.0/1.0 bits and values straddling the Hamming
+/// threshold
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn kernel_inputs() -> impl Strategy<Value = (Vec<f32>, Vec<f32>)> {
+    let value = prop_oneof![Just(0.0f32), Just(1.0f32), Just(0.5f32), -4.0f32..4.0];
+    (1usize..=400).prop_flat_map(move |len| {
+        let v = prop::collection::vec(value.clone(), len);
+        (v.clone(), v)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+proptest! {
+    #[test]
+    fn simd_kernels_match_scalar((a, b) in kernel_inputs()) {
+        use khadyota::distance::{scalar, simd};
+
+        if !simd::avx2_available() {
+            return Ok(());
+        }
+        let tolerance = ToleranceConfig { absolute: 1e-4, relative: 1e-4 };
+        let close = |x: f32, y: f32| tolerance.approx_eq(x, y);
+
+        unsafe {
+            prop_assert!(close(simd::manhattan_distance_avx2(&a, &b), scalar::manhattan_distance_scalar(&a, &b)));
+            prop_assert_eq!(simd::hamming_distance_avx2(&a, &b), scalar::hamming_distance_scalar(&a, &b));
+            prop_assert!(close(
+                simd::euclidean_distance_squared_avx2(&a, &b),
+                scalar::euclidean_distance_squared_scalar(&a, &b)
+            ));
+            prop_assert!(close(simd::dot_product_avx2(&a, &b), scalar::dot_product_scalar(&a, &b)));
+            prop_assert!(close(simd::cosine_similarity_avx2(&a, &b), scalar::cosine_similarity_scalar(&a, &b))
+                || (a.iter().all(|&x| x == 0.0) || b.iter().all(|&x| x == 0.0)));
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+proptest! {
+    #[test]
+    fn neon_kernels_match_scalar((a, b) in kernel_inputs()) {
+        use khadyota::distance::{neon, scalar};
+
+        if !neon::neon_available() {
+            return Ok(());
+        }
+        let tolerance = ToleranceConfig { absolute: 1e-4, relative: 1e-4 };
+        let close = |x: f32, y: f32| tolerance.approx_eq(x, y);
+
+        unsafe {
+            prop_assert!(close(neon::manhattan_distance_neon(&a, &b), scalar::manhattan_distance_scalar(&a, &b)));
+            prop_assert_eq!(neon::hamming_distance_neon(&a, &b), scalar::hamming_distance_scalar(&a, &b));
+            prop_assert!(close(
+                neon::euclidean_distance_squared_neon(&a, &b),
+                scalar::euclidean_distance_squared_scalar(&a, &b)
+            ));
+            prop_assert!(close(neon::dot_product_neon(&a, &b), scalar::dot_product_scalar(&a, &b)));
+            prop_assert!(close(neon::cosine_similarity_neon(&a, &b), scalar::cosine_similarity_scalar(&a, &b))
+                || (a.iter().all(|&x| x == 0.0) || b.iter().all(|&x| x == 0.0)));
+        }
+    }
+}
+
+/// Any float, weighted towards the edge cases comparisons get wrong
+fn edge_float() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        Just(0.0f32),
+        Just(-0.0f32),
+        Just(f32::INFINITY),
+        Just(f32::NEG_INFINITY),
+        Just(f32::MAX),
+        Just(f32::MIN_POSITIVE),
+        // Subnormals, of either sign
+        (1u32..0x0080_0000, any::<bool>()).prop_map(|(bits, negative)| f32::from_bits(bits | (u32::from(negative) << 31))),
+        -1e-5f32..1e-5,
+        any::<f32>(),
+    ]
+}
+
+fn tolerance() -> impl Strategy<Value = ToleranceConfig> {
+    (0.0f32..1e-3, 0.0f32..1e-2).prop_map(|(absolute, relative)| ToleranceConfig { absolute, relative })
+}
+
+proptest! {
+    #[test]
+    fn tolerance_is_reflexive_and_symmetric(a in edge_float(), b in edge_float(), tolerance in tolerance()) {
+        prop_assert_eq!(tolerance.approx_eq(a, a), !a.is_nan());
+        prop_assert_eq!(tolerance.approx_eq(a, b), tolerance.approx_eq(b, a));
+        prop_assert!(!tolerance.approx_eq(a, f32::NAN));
+        // Exact tolerance is plain equality
+        prop_assert_eq!(ToleranceConfig::EXACT.approx_eq(a, b), a == b);
+    }
+
+    #[test]
+    fn tolerance_never_relaxes_infinities(a in edge_float(), tolerance in tolerance()) {
+        prop_assert_eq!(tolerance.approx_eq(f32::INFINITY, a), a == f32::INFINITY);
+        prop_assert_eq!(tolerance.approx_eq(f32::NEG_INFINITY, a), a == f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn tolerance_near_zero_is_absolute(a in -1e-6f32..1e-6, b in -1e-6f32..1e-6, relative in 0.0f32..0.5) {
+        // Subnormals and values of opposite sign around zero are within
+        // any absolute tolerance above their distance...
+        let absolute = ToleranceConfig { absolute: 2e-6, relative };
+        prop_assert!(absolute.approx_eq(a, b));
+        // ...and relative tolerance below 100% never bridges a sign change
+        let relative_only = ToleranceConfig { absolute: 0.0, relative };
+        if a * b < 0.0 {
+            prop_assert!(!relative_only.approx_eq(a, b));
+        }
+    }
+
+    #[test]
+    fn tolerance_scales_with_magnitude(a in prop_oneof![1e-30f32..1e30, -1e30f32..-1e-30], relative in 1e-6f32..1e-2) {
+        let tolerance = ToleranceConfig { absolute: 0.0, relative };
+        prop_assert!(tolerance.approx_eq(a, a * (1.0 + relative / 2.0)));
+        prop_assert!(!tolerance.approx_eq(a, a * (1.0 + relative * 2.0)));
+    }
+}
@@ -0,0 +1,106 @@
+use khadyota::harness::{build_db, clustered_vectors};
+use khadyota::*;
+
+const DIMS: usize = 16;
+const CLUSTERS: usize = 8;
+const COUNT: usize = 1_000;
+
+// Tight clusters around uniform centres: members sit within ~0.2 of each
+// other, centres well over 1.0 apart
+const RADIUS: f32 = 0.5;
+
+fn config(quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions: DIMS,
+        quantizer,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 4,
+        num_clusters: CLUSTERS,
+        num_probe: 1,
+        seed: Some(5),
+        ..Default::default()
+    }
+}
+
+fn vectors() -> Vec<Vec<f32>> {
+    clustered_vectors(COUNT, DIMS, CLUSTERS, 0.02, 3)
+}
+
+fn brute_force(vectors: &[Vec<f32>], query: &[f32], radius: f32) -> Vec<u32> {
+    let mut ids: Vec<u32> = (0..vectors.len() as u32)
+        .filter(|&id| distance::compute_distance(query, &vectors[id as usize], DistanceMetric::Euclidean) <= radius)
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn sorted_ids(results: &[SearchResult]) -> Vec<u32> {
+    let mut ids: Vec<u32> = results.iter().map(|r| r.id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn assert_ascending_within(results: &[SearchResult], radius: f32) {
+    assert!(results.windows(2).all(|w| w[0].distance <= w[1].distance));
+    assert!(results.iter().all(|r| r.distance <= radius));
+}
+
+#[test]
+fn test_range_finds_exactly_the_query_cluster() {
+    for quantizer in [QuantizerKind::None, QuantizerKind::PQ] {
+        let db = build_db(config(quantizer), vectors()).unwrap();
+        for member in 0..CLUSTERS as u32 {
+            let query = db.get(member).unwrap().vector;
+            let results = db.range_search(&query, RADIUS).unwrap();
+
+            // Vector i belongs to centre i % CLUSTERS
+            let expected: Vec<u32> = (member..COUNT as u32).step_by(CLUSTERS).collect();
+            assert_eq!(sorted_ids(&results), expected, "{:?}", quantizer);
+            assert_ascending_within(&results, RADIUS);
+            assert_eq!(results[0].id, member);
+            assert_eq!(results[0].distance, 0.0);
+        }
+    }
+}
+
+#[test]
+fn test_pq_distances_are_verified() {
+    let vectors = vectors();
+    let db = build_db(config(QuantizerKind::PQ), vectors.clone()).unwrap();
+    let query = db.get(3).unwrap().vector;
+
+    // A radius cutting through the cluster, where PQ error matters
+    let exact = db.range_search_with_params(&query, f32::MAX, &SearchParams::builder().num_probe(1).build()).unwrap();
+    let radius = exact[exact.len() / 2].distance;
+    let truth = brute_force(&vectors, &query, radius);
+
+    for slack in [1.0, DEFAULT_RANGE_SLACK, f32::INFINITY] {
+        let params = SearchParams::builder().range_slack(slack).build();
+        let results = db.range_search_with_params(&query, radius, &params).unwrap();
+        assert_ascending_within(&results, radius);
+        for r in &results {
+            assert_eq!(r.distance, distance::compute_distance(&query, &vectors[r.id as usize], DistanceMetric::Euclidean));
+        }
+        // Never a false match; with unlimited slack nothing is missed
+        let found = sorted_ids(&results);
+        assert!(found.iter().all(|id| truth.binary_search(id).is_ok()));
+        if slack == f32::INFINITY {
+            assert_eq!(found, truth);
+        }
+    }
+}
+
+#[test]
+fn test_nothing_in_range_is_empty() {
+    let db = build_db(config(QuantizerKind::PQ), vectors()).unwrap();
+    let far = vec![100.0; DIMS];
+    assert!(db.range_search(&far, 1.0).unwrap().is_empty());
+
+    let params = SearchParams::builder().exclude([0]).build();
+    let query = db.get(0).unwrap().vector;
+    assert!(db.range_search_with_params(&query, 0.0, &params).unwrap().is_empty());
+
+    assert!(matches!(db.range_search(&query, f32::NAN), Err(KhadyotaError::InvalidConfig(_))));
+    let unbuilt = VectorDB::new(config(QuantizerKind::PQ)).unwrap();
+    assert!(matches!(unbuilt.range_search(&query, 1.0), Err(KhadyotaError::IndexNotBuilt)));
+}
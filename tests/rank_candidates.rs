@@ -0,0 +1,150 @@
+use khadyota::*;
+use std::collections::HashSet;
+
+const DIMS: usize = 16;
+
+fn vector(i: u32) -> Vec<f32> {
+    (0..DIMS as u32).map(|j| ((i * 13 + j * 3) as f32 * 0.23).sin()).collect()
+}
+
+fn build_db(use_pq: bool) -> VectorDB {
+    let config = Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Cosine,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 2,
+        seed: Some(9),
+        ..Default::default()
+    };
+
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..1_000 {
+        db.insert(vector(i), Some(serde_json::json!({"i": i}))).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+/// Candidates with runs of consecutive ids, stragglers and duplicates
+fn candidates() -> Vec<u32> {
+    let mut ids: Vec<u32> = (100..160).chain((400..1_000).step_by(9)).collect();
+    ids.extend([101, 3, 3, 999]);
+    ids
+}
+
+/// Top `k` of a full search restricted to `ids`
+fn filtered_search(db: &VectorDB, query: &[f32], ids: &[u32], k: usize, params: SearchParams) -> Vec<(u32, f32)> {
+    let ids: HashSet<u32> = ids.iter().copied().collect();
+    db.search_with_params(query, db.len(), &params)
+        .unwrap()
+        .into_iter()
+        .filter(|r| ids.contains(&r.id))
+        .take(k)
+        .map(|r| (r.id, r.distance))
+        .collect()
+}
+
+fn pairs(results: &[SearchResult]) -> Vec<(u32, f32)> {
+    results.iter().map(|r| (r.id, r.distance)).collect()
+}
+
+#[test]
+fn test_exact_ranking_matches_filtered_search() {
+    let db = build_db(false);
+    let ids = candidates();
+
+    for q in [7, 130, 555] {
+        let query = vector(q);
+        let ranked = db.rank_candidates(&query, &ids, 10, true).unwrap();
+        let expected = filtered_search(&db, &query, &ids, 10, SearchParams::default());
+        assert_eq!(ranked.len(), 10);
+        for ((id, distance), (expected_id, expected_distance)) in pairs(&ranked).into_iter().zip(expected) {
+            assert_eq!(id, expected_id);
+            approx::assert_relative_eq!(distance, expected_distance, epsilon = 1e-6);
+        }
+        assert_eq!(ranked[0].metadata, Some(serde_json::json!({"i": ranked[0].id})));
+    }
+
+    // More than the candidate count returns each id once
+    let ranked = db.rank_candidates(&vector(0), &ids, 1_000, true).unwrap();
+    let unique: HashSet<u32> = ids.iter().copied().collect();
+    assert_eq!(ranked.len(), unique.len());
+}
+
+#[test]
+fn test_pq_ranking_matches_filtered_search() {
+    let db = build_db(true);
+    let ids = candidates();
+    let query = vector(42);
+
+    // Probing every cluster makes the full search score every id by PQ
+    let everything = || SearchParams::builder().num_probe(8).build();
+    let ranked = db.rank_candidates(&query, &ids, 10, false).unwrap();
+    assert_eq!(pairs(&ranked), filtered_search(&db, &query, &ids, 10, everything()));
+
+    // Exact scoring ignores the codes; reranking lands in between
+    let exact = db.rank_candidates(&query, &ids, 10, true).unwrap();
+    let rerank = SearchParams::builder().rerank(40).build();
+    let reranked = db.rank_candidates_with_params(&query, &ids, 10, false, &rerank).unwrap();
+    assert_eq!(pairs(&reranked)[..5], pairs(&exact)[..5]);
+    assert!(pairs(&exact).iter().zip(pairs(&ranked)).any(|(a, b)| a.1 != b.1));
+}
+
+#[test]
+fn test_filters_apply() {
+    let db = build_db(false);
+    let ids = candidates();
+    let query = vector(130);
+    let top = db.rank_candidates(&query, &ids, 3, true).unwrap();
+
+    let params = SearchParams::builder().exclude([top[0].id]).build();
+    let ranked = db.rank_candidates_with_params(&query, &ids, 3, true, &params).unwrap();
+    assert_eq!(pairs(&ranked)[..2], pairs(&top)[1..]);
+
+    // max_candidates keeps the first ids as given
+    let params = SearchParams::builder().max_candidates(2).build();
+    let ranked = db.rank_candidates_with_params(&query, &[900, 130, 131], 3, true, &params).unwrap();
+    assert_eq!(ranked.iter().map(|r| r.id).collect::<HashSet<_>>(), HashSet::from([900, 130]));
+
+}
+
+#[test]
+fn test_invalid_input() {
+    let mut db = build_db(true);
+    db.delete(12).unwrap();
+
+    assert!(matches!(
+        db.rank_candidates(&vector(0), &[1, 2, 5_000], 3, true),
+        Err(KhadyotaError::VectorNotFound(5_000))
+    ));
+    assert!(matches!(
+        db.rank_candidates(&vector(0), &[11, 12], 3, true),
+        Err(KhadyotaError::VectorNotFound(12))
+    ));
+    assert!(matches!(
+        db.rank_candidates(&[1.0; 3], &[1], 3, true),
+        Err(KhadyotaError::DimensionMismatch { expected: 16, got: 3 })
+    ));
+    assert!(db.rank_candidates(&vector(0), &[], 3, false).unwrap().is_empty());
+
+    // Duplicates are scored once
+    let ranked = db.rank_candidates(&vector(4), &[4, 4, 4, 5], 10, true).unwrap();
+    assert_eq!(ranked.iter().map(|r| r.id).collect::<Vec<_>>(), [4, 5]);
+}
+
+#[test]
+fn test_works_on_stale_index_and_cold_storage() {
+    let mut db = build_db(true);
+    let dir = tempfile::TempDir::new().unwrap();
+    db.spill_originals(&dir.path().join("cold.bin"), 1 << 16).unwrap();
+    let id = db.insert(vector(7_777), None).unwrap();
+
+    // Without a current index, PQ requests fall back to exact distances
+    let exact = db.rank_candidates(&vector(7_777), &[id, 1, 2, 3], 2, true).unwrap();
+    let fallback = db.rank_candidates(&vector(7_777), &[id, 1, 2, 3], 2, false).unwrap();
+    assert_eq!(pairs(&exact), pairs(&fallback));
+    assert_eq!(exact[0].id, id);
+    assert!(exact[0].distance.abs() < 1e-6);
+}
@@ -0,0 +1,115 @@
+//! Original vectors kept at half precision
+
+use khadyota::harness::{clustered_vectors, exact_neighbors, recall};
+use khadyota::storage::{MmapVectors, Serializer};
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 64;
+const ROWS: usize = 3_000;
+
+fn config(raw_precision: Precision) -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        pq_subvectors: 8,
+        num_clusters: 16,
+        num_probe: 4,
+        seed: Some(1),
+        raw_precision,
+        ..Default::default()
+    }
+}
+
+fn built(raw_precision: Precision) -> VectorDB {
+    let mut db = VectorDB::new(config(raw_precision)).unwrap();
+    db.insert_batch(clustered_vectors(ROWS, DIMENSIONS, 16, 0.3, 1).into_iter().map(|v| (v, None)))
+        .unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+#[test]
+fn test_half_precision_keeps_reranked_recall() {
+    let queries = clustered_vectors(50, DIMENSIONS, 16, 0.3, 2);
+    let full = built(Precision::F32);
+    let exact: Vec<_> = queries.iter().map(|q| exact_neighbors(&full, q, 10).unwrap()).collect();
+    let rerank = SearchParams::builder().rerank(50).build();
+    let recall_of = |db: &VectorDB| {
+        queries
+            .iter()
+            .zip(&exact)
+            .map(|(q, exact)| recall(&db.search_with_params(q, 10, &rerank).unwrap(), exact))
+            .sum::<f64>()
+            / queries.len() as f64
+    };
+
+    let baseline = recall_of(&full);
+    for precision in [Precision::F16, Precision::BF16] {
+        let db = built(precision);
+        let drop = baseline - recall_of(&db);
+        assert!(drop < 0.01, "{:?} loses {} of {} recall", precision, drop, baseline);
+
+        let stored = db.get(7).unwrap().vector;
+        let original = full.get(7).unwrap().vector;
+        assert_ne!(stored, original);
+        assert!(stored.iter().zip(&original).all(|(a, b)| (a - b).abs() <= 1e-2 * b.abs().max(1e-3)));
+    }
+}
+
+#[test]
+fn test_half_precision_halves_vector_memory() {
+    let full = built(Precision::F32).stats();
+    for precision in [Precision::F16, Precision::BF16] {
+        let stats = built(precision).stats();
+        assert_eq!(stats.vector_bytes * 2, full.vector_bytes);
+        assert_eq!(stats.vector_bytes, ROWS * DIMENSIONS * 2);
+        assert_eq!(stats.index_bytes(), full.index_bytes());
+    }
+}
+
+#[test]
+fn test_saves_round_trip_in_their_precision() {
+    let dir = TempDir::new().unwrap();
+    let db = built(Precision::F16);
+    let query = clustered_vectors(1, DIMENSIONS, 16, 0.3, 3).remove(0);
+    let ids = |results: Vec<SearchResult>| results.iter().map(|r| r.id).collect::<Vec<_>>();
+    let expected = ids(db.search(&query, 10).unwrap());
+
+    let path = dir.path().join("half.khd");
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load_expecting(&path, &config(Precision::F16)).unwrap();
+    assert_eq!(loaded.checksum(), db.checksum());
+    assert_eq!(loaded.stats().vector_bytes, ROWS * DIMENSIONS * 2);
+    assert_eq!(ids(loaded.search(&query, 10).unwrap()), expected);
+
+    // Mapped vector files hold 16-bit rows and say so in their header
+    let mapped_path = dir.path().join("mapped.khd");
+    db.save_mapped(&mapped_path).unwrap();
+    let rows = MmapVectors::open(&dir.path().join("mapped.khd.vectors")).unwrap();
+    assert_eq!((rows.len(), rows.precision()), (ROWS, Precision::F16));
+    let mapped = VectorDB::load_mapped(&mapped_path, 1 << 16).unwrap();
+    assert_eq!(mapped.stats().mapped_vector_bytes, ROWS * DIMENSIONS * 2);
+    assert_eq!(mapped.get(11).unwrap().vector, db.get(11).unwrap().vector);
+    assert_eq!(ids(mapped.search(&query, 10).unwrap()), expected);
+}
+
+#[test]
+fn test_mismatched_precision_is_an_error() {
+    let dir = TempDir::new().unwrap();
+    let full = built(Precision::F32);
+    let full_path = dir.path().join("full.khd");
+    full.save(&full_path).unwrap();
+    match VectorDB::load_expecting(&full_path, &config(Precision::F16)) {
+        Err(KhadyotaError::ConfigMismatch { field, .. }) => assert_eq!(field, "raw_precision"),
+        other => panic!("expected a mismatch, got {:?}", other.map(|_| ())),
+    }
+
+    // An f32 vector file cannot be mapped for a database storing f16
+    let half_path = dir.path().join("half.khd");
+    built(Precision::F16).save_mapped(&half_path).unwrap();
+    let rows: Vec<Vec<f32>> = full.iter().map(|entry| entry.vector).collect();
+    Serializer::save_vectors(&rows, &dir.path().join("half.khd.vectors")).unwrap();
+    let error = VectorDB::load_mapped(&half_path, 1 << 16).err().expect("mapped an f32 file as f16");
+    assert!(error.to_string().contains("F32"), "{}", error);
+}
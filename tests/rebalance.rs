@@ -0,0 +1,127 @@
+//! Rebalancing an IVF index built over one dense blob and scattered rest
+
+use khadyota::harness::{exact_neighbors, random_vectors, recall};
+use khadyota::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tempfile::TempDir;
+
+const DIMENSIONS: usize = 16;
+
+/// 90% of the rows in one tight blob, the rest spread wide, so k-means
+/// seeding puts one centroid in the blob and the others outside it
+fn skewed_vectors(count: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|i| {
+            let (center, spread) = if i % 10 == 0 { (0.0, 20.0) } else { (5.0, 0.05) };
+            (0..DIMENSIONS).map(|_| center + spread * (rng.r#gen::<f32>() - 0.5)).collect()
+        })
+        .collect()
+}
+
+fn config(quantizer: QuantizerKind) -> Config {
+    Config {
+        dimensions: DIMENSIONS,
+        metric: DistanceMetric::Euclidean,
+        quantizer,
+        pq_subvectors: 4,
+        num_clusters: 64,
+        num_probe: 8,
+        seed: Some(4),
+        max_cluster_imbalance: 3.0,
+        ..Default::default()
+    }
+}
+
+fn build(config: Config, vectors: &[Vec<f32>]) -> VectorDB {
+    let mut db = VectorDB::new(config).unwrap();
+    db.insert_batch(vectors.iter().map(|v| (v.clone(), None))).unwrap();
+    db.build_index().unwrap();
+    db
+}
+
+/// Recall at 10, reranking codes so the clusters probed are what count
+fn mean_recall(db: &VectorDB, queries: &[Vec<f32>], quantizer: QuantizerKind) -> f64 {
+    let params = SearchParams {
+        rerank: (quantizer != QuantizerKind::None).then_some(200),
+        ..Default::default()
+    };
+    let total: f64 = queries
+        .iter()
+        .map(|q| recall(&db.search_with_params(q, 10, &params).unwrap(), &exact_neighbors(db, q, 10).unwrap()))
+        .sum();
+    total / queries.len() as f64
+}
+
+#[test]
+fn test_optimize_index_bounds_the_largest_cluster() {
+    let vectors = skewed_vectors(10_000, 1);
+    // Queries from the blob and from the scattered rows
+    let queries: Vec<Vec<f32>> = skewed_vectors(200, 2).into_iter().step_by(5).collect();
+
+    for quantizer in [QuantizerKind::None, QuantizerKind::PQ] {
+        let mut db = build(config(quantizer), &vectors);
+        let before = db.index_stats().unwrap();
+        assert!(before.imbalance_factor() > 3.0, "{}", before.imbalance_factor());
+
+        let recall_before = mean_recall(&db, &queries, quantizer);
+        let report = db.optimize_index().unwrap();
+        let after = db.index_stats().unwrap();
+        assert!(report.splits > 0, "{:?}", report);
+        assert!(after.imbalance_factor() <= 3.0, "{}", after.imbalance_factor());
+        assert_eq!(after.imbalance_factor(), report.imbalance_after);
+        assert_eq!(after.total_vectors, vectors.len());
+        assert_eq!(after.non_empty_clusters, after.num_clusters);
+
+        // Searches still find the right neighbors, residual codes included:
+        // exactly, when the lists are scanned, and no worse than before
+        // through codes, which tell blob rows apart better from the
+        // centroids of its pieces
+        let recall = mean_recall(&db, &queries, quantizer);
+        assert!(recall >= recall_before, "{:?}: recall {} from {}", quantizer, recall, recall_before);
+        if quantizer == QuantizerKind::None {
+            assert!(recall > 0.95, "recall {}", recall);
+        }
+
+        // Balanced now, so a second pass changes nothing
+        let again = db.optimize_index().unwrap();
+        assert_eq!((again.splits, again.dropped), (0, 0));
+    }
+}
+
+#[test]
+fn test_the_rebalanced_index_is_saved() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let mut db = build(config(QuantizerKind::PQ), &skewed_vectors(5_000, 3));
+    db.optimize_index().unwrap();
+    db.save(&path).unwrap();
+
+    let loaded = VectorDB::load(&path).unwrap();
+    assert_eq!(loaded.index_stats().unwrap().num_clusters, db.index_stats().unwrap().num_clusters);
+    for query in random_vectors(10, DIMENSIONS, 4) {
+        let ids = |db: &VectorDB| db.search(&query, 10).unwrap().into_iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&loaded), ids(&db));
+    }
+}
+
+#[test]
+fn test_balanced_indexes_and_bad_bounds() {
+    let mut db = VectorDB::new(config(QuantizerKind::None)).unwrap();
+    assert!(matches!(db.optimize_index(), Err(KhadyotaError::IndexNotBuilt)));
+
+    let mut db = build(
+        Config { max_cluster_imbalance: 1_000.0, ..config(QuantizerKind::None) },
+        &skewed_vectors(2_000, 5),
+    );
+    let clusters = db.index_stats().unwrap().num_clusters;
+    let report = db.optimize_index().unwrap();
+    assert_eq!((report.splits, report.dropped, report.imbalance_before), (0, 0, report.imbalance_after));
+    assert_eq!(db.index_stats().unwrap().num_clusters, clusters);
+
+    for bound in [0.5, f32::NAN] {
+        let config = Config { max_cluster_imbalance: bound, ..config(QuantizerKind::None) };
+        assert!(matches!(config.validate(), Err(KhadyotaError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,299 @@
+//! Recall and latency gate against `tests/baselines.toml`.
+//!
+//! Ignored by default because it takes a while and measures wall-clock
+//! time. Run it before merging anything that touches search, indexing or
+//! distance code:
+//!
+//! ```text
+//! cargo test --test regression -- --ignored
+//! ```
+//!
+//! After an intentional change in recall or speed, record new baselines
+//! with `KHADYOTA_UPDATE_BASELINES=1` set and commit the file.
+
+use khadyota::harness::{self, Workload};
+use khadyota::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DIMS: usize = 64;
+const VECTORS: usize = 5_000;
+const QUERIES: usize = 200;
+const CLUSTERS: usize = 64;
+const K: usize = 10;
+
+const UPDATE_VAR: &str = "KHADYOTA_UPDATE_BASELINES";
+const HEADER: &str = "\
+# Baselines for tests/regression.rs. Regenerate after an intentional change:
+#   KHADYOTA_UPDATE_BASELINES=1 cargo test --test regression -- --ignored
+# Thresholds are kept when regenerating; edit them by hand.
+
+";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Baselines {
+    thresholds: Thresholds,
+    scenarios: BTreeMap<String, Metrics>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Thresholds {
+    /// Largest allowed fall in recall@10, absolute
+    recall_drop: f64,
+
+    /// Latencies may grow to this multiple of the baseline. Generous,
+    /// since baselines are recorded on one machine and checked on others.
+    latency_factor: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            recall_drop: 0.02,
+            latency_factor: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Metrics {
+    recall_at_10: f64,
+    p50_us: u64,
+    p95_us: u64,
+}
+
+/// Search parameters measured against one collection
+struct Scenario {
+    name: &'static str,
+    params: SearchParams,
+}
+
+/// Collections to build, each with the scenarios measured on it. Building
+/// dominates the run time, so scenarios share a build where they can.
+fn collections() -> Vec<(Config, Vec<Scenario>)> {
+    let base = Config {
+        dimensions: DIMS,
+        metric: DistanceMetric::Euclidean,
+        num_clusters: CLUSTERS,
+        num_probe: 8,
+        seed: Some(7),
+        ..Default::default()
+    };
+    vec![
+        (
+            Config { quantizer: QuantizerKind::None, ..base.clone() },
+            vec![Scenario {
+                name: "ivf_exact",
+                params: SearchParams::default(),
+            }],
+        ),
+        (
+            Config { quantizer: QuantizerKind::PQ, pq_subvectors: 8, ..base },
+            vec![
+                Scenario {
+                    name: "ivf_pq",
+                    params: SearchParams::default(),
+                },
+                Scenario {
+                    name: "ivf_pq_rerank",
+                    params: SearchParams::builder().rerank(100).build(),
+                },
+            ],
+        ),
+    ]
+}
+
+/// Data and held-out queries drawn around the same centres
+fn dataset() -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+    let mut vectors = harness::clustered_vectors(VECTORS + QUERIES, DIMS, CLUSTERS, 0.15, 11);
+    let queries = vectors.split_off(VECTORS);
+    (vectors, queries)
+}
+
+fn measure(db: &VectorDB, scenario: &Scenario, queries: &[Vec<f32>]) -> Metrics {
+    let mut recall = 0.0;
+    for query in queries {
+        let found = db.search_with_params(query, K, &scenario.params).unwrap();
+        recall += harness::recall(&found, &harness::exact_neighbors(db, query, K).unwrap());
+    }
+
+    // Warm caches and branch predictors first, then keep the median of
+    // several trials so one descheduled run does not fail the gate
+    let workload = Workload {
+        k: K,
+        warmup: 2 * queries.len(),
+        measured: 5 * queries.len(),
+        trials: 5,
+        params: scenario.params.clone(),
+    };
+    let report = workload.run(db, queries).unwrap();
+    Metrics {
+        recall_at_10: (recall / queries.len() as f64 * 1e4).round() / 1e4,
+        p50_us: micros(report.latency.p50()),
+        p95_us: micros(report.latency.p95()),
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    (duration.as_micros() as u64).max(1)
+}
+
+fn baselines_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/baselines.toml")
+}
+
+fn read_baselines() -> Option<Baselines> {
+    let text = std::fs::read_to_string(baselines_path()).ok()?;
+    Some(toml::from_str(&text).expect("tests/baselines.toml does not parse"))
+}
+
+/// One metric compared against its baseline
+struct Check {
+    scenario: String,
+    metric: &'static str,
+    baseline: String,
+    measured: String,
+    limit: String,
+    passed: bool,
+}
+
+fn compare(name: &str, baseline: &Metrics, measured: &Metrics, thresholds: &Thresholds) -> Vec<Check> {
+    let min_recall = baseline.recall_at_10 - thresholds.recall_drop;
+    let mut checks = vec![Check {
+        scenario: name.to_string(),
+        metric: "recall@10",
+        baseline: format!("{:.4}", baseline.recall_at_10),
+        measured: format!("{:.4}", measured.recall_at_10),
+        limit: format!(">= {:.4}", min_recall),
+        passed: measured.recall_at_10 >= min_recall,
+    }];
+    for (metric, baseline, measured) in [
+        ("p50", baseline.p50_us, measured.p50_us),
+        ("p95", baseline.p95_us, measured.p95_us),
+    ] {
+        let max = (baseline as f64 * thresholds.latency_factor).ceil() as u64;
+        checks.push(Check {
+            scenario: name.to_string(),
+            metric,
+            baseline: format!("{}us", baseline),
+            measured: format!("{}us", measured),
+            limit: format!("<= {}us", max),
+            passed: measured <= max,
+        });
+    }
+    checks
+}
+
+fn table(checks: &[Check]) -> String {
+    let mut out = format!(
+        "{:<16} {:<10} {:>10} {:>10} {:>12}\n",
+        "scenario", "metric", "baseline", "measured", "limit"
+    );
+    for check in checks {
+        let _ = writeln!(
+            out,
+            "{:<16} {:<10} {:>10} {:>10} {:>12}{}",
+            check.scenario,
+            check.metric,
+            check.baseline,
+            check.measured,
+            check.limit,
+            if check.passed { "" } else { "  REGRESSED" }
+        );
+    }
+    out
+}
+
+#[test]
+#[ignore = "slow, timing-dependent; run with --ignored"]
+fn regression_gate() {
+    let (vectors, queries) = dataset();
+    let mut measured = BTreeMap::new();
+    for (config, scenarios) in collections() {
+        let tolerance = config.tolerance;
+        let db = harness::build_db(config, vectors.clone()).unwrap();
+
+        // The parallel batch path must agree with one-at-a-time search
+        let batched = db.batch_search(&queries, K).unwrap();
+        for (query, batch) in queries.iter().zip(&batched) {
+            let single = db.search(query, K).unwrap();
+            assert!(tolerance.results_equivalent(batch, &single), "{:?} vs {:?}", batch, single);
+        }
+
+        for scenario in scenarios {
+            measured.insert(scenario.name.to_string(), measure(&db, &scenario, &queries));
+        }
+    }
+
+    let recorded = read_baselines();
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        let baselines = Baselines {
+            thresholds: recorded.map(|b| b.thresholds).unwrap_or_default(),
+            scenarios: measured,
+        };
+        let text = format!("{}{}", HEADER, toml::to_string(&baselines).unwrap());
+        std::fs::write(baselines_path(), text).unwrap();
+        println!("Wrote {:?}", baselines_path());
+        return;
+    }
+
+    let baselines = recorded.unwrap_or_else(|| panic!("no tests/baselines.toml; record one with {}=1", UPDATE_VAR));
+    let mut checks = Vec::new();
+    let mut missing = Vec::new();
+    for (name, metrics) in &measured {
+        match baselines.scenarios.get(name) {
+            Some(baseline) => checks.extend(compare(name, baseline, metrics, &baselines.thresholds)),
+            None => missing.push(name.as_str()),
+        }
+    }
+
+    let report = table(&checks);
+    println!("{}", report);
+    let regressed = checks.iter().filter(|c| !c.passed).count();
+    assert!(
+        regressed == 0 && missing.is_empty(),
+        "{} metric(s) regressed beyond thresholds; scenarios without a baseline: {:?}\n\n{}\n\
+         If the change is intended, regenerate with {}=1",
+        regressed,
+        missing,
+        report,
+        UPDATE_VAR
+    );
+}
+
+#[test]
+fn test_comparison_flags_only_real_regressions() {
+    let thresholds = Thresholds::default();
+    let baseline = Metrics {
+        recall_at_10: 0.95,
+        p50_us: 100,
+        p95_us: 200,
+    };
+    let within = Metrics {
+        recall_at_10: 0.935,
+        p50_us: 390,
+        p95_us: 150,
+    };
+    assert!(compare("s", &baseline, &within, &thresholds).iter().all(|c| c.passed));
+
+    let worse = Metrics {
+        recall_at_10: 0.90,
+        p50_us: 401,
+        p95_us: 200,
+    };
+    let failed: Vec<&str> = compare("s", &baseline, &worse, &thresholds)
+        .iter()
+        .filter(|c| !c.passed)
+        .map(|c| c.metric)
+        .collect();
+    assert_eq!(failed, ["recall@10", "p50"]);
+
+    // The committed file parses and covers every scenario
+    let recorded = read_baselines().expect("tests/baselines.toml is committed");
+    for scenario in collections().into_iter().flat_map(|(_, scenarios)| scenarios) {
+        assert!(recorded.scenarios.contains_key(scenario.name), "no baseline for {}", scenario.name);
+    }
+}
@@ -0,0 +1,113 @@
+use khadyota::harness::{build_db, clustered_vectors, random_vectors};
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMS: usize = 32;
+
+fn config(metric: DistanceMetric, use_residuals: bool) -> Config {
+    Config {
+        dimensions: DIMS,
+        metric,
+        pq_subvectors: 4,
+        num_clusters: 16,
+        num_probe: 4,
+        seed: Some(4),
+        use_residuals,
+        ..Default::default()
+    }
+}
+
+/// Queries a little way off stored rows
+fn queries_near(rows: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    random_vectors(100, DIMS, 9)
+        .into_iter()
+        .zip(rows)
+        .map(|(noise, row)| row.iter().zip(&noise).map(|(x, n)| x + 0.03 * n).collect())
+        .collect()
+}
+
+#[test]
+fn test_residual_codes_raise_recall_over_raw_codes() {
+    let rows = clustered_vectors(4_000, DIMS, 16, 0.3, 21);
+    let queries = queries_near(&rows);
+    for metric in [DistanceMetric::Euclidean, DistanceMetric::DotProduct, DistanceMetric::Cosine] {
+        // Every cluster probed, so only the codes differ
+        let recall = |use_residuals| {
+            let db = build_db(Config { num_probe: 16, ..config(metric, use_residuals) }, rows.clone()).unwrap();
+            db.evaluate(&queries, 10).unwrap().recall
+        };
+        let (raw, residual) = (recall(false), recall(true));
+        assert!(residual > raw, "{:?}: {} with residuals, {} without", metric, residual, raw);
+        if metric == DistanceMetric::Euclidean {
+            assert!(residual >= raw + 0.1, "{} with residuals, {} without", residual, raw);
+        }
+    }
+}
+
+#[test]
+fn test_rows_added_after_the_build_are_encoded_against_their_cluster() {
+    let rows = clustered_vectors(3_000, DIMS, 16, 0.3, 6);
+    let mut db = build_db(
+        Config {
+            encode_policy: EncodePolicy::EagerOnInsert,
+            ..config(DistanceMetric::Euclidean, true)
+        },
+        rows[..2_000].to_vec(),
+    )
+    .unwrap();
+    let inserted: Vec<u32> = rows[2_000..].iter().map(|row| db.insert(row.clone(), None).unwrap()).collect();
+    for id in 0..100 {
+        db.update(id, rows[2_500 + id as usize].clone(), None).unwrap();
+    }
+
+    // Codes made against another centroid would be off by the distance
+    // between the two; these are as close as the build's own
+    let query = &queries_near(&rows)[0];
+    let error = |ids: &mut dyn Iterator<Item = u32>| {
+        let errors: Vec<f32> = ids
+            .map(|id| {
+                let pair = db.explain_pair(query, id).unwrap();
+                (pair.approximate_distance.unwrap() - pair.exact_distance.unwrap()).abs()
+            })
+            .collect();
+        errors.iter().sum::<f32>() / errors.len() as f32
+    };
+    let built = error(&mut (100..2_000));
+    assert!(error(&mut inserted.iter().copied()) < 1.5 * built);
+    assert!(error(&mut (0..100)) < 1.5 * built);
+    assert!(matches!(db.export_parts(false), Err(KhadyotaError::InvalidConfig(_))));
+}
+
+#[test]
+fn test_residual_and_raw_codes_both_search_the_same_after_loading() {
+    let dir = TempDir::new().unwrap();
+    let rows = clustered_vectors(1_500, DIMS, 16, 0.3, 8);
+    let queries = queries_near(&rows);
+    for use_residuals in [true, false] {
+        let db = build_db(config(DistanceMetric::Euclidean, use_residuals), rows.clone()).unwrap();
+        let path = dir.path().join(format!("{}.kdb", use_residuals));
+        db.save(&path).unwrap();
+        // Scored as raw codes, residuals would rank differently
+        let loaded = VectorDB::load(&path).unwrap();
+        let hits = |db: &VectorDB, query: &[f32]| -> Vec<(u32, f32)> {
+            db.search(query, 10).unwrap().into_iter().map(|r| (r.id, r.distance)).collect()
+        };
+        for query in &queries[..20] {
+            assert_eq!(hits(&loaded, query), hits(&db, query));
+        }
+    }
+}
+
+#[test]
+fn test_configs_saved_before_residuals_keep_raw_codes() {
+    assert!(Config::default().use_residuals);
+    let mut json = serde_json::to_value(Config::default()).unwrap();
+    json.as_object_mut().unwrap().remove("use_residuals");
+    let loaded: Config = serde_json::from_value(json).unwrap();
+    assert!(!loaded.use_residuals);
+
+    // Only PQ under metrics that split into centroid and residual
+    assert!(config(DistanceMetric::Cosine, true).uses_residuals());
+    assert!(!config(DistanceMetric::Manhattan, true).uses_residuals());
+    assert!(!Config { quantizer: QuantizerKind::SQ8, ..config(DistanceMetric::Euclidean, true) }.uses_residuals());
+}
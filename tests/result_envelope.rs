@@ -0,0 +1,110 @@
+//! Wire compatibility of [`ResultEnvelope`].
+//!
+//! `tests/fixtures/envelopes` holds envelopes as earlier builds wrote
+//! them. Every fixture must keep deserializing: a field added to the
+//! envelope has to be optional, or these tests fail. Add a fixture when
+//! the schema gains a field, and never edit an existing one.
+
+use khadyota::harness::{build_db, clustered_vectors};
+use khadyota::*;
+use serde_json::json;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/envelopes").join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{:?}: {}", path, e))
+}
+
+fn read(name: &str) -> ResultEnvelope {
+    serde_json::from_str(&fixture(name)).unwrap_or_else(|e| panic!("{} no longer deserializes: {}", name, e))
+}
+
+fn hits(results: &[SearchResult]) -> Vec<(u32, f32, Option<serde_json::Value>)> {
+    results.iter().map(|r| (r.id, r.distance, r.metadata.clone())).collect()
+}
+
+#[test]
+fn test_v1_fixtures_deserialize() {
+    let minimal = read("v1_minimal.json");
+    assert_eq!((minimal.schema_version, minimal.metric, minimal.k), (1, DistanceMetric::Cosine, 3));
+    assert!(minimal.produced_with(&SearchParams::default()));
+    assert_eq!(
+        hits(&minimal.into_results().unwrap()),
+        [(4, 0.125, None), (0, 0.5, None), (7, 1.0, None)]
+    );
+
+    let metadata = read("v1_metadata.json");
+    assert!(metadata.produced_with(&SearchParams::builder().rerank(50).build()));
+    assert!(!metadata.produced_with(&SearchParams::default()));
+    assert_eq!(
+        hits(&metadata.into_results().unwrap()),
+        [(12, 0.25, Some(json!({"title": "first", "tags": ["a", "b"]}))), (3, 2.5, None)]
+    );
+
+    // Fields this build does not know are skipped
+    let later = read("v1_later_writer.json");
+    assert_eq!(later.metric, DistanceMetric::DotProduct);
+    assert_eq!(hits(&later.into_results().unwrap()), [(9, -3.0, None), (1, -1.5, None)]);
+}
+
+/// The writer's output is pinned: names, field order and unset fields
+/// left out
+#[test]
+fn test_writer_matches_fixture() {
+    let results = [
+        SearchResult { id: 4, distance: 0.125, metadata: None, external_id: None },
+        SearchResult { id: 0, distance: 0.5, metadata: None, external_id: None },
+        SearchResult { id: 7, distance: 1.0, metadata: None, external_id: None },
+    ];
+    let envelope = ResultEnvelope::new(&results, DistanceMetric::Cosine, 3, &SearchParams::default());
+    assert_eq!(serde_json::to_string(&envelope).unwrap(), fixture("v1_minimal.json").trim_end());
+    assert_eq!(envelope, read("v1_minimal.json"));
+
+    // MessagePack round trip, for envelopes stored next to a database
+    let bytes = rmp_serde::to_vec_named(&envelope).unwrap();
+    assert_eq!(rmp_serde::from_slice::<ResultEnvelope>(&bytes).unwrap(), envelope);
+}
+
+#[test]
+fn test_newer_schema_is_refused() {
+    let mut envelope = read("v1_minimal.json");
+    envelope.schema_version = ENVELOPE_SCHEMA_VERSION + 1;
+    match envelope.into_results() {
+        Err(KhadyotaError::SerializationError(msg)) => assert_eq!(
+            msg,
+            format!("result envelope has schema version {}; this build reads up to 1", ENVELOPE_SCHEMA_VERSION + 1)
+        ),
+        other => panic!("expected SerializationError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_search_envelope_round_trips() {
+    let config = Config {
+        dimensions: 8,
+        quantizer: QuantizerKind::None,
+        metric: DistanceMetric::Euclidean,
+        num_clusters: 4,
+        num_probe: 2,
+        seed: Some(1),
+        ..Default::default()
+    };
+    let mut db = build_db(config, clustered_vectors(200, 8, 4, 0.1, 2)).unwrap();
+    let mut changeset = ChangeSet::new();
+    changeset.update_metadata(5, Some(json!({"name": "five"})));
+    db.apply_changeset(changeset).unwrap();
+    let query = db.get(5).unwrap().vector;
+
+    let params = SearchParams::builder().metric(DistanceMetric::Cosine).build();
+    let envelope = db.search_envelope(&query, 4, &params).unwrap();
+    assert_eq!((envelope.metric, envelope.k), (DistanceMetric::Cosine, 4));
+    assert!(envelope.produced_with(&params));
+    assert_eq!(envelope.results[0].metadata, Some(json!({"name": "five"})));
+
+    let wire = serde_json::to_string(&envelope).unwrap();
+    let back: ResultEnvelope = serde_json::from_str(&wire).unwrap();
+    assert_eq!(
+        hits(&back.into_results().unwrap()),
+        hits(&db.search_with_params(&query, 4, &params).unwrap())
+    );
+}
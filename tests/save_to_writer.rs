@@ -0,0 +1,125 @@
+//! Saving to any writer and loading from any reader, against the files
+//! `save()` and `load()` use
+
+use khadyota::harness::clustered_vectors;
+use khadyota::storage::Serializer;
+use khadyota::*;
+use std::io::{Cursor, Write};
+use tempfile::TempDir;
+
+fn db() -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: 16,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 4,
+        seed: Some(2),
+        ..Default::default()
+    })
+    .unwrap();
+    for (i, vector) in clustered_vectors(500, 16, 8, 0.3, 1).into_iter().enumerate() {
+        db.insert(vector, Some(serde_json::json!({ "i": i }))).unwrap();
+    }
+    db.build_index().unwrap();
+    db.delete(7).unwrap();
+    db
+}
+
+fn ranked(db: &VectorDB, query: &[f32]) -> Vec<(u32, f32)> {
+    db.search(query, 10).unwrap().into_iter().map(|r| (r.id, r.distance)).collect()
+}
+
+#[test]
+fn test_writer_and_file_hold_the_same_bytes() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let db = db();
+    db.save(&path).unwrap();
+
+    let mut buffer = Cursor::new(Vec::new());
+    let written = db.save_to(&mut buffer).unwrap();
+    let bytes = buffer.into_inner();
+    assert_eq!(written, bytes.len() as u64);
+    assert_eq!(bytes, std::fs::read(&path).unwrap());
+
+    // Each path reads what the other wrote
+    let from_buffer = VectorDB::load_from(bytes.as_slice()).unwrap();
+    let from_file = VectorDB::load_from(std::fs::File::open(&path).unwrap()).unwrap();
+    std::fs::write(&path, &bytes).unwrap();
+    let loaded = VectorDB::load(&path).unwrap();
+    let query = db.get(42).unwrap().vector;
+    for other in [&from_buffer, &from_file, &loaded] {
+        assert_eq!(other.checksum(), db.checksum());
+        assert_eq!(ranked(other, &query), ranked(&db, &query));
+        assert_eq!(other.get(42).unwrap().metadata, Some(serde_json::json!({ "i": 42 })));
+    }
+
+    let mut again = Vec::new();
+    from_buffer.save_to(&mut again).unwrap();
+    assert_eq!(again, bytes);
+}
+
+#[test]
+fn test_loaded_buffers_save_incrementally_in_full() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db.kdb");
+    let mut bytes = Vec::new();
+    db().save_to(&mut bytes).unwrap();
+
+    let mut db = VectorDB::load_from(Cursor::new(bytes)).unwrap();
+    let report = db.save_incremental(&path).unwrap();
+    assert!(report.full_rewrite.is_some());
+    assert_eq!(report.bytes_reused, 0);
+    // Once saved there, the file is the one reused
+    assert!(db.save_incremental(&path).unwrap().bytes_reused > 0);
+}
+
+#[test]
+fn test_bad_input_is_reported() {
+    /// Takes a few bytes, then fails
+    struct Full(usize);
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0 == 0 {
+                return Err(std::io::Error::other("disk full"));
+            }
+            let taken = buf.len().min(self.0);
+            self.0 -= taken;
+            Ok(taken)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let db = db();
+    assert!(matches!(
+        db.save_to(Full(100)),
+        Err(KhadyotaError::IoError(_) | KhadyotaError::RmpEncodeError(_))
+    ));
+
+    let mut bytes = Vec::new();
+    db.save_to(&mut bytes).unwrap();
+    bytes.truncate(bytes.len() / 2);
+    assert!(matches!(VectorDB::load_from(bytes.as_slice()), Err(KhadyotaError::SerializationError(_))));
+    assert!(VectorDB::load_from(&b"not a database"[..]).is_err());
+}
+
+#[test]
+fn test_serializer_round_trips_through_a_buffer() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("data.msgpack");
+    let data = (vec![1.5f32, -2.0], "label".to_string(), 7u64);
+
+    let mut bytes = Vec::new();
+    let written = Serializer::save_to(&data, &mut bytes).unwrap();
+    assert_eq!(written, bytes.len() as u64);
+    Serializer::save(&data, &path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+    let loaded: (Vec<f32>, String, u64) = Serializer::load_from(Cursor::new(&bytes)).unwrap();
+    assert_eq!(loaded, data);
+    assert_eq!(Serializer::load::<(Vec<f32>, String, u64)>(&path).unwrap(), data);
+}
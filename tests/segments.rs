@@ -0,0 +1,148 @@
+use khadyota::*;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn vector(i: usize) -> Vec<f32> {
+    (0..32).map(|j| ((i * 32 + j) as f32 * 0.37).sin()).collect()
+}
+
+fn config() -> Config {
+    Config {
+        dimensions: 32,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::None,
+        num_clusters: 16,
+        num_probe: 4,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_sustained_ingest_with_bounded_search() {
+    let mut db = SegmentedDB::new(config(), 500).unwrap();
+    // Merge in the insert that seals
+    db.set_auto_merge(true);
+    let mut worst_search = Duration::ZERO;
+
+    for i in 0..20_000 {
+        let id = db.insert(vector(i), Some(serde_json::json!({"n": i}))).unwrap();
+        assert_eq!(id as usize, i);
+
+        if i % 1000 == 999 {
+            let sizes = db.segment_sizes();
+            // Tiered merging keeps the segment count logarithmic in size
+            assert!(sizes.len() <= 12, "too many segments: {:?}", sizes);
+
+            let target = i / 2;
+            let start = Instant::now();
+            let results = db.search(&vector(target), 5).unwrap();
+            worst_search = worst_search.max(start.elapsed());
+
+            assert_eq!(results[0].id as usize, target);
+            assert_eq!(results[0].metadata.as_ref().unwrap()["n"], target);
+        }
+    }
+
+    assert_eq!(db.len(), 20_000);
+    println!("segments: {:?}, worst search: {:?}", db.segment_sizes(), worst_search);
+    assert!(worst_search < Duration::from_millis(500), "search took {:?}", worst_search);
+
+    // Vectors still in the unsealed segment are searchable too
+    let id = db.insert(vector(50_000), None).unwrap();
+    assert!(db.active_len() > 0);
+    assert_eq!(db.search(&vector(50_000), 1).unwrap()[0].id, id);
+}
+
+#[test]
+fn test_manifest_survives_reopen_and_ignores_orphans() {
+    let dir = TempDir::new().unwrap();
+    let mut db = SegmentedDB::new(config(), 100).unwrap();
+
+    for i in 0..450 {
+        db.insert(vector(i), Some(serde_json::json!({"n": i}))).unwrap();
+    }
+    db.save(dir.path()).unwrap();
+
+    // Segments written after the last save but never referenced by a
+    // manifest, as after a crash mid-save, must not leak into the store
+    for i in 450..900 {
+        db.insert(vector(i), None).unwrap();
+    }
+    std::fs::write(dir.path().join("segment-9999999999.kdb"), b"partial").unwrap();
+    std::fs::write(dir.path().join("MANIFEST.tmp"), b"partial").unwrap();
+
+    let reopened = SegmentedDB::open(dir.path()).unwrap();
+    assert_eq!(reopened.len(), 450);
+    assert_eq!(reopened.active_len(), 50);
+
+    for target in [0, 123, 399, 449] {
+        let results = reopened.search(&vector(target), 3).unwrap();
+        assert_eq!(results[0].id as usize, target);
+        assert_eq!(results[0].metadata.as_ref().unwrap()["n"], target);
+    }
+
+    // A later save replaces the manifest and drops unreferenced files
+    db.save(dir.path()).unwrap();
+    assert!(!dir.path().join("segment-9999999999.kdb").exists());
+    assert_eq!(SegmentedDB::open(dir.path()).unwrap().len(), 900);
+}
+
+#[test]
+fn test_partial_search_isolates_a_corrupt_segment() {
+    let dir = TempDir::new().unwrap();
+    let mut db = SegmentedDB::new(config(), 100).unwrap();
+    db.set_auto_merge(false);
+    for i in 0..300 {
+        db.insert(vector(i), Some(serde_json::json!({"n": i}))).unwrap();
+    }
+    assert_eq!(db.segment_sizes(), [100, 100, 100]);
+    db.save(dir.path()).unwrap();
+    std::fs::write(dir.path().join("segment-0000000001.kdb"), b"corrupt").unwrap();
+
+    assert!(SegmentedDB::open(dir.path()).is_err());
+    assert!(matches!(
+        SegmentedDB::open_with(dir.path(), FailurePolicy::Partial { min_segments: 3 }),
+        Err(KhadyotaError::TooFewSegments { healthy: 2, total: 3, required: 3 })
+    ));
+
+    let mut partial = SegmentedDB::open_with(dir.path(), FailurePolicy::Partial { min_segments: 2 }).unwrap();
+    assert_eq!(partial.len(), 300);
+    for target in [0, 150, 299] {
+        let outcome = partial.search_outcome(&vector(target), 3).unwrap();
+        assert!(outcome.degraded);
+        assert_eq!(outcome.segments_searched, 2);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].seq, 1);
+        assert!(outcome.failures[0].persistent);
+
+        // Rows of the failed segment are missing; the rest are found
+        let ids: Vec<usize> = outcome.results.iter().map(|r| r.id as usize).collect();
+        assert!(ids.iter().all(|id| !(100..200).contains(id)), "{:?}", ids);
+        if !(100..200).contains(&target) {
+            assert_eq!(ids[0], target);
+            assert_eq!(outcome.results[0].metadata.as_ref().unwrap()["n"], target);
+        }
+    }
+
+    // Plain search never returns incomplete results
+    assert!(matches!(
+        partial.search(&vector(0), 3),
+        Err(KhadyotaError::SegmentFailed { seq: 1, .. })
+    ));
+
+    let health = partial.health();
+    assert_eq!(health.status, HealthStatus::Degraded);
+    let failing: Vec<_> = health.failing().map(|c| c.name.as_str()).collect();
+    assert_eq!(failing, ["segments", "segment 1"]);
+
+    // Saving keeps the failed segment, so a repaired file is served again
+    partial.insert(vector(300), None).unwrap();
+    partial.save(dir.path()).unwrap();
+    assert!(dir.path().join("segment-0000000001.kdb").exists());
+    partial.set_failure_policy(FailurePolicy::FailFast);
+    assert!(matches!(
+        partial.search_outcome(&vector(0), 3),
+        Err(KhadyotaError::SegmentFailed { seq: 1, .. })
+    ));
+    assert_eq!(partial.health().component("segments").unwrap().status, HealthStatus::Unready);
+}
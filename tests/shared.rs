@@ -0,0 +1,172 @@
+use khadyota::*;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tempfile::TempDir;
+
+const DIMS: usize = 8;
+
+/// Every row of version `v` carries `v` in its vector and metadata, so a
+/// reader can tell whether it mixed two versions
+fn versioned_db(version: u64) -> VectorDB {
+    let mut db = VectorDB::new(Config {
+        dimensions: DIMS,
+        quantizer: QuantizerKind::None,
+        num_clusters: 4,
+        num_probe: 4,
+        seed: Some(1),
+        normalize: false,
+        ..Default::default()
+    })
+    .unwrap();
+    for i in 0..(50 + version as usize) {
+        let mut vector = vec![version as f32; DIMS];
+        vector[0] += i as f32 * 0.01;
+        db.insert(vector, Some(json!({ "version": version }))).unwrap();
+    }
+    db.build_index().unwrap();
+    db
+}
+
+fn assert_consistent(db: &VectorDB) {
+    let version = db.shared_version().unwrap();
+    assert_eq!(db.len(), 50 + version as usize);
+
+    let results = db.search(&[version as f32; DIMS], 5).unwrap();
+    assert_eq!(results.len(), 5);
+    for result in results {
+        assert_eq!(result.metadata.unwrap()["version"], version);
+        assert_eq!(db.get(result.id).unwrap().vector[1], version as f32);
+    }
+}
+
+fn published_files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("v-"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_open_shared_maps_published_vectors() {
+    let dir = TempDir::new().unwrap();
+    let writer = versioned_db(1);
+    assert_eq!(writer.publish(dir.path()).unwrap(), 1);
+
+    let reader = VectorDB::open_shared(dir.path()).unwrap();
+    assert_eq!(reader.shared_version(), Some(1));
+    assert!(reader.cold_cache_stats().is_some());
+    assert_consistent(&reader);
+
+    let query = [1.2; DIMS];
+    let ids = |db: &VectorDB| db.search(&query, 10).unwrap().iter().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids(&reader), ids(&writer));
+}
+
+#[test]
+fn test_refresh_follows_the_manifest() {
+    let dir = TempDir::new().unwrap();
+    versioned_db(1).publish(dir.path()).unwrap();
+
+    let mut reader = VectorDB::open_shared(dir.path()).unwrap();
+    reader.set_query_cache(Some(QueryCacheConfig::default()));
+    assert!(!reader.refresh().unwrap());
+
+    reader.search(&[1.0; DIMS], 5).unwrap();
+    versioned_db(2).publish(dir.path()).unwrap();
+    assert!(reader.refresh().unwrap());
+    assert_eq!(reader.shared_version(), Some(2));
+
+    // Settings carry over, and cached version-1 results are not served
+    assert!(reader.query_cache_stats().is_some());
+    assert_consistent(&reader);
+    assert!(!reader.refresh().unwrap());
+}
+
+#[test]
+fn test_refresh_needs_a_shared_database() {
+    let mut db = versioned_db(1);
+    assert!(matches!(db.refresh(), Err(KhadyotaError::InvalidConfig(_))));
+    assert_eq!(db.shared_version(), None);
+}
+
+#[test]
+fn test_superseded_versions_outlive_the_grace_period_only() {
+    let dir = TempDir::new().unwrap();
+    for version in 1..=3 {
+        versioned_db(version).publish(dir.path()).unwrap();
+    }
+    assert_eq!(published_files(dir.path()).len(), 6);
+
+    versioned_db(4).publish_with_grace(dir.path(), Duration::ZERO).unwrap();
+    assert_eq!(published_files(dir.path()), ["v-0000000004.kdb", "v-0000000004.vectors"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reader_keeps_serving_an_unlinked_version() {
+    let dir = TempDir::new().unwrap();
+    versioned_db(1).publish(dir.path()).unwrap();
+    let mut reader = VectorDB::open_shared(dir.path()).unwrap();
+
+    versioned_db(2).publish_with_grace(dir.path(), Duration::ZERO).unwrap();
+    versioned_db(3).publish_with_grace(dir.path(), Duration::ZERO).unwrap();
+    assert_consistent(&reader);
+
+    assert!(reader.refresh().unwrap());
+    assert_eq!(reader.shared_version(), Some(3));
+    assert_consistent(&reader);
+}
+
+#[test]
+fn test_empty_collections_publish() {
+    let dir = TempDir::new().unwrap();
+    VectorDB::new(Config::for_dimensions(DIMS)).unwrap().publish(dir.path()).unwrap();
+
+    let reader = VectorDB::open_shared(dir.path()).unwrap();
+    assert!(reader.is_empty());
+    assert_eq!(published_files(dir.path()), ["v-0000000001.kdb"]);
+}
+
+#[test]
+fn test_readers_never_observe_a_torn_version() {
+    let dir = TempDir::new().unwrap();
+    versioned_db(1).publish(dir.path()).unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let path = dir.path().to_path_buf();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let mut reader = VectorDB::open_shared(&path).unwrap();
+                let mut seen = vec![reader.shared_version().unwrap()];
+                while !done.load(Ordering::Acquire) {
+                    if reader.refresh().unwrap() {
+                        seen.push(reader.shared_version().unwrap());
+                    }
+                    assert_consistent(&reader);
+                }
+                reader.refresh().unwrap();
+                assert_consistent(&reader);
+                (seen, reader.shared_version().unwrap())
+            })
+        })
+        .collect();
+
+    for version in 2..=20 {
+        versioned_db(version).publish(dir.path()).unwrap();
+    }
+    done.store(true, Ordering::Release);
+
+    for reader in readers {
+        let (seen, last) = reader.join().unwrap();
+        assert!(seen.windows(2).all(|w| w[0] < w[1]), "versions went backwards: {:?}", seen);
+        assert_eq!(last, 20);
+    }
+}
@@ -0,0 +1,35 @@
+//! Shortened soak run. Ignored by default since it takes a while and
+//! watches process RSS, which other tests running alongside disturb:
+//!
+//! ```text
+//! cargo test --release --test soak -- --ignored
+//! ```
+
+use khadyota::soak::{self, Scenario, SoakOp};
+
+#[test]
+#[ignore]
+fn test_short_soak_holds_invariants_and_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let scenario = Scenario {
+        dimensions: 32,
+        num_clusters: 16,
+        initial_rows: 2_000,
+        max_rows: 4_000,
+        duration_secs: None,
+        max_ops: Some(10_000),
+        sample_every: 500,
+        max_unaccounted_growth_bytes: 32 * 1024 * 1024,
+        dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    
+    let report = soak::run(&scenario).unwrap();
+    assert!(report.passed(), "{:#?}", report.violations);
+    assert_eq!(report.ops.iter().filter(|(op, _)| *op != SoakOp::Setup).map(|(_, n)| n).sum::<u64>(), 10_000);
+    assert!(report.ops.iter().all(|&(op, n)| n > 0 || matches!(op, SoakOp::Setup | SoakOp::Check)));
+    assert_eq!(report.samples.len(), 21);
+    assert!(report.checks > 3 * 21);
+    // The save file is cleaned up
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
@@ -0,0 +1,78 @@
+use khadyota::harness::{build_db, clustered_vectors, recall_at_k};
+use khadyota::*;
+use tempfile::TempDir;
+
+const DIMS: usize = 32;
+
+fn config(quantizer: QuantizerKind, metric: DistanceMetric) -> Config {
+    Config {
+        dimensions: DIMS,
+        metric,
+        quantizer,
+        pq_subvectors: 8,
+        // Every cluster is probed, so recall measures the codes alone
+        num_clusters: 4,
+        num_probe: 4,
+        seed: Some(5),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_sq8_recall_beats_pq() {
+    let vectors = clustered_vectors(3_000, DIMS, 16, 0.4, 1);
+    let queries = clustered_vectors(50, DIMS, 16, 0.4, 2);
+
+    for metric in [DistanceMetric::Euclidean, DistanceMetric::Cosine] {
+        let pq = build_db(config(QuantizerKind::PQ, metric), vectors.clone()).unwrap();
+        let sq = build_db(config(QuantizerKind::SQ8, metric), vectors.clone()).unwrap();
+        assert_eq!(sq.memory_usage().code_bytes, 3_000 * DIMS);
+        assert_eq!(sq.check_compatibility().violations, []);
+
+        let pq_recall = recall_at_k(&pq, &queries, 10).unwrap();
+        let sq_recall = recall_at_k(&sq, &queries, 10).unwrap();
+        println!("{:?}: PQ recall@10 {:.3}, SQ8 {:.3}", metric, pq_recall, sq_recall);
+        assert!(sq_recall > 0.9, "{:?}: {}", metric, sq_recall);
+        assert!(sq_recall > pq_recall + 0.1, "{:?}: SQ8 {} vs PQ {}", metric, sq_recall, pq_recall);
+    }
+}
+
+#[test]
+fn test_sq8_survives_save_and_load() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("sq8.kdb");
+    let vectors = clustered_vectors(500, DIMS, 8, 0.4, 3);
+    let config = config(QuantizerKind::SQ8, DistanceMetric::Euclidean);
+    let hits = |db: &VectorDB| -> Vec<(u32, f32)> {
+        db.search(&vectors[17], 10).unwrap().iter().map(|r| (r.id, r.distance)).collect()
+    };
+
+    let db = build_db(config.clone(), vectors.clone()).unwrap();
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load_expecting(&path, &config).unwrap();
+    assert_eq!(hits(&loaded), hits(&db));
+    assert_eq!(loaded.checksum(), db.checksum());
+
+    // A PQ config does not describe SQ8 codes
+    let pq = Config { quantizer: QuantizerKind::PQ, ..config };
+    assert!(matches!(
+        VectorDB::load_expecting(&path, &pq),
+        Err(KhadyotaError::ConfigMismatch { field: "quantizer", .. })
+    ));
+}
+
+#[test]
+fn test_configs_with_use_pq_still_load() {
+    for (use_pq, quantizer) in [(true, QuantizerKind::PQ), (false, QuantizerKind::None)] {
+        let mut json = serde_json::to_value(Config::default()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("quantizer");
+        fields.insert("use_pq".to_string(), use_pq.into());
+        let loaded: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.quantizer, quantizer);
+    }
+
+    let sq = config(QuantizerKind::SQ8, DistanceMetric::Cosine);
+    let loaded: Config = serde_json::from_value(serde_json::to_value(&sq).unwrap()).unwrap();
+    assert_eq!(loaded.quantizer, QuantizerKind::SQ8);
+}
@@ -0,0 +1,199 @@
+use khadyota::harness::{build_db, clustered_vectors, random_vectors};
+use khadyota::indexing::IVFIndex;
+use khadyota::storage::{MmapVectors, QuantizedVectors, Serializer};
+use khadyota::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use tempfile::TempDir;
+
+const DIMS: usize = 16;
+const THREADS: usize = 8;
+const ROUNDS: usize = 50;
+
+fn config() -> Config {
+    Config {
+        dimensions: DIMS,
+        pq_subvectors: 4,
+        num_clusters: 8,
+        num_probe: 3,
+        seed: Some(3),
+        normalize: false,
+        ..Default::default()
+    }
+}
+
+fn ids(results: &[SearchResult]) -> Vec<u32> {
+    results.iter().map(|r| r.id).collect()
+}
+
+/// Shared read access from many threads, with every interior cache and
+/// counter switched on, must give the answers a single thread gets
+#[test]
+fn test_concurrent_readers_agree_with_one_thread() {
+    let dir = TempDir::new().unwrap();
+    let vectors = clustered_vectors(1_000, DIMS, 8, 0.2, 3);
+    let queries = random_vectors(20, DIMS, 4);
+
+    let mut db = build_db(config(), vectors.clone()).unwrap();
+    db.spill_originals(&dir.path().join("cold.vectors"), 16 * DIMS * 4).unwrap();
+    let rerank = SearchParams::builder().rerank(30).build();
+    let expected: Vec<_> = queries
+        .iter()
+        .map(|q| (ids(&db.search(q, 10).unwrap()), ids(&db.search_with_params(q, 10, &rerank).unwrap())))
+        .collect();
+
+    db.set_query_cache(Some(QueryCacheConfig::default()));
+    db.set_latency_recording(true);
+    db.set_access_tracking(Some(AccessTrackingConfig::default()));
+    let db = Arc::new(db);
+
+    let mapped_path = dir.path().join("rows.vectors");
+    Serializer::save_vectors(&vectors, &mapped_path).unwrap();
+    let mapped = Arc::new(MmapVectors::open(&mapped_path).unwrap());
+
+    let mut segmented = SegmentedDB::new(Config { quantizer: QuantizerKind::None, ..config() }, 200).unwrap();
+    for vector in &vectors {
+        segmented.insert(vector.clone(), None).unwrap();
+    }
+    let segmented = Arc::new(segmented);
+    let segmented_expected: Vec<_> = queries.iter().map(|q| ids(&segmented.search(q, 5).unwrap())).collect();
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let (db, mapped, segmented) = (Arc::clone(&db), Arc::clone(&mapped), Arc::clone(&segmented));
+            let (queries, vectors) = (queries.clone(), vectors.clone());
+            let (expected, segmented_expected, rerank) = (expected.clone(), segmented_expected.clone(), rerank.clone());
+            thread::spawn(move || {
+                for round in 0..ROUNDS {
+                    let i = (t + round) % queries.len();
+                    let query = &queries[i];
+                    assert_eq!(ids(&db.search(query, 10).unwrap()), expected[i].0);
+                    assert_eq!(ids(&db.search_with_params(query, 10, &rerank).unwrap()), expected[i].1);
+                    assert_eq!(ids(&segmented.search(query, 5).unwrap()), segmented_expected[i]);
+
+                    let id = ((t * ROUNDS + round) * 7 % vectors.len()) as u32;
+                    assert_eq!(db.get(id).unwrap().vector, vectors[id as usize]);
+                    assert_eq!(mapped.get(id as usize).unwrap(), vectors[id as usize].as_slice());
+
+                    if round % 10 == 0 {
+                        let batch = db.batch_search(&queries[..4], 10).unwrap();
+                        for (j, results) in batch.iter().enumerate() {
+                            assert_eq!(ids(results), expected[j].0);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let searches = THREADS * ROUNDS * 2 + THREADS * (ROUNDS / 10) * 4;
+    let cache = db.query_cache_stats().unwrap();
+    assert_eq!(cache.hits + cache.misses, searches as u64);
+    assert_eq!(db.latency_histogram().count(), searches as u64);
+    assert_eq!(db.access_stats().unwrap().queries, searches as u64);
+    assert!(db.cold_cache_stats().unwrap().cached_bytes <= 16 * DIMS * 4);
+}
+
+/// Searches through snapshot readers keep going while a writer inserts,
+/// rebuilds and hands out new snapshots, and only ever see rows that
+/// existed when their snapshot was taken
+#[test]
+fn test_readers_search_while_snapshots_are_rebuilt() {
+    const READERS: usize = 16;
+    const BATCHES: usize = 4;
+    const BATCH: usize = 250;
+
+    let vectors = clustered_vectors(1_000 + BATCHES * BATCH, DIMS, 8, 0.2, 7);
+    let queries = random_vectors(20, DIMS, 8);
+    let mut db = build_db(config(), vectors[..1_000].to_vec()).unwrap();
+    let inserted = Arc::new(AtomicU32::new(1_000));
+    let slot = Arc::new(RwLock::new(db.reader()));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|t| {
+            let (slot, inserted, done, queries) = (Arc::clone(&slot), Arc::clone(&inserted), Arc::clone(&done), queries.clone());
+            thread::spawn(move || {
+                let mut searches = 0;
+                while !done.load(Ordering::Acquire) || searches < 20 {
+                    let reader = slot.read().unwrap().clone();
+                    let query = &queries[(t + searches) % queries.len()];
+                    let results = if searches % 5 == 0 {
+                        reader.batch_search(&queries[..2], 10).unwrap().swap_remove(0)
+                    } else {
+                        reader.search(query, 10).unwrap()
+                    };
+                    assert_eq!(results.len(), 10);
+                    for r in &results {
+                        assert!(r.id < reader.len() as u32, "{} beyond a snapshot of {}", r.id, reader.len());
+                        assert!(r.id < inserted.load(Ordering::Acquire));
+                    }
+                    searches += 1;
+                }
+                searches
+            })
+        })
+        .collect();
+
+    for batch in vectors[1_000..].chunks(BATCH) {
+        for vector in batch {
+            db.insert(vector.clone(), None).unwrap();
+        }
+        inserted.store(db.len() as u32, Ordering::Release);
+        db.build_index().unwrap();
+        *slot.write().unwrap() = db.reader();
+    }
+    done.store(true, Ordering::Release);
+
+    for handle in readers {
+        assert!(handle.join().unwrap() >= 20);
+    }
+    let reader = slot.read().unwrap().clone();
+    assert_eq!(reader.len(), vectors.len());
+    assert_eq!(reader.seq(), db.applied_seq());
+}
+
+/// The building blocks are usable on their own from several threads
+#[test]
+fn test_components_are_shared_by_reference() {
+    let config = Config {
+        use_residuals: false,
+        ..config()
+    };
+    let db = build_db(config, clustered_vectors(600, DIMS, 8, 0.2, 5)).unwrap();
+    let parts = Arc::new(db.export_parts(true).unwrap());
+    let ivf = IVFIndex::from_assignments(DIMS, parts.centroids.clone(), &parts.assignments, 3, DistanceMetric::Cosine);
+    let quantized = QuantizedVectors::from_codes(parts.codec.clone(), parts.codes.clone()).unwrap();
+    let queries = random_vectors(10, DIMS, 6);
+    let expected: Vec<_> = queries
+        .iter()
+        .map(|q| {
+            let table = quantized.precompute_distance_table(q);
+            let scores: Vec<f32> = (0..quantized.len() as u32).map(|id| quantized.table_lookup_distance(&table, id)).collect();
+            (ivf.probe(q), scores)
+        })
+        .collect();
+
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let (parts, ivf, quantized, queries, expected) = (&parts, &ivf, &quantized, &queries, &expected);
+            scope.spawn(move || {
+                let vectors = parts.vectors.as_ref().unwrap();
+                for id in (t..vectors.len()).step_by(THREADS) {
+                    assert_eq!(parts.codec.encode(&vectors[id]), parts.codes[id]);
+                }
+                for (query, (probed, scores)) in queries.iter().zip(expected) {
+                    assert_eq!(&ivf.probe(query), probed);
+                    let table = quantized.precompute_distance_table(query);
+                    for id in (t..quantized.len()).step_by(THREADS) {
+                        assert_eq!(quantized.table_lookup_distance(&table, id as u32), scores[id]);
+                    }
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,179 @@
+//! Scalar and tiny-dimensional vectors (dims 1-4), checked against
+//! hand-computed nearest neighbors.
+
+use khadyota::*;
+use tempfile::TempDir;
+
+fn ids(results: &[SearchResult]) -> Vec<u32> {
+    results.iter().map(|r| r.id).collect()
+}
+
+fn flat(dimensions: usize, metric: DistanceMetric, num_clusters: usize) -> Config {
+    Config {
+        metric,
+        num_clusters,
+        num_probe: num_clusters,
+        seed: Some(7),
+        ..Config::for_dimensions(dimensions)
+    }
+}
+
+#[test]
+fn test_dim_2_end_to_end() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tiny.kdb");
+
+    let points = [[0.0, 0.0], [3.0, 4.0], [-1.0, 0.5], [10.0, 10.0], [2.0, 2.0]];
+    let mut db = VectorDB::new(flat(2, DistanceMetric::Euclidean, 2)).unwrap();
+    for (i, p) in points.iter().enumerate() {
+        db.insert(p.to_vec(), Some(serde_json::json!({"i": i}))).unwrap();
+    }
+    db.build_index().unwrap();
+
+    // From (2.5, 3): (3,4) at 1.118, (2,2) at 1.118, (0,0) at 3.905,
+    // (-1,0.5) at 4.301, (10,10) at 10.26
+    let query = [2.5, 3.0];
+    let exact = SearchParams::builder().num_probe(2).build();
+    let results = db.search_with_params(&query, 5, &exact).unwrap();
+    let mut top2 = ids(&results[..2]);
+    top2.sort();
+    assert_eq!(top2, vec![1, 4]);
+    assert_eq!(ids(&results[2..]), vec![0, 2, 3]);
+    assert!((results[0].distance - 1.25f32.sqrt()).abs() < 1e-6);
+    assert!((results[2].distance - 15.25f32.sqrt()).abs() < 1e-6);
+
+    db.save(&path).unwrap();
+    let loaded = VectorDB::load(&path).unwrap();
+    let reloaded = loaded.search_with_params(&query, 5, &exact).unwrap();
+    assert_eq!(ids(&reloaded), ids(&results));
+    assert_eq!(loaded.get(3).unwrap().vector, vec![10.0, 10.0]);
+    assert_eq!(loaded.search(&[9.0, 9.5], 1).unwrap()[0].id, 3);
+}
+
+#[test]
+fn test_dim_1_to_4_flat_and_ivf() {
+    for dims in 1..=4 {
+        // Points along the diagonal at 0, 1, ..., 9
+        let mut db = VectorDB::new(flat(dims, DistanceMetric::Euclidean, 1)).unwrap();
+        for i in 0..10 {
+            db.insert(vec![i as f32; dims], None).unwrap();
+        }
+        db.build_index().unwrap();
+
+        // 6.4 is nearest 6, then 7, then 5
+        let results = db.search(&vec![6.4; dims], 3).unwrap();
+        assert_eq!(ids(&results), vec![6, 7, 5], "dims {}", dims);
+        let expected = 0.4 * (dims as f32).sqrt();
+        assert!((results[0].distance - expected).abs() < 1e-5, "dims {}", dims);
+
+        // IVF-only: a full probe is exact
+        let mut db = VectorDB::new(flat(dims, DistanceMetric::Euclidean, 3)).unwrap();
+        for i in 0..10 {
+            db.insert(vec![i as f32; dims], None).unwrap();
+        }
+        db.build_index().unwrap();
+        let exact = SearchParams::builder().num_probe(3).build();
+        let results = db.search_with_params(&vec![-2.0; dims], 2, &exact).unwrap();
+        assert_eq!(ids(&results), vec![0, 1], "dims {}", dims);
+    }
+}
+
+#[test]
+fn test_dim_1_metrics() {
+    let values = [-3.0, -0.5, 0.25, 2.0, 8.0];
+    let build = |metric| {
+        let mut db = VectorDB::new(flat(1, metric, 1)).unwrap();
+        for v in values {
+            db.insert(vec![v], None).unwrap();
+        }
+        db.build_index().unwrap();
+        db
+    };
+
+    // |x - 1.5|: 2.0, 0.25, -0.5, -3.0, 8.0
+    let results = build(DistanceMetric::Euclidean).search(&[1.5], 5).unwrap();
+    assert_eq!(ids(&results), vec![3, 2, 1, 0, 4]);
+
+    // In one dimension cosine only sees the sign: 0 for same sign, 2 for opposite
+    let results = build(DistanceMetric::Cosine).search(&[4.0], 5).unwrap();
+    let mut same_sign = ids(&results[..3]);
+    same_sign.sort();
+    assert_eq!(same_sign, vec![2, 3, 4]);
+    assert!(results[..3].iter().all(|r| r.distance.abs() < 1e-6));
+    assert!(results[3..].iter().all(|r| (r.distance - 2.0).abs() < 1e-6));
+}
+
+#[test]
+fn test_more_clusters_than_vectors() {
+    let mut db = VectorDB::new(Config {
+        num_clusters: 100,
+        num_probe: 10,
+        ..flat(3, DistanceMetric::Euclidean, 100)
+    })
+    .unwrap();
+    for i in 0..4 {
+        db.insert(vec![i as f32, 0.0, 0.0], None).unwrap();
+    }
+    db.build_index().unwrap();
+
+    let results = db.search(&[2.2, 0.0, 0.0], 4).unwrap();
+    assert_eq!(ids(&results), vec![2, 3, 1, 0]);
+}
+
+#[test]
+fn test_pq_at_tiny_dimensions() {
+    // PQ still works when asked for explicitly, with codebooks no larger
+    // than the training set
+    let config = Config {
+        dimensions: 2,
+        metric: DistanceMetric::Euclidean,
+        quantizer: QuantizerKind::PQ,
+        pq_subvectors: 2,
+        num_clusters: 1,
+        num_probe: 1,
+        seed: Some(7),
+        ..Default::default()
+    };
+    let mut db = VectorDB::new(config).unwrap();
+    for i in 0..20 {
+        db.insert(vec![i as f32, -(i as f32)], None).unwrap();
+    }
+    db.build_index().unwrap();
+
+    let params = SearchParams::builder().rerank(20).build();
+    assert_eq!(db.search_with_params(&[13.1, -13.1], 1, &params).unwrap()[0].id, 13);
+}
+
+#[test]
+fn test_pq_config_errors_name_the_remedy() {
+    let message = |config: Config| match VectorDB::new(config) {
+        Err(KhadyotaError::InvalidConfig(msg)) => msg,
+        other => panic!("expected InvalidConfig, got {:?}", other.err()),
+    };
+
+    // The defaults ask for 8 subvectors, which two dimensions cannot hold
+    assert_eq!(
+        message(Config { dimensions: 2, ..Default::default() }),
+        "Dimensions (2) must be divisible by pq_subvectors (8); choose pq_subvectors from [1, 2] \
+         or set quantizer to SQ8 or None (None recommended at 4 dimensions or fewer)"
+    );
+    assert_eq!(
+        message(Config { dimensions: 12, ..Default::default() }),
+        "Dimensions (12) must be divisible by pq_subvectors (8); choose pq_subvectors from [1, 2, 3, 4, 6] \
+         or set quantizer to SQ8 or None"
+    );
+    assert_eq!(
+        message(Config { pq_subvectors: 0, ..Default::default() }),
+        "pq_subvectors must be > 0; set quantizer to SQ8 or None to disable PQ"
+    );
+
+    // for_dimensions picks a valid configuration
+    for dims in 1..=TINY_DIMENSIONS {
+        assert_eq!(Config::for_dimensions(dims).quantizer, QuantizerKind::None);
+    }
+    assert_eq!(Config::for_dimensions(12).pq_subvectors, 6);
+    assert_eq!(Config::for_dimensions(512).pq_subvectors, 8);
+    for dims in 1..=64 {
+        Config::for_dimensions(dims).validate().unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use khadyota::harness::{build_db, clustered_vectors, recall_at_k};
+use khadyota::*;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+fn config(use_pq: bool, max_training_vectors: Option<usize>) -> Config {
+    Config {
+        dimensions: 32,
+        metric: DistanceMetric::Euclidean,
+        quantizer: if use_pq { QuantizerKind::PQ } else { QuantizerKind::None },
+        pq_subvectors: 8,
+        num_clusters: 64,
+        num_probe: 8,
+        seed: Some(11),
+        max_training_vectors,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_sampled_training_is_faster_with_similar_recall() {
+    let vectors = clustered_vectors(50_000, 32, 64, 0.3, 5);
+    let queries = clustered_vectors(100, 32, 64, 0.3, 6);
+
+    let started = Instant::now();
+    let full = build_db(config(false, None), vectors.clone()).unwrap();
+    let full_time = started.elapsed();
+
+    let started = Instant::now();
+    let sampled = build_db(config(false, Some(5_000)), vectors).unwrap();
+    let sampled_time = started.elapsed();
+
+    assert!(
+        sampled_time * 3 < full_time,
+        "sampled build took {:?} against {:?}",
+        sampled_time,
+        full_time
+    );
+
+    // Every row is still indexed
+    assert_eq!(sampled.cluster_overview(0).unwrap().total_vectors, 50_000);
+
+    let full_recall = recall_at_k(&full, &queries, 10).unwrap();
+    let sampled_recall = recall_at_k(&sampled, &queries, 10).unwrap();
+    assert!(
+        sampled_recall >= full_recall - 0.05,
+        "recall@10 {} sampled against {} full",
+        sampled_recall,
+        full_recall
+    );
+}
+
+#[test]
+fn test_small_collections_train_on_every_row() {
+    let vectors = clustered_vectors(2_000, 32, 16, 0.3, 2);
+    let queries = clustered_vectors(20, 32, 16, 0.3, 3);
+    let capped = build_db(config(true, Some(2_000)), vectors.clone()).unwrap();
+    let uncapped = build_db(config(true, None), vectors).unwrap();
+    let hits = |db: &VectorDB, query| -> Vec<(u32, f32)> {
+        db.search(query, 10).unwrap().iter().map(|r| (r.id, r.distance)).collect()
+    };
+    for query in &queries {
+        assert_eq!(hits(&capped, query), hits(&uncapped, query));
+    }
+
+    // Larger ones train PQ on the sample and encode every row
+    let trained_on = Arc::new(Mutex::new(None));
+    let sink = Arc::clone(&trained_on);
+    let mut db = VectorDB::new(config(true, Some(500))).unwrap();
+    db.set_progress_callback(Some(Arc::new(move |event| {
+        if let BuildEvent::PqTrainingStarted { training_vectors, .. } = event {
+            *sink.lock().unwrap() = Some(training_vectors);
+        }
+    })));
+    db.insert_batch(clustered_vectors(2_000, 32, 16, 0.3, 2).into_iter().map(|v| (v, None)))
+        .unwrap();
+    db.build_index().unwrap();
+    assert_eq!(*trained_on.lock().unwrap(), Some(500));
+    assert_eq!(db.check_compatibility().violations, []);
+    assert_eq!(db.cluster_overview(0).unwrap().total_vectors, 2_000);
+
+    assert!(matches!(
+        VectorDB::new(config(true, Some(0))),
+        Err(KhadyotaError::InvalidConfig(_))
+    ));
+}